@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::fs;
+use std::ops::Not;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cargo::is_executable;
+use crate::format::human_size;
+use crate::install::discard_install;
+use crate::metadata;
+use crate::metadata::BinaryMetadata;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::spec::parse_spec;
+use crate::Switcher;
+
+/// Make sure every one of `files` can be registered, returning their names
+fn check_files(files: &[PathBuf]) -> Result<Vec<OsString>> {
+    let mut names = BTreeSet::new();
+    for file in files {
+        ensure!(file.exists(), "{} does not exist", file.display());
+        ensure!(file.is_file(), "{} is not a file", file.display());
+        ensure!(
+            is_executable(file),
+            "{} is not executable, run `chmod +x {}` if it's really a binary",
+            file.display(),
+            file.display()
+        );
+
+        let name = file
+            .file_name()
+            .with_context(|| format!("{} has no file name", file.display()))?
+            .to_owned();
+        if names.insert(name.clone()).not() {
+            bail!(
+                "Several of the given files are called {}",
+                Path::new(&name).display()
+            );
+        }
+    }
+
+    Ok(names.into_iter().collect())
+}
+
+impl Switcher {
+    /// Register binaries built or downloaded outside of cargo-switch as `package@version`, copying them into the
+    /// registry
+    pub fn add_binary(&self, package: &str, files: &[PathBuf]) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let (name, version) = parse_spec(package)?;
+        check_files(files)?;
+
+        let version_path = self.version_path(name, version);
+        ensure!(
+            version_path.exists().not(),
+            "{package} is already installed, uninstall it first to replace it"
+        );
+
+        let result = self.copy_binaries(&version_path, files);
+        if result.is_err() {
+            discard_install(&version_path);
+        }
+        let binaries = result?;
+
+        println!("Added {package}");
+        println!("  Location: {}", version_path.display());
+        for binary in &binaries {
+            println!("  - {} ({})", binary.name, human_size(binary.size));
+        }
+        println!("Switch to it with `cargo switch {package}`");
+
+        Ok(())
+    }
+
+    fn copy_binaries(&self, version_path: &Path, files: &[PathBuf]) -> Result<Vec<BinaryMetadata>> {
+        let bin_path = version_path.join("bin");
+        fs::create_dir_all(&bin_path)
+            .with_context(|| format!("Failed to create {}", bin_path.display()))?;
+
+        let mut binaries = Vec::new();
+        for file in files {
+            let destination = bin_path.join(file.file_name().unwrap_or_default());
+            fs::copy(file, &destination).with_context(|| {
+                format!(
+                    "Failed to copy {} to {}",
+                    file.display(),
+                    destination.display()
+                )
+            })?;
+
+            let mut permissions = fs::metadata(&destination)?.permissions();
+            permissions.set_mode(permissions.mode() | 0o755);
+            fs::set_permissions(&destination, permissions)?;
+
+            binaries.push(BinaryMetadata {
+                name: destination
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                size: fs::metadata(&destination)?.len(),
+                unstripped_size: None,
+            });
+        }
+
+        let metadata = VersionMetadata {
+            installed_at: Some(metadata::timestamp(SystemTime::now())),
+            source: Some(Source::External),
+            binaries: binaries.clone(),
+            ..VersionMetadata::default()
+        };
+        metadata.save(version_path)?;
+
+        Ok(binaries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::check_files;
+
+    #[test]
+    fn rejects_unusable_files() {
+        let root = tempfile::tempdir().unwrap();
+        let binary = root.path().join("mytool");
+        let text = root.path().join("README.md");
+        fs::write(&binary, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(&text, "# mytool\n").unwrap();
+
+        assert_eq!(
+            check_files(std::slice::from_ref(&binary)).unwrap(),
+            ["mytool"]
+        );
+        assert!(check_files(&[text]).is_err());
+        assert!(check_files(&[root.path().join("missing")]).is_err());
+        assert!(check_files(&[root.path().to_owned()]).is_err());
+        assert!(check_files(&[binary.clone(), binary]).is_err());
+    }
+}
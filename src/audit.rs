@@ -0,0 +1,632 @@
+//! `audit`: checking installed versions against the RustSec advisory database, `https://github.com/rustsec/advisory-db`,
+//! for the vulnerabilities that are known in the tools themselves. Their dependencies aren't checked.
+//!
+//! The database is downloaded as a whole into the cache and fetched again once it's older than `cache-ttl`. When it
+//! can't be, as when offline, whatever copy was fetched last is used, however old.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use semver::Op;
+use semver::Version;
+use semver::VersionReq;
+use serde::Deserialize;
+
+use crate::cache::CACHE_DIRECTORY_NAME;
+use crate::cache::DEFAULT_CACHE_TTL;
+use crate::download;
+use crate::extract::extract;
+use crate::extract::ArtifactKind;
+use crate::format::human_duration;
+use crate::metadata::timestamp;
+use crate::retry;
+use crate::retry::RetryPolicy;
+use crate::spec::split_label;
+use crate::table::print_table;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// The whole database, as GitHub archives it
+const DATABASE_URL: &str = "https://github.com/rustsec/advisory-db/archive/refs/heads/main.tar.gz";
+
+/// Directory, inside the cache, where the database lives
+const DATABASE_DIRECTORY_NAME: &str = "advisory-db";
+
+/// When the database was fetched, in seconds since the Unix epoch, inside its directory
+const FETCHED_AT_FILE_NAME: &str = "fetched-at";
+
+/// The front matter of an advisory, as far as we care about it
+#[derive(Debug, Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryFields,
+    #[serde(default)]
+    versions: VersionFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdvisoryFields {
+    id: String,
+    package: String,
+    url: Option<String>,
+    cvss: Option<String>,
+    informational: Option<String>,
+    withdrawn: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct VersionFields {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+/// An advisory about a crate
+#[derive(Debug, Clone)]
+pub struct Advisory {
+    /// As in `RUSTSEC-2021-0001`
+    pub id: String,
+    pub package: String,
+    pub title: String,
+    pub url: Option<String>,
+    /// The CVSS vector of the vulnerability, if it was scored
+    pub cvss: Option<String>,
+    /// What the advisory is about when it's not a vulnerability, as in `unmaintained` or `unsound`
+    pub informational: Option<String>,
+    pub withdrawn: bool,
+    /// The versions that were fixed
+    pub patched: Vec<VersionReq>,
+    /// The versions that were never affected
+    pub unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    /// Parse an advisory as the database keeps them: Markdown starting with TOML front matter in a ```` ```toml ````
+    /// block, followed by a title
+    pub fn parse(contents: &str) -> Result<Self> {
+        let front_matter = contents
+            .trim_start()
+            .strip_prefix("```toml")
+            .and_then(|rest| rest.split_once("\n```"))
+            .map(|(front_matter, _)| front_matter)
+            .context("Expected the advisory to start with TOML front matter")?;
+        let file: AdvisoryFile = toml::from_str(front_matter)?;
+
+        let title = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .unwrap_or_default()
+            .trim()
+            .to_owned();
+        let requirements = |requirements: &[String]| -> Result<Vec<VersionReq>> {
+            requirements
+                .iter()
+                .map(|requirement| {
+                    VersionReq::parse(requirement)
+                        .with_context(|| format!("Invalid version requirement `{requirement}`"))
+                })
+                .collect()
+        };
+
+        Ok(Self {
+            patched: requirements(&file.versions.patched)?,
+            unaffected: requirements(&file.versions.unaffected)?,
+            id: file.advisory.id,
+            package: file.advisory.package,
+            title,
+            url: file.advisory.url,
+            cvss: file.advisory.cvss,
+            informational: file.advisory.informational,
+            withdrawn: file.advisory.withdrawn.is_some(),
+        })
+    }
+
+    /// Whether `version` is affected
+    pub fn affects(&self, version: &Version) -> bool {
+        self.withdrawn.not()
+            && self
+                .patched
+                .iter()
+                .chain(&self.unaffected)
+                .all(|requirement| requirement.matches(version).not())
+    }
+
+    /// The oldest version newer than `version` that was fixed, if any was
+    pub fn first_fixed(&self, version: &Version) -> Option<Version> {
+        self.patched
+            .iter()
+            .flat_map(|requirement| &requirement.comparators)
+            .filter_map(|comparator| {
+                let mut fixed = Version::new(
+                    comparator.major,
+                    comparator.minor.unwrap_or(0),
+                    comparator.patch.unwrap_or(0),
+                );
+                fixed.pre = comparator.pre.clone();
+                match comparator.op {
+                    Op::GreaterEq | Op::Exact | Op::Caret | Op::Tilde => Some(fixed),
+                    Op::Greater => {
+                        fixed.patch += 1;
+                        Some(fixed)
+                    }
+                    _ => None,
+                }
+            })
+            .filter(|fixed| fixed > version)
+            .min()
+    }
+
+    /// How severe the advisory is: the CVSS rating of vulnerabilities, or what informational advisories are about
+    pub fn severity(&self) -> String {
+        if let Some(informational) = &self.informational {
+            return informational.clone();
+        }
+
+        match self.cvss.as_deref().and_then(cvss_score) {
+            Some(score) => format!("{} ({score:.1})", rating(score)),
+            None => "unknown".to_owned(),
+        }
+    }
+}
+
+/// The base score of a CVSS 3.x `vector`, as in `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`. Vectors of other
+/// versions aren't scored.
+pub fn cvss_score(vector: &str) -> Option<f64> {
+    let metrics = vector
+        .strip_prefix("CVSS:3.1/")
+        .or_else(|| vector.strip_prefix("CVSS:3.0/"))?;
+    let metrics: BTreeMap<_, _> = metrics
+        .split('/')
+        .filter_map(|metric| metric.split_once(':'))
+        .collect();
+    let metric = |name: &str| metrics.get(name).copied();
+
+    let changed = match metric("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector = match metric("AV")? {
+        "N" => 0.85,
+        "A" => 0.62,
+        "L" => 0.55,
+        "P" => 0.2,
+        _ => return None,
+    };
+    let attack_complexity = match metric("AC")? {
+        "L" => 0.77,
+        "H" => 0.44,
+        _ => return None,
+    };
+    let privileges = match (metric("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let user_interaction = match metric("UI")? {
+        "N" => 0.85,
+        "R" => 0.62,
+        _ => return None,
+    };
+    let impact = |name: &str| match metric(name)? {
+        "H" => Some(0.56),
+        "L" => Some(0.22),
+        "N" => Some(0.0),
+        _ => None,
+    };
+    let (confidentiality, integrity, availability) = (impact("C")?, impact("I")?, impact("A")?);
+
+    let base_impact = 1.0 - (1.0 - confidentiality) * (1.0 - integrity) * (1.0 - availability);
+    let impact = if changed {
+        7.52 * (base_impact - 0.029) - 3.25 * f64::powi(base_impact - 0.02, 15)
+    } else {
+        6.42 * base_impact
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * attack_complexity * privileges * user_interaction;
+    let score = if changed {
+        1.08 * (impact + exploitability)
+    } else {
+        impact + exploitability
+    };
+
+    Some(round_up(score.min(10.0)))
+}
+
+/// Round `score` up to one decimal, as the CVSS specification does it, steering clear of floating point errors
+fn round_up(score: f64) -> f64 {
+    let scaled = (score * 100_000.0).round() as u64;
+    if scaled.is_multiple_of(10_000) {
+        scaled as f64 / 100_000.0
+    } else {
+        ((scaled / 10_000) + 1) as f64 / 10.0
+    }
+}
+
+/// The qualitative rating of a CVSS score
+pub fn rating(score: f64) -> &'static str {
+    match score {
+        score if score >= 9.0 => "critical",
+        score if score >= 7.0 => "high",
+        score if score >= 4.0 => "medium",
+        score if score > 0.0 => "low",
+        _ => "none",
+    }
+}
+
+/// A copy of the advisory database
+#[derive(Debug, Clone)]
+pub struct AdvisoryDatabase {
+    /// The directory holding a directory of advisories per crate
+    crates: PathBuf,
+    /// When the copy was fetched
+    pub fetched_at: SystemTime,
+}
+
+impl AdvisoryDatabase {
+    /// The copy of the database in `directory`, if there's a whole one
+    fn open(directory: &Path) -> Option<Self> {
+        let fetched_at = fs::read_to_string(directory.join(FETCHED_AT_FILE_NAME)).ok()?;
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(fetched_at.trim().parse().ok()?);
+
+        Some(Self {
+            crates: directory.join("crates"),
+            fetched_at,
+        })
+    }
+
+    /// How old the copy is
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.fetched_at)
+            .unwrap_or_default()
+    }
+
+    /// Every advisory about the crate called `name`
+    pub fn advisories(&self, name: &str) -> Result<Vec<Advisory>> {
+        let directory = self.crates.join(name);
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", directory.display()))
+            }
+        };
+
+        let mut advisories = Vec::new();
+        for maybe_entry in entries {
+            let path = maybe_entry?.path();
+            if path.extension().is_none_or(|extension| extension != "md") {
+                continue;
+            }
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            // Newer advisories may use a format this build doesn't know, which shouldn't hide the others
+            match Advisory::parse(&contents) {
+                Ok(advisory) => advisories.push(advisory),
+                Err(err) => eprintln!(
+                    "Warning: skipping {}, which couldn't be parsed: {err:#}",
+                    path.display()
+                ),
+            }
+        }
+        advisories.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(advisories)
+    }
+}
+
+/// An installed version affected by an advisory
+#[derive(Debug, Clone)]
+struct Finding {
+    package: String,
+    version: String,
+    active: bool,
+    advisory: Advisory,
+    fixed: Option<Version>,
+}
+
+impl Switcher {
+    fn advisory_database_directory(&self) -> PathBuf {
+        self.registry
+            .join(CACHE_DIRECTORY_NAME)
+            .join(DATABASE_DIRECTORY_NAME)
+    }
+
+    /// Download the whole advisory database, replacing the copy there is
+    fn fetch_advisory_database(&self) -> Result<AdvisoryDatabase> {
+        let directory = self.advisory_database_directory();
+        let staging = directory.with_extension("tmp");
+        let _ = fs::remove_dir_all(&staging);
+        fs::create_dir_all(&staging)
+            .with_context(|| format!("Failed to create {}", staging.display()))?;
+
+        let result = (|| {
+            let archive = staging.join("advisory-db.tar.gz");
+            let retries = self.config.retries.unwrap_or(retry::DEFAULT_RETRIES);
+            RetryPolicy::new(retries).run("download the advisory database", || {
+                download::download(DATABASE_URL, &archive)
+            })?;
+            let extracted = staging.join("extracted");
+            extract(&archive, ArtifactKind::TarGz, "advisory-db", &extracted)?;
+
+            // GitHub puts everything in a directory named after the repository and the branch
+            let root = fs::read_dir(&extracted)?
+                .next()
+                .context("The advisory database's archive is empty")??
+                .path();
+            let crates = root.join("crates");
+            anyhow::ensure!(
+                crates.is_dir(),
+                "The advisory database's archive has no advisories"
+            );
+
+            let fresh = staging.join("database");
+            fs::create_dir_all(&fresh)?;
+            fs::rename(&crates, fresh.join("crates"))?;
+            fs::write(
+                fresh.join(FETCHED_AT_FILE_NAME),
+                timestamp(SystemTime::now()).to_string(),
+            )?;
+
+            let _ = fs::remove_dir_all(&directory);
+            fs::rename(&fresh, &directory)
+                .with_context(|| format!("Failed to move the database to {}", directory.display()))
+        })();
+        let _ = fs::remove_dir_all(&staging);
+        result?;
+
+        AdvisoryDatabase::open(&directory).context("The advisory database went missing")
+    }
+
+    /// The advisory database, fetched again if the copy there is goes back further than `cache-ttl`, or whatever copy
+    /// there is when it can't be fetched
+    pub fn advisory_database(&self) -> Result<AdvisoryDatabase> {
+        let cached = AdvisoryDatabase::open(&self.advisory_database_directory());
+        let ttl = self.config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL);
+        if let Some(cached) = &cached {
+            if self.refresh.not() && cached.age() < ttl {
+                return Ok(cached.clone());
+            }
+        }
+
+        match (self.fetch_advisory_database(), cached) {
+            (Ok(database), _) => Ok(database),
+            (Err(err), Some(cached)) => {
+                eprintln!(
+                    "Warning: failed to fetch the advisory database, so the copy from {} ago is used: {err:#}",
+                    human_age(cached.age())
+                );
+                Ok(cached)
+            }
+            (Err(err), None) => Err(err.context("Failed to fetch the advisory database")),
+        }
+    }
+
+    /// Every installed version affected by an advisory, and whether it's active
+    fn audit_findings(&self, database: &AdvisoryDatabase) -> Result<Vec<Finding>> {
+        let mut findings = Vec::new();
+        for package in self.installed_packages()? {
+            let (crate_name, _) = split_label(&package);
+            let advisories = database.advisories(crate_name)?;
+            if advisories.is_empty() {
+                continue;
+            }
+
+            let active = self.linked_version(&package)?;
+            for version in self.installed_versions(&package)? {
+                // Builds from git or elsewhere aren't releases advisories could be about
+                let Ok(release) = Version::parse(split_variant(&version).0) else {
+                    continue;
+                };
+                for advisory in advisories
+                    .iter()
+                    .filter(|advisory| advisory.affects(&release))
+                {
+                    findings.push(Finding {
+                        package: package.clone(),
+                        active: active.as_deref() == Some(version.as_str()),
+                        fixed: advisory.first_fixed(&release),
+                        version: version.clone(),
+                        advisory: advisory.clone(),
+                    });
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+
+    /// Report every installed version the advisory database knows to be vulnerable. Fails if an active version is,
+    /// unless `fix` is set and updating every such package made it go away.
+    pub fn audit(&self, fix: bool) -> Result<()> {
+        let database = self.advisory_database()?;
+        println!(
+            "Checked against the RustSec advisory database, fetched {} ago",
+            human_age(database.age())
+        );
+
+        let mut findings = self.audit_findings(&database)?;
+        if findings.is_empty() {
+            println!("No installed version is affected by a known advisory");
+            return Ok(());
+        }
+        print_findings(&findings);
+
+        if fix {
+            let affected: BTreeSet<_> = findings
+                .iter()
+                .filter(|finding| finding.active && finding.advisory.informational.is_none())
+                .map(|finding| finding.package.clone())
+                .collect();
+            for package in affected {
+                println!();
+                if let Err(err) = self.update(&package, false) {
+                    eprintln!("Failed to update {package}: {err:#}");
+                }
+            }
+            findings = self.audit_findings(&database)?;
+        }
+
+        let affected: BTreeSet<_> = findings
+            .iter()
+            .filter(|finding| finding.active && finding.advisory.informational.is_none())
+            .map(|finding| finding.package.as_str())
+            .collect();
+        if affected.is_empty().not() {
+            bail!(
+                "The active version of {} is affected by known vulnerabilities",
+                affected.into_iter().collect::<Vec<_>>().join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// How long ago something happened, to the minute
+fn human_age(age: Duration) -> String {
+    if age < Duration::from_secs(60) {
+        return "less than a minute".to_owned();
+    }
+
+    human_duration(Duration::from_secs(age.as_secs() / 60 * 60))
+}
+
+fn print_findings(findings: &[Finding]) {
+    let rows: Vec<_> = findings
+        .iter()
+        .map(|finding| {
+            [
+                finding.package.clone(),
+                finding.version.clone(),
+                if finding.active { "yes" } else { "" }.to_owned(),
+                finding.advisory.id.clone(),
+                finding.advisory.severity(),
+                finding
+                    .fixed
+                    .as_ref()
+                    .map_or_else(|| "-".to_owned(), Version::to_string),
+            ]
+        })
+        .collect();
+    println!();
+    print_table(
+        [
+            "PACKAGE", "VERSION", "ACTIVE", "ADVISORY", "SEVERITY", "FIXED IN",
+        ],
+        &rows,
+    );
+
+    let mut described = BTreeSet::new();
+    for Finding { advisory, .. } in findings {
+        if described.insert(&advisory.id).not() {
+            continue;
+        }
+
+        println!("\n{}: {}", advisory.id, advisory.title);
+        let requirements = |requirements: &[VersionReq]| {
+            let requirements: Vec<_> = requirements.iter().map(VersionReq::to_string).collect();
+            requirements.join(", ")
+        };
+        if advisory.patched.is_empty() {
+            println!("  Patched:    no fixed release");
+        } else {
+            println!("  Patched:    {}", requirements(&advisory.patched));
+        }
+        if advisory.unaffected.is_empty().not() {
+            println!("  Unaffected: {}", requirements(&advisory.unaffected));
+        }
+        if let Some(url) = &advisory.url {
+            println!("  More:       {url}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use semver::Version;
+
+    use super::cvss_score;
+    use super::Advisory;
+
+    const ADVISORY: &str = r#"```toml
+[advisory]
+id = "RUSTSEC-2024-0001"
+package = "tool"
+date = "2024-01-01"
+url = "https://example.com/advisory"
+cvss = "CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"
+
+[versions]
+patched = [">= 1.2.3, < 2.0.0", ">= 2.0.1"]
+unaffected = ["< 1.0.0"]
+```
+
+# Remote code execution in tool
+
+Details.
+"#;
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn tells_which_versions_are_affected() {
+        let advisory = Advisory::parse(ADVISORY).unwrap();
+        assert_eq!(advisory.id, "RUSTSEC-2024-0001");
+        assert_eq!(advisory.title, "Remote code execution in tool");
+        assert_eq!(advisory.severity(), "critical (9.8)");
+
+        assert!(advisory.affects(&version("1.0.0")));
+        assert!(advisory.affects(&version("2.0.0")));
+        assert!(advisory.affects(&version("0.9.0")).not());
+        assert!(advisory.affects(&version("1.2.3")).not());
+        assert!(advisory.affects(&version("2.1.0")).not());
+
+        assert_eq!(
+            advisory.first_fixed(&version("1.0.0")),
+            Some(version("1.2.3"))
+        );
+        assert_eq!(
+            advisory.first_fixed(&version("2.0.0")),
+            Some(version("2.0.1"))
+        );
+
+        let withdrawn = ADVISORY.replace("[versions]", "withdrawn = \"2024-02-01\"\n\n[versions]");
+        assert!(Advisory::parse(&withdrawn)
+            .unwrap()
+            .affects(&version("1.0.0"))
+            .not());
+        assert!(Advisory::parse("# No front matter").is_err());
+    }
+
+    #[test]
+    fn scores_cvss_vectors() {
+        let score = |vector: &str| cvss_score(&format!("CVSS:3.1/{vector}"));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(score("AV:N/AC:L/PR:N/UI:N/S:C/C:H/I:H/A:H"), Some(10.0));
+        assert_eq!(score("AV:L/AC:L/PR:L/UI:N/S:U/C:H/I:N/A:N"), Some(5.5));
+        assert_eq!(score("AV:N/AC:H/PR:N/UI:R/S:U/C:N/I:N/A:N"), Some(0.0));
+        assert_eq!(cvss_score("CVSS:4.0/AV:N/AC:L/AT:N/PR:N/UI:N"), None);
+    }
+}
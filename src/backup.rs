@@ -0,0 +1,333 @@
+//! Archives of the whole registry, as `.tar.zst` files.
+//!
+//! An archive starts with a manifest listing every file it holds along with its checksum, and which version of each
+//! package was active. Everything else is stored under `registry/`, relative to the registry's root, so that it can
+//! be restored anywhere.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cache::CACHE_DIRECTORY_NAME;
+use crate::checksum::sha256_file;
+use crate::format::human_size;
+use crate::lock::LINKS_LOCK_FILE_NAME;
+use crate::lock::LOCK_FILE_NAME;
+use crate::metadata;
+use crate::migrate;
+use crate::operation_log::LOG_FILE_NAME;
+use crate::operation_log::ROTATED_LOG_FILE_NAME;
+use crate::state::STATE_FILE_NAME;
+use crate::trash::TRASH_DIRECTORY_NAME;
+use crate::Switcher;
+
+const MANIFEST_NAME: &str = "manifest.json";
+/// Directory, inside of archives, holding the registry's contents
+const REGISTRY_PREFIX: &str = "registry";
+/// Directory, inside the registry, where archives get unpacked before their contents are moved into place
+const STAGING_DIRECTORY_NAME: &str = ".restore";
+/// Present in the registry for as long as a restore is moving things into place. If it's still around, the restore
+/// was interrupted and the registry is missing part of the archive.
+pub const RESTORE_MARKER_NAME: &str = ".restore-incomplete";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Manifest {
+    /// When the archive was made, in seconds since the Unix epoch
+    created_at: u64,
+    /// The version of each package that was active, keyed by package name
+    active: BTreeMap<String, String>,
+    files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ManifestEntry {
+    /// Relative to the registry's root
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+}
+
+/// Every file below `root`, relative to it, leaving out directories along the way
+fn collect_files(root: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for maybe_entry in fs::read_dir(root.join(relative))? {
+        let entry = maybe_entry?;
+        let path = relative.join(entry.file_name());
+
+        // Leftovers of an interrupted restore don't belong in a backup, and neither do locks, cached responses or
+        // what this machine's log says happened on it
+        if relative.as_os_str().is_empty()
+            && [
+                STAGING_DIRECTORY_NAME,
+                RESTORE_MARKER_NAME,
+                LINKS_LOCK_FILE_NAME,
+                CACHE_DIRECTORY_NAME,
+                LOG_FILE_NAME,
+                ROTATED_LOG_FILE_NAME,
+            ]
+            .iter()
+            .any(|name| entry.file_name() == *name)
+        {
+            continue;
+        }
+        if entry.file_name() == LOCK_FILE_NAME {
+            continue;
+        }
+
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpack `archive` into `staging`, checking everything it holds against its manifest
+fn unpack(archive: &Path, staging: &Path) -> Result<Manifest> {
+    let file =
+        File::open(archive).with_context(|| format!("Failed to open {}", archive.display()))?;
+    let mut archive_reader = tar::Archive::new(zstd::Decoder::new(file)?);
+    let mut entries = archive_reader.entries()?;
+
+    let mut manifest_entry = entries
+        .next()
+        .with_context(|| format!("{} is empty", archive.display()))??;
+    ensure!(
+        manifest_entry.path()?.as_os_str() == MANIFEST_NAME,
+        "{} has no manifest, it wasn't made by `cargo switch backup`",
+        archive.display()
+    );
+    let mut manifest_json = Vec::new();
+    manifest_entry.read_to_end(&mut manifest_json)?;
+    let manifest: Manifest = serde_json::from_slice(&manifest_json)
+        .with_context(|| format!("The manifest of {} is corrupt", archive.display()))?;
+
+    fs::create_dir_all(staging.join(REGISTRY_PREFIX))?;
+    for maybe_entry in entries {
+        let mut entry = maybe_entry?;
+        ensure!(
+            entry.path()?.starts_with(REGISTRY_PREFIX),
+            "{} holds an unexpected entry: {}",
+            archive.display(),
+            entry.path()?.display()
+        );
+        // Refuses anything that would end up outside of `staging`
+        entry.unpack_in(staging)?;
+    }
+
+    let registry = staging.join(REGISTRY_PREFIX);
+    for entry in &manifest.files {
+        let path = registry.join(&entry.path);
+        let metadata = fs::symlink_metadata(&path).with_context(|| {
+            format!(
+                "{} is missing from {}",
+                entry.path.display(),
+                archive.display()
+            )
+        })?;
+        if metadata.is_file().not() {
+            continue;
+        }
+
+        if sha256_file(&path)? != entry.sha256 {
+            bail!(
+                "{} in {} is corrupt: its checksum doesn't match the manifest",
+                entry.path.display(),
+                archive.display()
+            );
+        }
+    }
+
+    Ok(manifest)
+}
+
+impl Switcher {
+    /// Archive the registry, bookkeeping included, into `archive`
+    pub fn backup(&self, archive: &Path) -> Result<()> {
+        let mut files = Vec::new();
+        collect_files(&self.registry, Path::new(""), &mut files)?;
+        files.sort();
+
+        let mut entries = Vec::new();
+        for path in files {
+            let full_path = self.registry.join(&path);
+            let metadata = fs::symlink_metadata(&full_path)?;
+            let sha256 = if metadata.is_file() {
+                sha256_file(&full_path)?
+            } else {
+                String::new()
+            };
+
+            entries.push(ManifestEntry {
+                path,
+                size: metadata.len(),
+                sha256,
+            });
+        }
+
+        let mut active = BTreeMap::new();
+        for package in self.installed_packages()? {
+            if let Some(version) = self.linked_version(&package)? {
+                active.insert(package, version);
+            }
+        }
+
+        let manifest = Manifest {
+            created_at: metadata::timestamp(SystemTime::now()),
+            active,
+            files: entries,
+        };
+
+        // Write somewhere else first, so that an interrupted backup never looks like a complete one
+        let mut file_name = OsString::from(".");
+        file_name.push(archive.file_name().unwrap_or_default());
+        file_name.push(".tmp");
+        let temporary = archive.with_file_name(file_name);
+        let file = File::create(&temporary)
+            .with_context(|| format!("Failed to create {}", temporary.display()))?;
+        let encoder = zstd::Encoder::new(file, 0)?;
+        let mut builder = tar::Builder::new(encoder);
+        builder.follow_symlinks(false);
+
+        let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(manifest_json.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(manifest.created_at);
+        header.set_cksum();
+        builder.append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())?;
+
+        for entry in &manifest.files {
+            builder
+                .append_path_with_name(
+                    self.registry.join(&entry.path),
+                    Path::new(REGISTRY_PREFIX).join(&entry.path),
+                )
+                .with_context(|| format!("Failed to archive {}", entry.path.display()))?;
+        }
+
+        builder.into_inner()?.finish()?.sync_all()?;
+        fs::rename(&temporary, archive)
+            .with_context(|| format!("Failed to move the backup to {}", archive.display()))?;
+
+        let total_size: u64 = manifest.files.iter().map(|entry| entry.size).sum();
+        println!(
+            "Backed up {} file(s) ({}) to {}",
+            manifest.files.len(),
+            human_size(total_size),
+            archive.display()
+        );
+
+        Ok(())
+    }
+
+    /// Restore the registry from `archive`, made by [`Switcher::backup`], and relink the versions that were active.
+    /// The registry must be empty unless `force` is set, in which case its contents are replaced.
+    pub fn restore(&self, archive: &Path, force: bool) -> Result<()> {
+        let staging = self.registry.join(STAGING_DIRECTORY_NAME);
+        let marker = self.registry.join(RESTORE_MARKER_NAME);
+
+        // Whatever an interrupted restore left behind is useless now
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+
+        let existing: Vec<_> = fs::read_dir(&self.registry)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<_>>()?;
+        let links_lock = self.registry.join(LINKS_LOCK_FILE_NAME);
+        // The log tells what happened on this machine, which a restore doesn't change
+        let logs = [
+            self.registry.join(LOG_FILE_NAME),
+            self.registry.join(ROTATED_LOG_FILE_NAME),
+        ];
+        let existing: Vec<_> = existing
+            .into_iter()
+            .filter(|path| *path != marker && *path != links_lock && logs.contains(path).not())
+            .collect();
+        // The state file alone, as stamped on new registries, means nothing without versions to point to. Neither does
+        // the trash, as what it holds was uninstalled already.
+        let state_file = self.registry.join(STATE_FILE_NAME);
+        let trash = self.registry.join(TRASH_DIRECTORY_NAME);
+        ensure!(
+            existing
+                .iter()
+                .all(|path| *path == state_file || *path == trash)
+                || force,
+            "{} is not empty, pass --force to replace its contents",
+            self.registry.display()
+        );
+
+        let manifest = match unpack(archive, &staging) {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&staging);
+                return Err(err)
+                    .with_context(|| format!("Failed to restore {}", archive.display()));
+            }
+        };
+        if let Err(err) = migrate::ensure_supported_format(&staging.join(REGISTRY_PREFIX)) {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err).with_context(|| format!("Failed to restore {}", archive.display()));
+        }
+
+        // From here on, the registry is only partly there until the marker goes away
+        fs::write(&marker, "")?;
+        let links = self.lock_links()?;
+
+        for link in self.managed_links()? {
+            self.remove_link(&link.link)?;
+        }
+        for path in existing {
+            if path.is_dir() {
+                fs::remove_dir_all(&path)?;
+            } else {
+                fs::remove_file(&path)?;
+            }
+        }
+
+        for maybe_entry in fs::read_dir(staging.join(REGISTRY_PREFIX))? {
+            let entry = maybe_entry?;
+            fs::rename(entry.path(), self.registry.join(entry.file_name()))?;
+        }
+        fs::remove_dir_all(&staging)?;
+        fs::remove_file(&marker)?;
+        drop(links);
+        // Archives of older registries are in their format
+        self.migrate_registry()?;
+
+        println!(
+            "Restored {} file(s) from {}",
+            manifest.files.len(),
+            archive.display()
+        );
+
+        let mut errors = 0;
+        for (package, version) in &manifest.active {
+            if let Err(err) = self.switch_package(&format!("{package}@{version}")) {
+                eprintln!("Failed to switch to {package}@{version}: {err:#}");
+                errors += 1;
+            }
+        }
+        ensure!(errors == 0, "Failed to relink {errors} package(s)");
+
+        Ok(())
+    }
+}
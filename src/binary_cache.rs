@@ -0,0 +1,557 @@
+//! The binary cache: a directory shared with others, as in a network mount, set through `binary-cache`, that builds
+//! from crates.io are pushed to so that whoever installs the same version the same way copies its binaries rather
+//! than building them again.
+//!
+//! Entries live at `<package>/<version>/<key>`, the key being a hash of everything that changes what a build
+//! produces: the target triple or platform, what `rustc --version` said, the profile, the features and the flags,
+//! build environment included. A build that differs in any of them misses the cache. Each entry holds the binaries
+//! in `bin`, the version's metadata as it was when it was built, and an `entry.json` recording the binaries'
+//! checksums, which copies are checked against, and who pushed it.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::ops::Not;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::checksum::sha256;
+use crate::checksum::sha256_file;
+use crate::install::discard_install;
+use crate::install::BuildPlan;
+use crate::installer::BuildFlags;
+use crate::metadata;
+use crate::metadata::LockfileStatus;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::operation_log::Operation;
+use crate::spec::parse_spec;
+use crate::spec::split_label;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// What every entry records on top of its binaries and metadata
+const ENTRY_FILE_NAME: &str = "entry.json";
+
+/// Everything a build must match to be copied from the cache
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CacheKey {
+    /// The target triple the binaries were cross-compiled for, or else the platform they were built on, as in
+    /// `x86_64-linux`
+    pub target: String,
+    /// What `rustc --version` said for the compiler the binaries were built with
+    pub rustc: String,
+    pub profile: String,
+    pub features: Vec<String>,
+    pub flags: BuildFlags,
+}
+
+impl CacheKey {
+    /// The key of a build for `target`, recorded as `metadata` says, `None` if it isn't one for the cache: only
+    /// builds from crates.io by a compiler that's known are
+    pub fn from_metadata(metadata: &VersionMetadata, target: Option<&str>) -> Option<Self> {
+        if metadata.source != Some(Source::CratesIo) {
+            return None;
+        }
+
+        Some(Self {
+            target: target.map_or_else(platform, str::to_owned),
+            rustc: metadata.rustc.clone()?,
+            profile: metadata.profile.clone()?,
+            features: metadata.features.clone(),
+            flags: metadata.flags.clone()?,
+        })
+    }
+
+    /// What entries for this key are named after
+    fn hash(&self) -> String {
+        let key = serde_json::to_vec(self).unwrap_or_default();
+        let mut hash = sha256(key.as_slice()).unwrap_or_default();
+        hash.truncate(16);
+
+        hash
+    }
+}
+
+/// What a cache entry says about itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct CacheEntry {
+    key: CacheKey,
+    /// The SHA-256 checksums of the binaries, by name
+    checksums: BTreeMap<String, String>,
+    /// Who pushed the entry, as in `alice@build-box`
+    pushed_by: String,
+    /// Seconds since the Unix epoch
+    pushed_at: u64,
+}
+
+/// Where a version copied from the binary cache came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CacheProvenance {
+    /// The entry the binaries were copied from
+    pub entry: PathBuf,
+    /// Who pushed it, as in `alice@build-box`
+    pub pushed_by: String,
+    /// Seconds since the Unix epoch
+    pub pushed_at: u64,
+    /// How long copying the binaries out of the cache took, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub copy_duration_ms: Option<u64>,
+}
+
+impl CacheProvenance {
+    pub fn pushed_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.pushed_at)
+    }
+
+    pub fn copy_duration(&self) -> Option<Duration> {
+        Some(Duration::from_millis(self.copy_duration_ms?))
+    }
+}
+
+/// The platform cargo-switch runs on, which builds that weren't cross-compiled are for
+fn platform() -> String {
+    format!("{}-{}", env::consts::ARCH, env::consts::OS)
+}
+
+fn hostname() -> Option<String> {
+    let mut buffer = [0u8; 256];
+    let result = unsafe { libc::gethostname(buffer.as_mut_ptr().cast(), buffer.len()) };
+    if result != 0 {
+        return None;
+    }
+    let end = buffer.iter().position(|&byte| byte == 0)?;
+
+    String::from_utf8(buffer[..end].to_vec()).ok()
+}
+
+/// Who is pushing, as in `alice@build-box`
+fn pusher() -> String {
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_else(|_| "someone".to_owned());
+
+    match hostname() {
+        Some(hostname) => format!("{user}@{hostname}"),
+        None => user,
+    }
+}
+
+/// Whether `name` names a file right inside a directory, rather than somewhere else through `..` or `/`
+fn is_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
+
+/// Copy the binaries `entry` lists and the `Cargo.lock` of the version at `version_path` into `entry_path`, along
+/// with `entry` itself
+fn copy_entry(version_path: &Path, entry_path: &Path, entry: &CacheEntry) -> Result<()> {
+    let bin = entry_path.join("bin");
+    fs::create_dir_all(&bin).with_context(|| format!("Failed to create {}", bin.display()))?;
+    for name in entry.checksums.keys() {
+        let from = version_path.join("bin").join(name);
+        fs::copy(&from, bin.join(name))
+            .with_context(|| format!("Failed to copy {}", from.display()))?;
+    }
+
+    fs::create_dir_all(VersionMetadata::directory(entry_path))?;
+    let lockfile = VersionMetadata::lockfile_path(version_path);
+    if lockfile.exists() {
+        fs::copy(&lockfile, VersionMetadata::lockfile_path(entry_path))
+            .with_context(|| format!("Failed to copy {}", lockfile.display()))?;
+    }
+    fs::write(
+        entry_path.join(ENTRY_FILE_NAME),
+        serde_json::to_string_pretty(entry)?,
+    )
+    .with_context(|| format!("Failed to write {}", entry_path.display()))
+}
+
+impl Switcher {
+    /// Where the entry for `crate_name@version` built as `key` says is, or would be
+    fn cache_entry_path(cache: &Path, crate_name: &str, version: &str, key: &CacheKey) -> PathBuf {
+        cache.join(crate_name).join(version).join(key.hash())
+    }
+
+    /// Copy what a build following `plan` would produce from the binary cache into `target_path`, if it has it,
+    /// returning the version's metadata. Anything going wrong is only worth a warning, the version being built
+    /// instead.
+    pub(crate) fn pull_from_binary_cache(
+        &self,
+        plan: &BuildPlan,
+        target_path: &Path,
+    ) -> Option<VersionMetadata> {
+        let cache = self.config.binary_cache.as_deref()?;
+        // Only builds from crates.io's sources are cached
+        if plan.from_url.is_some()
+            || plan.path.is_some()
+            || plan.git.is_some()
+            || plan.locked.is_some()
+        {
+            return None;
+        }
+        let key = CacheKey {
+            target: plan.target.map_or_else(platform, str::to_owned),
+            rustc: self
+                .installer
+                .rustc_version(plan.flags.toolchain.as_deref())?,
+            profile: plan.profile.to_owned(),
+            features: plan.features.to_vec(),
+            flags: plan.flags.clone(),
+        };
+        let crate_name = split_label(plan.name).0;
+        let entry_path = Self::cache_entry_path(cache, crate_name, plan.version, &key);
+        if entry_path.join(ENTRY_FILE_NAME).exists().not() {
+            return None;
+        }
+
+        let started = Instant::now();
+        match self.copy_from_binary_cache(&entry_path, &key, plan.require_checksum, target_path) {
+            Ok(mut metadata) => {
+                // The build duration stays the one of the build that was pushed
+                if let Some(provenance) = &mut metadata.binary_cache {
+                    provenance.copy_duration_ms = Some(started.elapsed().as_millis() as u64);
+                }
+                if let Err(err) = metadata.save(target_path) {
+                    eprintln!(
+                        "Warning: failed to record how {} was installed: {err:#}",
+                        plan.name
+                    );
+                }
+                let pushed_by = metadata
+                    .binary_cache
+                    .as_ref()
+                    .map(|provenance| provenance.pushed_by.as_str())
+                    .unwrap_or_default();
+                let version = target_path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy();
+                self.log(
+                    Operation::Install,
+                    Some(plan.name),
+                    Some(&version),
+                    format!("copied from the binary cache, pushed by {pushed_by}"),
+                );
+                println!(
+                    "Copied {}@{version} from the binary cache, pushed by {pushed_by}",
+                    plan.name
+                );
+
+                Some(metadata)
+            }
+            Err(err) => {
+                discard_install(target_path);
+                eprintln!(
+                    "Warning: couldn't copy {}@{} from the binary cache, building it instead: {err:#}",
+                    plan.name, plan.version
+                );
+                None
+            }
+        }
+    }
+
+    fn copy_from_binary_cache(
+        &self,
+        entry_path: &Path,
+        key: &CacheKey,
+        require_checksum: bool,
+        target_path: &Path,
+    ) -> Result<VersionMetadata> {
+        let entry_file = entry_path.join(ENTRY_FILE_NAME);
+        let entry: CacheEntry = serde_json::from_str(
+            &fs::read_to_string(&entry_file)
+                .with_context(|| format!("Failed to read {}", entry_file.display()))?,
+        )
+        .with_context(|| format!("{} is corrupt", entry_file.display()))?;
+        ensure!(
+            entry.key == *key,
+            "{} was pushed for another build",
+            entry_path.display()
+        );
+        let cached = VersionMetadata::load(entry_path)?
+            .with_context(|| format!("{} has no metadata", entry_path.display()))?;
+        ensure!(
+            require_checksum.not()
+                || (cached.checksum.is_some() && cached.checksum_unverified.not()),
+            "the checksum of the .crate it was built from wasn't checked when it was pushed"
+        );
+
+        let bin = target_path.join("bin");
+        fs::create_dir_all(&bin).with_context(|| format!("Failed to create {}", bin.display()))?;
+        for (name, checksum) in &entry.checksums {
+            ensure!(
+                is_file_name(name),
+                "{} lists `{}`, which isn't a binary name",
+                entry_file.display(),
+                name.escape_debug()
+            );
+            let copy = bin.join(name);
+            fs::copy(entry_path.join("bin").join(name), &copy)
+                .with_context(|| format!("Failed to copy {name}"))?;
+            let copied = sha256_file(&copy)?;
+            if &copied != checksum {
+                bail!("{name} has the checksum {copied}, but was pushed with {checksum}");
+            }
+        }
+        let lockfile = match cached.lockfile {
+            Some(LockfileStatus::Kept) => {
+                fs::create_dir_all(VersionMetadata::directory(target_path))?;
+                match fs::copy(
+                    VersionMetadata::lockfile_path(entry_path),
+                    VersionMetadata::lockfile_path(target_path),
+                ) {
+                    Ok(_) => Some(LockfileStatus::Kept),
+                    Err(err) => Some(LockfileStatus::Missing {
+                        reason: format!("it couldn't be copied from the binary cache: {err}"),
+                    }),
+                }
+            }
+            lockfile => lockfile,
+        };
+
+        Ok(VersionMetadata {
+            installed_at: Some(metadata::timestamp(SystemTime::now())),
+            lockfile,
+            quarantined: None,
+            binary_cache: Some(CacheProvenance {
+                entry: entry_path.to_owned(),
+                pushed_by: entry.pushed_by,
+                pushed_at: entry.pushed_at,
+                copy_duration_ms: None,
+            }),
+            ..cached
+        })
+    }
+
+    /// Push the version built into `version_path` for `target`, as `metadata` says, to the binary cache, returning
+    /// whether it had to be: the cache may have it already. Fails for versions that aren't for the cache.
+    fn push_to_binary_cache(
+        &self,
+        cache: &Path,
+        package: &str,
+        version_path: &Path,
+        metadata: &VersionMetadata,
+        target: Option<&str>,
+    ) -> Result<bool> {
+        let version = version_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let Some(key) = CacheKey::from_metadata(metadata, target) else {
+            bail!(
+                "{package}@{version} can't go in the binary cache, which only holds builds from crates.io whose \
+                 compiler was recorded"
+            );
+        };
+        // Copies from the cache don't bring along their variant labels, which the key covers
+        let (bare_version, _) = split_variant(&version);
+        let entry_path = Self::cache_entry_path(cache, split_label(package).0, bare_version, &key);
+        if entry_path.join(ENTRY_FILE_NAME).exists() {
+            return Ok(false);
+        }
+        let staging_name = format!(".{}.{}.tmp", key.hash(), process::id());
+        let checksums = metadata
+            .binaries
+            .iter()
+            .map(|binary| {
+                let path = version_path.join("bin").join(&binary.name);
+                Ok((binary.name.clone(), sha256_file(&path)?))
+            })
+            .collect::<Result<_>>()?;
+        let entry = CacheEntry {
+            key,
+            checksums,
+            pushed_by: pusher(),
+            pushed_at: metadata::timestamp(SystemTime::now()),
+        };
+
+        // Written elsewhere first, so that nobody ever copies half an entry
+        let parent = entry_path
+            .parent()
+            .with_context(|| format!("{} has no parent directory", entry_path.display()))?;
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+        let staging = parent.join(staging_name);
+        let _ = fs::remove_dir_all(&staging);
+        let copied = copy_entry(version_path, &staging, &entry).and_then(|()| {
+            let mut recorded = metadata.clone();
+            recorded.binary_cache = None;
+            recorded.save(&staging)
+        });
+        if let Err(err) = copied {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(err);
+        }
+        if fs::rename(&staging, &entry_path).is_err() {
+            let _ = fs::remove_dir_all(&staging);
+            // Somebody else pushed the same build in the meantime
+            ensure!(
+                entry_path.join(ENTRY_FILE_NAME).exists(),
+                "Failed to move the entry for {package}@{version} into {}",
+                parent.display()
+            );
+            return Ok(false);
+        }
+
+        Ok(true)
+    }
+
+    /// Push `package@version`, freshly built for `target` as `metadata` says, to the binary cache, if there's one and
+    /// the version is for it. Quarantined versions aren't, and versions that weren't `smoke_tested` yet are smoke
+    /// tested first. Only worth a warning if it fails.
+    pub(crate) fn push_fresh_build(
+        &self,
+        package: &str,
+        version_path: &Path,
+        metadata: &VersionMetadata,
+        target: Option<&str>,
+        smoke_tested: bool,
+    ) {
+        let Some(cache) = self.config.binary_cache.as_deref() else {
+            return;
+        };
+        if CacheKey::from_metadata(metadata, target).is_none() {
+            return;
+        }
+
+        let version = version_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let quarantined = VersionMetadata::load(version_path)
+            .ok()
+            .flatten()
+            .is_some_and(|metadata| metadata.quarantined.is_some());
+        if quarantined {
+            eprintln!("Warning: not pushing {package}@{version} to the binary cache, since it's quarantined");
+            return;
+        }
+        // Binaries cross-compiled for another target can't be run here
+        if smoke_tested.not() && target.is_none() {
+            if let Some(Err(failure)) = self.smoke_test(package, &version) {
+                eprintln!(
+                    "Warning: not pushing {package}@{version} to the binary cache, since it failed its smoke test. It \
+                     {failure}"
+                );
+                return;
+            }
+        }
+        match self.push_to_binary_cache(cache, package, version_path, metadata, target) {
+            Ok(true) => println!("Pushed {package}@{version} to the binary cache"),
+            Ok(false) => {}
+            Err(err) => eprintln!(
+                "Warning: failed to push {package}@{version} to the binary cache: {err:#}"
+            ),
+        }
+    }
+
+    /// `push`: copy installed versions, as in `ripgrep@14.1.0`, to the binary cache
+    pub fn push(&self, specs: &[String]) -> Result<()> {
+        let Some(cache) = self.config.binary_cache.as_deref() else {
+            bail!(
+                "There's no binary cache to push to, set one through the `binary-cache` config key"
+            );
+        };
+
+        for spec in specs {
+            let (package, version) = parse_spec(spec)?;
+            let package = self.canonical_name(package)?;
+            let version = self.pick_variant(&package, version)?;
+            let version_path = self.version_path(&package, &version);
+            let metadata = VersionMetadata::load(&version_path)?.with_context(|| {
+                format!("Nothing was recorded about how {package}@{version} was built")
+            })?;
+
+            match self.push_to_binary_cache(cache, &package, &version_path, &metadata, None)? {
+                true => println!("Pushed {package}@{version} to the binary cache"),
+                false => println!("The binary cache has {package}@{version} already"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::is_file_name;
+    use super::CacheKey;
+    use crate::installer::BuildFlags;
+
+    #[test]
+    fn only_takes_names_right_inside_the_directory() {
+        assert!(is_file_name("rg"));
+        assert!(is_file_name("cargo-nextest"));
+        for name in [
+            "",
+            ".",
+            "..",
+            "../rg",
+            "bin/rg",
+            "/usr/bin/rg",
+            "../../../.local/bin/x",
+        ] {
+            assert!(is_file_name(name).not(), "{name}");
+        }
+    }
+
+    #[test]
+    fn keys_tell_apart_anything_that_changes_the_build() {
+        let key = CacheKey {
+            target: "x86_64-linux".to_owned(),
+            rustc: "rustc 1.75.0 (82e1608df 2023-12-21)".to_owned(),
+            profile: "release".to_owned(),
+            features: Vec::new(),
+            flags: BuildFlags::default(),
+        };
+        assert_eq!(key.hash(), key.clone().hash());
+        assert_eq!(key.hash().len(), 16);
+
+        let changed = [
+            CacheKey {
+                rustc: "rustc 1.76.0 (07dca489a 2024-02-04)".to_owned(),
+                ..key.clone()
+            },
+            CacheKey {
+                features: vec!["pcre2".to_owned()],
+                ..key.clone()
+            },
+            CacheKey {
+                target: "aarch64-macos".to_owned(),
+                ..key.clone()
+            },
+            CacheKey {
+                flags: BuildFlags {
+                    locked: true,
+                    ..BuildFlags::default()
+                },
+                ..key.clone()
+            },
+        ];
+        for changed in changed {
+            assert_ne!(changed.hash(), key.hash(), "{changed:?}");
+        }
+    }
+}
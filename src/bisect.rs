@@ -0,0 +1,242 @@
+use std::cmp::Ordering;
+use std::env;
+use std::ffi::OsString;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::version::compare_versions;
+use crate::Switcher;
+
+/// Exit code used by `git bisect run` to mean "this revision can't be tested", which we honor as well
+const SKIP_EXIT_CODE: i32 = 125;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Good,
+    Bad,
+    Skip,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct BisectOutcome {
+    /// Index of the first bad candidate, or `None` if every candidate was good (meaning the known-bad bound is the
+    /// first bad version)
+    first_bad: Option<usize>,
+    /// Skipped candidates that sit between the last good and the first bad version, any of which could be the
+    /// actual culprit
+    ambiguous: Vec<usize>,
+}
+
+/// Binary search `candidates`, which are assumed to sit strictly between a known-good and a known-bad version.
+fn search<T>(
+    candidates: &[T],
+    mut test: impl FnMut(&T) -> Result<Verdict>,
+) -> Result<BisectOutcome> {
+    let mut remaining: Vec<usize> = (0..candidates.len()).collect();
+    let mut skipped = Vec::new();
+
+    // Everything in `remaining[..low]` is good and everything in `remaining[high..]` is bad
+    let (mut low, mut high) = (0, remaining.len());
+    while low < high {
+        let middle = low + (high - low) / 2;
+        let index = remaining[middle];
+
+        match test(&candidates[index])? {
+            Verdict::Good => low = middle + 1,
+            Verdict::Bad => high = middle,
+            Verdict::Skip => {
+                skipped.push(index);
+                remaining.remove(middle);
+                high -= 1;
+            }
+        }
+    }
+
+    let first_bad = remaining.get(low).copied();
+    let last_good = low.checked_sub(1).map(|position| remaining[position]);
+
+    let mut ambiguous: Vec<usize> = skipped
+        .into_iter()
+        .filter(|&index| last_good.is_none_or(|good| index > good))
+        .filter(|&index| first_bad.is_none_or(|bad| index < bad))
+        .collect();
+    ambiguous.sort_unstable();
+
+    Ok(BisectOutcome {
+        first_bad,
+        ambiguous,
+    })
+}
+
+impl Switcher {
+    /// Find the first bad version of `package` between `good` and `bad`.
+    ///
+    /// Each candidate is tested by running `command` with that version's binaries prepended to `PATH`, so the links
+    /// in `.cargo/bin` are never touched and the active version stays as it was, even if we're interrupted. If no
+    /// command is given, a shell is spawned for every candidate and the user is asked for a verdict once it exits.
+    pub fn bisect(&self, package: &str, good: &str, bad: &str, command: &[OsString]) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        ensure!(
+            compare_versions(good, bad) == Ordering::Less,
+            "The good version ({good}) must be older than the bad version ({bad})"
+        );
+
+        let candidates: Vec<String> = self
+            .installed_versions(package)?
+            .into_iter()
+            .filter(|version| compare_versions(version, good) == Ordering::Greater)
+            .filter(|version| compare_versions(version, bad) == Ordering::Less)
+            .collect();
+
+        println!(
+            "Bisecting {} installed version(s) of {package} between {good} and {bad}",
+            candidates.len()
+        );
+
+        let outcome = search(&candidates, |version| {
+            let verdict = if command.is_empty() {
+                self.bisect_interactively(package, version)?
+            } else {
+                self.bisect_with_command(package, version, command)?
+            };
+            println!("{package}@{version} is {verdict:?}");
+
+            Ok(verdict)
+        })?;
+
+        let first_bad = outcome
+            .first_bad
+            .map_or(bad, |index| candidates[index].as_str());
+
+        if outcome.ambiguous.is_empty() {
+            println!("The first bad version is {package}@{first_bad}");
+        } else {
+            println!("The first bad version could be any of:");
+            for index in outcome.ambiguous {
+                println!("  - {package}@{}", candidates[index]);
+            }
+            println!("  - {package}@{first_bad}");
+        }
+
+        Ok(())
+    }
+
+    fn bisect_with_command(
+        &self,
+        package: &str,
+        version: &str,
+        command: &[OsString],
+    ) -> Result<Verdict> {
+        let program = Path::new(&command[0]).display();
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .env("PATH", self.path_with_version(package, version)?)
+            .status()
+            .with_context(|| format!("Failed to run `{program}`"))?;
+
+        let Some(code) = status.code() else {
+            bail!("`{program}` was terminated by a signal, aborting the bisect");
+        };
+
+        Ok(match code {
+            0 => Verdict::Good,
+            SKIP_EXIT_CODE => Verdict::Skip,
+            _ => Verdict::Bad,
+        })
+    }
+
+    fn bisect_interactively(&self, package: &str, version: &str) -> Result<Verdict> {
+        let shell = env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into());
+
+        println!("Spawning a shell with {package}@{version} first in PATH. Exit it once you're done testing.");
+        Command::new(&shell)
+            .env("PATH", self.path_with_version(package, version)?)
+            .status()
+            .with_context(|| "Failed to spawn a shell")?;
+
+        let stdin = io::stdin();
+        loop {
+            print!("Is {package}@{version} [g]ood, [b]ad or should it be [s]kipped? ");
+            io::stdout().flush()?;
+
+            let mut answer = String::new();
+            if stdin.read_line(&mut answer)? == 0 {
+                bail!("Bisect aborted");
+            }
+
+            match answer.trim() {
+                "g" | "good" => return Ok(Verdict::Good),
+                "b" | "bad" => return Ok(Verdict::Bad),
+                "s" | "skip" => return Ok(Verdict::Skip),
+                _ => continue,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::search;
+    use super::BisectOutcome;
+    use super::Verdict;
+
+    fn verdicts(first_bad: u32, skips: &[u32]) -> impl FnMut(&u32) -> anyhow::Result<Verdict> + '_ {
+        move |&version| {
+            Ok(if skips.contains(&version) {
+                Verdict::Skip
+            } else if version >= first_bad {
+                Verdict::Bad
+            } else {
+                Verdict::Good
+            })
+        }
+    }
+
+    #[test]
+    fn finds_first_bad_version() {
+        let candidates: Vec<u32> = (0..10).collect();
+
+        for first_bad in 0..10 {
+            let outcome = search(&candidates, verdicts(first_bad, &[])).unwrap();
+            assert_eq!(
+                outcome,
+                BisectOutcome {
+                    first_bad: Some(first_bad as usize),
+                    ambiguous: vec![]
+                }
+            );
+        }
+
+        let outcome = search(&candidates, verdicts(10, &[])).unwrap();
+        assert_eq!(outcome.first_bad, None);
+
+        let outcome = search(&[] as &[u32], verdicts(0, &[])).unwrap();
+        assert_eq!(outcome.first_bad, None);
+    }
+
+    #[test]
+    fn reports_skipped_versions_as_ambiguous() {
+        let candidates: Vec<u32> = (0..10).collect();
+
+        let outcome = search(&candidates, verdicts(5, &[4])).unwrap();
+        assert_eq!(
+            outcome,
+            BisectOutcome {
+                first_bad: Some(5),
+                ambiguous: vec![4]
+            }
+        );
+
+        // Skipping a version far away from the culprit doesn't make the result ambiguous
+        let outcome = search(&candidates, verdicts(7, &[2])).unwrap();
+        assert_eq!(outcome.first_bad, Some(7));
+        assert!(outcome.ambiguous.is_empty());
+    }
+}
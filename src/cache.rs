@@ -0,0 +1,215 @@
+//! What crates.io said about crates, kept under the registry for a while so that looking up the newest versions of
+//! many packages doesn't ask crates.io the same questions over and over.
+//!
+//! The cache only ever informs cargo-switch's own decisions, such as which version is the newest. `cargo install`
+//! goes to crates.io by itself, whatever is cached. Entries that can't be read, for whatever reason, are treated as
+//! missing.
+//!
+//! The cache also holds what `vendor` downloaded for offline installs, see [`vendor`].
+//!
+//! [`vendor`]: crate::vendor
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::download::Validators;
+use crate::metadata::timestamp;
+use crate::Switcher;
+
+/// How long entries stay fresh, unless the `cache-ttl` config key says otherwise
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(30 * 60);
+
+/// Directory, inside the registry, where the cache lives
+pub const CACHE_DIRECTORY_NAME: &str = ".cache";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Entry {
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    response: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_modified: Option<String>,
+}
+
+/// Responses of one API, keyed by what was asked
+#[derive(Debug, Clone)]
+pub struct Cache {
+    directory: PathBuf,
+    ttl: Duration,
+    /// Ignore whatever is cached, only ever storing fresh responses
+    refresh: bool,
+}
+
+impl Cache {
+    pub fn new(directory: impl Into<PathBuf>, ttl: Duration, refresh: bool) -> Self {
+        Self {
+            directory: directory.into(),
+            ttl,
+            refresh,
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(format!("{key}.json"))
+    }
+
+    fn entry(&self, key: &str) -> Option<Entry> {
+        let contents = fs::read_to_string(self.path(key)).ok()?;
+
+        serde_json::from_str(&contents).ok()
+    }
+
+    /// The response cached for `key`, if there's one and it's still fresh
+    pub fn get(&self, key: &str) -> Option<Value> {
+        if self.refresh {
+            return None;
+        }
+
+        let entry = self.entry(key)?;
+        // Entries from the future can't be trusted to be fresh
+        let age = timestamp(SystemTime::now()).checked_sub(entry.fetched_at)?;
+
+        (age < self.ttl.as_secs()).then_some(entry.response)
+    }
+
+    /// The response cached for `key`, however old, along with what tells whether it's still current. Asking the
+    /// server whether it is counts as refreshing.
+    pub fn get_stale(&self, key: &str) -> Option<(Value, Validators)> {
+        let entry = self.entry(key)?;
+        let validators = Validators {
+            etag: entry.etag,
+            last_modified: entry.last_modified,
+        };
+
+        Some((entry.response, validators))
+    }
+
+    /// Cache `response` for `key`. Failing to is no reason to fail whatever asked, so errors are ignored.
+    pub fn put(&self, key: &str, response: &Value, validators: &Validators) {
+        let entry = Entry {
+            fetched_at: timestamp(SystemTime::now()),
+            response: response.clone(),
+            etag: validators.etag.clone(),
+            last_modified: validators.last_modified.clone(),
+        };
+        let Ok(contents) = serde_json::to_string(&entry) else {
+            return;
+        };
+
+        let path = self.path(key);
+        let temporary_path = path.with_extension("json.tmp");
+        let _ = fs::create_dir_all(&self.directory)
+            .and_then(|()| fs::write(&temporary_path, contents))
+            .and_then(|()| fs::rename(&temporary_path, &path));
+    }
+
+    /// How many entries there are, fresh or not
+    pub fn entry_count(&self) -> usize {
+        fs::read_dir(&self.directory).map_or(0, |entries| entries.count())
+    }
+
+    /// Remove every entry, returning how many there were
+    pub fn clear(&self) -> Result<usize> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("Failed to read {}", self.directory.display()))
+            }
+        };
+
+        let mut removed = 0;
+        for maybe_entry in entries {
+            let path = maybe_entry?.path();
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+            removed += 1;
+        }
+
+        Ok(removed)
+    }
+}
+
+impl Switcher {
+    /// The cache of what crates.io said
+    pub fn crates_io_cache(&self) -> Cache {
+        Cache::new(
+            self.registry.join(CACHE_DIRECTORY_NAME).join("crates-io"),
+            self.config.cache_ttl.unwrap_or(DEFAULT_CACHE_TTL),
+            self.refresh,
+        )
+    }
+
+    /// Forget whatever crates.io said
+    pub fn clear_cache(&self) -> Result<()> {
+        let removed = self.crates_io_cache().clear()?;
+        println!("Removed {removed} cached response(s)");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::Cache;
+    use crate::download::Validators;
+
+    #[test]
+    fn expires_entries_and_survives_corruption() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache = Cache::new(directory.path(), Duration::from_secs(60), false);
+        assert_eq!(cache.get("tool"), None);
+
+        let validators = Validators {
+            etag: Some("\"abc\"".to_owned()),
+            last_modified: None,
+        };
+        cache.put(
+            "tool",
+            &json!({"crate": {"max_version": "1.0.0"}}),
+            &validators,
+        );
+        assert_eq!(
+            cache.get("tool"),
+            Some(json!({"crate": {"max_version": "1.0.0"}}))
+        );
+        // Refreshing skips the cache
+        assert_eq!(
+            Cache::new(directory.path(), Duration::from_secs(60), true).get("tool"),
+            None
+        );
+        // As do stale entries, which can still be revalidated
+        let stale = Cache::new(directory.path(), Duration::ZERO, false);
+        assert_eq!(stale.get("tool"), None);
+        assert_eq!(stale.get_stale("tool").unwrap().1, validators);
+
+        let path = directory.path().join("tool.json");
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::write(&path, &contents[..contents.len() / 2]).unwrap();
+        assert_eq!(cache.get("tool"), None);
+        fs::write(&path, r#"{"fetched-at": 99999999999, "response": {}}"#).unwrap();
+        assert_eq!(cache.get("tool"), None);
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.clear().unwrap(), 0);
+        assert_eq!(cache.get("tool"), None);
+    }
+}
@@ -0,0 +1,859 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::mem;
+use std::ops::Not;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cargo_records;
+use crate::checksum::sha256_file;
+use crate::crate_checksum;
+use crate::events;
+use crate::events::ErrorKind;
+use crate::format::human_duration;
+use crate::git;
+use crate::install_root::CargoEnv;
+use crate::installer::BuildOptions;
+use crate::installer::InstallOutcome;
+use crate::installer::Installer;
+use crate::installer::BUILD_ENV_VARIABLES;
+use crate::interrupt;
+use crate::lockfile;
+use crate::retry;
+use crate::retry::Failure;
+use crate::vendor;
+
+/// Whether `path` is a file we could execute
+pub fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Look for an executable called `name` in the directories of `path`, which is formatted like `$PATH`
+pub fn find_in_path(name: impl AsRef<OsStr>, path: &OsStr) -> Option<PathBuf> {
+    env::split_paths(path)
+        .map(|directory| directory.join(name.as_ref()))
+        .find(|candidate| is_executable(candidate))
+}
+
+/// How cargo's output gets to the user. Either way, it's kept to tell network failures apart, so that they can be
+/// retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Cargo's stderr is passed on to our own as it comes, byte for byte, showing its progress bars and colors just
+    /// as it normally would
+    Tee,
+    /// Cargo's stderr is read line by line, to be reported as events and, if `echo` is set, to be printed again
+    Capture { echo: bool },
+}
+
+impl OutputMode {
+    /// Pass cargo's output on as it is whenever someone is watching it and nothing needs it line by line
+    pub fn new(stderr_is_terminal: bool, quiet: bool, events: bool) -> Self {
+        if stderr_is_terminal && quiet.not() && events.not() {
+            OutputMode::Tee
+        } else {
+            OutputMode::Capture { echo: quiet.not() }
+        }
+    }
+
+    /// The `--color` to pass to cargo, leaving the choice to the user's `CARGO_TERM_COLOR`, as in `user_color`, if
+    /// they made one. Cargo can't tell that its output ends up on a terminal when passed on, while captured output
+    /// would otherwise fill logs and events with escape codes.
+    pub fn color(self, user_color: Option<&OsStr>) -> Option<&'static str> {
+        match self {
+            _ if user_color.is_some() => None,
+            OutputMode::Tee => Some("always"),
+            OutputMode::Capture { .. } => Some("never"),
+        }
+    }
+}
+
+/// How many columns the terminal our stderr goes to has, if it goes to one
+fn terminal_width() -> Option<u16> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    let result = unsafe { libc::ioctl(libc::STDERR_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+    (result == 0 && size.ws_col > 0).then_some(size.ws_col)
+}
+
+/// Builds packages with `cargo install`
+#[derive(Debug, Default, Clone)]
+pub struct CargoInstaller {
+    /// The cargo binary to use, from the `cargo-path` config key
+    pub cargo_path: Option<PathBuf>,
+    /// Print which cargo binary is used, and have cargo print the commands it runs
+    pub verbose: bool,
+    /// Only show cargo's output if the build fails
+    pub quiet: bool,
+    /// Whether our stderr is a terminal, someone being there to watch cargo's progress
+    pub stderr_is_terminal: bool,
+}
+
+impl CargoInstaller {
+    /// Find the cargo binary used to install packages.
+    ///
+    /// The `cargo-path` config key wins, followed by `$CARGO` (which cargo sets when running us as a subcommand, so
+    /// it's the cargo the user actually invoked) and finally whatever `cargo` is first in `$PATH`.
+    pub fn cargo(&self) -> Result<PathBuf> {
+        let (cargo, source) = if let Some(cargo) = &self.cargo_path {
+            (cargo.clone(), "the `cargo-path` config key")
+        } else if let Some(cargo) = env::var_os("CARGO") {
+            (PathBuf::from(cargo), "$CARGO")
+        } else if let Some(cargo) = find_in_path("cargo", &env::var_os("PATH").unwrap_or_default())
+        {
+            (cargo, "$PATH")
+        } else {
+            bail!(
+                "Failed to find cargo: $CARGO is not set, `cargo-path` is not configured and there's no `cargo` in \
+                 $PATH. Install Rust through https://rustup.rs or point `cargo-path` to your cargo binary"
+            );
+        };
+
+        ensure!(
+            is_executable(&cargo),
+            "{} (from {source}) is not an executable",
+            cargo.display()
+        );
+        if self.verbose {
+            eprintln!("Using cargo at {} (from {source})", cargo.display());
+        }
+
+        Ok(cargo)
+    }
+
+    /// Fetch the sources of `spec` and copy them into `staging`, resolving their dependencies there unless the
+    /// build is to use their own `Cargo.lock`, see [`lockfile`](crate::lockfile). Returns the crate's directory in
+    /// the copy, which is a member of a workspace at times, and where its sources came from, as cargo records it.
+    fn stage_sources(
+        &self,
+        cargo: &Path,
+        spec: &str,
+        staging: &Path,
+        options: &BuildOptions,
+        started: Instant,
+    ) -> Result<(PathBuf, String), Failure> {
+        let toolchain = options.flags.toolchain.as_deref();
+        let crate_name = spec.split('@').next().unwrap_or(spec);
+
+        let (sources, source) = match options.path {
+            Some(path) => {
+                let path = fs::canonicalize(path)
+                    .with_context(|| format!("Failed to find {}", path.display()))
+                    .map_err(Failure::Permanent)?;
+                let source = format!("path+file://{}", path.display());
+                (path, source)
+            }
+            None => {
+                let version = spec.split_once('@').map(|(_, version)| version);
+                let manifest =
+                    lockfile::fetching_manifest(crate_name, version, options.git, options.rev);
+                let _ = fs::remove_dir_all(staging);
+                fs::create_dir_all(staging)
+                    .and_then(|()| fs::write(staging.join("Cargo.toml"), manifest))
+                    .and_then(|()| fs::write(staging.join("lib.rs"), ""))
+                    .with_context(|| format!("Failed to write to {}", staging.display()))
+                    .map_err(Failure::Permanent)?;
+                let mut command = toolchain_command(cargo, toolchain);
+                command
+                    .arg("metadata")
+                    .arg("--format-version")
+                    .arg("1")
+                    .arg("--manifest-path")
+                    .arg(staging.join("Cargo.toml"));
+                self.run_preparation(command, "metadata", spec, options, started)?;
+
+                let cargo_home = CargoEnv::current()
+                    .cargo_home()
+                    .context("Failed to find cargo's home, as neither $CARGO_HOME nor $HOME is set")
+                    .map_err(Failure::Permanent)?;
+                let sources =
+                    lockfile::built_sources(&cargo_home, spec, None, options.git, options.rev)
+                        .map_err(Failure::Permanent)?;
+                match (options.git, options.rev) {
+                    (Some(url), rev) => {
+                        // Cargo records the whole hash even when given the start of one
+                        let full_rev = git::head_rev(&sources).or(rev.map(str::to_owned));
+                        let source = match (rev, full_rev) {
+                            (Some(rev), Some(full_rev)) => {
+                                format!("git+{url}?rev={rev}#{full_rev}")
+                            }
+                            _ => format!("git+{url}"),
+                        };
+                        // A repository may hold several crates
+                        let sources = lockfile::find_crate(&sources, crate_name)
+                            .map_err(Failure::Permanent)?;
+                        (sources, source)
+                    }
+                    (None, _) => (sources, lockfile::CRATES_IO_SOURCES[0].to_owned()),
+                }
+            }
+        };
+
+        // A member of a workspace is resolved along with the rest of it
+        let mut command = toolchain_command(cargo, toolchain);
+        command
+            .arg("locate-project")
+            .arg("--workspace")
+            .arg("--message-format")
+            .arg("plain")
+            .arg("--manifest-path")
+            .arg(sources.join("Cargo.toml"));
+        let workspace = PathBuf::from(
+            self.run_preparation(command, "locate-project", spec, options, started)?
+                .trim(),
+        );
+        let workspace = workspace.parent().unwrap_or(&sources);
+        let member = sources.strip_prefix(workspace).ok();
+        lockfile::stage_sources(workspace, staging, options.flags.locked)
+            .map_err(Failure::Permanent)?;
+
+        if options.flags.locked.not() {
+            let mut command = toolchain_command(cargo, toolchain);
+            command
+                .arg("generate-lockfile")
+                .arg("--manifest-path")
+                .arg(staging.join("Cargo.toml"));
+            self.run_preparation(command, "generate-lockfile", spec, options, started)?;
+        }
+
+        let sources = match member {
+            Some(member) if member.as_os_str().is_empty().not() => staging.join(member),
+            _ => staging.to_owned(),
+        };
+        Ok((sources, source))
+    }
+
+    /// Run `command`, one of the cargo commands preparing the build of `spec` that was `started` then, returning what
+    /// it printed to stdout. Like builds, it's killed past `--timeout` or when interrupted, and failures caused by
+    /// the network are worth another try.
+    fn run_preparation(
+        &self,
+        mut command: Command,
+        name: &str,
+        spec: &str,
+        options: &BuildOptions,
+        started: Instant,
+    ) -> Result<String, Failure> {
+        let own_group = interrupt::handler_installed();
+        if own_group {
+            command.process_group(0);
+        }
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to execute cargo {name}"))
+            .map_err(Failure::Permanent)?;
+        let _kill_on_interrupt = own_group.then(|| interrupt::kill_on_interrupt(child.id()));
+        let watchdog = options.timeout.map(|timeout| {
+            Watchdog::spawn(spec, child.id(), own_group, started + timeout, self.verbose)
+        });
+
+        // Read both at once, or whichever fills its pipe first would block the other
+        let stderr = child.stderr.take().map(|mut stderr| {
+            thread::spawn(move || {
+                let mut output = Vec::new();
+                let _ = stderr.read_to_end(&mut output);
+                output
+            })
+        });
+        let mut stdout = Vec::new();
+        if let Some(mut pipe) = child.stdout.take() {
+            let _ = pipe.read_to_end(&mut stdout);
+        }
+        let stderr = stderr
+            .and_then(|stderr| stderr.join().ok())
+            .unwrap_or_default();
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait on cargo {name}"))
+            .map_err(Failure::Permanent)?;
+        let timed_out = watchdog.is_some_and(Watchdog::stop);
+        if status.success() {
+            return Ok(String::from_utf8_lossy(&stdout).into_owned());
+        }
+
+        let stderr = String::from_utf8_lossy(&stderr);
+        eprint!("{stderr}");
+        if let (true, Some(timeout)) = (timed_out, options.timeout) {
+            return Err(timed_out_failure(spec, timeout, &stderr));
+        }
+        let err = match first_error(&stderr) {
+            Some(error) => anyhow!("cargo {name} exited with {status}: {error}"),
+            None => anyhow!("cargo {name} exited with {status}"),
+        };
+        if retry::looks_like_network_error(&stderr) {
+            Err(Failure::Transient(err))
+        } else {
+            Err(Failure::Permanent(err))
+        }
+    }
+}
+
+/// The failure of the build of `spec`, killed after running for `timeout` while printing `output`. Only worth
+/// another try if it was stuck on the network.
+fn timed_out_failure(spec: &str, timeout: Duration, output: &str) -> Failure {
+    let err = events::classify(
+        anyhow!(
+            "cargo install {spec} was killed after running for {}, past --timeout",
+            human_duration(timeout)
+        ),
+        ErrorKind::TimedOut,
+    );
+
+    if retry::looks_like_network_error(output) {
+        Failure::Transient(err)
+    } else {
+        Failure::Permanent(err)
+    }
+}
+
+/// The SHA-256 checksum of the `.crate` file cargo downloaded to build `spec` from, if it was built from a registry
+/// and the file is still around
+fn built_crate_checksum(spec: &str, options: &BuildOptions) -> Option<String> {
+    if options.path.is_some() || options.git.is_some() || options.vendored.is_some() {
+        return None;
+    }
+
+    let (crate_name, version) = spec.split_once('@')?;
+    let cargo_home = CargoEnv::current().cargo_home()?;
+    let path = crate_checksum::cached_crate(&cargo_home, crate_name, version)?;
+    sha256_file(&path).ok()
+}
+
+/// How often `--verbose` tells how long a build has left before timing out
+const TIMEOUT_PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Kills a build that's still running at its deadline, along with its process group if it has one of its own
+struct Watchdog {
+    /// Dropped to call the watchdog off
+    stop: mpsc::Sender<()>,
+    fired: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    fn spawn(spec: &str, pid: u32, own_group: bool, deadline: Instant, verbose: bool) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let fired = Arc::new(AtomicBool::new(false));
+        let spec = spec.to_owned();
+        let watchdog_fired = Arc::clone(&fired);
+        thread::spawn(move || loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                watchdog_fired.store(true, Ordering::SeqCst);
+                eprintln!("Building {spec} timed out, killing it");
+                if own_group {
+                    interrupt::kill_group(pid);
+                } else {
+                    unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) };
+                }
+                return;
+            }
+            if verbose {
+                eprintln!(
+                    "Building {spec}, {} left before timing out",
+                    human_duration(remaining)
+                );
+            }
+            match stopped.recv_timeout(remaining.min(TIMEOUT_PROGRESS_INTERVAL)) {
+                Err(RecvTimeoutError::Timeout) => {}
+                Ok(()) | Err(RecvTimeoutError::Disconnected) => return,
+            }
+        });
+
+        Self { stop, fired }
+    }
+
+    /// Call the watchdog off, returning whether it killed the build already
+    fn stop(self) -> bool {
+        drop(self.stop);
+        self.fired.load(Ordering::SeqCst)
+    }
+}
+
+impl Installer for CargoInstaller {
+    /// What `rustc --version` says, asking the rustc next to our cargo, as rustup has it, or else the one in `$PATH`
+    fn rustc_version(&self, toolchain: Option<&str>) -> Option<String> {
+        let rustc = self.cargo().ok()?.with_file_name("rustc");
+        let rustc = if is_executable(&rustc) {
+            rustc
+        } else {
+            PathBuf::from("rustc")
+        };
+        let output = toolchain_command(&rustc, toolchain)
+            .arg("--version")
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        if output.status.success().not() {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+
+    fn fetches_from_crates_io(&self) -> bool {
+        true
+    }
+
+    /// Run `cargo install` once, telling apart failures caused by the network from the ones that would happen
+    /// again
+    fn install(
+        &self,
+        spec: &str,
+        root: &Path,
+        options: &BuildOptions,
+    ) -> Result<InstallOutcome, Failure> {
+        let cargo = self.cargo().map_err(Failure::Permanent)?;
+        let started = Instant::now();
+
+        // Every build is locked, to the `Cargo.lock` that's kept of it
+        let staging = root.join(lockfile::STAGING_DIRECTORY_NAME);
+        let (sources, staged_source) = match (options.locked, options.vendored) {
+            (Some(sources), _) => (sources.to_owned(), None),
+            (None, Some(vendored)) => (vendored.join(vendor::SOURCE_DIRECTORY_NAME), None),
+            (None, None) => {
+                let (sources, source) = self
+                    .stage_sources(&cargo, spec, &staging, options, started)
+                    .inspect_err(|_| {
+                        let _ = fs::remove_dir_all(&staging);
+                    })?;
+                (sources, Some(source))
+            }
+        };
+        let lockfile_path = match staged_source {
+            Some(_) => staging.join("Cargo.lock"),
+            None => sources.join("Cargo.lock"),
+        };
+
+        let mut command = toolchain_command(&cargo, options.flags.toolchain.as_deref());
+        command
+            .arg("install")
+            .arg("--path")
+            .arg(&sources)
+            .arg("--locked");
+        // Vendored sources are built offline
+        if let (None, Some(vendored)) = (options.locked, options.vendored) {
+            command
+                .arg("--offline")
+                .arg("--config")
+                .arg(vendored.join(vendor::CARGO_CONFIG_FILE_NAME));
+        }
+        if let Some(target) = options.target {
+            command.arg("--target").arg(target);
+        }
+        if options.features.is_empty().not() {
+            command.arg("--features").arg(options.features.join(","));
+        }
+        if options.flags.no_default_features {
+            command.arg("--no-default-features");
+        }
+        command.arg("--root").arg(root);
+        match options.profile {
+            "release" => {}
+            "dev" => {
+                command.arg("--debug");
+            }
+            profile => {
+                command.arg("--profile").arg(profile);
+            }
+        }
+
+        command.args(&options.flags.extra_args);
+        // Only what was recorded, so that rebuilds don't pick up whatever the environment holds now
+        if let Some(env) = &options.flags.env {
+            for variable in BUILD_ENV_VARIABLES {
+                command.env_remove(variable);
+            }
+            command.envs(env);
+        }
+
+        if self.verbose {
+            command.arg("--verbose");
+        }
+        if self.quiet {
+            command.arg("--quiet");
+        }
+        let mode = OutputMode::new(
+            self.stderr_is_terminal,
+            self.quiet,
+            options.events.is_some(),
+        );
+        if let Some(color) = mode.color(env::var_os("CARGO_TERM_COLOR").as_deref()) {
+            command.arg("--color").arg(color);
+        }
+        // Nor can it tell that there's room for its progress bar
+        if let (OutputMode::Tee, None, Some(width)) = (
+            mode,
+            env::var_os("CARGO_TERM_PROGRESS_WHEN"),
+            terminal_width(),
+        ) {
+            command
+                .env("CARGO_TERM_PROGRESS_WHEN", "always")
+                .env("CARGO_TERM_PROGRESS_WIDTH", width.to_string());
+        }
+
+        // In a process group of its own, the build can be stopped along with every `rustc` it spawned
+        let own_group = interrupt::handler_installed();
+        if own_group {
+            command.process_group(0);
+        }
+
+        let mut child = command
+            .stdout(Stdio::inherit())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| "Failed to execute cargo install")
+            .map_err(Failure::Permanent)?;
+        let _kill_on_interrupt = own_group.then(|| interrupt::kill_on_interrupt(child.id()));
+        let watchdog = options.timeout.map(|timeout| {
+            Watchdog::spawn(spec, child.id(), own_group, started + timeout, self.verbose)
+        });
+
+        // Keep the output around to figure out whether a failure was network-related
+        let mut output = String::new();
+        match (mode, child.stderr.take()) {
+            (OutputMode::Tee, Some(mut stderr)) => {
+                let mut passed_on = Vec::new();
+                let mut buffer = [0; 8192];
+                loop {
+                    match stderr.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(read) => {
+                            let mut our_stderr = io::stderr();
+                            let _ = our_stderr.write_all(&buffer[..read]);
+                            let _ = our_stderr.flush();
+                            passed_on.extend_from_slice(&buffer[..read]);
+                        }
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(_) => break,
+                    }
+                }
+                output = String::from_utf8_lossy(&passed_on).into_owned();
+            }
+            (OutputMode::Capture { echo }, Some(stderr)) => {
+                for line in io::BufReader::new(stderr).lines() {
+                    if let Ok(line) = line {
+                        if echo {
+                            eprintln!("{}", line);
+                        }
+                        options.output(&line);
+                        output.push_str(&line);
+                        output.push('\n');
+                    }
+                }
+            }
+            (_, None) => {}
+        }
+
+        let status = child
+            .wait()
+            .with_context(|| "Failed to wait on cargo install")
+            .map_err(Failure::Permanent)?;
+        let timed_out = watchdog.is_some_and(Watchdog::stop);
+
+        let lockfile = status.success().then(|| {
+            fs::read_to_string(&lockfile_path)
+                .with_context(|| format!("Failed to read {}", lockfile_path.display()))
+                .map_err(|err| format!("{err:#}"))
+        });
+        if let Some(source) = &staged_source {
+            let _ = fs::remove_dir_all(&staging);
+            if let Err(err) = status
+                .success()
+                .then(|| cargo_records::record_source(root, source))
+                .transpose()
+            {
+                eprintln!("Warning: failed to record where {spec} came from: {err:#}");
+            }
+        }
+        if let Some(lockfile) = lockfile {
+            let build_duration = started.elapsed();
+            return Ok(InstallOutcome {
+                build_duration,
+                lockfile,
+                crate_checksum: built_crate_checksum(spec, options),
+            });
+        }
+
+        // Whoever asked for quiet still needs to know why the build failed
+        if mode == (OutputMode::Capture { echo: false }) {
+            eprint!("{output}");
+        }
+        if let (true, Some(timeout)) = (timed_out, options.timeout) {
+            return Err(timed_out_failure(spec, timeout, &output));
+        }
+        // The generic failure is of no help to whoever couldn't see cargo's output
+        let err = match first_error(&output) {
+            Some(error) => anyhow!("cargo install exited with {status}: {error}"),
+            None => anyhow!("cargo install exited with {status}"),
+        };
+        if retry::looks_like_network_error(&output) {
+            Err(Failure::Transient(err))
+        } else {
+            Err(Failure::Permanent(err))
+        }
+    }
+}
+
+/// A command running `program`, or the program of the same name of the rustup toolchain `toolchain` if there's one.
+/// `+toolchain` only means something to rustup's proxies, and the cargo running us as a subcommand is the toolchain's
+/// own binary rather than a proxy, so toolchains are picked through `rustup run` instead.
+fn toolchain_command(program: &Path, toolchain: Option<&str>) -> Command {
+    let Some(toolchain) = toolchain else {
+        return Command::new(program);
+    };
+
+    let mut command = Command::new("rustup");
+    command
+        .arg("run")
+        .arg(toolchain)
+        .arg(program.file_name().unwrap_or(program.as_os_str()));
+    command
+}
+
+/// The first error cargo reported in `output`, as in `error: could not find `tool` in registry`
+fn first_error(output: &str) -> Option<&str> {
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("error"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::ops::Not;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use super::toolchain_command;
+    use super::CargoInstaller;
+    use super::OutputMode;
+    use crate::events::error_kind;
+    use crate::events::ErrorKind;
+    use crate::events::Event;
+    use crate::events::EventSink;
+    use crate::installer::BuildOptions;
+    use crate::installer::Installer;
+    use crate::retry::Failure;
+
+    #[test]
+    fn picks_toolchains_through_rustup() {
+        let cargo = Path::new("/home/me/.rustup/toolchains/stable/bin/cargo");
+        let command = toolchain_command(cargo, None);
+        assert_eq!(command.get_program(), cargo);
+        assert_eq!(command.get_args().count(), 0);
+
+        let command = toolchain_command(cargo, Some("1.75"));
+        assert_eq!(command.get_program(), "rustup");
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["run", "1.75", "cargo"]);
+    }
+
+    #[test]
+    fn passes_output_on_only_when_watched_and_not_needed() {
+        let capture = |echo| OutputMode::Capture { echo };
+        // Terminal, quiet, events
+        assert_eq!(OutputMode::new(true, false, false), OutputMode::Tee);
+        assert_eq!(OutputMode::new(true, true, false), capture(false));
+        assert_eq!(OutputMode::new(true, false, true), capture(true));
+        assert_eq!(OutputMode::new(true, true, true), capture(false));
+        assert_eq!(OutputMode::new(false, false, false), capture(true));
+        assert_eq!(OutputMode::new(false, true, false), capture(false));
+        assert_eq!(OutputMode::new(false, false, true), capture(true));
+
+        assert_eq!(OutputMode::Tee.color(None), Some("always"));
+        assert_eq!(capture(true).color(None), Some("never"));
+        assert_eq!(capture(true).color(Some(OsStr::new("always"))), None);
+        assert_eq!(OutputMode::Tee.color(Some(OsStr::new("never"))), None);
+    }
+
+    #[derive(Debug, Default)]
+    struct Chunks(RefCell<Vec<String>>);
+
+    impl EventSink for Chunks {
+        fn emit(&self, event: &Event) {
+            if let Event::CargoOutput { chunk } = event {
+                self.0.borrow_mut().push(chunk.clone());
+            }
+        }
+    }
+
+    /// A cargo that prepares builds of the crate in `project` as if it was its own workspace, then records its
+    /// arguments and where its stderr goes and fails as if the network was down
+    fn fake_cargo(directory: &Path) -> CargoInstaller {
+        let cargo = directory.join("cargo");
+        fs::write(
+            &cargo,
+            format!(
+                "#!/bin/sh\n\
+                 case $1 in\n\
+                 locate-project) echo {0}/project/Cargo.toml; exit 0 ;;\n\
+                 generate-lockfile) exit 0 ;;\n\
+                 esac\n\
+                 echo \"$@\" > {0}/args\n\
+                 readlink /proc/$$/fd/2 > {0}/stderr\n\
+                 echo 'error: Couldn'\"'\"'t resolve host name' >&2\n\
+                 exit 101\n",
+                directory.display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+
+        CargoInstaller {
+            cargo_path: Some(cargo),
+            ..CargoInstaller::default()
+        }
+    }
+
+    #[test]
+    fn runs_cargo_as_the_output_mode_says() {
+        let directory = tempfile::tempdir().unwrap();
+        let read = |name: &str| fs::read_to_string(directory.path().join(name)).unwrap();
+        let root = directory.path().join("root");
+        let project = directory.path().join("project");
+        fs::create_dir(&project).unwrap();
+        fs::write(project.join("Cargo.toml"), "").unwrap();
+        let options = BuildOptions {
+            path: Some(&project),
+            ..BuildOptions::default()
+        };
+
+        // Someone is watching, so cargo's stderr is passed on in colors, and kept to tell its failures apart
+        let installer = CargoInstaller {
+            stderr_is_terminal: true,
+            ..fake_cargo(directory.path())
+        };
+        let outcome = installer.install("tool@1.0.0", &root, &options);
+        assert!(matches!(outcome, Err(Failure::Transient(_))));
+        assert!(read("stderr").starts_with("pipe:"));
+        assert_eq!(
+            read("args").contains("--color always"),
+            env::var_os("CARGO_TERM_COLOR").is_none()
+        );
+
+        // Events need the output, which then shows network failures for what they are
+        let chunks = Chunks::default();
+        let watched = BuildOptions {
+            events: Some(&chunks),
+            ..options
+        };
+        let outcome = installer.install("tool@1.0.0", &root, &watched);
+        assert!(matches!(outcome, Err(Failure::Transient(_))));
+        assert!(read("stderr").starts_with("pipe:"));
+        // Unless the user chose for themselves
+        assert_eq!(
+            read("args").contains("--color never"),
+            env::var_os("CARGO_TERM_COLOR").is_none()
+        );
+        assert_eq!(*chunks.0.borrow(), ["error: Couldn't resolve host name"]);
+
+        // Nobody is watching
+        let installer = CargoInstaller {
+            quiet: true,
+            ..fake_cargo(directory.path())
+        };
+        let outcome = installer.install("tool@1.0.0", &root, &options);
+        let Err(Failure::Transient(err)) = outcome else {
+            panic!("expected the build to fail as if the network was down");
+        };
+        assert_eq!(
+            err.to_string(),
+            "cargo install exited with exit status: 101: error: Couldn't resolve host name"
+        );
+        assert!(read("stderr").starts_with("pipe:"));
+        assert!(read("args").contains("--quiet"));
+        // Built locked from a copy of the workspace, which is gone once the build is
+        assert!(read("args").starts_with(&format!(
+            "install --path {}/.lockfile --locked",
+            root.display()
+        )));
+        assert!(root.join(".lockfile").exists().not());
+
+        // Vendored sources are built from where they are, without the network
+        let vendored = directory.path().join("vendored");
+        let options = BuildOptions {
+            vendored: Some(&vendored),
+            ..BuildOptions::default()
+        };
+        let _ = installer.install("tool@1.0.0", &root, &options);
+        assert!(read("args").starts_with(&format!(
+            "install --path {0}/source --locked --offline --config {0}/cargo-config.toml",
+            vendored.display()
+        )));
+    }
+
+    #[test]
+    fn kills_builds_that_run_past_their_timeout() {
+        let directory = tempfile::tempdir().unwrap();
+        let cargo = directory.path().join("cargo");
+        fs::write(&cargo, "#!/bin/sh\nexec sleep 30\n").unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+        let installer = CargoInstaller {
+            cargo_path: Some(cargo),
+            quiet: true,
+            ..CargoInstaller::default()
+        };
+
+        let started = Instant::now();
+        let options = BuildOptions {
+            locked: Some(directory.path()),
+            timeout: Some(Duration::from_millis(200)),
+            ..BuildOptions::default()
+        };
+        let outcome = installer.install("tool@1.0.0", &directory.path().join("root"), &options);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let Err(Failure::Permanent(err)) = outcome else {
+            panic!("expected the build to time out for good");
+        };
+        assert_eq!(error_kind(&err), ErrorKind::TimedOut);
+
+        // Preparing the build counts towards its timeout too
+        let started = Instant::now();
+        let options = BuildOptions {
+            path: Some(directory.path()),
+            timeout: Some(Duration::from_millis(200)),
+            ..BuildOptions::default()
+        };
+        let outcome = installer.install("tool@1.0.0", &directory.path().join("root"), &options);
+        assert!(started.elapsed() < Duration::from_secs(10));
+        let Err(Failure::Permanent(err)) = outcome else {
+            panic!("expected preparing the build to time out for good");
+        };
+        assert_eq!(error_kind(&err), ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,315 @@
+//! Keeping the records cargo keeps in the install root `.cargo/bin` belongs to in line with what's linked there.
+//!
+//! Cargo decides what it installed, and so what it may overwrite, from `.crates.toml` and `.crates2.json`. Versions
+//! are installed into roots of their own, so the main root's records go stale as soon as we switch: a later
+//! `cargo install` of the package would happily replace the links with binaries of its own. Whenever a package is
+//! switched to, its records are replaced by the ones of the version now linked, and they go away along with the
+//! links.
+//!
+//! Cargo holds an exclusive lock on both files while it works with them, and so do we. Records cargo would refuse to
+//! read are left alone, as are install roots where cargo never recorded anything.
+
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::mem;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::crates_json::CratesJson;
+use crate::crates_json::InstallInfo;
+use crate::crates_json::CRATES_JSON_FILE_NAME;
+use crate::crates_toml::CratesToml;
+use crate::crates_toml::CRATES_TOML_FILE_NAME;
+use crate::lockfile::STAGING_DIRECTORY_NAME;
+use crate::spec::split_label;
+use crate::Switcher;
+
+/// The name of the package in a package id, as in `ripgrep 14.1.0 (registry+https://…)`
+fn package_name(id: &str) -> &str {
+    id.split(' ').next().unwrap_or(id)
+}
+
+/// Both records of an install root, locked for as long as they live
+struct Records {
+    toml_file: File,
+    json_file: File,
+    toml: CratesToml,
+    json: CratesJson,
+}
+
+/// Open and lock the record at `path`, creating it if needed, and read it
+fn open_locked(path: &Path) -> Result<(File, String)> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => bail!("cargo is busy with {}", path.display()),
+        Err(TryLockError::Error(err)) => {
+            return Err(err).with_context(|| format!("Failed to lock {}", path.display()))
+        }
+    }
+
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+
+    Ok((file, contents))
+}
+
+/// Replace the contents of the locked `file`, at `path`. Renaming a new file over it would leave whoever waits on
+/// the lock with the old one.
+fn overwrite(mut file: &File, path: &Path, contents: &str) -> Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+impl Records {
+    fn open(root: &Path) -> Result<Self> {
+        let toml_path = root.join(CRATES_TOML_FILE_NAME);
+        let json_path = root.join(CRATES_JSON_FILE_NAME);
+
+        let (toml_file, contents) = open_locked(&toml_path)?;
+        let toml = CratesToml::parse(&contents).with_context(|| {
+            format!(
+                "{} is corrupt, cargo won't install anything until it's fixed",
+                toml_path.display()
+            )
+        })?;
+        let (json_file, contents) = open_locked(&json_path)?;
+        let json = CratesJson::parse(&contents).with_context(|| {
+            format!(
+                "{} is corrupt, cargo won't install anything until it's fixed",
+                json_path.display()
+            )
+        })?;
+
+        Ok(Self {
+            toml_file,
+            json_file,
+            toml,
+            json,
+        })
+    }
+
+    /// The records of a version's own install root, if cargo left readable ones there
+    fn of_version(version_path: &Path) -> (CratesToml, CratesJson) {
+        let read = |name: &str| fs::read_to_string(version_path.join(name)).unwrap_or_default();
+        let toml = CratesToml::parse(&read(CRATES_TOML_FILE_NAME)).unwrap_or_default();
+        let json = CratesJson::parse(&read(CRATES_JSON_FILE_NAME)).unwrap_or_default();
+
+        (toml, json)
+    }
+
+    fn save(&self, root: &Path) -> Result<()> {
+        overwrite(
+            &self.toml_file,
+            &root.join(CRATES_TOML_FILE_NAME),
+            &self.toml.render()?,
+        )?;
+        overwrite(
+            &self.json_file,
+            &root.join(CRATES_JSON_FILE_NAME),
+            &self.json.render()?,
+        )
+    }
+
+    /// Forget every install of `package`, along with whatever other install claims one of `binaries`
+    fn forget(&mut self, package: &str, binaries: &[String]) -> bool {
+        let before = (self.toml.v1.len(), self.json.installs.len());
+
+        for bins in self.toml.v1.values_mut() {
+            bins.retain(|bin| binaries.contains(bin).not());
+        }
+        self.toml
+            .v1
+            .retain(|id, bins| package_name(id) != package && bins.is_empty().not());
+        for install in self.json.installs.values_mut() {
+            install.bins.retain(|bin| binaries.contains(bin).not());
+        }
+        self.json
+            .installs
+            .retain(|id, install| package_name(id) != package && install.bins.is_empty().not());
+
+        before != (self.toml.v1.len(), self.json.installs.len())
+    }
+
+    /// Record the installs cargo built from a copy of their sources, see [`lockfile`](crate::lockfile), as coming
+    /// from `source` instead, as in `registry+https://github.com/rust-lang/crates.io-index`
+    fn move_source(&mut self, source: &str) -> bool {
+        // Cargo percent-encodes the path, which the staging directory's name is left alone by
+        let staged = |id: &str| {
+            id.split_once(" (path+file://").is_some_and(|(_, path)| {
+                Path::new(path.trim_end_matches(')'))
+                    .iter()
+                    .any(|component| component == STAGING_DIRECTORY_NAME)
+            })
+        };
+        let moved = |id: String| match id.split_once(" (") {
+            Some((name_version, _)) if staged(&id) => format!("{name_version} ({source})"),
+            _ => id,
+        };
+        let before = (self.toml.v1.clone(), self.json.installs.clone());
+
+        self.toml.v1 = mem::take(&mut self.toml.v1)
+            .into_iter()
+            .map(|(id, bins)| (moved(id), bins))
+            .collect();
+        self.json.installs = mem::take(&mut self.json.installs)
+            .into_iter()
+            .map(|(id, install)| (moved(id), install))
+            .collect();
+
+        before != (self.toml.v1.clone(), self.json.installs.clone())
+    }
+
+    /// Record the installs of a version's own records, keeping both files in line with each other as cargo does
+    fn record(&mut self, toml: CratesToml, mut json: CratesJson) {
+        for (id, bins) in toml.v1 {
+            json.installs
+                .entry(id.clone())
+                .or_insert_with(|| InstallInfo {
+                    bins: bins.clone(),
+                    ..InstallInfo::default()
+                });
+            self.toml.v1.insert(id, bins);
+        }
+        for (id, install) in json.installs {
+            self.toml
+                .v1
+                .entry(id.clone())
+                .or_insert_with(|| install.bins.clone());
+            self.json.installs.insert(id, install);
+        }
+    }
+}
+
+impl Switcher {
+    /// The install root cargo itself would install into `.cargo/bin` through, if it ever did
+    fn cargo_records_root(&self) -> Option<PathBuf> {
+        if self.cargo_bin.file_name()? != "bin" {
+            return None;
+        }
+        let root = self.cargo_bin.parent()?;
+
+        root.join(CRATES_TOML_FILE_NAME)
+            .exists()
+            .then(|| root.to_owned())
+    }
+
+    fn update_cargo_records(&self, update: impl FnOnce(&mut Records) -> bool) -> Result<()> {
+        let Some(root) = self.cargo_records_root() else {
+            return Ok(());
+        };
+
+        let mut records = Records::open(&root)?;
+        if update(&mut records) {
+            records.save(&root)?;
+        }
+
+        Ok(())
+    }
+
+    /// Tell cargo that `binaries` in `.cargo/bin` now belong to `package@version`. Failing to is only worth a
+    /// warning, since the links themselves are fine either way.
+    pub(crate) fn record_switch(&self, package: &str, version: &str, binaries: &[OsString]) {
+        let binaries: Vec<_> = binaries
+            .iter()
+            .filter_map(|binary| binary.to_str())
+            .map(str::to_owned)
+            .collect();
+        let (toml, json) = Records::of_version(&self.version_path(package, version));
+
+        // Cargo only knows the crate, which every fork of it recorded under
+        let (crate_name, _) = split_label(package);
+        let result = self.update_cargo_records(|records| {
+            let forgot = records.forget(crate_name, &binaries);
+            let recorded = toml.v1.is_empty().not() || json.installs.is_empty().not();
+            records.record(toml, json);
+            forgot || recorded
+        });
+        if let Err(err) = result {
+            eprintln!("Warning: failed to update cargo's records of {package}: {err:#}");
+        }
+    }
+
+    /// Tell cargo that `package` isn't installed anymore, after its links were removed
+    pub(crate) fn forget_cargo_records(&self, package: &str) {
+        let (crate_name, _) = split_label(package);
+        if let Err(err) = self.update_cargo_records(|records| records.forget(crate_name, &[])) {
+            eprintln!("Warning: failed to update cargo's records of {package}: {err:#}");
+        }
+    }
+}
+
+/// Tell cargo that what it installed into the version root `root` from a copy of its sources came from `source`,
+/// where it would have fetched them from itself
+pub(crate) fn record_source(root: &Path, source: &str) -> Result<()> {
+    let mut records = Records::open(root)?;
+    if records.move_source(source) {
+        records.save(root)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::package_name;
+    use super::record_source;
+    use super::Records;
+    use crate::crates_json::CRATES_JSON_FILE_NAME;
+    use crate::crates_toml::CRATES_TOML_FILE_NAME;
+
+    #[test]
+    fn reads_package_names() {
+        assert_eq!(
+            package_name("ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)"),
+            "ripgrep"
+        );
+        assert_eq!(package_name("tool"), "tool");
+    }
+
+    #[test]
+    fn records_where_staged_builds_came_from() {
+        let root = tempfile::tempdir().unwrap();
+        let staged = "tool 1.0.0 (path+file:///home/me/registry/tool/1.0.0/.lockfile)";
+        let other = "other 2.0.0 (path+file:///home/me/other)";
+        fs::write(
+            root.path().join(CRATES_TOML_FILE_NAME),
+            format!("[v1]\n\"{staged}\" = [\"tool\"]\n\"{other}\" = [\"other\"]\n"),
+        )
+        .unwrap();
+        fs::write(
+            root.path().join(CRATES_JSON_FILE_NAME),
+            format!(r#"{{"installs":{{"{staged}":{{"bins":["tool"]}}}}}}"#),
+        )
+        .unwrap();
+
+        let source = "registry+https://github.com/rust-lang/crates.io-index";
+        record_source(root.path(), source).unwrap();
+        let (toml, json) = Records::of_version(root.path());
+        let moved = format!("tool 1.0.0 ({source})");
+        assert_eq!(toml.v1.keys().collect::<Vec<_>>(), [other, moved.as_str()]);
+        assert_eq!(json.installs.keys().collect::<Vec<_>>(), [&moved]);
+    }
+}
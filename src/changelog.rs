@@ -0,0 +1,492 @@
+//! `changelog`: what changed between the active version of a package and its latest release, so that updating
+//! brings no surprises.
+//!
+//! The crate's repository, as crates.io knows it, is looked for a `CHANGELOG.md` or the like, whose sections for the
+//! versions in between are shown. Repositories on GitHub that have no such file may still have release notes. When
+//! neither is there, the repository and the versions in between are all there is to show.
+
+use std::env;
+use std::fmt::Write as _;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::ops::Not;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::Context;
+use anyhow::Result;
+use semver::Version;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::download;
+use crate::download::Fetched;
+use crate::download::Validators;
+use crate::retry;
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::spec::split_label;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// The files changelogs are usually kept in, in the order they're looked for
+const CHANGELOG_FILE_NAMES: [&str; 4] = ["CHANGELOG.md", "CHANGELOG", "CHANGES.md", "HISTORY.md"];
+
+/// What pages the output when `$PAGER` isn't set
+const DEFAULT_PAGER: &str = "less -FRX";
+
+/// The part of a changelog about one version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Section {
+    pub version: String,
+    /// The heading as the changelog words it, as in `14.1.0 (2024-01-06)`
+    pub title: String,
+    pub body: String,
+}
+
+/// Where the sections came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum ChangelogSource {
+    /// A changelog file, fetched from `url`
+    File { url: String },
+    /// The release notes of a GitHub repository
+    GithubReleases { url: String },
+    /// Nothing could be fetched
+    None,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Changelog {
+    pub package: String,
+    pub current: String,
+    pub latest: String,
+    pub repository: Option<String>,
+    pub source: ChangelogSource,
+    /// From the newest version to the oldest
+    pub sections: Vec<Section>,
+}
+
+/// The version a changelog heading or a tag is about, as in `## [14.1.0] - 2024-01-06` or `ripgrep-14.1.0`
+pub fn mentioned_version(text: &str) -> Option<Version> {
+    let tokens = text.split(|c: char| c.is_whitespace() || "[]()/:,;*`'\"".contains(c));
+    for token in tokens {
+        let token = token.trim_end_matches('.');
+        for (index, c) in token.char_indices() {
+            let starts_number = index == 0
+                || token[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|previous| previous.is_ascii_digit().not() && previous != '.');
+            if c.is_ascii_digit() && starts_number {
+                if let Ok(version) = Version::parse(&token[index..]) {
+                    return Some(version);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Whether `version` is newer than `current` and not newer than `latest`
+fn in_range(version: &Version, current: &Version, latest: &Version) -> bool {
+    version > current && version <= latest
+}
+
+/// The sections of a Markdown `changelog` about the versions after `current` up to `latest`. Sections start at the
+/// headings that mention a version and go on until the next heading of the same level or above.
+pub fn changelog_sections(changelog: &str, current: &Version, latest: &Version) -> Vec<Section> {
+    let heading = |line: &str| {
+        let level = line.chars().take_while(|&c| c == '#').count();
+        let title = line[level..].trim();
+        (level > 0 && line[level..].starts_with(' ')).then_some((level, title.to_owned()))
+    };
+
+    // The level of the first heading that mentions a version is the one every version is at
+    let level = changelog.lines().find_map(|line| {
+        let (level, title) = heading(line)?;
+        mentioned_version(&title).map(|_| level)
+    });
+    let Some(level) = level else {
+        return Vec::new();
+    };
+
+    let mut sections = Vec::new();
+    let mut current_section: Option<(Version, String, String)> = None;
+    for line in changelog.lines() {
+        if let Some((line_level, title)) = heading(line) {
+            if line_level <= level {
+                if let Some((version, title, body)) = current_section.take() {
+                    if in_range(&version, current, latest) {
+                        sections.push(Section {
+                            version: version.to_string(),
+                            title,
+                            body: body.trim().to_owned(),
+                        });
+                    }
+                }
+                if line_level == level {
+                    current_section =
+                        mentioned_version(&title).map(|version| (version, title, String::new()));
+                }
+                continue;
+            }
+        }
+        if let Some((_, _, body)) = &mut current_section {
+            body.push_str(line);
+            body.push('\n');
+        }
+    }
+    if let Some((version, title, body)) = current_section {
+        if in_range(&version, current, latest) {
+            sections.push(Section {
+                version: version.to_string(),
+                title,
+                body: body.trim().to_owned(),
+            });
+        }
+    }
+
+    sections.sort_by(|a, b| {
+        let version = |section: &Section| Version::parse(&section.version).ok();
+        version(b).cmp(&version(a))
+    });
+
+    sections
+}
+
+/// A release of a GitHub repository, as its API describes it
+#[derive(Debug, Deserialize)]
+pub struct GithubRelease {
+    pub tag_name: String,
+    pub name: Option<String>,
+    pub body: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+}
+
+/// The notes of `releases` about the versions after `current` up to `latest`, newest first
+pub fn release_sections(
+    releases: Vec<GithubRelease>,
+    current: &Version,
+    latest: &Version,
+) -> Vec<Section> {
+    let mut sections: Vec<_> = releases
+        .into_iter()
+        .filter(|release| release.draft.not())
+        .filter_map(|release| {
+            let version = mentioned_version(&release.tag_name)?;
+            in_range(&version, current, latest).then(|| {
+                (
+                    version.clone(),
+                    Section {
+                        version: version.to_string(),
+                        title: release
+                            .name
+                            .filter(|name| name.trim().is_empty().not())
+                            .unwrap_or(release.tag_name),
+                        body: release.body.unwrap_or_default().trim().to_owned(),
+                    },
+                )
+            })
+        })
+        .collect();
+    sections.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    sections.into_iter().map(|(_, section)| section).collect()
+}
+
+/// The owner and name of a repository on GitHub, as in `BurntSushi` and `ripgrep`
+fn github_repository(url: &str) -> Option<(&str, &str)> {
+    let path = url
+        .strip_prefix("https://github.com/")
+        .or_else(|| url.strip_prefix("http://github.com/"))?;
+    let mut parts = path.trim_end_matches('/').split('/');
+    let owner = parts.next().filter(|owner| owner.is_empty().not())?;
+    let name = parts.next()?.trim_end_matches(".git");
+
+    (name.is_empty().not()).then_some((owner, name))
+}
+
+/// Where the raw contents of `file` at the head of the repository at `url` can be fetched from, for the forges that
+/// are known to serve them
+fn raw_file_url(url: &str, file: &str) -> Option<String> {
+    if let Some((owner, name)) = github_repository(url) {
+        return Some(format!(
+            "https://raw.githubusercontent.com/{owner}/{name}/HEAD/{file}"
+        ));
+    }
+    let url = url.trim_end_matches('/').trim_end_matches(".git");
+    url.starts_with("https://gitlab.com/")
+        .then(|| format!("{url}/-/raw/HEAD/{file}"))
+}
+
+/// Fetch `url` as text, which may well not exist
+fn fetch_text(url: &str) -> Result<Option<String>, Failure> {
+    match download::get_conditional(url, &Validators::default())? {
+        Fetched::Modified { body, .. } => Ok(Some(body)),
+        Fetched::NotModified | Fetched::NotFound => Ok(None),
+    }
+}
+
+/// Show `text` through the user's pager when someone is there to read it
+fn page(text: &str) -> Result<()> {
+    if io::stdout().is_terminal().not() {
+        print!("{text}");
+        return Ok(());
+    }
+
+    let pager = env::var("PAGER")
+        .ok()
+        .filter(|pager| pager.trim().is_empty().not())
+        .unwrap_or_else(|| DEFAULT_PAGER.to_owned());
+    let Ok(mut child) = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        print!("{text}");
+        return Ok(());
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // Quitting the pager early closes the pipe, which is no error
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait().context("Failed to wait on the pager")?;
+
+    Ok(())
+}
+
+impl Switcher {
+    /// Find out what changed between the active version of `package` and its latest release
+    pub fn changelog(&self, package: &str) -> Result<Changelog> {
+        let package = &self.canonical_name(package)?;
+        let current = self
+            .linked_version(package)?
+            .with_context(|| format!("{package} has no active version to compare with"))?;
+        let (crate_name, _) = split_label(package);
+        let current_version = Version::parse(split_variant(&current).0).with_context(|| {
+            format!("{package}@{current} isn't a crates.io release, so it has no changelog to compare with")
+        })?;
+
+        let retries = self.config.retries.unwrap_or(retry::DEFAULT_RETRIES);
+        let crates_io = self.crates_io()?;
+        let latest = RetryPolicy::new(retries)
+            .run(&format!("look up {crate_name} on crates.io"), || {
+                crates_io.newest_version(crate_name, self.allows_prereleases(package))
+            })?;
+        let repository = RetryPolicy::new(retries)
+            .run(&format!("look up {crate_name} on crates.io"), || {
+                crates_io.repository(crate_name)
+            })?;
+
+        let mut changelog = Changelog {
+            package: package.to_owned(),
+            current: current_version.to_string(),
+            latest: latest.to_string(),
+            repository: repository.clone(),
+            source: ChangelogSource::None,
+            sections: Vec::new(),
+        };
+        let Some(repository) = repository else {
+            return Ok(changelog);
+        };
+        if latest <= current_version {
+            return Ok(changelog);
+        }
+
+        // What can't be fetched is as good as missing, which the output says
+        for file in CHANGELOG_FILE_NAMES {
+            let Some(url) = raw_file_url(&repository, file) else {
+                break;
+            };
+            let Ok(Some(contents)) = fetch_text(&url) else {
+                continue;
+            };
+            let sections = changelog_sections(&contents, &current_version, &latest);
+            if sections.is_empty().not() {
+                changelog.source = ChangelogSource::File { url };
+                changelog.sections = sections;
+                return Ok(changelog);
+            }
+        }
+
+        if let Some((owner, name)) = github_repository(&repository) {
+            let url = format!("https://api.github.com/repos/{owner}/{name}/releases?per_page=100");
+            if let Ok(releases) = download::get_json::<Vec<GithubRelease>>(&url) {
+                let sections = release_sections(releases, &current_version, &latest);
+                if sections.is_empty().not() {
+                    changelog.source = ChangelogSource::GithubReleases {
+                        url: format!("https://github.com/{owner}/{name}/releases"),
+                    };
+                    changelog.sections = sections;
+                }
+            }
+        }
+
+        Ok(changelog)
+    }
+
+    /// Print what changed between the active version of `package` and its latest release, as JSON if `json` is set
+    pub fn print_changelog(&self, package: &str, json: bool) -> Result<()> {
+        let changelog = self.changelog(package)?;
+        if json {
+            println!("{}", serde_json::to_string_pretty(&changelog)?);
+            return Ok(());
+        }
+
+        let Changelog {
+            current, latest, ..
+        } = &changelog;
+        if Version::parse(latest)? <= Version::parse(current)? {
+            println!("{package} is at {current}, its latest release");
+            return Ok(());
+        }
+
+        let mut text = String::new();
+        match &changelog.source {
+            ChangelogSource::File { url } | ChangelogSource::GithubReleases { url } => {
+                writeln!(text, "{package}: {current} -> {latest}, from {url}")?;
+                for section in &changelog.sections {
+                    let rule = "=".repeat(section.title.chars().count());
+                    writeln!(text, "\n{}\n{rule}\n", section.title)?;
+                    if section.body.is_empty().not() {
+                        writeln!(text, "{}", section.body)?;
+                    }
+                }
+            }
+            ChangelogSource::None => {
+                match &changelog.repository {
+                    Some(repository) => writeln!(
+                        text,
+                        "Couldn't find what changed in {package} between {current} and {latest}. Have a look at \
+                         {repository}"
+                    )?,
+                    None => writeln!(
+                        text,
+                        "Couldn't find what changed in {package} between {current} and {latest}, and crates.io \
+                         knows of no repository for it"
+                    )?,
+                }
+            }
+        }
+
+        page(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::Version;
+
+    use super::changelog_sections;
+    use super::mentioned_version;
+    use super::raw_file_url;
+    use super::release_sections;
+    use super::GithubRelease;
+
+    const CHANGELOG: &str = "\
+# Changelog
+
+## [Unreleased]
+
+- Something in the works
+
+## [14.1.0] - 2024-01-06
+
+### Bug fixes
+
+- Fixed a thing
+
+## 14.0.3 (2023-11-28)
+
+- Fixed another thing
+
+## v14.0.2
+
+- Nothing to see
+";
+
+    fn version(version: &str) -> Version {
+        Version::parse(version).unwrap()
+    }
+
+    #[test]
+    fn finds_versions_in_headings_and_tags() {
+        assert_eq!(
+            mentioned_version("[14.1.0] - 2024-01-06"),
+            Some(version("14.1.0"))
+        );
+        assert_eq!(mentioned_version("v0.7.2"), Some(version("0.7.2")));
+        assert_eq!(mentioned_version("ripgrep-14.1.0"), Some(version("14.1.0")));
+        assert_eq!(
+            mentioned_version("1.0.0-rc.1."),
+            Some(version("1.0.0-rc.1"))
+        );
+        assert_eq!(mentioned_version("2024-01-06"), None);
+        assert_eq!(mentioned_version("Unreleased"), None);
+    }
+
+    #[test]
+    fn picks_the_sections_in_between() {
+        let sections = changelog_sections(CHANGELOG, &version("14.0.2"), &version("14.1.0"));
+        let titles: Vec<_> = sections
+            .iter()
+            .map(|section| section.title.as_str())
+            .collect();
+        assert_eq!(titles, ["[14.1.0] - 2024-01-06", "14.0.3 (2023-11-28)"]);
+        // Subheadings are part of the section
+        assert_eq!(sections[0].body, "### Bug fixes\n\n- Fixed a thing");
+
+        assert!(changelog_sections(CHANGELOG, &version("14.1.0"), &version("14.1.0")).is_empty());
+        assert!(
+            changelog_sections("# Notes\n\nNothing", &version("1.0.0"), &version("2.0.0"))
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn picks_the_releases_in_between() {
+        let release = |tag: &str, draft| GithubRelease {
+            tag_name: tag.to_owned(),
+            name: None,
+            body: Some(format!("Notes of {tag}\n")),
+            draft,
+        };
+        let releases = vec![
+            release("v1.0.0", false),
+            release("v1.2.0", false),
+            release("v1.1.0", false),
+            release("v1.3.0", true),
+        ];
+
+        let sections = release_sections(releases, &version("1.0.0"), &version("1.3.0"));
+        let versions: Vec<_> = sections
+            .iter()
+            .map(|section| section.version.as_str())
+            .collect();
+        assert_eq!(versions, ["1.2.0", "1.1.0"]);
+        assert_eq!(sections[0].title, "v1.2.0");
+        assert_eq!(sections[0].body, "Notes of v1.2.0");
+    }
+
+    #[test]
+    fn knows_where_forges_serve_files() {
+        assert_eq!(
+            raw_file_url("https://github.com/BurntSushi/ripgrep.git", "CHANGELOG.md").as_deref(),
+            Some("https://raw.githubusercontent.com/BurntSushi/ripgrep/HEAD/CHANGELOG.md")
+        );
+        assert_eq!(
+            raw_file_url("https://gitlab.com/owner/tool/", "CHANGELOG.md").as_deref(),
+            Some("https://gitlab.com/owner/tool/-/raw/HEAD/CHANGELOG.md")
+        );
+        assert_eq!(
+            raw_file_url("https://example.com/tool", "CHANGELOG.md"),
+            None
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Named channels, like `testing` or `stable`, each pointing to an installed version of a package. `PACKAGE@CHANNEL`
+//! can be used wherever `PACKAGE@VERSION` can be switched to or run, and `promote` moves a version from one channel
+//! to the next.
+
+use std::collections::BTreeMap;
+use std::ops::Not;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::state::State;
+use crate::Switcher;
+
+/// Whether `name` can name a channel. Versions always start with a digit, so channels start with a letter and the two
+/// can never be mixed up.
+pub fn is_channel_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic())
+        && chars.all(|char| char.is_ascii_alphanumeric() || matches!(char, '-' | '_'))
+}
+
+impl Switcher {
+    /// The channels of `package`, keyed by name
+    pub fn channels(&self, package: &str) -> Result<BTreeMap<String, String>> {
+        let package = &self.canonical_name(package)?;
+        let mut state = State::load(&self.registry)?;
+
+        Ok(state.channels.remove(package).unwrap_or_default())
+    }
+
+    /// The version the channel `channel` of `package` points to, if there's such a channel
+    pub fn channel_version(&self, package: &str, channel: &str) -> Result<Option<String>> {
+        if is_channel_name(channel).not() {
+            return Ok(None);
+        }
+        let Some(version) = self.channels(package)?.remove(channel) else {
+            return Ok(None);
+        };
+
+        ensure!(
+            self.installed_versions(package)?.contains(&version),
+            "The {channel} channel of {package} points to {version}, which is no longer installed. Reinstall it or \
+             run `cargo switch channel set {package} {channel} VERSION`"
+        );
+
+        Ok(Some(version))
+    }
+
+    pub fn set_channel(&self, package: &str, channel: &str, version: &str) -> Result<()> {
+        self.ensure_per_user("set channels")?;
+        let package = &self.canonical_name(package)?;
+        ensure!(
+            is_channel_name(channel),
+            "Invalid channel name `{channel}`: channels must start with a letter, followed by letters, digits, `-` \
+             or `_`"
+        );
+        if self
+            .installed_versions(package)?
+            .iter()
+            .any(|installed| installed == version)
+            .not()
+        {
+            return Err(self.not_installed(package, Some(version)));
+        }
+
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        let previous = state
+            .channels
+            .entry(package.to_owned())
+            .or_default()
+            .insert(channel.to_owned(), version.to_owned());
+        state.save(&self.registry)?;
+
+        match previous {
+            Some(previous) if previous != version => {
+                println!("The {channel} channel of {package} now points to {version}, instead of {previous}")
+            }
+            _ => println!("The {channel} channel of {package} now points to {version}"),
+        }
+
+        Ok(())
+    }
+
+    pub fn unset_channel(&self, package: &str, channel: &str) -> Result<()> {
+        self.ensure_per_user("unset channels")?;
+        let package = &self.canonical_name(package)?;
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        let channels = state.channels.entry(package.to_owned()).or_default();
+        ensure!(
+            channels.remove(channel).is_some(),
+            "{package} has no {channel} channel"
+        );
+        if channels.is_empty() {
+            state.channels.remove(package);
+        }
+        state.save(&self.registry)?;
+
+        println!("Removed the {channel} channel of {package}");
+
+        Ok(())
+    }
+
+    /// Point the channel `to` of `package` to whatever version its channel `from` points to
+    pub fn promote(&self, package: &str, from: &str, to: &str) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        ensure!(
+            from != to,
+            "Can't promote the {from} channel of {package} to itself"
+        );
+        let version = self
+            .channel_version(package, from)?
+            .with_context(|| format!("{package} has no {from} channel"))?;
+
+        self.set_channel(package, to, &version)
+    }
+
+    pub fn print_channels(&self, package: &str) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        let channels = self.channels(package)?;
+        if channels.is_empty() {
+            println!("{package} has no channels");
+            return Ok(());
+        }
+
+        let installed = self.installed_versions(package)?;
+        let width = channels.keys().map(String::len).max().unwrap_or_default();
+        for (channel, version) in &channels {
+            let annotation = if installed.contains(version) {
+                ""
+            } else {
+                " (no longer installed)"
+            };
+            println!("{channel:width$}  {package}@{version}{annotation}");
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::is_channel_name;
+
+    #[test]
+    fn tells_channels_from_versions() {
+        for channel in ["stable", "testing", "release-candidate", "v2_beta"] {
+            assert!(is_channel_name(channel), "{channel}");
+        }
+        for not_a_channel in [
+            "",
+            "1.0.0",
+            "2.0.0-rc1",
+            "1.0.0+debug",
+            "-stable",
+            "sta ble",
+            "stable+debug",
+        ] {
+            assert!(is_channel_name(not_a_channel).not(), "{not_a_channel}");
+        }
+    }
+}
@@ -0,0 +1,342 @@
+//! `check`: making sure the active versions still run, by running each package's primary binary with `--version`
+//! the way the user's shell would, and comparing what it reports with the version the links point to.
+
+use std::env;
+use std::ffi::OsString;
+use std::io::Read;
+use std::ops::Not;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Result;
+
+use crate::format::human_duration;
+use crate::interrupt;
+use crate::shadow::find_shadowing_executable;
+use crate::spec::split_label;
+use crate::table::print_table;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// How long a binary gets to answer, unless told otherwise
+pub const DEFAULT_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How checking a package went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub package: String,
+    pub version: String,
+    /// The binary that was run, by name
+    pub binary: String,
+    /// Why the check failed, if it did
+    pub failure: Option<String>,
+}
+
+/// The binary of `binaries` that stands for `package`: the configured one, the one named after the package, the
+/// one named after it without its `-cli` suffix or else the first one
+fn primary_binary(package: &str, configured: Option<&str>, binaries: &[String]) -> Option<String> {
+    let candidates = [configured, Some(package), package.strip_suffix("-cli")];
+    candidates
+        .into_iter()
+        .flatten()
+        .find(|candidate| binaries.iter().any(|binary| binary == candidate))
+        .or(binaries.first().map(String::as_str))
+        .map(str::to_owned)
+}
+
+/// Whether `output` mentions `version`, as in `ripgrep 14.1.0`, `tool@1.0.0` or `v1.2.3 (abcdef)`
+fn reports_version(output: &str, version: &str) -> bool {
+    output
+        .split(|c: char| (c.is_ascii_alphanumeric() || ".-+".contains(c)).not())
+        .any(|word| word.strip_prefix('v').unwrap_or(word) == version)
+}
+
+/// Run `command`, returning how it exited and what it printed if it does so within `timeout`. It runs in a process
+/// group of its own, so that whatever it starts is killed along with it.
+pub(crate) fn run_with_timeout(
+    mut command: Command,
+    timeout: Duration,
+) -> Result<(ExitStatus, String), String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0)
+        .spawn()
+        .map_err(|err| format!("failed to run: {err}"))?;
+    let group = child.id();
+    let _kill_on_interrupt = interrupt::kill_on_interrupt(group);
+    let timed_out = || format!("timed out after {}", human_duration(timeout));
+
+    // Read both while waiting, or a binary printing more than a pipe holds would block until the timeout
+    let (sender, drained) = mpsc::channel();
+    let drain = |index: usize, pipe: Option<Box<dyn Read + Send>>| {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+            let _ = sender.send((index, output));
+        });
+    };
+    drain(0, child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    drain(1, child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            Ok(None) => {
+                interrupt::kill_group(group);
+                let _ = child.wait();
+                return Err(timed_out());
+            }
+            Err(err) => return Err(format!("failed to wait: {err}")),
+        }
+    };
+
+    // Whatever it left running in the background may hold on to the pipes past the deadline
+    let mut outputs = [Vec::new(), Vec::new()];
+    for _ in 0..outputs.len() {
+        match drained.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok((index, output)) => outputs[index] = output,
+            Err(_) => {
+                interrupt::kill_group(group);
+                return Err(timed_out());
+            }
+        }
+    }
+
+    // Some tools print their version to stderr
+    let [mut output, stderr] = outputs;
+    output.extend(stderr);
+    let output = String::from_utf8_lossy(&output).into_owned();
+
+    Ok((status, output))
+}
+
+/// Run `binary` with `args`, returning what it printed if it exits successfully within `timeout`
+fn run_check(binary: &Path, args: &[String], timeout: Duration) -> Result<String, String> {
+    let mut command = Command::new(binary);
+    command.args(args);
+
+    match run_with_timeout(command, timeout)? {
+        (status, _) if status.success().not() => Err(format!("exited with {status}")),
+        (_, output) => Ok(output),
+    }
+}
+
+impl Switcher {
+    /// Check `package`, or every package with an active version
+    pub fn check_active(
+        &self,
+        package: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Vec<CheckResult>> {
+        let package = package
+            .map(|package| self.canonical_name(package))
+            .transpose()?;
+        let package = package.as_deref();
+        let explicit = package.is_some();
+        let packages = match package {
+            Some(package) if self.package_exists(package).not() => {
+                return Err(self.not_installed(package, None));
+            }
+            Some(package) => vec![package.to_owned()],
+            None => self.installed_packages()?,
+        };
+        let path = env::var_os("PATH").unwrap_or_default();
+
+        let mut results = Vec::new();
+        for package in packages {
+            let Some(version) = self.linked_version(&package)? else {
+                if explicit {
+                    bail!("{package} has no active version to check");
+                }
+                continue;
+            };
+
+            let config = self.config.package(&package);
+            let binaries: Vec<_> = self
+                .version_binaries(&package, &version)?
+                .iter()
+                .filter_map(|binary| binary.file_name()?.to_str().map(str::to_owned))
+                .collect();
+            let Some(binary) = primary_binary(
+                split_label(&package).0,
+                config.and_then(|config| config.check_binary.as_deref()),
+                &binaries,
+            ) else {
+                continue;
+            };
+            let args = config
+                .and_then(|config| config.check_args.clone())
+                .unwrap_or_else(|| vec!["--version".to_owned()]);
+
+            // Whatever comes first in $PATH is what the user actually gets
+            let name = OsString::from(&binary);
+            let run: PathBuf = find_shadowing_executable(&name, &self.cargo_bin, &path)
+                .unwrap_or_else(|| self.cargo_bin.join(&binary));
+            let (bare_version, _) = split_variant(&version);
+            let failure = match run_check(&run, &args, timeout) {
+                Err(failure) => Some(failure),
+                Ok(output) if reports_version(&output, bare_version) => None,
+                Ok(output) => {
+                    let reported = output.lines().next().unwrap_or_default().trim();
+                    Some(format!("reported `{reported}`, runs {}", run.display()))
+                }
+            };
+
+            results.push(CheckResult {
+                package,
+                version,
+                binary,
+                failure,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// Check `package`, or every package with an active version, printing how it went and failing if any check did.
+    /// With `toolchain_drift`, versions built with compilers too far behind the default toolchain fail too. With
+    /// `smoke_test`, packages run their smoke test instead, see [`smoke_test`](crate::smoke_test).
+    pub fn check(
+        &self,
+        package: Option<&str>,
+        timeout: Duration,
+        toolchain_drift: bool,
+        smoke_test: bool,
+    ) -> Result<()> {
+        let (failed, checked) = match smoke_test {
+            true => self.report_smoke_tests(package)?,
+            false => self.report_checks(package, timeout)?,
+        };
+
+        let drifted = match toolchain_drift {
+            true => self.report_toolchain_drift(package)?,
+            false => 0,
+        };
+        if failed > 0 {
+            match smoke_test {
+                true => bail!("{failed} of {checked} smoke tests failed"),
+                false => bail!("{failed} of {checked} checks failed"),
+            }
+        }
+        ensure!(
+            drifted == 0,
+            "{drifted} version(s) were built with compilers too far behind the default toolchain"
+        );
+
+        Ok(())
+    }
+
+    /// Check `package`, or every package with an active version, printing how it went, and return how many checks
+    /// failed out of how many
+    fn report_checks(&self, package: Option<&str>, timeout: Duration) -> Result<(usize, usize)> {
+        let results = self.check_active(package, timeout)?;
+        if results.is_empty() {
+            println!("Nothing to check, no package has an active version");
+        } else {
+            let rows: Vec<_> = results
+                .iter()
+                .map(|result| {
+                    [
+                        result.package.clone(),
+                        result.version.clone(),
+                        result.binary.clone(),
+                        match result.failure {
+                            None => "pass".to_owned(),
+                            Some(_) => "fail".to_owned(),
+                        },
+                        result.failure.clone().unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            print_table(["PACKAGE", "VERSION", "BINARY", "RESULT", "DETAILS"], &rows);
+        }
+
+        let failed = results
+            .iter()
+            .filter(|result| result.failure.is_some())
+            .count();
+
+        Ok((failed, results.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+    use std::process::Command;
+    use std::time::Duration;
+
+    use super::primary_binary;
+    use super::reports_version;
+    use super::run_with_timeout;
+
+    #[test]
+    fn picks_primary_binaries() {
+        let binaries =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+
+        assert_eq!(
+            primary_binary("ripgrep", None, &binaries(&["rg"])).as_deref(),
+            Some("rg")
+        );
+        assert_eq!(
+            primary_binary("sqlx-cli", None, &binaries(&["cargo-sqlx", "sqlx"])).as_deref(),
+            Some("sqlx")
+        );
+        assert_eq!(
+            primary_binary("tool", None, &binaries(&["other", "tool"])).as_deref(),
+            Some("tool")
+        );
+        assert_eq!(
+            primary_binary("tool", Some("other"), &binaries(&["other", "tool"])).as_deref(),
+            Some("other")
+        );
+        assert_eq!(primary_binary("tool", None, &[]), None);
+    }
+
+    #[test]
+    fn spots_reported_versions() {
+        assert!(reports_version(
+            "ripgrep 14.1.0\n\nfeatures:+pcre2",
+            "14.1.0"
+        ));
+        assert!(reports_version("tool@1.0.0 release", "1.0.0"));
+        assert!(reports_version("just v1.2.3 (abcdef 2024-01-01)", "1.2.3"));
+        assert!(reports_version("tool 1.0.0-rc.1", "1.0.0-rc.1"));
+        assert!(reports_version("ripgrep 14.1.1", "14.1.0").not());
+        assert!(reports_version("tool 11.0.0", "1.0.0").not());
+        assert!(reports_version("tool 1.0.0-rc.1", "1.0.0").not());
+    }
+
+    #[test]
+    fn reads_more_than_a_pipe_holds() {
+        // Well past the 64KiB a pipe holds on Linux, on both streams
+        let mut command = Command::new("sh");
+        command.args([
+            "-c",
+            "head -c 200000 /dev/zero; head -c 200000 /dev/zero >&2",
+        ]);
+
+        let (status, output) = run_with_timeout(command, Duration::from_secs(5)).unwrap();
+        assert!(status.success());
+        assert_eq!(output.len(), 400000);
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::de;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::format;
+use crate::link_style::LinkStyle;
+use crate::spec::normalize_name;
+use crate::summary::FailureMode;
+use crate::versioned_links::LinkSuffix;
+
+/// User configuration, read from `$CARGO_SWITCH_CONFIG` or `~/.config/cargo-switch/config.toml`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    /// The cargo binary to use, taking precedence over `$CARGO` and `$PATH`
+    pub cargo_path: Option<PathBuf>,
+    /// The directory to link binaries into, rather than the one cargo installs into. See [`install_root`].
+    ///
+    /// [`install_root`]: crate::install_root
+    pub cargo_bin: Option<PathBuf>,
+    /// The directory to link binaries into, when it isn't a cargo bin directory at all, as in `~/.local/bin`. See
+    /// [`install_root`].
+    ///
+    /// [`install_root`]: crate::install_root
+    pub link_dir: Option<PathBuf>,
+    /// A registry of versions installed for every user, as in `/opt/cargo-switch/registry`, consulted after the
+    /// user's own. See [`shared`].
+    ///
+    /// [`shared`]: crate::shared
+    pub shared_registry: Option<PathBuf>,
+    /// A directory shared with others, as in a network mount, that builds from crates.io are pushed to and copied
+    /// from rather than built again. See [`binary_cache`].
+    ///
+    /// [`binary_cache`]: crate::binary_cache
+    pub binary_cache: Option<PathBuf>,
+    /// How many times to retry operations that failed because of the network
+    pub retries: Option<u32>,
+    /// How long builds may run, as in `30m`, before they're taken to be hung and killed, unless `--timeout` says
+    /// otherwise
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub build_timeout: Option<Duration>,
+    /// How many releases behind the default toolchain the compiler a version was built with may be before
+    /// `--toolchain-drift` flags it. See [`drift`].
+    ///
+    /// [`drift`]: crate::drift
+    pub max_toolchain_drift: Option<u64>,
+    /// Whether to strip the binaries of every package installed without `--strip` or `--no-strip`
+    pub strip: Option<bool>,
+    /// Whether links in `.cargo/bin` point into the registry through absolute or relative paths
+    pub link_style: Option<LinkStyle>,
+    /// How many versions of each package to keep around, the oldest others being pruned after installs
+    pub keep_versions: Option<usize>,
+    /// How long what crates.io said about crates is trusted, as in `30m` or `1h`. See [`cache`].
+    ///
+    /// [`cache`]: crate::cache
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub cache_ttl: Option<Duration>,
+    /// How large the registry may grow, as in `2 GiB`, before the versions that went unused the longest are evicted
+    /// after installs. See [`quota`].
+    ///
+    /// [`quota`]: crate::quota
+    #[serde(deserialize_with = "deserialize_size")]
+    pub max_size: Option<u64>,
+    /// How long trashed versions are kept, as in `7d`, before pruning removes them for good. 30 days by default.
+    /// See [`trash`].
+    ///
+    /// [`trash`]: crate::trash
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub trash_retention: Option<Duration>,
+    /// How large the operation log may grow, as in `1 MiB`, before it's rotated. See [`operation_log`].
+    ///
+    /// [`operation_log`]: crate::operation_log
+    #[serde(deserialize_with = "deserialize_size")]
+    pub log_max_size: Option<u64>,
+    /// The names packages go by in `.tool-versions`, keyed by package name, for the ones that aren't named after
+    /// their package there, as in `ripgrep = "rg"`. See [`tool_versions`].
+    ///
+    /// [`tool_versions`]: crate::tool_versions
+    pub tool_versions: BTreeMap<String, String>,
+    /// Sets of packages to install or switch to all at once, keyed by group name, as in
+    /// `web = ["sqlx-cli@0.7.2", "trunk@0.18.0"]`. See [`group`].
+    ///
+    /// [`group`]: crate::group
+    pub groups: BTreeMap<String, Vec<String>>,
+    /// Whether commands dealing with many specs at once keep going past failures or stop at the first one, unless
+    /// `--keep-going` or `--fail-fast` says otherwise. See [`summary`].
+    ///
+    /// [`summary`]: crate::summary
+    pub failure_mode: Option<FailureMode>,
+    /// Whether `run` installs the versions it's asked for that aren't installed yet, without switching to them, as
+    /// `--install-missing` does
+    pub ephemeral: bool,
+    /// Whether to mention, at most once a day, that active versions have newer releases, going by what crates.io
+    /// said last. See [`update_hints`].
+    ///
+    /// [`update_hints`]: crate::update_hints
+    pub update_hints: bool,
+    /// Settings that only apply to one package, keyed by package name
+    pub packages: BTreeMap<String, PackageConfig>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct PackageConfig {
+    /// Variables to set whenever one of the package's binaries runs. `{package}` and `{version}` in values are
+    /// replaced by the package's name and active version.
+    ///
+    /// Packages with variables get wrapper scripts in `.cargo/bin` rather than symlinks.
+    pub env: BTreeMap<String, String>,
+    /// How many versions of the package to keep around, overriding the global `keep-versions`
+    pub keep_versions: Option<usize>,
+    /// The binary `check` runs, the one named after the package by default
+    pub check_binary: Option<String>,
+    /// What `check` runs the binary with, `--version` by default. Whatever the binary prints must mention the
+    /// active version.
+    pub check_args: Option<Vec<String>>,
+    /// A command that freshly built versions must pass before they're switched to, as in `rg --version`, run by
+    /// `sh` with the version's binaries first in `$PATH`. Versions that fail it are quarantined, see [`smoke_test`].
+    ///
+    /// [`smoke_test`]: crate::smoke_test
+    pub smoke_test: Option<String>,
+    /// How long the smoke test may run, as in `30s`, 10 seconds by default
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub smoke_test_timeout: Option<Duration>,
+    /// Also link the binaries of every installed version under suffixed names, as in `rg-13` and `rg-14`. See
+    /// [`versioned_links`].
+    ///
+    /// [`versioned_links`]: crate::versioned_links
+    pub versioned_links: bool,
+    /// What versioned links are suffixed with, the major version by default
+    pub versioned_links_suffix: LinkSuffix,
+    /// Let pre-releases, as in `2.0.0-beta.1`, be the newest version of the package, as `--pre` does
+    pub pre: bool,
+    /// Cargo features to always build the package with, on top of the ones given through `--features`
+    pub features: Vec<String>,
+    /// Whether to build with the crate's default features, which `--no-default-features` turns off all the same
+    pub default_features: Option<bool>,
+    /// Always build with the `Cargo.lock` the crate was published with, as `--locked` does
+    pub locked: bool,
+    /// The rustup toolchain to build the package with, unless `--toolchain` names another
+    pub toolchain: Option<String>,
+    /// Arguments passed on to `cargo install` as they are, as in `["--jobs", "2"]`
+    pub extra_args: Vec<String>,
+    /// Variables to set on `cargo install` when building the package, as in `RUSTFLAGS = "-C target-cpu=native"`.
+    /// Recorded with every version built, along with the build-relevant variables the environment had, so that
+    /// rebuilds get the same.
+    pub build_env: BTreeMap<String, String>,
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    let duration = String::deserialize(deserializer)?;
+
+    humantime::parse_duration(&duration)
+        .map(Some)
+        .map_err(|err| de::Error::custom(format!("invalid duration `{duration}`: {err}")))
+}
+
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Bytes(u64),
+        Human(String),
+    }
+
+    match Size::deserialize(deserializer)? {
+        Size::Bytes(bytes) => Ok(Some(bytes)),
+        Size::Human(size) => format::parse_size(&size)
+            .map(Some)
+            .map_err(|err| de::Error::custom(format!("{err:#}"))),
+    }
+}
+
+impl Config {
+    /// Where the configuration file is expected to be
+    pub fn path() -> Option<PathBuf> {
+        if let Some(path) = env::var_os("CARGO_SWITCH_CONFIG") {
+            return Some(PathBuf::from(path));
+        }
+
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+
+        Some(config_home.join("cargo-switch").join("config.toml"))
+    }
+
+    /// Load the configuration file, if there is one
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    /// The configuration of `package`, whichever of `-` and `_` either spells it with
+    pub fn package(&self, package: &str) -> Option<&PackageConfig> {
+        self.packages.get(package).or_else(|| {
+            let normalized = normalize_name(package);
+            self.packages
+                .iter()
+                .find_map(|(name, config)| (normalize_name(name) == normalized).then_some(config))
+        })
+    }
+
+    /// How many versions of `package` to keep around, if any retention policy applies to it
+    pub fn keep_versions(&self, package: &str) -> Option<usize> {
+        self.package(package)
+            .and_then(|config| config.keep_versions)
+            .or(self.keep_versions)
+    }
+
+    /// Whether pre-releases of `package` may be its newest version
+    pub fn pre(&self, package: &str) -> bool {
+        self.package(package).is_some_and(|config| config.pre)
+    }
+
+    /// The name `package` goes by in `.tool-versions`
+    pub fn tool_name<'a>(&'a self, package: &'a str) -> &'a str {
+        self.tool_versions
+            .get(package)
+            .map_or(package, String::as_str)
+    }
+
+    /// The package among `installed` that `tool`, as named in `.tool-versions`, stands for, if any
+    pub fn tool_package(&self, tool: &str, installed: &[String]) -> Option<String> {
+        let mapped = self
+            .tool_versions
+            .iter()
+            .find_map(|(package, name)| (name == tool).then_some(package));
+
+        mapped
+            .or_else(|| installed.iter().find(|package| *package == tool))
+            .filter(|package| installed.contains(package))
+            .cloned()
+    }
+
+    /// Whether `package` gets versioned links, and what they're suffixed with
+    pub fn versioned_links(&self, package: &str) -> Option<LinkSuffix> {
+        self.package(package)
+            .filter(|config| config.versioned_links)
+            .map(|config| config.versioned_links_suffix)
+    }
+
+    /// The variables to set when running the binaries of `package`
+    pub fn package_env(&self, package: &str) -> Option<&BTreeMap<String, String>> {
+        self.package(package)
+            .map(|config| &config.env)
+            .filter(|env| env.is_empty().not())
+    }
+}
@@ -0,0 +1,106 @@
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::format::human_size;
+use crate::spec::parse_spec;
+use crate::strip::strip_binary;
+use crate::strip::strip_for;
+use crate::Switcher;
+
+/// How `copy-to` copies binaries out of the registry
+#[derive(Debug, Default, Clone)]
+pub struct CopyOptions {
+    /// Strip the copies of their symbols
+    pub strip: bool,
+    /// Copy the build cross-compiled for this target triple rather than the regular one
+    pub target: Option<String>,
+    /// Overwrite files that already exist at the destination
+    pub force: bool,
+}
+
+impl Switcher {
+    /// Copy the binaries of `package@version` into `destination`, as actual files rather than links
+    pub fn copy_to(&self, package: &str, destination: &Path, options: &CopyOptions) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let (name, version) = parse_spec(package)?;
+
+        let binaries = match &options.target {
+            Some(target) => {
+                let bin_path = self
+                    .target_registry(target)
+                    .join(name)
+                    .join(version)
+                    .join("bin");
+                ensure!(
+                    bin_path.exists(),
+                    "Project {package} is not installed for {target}! Install it with `install --target {target}`"
+                );
+                Switcher::scan_binaries(&bin_path)?
+            }
+            None => self.version_binaries(name, version)?,
+        };
+
+        // Find every collision up front, so that nothing gets copied unless everything can be
+        let destinations: Vec<_> = binaries
+            .iter()
+            .map(|binary| destination.join(binary.file_name().unwrap_or_default()))
+            .collect();
+        if options.force.not() {
+            let existing: Vec<_> = destinations
+                .iter()
+                .filter(|destination| destination.symlink_metadata().is_ok())
+                .map(|destination| destination.display().to_string())
+                .collect();
+            if existing.is_empty().not() {
+                bail!(
+                    "Refusing to overwrite {} without --force",
+                    existing.join(", ")
+                );
+            }
+        }
+
+        fs::create_dir_all(destination)
+            .with_context(|| format!("Failed to create {}", destination.display()))?;
+        let strip = if options.strip {
+            Some(strip_for(options.target.as_deref())?)
+        } else {
+            None
+        };
+
+        let mut total_size = 0;
+        for (binary, copy) in binaries.iter().zip(&destinations) {
+            // Don't write through whatever link might already be there
+            if copy.symlink_metadata().is_ok() {
+                fs::remove_file(copy)
+                    .with_context(|| format!("Failed to remove {}", copy.display()))?;
+            }
+            // Permissions, executable bit included, come along
+            fs::copy(binary, copy).with_context(|| {
+                format!("Failed to copy {} to {}", binary.display(), copy.display())
+            })?;
+
+            if let Some(strip) = &strip {
+                strip_binary(strip, copy)?;
+            }
+
+            let size = fs::metadata(copy).map_or(0, |metadata| metadata.len());
+            total_size += size;
+            println!("{} ({})", copy.display(), human_size(size));
+        }
+
+        println!(
+            "Copied {} binar{} of {package} ({})",
+            binaries.len(),
+            if binaries.len() == 1 { "y" } else { "ies" },
+            human_size(total_size)
+        );
+
+        Ok(())
+    }
+}
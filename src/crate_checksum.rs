@@ -0,0 +1,157 @@
+//! Making sure what cargo built from crates.io is what was published: the index lists the SHA-256 checksum of every
+//! `.crate` file, and cargo keeps the ones it downloaded in `$CARGO_HOME/registry/cache`. The index's checksum is
+//! recorded along with the build, so that it can be checked again later on, wherever the version is installed. It's
+//! recorded even when the `.crate` file is gone, marked unverified.
+
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Result;
+
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::Switcher;
+
+/// The `.crate` file of `crate_name@version` that cargo downloaded into `cargo_home`, if it's still there. Cargo
+/// keeps one directory per index, and the one touched last is the one it just built from.
+pub(crate) fn cached_crate(cargo_home: &Path, crate_name: &str, version: &str) -> Option<PathBuf> {
+    let file_name = format!("{crate_name}-{version}.crate");
+    fs::read_dir(cargo_home.join("registry/cache"))
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path().join(&file_name);
+            let modified = path.metadata().ok()?.modified().ok()?;
+            Some((modified, path))
+        })
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Compare the checksum of the `.crate` file `spec` was `built` from with the one the index `listed`, failing on
+/// mismatches, and on the index listing none, only if `require` is set
+fn compare_checksums(spec: &str, listed: Option<&str>, built: &str, require: bool) -> Result<()> {
+    match listed {
+        Some(listed) if listed.eq_ignore_ascii_case(built) => Ok(()),
+        Some(listed) => {
+            let message = format!(
+                "{spec} was built from a .crate whose checksum is {built}, but the index says it was published \
+                 with {listed}. The copy in cargo's registry cache may have been tampered with"
+            );
+            match require {
+                true => bail!("{message}"),
+                false => {
+                    eprintln!("Warning: CHECKSUM MISMATCH. {message}");
+                    Ok(())
+                }
+            }
+        }
+        None if require => bail!(
+            "The index lists no checksum for {spec}, which --require-checksum can't do without"
+        ),
+        None => Ok(()),
+    }
+}
+
+/// The checksum the index lists for a build, to be recorded along with it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ListedChecksum {
+    pub checksum: String,
+    /// Whether the `.crate` file the build came from was checked against it
+    pub verified: bool,
+}
+
+impl Switcher {
+    /// Check the checksum of the `.crate` file `crate_name@version` was `built` from, if known, against the one the
+    /// index lists, returning the latter to be recorded. Unless `require` is set, a checksum that can't be looked up
+    /// is only worth a warning, and a mismatch only worth a loud one.
+    pub(crate) fn verify_crate_checksum(
+        &self,
+        crate_name: &str,
+        version: &str,
+        built: Option<&str>,
+        require: bool,
+    ) -> Result<Option<ListedChecksum>> {
+        let spec = format!("{crate_name}@{version}");
+        ensure!(
+            require.not() || built.is_some(),
+            "The .crate {spec} was built from couldn't be found in cargo's registry cache, which \
+             --require-checksum can't do without"
+        );
+
+        let versions =
+            RetryPolicy::new(0).run(&format!("look up {crate_name} on crates.io"), || {
+                self.crates_io()
+                    .map_err(Failure::Permanent)?
+                    .versions(crate_name)
+            });
+        let listed = match versions {
+            Ok(versions) => versions
+                .into_iter()
+                .find(|published| published.num == version)
+                .and_then(|published| published.checksum),
+            Err(err) if require => return Err(err),
+            Err(err) => {
+                eprintln!("Warning: couldn't look up the checksum of {spec}: {err:#}");
+                return Ok(None);
+            }
+        };
+        if let Some(built) = built {
+            compare_checksums(&spec, listed.as_deref(), built, require)?;
+        }
+
+        Ok(listed.map(|listed| ListedChecksum {
+            checksum: listed.to_ascii_lowercase(),
+            verified: built.is_some(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use super::cached_crate;
+    use super::compare_checksums;
+
+    #[test]
+    fn compares_checksums() {
+        assert!(compare_checksums("tool@1.0.0", Some("ABC"), "abc", true).is_ok());
+        assert!(compare_checksums("tool@1.0.0", Some("abc"), "def", false).is_ok());
+        assert!(compare_checksums("tool@1.0.0", Some("abc"), "def", true).is_err());
+        assert!(compare_checksums("tool@1.0.0", None, "abc", false).is_ok());
+        assert!(compare_checksums("tool@1.0.0", None, "abc", true).is_err());
+    }
+
+    #[test]
+    fn finds_cached_crates() {
+        let cargo_home = tempfile::tempdir().unwrap();
+        let cache = cargo_home.path().join("registry/cache");
+        assert_eq!(cached_crate(cargo_home.path(), "tool", "1.0.0"), None);
+
+        for (index, age) in [("index.crates.io-1", 10), ("mirror-2", 0)] {
+            let path = cache.join(index).join("tool-1.0.0.crate");
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, "crate").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(age);
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        fs::write(cache.join("mirror-2/tool-1.0.1.crate"), "crate").unwrap();
+
+        assert_eq!(
+            cached_crate(cargo_home.path(), "tool", "1.0.0"),
+            Some(cache.join("mirror-2/tool-1.0.0.crate"))
+        );
+        assert_eq!(cached_crate(cargo_home.path(), "tool", "2.0.0"), None);
+    }
+}
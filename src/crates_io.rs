@@ -0,0 +1,682 @@
+//! What crates.io knows about published crates, as told by its sparse index or, when cargo was configured to replace
+//! crates.io with something that isn't a sparse index, by its API. Either way, callers get the same list of
+//! releases.
+
+use std::ops::Not;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::thread;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use semver::Version;
+use semver::VersionReq;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cache::Cache;
+use crate::checksum::sha256;
+use crate::download;
+use crate::download::Fetched;
+use crate::download::Validators;
+use crate::install_root::CargoEnv;
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::sparse_index;
+use crate::sparse_index::IndexSource;
+use crate::Switcher;
+
+const API_URL: &str = "https://crates.io/api/v1/crates";
+
+/// How many crates are looked up at once
+const MAX_CONCURRENT_REQUESTS: usize = 8;
+
+/// A release of a crate
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedVersion {
+    /// The name of the crate, as spelled on crates.io, if the index says
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub num: String,
+    #[serde(default)]
+    pub yanked: bool,
+    /// The SHA-256 checksum of the `.crate` file, as a hex string
+    #[serde(default)]
+    pub checksum: Option<String>,
+    /// The oldest rustc able to build the release, as in `1.70`, if its manifest says
+    #[serde(default)]
+    pub rust_version: Option<String>,
+}
+
+/// Releases as the API lists them, and as they're cached
+#[derive(Debug, Serialize, Deserialize)]
+struct VersionsResponse {
+    versions: Vec<PublishedVersion>,
+}
+
+/// What the API says about a crate itself, as far as we care about it
+#[derive(Debug, Deserialize)]
+struct CrateResponse {
+    #[serde(rename = "crate")]
+    krate: CrateInfo,
+    #[serde(default)]
+    versions: Vec<CrateVersion>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateInfo {
+    #[serde(default)]
+    name: Option<String>,
+    repository: Option<String>,
+    description: Option<String>,
+    documentation: Option<String>,
+    #[serde(default)]
+    downloads: u64,
+    max_version: Option<String>,
+    max_stable_version: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CrateVersion {
+    num: String,
+    created_at: Option<String>,
+    license: Option<String>,
+}
+
+/// What crates.io's search found
+#[derive(Debug, Deserialize)]
+struct SearchResponse {
+    crates: Vec<FoundCrate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FoundCrate {
+    name: String,
+}
+
+/// What crates.io says about a crate, as `info` shows it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CrateDetails {
+    pub description: Option<String>,
+    pub repository: Option<String>,
+    pub documentation: Option<String>,
+    /// The license of the latest release
+    pub license: Option<String>,
+    pub latest_version: Option<String>,
+    /// When the latest release was published, as crates.io formats it
+    pub published_at: Option<String>,
+    pub downloads: u64,
+}
+
+/// Whatever isn't blank of `value`
+fn non_blank(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| value.is_empty().not())
+}
+
+impl CrateResponse {
+    /// The details worth showing, the latest release being the newest pre-release too if `pre` is set
+    fn details(self, pre: bool) -> CrateDetails {
+        let CrateResponse { krate, versions } = self;
+        let latest_version = if pre {
+            krate.max_version
+        } else {
+            krate.max_stable_version.or(krate.max_version)
+        };
+        let latest = versions
+            .into_iter()
+            .find(|version| Some(&version.num) == latest_version.as_ref());
+
+        CrateDetails {
+            description: non_blank(krate.description),
+            repository: non_blank(krate.repository),
+            documentation: non_blank(krate.documentation),
+            license: latest
+                .as_ref()
+                .and_then(|latest| non_blank(latest.license.clone())),
+            published_at: latest.and_then(|latest| latest.created_at),
+            latest_version,
+            downloads: krate.downloads,
+        }
+    }
+}
+
+/// The spellings crates.io may have `name` under, `name` itself first. Crates can't differ only in which of `-` and
+/// `_` they use, but the index only knows each by its own.
+fn spellings(name: &str) -> Vec<String> {
+    let mut spellings = vec![name.to_owned()];
+    for spelling in [name.replace('_', "-"), name.replace('-', "_")] {
+        if spellings.contains(&spelling).not() {
+            spellings.push(spelling);
+        }
+    }
+
+    spellings
+}
+
+/// The releases of `versions` that weren't yanked, leaving out the ones whose version doesn't parse
+fn releases(versions: &[PublishedVersion]) -> impl Iterator<Item = Version> + '_ {
+    versions
+        .iter()
+        .filter(|version| version.yanked.not())
+        .filter_map(|version| Version::parse(&version.num).ok())
+}
+
+/// The newest of `versions`, skipping pre-releases unless `pre` is set or there's nothing else
+fn newest(versions: &[PublishedVersion], pre: bool) -> Option<Version> {
+    releases(versions)
+        .filter(|version| pre || version.pre.is_empty())
+        .max()
+        .or_else(|| releases(versions).max())
+}
+
+/// The newest of `versions` that matches `requirement`, leaving yanked releases out
+pub fn newest_matching(versions: &[PublishedVersion], requirement: &VersionReq) -> Option<Version> {
+    releases(versions)
+        .filter(|version| requirement.matches(version))
+        .max()
+}
+
+/// `value` escaped to be part of a URL's query string, leaving only the characters that never need it as they are
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                char::from(byte).to_string()
+            }
+            byte => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// The cache key of what the sparse index at `index` says about `name`. Indexes and their mirrors don't all say the
+/// same, so each gets entries of its own, told apart by a hash of its URL.
+fn index_key(index: &str, name: &str) -> String {
+    let hash = sha256(index.as_bytes()).unwrap_or_default();
+
+    format!("{name}.{}.index", hash.get(..16).unwrap_or(&hash))
+}
+
+/// Asks crates.io about crates, remembering what it said for a while
+#[derive(Debug, Clone)]
+pub struct CratesIo {
+    index: IndexSource,
+    cache: Cache,
+}
+
+impl CratesIo {
+    pub fn new(index: IndexSource, cache: Cache) -> Self {
+        Self { index, cache }
+    }
+
+    /// Every release of `name`, yanked ones included
+    pub fn versions(&self, name: &str) -> Result<Vec<PublishedVersion>, Failure> {
+        self.lookup(name)?.ok_or_else(|| {
+            Failure::Permanent(anyhow!("There's no crate named `{name}` on crates.io"))
+        })
+    }
+
+    /// Every release of `name`, yanked ones included, or `None` if there's no such crate. Only sparse indexes can
+    /// tell, the API failing for crates it doesn't have like it does for anything else.
+    pub fn lookup(&self, name: &str) -> Result<Option<Vec<PublishedVersion>>, Failure> {
+        match &self.index {
+            IndexSource::Sparse(index) => self.index_versions(index, name),
+            IndexSource::Unsupported(_) => self.api_versions(name).map(Some),
+        }
+    }
+
+    /// The name `name` has on crates.io, however it spells `-` and `_`, or `None` if there's no such crate
+    pub fn real_name(&self, name: &str) -> Result<Option<String>, Failure> {
+        match &self.index {
+            IndexSource::Sparse(_) => {
+                for spelling in spellings(name) {
+                    if let Some(versions) = self.lookup(&spelling)? {
+                        let recorded = versions.iter().find_map(|version| version.name.clone());
+                        return Ok(Some(recorded.unwrap_or(spelling)));
+                    }
+                }
+                Ok(None)
+            }
+            // The API answers to any spelling
+            IndexSource::Unsupported(_) => Ok(Some(
+                self.crate_response(name)?
+                    .krate
+                    .name
+                    .unwrap_or_else(|| name.to_owned()),
+            )),
+        }
+    }
+
+    fn cached(&self, key: &str) -> Option<Vec<PublishedVersion>> {
+        // Whatever was cached by a build expecting something else is as good as missing
+        serde_json::from_value::<VersionsResponse>(self.cache.get(key)?)
+            .ok()
+            .map(|response| response.versions)
+    }
+
+    fn cache(&self, key: &str, versions: Vec<PublishedVersion>, validators: &Validators) {
+        if let Ok(response) = serde_json::to_value(VersionsResponse { versions }) {
+            self.cache.put(key, &response, validators);
+        }
+    }
+
+    fn api_versions(&self, name: &str) -> Result<Vec<PublishedVersion>, Failure> {
+        let key = format!("{name}.versions");
+        if let Some(versions) = self.cached(&key) {
+            return Ok(versions);
+        }
+
+        let url = format!("{API_URL}/{name}/versions");
+        let response: VersionsResponse = download::get_json(&url)?;
+        self.cache(&key, response.versions.clone(), &Validators::default());
+
+        Ok(response.versions)
+    }
+
+    fn index_versions(
+        &self,
+        index: &str,
+        name: &str,
+    ) -> Result<Option<Vec<PublishedVersion>>, Failure> {
+        let key = index_key(index, name);
+        if let Some(versions) = self.cached(&key) {
+            return Ok(Some(versions));
+        }
+
+        // Stale entries only need the server to confirm they're still current
+        let stale = self
+            .cache
+            .get_stale(&key)
+            .and_then(|(response, validators)| {
+                let response = serde_json::from_value::<VersionsResponse>(response).ok()?;
+                Some((response.versions, validators))
+            });
+        let validators = stale
+            .as_ref()
+            .map(|(_, validators)| validators.clone())
+            .unwrap_or_default();
+
+        let url = format!("{index}{}", sparse_index::index_path(name));
+        match download::get_conditional(&url, &validators)? {
+            Fetched::Modified { body, validators } => {
+                let versions = sparse_index::parse_index_file(&body);
+                self.cache(&key, versions.clone(), &validators);
+                Ok(Some(versions))
+            }
+            Fetched::NotModified => match stale {
+                Some((versions, validators)) => {
+                    self.cache(&key, versions.clone(), &validators);
+                    Ok(Some(versions))
+                }
+                None => Err(Failure::Transient(anyhow!(
+                    "{url} said nothing changed, but nothing was asked for"
+                ))),
+            },
+            Fetched::NotFound => Ok(None),
+        }
+    }
+
+    /// What the API says about `name` itself, if it was said recently
+    fn cached_crate(&self, name: &str) -> Option<CrateResponse> {
+        serde_json::from_value(self.cache.get(&format!("{name}.crate"))?).ok()
+    }
+
+    /// What the API says about `name` itself, asking it unless it was said recently
+    fn crate_response(&self, name: &str) -> Result<CrateResponse, Failure> {
+        let key = format!("{name}.crate");
+        let response = match self.cache.get(&key) {
+            Some(response) => response,
+            None => {
+                let response: serde_json::Value = download::get_json(&format!("{API_URL}/{name}"))?;
+                self.cache.put(&key, &response, &Validators::default());
+                response
+            }
+        };
+
+        serde_json::from_value(response)
+            .context("crates.io responded with unexpected JSON")
+            .map_err(Failure::Permanent)
+    }
+
+    /// The names of up to `limit` crates matching `query`, the best matches first, as crates.io's search finds them
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<String>, Failure> {
+        let key = format!("{query}.search");
+        let response = match self.cache.get(&key) {
+            Some(response) => response,
+            None => {
+                let url = format!("{API_URL}?q={}&per_page={limit}", percent_encode(query));
+                let response: serde_json::Value = download::get_json(&url)?;
+                self.cache.put(&key, &response, &Validators::default());
+                response
+            }
+        };
+
+        let response: SearchResponse = serde_json::from_value(response)
+            .context("crates.io responded with unexpected JSON")
+            .map_err(Failure::Permanent)?;
+
+        Ok(response
+            .crates
+            .into_iter()
+            .take(limit)
+            .map(|found| found.name)
+            .collect())
+    }
+
+    /// Where the sources of `name` are, if its manifest says
+    pub fn repository(&self, name: &str) -> Result<Option<String>, Failure> {
+        Ok(non_blank(self.crate_response(name)?.krate.repository))
+    }
+
+    /// What crates.io says about `name`, the latest release being the newest pre-release too if `pre` is set
+    pub fn crate_details(&self, name: &str, pre: bool) -> Result<CrateDetails, Failure> {
+        Ok(self.crate_response(name)?.details(pre))
+    }
+
+    /// [`CratesIo::crate_details`], only if crates.io was asked recently enough for the answer to be cached
+    pub fn cached_crate_details(&self, name: &str, pre: bool) -> Option<CrateDetails> {
+        Some(self.cached_crate(name)?.details(pre))
+    }
+
+    /// The newest release of `name` as of the last time crates.io was asked about it, however long ago, without
+    /// asking it again. `None` if it was never asked.
+    pub fn cached_newest_version(&self, name: &str, pre: bool) -> Option<Version> {
+        let key = match &self.index {
+            IndexSource::Sparse(index) => index_key(index, name),
+            IndexSource::Unsupported(_) => format!("{name}.versions"),
+        };
+        let (response, _) = self.cache.get_stale(&key)?;
+        let response = serde_json::from_value::<VersionsResponse>(response).ok()?;
+
+        newest(&response.versions, pre)
+    }
+
+    /// The newest release of `name`, skipping pre-releases unless `pre` is set or there's nothing else
+    pub fn newest_version(&self, name: &str, pre: bool) -> Result<Version, Failure> {
+        newest(&self.versions(name)?, pre).ok_or_else(|| {
+            Failure::Permanent(anyhow!(
+                "{name} has no release on crates.io that wasn't yanked"
+            ))
+        })
+    }
+
+    /// The releases of every one of `names`, looked up a few at a time and retried `retries` times, in the same
+    /// order
+    pub fn versions_of_many(
+        &self,
+        names: &[&str],
+        retries: u32,
+    ) -> Vec<Result<Vec<PublishedVersion>>> {
+        let next = AtomicUsize::new(0);
+        let results: Vec<_> = names.iter().map(|_| Mutex::new(None)).collect();
+
+        thread::scope(|scope| {
+            for _ in 0..names.len().min(MAX_CONCURRENT_REQUESTS) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(name) = names.get(index) else {
+                        break;
+                    };
+
+                    let result = RetryPolicy::new(retries)
+                        .run(&format!("look up {name} on crates.io"), || {
+                            self.versions(name)
+                        });
+                    *results[index].lock().unwrap() = Some(result);
+                });
+            }
+        });
+
+        results
+            .into_iter()
+            .map(|result| {
+                result
+                    .into_inner()
+                    .unwrap()
+                    .unwrap_or_else(|| Err(anyhow!("Never looked up")))
+            })
+            .collect()
+    }
+}
+
+impl Switcher {
+    /// What asks crates.io, or whatever replaces it in cargo's configuration, about crates
+    pub fn crates_io(&self) -> Result<CratesIo> {
+        let index = match &self.crates_io_index {
+            Some(index) => IndexSource::Sparse(index.clone()),
+            None => sparse_index::configured_index(&CargoEnv::current().config_files())
+                .context("Failed to find out where crates.io's index is")?,
+        };
+
+        Ok(CratesIo::new(index, self.crates_io_cache()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use semver::VersionReq;
+
+    use super::newest;
+    use super::newest_matching;
+    use super::percent_encode;
+    use super::CrateResponse;
+    use super::CratesIo;
+    use super::PublishedVersion;
+    use crate::cache::Cache;
+    use crate::sparse_index::IndexSource;
+
+    fn versions(versions: &[(&str, bool)]) -> Vec<PublishedVersion> {
+        versions
+            .iter()
+            .map(|&(num, yanked)| PublishedVersion {
+                name: None,
+                num: num.to_owned(),
+                yanked,
+                checksum: None,
+                rust_version: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn escapes_queries() {
+        assert_eq!(percent_encode("ripgrep_all-2.0"), "ripgrep_all-2.0");
+        assert_eq!(percent_encode("a&b=c d/é"), "a%26b%3Dc%20d%2F%C3%A9");
+    }
+
+    #[test]
+    fn prefers_stable_versions() {
+        let published = versions(&[("0.3.0-rc.1", false), ("0.2.0", false), ("0.2.1", true)]);
+        assert_eq!(newest(&published, false).unwrap().to_string(), "0.2.0");
+        assert_eq!(newest(&published, true).unwrap().to_string(), "0.3.0-rc.1");
+
+        let published = versions(&[("0.1.0-alpha", false)]);
+        assert_eq!(
+            newest(&published, false).unwrap().to_string(),
+            "0.1.0-alpha"
+        );
+
+        assert_eq!(newest(&versions(&[("1.0.0", true)]), false), None);
+    }
+
+    #[test]
+    fn sums_up_crates() {
+        let response: CrateResponse = serde_json::from_value(serde_json::json!({
+            "crate": {
+                "description": "  Fast line-oriented search  ",
+                "repository": "https://github.com/BurntSushi/ripgrep",
+                "documentation": "",
+                "downloads": 1234567,
+                "max_version": "15.0.0-beta.1",
+                "max_stable_version": "14.1.0",
+            },
+            "versions": [
+                {"num": "15.0.0-beta.1", "created_at": "2025-01-01T00:00:00+00:00", "license": "MIT"},
+                {"num": "14.1.0", "created_at": "2024-01-06T12:00:00+00:00", "license": "Unlicense OR MIT"},
+            ],
+        }))
+        .unwrap();
+        let details = response.details(false);
+        assert_eq!(
+            details.description.as_deref(),
+            Some("Fast line-oriented search")
+        );
+        assert_eq!(details.documentation, None);
+        assert_eq!(details.latest_version.as_deref(), Some("14.1.0"));
+        assert_eq!(
+            details.published_at.as_deref(),
+            Some("2024-01-06T12:00:00+00:00")
+        );
+        assert_eq!(details.license.as_deref(), Some("Unlicense OR MIT"));
+        assert_eq!(details.downloads, 1_234_567);
+
+        // Caches from before any of this was looked at still hold what repositories need
+        let response: CrateResponse =
+            serde_json::from_value(serde_json::json!({"crate": {"repository": null}})).unwrap();
+        assert_eq!(response.details(true).latest_version, None);
+    }
+
+    #[test]
+    fn picks_the_newest_matching_release() {
+        let published = versions(&[
+            ("1.3.0", true),
+            ("2.0.0", false),
+            ("1.2.1", false),
+            ("1.2.0", false),
+            ("1.4.0-rc.1", false),
+            ("garbage", false),
+        ]);
+        let newest = |requirement: &str| {
+            newest_matching(&published, &VersionReq::parse(requirement).unwrap())
+                .map(|version| version.to_string())
+        };
+
+        assert_eq!(newest("1").as_deref(), Some("1.2.1"));
+        assert_eq!(newest("=1.2.0").as_deref(), Some("1.2.0"));
+        assert_eq!(newest(">=1.4.0-rc.1, <2").as_deref(), Some("1.4.0-rc.1"));
+        assert_eq!(newest("3"), None);
+    }
+
+    /// Serve the index file of every crate with an ETag, answering 304 to requests that already have it and 404 to
+    /// requests for `missing`. Returns the index's URL and how many index files were sent in full.
+    fn serve_index() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let sent = Arc::new(AtomicUsize::new(0));
+
+        let counter = Arc::clone(&sent);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    request.push(line.to_ascii_lowercase());
+                }
+
+                let path = request[0].split(' ').nth(1).unwrap().to_owned();
+                let name = path.rsplit('/').next().unwrap().to_owned();
+                let response = if name == "missing" {
+                    "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_owned()
+                } else if request.iter().any(|line| line == "if-none-match: \"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\n\r\n".to_owned()
+                } else {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    let body =
+                        format!("{{\"name\":\"{name}\",\"vers\":\"1.0.0\",\"cksum\":\"abc\"}}\n");
+                    format!(
+                        "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                };
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+
+        (url, sent)
+    }
+
+    #[test]
+    fn reads_the_sparse_index_through_the_cache() {
+        let (url, sent) = serve_index();
+        let cache_directory = tempfile::tempdir().unwrap();
+        let client = |ttl: Duration| {
+            CratesIo::new(
+                IndexSource::Sparse(url.clone()),
+                Cache::new(cache_directory.path(), ttl, false),
+            )
+        };
+
+        let fresh = client(Duration::from_secs(60));
+        let names = ["tool", "ripgrep", "a", "syn", "missing"];
+        let results = fresh.versions_of_many(&names, 0);
+        assert_eq!(results.len(), names.len());
+        for result in &results[..4] {
+            let versions = result.as_ref().unwrap();
+            assert_eq!(versions[0].num, "1.0.0");
+            assert_eq!(versions[0].checksum.as_deref(), Some("abc"));
+        }
+        let err = results[4].as_ref().unwrap_err();
+        assert!(format!("{err:#}").contains("There's no crate named `missing`"));
+        assert_eq!(sent.load(Ordering::SeqCst), 4);
+
+        // Fresh entries don't need asking, stale ones only need the server to say they're still current
+        assert_eq!(
+            fresh
+                .newest_version("tool", false)
+                .ok()
+                .unwrap()
+                .to_string(),
+            "1.0.0"
+        );
+        let stale = client(Duration::ZERO);
+        assert_eq!(
+            stale
+                .newest_version("tool", false)
+                .ok()
+                .unwrap()
+                .to_string(),
+            "1.0.0"
+        );
+        assert_eq!(sent.load(Ordering::SeqCst), 4);
+
+        // Crates that don't exist aren't a failure to look them up
+        assert_eq!(fresh.lookup("missing").ok().unwrap(), None);
+        assert_eq!(sent.load(Ordering::SeqCst), 4);
+
+        // What was cached can be read without asking at all, however stale
+        assert_eq!(
+            stale
+                .cached_newest_version("tool", false)
+                .unwrap()
+                .to_string(),
+            "1.0.0"
+        );
+        assert_eq!(stale.cached_newest_version("serde", false), None);
+        assert_eq!(sent.load(Ordering::SeqCst), 4);
+
+        // Which another index knows nothing about
+        let mirror = CratesIo::new(
+            IndexSource::Sparse("http://127.0.0.1:1/mirror/".to_owned()),
+            Cache::new(cache_directory.path(), Duration::from_secs(60), false),
+        );
+        assert_eq!(mirror.cached_newest_version("tool", false), None);
+    }
+}
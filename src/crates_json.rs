@@ -0,0 +1,136 @@
+//! Cargo's own record of what it installed into a root, kept in `<root>/.crates2.json`. See [`crates_toml`] for the
+//! older record cargo keeps alongside it.
+//!
+//! Fields cargo may add in the future are kept as they are, so that the records survive us rewriting them.
+//!
+//! [`crates_toml`]: crate::crates_toml
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+pub const CRATES_JSON_FILE_NAME: &str = ".crates2.json";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CratesJson {
+    /// Keyed by cargo's package id, e.g. `ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)`
+    #[serde(default)]
+    pub installs: BTreeMap<String, InstallInfo>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+/// What cargo knows about an install. Cargo requires every one of these fields, so they're always written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstallInfo {
+    pub version_req: Option<String>,
+    pub bins: BTreeSet<String>,
+    pub features: BTreeSet<String>,
+    pub all_features: bool,
+    pub no_default_features: bool,
+    pub profile: String,
+    pub target: Option<String>,
+    pub rustc: Option<String>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, serde_json::Value>,
+}
+
+impl Default for InstallInfo {
+    /// What cargo itself records for installs it only knows about through `.crates.toml`
+    fn default() -> Self {
+        Self {
+            version_req: None,
+            bins: BTreeSet::new(),
+            features: BTreeSet::new(),
+            all_features: false,
+            no_default_features: false,
+            profile: "release".to_owned(),
+            target: None,
+            rustc: None,
+            other: BTreeMap::new(),
+        }
+    }
+}
+
+impl CratesJson {
+    /// Read the records of the install root `root`. Missing or unreadable records are treated as absent, since
+    /// they're only ever used as a hint.
+    pub fn load(root: &Path) -> Option<Self> {
+        let contents = fs::read_to_string(root.join(CRATES_JSON_FILE_NAME)).ok()?;
+
+        Self::parse(&contents).ok()
+    }
+
+    /// Parse records the way cargo does, an empty file standing for no records at all
+    pub fn parse(contents: &str) -> serde_json::Result<Self> {
+        if contents.is_empty() {
+            return Ok(Self::default());
+        }
+
+        serde_json::from_str(contents)
+    }
+
+    pub fn render(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    /// Every binary cargo says it installed into this root
+    pub fn binaries(&self) -> BTreeSet<&str> {
+        self.installs
+            .values()
+            .flat_map(|install| install.bins.iter().map(String::as_str))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CratesJson;
+
+    #[test]
+    fn reads_installed_binaries() {
+        let contents = r#"{
+            "installs": {
+                "cargo-edit 0.12.2 (registry+https://github.com/rust-lang/crates.io-index)": {
+                    "version_req": null,
+                    "bins": ["cargo-add", "cargo-rm", "cargo-set-version", "cargo-upgrade"],
+                    "features": [],
+                    "all_features": false,
+                    "no_default_features": false,
+                    "profile": "release",
+                    "target": "x86_64-unknown-linux-gnu",
+                    "rustc": "rustc 1.78.0 (9b00956e5 2024-04-29)"
+                }
+            }
+        }"#;
+
+        let crates_json: CratesJson = serde_json::from_str(contents).unwrap();
+
+        assert_eq!(
+            crates_json.binaries().into_iter().collect::<Vec<_>>(),
+            [
+                "cargo-add",
+                "cargo-rm",
+                "cargo-set-version",
+                "cargo-upgrade"
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips() {
+        let contents = r#"{"installs":{"ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)":{"version_req":null,"bins":["rg"],"features":[],"all_features":false,"no_default_features":false,"profile":"release","target":"x86_64-unknown-linux-gnu","rustc":"rustc 1.78.0 (9b00956e5 2024-04-29)","from_the_future":[1,2]}},"also_from_the_future":true}"#;
+
+        let crates_json = CratesJson::parse(contents).unwrap();
+        assert!(crates_json.other.contains_key("also_from_the_future"));
+        assert_eq!(crates_json.render().unwrap(), contents);
+
+        assert_eq!(CratesJson::parse("").unwrap(), CratesJson::default());
+        assert!(CratesJson::parse("{\"installs\": [").is_err());
+    }
+}
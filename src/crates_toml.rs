@@ -0,0 +1,64 @@
+//! Cargo's older record of what it installed into a root, kept in `<root>/.crates.toml` next to [`crates_json`]. It
+//! only maps package ids to their binaries, and cargo takes it as the source of truth: .crates2.json entries it
+//! doesn't list are dropped the next time cargo installs anything.
+//!
+//! [`crates_json`]: crate::crates_json
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+pub const CRATES_TOML_FILE_NAME: &str = ".crates.toml";
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CratesToml {
+    /// The binaries of every install, keyed by cargo's package id
+    #[serde(default)]
+    pub v1: BTreeMap<String, BTreeSet<String>>,
+    #[serde(flatten)]
+    pub other: BTreeMap<String, toml::Value>,
+}
+
+impl CratesToml {
+    /// Parse records the way cargo does, an empty file standing for no records at all
+    pub fn parse(contents: &str) -> Result<Self, toml::de::Error> {
+        if contents.is_empty() {
+            return Ok(Self::default());
+        }
+
+        toml::from_str(contents)
+    }
+
+    pub fn render(&self) -> Result<String, toml::ser::Error> {
+        toml::to_string(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CratesToml;
+
+    #[test]
+    fn round_trips() {
+        let contents = "[v1]
+\"cargo-edit 0.12.2 (registry+https://github.com/rust-lang/crates.io-index)\" = [\"cargo-add\", \"cargo-rm\"]
+\"ripgrep 14.1.0 (registry+https://github.com/rust-lang/crates.io-index)\" = [\"rg\"]
+
+[v2]
+from-the-future = true
+";
+
+        let crates_toml = CratesToml::parse(contents).unwrap();
+        assert_eq!(crates_toml.v1.len(), 2);
+        assert!(crates_toml.other.contains_key("v2"));
+        assert_eq!(
+            CratesToml::parse(&crates_toml.render().unwrap()).unwrap(),
+            crates_toml
+        );
+
+        assert_eq!(CratesToml::parse("").unwrap(), CratesToml::default());
+        assert!(CratesToml::parse("[v1").is_err());
+    }
+}
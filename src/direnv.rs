@@ -0,0 +1,196 @@
+//! direnv integration: a snippet for a project's `.envrc` that puts the versions pinned in its `.cargo-switch.toml`
+//! or `.tool-versions` first in `$PATH` whenever direnv loads it, nothing in `.cargo/bin` being touched along the
+//! way.
+//!
+//! The snippet calls back into `cargo-switch direnv --refresh`, which links the pinned versions' binaries into
+//! `.direnv/cargo-switch/bin`, next to the project file. direnv watches both files, so links follow the pins as they
+//! change.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::os::unix;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::project::Pin;
+use crate::project::ProjectFile;
+use crate::project::PROJECT_FILE_NAME;
+use crate::tool_versions::ToolVersions;
+use crate::tool_versions::TOOL_VERSIONS_FILE_NAME;
+use crate::wrapper;
+use crate::Switcher;
+
+/// Where, relative to the project's root, the links to its pinned versions live
+pub const LINKS_DIRECTORY: &str = ".direnv/cargo-switch/bin";
+const ENVRC_FILE_NAME: &str = ".envrc";
+/// The first line of the snippet, telling whether a `.envrc` already has it
+const SNIPPET_MARKER: &str =
+    "# cargo-switch: the versions pinned in .cargo-switch.toml come first in $PATH";
+
+/// What to add to a project's `.envrc`
+pub fn envrc_snippet() -> String {
+    format!(
+        "{SNIPPET_MARKER}
+watch_file {PROJECT_FILE_NAME} {TOOL_VERSIONS_FILE_NAME}
+if has cargo-switch; then
+  cargo-switch direnv --refresh || log_error \"cargo-switch failed to link the pinned versions\"
+  PATH_add {LINKS_DIRECTORY}
+else
+  log_error \"cargo-switch isn't installed, so the versions pinned in {PROJECT_FILE_NAME} aren't used\"
+fi
+"
+    )
+}
+
+/// Append the snippet to the `.envrc` next to the project file closest to `directory`, unless it's there already.
+/// Returns the path of the `.envrc` and whether it was changed.
+pub fn write_envrc(directory: &Path) -> Result<(PathBuf, bool)> {
+    let root = project_root(directory)?;
+    let path = root.join(ENVRC_FILE_NAME);
+
+    let mut contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if contents.lines().any(|line| line == SNIPPET_MARKER) {
+        return Ok((path, false));
+    }
+
+    if contents.is_empty().not() {
+        if contents.ends_with('\n').not() {
+            contents.push('\n');
+        }
+        contents.push('\n');
+    }
+    contents.push_str(&envrc_snippet());
+    fs::write(&path, contents).with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok((path, true))
+}
+
+/// The directory holding the project file closest to `directory`, or else the closest `.tool-versions`
+fn project_root(directory: &Path) -> Result<PathBuf> {
+    let path = match ProjectFile::discover(directory)? {
+        Some((path, _)) => path,
+        None => ToolVersions::discover(directory)?
+            .map(|(path, _)| path)
+            .with_context(|| {
+                format!(
+                    "No {PROJECT_FILE_NAME} or {TOOL_VERSIONS_FILE_NAME} in {} or any of its parents",
+                    directory.display()
+                )
+            })?,
+    };
+
+    Ok(path.parent().unwrap_or(directory).to_owned())
+}
+
+impl Switcher {
+    /// Link the binaries of the versions pinned by the project file closest to `directory` into the project's
+    /// [`LINKS_DIRECTORY`], removing whatever else is in there.
+    ///
+    /// Versions that aren't installed are warned about and left out, so that `$PATH` falls back to whatever it had
+    /// rather than pointing at nothing.
+    pub fn refresh_direnv_links(&self, directory: &Path) -> Result<()> {
+        let Some(project) = self.project_pins(directory)? else {
+            // The project file went away, and its pins along with it
+            let links = directory.join(LINKS_DIRECTORY);
+            if links.exists() {
+                fs::remove_dir_all(&links)
+                    .with_context(|| format!("Failed to remove {}", links.display()))?;
+            }
+            return Ok(());
+        };
+        let links = project.root.join(LINKS_DIRECTORY);
+        for conflict in &project.conflicts {
+            eprintln!("Warning: {conflict}");
+        }
+
+        // Binary names, along with what they should run and the version providing them
+        let mut wanted = BTreeMap::new();
+        for (package, Pin { version, .. }) in &project.pins {
+            let version = match self.pick_variant(package, version) {
+                Ok(version) => version,
+                Err(err) => {
+                    eprintln!("Warning: {package}@{version} is pinned but won't be used: {err:#}");
+                    continue;
+                }
+            };
+            let env = self.wrapper_env(package, &version);
+
+            for binary in self.version_binaries(package, &version)? {
+                let name = binary.file_name().unwrap_or_default().to_owned();
+                match wanted.get(&name) {
+                    Some((_, _, other)) => eprintln!(
+                        "Warning: {package}@{version} and {other} both provide {}, using the one from {other}",
+                        Path::new(&name).display()
+                    ),
+                    None => {
+                        wanted.insert(name, (binary, env.clone(), format!("{package}@{version}")));
+                    }
+                }
+            }
+        }
+
+        fs::create_dir_all(&links)
+            .with_context(|| format!("Failed to create {}", links.display()))?;
+        for entry in fs::read_dir(&links)? {
+            let entry = entry?;
+            if wanted.contains_key(&entry.file_name()).not() {
+                fs::remove_file(entry.path())?;
+            }
+        }
+
+        for (name, (binary, env, _)) in wanted {
+            let link = links.join(name);
+            if let Some(env) = env {
+                wrapper::write_wrapper(&link, &binary, &env)?;
+                continue;
+            }
+
+            // Only touch what changed, as other shells may be using the links
+            if fs::read_link(&link).is_ok_and(|target| target == binary) {
+                continue;
+            }
+            self.remove_link(&link)?;
+            unix::fs::symlink(&binary, &link)
+                .with_context(|| format!("Failed to link {}", link.display()))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::ops::Not;
+
+    use super::envrc_snippet;
+    use super::write_envrc;
+    use crate::project::PROJECT_FILE_NAME;
+
+    #[test]
+    fn appends_the_snippet_once() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("src");
+        fs::create_dir(&nested).unwrap();
+        fs::write(root.path().join(PROJECT_FILE_NAME), "[pins]\n").unwrap();
+        fs::write(root.path().join(".envrc"), "use flake").unwrap();
+
+        let (path, changed) = write_envrc(&nested).unwrap();
+        assert_eq!(path, root.path().join(".envrc"));
+        assert!(changed);
+        assert!(write_envrc(&nested).unwrap().1.not());
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("use flake\n\n{}", envrc_snippet())
+        );
+    }
+}
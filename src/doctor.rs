@@ -0,0 +1,298 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::backup::RESTORE_MARKER_NAME;
+use crate::install_root;
+use crate::link_style::LinkStyle;
+use crate::links::ActiveState;
+use crate::shadow::same_directory;
+use crate::shared::directory_names;
+use crate::spec::normalize_name;
+use crate::state::State;
+use crate::Switcher;
+
+impl Switcher {
+    /// Look for everything that could make cargo-switch misbehave, failing if anything was found. With
+    /// `convert_links`, existing links are rewritten to follow that style first. Packages installed under several
+    /// spellings are merged if `merge_duplicates` is set, or if the user agrees to it when asked, and so are the links
+    /// left in directories links don't go to anymore removed if `remove_stale_links` is.
+    pub fn doctor(
+        &self,
+        probe: bool,
+        convert_links: Option<LinkStyle>,
+        merge_duplicates: bool,
+        remove_stale_links: bool,
+        toolchain_drift: bool,
+    ) -> Result<()> {
+        let mut problems = 0;
+
+        if let Some(style) = convert_links {
+            println!("Converting links...");
+            let converted = self.convert_links(style)?;
+            println!("Converted {converted} link(s)");
+        }
+
+        println!("Checking the registry...");
+        if self.registry.join(RESTORE_MARKER_NAME).exists() {
+            println!(
+                "The registry is incomplete since a restore was interrupted, run `cargo switch restore` again"
+            );
+            problems += 1;
+        }
+
+        println!("Checking for packages installed under several spellings...");
+        for spellings in self.duplicate_packages()? {
+            let names = spellings.join(", ");
+            let merge = merge_duplicates
+                || self.confirm(&format!(
+                    "{names} are the same package, installed under different spellings. Merge them?"
+                ))?;
+            if merge {
+                let merged = self.merge_duplicates(&spellings)?;
+                println!("Merged {names} into {merged}");
+            } else {
+                println!(
+                    "{names} are the same package, installed under different spellings. Run `cargo switch doctor \
+                     --merge-duplicates` to merge them"
+                );
+                problems += 1;
+            }
+        }
+
+        println!("Checking for links left in other directories...");
+        for (directory, links) in self.stale_links()? {
+            let question = format!(
+                "{} holds {} link(s) into the registry, as binaries were linked there before, but links go to {} now. \
+                 Remove them?",
+                directory.display(),
+                links.len(),
+                self.cargo_bin.display()
+            );
+            if remove_stale_links || self.confirm(&question)? {
+                for link in &links {
+                    self.remove_link(link)?;
+                    println!("Removed {}", link.display());
+                }
+                self.forget_link_dir(&directory)?;
+            } else {
+                println!(
+                    "{} holds {} link(s) into the registry, as binaries were linked there before, but links go to {} \
+                     now. Run `cargo switch doctor --remove-stale-links` to remove them, or pass --link-dir {} to \
+                     keep linking there",
+                    directory.display(),
+                    links.len(),
+                    self.cargo_bin.display(),
+                    directory.display()
+                );
+                problems += 1;
+            }
+        }
+
+        // Not a problem as such, but whoever meant another one should hear about it
+        if self.config.cargo_bin.is_none() && self.config.link_dir.is_none() {
+            let path = env::var_os("PATH").unwrap_or_default();
+            let chosen = install_root::find_cargo_bin(&path);
+            if chosen.is_some_and(|chosen| same_directory(&chosen, &self.cargo_bin)) {
+                install_root::warn_about_several_cargo_bins(&path, &self.cargo_bin);
+            }
+        }
+
+        println!("Checking for broken links...");
+        for package in self.installed_packages()? {
+            if let ActiveState::Broken { version } = self.active_state(&package)? {
+                println!("{package}: the links to {package}@{version} are broken, switch to another version to fix them");
+                problems += 1;
+            }
+        }
+
+        println!("Checking for shadowed binaries...");
+        problems += self.report_shadowing(probe)?;
+
+        if toolchain_drift {
+            println!("Checking for versions built with old compilers...");
+            problems += self.report_toolchain_drift(None)?;
+        }
+
+        if problems > 0 {
+            bail!("Found {problems} problem(s)");
+        }
+
+        println!("Everything looks good");
+
+        Ok(())
+    }
+
+    /// The links into the registry in every directory links were made into before, other than the one they go to now,
+    /// keyed by directory. Directories without any are forgotten about.
+    fn stale_links(&self) -> Result<BTreeMap<PathBuf, Vec<PathBuf>>> {
+        let state = State::load(&self.registry)?;
+        let mut stale = BTreeMap::new();
+        for directory in &state.link_dirs {
+            if same_directory(directory, &self.cargo_bin) {
+                continue;
+            }
+
+            let mut links = Vec::new();
+            if let Ok(entries) = fs::read_dir(directory) {
+                for maybe_entry in entries {
+                    let link = maybe_entry?.path();
+                    if self.link_target_in(directory, &link).is_some_and(|target| {
+                        self.registries()
+                            .any(|registry| target.starts_with(registry))
+                    }) {
+                        links.push(link);
+                    }
+                }
+            }
+
+            if links.is_empty() {
+                self.forget_link_dir(directory)?;
+            } else {
+                links.sort();
+                stale.insert(directory.clone(), links);
+            }
+        }
+
+        Ok(stale)
+    }
+
+    fn forget_link_dir(&self, directory: &Path) -> Result<()> {
+        let _links = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        if state.link_dirs.remove(directory) {
+            state.save(&self.registry)?;
+        }
+
+        Ok(())
+    }
+
+    /// The packages installed under several spellings that only differ in which of `-` and `_` they use, as was
+    /// possible before names were normalized, see [`normalize_name`]
+    fn duplicate_packages(&self) -> Result<Vec<Vec<String>>> {
+        let mut spellings: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for package in self.installed_packages()? {
+            spellings
+                .entry(normalize_name(&package))
+                .or_default()
+                .push(package);
+        }
+
+        Ok(spellings
+            .into_values()
+            .filter(|spellings| spellings.len() > 1)
+            .collect())
+    }
+
+    /// Move every version of the packages in `spellings` into one of them, the one with an active version if any,
+    /// or else the one with the most versions, returning which one it was. Versions installed under several
+    /// spellings keep the build of that one.
+    fn merge_duplicates(&self, spellings: &[String]) -> Result<String> {
+        let mut candidates = Vec::new();
+        for package in spellings {
+            let active = self.linked_version(package)?.is_some();
+            let versions = self.installed_versions(package)?.len();
+            candidates.push((active, versions, package));
+        }
+        let target = candidates
+            .iter()
+            .max_by_key(|(active, versions, _)| (*active, *versions))
+            .map(|&(_, _, package)| package.clone())
+            .context("Nothing to merge")?;
+        self.merge_spellings(spellings, &target)?;
+
+        Ok(target)
+    }
+
+    /// Move every version of the packages in `spellings` into `target`, which needn't be installed yet, along with
+    /// whatever the state says about them. The active version of the others is switched to again if `target` has
+    /// none, while versions installed under several spellings keep the build of `target`.
+    pub(crate) fn merge_spellings(&self, spellings: &[String], target: &str) -> Result<()> {
+        let mut locked_packages: Vec<_> = spellings.iter().map(String::as_str).collect();
+        locked_packages.push(target);
+        locked_packages.sort_unstable();
+        locked_packages.dedup();
+        let _locks = locked_packages
+            .iter()
+            .map(|package| self.lock_package(package))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut replaced_active = None;
+        {
+            let _links = self.lock_links()?;
+            let mut state = State::load(&self.registry)?;
+            // Created along with its lock
+            let target_directory = self.registry.join(target);
+            for package in spellings.iter().filter(|package| *package != target) {
+                replaced_active = replaced_active.or(self.linked_version(package)?);
+                // Links into a directory about to go away would only dangle
+                for link in self.managed_links()? {
+                    if link.package == *package {
+                        self.remove_link(&link.link)?;
+                        println!("Removed {}", link.link.display());
+                    }
+                }
+                state
+                    .versioned_links
+                    .retain(|_, owner| owner.package != *package);
+
+                // Only what's in our own registry can be moved
+                for version in directory_names(&self.registry.join(package))? {
+                    let from = self.registry.join(package).join(&version);
+                    let to = target_directory.join(&version);
+                    if to.exists() {
+                        fs::remove_dir_all(&from)
+                            .with_context(|| format!("Failed to remove {}", from.display()))?;
+                        println!(
+                            "Removed {package}@{version}, as {target}@{version} is installed too"
+                        );
+                    } else {
+                        fs::rename(&from, &to).with_context(|| {
+                            format!("Failed to move {} to {}", from.display(), to.display())
+                        })?;
+                    }
+                }
+
+                // What was recorded under the other spelling carries over, unless it says otherwise
+                if let Some(default) = state.defaults.remove(package) {
+                    state.defaults.entry(target.to_owned()).or_insert(default);
+                }
+                for (channel, version) in state.channels.remove(package).unwrap_or_default() {
+                    let channels = state.channels.entry(target.to_owned()).or_default();
+                    channels.entry(channel).or_insert(version);
+                }
+                for (version, at) in state.activated_at.remove(package).unwrap_or_default() {
+                    let activated_at = state.activated_at.entry(target.to_owned()).or_default();
+                    let latest = activated_at.entry(version).or_insert(at);
+                    *latest = (*latest).max(at);
+                }
+                let locked = state.locked.remove(package).unwrap_or_default();
+                if locked.is_empty().not() {
+                    state
+                        .locked
+                        .entry(target.to_owned())
+                        .or_default()
+                        .extend(locked);
+                }
+            }
+            state.save(&self.registry)?;
+        }
+
+        // The merged versions may deserve versioned links of their own
+        self.sync_versioned_links(target)?;
+        if let (Some(version), None, false) =
+            (replaced_active, self.linked_version(target)?, self.system)
+        {
+            self.switch_package_unlocked(&format!("{target}@{version}"))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use serde::de::DeserializeOwned;
+
+use crate::checksum::sha256_file;
+use crate::extract::extract;
+use crate::extract::find_executables;
+use crate::extract::ArtifactKind;
+use crate::network;
+use crate::network::Route;
+use crate::retry::Failure;
+
+/// How we introduce ourselves to servers, as crates.io asks API users to
+pub const USER_AGENT: &str = concat!(
+    "cargo-switch/",
+    env!("CARGO_PKG_VERSION"),
+    " (https://github.com/vrmiguel/cargo-switch)"
+);
+
+/// Directory, inside of the version being installed, where artifacts are downloaded and unpacked
+const DOWNLOAD_DIRECTORY_NAME: &str = ".download";
+
+/// Whether a failed request is worth trying again
+fn classify(err: ureq::Error, url: &str, route: &Route) -> Failure {
+    let transient = match &err {
+        ureq::Error::StatusCode(status) => *status == 429 || *status >= 500,
+        ureq::Error::Io(_)
+        | ureq::Error::Timeout(_)
+        | ureq::Error::HostNotFound
+        | ureq::Error::Protocol(_)
+        | ureq::Error::ConnectionFailed
+        | ureq::Error::BodyStalled => true,
+        _ => false,
+    };
+
+    let timed_out = matches!(err, ureq::Error::Timeout(_));
+    let err = anyhow!(err).context(format!(
+        "Failed to fetch {url}{}",
+        route.describe(timed_out)
+    ));
+    if transient {
+        Failure::Transient(err)
+    } else {
+        Failure::Permanent(err)
+    }
+}
+
+/// Download `url` into the file at `destination`
+pub fn download(url: &str, destination: &Path) -> Result<(), Failure> {
+    let route = network::route(url);
+    let response = route
+        .agent
+        .get(url)
+        .header("User-Agent", USER_AGENT)
+        .call()
+        .map_err(|err| classify(err, url, &route))?;
+
+    let mut file = File::create(destination)
+        .with_context(|| format!("Failed to create {}", destination.display()))
+        .map_err(Failure::Permanent)?;
+    io::copy(&mut response.into_body().into_reader(), &mut file)
+        .map_err(|err| classify(ureq::Error::Io(err), url, &route))?;
+
+    Ok(())
+}
+
+/// Fetch `url` and parse the JSON it responds with
+pub fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, Failure> {
+    let route = network::route(url);
+    let mut response = route
+        .agent
+        .get(url)
+        .header("User-Agent", USER_AGENT)
+        .header("Accept", "application/json")
+        .call()
+        .map_err(|err| classify(err, url, &route))?;
+
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|err| classify(err, url, &route))?;
+
+    serde_json::from_str(&body)
+        .with_context(|| format!("{url} responded with unexpected JSON"))
+        .map_err(Failure::Permanent)
+}
+
+/// What a server said about a resource, as of the last time it was fetched. Cached responses are only worth
+/// fetching again once the server says they changed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+/// What a conditional request got back
+#[derive(Debug)]
+pub enum Fetched {
+    /// The resource changed since `Validators` were given, or they weren't
+    Modified {
+        body: String,
+        validators: Validators,
+    },
+    NotModified,
+    NotFound,
+}
+
+/// Fetch `url` unless it didn't change since it was fetched with `validators`
+pub fn get_conditional(url: &str, validators: &Validators) -> Result<Fetched, Failure> {
+    let route = network::route(url);
+    let mut request = route.agent.get(url).header("User-Agent", USER_AGENT);
+    if let Some(etag) = &validators.etag {
+        request = request.header("If-None-Match", etag);
+    }
+    if let Some(last_modified) = &validators.last_modified {
+        request = request.header("If-Modified-Since", last_modified);
+    }
+    let mut response = request
+        .config()
+        .http_status_as_error(false)
+        .build()
+        .call()
+        .map_err(|err| classify(err, url, &route))?;
+
+    match response.status().as_u16() {
+        304 => return Ok(Fetched::NotModified),
+        // As cargo does, since the servers behind sparse indexes answer any of these for crates they don't have
+        403 | 404 | 410 | 451 => return Ok(Fetched::NotFound),
+        200..=299 => {}
+        status => return Err(classify(ureq::Error::StatusCode(status), url, &route)),
+    }
+
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned)
+    };
+    let validators = Validators {
+        etag: header("etag"),
+        last_modified: header("last-modified"),
+    };
+    let body = response
+        .body_mut()
+        .read_to_string()
+        .map_err(|err| classify(err, url, &route))?;
+
+    Ok(Fetched::Modified { body, validators })
+}
+
+/// Download the artifact at `url` and lay its executables out in `target_path/bin`, as `cargo install` would have.
+/// Nothing is extracted unless the artifact's SHA-256 checksum is `sha256`. Returns how long it all took.
+pub fn install_artifact(
+    package: &str,
+    url: &str,
+    sha256: &str,
+    target_path: &Path,
+) -> Result<Duration, Failure> {
+    let started = Instant::now();
+
+    let staging = target_path.join(DOWNLOAD_DIRECTORY_NAME);
+    let _ = fs::remove_dir_all(&staging);
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create {}", staging.display()))
+        .map_err(Failure::Permanent)?;
+
+    let result = download(url, &staging.join("artifact")).and_then(|()| {
+        unpack_artifact(package, url, sha256, &staging, &target_path.join("bin"))
+            .map_err(Failure::Permanent)
+    });
+    let _ = fs::remove_dir_all(&staging);
+    result?;
+
+    Ok(started.elapsed())
+}
+
+fn unpack_artifact(
+    package: &str,
+    url: &str,
+    sha256: &str,
+    staging: &Path,
+    bin_path: &Path,
+) -> Result<()> {
+    let artifact = staging.join("artifact");
+    let checksum = sha256_file(&artifact)?;
+    ensure!(
+        checksum.eq_ignore_ascii_case(sha256.trim()),
+        "Checksum mismatch for {url}: expected {}, got {checksum}",
+        sha256.trim()
+    );
+
+    let extracted = staging.join("extracted");
+    extract(&artifact, ArtifactKind::from_url(url), package, &extracted)?;
+
+    fs::create_dir_all(bin_path)?;
+    for (name, path) in find_executables(&extracted, package)? {
+        let destination = bin_path.join(&name);
+        fs::rename(&path, &destination).with_context(|| {
+            format!(
+                "Failed to move {} into {}",
+                path.display(),
+                bin_path.display()
+            )
+        })?;
+        let mode = fs::metadata(&destination)?.permissions().mode();
+        fs::set_permissions(&destination, fs::Permissions::from_mode(mode | 0o755))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+//! Telling which versions were built by a compiler that fell behind the default toolchain, which `rustup update`
+//! moves on while the binaries stay as they were. Now and then that matters, be it for linker or runtime issues or
+//! for target features older compilers lack, and rebuilding them is the cure.
+//!
+//! The compiler of each version is whatever `rustc --version` said when it was built, as recorded in its metadata.
+//! Versions installed before it was recorded can't be told about.
+
+use std::ops::Not;
+
+use anyhow::Result;
+
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::msrv::RustcVersion;
+use crate::Switcher;
+
+/// How many releases behind the default toolchain builds may be before they're flagged, unless the
+/// `max-toolchain-drift` config key says otherwise. About half a year's worth.
+pub const DEFAULT_MAX_TOOLCHAIN_DRIFT: u64 = 4;
+
+/// Which compiler built a version, compared with the default toolchain's
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drift {
+    /// Built with `rustc`, `behind` releases older than the default toolchain's
+    Behind { rustc: RustcVersion, behind: u64 },
+    /// Nothing was recorded about the compiler, or nothing that could be read
+    Unknown,
+}
+
+impl Switcher {
+    /// The versions of every package, or only of `package`, built with a compiler more than `max-toolchain-drift`
+    /// releases older than `current`, along with the ones whose compiler isn't known. Versions that weren't built by
+    /// us, as downloaded or added ones, are left out.
+    pub fn toolchain_drift(
+        &self,
+        package: Option<&str>,
+        current: &RustcVersion,
+    ) -> Result<Vec<(String, String, Drift)>> {
+        let max_drift = self
+            .config
+            .max_toolchain_drift
+            .unwrap_or(DEFAULT_MAX_TOOLCHAIN_DRIFT);
+        let packages = match package {
+            Some(package) => vec![self.canonical_name(package)?],
+            None => self.installed_packages()?,
+        };
+
+        let mut drifted = Vec::new();
+        for package in packages {
+            for version in self.installed_versions(&package)? {
+                let metadata = VersionMetadata::load(&self.version_path(&package, &version))?;
+                if let Some(Source::External | Source::Url { .. }) = metadata
+                    .as_ref()
+                    .and_then(|metadata| metadata.source.as_ref())
+                {
+                    continue;
+                }
+
+                let rustc = metadata
+                    .and_then(|metadata| metadata.rustc)
+                    .and_then(|rustc| RustcVersion::parse(&rustc));
+                let drift = match rustc {
+                    Some(rustc) if rustc.is_older_than(current).not() => continue,
+                    Some(rustc) => {
+                        let behind = rustc.releases_behind(current);
+                        if behind <= max_drift {
+                            continue;
+                        }
+                        Drift::Behind { rustc, behind }
+                    }
+                    None => Drift::Unknown,
+                };
+                drifted.push((package.clone(), version, drift));
+            }
+        }
+
+        Ok(drifted)
+    }
+
+    /// Print which versions of every package, or only of `package`, were built with a compiler too far behind the
+    /// default toolchain, returning how many were
+    pub(crate) fn report_toolchain_drift(&self, package: Option<&str>) -> Result<usize> {
+        let current = self.installer.rustc_version(None);
+        let Some(current) = current.as_deref().and_then(RustcVersion::parse) else {
+            println!("Couldn't tell which rustc the default toolchain has, so there's nothing to compare with");
+            return Ok(0);
+        };
+
+        let mut behind = 0;
+        let mut unknown = Vec::new();
+        for (package, version, drift) in self.toolchain_drift(package, &current)? {
+            match drift {
+                Drift::Behind {
+                    rustc,
+                    behind: releases,
+                } => {
+                    println!(
+                        "{package}@{version} was built with rustc {rustc}, {releases} releases behind the default \
+                         toolchain's rustc {current}. Run `cargo switch rebuild {package}@{version}` to build it \
+                         again"
+                    );
+                    behind += 1;
+                }
+                Drift::Unknown => unknown.push(format!("{package}@{version}")),
+            }
+        }
+        if unknown.is_empty().not() {
+            println!(
+                "Which rustc built {} is unknown, as it wasn't recorded",
+                unknown.join(", ")
+            );
+        }
+
+        Ok(behind)
+    }
+}
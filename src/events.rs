@@ -0,0 +1,217 @@
+//! Machine-readable events, emitted as things happen for whatever wraps cargo-switch, as with
+//! `--message-format json`.
+//!
+//! Every event is a JSON object on a line of its own, telling what it is through its `event` key:
+//!
+//! ```json
+//! {"event":"install-started","package":"ripgrep","version":"14.1.0"}
+//! {"event":"cargo-output","chunk":"   Compiling ripgrep v14.1.0"}
+//! {"event":"install-finished","package":"ripgrep","version":"14.1.0","duration-ms":41250,"location":"…","binaries":["…/bin/rg"]}
+//! {"event":"link-replaced","link":"…/.cargo/bin/rg","old-target":"…/13.0.0/bin/rg","new-target":"…/14.1.0/bin/rg"}
+//! {"event":"summary","specs":[{"spec":"ripgrep@14.1.0","outcome":"installed","detail":"41s, rg (4.2 MiB)"}]}
+//! {"event":"error","kind":"build-failed","message":"…"}
+//! ```
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::os::fd::FromRawFd;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::summary::SpecOutcome;
+use crate::Switcher;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(
+    tag = "event",
+    rename_all = "kebab-case",
+    rename_all_fields = "kebab-case"
+)]
+pub enum Event {
+    /// A version started building, or downloading
+    InstallStarted { package: String, version: String },
+    /// A line of what cargo printed while building
+    CargoOutput { chunk: String },
+    /// A version was built into the registry
+    InstallFinished {
+        package: String,
+        version: String,
+        duration_ms: u64,
+        /// The version's directory in the registry
+        location: PathBuf,
+        binaries: Vec<PathBuf>,
+    },
+    /// A link, or wrapper script, was created in `.cargo/bin` where there was nothing
+    LinkCreated { link: PathBuf, target: PathBuf },
+    /// A link, or wrapper script, in `.cargo/bin` was pointed somewhere else
+    LinkReplaced {
+        link: PathBuf,
+        /// Where the link pointed before, if it was a link rather than a wrapper script or a binary
+        old_target: Option<PathBuf>,
+        new_target: PathBuf,
+    },
+    /// A command dealing with many specs at once is done with them all, see [`summary`]
+    ///
+    /// [`summary`]: crate::summary
+    Summary { specs: Vec<SpecOutcome> },
+    /// The command failed
+    Error { kind: ErrorKind, message: String },
+}
+
+impl Event {
+    /// The event for a command failing with `error`
+    pub fn error(error: &anyhow::Error) -> Self {
+        Event::Error {
+            kind: error_kind(error),
+            message: format!("{error:#}"),
+        }
+    }
+}
+
+/// What kind of failure an [`Event::Error`] reports, for wrappers to act on without parsing messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    /// The package or version asked for isn't installed
+    NotInstalled,
+    /// Another cargo-switch held on to the package, or to the links, for too long
+    Busy,
+    /// Building, or downloading, a version failed
+    BuildFailed,
+    /// The registry was written by a newer cargo-switch
+    UnsupportedFormat,
+    /// A build ran for longer than `--timeout` allows, and was killed
+    TimedOut,
+    Other,
+}
+
+/// What cargo-switch exits with when it failed because a build timed out, as `timeout` does
+pub const TIMED_OUT_EXIT_CODE: i32 = 124;
+
+/// An error tagged with its kind, reading exactly like the error it wraps
+#[derive(Debug)]
+struct Classified {
+    kind: ErrorKind,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.error.to_string(), f)
+    }
+}
+
+impl Error for Classified {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.chain().nth(1)
+    }
+}
+
+/// Tag `error` as being of `kind`, which [`error_kind`] finds back through whatever context is added later
+pub(crate) fn classify(error: anyhow::Error, kind: ErrorKind) -> anyhow::Error {
+    anyhow::Error::new(Classified { kind, error })
+}
+
+/// The kind `error` was tagged with, if any
+pub fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<Classified>())
+        .map_or(ErrorKind::Other, |classified| classified.kind)
+}
+
+/// How commands report what they're doing
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// Messages for humans only
+    #[default]
+    Human,
+    /// Events as JSON lines on stdout, messages for humans going to stderr
+    Json,
+}
+
+/// Where events go
+pub trait EventSink: fmt::Debug {
+    fn emit(&self, event: &Event);
+}
+
+/// Writes events as JSON lines to what was stdout
+#[derive(Debug)]
+pub struct JsonLines {
+    output: File,
+}
+
+impl JsonLines {
+    /// Take stdout over for events, everything else printed to it, cargo's output included, going to stderr
+    pub fn take_stdout() -> Result<Self> {
+        // Close-on-exec, so that cargo can't write to it
+        let fd = unsafe { libc::fcntl(libc::STDOUT_FILENO, libc::F_DUPFD_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let output = unsafe { File::from_raw_fd(fd) };
+        if unsafe { libc::dup2(libc::STDERR_FILENO, libc::STDOUT_FILENO) } < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+
+        Ok(Self { output })
+    }
+
+    pub fn try_clone(&self) -> Result<Self> {
+        Ok(Self {
+            output: self.output.try_clone()?,
+        })
+    }
+}
+
+impl EventSink for JsonLines {
+    fn emit(&self, event: &Event) {
+        let Ok(mut line) = serde_json::to_string(event) else {
+            return;
+        };
+        line.push('\n');
+        // Whoever reads the events going away shouldn't stop whatever is being done
+        let _ = (&self.output).write_all(line.as_bytes());
+    }
+}
+
+impl Switcher {
+    pub(crate) fn emit(&self, event: Event) {
+        if let Some(events) = &self.events {
+            events.emit(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use anyhow::Context;
+
+    use super::classify;
+    use super::error_kind;
+    use super::ErrorKind;
+
+    #[test]
+    fn finds_error_kinds_through_context() {
+        let error = classify(anyhow!("could not compile"), ErrorKind::BuildFailed);
+        assert_eq!(error_kind(&error), ErrorKind::BuildFailed);
+        assert_eq!(error.to_string(), "could not compile");
+
+        let error = Err::<(), _>(error)
+            .context("Failed to install tool@1.0.0")
+            .unwrap_err();
+        assert_eq!(error_kind(&error), ErrorKind::BuildFailed);
+        assert_eq!(
+            format!("{error:#}"),
+            "Failed to install tool@1.0.0: could not compile"
+        );
+
+        assert_eq!(error_kind(&anyhow!("oops")), ErrorKind::Other);
+    }
+}
@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::ops::Not;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::install::InstallOptions;
+use crate::spec::parse_spec;
+use crate::Switcher;
+
+/// Split every one of `specs` into a package and a version, in order and without duplicates, making sure no package
+/// is asked for in two different versions
+pub fn unique_versions(specs: &[String]) -> Result<Vec<(&str, &str)>> {
+    let mut versions: BTreeMap<&str, &str> = BTreeMap::new();
+    let mut order = Vec::new();
+    for spec in specs {
+        let (package, version) = parse_spec(spec)?;
+
+        match versions.insert(package, version) {
+            Some(other) if other != version => {
+                bail!("Conflicting versions of {package}: {other} and {version}")
+            }
+            Some(_) => {}
+            None => order.push((package, version)),
+        }
+    }
+
+    Ok(order)
+}
+
+impl Switcher {
+    /// Run `command` with the binaries of every one of `specs` first in `$PATH`, without touching the links in
+    /// `.cargo/bin`. Missing versions are installed first if `install` is set.
+    ///
+    /// On success, this never returns since the current process is replaced by the command, which therefore keeps
+    /// its exit code and signals.
+    pub fn exec_with(&self, specs: &[String], install: bool, command: &[OsString]) -> Result<()> {
+        let specs: Vec<_> = specs
+            .iter()
+            .map(|spec| self.canonical_spec(spec))
+            .collect::<Result<_>>()?;
+        let Some((program, args)) = command.split_first() else {
+            bail!("No command given, pass it after `--`");
+        };
+
+        let order = unique_versions(&specs)?;
+
+        // Everything must be in place before the command starts
+        for (package, version) in &order {
+            let missing = self.version_path(package, version).exists().not();
+            if missing && install {
+                let options = InstallOptions {
+                    no_switch: true,
+                    ..InstallOptions::default()
+                };
+                self.install_package(&format!("{package}@{version}"), &options)?
+                    .print();
+            }
+        }
+
+        let mut child = Command::new(program);
+        child
+            .args(args)
+            .env("PATH", self.path_with_versions(&order)?);
+        for (package, version) in &order {
+            for (name, value) in self.wrapper_env(package, version).unwrap_or_default() {
+                child.env(name, value);
+            }
+        }
+
+        let err = child.exec();
+
+        Err(err).with_context(|| format!("Failed to run {}", Path::new(program).display()))
+    }
+}
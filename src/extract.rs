@@ -0,0 +1,275 @@
+//! Unpacking downloaded release artifacts: `.tar.gz`, `.tar.xz` and `.zip` archives, or bare binaries.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::ops::Not;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    TarGz,
+    TarXz,
+    Zip,
+    /// The artifact is the binary itself
+    Binary,
+}
+
+impl ArtifactKind {
+    /// Tell the kind of artifact from its URL
+    pub fn from_url(url: &str) -> Self {
+        let path = url.split(['?', '#']).next().unwrap_or_default();
+
+        if path.ends_with(".tar.gz") || path.ends_with(".tgz") {
+            ArtifactKind::TarGz
+        } else if path.ends_with(".tar.xz") || path.ends_with(".txz") {
+            ArtifactKind::TarXz
+        } else if path.ends_with(".zip") {
+            ArtifactKind::Zip
+        } else {
+            ArtifactKind::Binary
+        }
+    }
+}
+
+/// Make sure that `path`, coming from an archive, stays inside the directory it's extracted into
+fn check_entry_path(path: &Path) -> Result<()> {
+    let escapes = path.components().any(|component| {
+        matches!(
+            component,
+            Component::ParentDir | Component::RootDir | Component::Prefix(_)
+        )
+    });
+    ensure!(
+        escapes.not(),
+        "Refusing to extract {}, which would end up outside of the extraction directory",
+        path.display()
+    );
+
+    Ok(())
+}
+
+fn extract_tar(reader: impl Read, destination: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(reader);
+    for maybe_entry in archive.entries()? {
+        let mut entry = maybe_entry?;
+        let path = entry.path()?.into_owned();
+        check_entry_path(&path)?;
+
+        // Links could point anywhere, and binaries don't need them
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_file().not() && entry_type.is_dir().not() {
+            continue;
+        }
+
+        ensure!(
+            entry.unpack_in(destination)?,
+            "Refusing to extract {}",
+            path.display()
+        );
+    }
+
+    Ok(())
+}
+
+fn extract_zip(file: File, destination: &Path) -> Result<()> {
+    let mut archive = zip::ZipArchive::new(file)?;
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let Some(path) = entry.enclosed_name() else {
+            bail!(
+                "Refusing to extract {}, which would end up outside of the extraction directory",
+                String::from_utf8_lossy(entry.name_raw())
+            );
+        };
+        check_entry_path(&path)?;
+
+        let output = destination.join(&path);
+        if entry.is_dir() {
+            fs::create_dir_all(&output)?;
+            continue;
+        }
+        if entry.is_file().not() {
+            continue;
+        }
+
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        io::copy(&mut entry, &mut File::create(&output)?)
+            .with_context(|| format!("Failed to extract {}", path.display()))?;
+        if let Some(mode) = entry.unix_mode() {
+            fs::set_permissions(&output, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Unpack the artifact at `artifact` into `destination`. Bare binaries are copied there as `name`.
+pub fn extract(artifact: &Path, kind: ArtifactKind, name: &str, destination: &Path) -> Result<()> {
+    fs::create_dir_all(destination)?;
+    let open =
+        || File::open(artifact).with_context(|| format!("Failed to open {}", artifact.display()));
+
+    match kind {
+        ArtifactKind::TarGz => extract_tar(flate2::read::GzDecoder::new(open()?), destination),
+        ArtifactKind::TarXz => extract_tar(xz2::read::XzDecoder::new(open()?), destination),
+        ArtifactKind::Zip => extract_zip(open()?, destination),
+        ArtifactKind::Binary => {
+            let binary = destination.join(name);
+            fs::copy(artifact, &binary)?;
+            fs::set_permissions(&binary, fs::Permissions::from_mode(0o755))?;
+            Ok(())
+        }
+    }
+}
+
+fn collect_files(directory: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for maybe_entry in fs::read_dir(directory)? {
+        let entry = maybe_entry?;
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    Ok(())
+}
+
+/// The executables among what was extracted into `directory`, keyed by file name. Archives that lost the executable
+/// bits along the way are expected to at least have a file named after the package.
+pub fn find_executables(directory: &Path, package: &str) -> Result<BTreeMap<OsString, PathBuf>> {
+    let mut files = Vec::new();
+    collect_files(directory, &mut files)?;
+    files.sort();
+
+    let mut executables = BTreeMap::new();
+    for file in &files {
+        let executable = fs::metadata(file)?.permissions().mode() & 0o111 != 0;
+        if executable.not() {
+            continue;
+        }
+
+        let name = file.file_name().unwrap_or_default().to_owned();
+        if let Some(other) = executables.insert(name.clone(), file.clone()) {
+            bail!(
+                "The artifact has several executables called {}: {} and {}",
+                Path::new(&name).display(),
+                other.display(),
+                file.display()
+            );
+        }
+    }
+
+    if executables.is_empty() {
+        let named = files
+            .iter()
+            .find(|file| file.file_name().is_some_and(|name| name == package))
+            .with_context(|| "Found no executable in the artifact")?;
+        executables.insert(package.into(), named.clone());
+    }
+
+    Ok(executables)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::ops::Not;
+
+    use super::extract;
+    use super::find_executables;
+    use super::ArtifactKind;
+
+    fn tar_gz(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut builder = tar::Builder::new(encoder);
+        for (path, mode, contents) in entries {
+            let mut header = tar::Header::new_gnu();
+            // `append_data` refuses to write `..`, so the path goes straight into the header
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(*mode);
+            header.set_cksum();
+            builder.append(&header, *contents).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn tells_artifacts_apart() {
+        assert_eq!(
+            ArtifactKind::from_url("https://example.com/mytool-1.4.0-x86_64-linux.tar.gz"),
+            ArtifactKind::TarGz
+        );
+        assert_eq!(
+            ArtifactKind::from_url("https://example.com/mytool.tar.xz?raw=true"),
+            ArtifactKind::TarXz
+        );
+        assert_eq!(
+            ArtifactKind::from_url("https://example.com/mytool.zip"),
+            ArtifactKind::Zip
+        );
+        assert_eq!(
+            ArtifactKind::from_url("https://example.com/mytool-linux-amd64"),
+            ArtifactKind::Binary
+        );
+    }
+
+    #[test]
+    fn extracts_executables() {
+        let root = tempfile::tempdir().unwrap();
+        let artifact = root.path().join("mytool.tar.gz");
+        fs::write(
+            &artifact,
+            tar_gz(&[
+                ("mytool-1.4.0/mytool", 0o755, b"#!/bin/sh\n"),
+                ("mytool-1.4.0/mytool-helper", 0o755, b"#!/bin/sh\n"),
+                ("mytool-1.4.0/README.md", 0o644, b"# mytool\n"),
+            ]),
+        )
+        .unwrap();
+
+        let destination = root.path().join("extracted");
+        extract(&artifact, ArtifactKind::TarGz, "mytool", &destination).unwrap();
+        let executables = find_executables(&destination, "mytool").unwrap();
+        assert_eq!(
+            executables.keys().collect::<Vec<_>>(),
+            ["mytool", "mytool-helper"]
+        );
+    }
+
+    #[test]
+    fn refuses_path_traversal() {
+        let root = tempfile::tempdir().unwrap();
+        let destination = root.path().join("extracted");
+
+        let artifact = root.path().join("evil.tar.gz");
+        fs::write(&artifact, tar_gz(&[("../evil", 0o755, b"#!/bin/sh\n")])).unwrap();
+        assert!(extract(&artifact, ArtifactKind::TarGz, "evil", &destination).is_err());
+        assert!(root.path().join("evil").exists().not());
+
+        let artifact = root.path().join("evil.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&artifact).unwrap());
+        zip.start_file("../evil", zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(b"#!/bin/sh\n").unwrap();
+        zip.finish().unwrap();
+        assert!(extract(&artifact, ArtifactKind::Zip, "evil", &destination).is_err());
+        assert!(root.path().join("evil").exists().not());
+    }
+}
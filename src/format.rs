@@ -0,0 +1,361 @@
+//! A tiny template language for custom output, e.g. `{package}\t{version}\t{active}`.
+
+use std::fmt::Write;
+use std::fs;
+use std::ops::Not;
+use std::str::FromStr;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::listing::VersionListing;
+use crate::metadata::VersionMetadata;
+
+/// Format a size in bytes for humans, e.g. `4.2 MiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit + 1 < UNITS.len() {
+        size /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+/// Parse a size for humans, e.g. `2 GiB`, `500MB` or `1048576`. Units are powers of 1024, whether they say so
+/// with an `i` or not.
+pub fn parse_size(size: &str) -> Result<u64> {
+    const UNITS: &[(&str, u32)] = &[
+        ("b", 0),
+        ("k", 1),
+        ("kb", 1),
+        ("kib", 1),
+        ("m", 2),
+        ("mb", 2),
+        ("mib", 2),
+        ("g", 3),
+        ("gb", 3),
+        ("gib", 3),
+        ("t", 4),
+        ("tb", 4),
+        ("tib", 4),
+    ];
+
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|ch: char| ch.is_ascii_digit().not() && ch != '.')
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+    let unit = unit.trim().to_ascii_lowercase();
+
+    let Ok(number) = number.parse::<f64>() else {
+        bail!("invalid size `{size}`, expected something like `2 GiB`");
+    };
+    let exponent = match UNITS.iter().find(|(name, _)| *name == unit) {
+        Some((_, exponent)) => *exponent,
+        None if unit.is_empty() => 0,
+        None => bail!("invalid size `{size}`: unknown unit `{unit}`"),
+    };
+
+    Ok((number * 1024f64.powi(exponent as i32)) as u64)
+}
+
+/// Format a count for humans, with thousands separated, e.g. `1,234,567`
+pub fn human_count(count: u64) -> String {
+    let digits = count.to_string();
+    let mut formatted = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+
+    formatted
+}
+
+/// Format a duration for humans, rounded to the second, e.g. `1m 23s`
+pub fn human_duration(duration: Duration) -> String {
+    humantime::format_duration(Duration::from_secs(duration.as_secs())).to_string()
+}
+
+/// Every field that can be used in a template, along with its placeholder
+const FIELDS: &[(&str, Field)] = &[
+    ("package", Field::Package),
+    ("version", Field::Version),
+    ("active", Field::Active),
+    ("binaries", Field::Binaries),
+    ("size", Field::Size),
+    ("installed", Field::Installed),
+    ("source", Field::Source),
+    ("path", Field::Path),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Package,
+    Version,
+    Active,
+    Binaries,
+    Size,
+    Installed,
+    Source,
+    Path,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Field(Field),
+}
+
+/// A parsed `--format` template
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+/// The values a template can be rendered with
+#[derive(Debug, Clone)]
+pub struct Record {
+    pub package: String,
+    pub version: String,
+    pub active: bool,
+    pub binaries: Vec<String>,
+    /// Combined size of the binaries, in bytes
+    pub size: u64,
+    pub installed: Option<SystemTime>,
+    pub source: String,
+    pub path: String,
+}
+
+impl Record {
+    pub fn new(package: &str, version: &VersionListing) -> Self {
+        let metadata = VersionMetadata::load(&version.path).ok().flatten();
+
+        let size = version
+            .binaries
+            .iter()
+            .filter_map(|binary| fs::metadata(&binary.path).ok())
+            .map(|metadata| metadata.len())
+            .sum();
+
+        Self {
+            package: package.to_owned(),
+            version: version.version.clone(),
+            active: version.active,
+            binaries: version
+                .binaries
+                .iter()
+                .map(|binary| binary.name.clone())
+                .collect(),
+            size,
+            installed: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.installed_at())
+                .or_else(|| fs::metadata(&version.path).ok()?.modified().ok()),
+            source: version
+                .source
+                .as_ref()
+                .map_or_else(|| "unknown".to_owned(), ToString::to_string),
+            path: version.path.display().to_string(),
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(template: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let Some(end) = rest.find('}') else {
+                        bail!("Unclosed `{{` in format string `{template}`");
+                    };
+                    let name = &rest[..end];
+
+                    let Some(&(_, field)) =
+                        FIELDS.iter().find(|(placeholder, _)| *placeholder == name)
+                    else {
+                        let valid: Vec<_> = FIELDS
+                            .iter()
+                            .map(|(placeholder, _)| format!("{{{placeholder}}}"))
+                            .collect();
+                        bail!(
+                            "Unknown placeholder `{{{name}}}`, valid ones are: {}",
+                            valid.join(", ")
+                        );
+                    };
+
+                    if literal.is_empty().not() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                '}' => bail!(
+                    "Unmatched `}}` in format string `{template}`, use `}}}}` for a literal one"
+                ),
+                ch => literal.push(ch),
+            }
+        }
+
+        if literal.is_empty().not() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+}
+
+impl Template {
+    pub fn render(&self, record: &Record) -> String {
+        let mut output = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Field(field) => {
+                    // Writing to a String can't fail
+                    let _ = match field {
+                        Field::Package => write!(output, "{}", record.package),
+                        Field::Version => write!(output, "{}", record.version),
+                        Field::Active => write!(output, "{}", record.active),
+                        Field::Binaries => write!(output, "{}", record.binaries.join(",")),
+                        Field::Size => write!(output, "{}", record.size),
+                        Field::Installed => match record.installed {
+                            Some(installed) => {
+                                write!(output, "{}", humantime::format_rfc3339_seconds(installed))
+                            }
+                            None => write!(output, "unknown"),
+                        },
+                        Field::Source => write!(output, "{}", record.source),
+                        Field::Path => write!(output, "{}", record.path),
+                    };
+                }
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::time::UNIX_EPOCH;
+
+    use super::human_count;
+    use super::human_size;
+    use super::parse_size;
+    use super::Record;
+    use super::Template;
+
+    fn record() -> Record {
+        Record {
+            package: "cargo-edit".to_owned(),
+            version: "0.12.2".to_owned(),
+            active: true,
+            binaries: vec!["cargo-add".to_owned(), "cargo-rm".to_owned()],
+            size: 1024,
+            installed: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            source: "crates.io".to_owned(),
+            path: "/registry/cargo-edit/0.12.2".to_owned(),
+        }
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1023), "1023 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(4_404_019), "4.2 MiB");
+    }
+
+    #[test]
+    fn formats_counts() {
+        assert_eq!(human_count(0), "0");
+        assert_eq!(human_count(999), "999");
+        assert_eq!(human_count(1000), "1,000");
+        assert_eq!(human_count(1_234_567), "1,234,567");
+    }
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("1048576").unwrap(), 1_048_576);
+        assert_eq!(parse_size("2 GiB").unwrap(), 2 * 1024 * 1024 * 1024);
+        assert_eq!(parse_size("500MB").unwrap(), 500 * 1024 * 1024);
+        assert_eq!(parse_size("1.5k").unwrap(), 1536);
+        assert!(parse_size("lots").is_err());
+        assert!(parse_size("2 parsecs").is_err());
+    }
+
+    fn render(template: &str) -> String {
+        template.parse::<Template>().unwrap().render(&record())
+    }
+
+    #[test]
+    fn renders_placeholders() {
+        assert_eq!(
+            render(r"{package}\t{version}\t{active}\t{path}"),
+            "cargo-edit\t0.12.2\ttrue\t/registry/cargo-edit/0.12.2"
+        );
+        assert_eq!(
+            render("{binaries} ({size} bytes) from {source}"),
+            "cargo-add,cargo-rm (1024 bytes) from crates.io"
+        );
+        assert_eq!(render("{installed}"), "2023-11-14T22:13:20Z");
+        assert_eq!(render("no placeholders"), "no placeholders");
+        assert_eq!(render(""), "");
+    }
+
+    #[test]
+    fn handles_escapes() {
+        assert_eq!(render("{{{package}}}"), "{cargo-edit}");
+        assert_eq!(render(r"a\nb\\c\q"), "a\nb\\c\\q");
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let err = "{package} {nope}".parse::<Template>().unwrap_err();
+        assert!(err.to_string().contains("Unknown placeholder `{nope}`"));
+        assert!(err.to_string().contains("{package}, {version}"));
+
+        assert!("{package".parse::<Template>().is_err());
+        assert!("package}".parse::<Template>().is_err());
+        assert!("{}".parse::<Template>().is_err());
+    }
+}
@@ -0,0 +1,181 @@
+//! Installs from git repositories, as with `install tool --git URL --branch main`.
+//!
+//! Branches move, so every install is pinned to the commit the branch pointed to at the time, which is what cargo is
+//! asked to build. Each snapshot gets a directory of its own, named after the branch and the commit, as in
+//! `main.1a2b3c4`, so that snapshots of the same branch live side by side and can be switched back to.
+
+use std::ops::Not;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::crates_json::CratesJson;
+
+/// How many characters of a commit hash go into directory names
+pub const SHORT_REV_LENGTH: usize = 7;
+
+/// Whether `rev` looks like a commit hash, or the start of one
+pub fn is_commit_hash(rev: &str) -> bool {
+    rev.len() >= SHORT_REV_LENGTH && rev.chars().all(|ch| ch.is_ascii_hexdigit())
+}
+
+/// The directory a snapshot of `reference`, a branch name or else `rev` or `head`, at commit `rev` is kept in
+pub fn snapshot_directory(reference: &str, rev: &str) -> String {
+    // Branches like `feature/thing` must not end up as nested directories
+    let reference: String = reference
+        .chars()
+        .map(|ch| match ch {
+            ch if ch.is_ascii_alphanumeric() || ch == '-' || ch == '_' => ch,
+            _ => '-',
+        })
+        .collect();
+    let rev = rev.get(..SHORT_REV_LENGTH).unwrap_or(rev);
+
+    format!("{reference}.{}", rev.to_ascii_lowercase())
+}
+
+/// Whether `version` names a snapshot directory, which doesn't have to look like a version at all
+pub fn is_snapshot_directory(version: &str) -> bool {
+    version.rsplit_once('.').is_some_and(|(reference, rev)| {
+        reference.is_empty().not()
+            && rev.len() == SHORT_REV_LENGTH
+            && rev.chars().all(|ch| matches!(ch, '0'..='9' | 'a'..='f'))
+    })
+}
+
+/// Where a branch of a repository is at
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BranchHead {
+    /// The branch's name, which for the default branch is whatever the repository calls it, as in `main`, if it
+    /// says
+    pub branch: Option<String>,
+    pub rev: String,
+}
+
+/// Where `branch` of the repository at `url` is at, or its default branch if `branch` isn't given
+pub fn resolve_branch(url: &str, branch: Option<&str>) -> Result<BranchHead> {
+    let reference = match branch {
+        Some(branch) => format!("refs/heads/{branch}"),
+        None => "HEAD".to_owned(),
+    };
+
+    // `--symref` tells which branch HEAD is, as in `ref: refs/heads/main\tHEAD`
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--symref")
+        .arg("--")
+        .arg(url)
+        .arg(&reference)
+        .output()
+        .context("Failed to run git, which is needed to install from git repositories")?;
+    if output.status.success().not() {
+        bail!(
+            "Failed to reach the git repository at {url}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let mut name = branch.map(str::to_owned);
+    let mut rev = None;
+    for (value, line_reference) in stdout.lines().filter_map(|line| line.split_once('\t')) {
+        if line_reference != reference {
+            continue;
+        }
+        match value.strip_prefix("ref: refs/heads/") {
+            Some(symref) => name = name.or(Some(symref.to_owned())),
+            None => rev = Some(value.to_owned()),
+        }
+    }
+
+    let Some(rev) = rev else {
+        match branch {
+            Some(branch) => bail!("The git repository at {url} has no branch named `{branch}`"),
+            None => bail!("The git repository at {url} has no default branch"),
+        }
+    };
+    ensure!(
+        is_commit_hash(&rev),
+        "git reported a bogus commit for {reference} of {url}: {rev}"
+    );
+
+    Ok(BranchHead { branch: name, rev })
+}
+
+/// The commit the checkout at `repository` is at, if git can tell
+pub fn head_rev(repository: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repository)
+        .arg("rev-parse")
+        .arg("HEAD")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    let rev = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+
+    (output.status.success() && is_commit_hash(&rev)).then_some(rev)
+}
+
+/// The commit cargo says it built `package` from, according to the records it left in the install root `root`, as
+/// in `tool 0.1.0 (git+https://github.com/owner/tool?branch=main#1a2b3c4d…)`
+pub fn recorded_rev(root: &Path, package: &str) -> Option<String> {
+    let records = CratesJson::load(root)?;
+
+    records.installs.keys().find_map(|id| {
+        let (name, source) = id.split_once(' ')?;
+        let (_, source) = source.split_once("(git+")?;
+        let (_, rev) = source.strip_suffix(')')?.rsplit_once('#')?;
+
+        (name == package && is_commit_hash(rev)).then(|| rev.to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::ops::Not;
+
+    use super::is_snapshot_directory;
+    use super::recorded_rev;
+    use super::snapshot_directory;
+
+    #[test]
+    fn names_snapshot_directories() {
+        let rev = "1A2B3C4D5E6F7A8B9C0D1E2F3A4B5C6D7E8F9A0B";
+        assert_eq!(snapshot_directory("main", rev), "main.1a2b3c4");
+        assert_eq!(
+            snapshot_directory("feature/thing", rev),
+            "feature-thing.1a2b3c4"
+        );
+
+        assert!(is_snapshot_directory("main.1a2b3c4"));
+        assert!(is_snapshot_directory("head.abcdefa"));
+        assert!(is_snapshot_directory("main").not());
+        assert!(is_snapshot_directory(".abcdefa").not());
+        assert!(is_snapshot_directory("main.abcdefg").not());
+    }
+
+    #[test]
+    fn reads_recorded_revs() {
+        let root = tempfile::tempdir().unwrap();
+        let rev = "0123456789abcdef0123456789abcdef01234567";
+        fs::write(
+            root.path().join(".crates2.json"),
+            format!(
+                r#"{{"installs":{{"tool 0.1.0 (git+https://github.com/owner/tool?branch=main#{rev})":{{"bins":["tool"]}}}}}}"#
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(recorded_rev(root.path(), "tool").as_deref(), Some(rev));
+        assert_eq!(recorded_rev(root.path(), "other"), None);
+        assert_eq!(recorded_rev(&root.path().join("missing"), "tool"), None);
+    }
+}
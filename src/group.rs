@@ -0,0 +1,198 @@
+//! Groups of packages, defined in the config file to be installed or switched to all at once, as in
+//!
+//! ```toml
+//! [groups]
+//! web = ["sqlx-cli@0.7.2", "trunk@0.18.0", "wasm-bindgen-cli@0.2.89"]
+//! embedded = ["probe-rs-tools@0.24.0", "flip-link@0.1.9"]
+//! ```
+//!
+//! A package may belong to several groups, each with a version of its own. Whichever group was switched to last
+//! wins, and switching says which group's version it replaced.
+
+use std::collections::BTreeSet;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::install::InstallOptions;
+use crate::spec::normalize_name;
+use crate::spec::parse_spec;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::table::print_table;
+use crate::Switcher;
+
+impl Switcher {
+    /// The packages of the group `name` and the versions it wants, under the names they're installed under
+    fn group(&self, name: &str) -> Result<Vec<(String, String)>> {
+        let Some(specs) = self.config.groups.get(name) else {
+            let known: Vec<_> = self.config.groups.keys().map(String::as_str).collect();
+            if known.is_empty() {
+                bail!("No group is named {name}, as none is defined under [groups] in the config file");
+            }
+            bail!(
+                "No group is named {name}. Known groups: {}",
+                known.join(", ")
+            );
+        };
+
+        let mut seen = BTreeSet::new();
+        let mut packages = Vec::new();
+        for spec in specs {
+            let spec = self.canonical_spec(spec)?;
+            let (package, version) =
+                parse_spec(&spec).with_context(|| format!("Invalid spec in group {name}"))?;
+            ensure!(
+                seen.insert(package.to_owned()),
+                "Group {name} lists {package} more than once"
+            );
+            packages.push((package.to_owned(), version.to_owned()));
+        }
+
+        Ok(packages)
+    }
+
+    /// The groups other than `group` that list `package` at `version`
+    fn groups_wanting(&self, group: &str, package: &str, version: &str) -> Vec<String> {
+        self.config
+            .groups
+            .iter()
+            .filter(|(other, _)| *other != group)
+            .filter(|(_, specs)| {
+                specs.iter().any(|spec| {
+                    parse_spec(spec).is_ok_and(|(name, wanted)| {
+                        normalize_name(name) == normalize_name(package) && wanted == version
+                    })
+                })
+            })
+            .map(|(other, _)| other.clone())
+            .collect()
+    }
+
+    /// Install every package of the group `name` that isn't installed yet with `options`, without switching to any
+    /// of them
+    pub fn group_install(&self, name: &str, options: &InstallOptions) -> Result<()> {
+        let options = InstallOptions {
+            // That's up to `group switch`
+            no_switch: true,
+            ..options.clone()
+        };
+
+        let mut summary = Summary::new(self.failure_mode);
+        for (package, version) in self.group(name)? {
+            let spec = format!("{package}@{version}");
+            if summary.skip_if_stopped(&spec) {
+                continue;
+            }
+            if self.pick_variant(&package, &version).is_ok() {
+                summary.record(spec, Outcome::Skipped, Some("installed already".to_owned()));
+                continue;
+            }
+
+            self.install_into(&mut summary, &spec, &options);
+        }
+
+        self.finish_summary(summary, "packages failed")
+    }
+
+    /// Switch to every package of the group `name`, installing the missing ones first with `options` if `install` is
+    /// set or the user agrees to it when asked
+    pub fn group_switch(&self, name: &str, install: bool, options: &InstallOptions) -> Result<()> {
+        let mut summary = Summary::new(self.failure_mode);
+        for (package, version) in self.group(name)? {
+            let spec = format!("{package}@{version}");
+            if summary.skip_if_stopped(&spec) {
+                continue;
+            }
+
+            if self.pick_variant(&package, &version).is_err() {
+                if install
+                    || self.confirm(&format!(
+                        "{spec}, of group {name}, is not installed. Install it now?"
+                    ))?
+                {
+                    self.install_into(&mut summary, &spec, options);
+                } else {
+                    let err = self.not_installed(&package, Some(&version));
+                    summary.record_failure(spec, &err);
+                }
+                continue;
+            }
+
+            let previous = self.linked_version(&package).ok().flatten();
+            if previous.as_ref() == Some(&version) {
+                summary.record(spec, Outcome::Skipped, Some("active already".to_owned()));
+                continue;
+            }
+            if let Err(err) = self.switch_package(&spec) {
+                eprintln!("Failed to switch to {spec}: {err:#}");
+                summary.record_failure(spec, &err);
+                continue;
+            }
+
+            // Whatever another group switched to is replaced, which is worth knowing about
+            let detail =
+                previous.map(
+                    |previous| match &self.groups_wanting(name, &package, &previous)[..] {
+                        [] => format!("replaced {previous}"),
+                        [group] => format!("replaced {previous}, from group {group}"),
+                        groups => format!("replaced {previous}, from groups {}", groups.join(", ")),
+                    },
+                );
+            summary.record(spec, Outcome::Switched, detail);
+        }
+
+        self.finish_summary(summary, "packages failed")
+    }
+
+    /// Show every group, with how each of its packages is doing and the other groups they belong to
+    pub fn print_groups(&self) -> Result<()> {
+        if self.config.groups.is_empty() {
+            println!("No group is defined. Define them under [groups] in the config file");
+            return Ok(());
+        }
+
+        let mut rows = Vec::new();
+        for group in self.config.groups.keys() {
+            for (package, version) in self.group(group)? {
+                let status = if self.pick_variant(&package, &version).is_err() {
+                    "not installed"
+                } else if self.linked_version(&package)?.as_deref() == Some(version.as_str()) {
+                    "active"
+                } else {
+                    "installed"
+                };
+                let others: Vec<_> = self
+                    .config
+                    .groups
+                    .iter()
+                    .filter(|(other, specs)| {
+                        *other != group
+                            && specs.iter().any(|spec| {
+                                parse_spec(spec).is_ok_and(|(name, _)| {
+                                    normalize_name(name) == normalize_name(&package)
+                                })
+                            })
+                    })
+                    .map(|(other, _)| other.as_str())
+                    .collect();
+                rows.push([
+                    group.clone(),
+                    package,
+                    version,
+                    status.to_owned(),
+                    if others.is_empty() {
+                        "-".to_owned()
+                    } else {
+                        others.join(", ")
+                    },
+                ]);
+            }
+        }
+        print_table(["GROUP", "PACKAGE", "VERSION", "STATUS", "ALSO IN"], &rows);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+use std::fs;
+use std::ops::Not;
+
+use anyhow::Result;
+
+use crate::format::human_count;
+use crate::format::human_duration;
+use crate::format::human_size;
+use crate::metadata::LockfileStatus;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::retry;
+use crate::retry::RetryPolicy;
+use crate::spec::split_label;
+use crate::variant::split_variant;
+use crate::variant::variant_profile;
+use crate::Switcher;
+
+impl Switcher {
+    /// Print what we know about every installed version of `package`, or only about `version` if given, followed by
+    /// what crates.io says about it if `remote` is set or crates.io was asked recently enough for it to be cached
+    pub fn print_info(&self, package: &str, version: Option<&str>, remote: bool) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        let listing = self.package_listing(package)?;
+        let channels = self.channels(package)?;
+        // `PACKAGE@CHANNEL` shows the version the channel points to
+        let channel_version = match version {
+            Some(version) => self.channel_version(package, version)?,
+            None => None,
+        };
+        let version = channel_version.as_deref().or(version);
+        if let Some(version) = version {
+            if listing
+                .versions
+                .iter()
+                .any(|listing| listing.version == version)
+                .not()
+            {
+                return Err(self.not_installed(package, Some(version)));
+            }
+        }
+
+        let versions = listing
+            .versions
+            .iter()
+            .filter(|listing| version.is_none_or(|version| listing.version == version));
+
+        for (index, listing) in versions.enumerate() {
+            if index > 0 {
+                println!();
+            }
+
+            let marker = if listing.active { " (active)" } else { "" };
+            println!("{package}@{}{marker}", listing.version);
+            println!("  Location:   {}", listing.path.display());
+
+            let metadata = VersionMetadata::load(&listing.path)?;
+            match metadata.as_ref().and_then(VersionMetadata::installed_at) {
+                Some(installed_at) => println!(
+                    "  Installed:  {}",
+                    humantime::format_rfc3339_seconds(installed_at)
+                ),
+                None => println!("  Installed:  unknown"),
+            }
+            // Builds from before profiles were recorded are told apart by their variant label alone, while binaries
+            // that weren't built by us have no profile at all
+            let profile = match &metadata {
+                Some(metadata) if metadata.profile.is_some() => metadata.profile.as_deref(),
+                Some(VersionMetadata {
+                    source: Some(Source::External | Source::Url { .. }),
+                    ..
+                }) => None,
+                _ => Some(variant_profile(split_variant(&listing.version).1)),
+            };
+            if let Some(profile) = profile {
+                println!("  Profile:    {profile}");
+            }
+            if let Some(metadata) = metadata.as_ref().filter(|m| m.features.is_empty().not()) {
+                println!("  Features:   {}", metadata.features.join(", "));
+            }
+            if let Some(flags) = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.flags.as_ref())
+                .filter(|flags| flags.is_empty().not())
+            {
+                println!("  Flags:      {flags}");
+            }
+            let version_channels: Vec<_> = channels
+                .iter()
+                .filter(|(_, version)| **version == listing.version)
+                .map(|(channel, _)| channel.as_str())
+                .collect();
+            if version_channels.is_empty().not() {
+                println!("  Channels:   {}", version_channels.join(", "));
+            }
+            match metadata
+                .as_ref()
+                .and_then(|metadata| metadata.source.as_ref())
+            {
+                Some(Source::CratesIo) => println!("  Source:     crates.io"),
+                Some(Source::Git { url, rev, branch }) => {
+                    println!("  Source:     git");
+                    println!("  Repository: {url}");
+                    if let Some(branch) = branch {
+                        println!("  Branch:     {branch}");
+                    }
+                    println!("  Revision:   {}", rev.as_deref().unwrap_or("unknown"));
+                }
+                Some(Source::Path { path }) => {
+                    println!("  Source:     path");
+                    println!("  Built from: {}", path.display());
+                }
+                Some(Source::Url { url, sha256 }) => {
+                    println!("  Source:     url");
+                    println!("  Downloaded: {url}");
+                    println!("  SHA-256:    {sha256}");
+                }
+                Some(Source::External) => println!("  Source:     binary, added with add-binary"),
+                None => println!("  Source:     unknown"),
+            }
+            if let Some(provenance) = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.binary_cache.as_ref())
+            {
+                let copied_in = provenance
+                    .copy_duration()
+                    .map(|duration| format!(", in {}", human_duration(duration)))
+                    .unwrap_or_default();
+                println!(
+                    "  Cached:     copied from the binary cache{copied_in}, pushed by {} on {}",
+                    provenance.pushed_by,
+                    humantime::format_rfc3339_seconds(provenance.pushed_at())
+                );
+            }
+            if let Some(build_duration) =
+                metadata.as_ref().and_then(VersionMetadata::build_duration)
+            {
+                println!("  Build time: {}", human_duration(build_duration));
+            }
+            if let Some(rustc) = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.rustc.as_ref())
+            {
+                println!("  Compiler:   {rustc}");
+            }
+            if let Some(metadata) = metadata.as_ref() {
+                match (&metadata.checksum, metadata.checksum_unverified) {
+                    (Some(checksum), false) => println!("  Checksum:   {checksum}"),
+                    (Some(checksum), true) => println!(
+                        "  Checksum:   {checksum} (unverified, the .crate it was built from was gone)"
+                    ),
+                    (None, _) => {}
+                }
+            }
+            if let Some(reason) = metadata
+                .as_ref()
+                .and_then(|metadata| metadata.quarantined.as_ref())
+            {
+                let reason = reason.lines().next().unwrap_or_default();
+                println!("  Quarantine: failed its smoke test, {reason}");
+            }
+            match metadata
+                .as_ref()
+                .and_then(|metadata| metadata.lockfile.as_ref())
+            {
+                Some(LockfileStatus::Kept) => {
+                    println!("  Lockfile:   kept, see `cargo switch deps`")
+                }
+                Some(LockfileStatus::Missing { reason }) => {
+                    println!("  Lockfile:   not kept ({reason})")
+                }
+                None => {}
+            }
+
+            println!("  Binaries:");
+            for binary in &listing.binaries {
+                let size = fs::metadata(&binary.path).map_or(0, |metadata| metadata.len());
+                println!("    - {} ({})", binary.name, human_size(size));
+            }
+        }
+
+        self.print_crate_details(package, remote);
+
+        Ok(())
+    }
+
+    /// Print what crates.io says about `package`, asking it if `remote` is set, or else only if the answer is cached.
+    /// Not getting an answer only warrants a warning, as what's installed was shown already.
+    fn print_crate_details(&self, package: &str, remote: bool) {
+        let (crate_name, _) = split_label(package);
+        let pre = self.allows_prereleases(package);
+        let details = if remote {
+            let retries = self.config.retries.unwrap_or(retry::DEFAULT_RETRIES);
+            let details = self.crates_io().and_then(|crates_io| {
+                RetryPolicy::new(retries).run(&format!("look up {crate_name} on crates.io"), || {
+                    crates_io.crate_details(crate_name, pre)
+                })
+            });
+            match details {
+                Ok(details) => details,
+                Err(err) => {
+                    eprintln!(
+                        "Warning: couldn't get what crates.io says about {crate_name}: {err:#}"
+                    );
+                    return;
+                }
+            }
+        } else {
+            let cached = self
+                .crates_io()
+                .ok()
+                .and_then(|crates_io| crates_io.cached_crate_details(crate_name, pre));
+            match cached {
+                Some(details) => details,
+                None => return,
+            }
+        };
+
+        println!();
+        println!("{crate_name} on crates.io");
+        let unknown = || "unknown".to_owned();
+        if let Some(description) = &details.description {
+            // Descriptions may span several lines, which would break the layout
+            let description: Vec<_> = description.split_whitespace().collect();
+            println!("  Summary:    {}", description.join(" "));
+        }
+        if let Some(repository) = &details.repository {
+            println!("  Repository: {repository}");
+        }
+        if let Some(documentation) = &details.documentation {
+            println!("  Docs:       {documentation}");
+        }
+        println!(
+            "  License:    {}",
+            details.license.clone().unwrap_or_else(unknown)
+        );
+        match (&details.latest_version, &details.published_at) {
+            (Some(version), Some(published_at)) => {
+                // As in `2024-01-31T12:34:56.789012+00:00`, of which the date is plenty
+                let date = published_at.get(..10).unwrap_or(published_at);
+                println!("  Latest:     {version}, published {date}");
+            }
+            (Some(version), None) => println!("  Latest:     {version}"),
+            (None, _) => println!("  Latest:     {}", unknown()),
+        }
+        println!("  Downloads:  {}", human_count(details.downloads));
+    }
+}
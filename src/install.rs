@@ -0,0 +1,951 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::binary_cache::CacheProvenance;
+use crate::channel::is_channel_name;
+use crate::download;
+use crate::events;
+use crate::events::ErrorKind;
+use crate::events::Event;
+use crate::format::human_duration;
+use crate::format::human_size;
+use crate::git;
+use crate::installer::build_env;
+use crate::installer::describe_env;
+use crate::installer::BuildFlags;
+use crate::installer::BuildOptions;
+use crate::interrupt;
+use crate::metadata;
+use crate::metadata::BinaryMetadata;
+use crate::metadata::LockfileStatus;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::operation_log::Operation;
+use crate::retry;
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::spec::parse_spec;
+use crate::spec::split_label;
+use crate::spec::validate_label;
+use crate::spec::validate_name;
+use crate::spec::LABEL_SEPARATOR;
+use crate::spec_file::read_specs;
+use crate::spec_file::SpecList;
+use crate::strip;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::variant::normalize_features;
+use crate::variant::profile_variant;
+use crate::variant::split_variant;
+use crate::variant::variant_directory;
+use crate::variant::variant_profile;
+use crate::Switcher;
+
+/// How packages get installed
+#[derive(Debug, Default, Clone)]
+pub struct InstallOptions {
+    /// How many times to retry installs that failed because of the network
+    pub retries: Option<u32>,
+    /// The cargo profile to build with, `release` if not given
+    pub profile: Option<String>,
+    /// Build the crate found in this directory rather than fetching it from crates.io
+    pub path: Option<PathBuf>,
+    /// Cross-compile for this target triple. Such builds can't be switched to, only copied out of the registry.
+    pub target: Option<String>,
+    /// Download a prebuilt artifact from this URL rather than building anything
+    pub from_url: Option<String>,
+    /// The SHA-256 checksum the artifact downloaded from `from_url` must have
+    pub sha256: Option<String>,
+    /// Build from this git repository rather than crates.io, at the head of `branch` or else at `rev`. The version
+    /// is named after the commit, see [`git::snapshot_directory`].
+    pub git: Option<String>,
+    /// The branch of `git` to build, its default branch if neither this nor `rev` is given
+    pub branch: Option<String>,
+    /// The commit of `git` to build
+    pub rev: Option<String>,
+    /// Keep the build apart from other builds of the same crate, e.g. from forks of it, as `PACKAGE#LABEL`
+    pub label: Option<String>,
+    /// The cargo features to build with. Builds with different features are kept apart, see [`variant_directory`].
+    pub features: Vec<String>,
+    /// Whether to strip the installed binaries, following the `strip` config key if not given
+    pub strip: Option<bool>,
+    /// Leave `.cargo/bin` alone rather than switching to what was installed
+    pub no_switch: bool,
+    /// Keep every old version around, even if `keep-versions` says otherwise
+    pub no_auto_prune: bool,
+    /// Build from what `vendor` downloaded beforehand, without the network. See [`vendor`](crate::vendor).
+    pub offline: bool,
+    /// Build with this rustup toolchain rather than the active one, whatever the package's `toolchain` config key
+    /// says
+    pub toolchain: Option<String>,
+    /// Whether to build without the crate's default features, following the package's `default-features` config key
+    /// if not given
+    pub no_default_features: Option<bool>,
+    /// Whether to build with the `Cargo.lock` the crate was published with, following the package's `locked` config
+    /// key if not given
+    pub locked: Option<bool>,
+    /// Build even if crates.io says the version needs a newer rustc, see [`msrv`](crate::msrv)
+    pub skip_msrv_check: bool,
+    /// Leave out the features and flags of the package's section of the config file
+    pub no_config_flags: bool,
+    /// How long builds may run before they're killed, following the `build-timeout` config key if not given
+    pub timeout: Option<Duration>,
+    /// Fail builds from crates.io unless the `.crate` file cargo built from is known to have the checksum the index
+    /// lists, see [`crate_checksum`](crate::crate_checksum)
+    pub require_checksum: bool,
+    /// Build even if the binary cache has the same build, and leave it alone. See [`binary_cache`].
+    ///
+    /// [`binary_cache`]: crate::binary_cache
+    pub no_binary_cache: bool,
+}
+
+/// Everything needed to build a version, once the install options were resolved
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct BuildPlan<'a> {
+    pub name: &'a str,
+    /// The version to build, without its variant label
+    pub version: &'a str,
+    pub profile: &'a str,
+    /// Already normalized
+    pub features: &'a [String],
+    pub path: Option<&'a Path>,
+    pub target: Option<&'a str>,
+    pub from_url: Option<&'a str>,
+    pub sha256: Option<&'a str>,
+    /// The git repository to build from, at `rev`
+    pub git: Option<&'a str>,
+    pub rev: Option<&'a str>,
+    /// Where what `vendor` downloaded is, for builds that go without the network
+    pub vendored: Option<&'a Path>,
+    /// Where sources holding the `Cargo.lock` to build with are, for builds reproducing an earlier one
+    pub locked: Option<&'a Path>,
+    /// The branch `rev` is the head of, which is only recorded
+    pub branch: Option<&'a str>,
+    pub flags: &'a BuildFlags,
+    pub timeout: Option<Duration>,
+    pub require_checksum: bool,
+    pub strip: bool,
+    pub retries: u32,
+}
+
+/// The commit a git install is pinned to
+#[derive(Debug)]
+struct GitSnapshot {
+    /// Where the install goes, see [`git::snapshot_directory`]
+    directory: String,
+    rev: String,
+    /// The branch `rev` is the head of, if any
+    branch: Option<String>,
+}
+
+impl GitSnapshot {
+    /// Pin an install of `url` as `options` say. A `rev` given along with a `branch` is taken to be the head of
+    /// that branch.
+    fn resolve(url: &str, options: &InstallOptions) -> Result<Self> {
+        if let Some(rev) = &options.rev {
+            ensure!(
+                git::is_commit_hash(rev),
+                "--rev expects a commit hash, at least {} characters long, found `{rev}`",
+                git::SHORT_REV_LENGTH
+            );
+            let reference = options.branch.as_deref().unwrap_or("rev");
+            return Ok(Self {
+                directory: git::snapshot_directory(reference, rev),
+                rev: rev.to_ascii_lowercase(),
+                branch: options.branch.clone(),
+            });
+        }
+
+        let head = git::resolve_branch(url, options.branch.as_deref())?;
+        Ok(Self {
+            directory: git::snapshot_directory(head.branch.as_deref().unwrap_or("head"), &head.rev),
+            rev: head.rev,
+            branch: head.branch,
+        })
+    }
+}
+
+/// What happened when installing a package
+#[derive(Debug)]
+pub struct InstallReport {
+    pub package: String,
+    pub version: String,
+    pub location: PathBuf,
+    /// How long the successful `cargo install` took
+    pub build_duration: Duration,
+    pub binaries: Vec<BinaryMetadata>,
+    /// The target triple the binaries were cross-compiled for, if they were
+    pub target: Option<String>,
+    /// Whether the freshly installed version was switched to
+    pub switched: bool,
+    /// Why switching to the freshly installed version failed, if it did
+    pub switch_error: Option<anyhow::Error>,
+    /// The old versions removed afterwards, following `keep-versions`
+    pub pruned: Vec<String>,
+}
+
+impl InstallReport {
+    fn binaries_summary(&self) -> String {
+        let binaries: Vec<_> = self
+            .binaries
+            .iter()
+            .map(|binary| format!("{} ({})", binary.name, human_size(binary.size)))
+            .collect();
+
+        binaries.join(", ")
+    }
+
+    pub fn print(&self) {
+        println!(
+            "Installed {}@{} in {}",
+            self.package,
+            self.version,
+            human_duration(self.build_duration)
+        );
+        println!("  Location: {}", self.location.display());
+        println!("  Binaries: {}", self.binaries_summary());
+        match (&self.target, &self.switch_error) {
+            (Some(target), _) => println!("  Switched: no (built for {target})"),
+            (None, None) if self.switched => println!("  Switched: yes"),
+            (None, None) => println!("  Switched: no"),
+            (None, Some(err)) => println!("  Switched: no ({err:#})"),
+        }
+        if self.pruned.is_empty().not() {
+            println!("  Pruned:   {}", self.pruned.join(", "));
+        }
+    }
+}
+
+/// Ask the user a yes-or-no `question`, taking a no for an answer whenever nobody is around to answer
+fn confirm(question: &str) -> Result<bool> {
+    if io::stdin().is_terminal().not() || io::stderr().is_terminal().not() {
+        return Ok(false);
+    }
+
+    eprint!("{question} [y/N] ");
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Remove what a failed install left at `target_path`, along with its package's directory if nothing else is in there
+pub fn discard_install(target_path: &Path) {
+    let _ = fs::remove_dir_all(target_path);
+    if let Some(package_path) = target_path.parent() {
+        // Only succeeds if the directory is empty
+        let _ = fs::remove_dir(package_path);
+    }
+}
+
+impl Switcher {
+    /// Ask the user a yes-or-no `question`, taking a no for an answer if the switcher is non-interactive or nobody is
+    /// around to answer
+    pub(crate) fn confirm(&self, question: &str) -> Result<bool> {
+        if self.non_interactive {
+            return Ok(false);
+        }
+
+        confirm(question)
+    }
+
+    /// The features and flags to build `package` with: the ones in `options`, on top of the ones of its section of
+    /// the config file unless `no_config_flags` is set. Features add up, while a toolchain or a flag given in
+    /// `options` wins.
+    pub(crate) fn build_flags(
+        &self,
+        package: &str,
+        options: &InstallOptions,
+    ) -> (Vec<String>, BuildFlags) {
+        let mut features = options.features.clone();
+        let mut flags = BuildFlags {
+            toolchain: options.toolchain.clone(),
+            no_default_features: options.no_default_features.unwrap_or_default(),
+            locked: options.locked.unwrap_or_default(),
+            extra_args: Vec::new(),
+            env: None,
+        };
+        let presets = match options.no_config_flags {
+            true => None,
+            false => self.config.package(split_label(package).0),
+        };
+        if let Some(presets) = presets {
+            features.extend(presets.features.iter().cloned());
+            flags.toolchain = flags.toolchain.or(presets.toolchain.clone());
+            flags.no_default_features = options
+                .no_default_features
+                .or(presets.default_features.map(Not::not))
+                .unwrap_or_default();
+            flags.locked = options.locked.unwrap_or(presets.locked);
+            flags.extra_args = presets.extra_args.clone();
+        }
+        let configured = presets
+            .map(|presets| presets.build_env.clone())
+            .unwrap_or_default();
+        flags.env = Some(build_env(&configured, |variable| env::var(variable).ok()));
+
+        (normalize_features(&features), flags)
+    }
+
+    /// Switch to `package`, installing it first if it's missing and either `install` is set or the user agrees to it
+    /// when asked
+    pub fn switch_or_install(
+        &self,
+        package: &str,
+        install: bool,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        self.ensure_per_user(&format!("switch to {package}"))?;
+        let (name, version) = parse_spec(package)?;
+        // Channels can only point to what's installed already, so there's nothing to offer installing
+        if self.pick_variant(name, version).is_ok() || is_channel_name(version) {
+            return self.switch_package(package);
+        }
+
+        if install.not()
+            && self
+                .confirm(&format!("{package} is not installed. Install it now?"))?
+                .not()
+        {
+            return self.switch_package(package);
+        }
+
+        let report = self.install_package(package, options)?;
+        report.print();
+
+        match report.switch_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Install the packages given on the command line, where `-` stands for specs read from stdin, along with the
+    /// ones listed in `from_file`
+    pub fn install_from_args(
+        &self,
+        args: &[String],
+        from_file: Option<&Path>,
+        options: &InstallOptions,
+    ) -> Result<()> {
+        let mut list = SpecList::default();
+        let mut extend = |other: SpecList| {
+            list.specs.extend(other.specs);
+            list.errors.extend(other.errors);
+        };
+
+        for arg in args {
+            if arg == "-" {
+                extend(read_specs(io::stdin().lock(), "<stdin>")?);
+            } else {
+                extend(SpecList {
+                    specs: vec![arg.clone()],
+                    errors: Vec::new(),
+                });
+            }
+        }
+
+        if let Some(path) = from_file {
+            let file =
+                File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+            extend(read_specs(
+                io::BufReader::new(file),
+                &path.display().to_string(),
+            )?);
+        }
+
+        for error in &list.errors {
+            eprintln!("{error}");
+        }
+
+        if options.path.is_some() {
+            ensure!(
+                list.specs.len() == 1,
+                "--path builds a single crate, but {} packages were given",
+                list.specs.len()
+            );
+        }
+        if options.from_url.is_some() {
+            ensure!(
+                list.specs.len() == 1,
+                "--from-url installs a single package, but {} packages were given",
+                list.specs.len()
+            );
+        }
+        if options.git.is_some() {
+            ensure!(
+                list.specs.len() == 1,
+                "--git builds a single package, but {} packages were given",
+                list.specs.len()
+            );
+        }
+
+        if list.specs.is_empty().not() {
+            self.install_packages(&list.specs, options)?;
+        }
+
+        ensure!(
+            list.errors.is_empty(),
+            "Skipped {} malformed spec(s)",
+            list.errors.len()
+        );
+
+        Ok(())
+    }
+
+    /// Install every one of `packages`, carrying on past failures unless failing fast, and summarize how it went
+    pub fn install_packages(&self, packages: &[String], options: &InstallOptions) -> Result<()> {
+        let mut summary = Summary::new(self.failure_mode);
+        for package in packages {
+            if summary.skip_if_stopped(package) {
+                continue;
+            }
+
+            self.install_into(&mut summary, package, options);
+        }
+
+        self.finish_summary(summary, "packages failed")
+    }
+
+    /// Install `package` as one of many, recording how it went in `summary`
+    pub(crate) fn install_into(
+        &self,
+        summary: &mut Summary,
+        package: &str,
+        options: &InstallOptions,
+    ) {
+        match self.install_package(package, options) {
+            Ok(report) => {
+                report.print();
+                let spec = format!("{}@{}", report.package, report.version);
+                let detail = format!(
+                    "{}, {}",
+                    human_duration(report.build_duration),
+                    report.binaries_summary()
+                );
+                match &report.switch_error {
+                    None => summary.record(spec, Outcome::Installed, Some(detail)),
+                    Some(err) => {
+                        eprintln!("Failed to switch to {package}: {err:#}");
+                        let err = anyhow!("installed, but not switched to: {err:#}");
+                        summary.record_failure(spec, &err);
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to install {package}: {err:#}");
+                summary.record_failure(package, &err);
+            }
+        }
+    }
+
+    pub fn install_package(
+        &self,
+        package: &str,
+        options: &InstallOptions,
+    ) -> Result<InstallReport> {
+        // Git installs are named after the commit they're pinned to, which takes asking the repository
+        let snapshot = match &options.git {
+            Some(url) => {
+                ensure!(
+                    package.contains('@').not(),
+                    "Versions installed from git are named after the commit they're built from, so expected a \
+                     package name without a version, found `{package}`"
+                );
+                validate_name(package)?;
+                Some(GitSnapshot::resolve(url, options)?)
+            }
+            None => None,
+        };
+        let (name, version) = match &snapshot {
+            Some(snapshot) => (package, snapshot.directory.as_str()),
+            None => parse_spec(package)?,
+        };
+        let labeled;
+        let name = match &options.label {
+            Some(label) => {
+                ensure!(
+                    split_label(name).1.is_none(),
+                    "{name} is labeled already, so it can't be given --label as well"
+                );
+                validate_label(label)?;
+                labeled = format!("{name}{LABEL_SEPARATOR}{label}");
+                labeled.as_str()
+            }
+            None => name,
+        };
+        // Builds from crates.io go by the name crates.io has for them. Otherwise, installing `fd_find` next to
+        // `fd-find` adds a version to it, rather than a package of its own.
+        let from_index = options.path.is_none()
+            && options.git.is_none()
+            && options.from_url.is_none()
+            && options.offline.not()
+            && split_label(name).1.is_none()
+            && self.installer.fetches_from_crates_io();
+        let canonical = match from_index {
+            true => self.adopt_real_name(name)?,
+            false => self.canonical_name(name)?,
+        };
+        let name = canonical.as_str();
+        // Builds from crates.io can't be forks of anything
+        ensure!(
+            split_label(name).1.is_none() || options.git.is_some() || options.path.is_some(),
+            "{name} is labeled, but labels only tell apart forks installed with --git or --path"
+        );
+        ensure!(
+            is_channel_name(version).not(),
+            "{package} names a channel, which can't be installed. Install the version it should point to instead"
+        );
+
+        // Asking for a variant, as in `tool@1.0.0+debug`, is the same as passing its profile. Labels of builds with
+        // features can't be told apart from profiles, so those must be asked for through --features.
+        let (version, variant) = split_variant(version);
+        let (features, flags) = self.build_flags(name, options);
+        let variant = match variant {
+            Some(label) if features.is_empty().not() => {
+                let expected = variant_directory(
+                    version,
+                    options.profile.as_deref().unwrap_or("release"),
+                    &features,
+                );
+                ensure!(
+                    expected == format!("{version}+{label}"),
+                    "{package} asks for the `{label}` variant, but these options build {expected}"
+                );
+                None
+            }
+            variant => variant,
+        };
+        let profile = match (variant, options.profile.as_deref()) {
+            (None, profile) => profile.unwrap_or("release"),
+            (Some(variant), None) => variant_profile(Some(variant)),
+            (Some(variant), Some(profile)) => {
+                ensure!(
+                    profile_variant(profile) == Some(variant),
+                    "{package} asks for the `{variant}` variant, which wasn't built with the `{profile}` profile"
+                );
+                profile
+            }
+        };
+
+        let directory_name = variant_directory(version, profile, &features);
+        let target_path = match &options.target {
+            Some(target) => self.target_registry(target).join(name),
+            None => self.registry.join(name),
+        }
+        .join(&directory_name);
+        let retries = options
+            .retries
+            .or(self.config.retries)
+            .unwrap_or(retry::DEFAULT_RETRIES);
+        // Better to find out now than halfway through a build that reached for the network
+        let vendored = if options.offline {
+            ensure!(
+                options.path.is_none() && options.git.is_none() && options.from_url.is_none(),
+                "--offline only builds what `cargo switch vendor` downloaded from crates.io"
+            );
+            Some(self.vendored(split_label(name).0, version)?)
+        } else {
+            None
+        };
+        // Only crates.io tells which rustc a version needs
+        let from_crates_io = options.path.is_none()
+            && options.git.is_none()
+            && options.from_url.is_none()
+            && vendored.is_none();
+        // A typo is better found out before cargo spends a while updating its index
+        if from_crates_io && self.installer.fetches_from_crates_io() {
+            self.check_published(split_label(name).0, version)?;
+        } else if vendored.is_some() {
+            eprintln!(
+                "Not making sure {name}@{version} is on crates.io, since --offline builds what was vendored"
+            );
+        }
+        if from_crates_io && options.skip_msrv_check.not() {
+            self.check_rust_version(split_label(name).0, version, flags.toolchain.as_deref())?;
+        }
+        let plan = BuildPlan {
+            name,
+            version,
+            profile,
+            features: &features,
+            path: options.path.as_deref(),
+            target: options.target.as_deref(),
+            from_url: options.from_url.as_deref(),
+            sha256: options.sha256.as_deref(),
+            git: options.git.as_deref(),
+            rev: snapshot.as_ref().map(|snapshot| snapshot.rev.as_str()),
+            vendored: vendored.as_deref(),
+            locked: None,
+            branch: snapshot
+                .as_ref()
+                .and_then(|snapshot| snapshot.branch.as_deref()),
+            flags: &flags,
+            timeout: options.timeout.or(self.config.build_timeout),
+            require_checksum: options.require_checksum,
+            strip: options.strip.or(self.config.strip).unwrap_or(false),
+            retries,
+        };
+
+        // Held until the new version is switched to, so that nobody else touches the package in the meantime
+        let lock = self.lock_package(name)?;
+        let fresh_install = target_path.exists().not();
+        // Building the same version again with another environment is how a native build replaces a portable one,
+        // which is worth calling out in case it wasn't meant to
+        let recorded_env = match fresh_install {
+            true => None,
+            false => VersionMetadata::load(&target_path)
+                .ok()
+                .flatten()
+                .and_then(|metadata| metadata.flags?.env),
+        };
+        if let (Some(recorded), Some(env)) = (&recorded_env, &flags.env) {
+            if recorded != env {
+                let describe = |env: &BTreeMap<String, String>| match env.is_empty() {
+                    true => "no build environment".to_owned(),
+                    false => describe_env(env),
+                };
+                eprintln!(
+                    "Warning: {name}@{directory_name} was built with {}, but is being built again with {}",
+                    describe(recorded),
+                    describe(env)
+                );
+            }
+        }
+        // Until its metadata is saved, a fresh install is only a half-built directory
+        let remove_on_interrupt =
+            fresh_install.then(|| interrupt::remove_on_interrupt(&target_path));
+        // Whoever pushed the same build to the binary cache saves us from building it again
+        let cached = match fresh_install && options.no_binary_cache.not() {
+            true => self.pull_from_binary_cache(&plan, &target_path),
+            false => None,
+        };
+        let built = cached.is_none();
+        let metadata = match cached {
+            Some(metadata) => metadata,
+            None => self.build_version(&plan, &target_path, fresh_install)?,
+        };
+        // What installing took, which for copies from the binary cache is copying rather than building
+        let build_duration = metadata
+            .binary_cache
+            .as_ref()
+            .and_then(CacheProvenance::copy_duration)
+            .or_else(|| metadata.build_duration())
+            .unwrap_or_default();
+        drop(remove_on_interrupt);
+        self.emit_install_finished(name, &directory_name, &target_path, &metadata);
+
+        // Cross-compiled binaries most likely can't run here, and the shared registry is only installed into
+        let switch = options.target.is_none() && options.no_switch.not() && self.system.not();
+        let switch_error = switch
+            .then(|| {
+                self.smoke_test_fresh(name, &directory_name)?;
+                self.switch_package_unlocked(&format!("{name}@{directory_name}"))
+            })
+            .and_then(Result::err);
+        let switched = switch && switch_error.is_none();
+        // Only once the smoke test had its say, so that nobody else copies a build that fails it
+        if built && options.no_binary_cache.not() {
+            let smoke_tested = switch && self.ignore_smoke_test.not();
+            self.push_fresh_build(name, &target_path, &metadata, plan.target, smoke_tested);
+        }
+        // Switching takes care of versioned links, which the new version may deserve all the same
+        if switch.not() && options.target.is_none() {
+            if let Err(err) = self.sync_versioned_links(name) {
+                eprintln!("Warning: failed to update the versioned links of {name}: {err:#}");
+            }
+        }
+
+        // Only once the new version took over, so that what it replaced can't be the active one anymore
+        let mut pruned = Vec::new();
+        if let Some(keep) = self.config.keep_versions(name) {
+            if switched && options.no_auto_prune.not() {
+                pruned = self.prune_unlocked(name, keep).unwrap_or_else(|err| {
+                    eprintln!("Warning: failed to prune old versions of {name}: {err:#}");
+                    Vec::new()
+                });
+            }
+        }
+
+        // Every package may lose versions to eviction, and taking their locks while holding this one could deadlock
+        drop(lock);
+        if self.no_evict.not() {
+            if let Err(err) = self.enforce_max_size(name, &directory_name) {
+                eprintln!("Warning: failed to keep the registry under its max-size: {err:#}");
+            }
+        }
+
+        Ok(InstallReport {
+            package: name.to_owned(),
+            version: directory_name,
+            location: target_path,
+            build_duration,
+            binaries: metadata.binaries,
+            target: options.target.clone(),
+            switched,
+            switch_error,
+            pruned,
+        })
+    }
+
+    /// Build what `plan` describes into `target_path`, leaving its binaries in `target_path/bin` and its metadata
+    /// next to them. Whatever a failed build left behind is removed if `fresh_install` is set, and kept otherwise.
+    /// Either way, how it went goes to the [operation log](crate::operation_log).
+    pub(crate) fn build_version(
+        &self,
+        plan: &BuildPlan,
+        target_path: &Path,
+        fresh_install: bool,
+    ) -> Result<VersionMetadata> {
+        let built = self.build_version_unlogged(plan, target_path, fresh_install);
+
+        let version = target_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy();
+        let mut detail = match (&built, plan.from_url) {
+            (Err(err), _) => format!("failed: {err:#}"),
+            (Ok(metadata), Some(url)) => format!(
+                "downloaded from {url} in {}",
+                human_duration(metadata.build_duration().unwrap_or_default())
+            ),
+            (Ok(metadata), None) => format!(
+                "built in {}",
+                human_duration(metadata.build_duration().unwrap_or_default())
+            ),
+        };
+        if plan.from_url.is_none() && plan.flags.is_empty().not() {
+            detail.push_str(&format!(", with {}", plan.flags));
+        }
+        self.log(Operation::Install, Some(plan.name), Some(&version), detail);
+
+        built
+    }
+
+    fn build_version_unlogged(
+        &self,
+        plan: &BuildPlan,
+        target_path: &Path,
+        fresh_install: bool,
+    ) -> Result<VersionMetadata> {
+        let BuildPlan { name, version, .. } = *plan;
+        let package = format!("{name}@{version}");
+        // Cargo knows nothing about labels, nor about spellings crates.io doesn't have, which older versions may be
+        // installed under
+        let (crate_name, _) = split_label(name);
+        let from_crates_io = plan.path.is_none()
+            && plan.git.is_none()
+            && plan.from_url.is_none()
+            && plan.vendored.is_none();
+        let crate_name = match from_crates_io && self.installer.fetches_from_crates_io() {
+            true => self.real_crate_name(crate_name),
+            false => crate_name.to_owned(),
+        };
+        let spec = format!("{crate_name}@{version}");
+        self.emit(Event::InstallStarted {
+            package: name.to_owned(),
+            version: target_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        });
+        let (build_duration, lockfile, crate_checksum) = RetryPolicy::new(plan.retries)
+            .run(&format!("install {package}"), || match plan.from_url {
+                Some(url) => {
+                    let sha256 = plan
+                        .sha256
+                        .with_context(|| {
+                            "--from-url needs the artifact's checksum, given through --sha256"
+                        })
+                        .map_err(Failure::Permanent)?;
+                    download::install_artifact(name, url, sha256, target_path).map(|duration| {
+                        (
+                            duration,
+                            Err("it was downloaded prebuilt, with no Cargo.lock".to_owned()),
+                            None,
+                        )
+                    })
+                }
+                None => {
+                    let build = BuildOptions {
+                        profile: plan.profile,
+                        path: plan.path,
+                        target: plan.target,
+                        features: plan.features,
+                        git: plan.git,
+                        rev: plan.rev,
+                        vendored: plan.vendored,
+                        locked: plan.locked,
+                        flags: plan.flags,
+                        timeout: plan.timeout,
+                        events: self.events.as_deref(),
+                    };
+                    self.installer
+                        .install(&spec, target_path, &build)
+                        .map(|outcome| {
+                            (
+                                outcome.build_duration,
+                                outcome.lockfile,
+                                outcome.crate_checksum,
+                            )
+                        })
+                }
+            })
+            .map_err(|err| {
+                // Whatever a failed build left behind would look like an installed version
+                if fresh_install {
+                    discard_install(target_path);
+                }
+                // Timeouts are worth telling apart from other failures
+                match events::error_kind(&err) {
+                    ErrorKind::Other => events::classify(err, ErrorKind::BuildFailed),
+                    _ => err,
+                }
+            })?;
+
+        // Only crates.io lists checksums, and vendored sources were checked when they were downloaded
+        let from_crates_io = plan.from_url.is_none()
+            && plan.path.is_none()
+            && plan.git.is_none()
+            && plan.vendored.is_none();
+        let checksum = match from_crates_io {
+            true => self
+                .verify_crate_checksum(
+                    &crate_name,
+                    version,
+                    crate_checksum.as_deref(),
+                    plan.require_checksum,
+                )
+                .inspect_err(|_| {
+                    if fresh_install {
+                        discard_install(target_path);
+                    }
+                })?,
+            false => None,
+        };
+
+        let bin_path = target_path.join("bin");
+        let unstripped_sizes = if plan.strip {
+            strip::strip_installed(&bin_path, plan.target)?
+        } else {
+            BTreeMap::new()
+        };
+
+        // Don't trust whatever was recorded by a previous install of the same version
+        let binaries = Switcher::scan_binaries(&bin_path)?
+            .into_iter()
+            .map(|path| {
+                let name = path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                BinaryMetadata {
+                    size: fs::metadata(&path).map_or(0, |metadata| metadata.len()),
+                    unstripped_size: unstripped_sizes.get(&name).copied(),
+                    name,
+                }
+            })
+            .collect();
+
+        // A lockfile kept by a previous install of the same version would be taken for this one's
+        let lockfile_path = VersionMetadata::lockfile_path(target_path);
+        let _ = fs::remove_file(&lockfile_path);
+        let lockfile = lockfile.and_then(|contents| {
+            fs::create_dir_all(VersionMetadata::directory(target_path))
+                .and_then(|()| fs::write(&lockfile_path, contents))
+                .map_err(|err| format!("Failed to write {}: {err}", lockfile_path.display()))
+        });
+        let lockfile = match lockfile {
+            Ok(()) => LockfileStatus::Kept,
+            Err(reason) => {
+                if plan.from_url.is_none() {
+                    eprintln!("Warning: the Cargo.lock of {package} couldn't be kept: {reason}");
+                }
+                LockfileStatus::Missing { reason }
+            }
+        };
+
+        let metadata = VersionMetadata {
+            installed_at: Some(metadata::timestamp(SystemTime::now())),
+            build_duration_ms: Some(build_duration.as_millis() as u64),
+            // Downloaded artifacts were built by somebody else, with whatever profile they liked
+            profile: plan.from_url.is_none().then(|| plan.profile.to_owned()),
+            features: plan.features.to_vec(),
+            source: Some(match (plan.from_url, plan.path, plan.git) {
+                (Some(url), _, _) => Source::Url {
+                    url: url.to_owned(),
+                    sha256: plan.sha256.unwrap_or_default().to_ascii_lowercase(),
+                },
+                (None, Some(path), _) => Source::Path {
+                    path: fs::canonicalize(path).unwrap_or_else(|_| path.to_owned()),
+                },
+                // Cargo knows the whole hash even when given the start of one
+                (None, None, Some(url)) => Source::Git {
+                    url: url.to_owned(),
+                    rev: git::recorded_rev(target_path, &crate_name)
+                        .or(plan.rev.map(str::to_owned)),
+                    branch: plan.branch.map(str::to_owned),
+                },
+                (None, None, None) => Source::CratesIo,
+            }),
+            binaries,
+            lockfile: Some(lockfile),
+            flags: Some(plan.flags.clone()),
+            rustc: match plan.from_url {
+                Some(_) => None,
+                None => self
+                    .installer
+                    .rustc_version(plan.flags.toolchain.as_deref()),
+            },
+            checksum_unverified: checksum
+                .as_ref()
+                .is_some_and(|checksum| checksum.verified.not()),
+            checksum: checksum.map(|checksum| checksum.checksum),
+            // Smoke tested once it's in place
+            quarantined: None,
+            binary_cache: None,
+        };
+        metadata.save(target_path)?;
+
+        Ok(metadata)
+    }
+
+    /// Report that `package@version` was built into `location`
+    pub(crate) fn emit_install_finished(
+        &self,
+        package: &str,
+        version: &str,
+        location: &Path,
+        metadata: &VersionMetadata,
+    ) {
+        self.emit(Event::InstallFinished {
+            package: package.to_owned(),
+            version: version.to_owned(),
+            duration_ms: metadata.build_duration_ms.unwrap_or_default(),
+            location: location.to_owned(),
+            binaries: metadata
+                .binaries
+                .iter()
+                .map(|binary| location.join("bin").join(&binary.name))
+                .collect(),
+        });
+    }
+}
@@ -0,0 +1,401 @@
+//! Finding the directory cargo installs binaries into, which is where the links go.
+//!
+//! Cargo picks its install root from `$CARGO_INSTALL_ROOT`, then from `install.root` in its configuration files, and
+//! falls back to `$CARGO_HOME`. Its configuration files are `.cargo/config.toml` in the current directory and every
+//! one of its parents, closer ones taking precedence, followed by `$CARGO_HOME/config.toml`.
+//!
+//! Without an install root configured, a `.cargo/bin` in `$PATH` is used, as it's what the user actually runs
+//! binaries from, and `$CARGO_HOME/bin` only if there's none. `$PATH` may well have several of them, say a
+//! system-wide one and a per-user one, in which case the first that exists and can be written to wins.
+//!
+//! `--cargo-bin` and the `cargo-bin` config key take precedence over all of this. `--link-dir` and the `link-dir`
+//! config key go further, sending the links somewhere else entirely, as in `~/.local/bin` when `.cargo/bin` is
+//! read-only, while the registry stays next to the cargo bin directory if one is there already.
+
+use std::env;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::shadow::same_directory;
+
+/// What decided where the links go
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BinSource {
+    /// `--cargo-bin`
+    Flag,
+    /// The `cargo-bin` config key
+    ConfigKey,
+    /// `--link-dir`
+    LinkDirFlag,
+    /// The `link-dir` config key
+    LinkDirConfigKey,
+    /// `$CARGO_INSTALL_ROOT`
+    InstallRootVariable,
+    /// `install.root` in one of cargo's configuration files
+    Config(PathBuf),
+    /// The first `.cargo/bin` in `$PATH`
+    Path,
+    /// `$CARGO_HOME`, or `~/.cargo` if it isn't set
+    CargoHome,
+}
+
+impl fmt::Display for BinSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BinSource::Flag => write!(f, "--cargo-bin"),
+            BinSource::ConfigKey => write!(f, "the `cargo-bin` config key"),
+            BinSource::LinkDirFlag => write!(f, "--link-dir"),
+            BinSource::LinkDirConfigKey => write!(f, "the `link-dir` config key"),
+            BinSource::InstallRootVariable => write!(f, "$CARGO_INSTALL_ROOT"),
+            BinSource::Config(path) => write!(f, "install.root in {}", path.display()),
+            BinSource::Path => write!(f, "$PATH"),
+            BinSource::CargoHome => write!(f, "$CARGO_HOME"),
+        }
+    }
+}
+
+/// The parts of the environment that decide where cargo installs binaries
+#[derive(Debug, Clone, Default)]
+pub struct CargoEnv {
+    /// Where to start looking for cargo's configuration files
+    pub current_dir: PathBuf,
+    pub home: Option<PathBuf>,
+    pub cargo_home: Option<PathBuf>,
+    pub install_root: Option<PathBuf>,
+    pub path: OsString,
+}
+
+/// Every `.cargo/bin` directory in `path`, which is formatted like `$PATH`, in order and without duplicates. Only
+/// whole components count, so `/opt/.cargo/bin-backup` isn't one.
+pub fn cargo_bin_candidates(path: &OsStr) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+    for directory in env::split_paths(path) {
+        if directory.ends_with(".cargo/bin") && candidates.contains(&directory).not() {
+            candidates.push(directory);
+        }
+    }
+
+    candidates
+}
+
+/// Whether `directory` exists and we may create links in it
+pub(crate) fn is_writable_directory(directory: &Path) -> bool {
+    let Ok(path) = CString::new(directory.as_os_str().as_bytes()) else {
+        return false;
+    };
+
+    directory.is_dir() && unsafe { libc::access(path.as_ptr(), libc::W_OK) } == 0
+}
+
+/// Get the directory given through `--link-dir` or `link-dir` ready to link into, creating it if it doesn't exist
+pub fn prepare_link_dir(directory: &Path) -> Result<()> {
+    fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+    if is_writable_directory(directory).not() {
+        bail!(
+            "{} can't be written to, so nothing can be linked into it",
+            directory.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Whether `directory` is in `path`, formatted like `$PATH`, so that what's linked into it runs by name
+pub fn in_path(directory: &Path, path: &OsStr) -> bool {
+    env::split_paths(path).any(|entry| same_directory(&entry, directory))
+}
+
+/// The `.cargo/bin` directories in `path` that can be written to, in order
+pub fn writable_cargo_bins(path: &OsStr) -> Vec<PathBuf> {
+    cargo_bin_candidates(path)
+        .into_iter()
+        .filter(|candidate| is_writable_directory(candidate))
+        .collect()
+}
+
+/// Warn that `path` has several `.cargo/bin` directories that could be linked into, if it does, and which one the
+/// links go into, `chosen`, for those who'd rather have another
+pub fn warn_about_several_cargo_bins(path: &OsStr, chosen: &Path) {
+    let viable = writable_cargo_bins(path);
+    if viable.len() < 2 {
+        return;
+    }
+
+    let viable: Vec<_> = viable
+        .iter()
+        .map(|candidate| candidate.display().to_string())
+        .collect();
+    eprintln!(
+        "Warning: $PATH has several cargo bin directories ({}), linking into {}. Pass --cargo-bin or set `cargo-bin` \
+         to pick another",
+        viable.join(", "),
+        chosen.display()
+    );
+}
+
+/// The best of the `.cargo/bin` directories in `path`: the first one that can be written to, or the first one that
+/// exists otherwise
+pub fn find_cargo_bin(path: &OsStr) -> Option<PathBuf> {
+    match writable_cargo_bins(path).into_iter().next() {
+        Some(first) => Some(first),
+        None => cargo_bin_candidates(path)
+            .into_iter()
+            .find(|candidate| candidate.is_dir()),
+    }
+}
+
+/// The configuration file in the `.cargo` directory at `directory`, if any. Like cargo, this prefers the
+/// extensionless `config` when both are there.
+fn config_file(directory: &Path) -> Option<PathBuf> {
+    ["config", "config.toml"]
+        .into_iter()
+        .map(|name| directory.join(name))
+        .find(|path| path.is_file())
+}
+
+/// `install.root` as set in the configuration file at `path`, relative paths being relative to the directory holding
+/// the `.cargo` directory the file is in
+fn install_root_in(path: &Path) -> Result<Option<PathBuf>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let config: toml::Table =
+        toml::from_str(&contents).with_context(|| format!("Failed to parse {}", path.display()))?;
+
+    let Some(root) = config
+        .get("install")
+        .and_then(|install| install.get("root"))
+    else {
+        return Ok(None);
+    };
+    let Some(root) = root.as_str() else {
+        bail!("install.root in {} must be a string", path.display());
+    };
+
+    let base = path
+        .parent()
+        .and_then(Path::parent)
+        .unwrap_or_else(|| Path::new("/"));
+
+    Ok(Some(base.join(root)))
+}
+
+impl CargoEnv {
+    /// The environment of the running process
+    pub fn current() -> Self {
+        Self {
+            current_dir: env::current_dir().unwrap_or_default(),
+            home: env::var_os("HOME").map(PathBuf::from),
+            cargo_home: env::var_os("CARGO_HOME").map(PathBuf::from),
+            install_root: env::var_os("CARGO_INSTALL_ROOT").map(PathBuf::from),
+            path: env::var_os("PATH").unwrap_or_default(),
+        }
+    }
+
+    /// `$CARGO_HOME`, or `~/.cargo` if it isn't set
+    pub fn cargo_home(&self) -> Option<PathBuf> {
+        self.cargo_home
+            .clone()
+            .or_else(|| Some(self.home.as_ref()?.join(".cargo")))
+    }
+
+    /// Cargo's configuration files, from the one that takes precedence to the one that doesn't
+    pub fn config_files(&self) -> Vec<PathBuf> {
+        let mut files: Vec<_> = self
+            .current_dir
+            .ancestors()
+            .filter_map(|directory| config_file(&directory.join(".cargo")))
+            .collect();
+
+        if let Some(home_config) = self.cargo_home().as_deref().and_then(config_file) {
+            if files.contains(&home_config).not() {
+                files.push(home_config);
+            }
+        }
+
+        files
+    }
+
+    /// The install root set in cargo's configuration files, along with the file that set it
+    pub fn configured_install_root(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        for file in self.config_files() {
+            if let Some(root) = install_root_in(&file)? {
+                return Ok(Some((root, file)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// The directory to link binaries into, and what decided it
+    pub fn cargo_bin(&self) -> Result<(PathBuf, BinSource)> {
+        if let Some(root) = &self.install_root {
+            return Ok((root.join("bin"), BinSource::InstallRootVariable));
+        }
+        if let Some((root, file)) = self.configured_install_root()? {
+            return Ok((root.join("bin"), BinSource::Config(file)));
+        }
+        if let Some(cargo_bin) = find_cargo_bin(&self.path) {
+            return Ok((cargo_bin, BinSource::Path));
+        }
+        if let Some(cargo_bin) = self
+            .cargo_home()
+            .map(|cargo_home| cargo_home.join("bin"))
+            .filter(|cargo_bin| cargo_bin.is_dir())
+        {
+            return Ok((cargo_bin, BinSource::CargoHome));
+        }
+
+        bail!("Failed to find your .cargo/bin directory. Is Cargo configured in your PATH?")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::Path;
+
+    use super::cargo_bin_candidates;
+    use super::find_cargo_bin;
+    use super::BinSource;
+    use super::CargoEnv;
+
+    #[test]
+    fn finds_cargo_bins_in_path() {
+        let path = OsStr::from_bytes(b"/usr/bin:/home/j\xf6rg/.cargo/bin/:/bin");
+        assert_eq!(
+            cargo_bin_candidates(path),
+            [Path::new(OsStr::from_bytes(b"/home/j\xf6rg/.cargo/bin"))]
+        );
+        assert!(cargo_bin_candidates(OsStr::new("/usr/bin:/not.cargo/bin")).is_empty());
+    }
+
+    #[test]
+    fn picks_among_several_cargo_bins() {
+        let root = tempfile::tempdir().unwrap();
+        let system = root.path().join("opt/.cargo/bin");
+        let user = root.path().join("home/.cargo/bin");
+        let stale = root.path().join("gone/.cargo/bin");
+        let backup = root.path().join("opt/.cargo/bin-backup");
+        for directory in [&system, &user, &backup] {
+            fs::create_dir_all(directory).unwrap();
+        }
+        let path = |directories: &[&Path]| env::join_paths(directories).unwrap();
+
+        assert_eq!(
+            cargo_bin_candidates(&path(&[&backup, &stale, &user, &stale])),
+            [stale.clone(), user.clone()]
+        );
+        assert_eq!(
+            find_cargo_bin(&path(&[&backup, &stale, &user])),
+            Some(user.clone())
+        );
+        assert_eq!(find_cargo_bin(&path(&[&stale])), None);
+
+        // Root can write anywhere, so read-only directories can only be told apart by everyone else
+        fs::set_permissions(&system, fs::Permissions::from_mode(0o555)).unwrap();
+        if unsafe { libc::geteuid() } != 0 {
+            assert_eq!(find_cargo_bin(&path(&[&system, &user])), Some(user.clone()));
+        }
+        fs::set_permissions(&system, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_cargo_bin(&path(&[&system, &user])), Some(system));
+    }
+
+    #[test]
+    fn follows_cargo_config_precedence() {
+        let root = tempfile::tempdir().unwrap();
+        let home = root.path().join("home");
+        let project = home.join("code").join("project");
+        let nested = project.join("crates").join("nested");
+        fs::create_dir_all(&nested).unwrap();
+        fs::create_dir_all(home.join(".cargo").join("bin")).unwrap();
+        let in_path = root.path().join("elsewhere/.cargo/bin");
+        fs::create_dir_all(&in_path).unwrap();
+
+        let env = CargoEnv {
+            current_dir: nested.clone(),
+            home: Some(home.clone()),
+            path: env::join_paths([Path::new("/usr/bin"), &in_path]).unwrap(),
+            ..CargoEnv::default()
+        };
+
+        // Nothing configured, so `$PATH` decides, and `~/.cargo/bin` only once it doesn't
+        assert_eq!(env.cargo_bin().unwrap(), (in_path, BinSource::Path));
+        let without_path = CargoEnv {
+            path: "/usr/bin".into(),
+            ..env.clone()
+        };
+        assert_eq!(
+            without_path.cargo_bin().unwrap(),
+            (home.join(".cargo/bin"), BinSource::CargoHome)
+        );
+
+        let write_config = |directory: &Path, name: &str, contents: &str| {
+            let path = directory.join(".cargo").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let home_config = write_config(&home, "config.toml", "[install]\nroot = \"/opt/tools\"\n");
+        assert_eq!(
+            env.cargo_bin().unwrap(),
+            ("/opt/tools/bin".into(), BinSource::Config(home_config))
+        );
+
+        // Closer configuration files win, and their relative paths start next to their `.cargo` directory
+        let project_config = write_config(&project, "config", "[install]\nroot = \"tools\"\n");
+        write_config(&nested, "config.toml", "[build]\njobs = 2\n");
+        assert_eq!(
+            env.cargo_bin().unwrap(),
+            (
+                project.join("tools").join("bin"),
+                BinSource::Config(project_config)
+            )
+        );
+
+        let with_variable = CargoEnv {
+            install_root: Some("/from/variable".into()),
+            ..env.clone()
+        };
+        assert_eq!(
+            with_variable.cargo_bin().unwrap(),
+            ("/from/variable/bin".into(), BinSource::InstallRootVariable)
+        );
+
+        // `$CARGO_HOME` moves the home configuration along with it
+        let elsewhere = root.path().join("cargo-home");
+        write_config(
+            &elsewhere,
+            "config.toml",
+            "[install]\nroot = \"/ignored\"\n",
+        );
+        let with_cargo_home = CargoEnv {
+            current_dir: root.path().to_owned(),
+            cargo_home: Some(elsewhere.join(".cargo")),
+            ..env.clone()
+        };
+        assert_eq!(with_cargo_home.config_files().len(), 1);
+        write_config(&nested, "config.toml", "[install]\nroot = 3\n");
+        assert!(env.cargo_bin().is_err());
+    }
+}
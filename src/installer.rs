@@ -0,0 +1,215 @@
+//! What actually builds a package into the registry. [`CargoInstaller`](crate::cargo::CargoInstaller) runs
+//! `cargo install`, but anything that can leave binaries in a `bin` directory will do, which is how tests get by
+//! without the network or a compiler.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::ops::Not;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::events::Event;
+use crate::events::EventSink;
+use crate::retry::Failure;
+
+/// What `cargo install` is told on top of what cargo-switch decides itself, from the `[packages.<name>]` presets of
+/// the config file and the command line. Recorded along with the version, so that it can be built again the same way.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct BuildFlags {
+    /// Build with this rustup toolchain, as in `cargo +1.75 install`, rather than the active one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub toolchain: Option<String>,
+    /// Leave the crate's default features out
+    #[serde(skip_serializing_if = "Not::not")]
+    pub no_default_features: bool,
+    /// Build with the `Cargo.lock` the crate was published with
+    #[serde(skip_serializing_if = "Not::not")]
+    pub locked: bool,
+    /// Passed on to `cargo install` as they are
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub extra_args: Vec<String>,
+    /// The environment `cargo install` runs with, as far as it changes what's built: the package's `build-env` on
+    /// top of whichever of [`BUILD_ENV_VARIABLES`] were set. `None` for builds from before it was recorded, which
+    /// get whatever the environment holds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// The variables that change what a build produces, as in `RUSTFLAGS="-C target-cpu=native"`, recorded along with
+/// every build they were set for
+pub const BUILD_ENV_VARIABLES: [&str; 8] = [
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "CARGO_BUILD_RUSTFLAGS",
+    "CC",
+    "CXX",
+    "CFLAGS",
+    "CXXFLAGS",
+    "LDFLAGS",
+];
+
+/// The environment a build runs with: whichever of [`BUILD_ENV_VARIABLES`] `variable` says are set, with
+/// `configured` on top
+pub fn build_env(
+    configured: &BTreeMap<String, String>,
+    variable: impl Fn(&str) -> Option<String>,
+) -> BTreeMap<String, String> {
+    let mut env: BTreeMap<_, _> = BUILD_ENV_VARIABLES
+        .iter()
+        .filter_map(|name| Some((name.to_string(), variable(name)?)))
+        .collect();
+    env.extend(configured.clone());
+
+    env
+}
+
+/// `env` as it would read on a command line, as in `RUSTFLAGS="-C target-cpu=native" CC=clang`
+pub fn describe_env(env: &BTreeMap<String, String>) -> String {
+    let words: Vec<_> = env
+        .iter()
+        .map(|(name, value)| {
+            let plain = value.is_empty().not()
+                && value
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || "-_./:=,+".contains(ch));
+            match plain {
+                true => format!("{name}={value}"),
+                false => format!("{name}={value:?}"),
+            }
+        })
+        .collect();
+
+    words.join(" ")
+}
+
+impl BuildFlags {
+    pub fn is_empty(&self) -> bool {
+        self.toolchain.is_none()
+            && self.no_default_features.not()
+            && self.locked.not()
+            && self.extra_args.is_empty()
+            && self.env.as_ref().is_none_or(BTreeMap::is_empty)
+    }
+}
+
+/// As they would read on cargo's command line, as in `RUSTFLAGS="-C target-cpu=native" +1.75 --locked`
+impl fmt::Display for BuildFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words = Vec::new();
+        if let Some(env) = self.env.as_ref().filter(|env| env.is_empty().not()) {
+            words.push(describe_env(env));
+        }
+        if let Some(toolchain) = &self.toolchain {
+            words.push(format!("+{toolchain}"));
+        }
+        if self.no_default_features {
+            words.push("--no-default-features".to_owned());
+        }
+        if self.locked {
+            words.push("--locked".to_owned());
+        }
+        words.extend(self.extra_args.iter().cloned());
+
+        f.write_str(&words.join(" "))
+    }
+}
+
+static NO_FLAGS: BuildFlags = BuildFlags {
+    toolchain: None,
+    no_default_features: false,
+    locked: false,
+    extra_args: Vec::new(),
+    env: None,
+};
+
+/// How to build a package, once the install options were resolved
+#[derive(Debug, Clone, Copy)]
+pub struct BuildOptions<'a> {
+    /// The cargo profile to build with
+    pub profile: &'a str,
+    /// Build the crate found in this directory rather than fetching it from crates.io
+    pub path: Option<&'a Path>,
+    /// Cross-compile for this target triple
+    pub target: Option<&'a str>,
+    /// The cargo features to build with, already normalized
+    pub features: &'a [String],
+    /// Build the crate found in this git repository rather than fetching it from crates.io, at commit `rev`
+    pub git: Option<&'a str>,
+    pub rev: Option<&'a str>,
+    /// Build offline from what `vendor` downloaded into this directory, see [`vendor`](crate::vendor)
+    pub vendored: Option<&'a Path>,
+    /// Build the sources in this directory with `--locked`, reproducing the build whose `Cargo.lock` they were given
+    pub locked: Option<&'a Path>,
+    pub flags: &'a BuildFlags,
+    /// How long the build may run before it's killed, if there's a limit
+    pub timeout: Option<Duration>,
+    /// Where to report the build's output as it comes, if anywhere
+    pub events: Option<&'a dyn EventSink>,
+}
+
+impl Default for BuildOptions<'_> {
+    fn default() -> Self {
+        Self {
+            profile: "release",
+            path: None,
+            target: None,
+            features: &[],
+            git: None,
+            rev: None,
+            vendored: None,
+            locked: None,
+            flags: &NO_FLAGS,
+            timeout: None,
+            events: None,
+        }
+    }
+}
+
+impl BuildOptions<'_> {
+    /// Report a line of what the build printed
+    pub fn output(&self, chunk: &str) {
+        if let Some(events) = self.events {
+            events.emit(&Event::CargoOutput {
+                chunk: chunk.to_owned(),
+            });
+        }
+    }
+}
+
+/// What an installer reports back after a successful build
+#[derive(Debug, Clone)]
+pub struct InstallOutcome {
+    pub build_duration: Duration,
+    /// The `Cargo.lock` the build resolved, or why it couldn't be told
+    pub lockfile: Result<String, String>,
+    /// The SHA-256 checksum of the `.crate` file the build was made from, for builds from crates.io, if it could be
+    /// told
+    pub crate_checksum: Option<String>,
+}
+
+pub trait Installer: fmt::Debug {
+    /// Build `spec`, as in `name@version`, into `root`, leaving its binaries in `root/bin`. Failures tell apart the
+    /// ones worth retrying from the ones that would happen again.
+    fn install(
+        &self,
+        spec: &str,
+        root: &Path,
+        options: &BuildOptions,
+    ) -> Result<InstallOutcome, Failure>;
+
+    /// What `rustc --version` says for the rustc builds with `toolchain`, or the active toolchain, would use, if it
+    /// can be told. See [`RustcVersion`](crate::msrv::RustcVersion).
+    fn rustc_version(&self, _toolchain: Option<&str>) -> Option<String> {
+        None
+    }
+
+    /// Whether builds of versions from crates.io fetch them from crates.io, making it worth checking that it has them
+    /// beforehand. See [`preflight`](crate::preflight).
+    fn fetches_from_crates_io(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,246 @@
+//! Cleaning up after Ctrl-C, or anything else sending SIGINT or SIGTERM.
+//!
+//! Whatever would be left half done by an interruption registers itself here for as long as it's in flight: builds
+//! to kill, directories of installs to remove and package locks to release. Once a signal comes in, they're taken
+//! care of in that order and the process exits with the conventional status of 128 plus the signal's number.
+//!
+//! Updates of the links in `.cargo/bin` can't be cleaned up after, so they're made uninterruptible through
+//! [`defer`] instead: a signal arriving in the meantime is only acted upon once they're done.
+//!
+//! The signal handler itself only writes the signal's number to a pipe, as that's about all that is safe to do in a
+//! signal handler. A thread of ours reads it and does the actual work.
+
+use std::fs;
+use std::fs::File;
+use std::io::Read;
+use std::os::fd::FromRawFd;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::lock;
+
+/// How long builds get to stop on their own before being killed
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Write end of the pipe the signal handler writes to
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+static STATE: Mutex<State> = Mutex::new(State {
+    next_id: 0,
+    tasks: Vec::new(),
+    deferred: 0,
+    pending: None,
+});
+
+#[derive(Debug)]
+enum Task {
+    /// A child running in a process group of its own, like `cargo install` and the `rustc`s it spawns
+    KillGroup(u32),
+    /// The directory of an install that isn't complete yet
+    RemoveDirectory(PathBuf),
+    /// A package lock, see [`lock::release`]
+    ReleaseLock { path: PathBuf, directory: PathBuf },
+}
+
+impl Task {
+    /// Later tasks depend on earlier ones being done: nothing can be removed while a build still writes to it
+    fn order(&self) -> u8 {
+        match self {
+            Task::KillGroup(_) => 0,
+            Task::RemoveDirectory(_) => 1,
+            Task::ReleaseLock { .. } => 2,
+        }
+    }
+}
+
+struct State {
+    next_id: u64,
+    tasks: Vec<(u64, Task)>,
+    /// How many uninterruptible sections are running
+    deferred: usize,
+    /// A signal that came in during an uninterruptible section
+    pending: Option<i32>,
+}
+
+/// Keeps a task registered until dropped
+#[must_use]
+#[derive(Debug)]
+pub struct Registration {
+    id: u64,
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        state().tasks.retain(|(id, _)| *id != self.id);
+    }
+}
+
+/// Keeps signals from being acted upon until dropped
+#[must_use]
+#[derive(Debug)]
+pub struct Deferred(());
+
+impl Drop for Deferred {
+    fn drop(&mut self) {
+        let mut state = state();
+        state.deferred -= 1;
+        if let (0, Some(signal)) = (state.deferred, state.pending) {
+            clean_up_and_exit(state, signal);
+        }
+    }
+}
+
+/// The state, even if whoever held it last panicked: cleaning up is still worth a shot
+fn state() -> MutexGuard<'static, State> {
+    STATE
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn register(task: Task) -> Registration {
+    let mut state = state();
+    let id = state.next_id;
+    state.next_id += 1;
+    state.tasks.push((id, task));
+
+    Registration { id }
+}
+
+/// Kill the process group `group` if interrupted before the registration is dropped
+pub fn kill_on_interrupt(group: u32) -> Registration {
+    register(Task::KillGroup(group))
+}
+
+/// Remove `directory` if interrupted before the registration is dropped. Only meant for the directories of installs
+/// in flight, which must be dropped once complete.
+pub fn remove_on_interrupt(directory: &Path) -> Registration {
+    register(Task::RemoveDirectory(directory.to_owned()))
+}
+
+/// Release the package lock at `path`, living in `directory`, if interrupted before the registration is dropped
+pub fn release_on_interrupt(path: &Path, directory: &Path) -> Registration {
+    register(Task::ReleaseLock {
+        path: path.to_owned(),
+        directory: directory.to_owned(),
+    })
+}
+
+/// Hold off acting on signals for as long as the returned guard lives
+pub fn defer() -> Deferred {
+    state().deferred += 1;
+    Deferred(())
+}
+
+/// Whether [`install_handler`] was called. Children are only given process groups of their own when it was, as
+/// they would otherwise miss out on the terminal's Ctrl-C without anyone passing it along.
+pub fn handler_installed() -> bool {
+    INSTALLED.load(Ordering::Relaxed)
+}
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    // Nothing to be done if it fails, and `write` is one of the few things that can be done here
+    unsafe {
+        libc::write(
+            SIGNAL_PIPE.load(Ordering::Relaxed),
+            (&byte as *const u8).cast(),
+            1,
+        );
+    }
+}
+
+/// Clean up after SIGINT and SIGTERM from now on, rather than dying on the spot
+pub fn install_handler() -> Result<()> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+        bail!(
+            "Failed to set up the signal handler: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    let [read_fd, write_fd] = fds;
+    // Neither end is of any use to children
+    for fd in fds {
+        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+    }
+    SIGNAL_PIPE.store(write_fd, Ordering::Relaxed);
+
+    // Safe since nothing else owns the read end
+    let mut pipe = unsafe { File::from_raw_fd(read_fd) };
+    thread::Builder::new()
+        .name("signals".to_owned())
+        .spawn(move || {
+            let mut signal = [0];
+            while pipe.read_exact(&mut signal).is_ok() {
+                let mut state = state();
+                if state.deferred > 0 {
+                    state.pending = Some(i32::from(signal[0]));
+                    continue;
+                }
+                clean_up_and_exit(state, i32::from(signal[0]));
+            }
+        })?;
+
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        // Interrupted system calls carry on rather than failing with EINTR all over the place
+        action.sa_flags = libc::SA_RESTART;
+        unsafe { libc::sigemptyset(&mut action.sa_mask) };
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            bail!(
+                "Failed to set up the signal handler: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+    INSTALLED.store(true, Ordering::Relaxed);
+
+    Ok(())
+}
+
+/// Ask the process group `group` to stop, killing it if it takes too long
+pub(crate) fn kill_group(group: u32) {
+    let group = group as libc::pid_t;
+    let group_exists = || unsafe { libc::kill(-group, 0) } == 0;
+
+    unsafe { libc::kill(-group, libc::SIGTERM) };
+    let deadline = Instant::now() + KILL_GRACE_PERIOD;
+    while group_exists() && Instant::now() < deadline {
+        thread::sleep(POLL_INTERVAL);
+    }
+    unsafe { libc::kill(-group, libc::SIGKILL) };
+}
+
+/// Take care of every registered task and exit. The state stays locked throughout, so that whatever else is running
+/// blocks as soon as it registers or drops anything rather than carrying on.
+fn clean_up_and_exit(mut state: MutexGuard<State>, signal: i32) -> ! {
+    let mut tasks = std::mem::take(&mut state.tasks);
+    tasks.sort_by_key(|(id, task)| (task.order(), *id));
+
+    for (_, task) in tasks {
+        match task {
+            Task::KillGroup(group) => kill_group(group),
+            Task::RemoveDirectory(directory) => {
+                eprintln!("Interrupted, removing {}", directory.display());
+                let _ = fs::remove_dir_all(&directory);
+            }
+            Task::ReleaseLock { path, directory } => lock::release(&path, &directory),
+        }
+    }
+
+    process::exit(128 + signal);
+}
@@ -0,0 +1,770 @@
+#![allow(clippy::manual_flatten)]
+//! Keeps several versions of the same Cargo binary side by side in a registry under `.cargo/bin`, and switches
+//! between them by relinking.
+
+pub mod add_binary;
+pub mod audit;
+pub mod backup;
+pub mod binary_cache;
+pub mod bisect;
+pub mod cache;
+pub mod cargo;
+pub mod cargo_records;
+pub mod changelog;
+pub mod channel;
+pub mod check;
+pub mod checksum;
+pub mod config;
+pub mod copy;
+pub mod crate_checksum;
+pub mod crates_io;
+pub mod crates_json;
+pub mod crates_toml;
+pub mod direnv;
+pub mod doctor;
+pub mod download;
+pub mod drift;
+pub mod events;
+pub mod exec;
+pub mod extract;
+pub mod format;
+pub mod git;
+pub mod group;
+pub mod info;
+pub mod install;
+pub mod install_root;
+pub mod installer;
+pub mod interrupt;
+pub mod link_style;
+pub mod links;
+pub mod listing;
+pub mod lock;
+pub mod lockfile;
+pub mod metadata;
+pub mod migrate;
+pub mod msrv;
+pub mod network;
+pub mod operation_log;
+pub mod paths;
+pub mod preflight;
+pub mod project;
+pub mod project_install;
+pub mod prompt;
+pub mod protected;
+pub mod prune;
+pub mod purge;
+pub mod quota;
+pub mod rebuild;
+pub mod resolve;
+pub mod retry;
+pub mod run;
+pub mod self_switch;
+pub mod self_update;
+pub mod setup;
+pub mod shadow;
+pub mod shared;
+pub mod shell;
+pub mod smoke_test;
+pub mod sparse_index;
+pub mod spec;
+pub mod spec_file;
+pub mod state;
+pub mod strip;
+pub mod suggest;
+pub mod summary;
+pub mod switch_many;
+pub mod table;
+pub mod test_matrix;
+pub mod tool_versions;
+pub mod trash;
+pub mod uninstall;
+pub mod update;
+pub mod update_hints;
+pub mod variant;
+pub mod vendor;
+pub mod version;
+pub mod version_lock;
+pub mod versioned_links;
+pub mod why;
+pub mod wrapper;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::read_dir;
+use std::io;
+use std::io::IsTerminal;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use cargo::CargoInstaller;
+use config::Config;
+use crates_json::CratesJson;
+use events::Event;
+use events::EventSink;
+use install_root::BinSource;
+use installer::Installer;
+use link_style::LinkStyle;
+use metadata::VersionMetadata;
+use operation_log::Operation;
+use state::State;
+use summary::FailureMode;
+
+/// Directory holding the registry, inside of the cargo bin directory or the link dir, unless told otherwise
+const REGISTRY_DIRECTORY_NAME: &str = "cargo-switch-registry";
+
+pub struct Switcher {
+    /// Where the links to the active binaries go: the cargo bin directory, or the link dir if there's one
+    cargo_bin: PathBuf,
+    registry: PathBuf,
+    /// Versions installed for every user, consulted after `registry`, see [`shared`]
+    shared_registry: Option<PathBuf>,
+    /// Whether `registry` is the shared registry, as with `--system`
+    system: bool,
+    config: Config,
+    /// How new links in `.cargo/bin` point into the registry
+    link_style: LinkStyle,
+    /// Let switching replace the toolchain's binaries, see [`protected`]
+    allow_overwrite_toolchain: bool,
+    /// Switch to versions whether or not they passed their smoke test, see [`smoke_test`]
+    ignore_smoke_test: bool,
+    /// What builds packages into the registry
+    installer: Box<dyn Installer>,
+    /// Where events go, if anywhere
+    events: Option<Box<dyn EventSink>>,
+    /// Ask crates.io again rather than trusting what it said recently
+    refresh: bool,
+    /// Let the registry grow past `max-size` rather than evicting anything
+    no_evict: bool,
+    /// Give every package switched to versioned links, see [`versioned_links`]
+    keep_suffixed: bool,
+    /// What commands dealing with many specs at once do about failures, see [`summary`]
+    failure_mode: FailureMode,
+    /// Let pre-releases be the newest version of every package
+    pre: bool,
+    /// Never ask anything, taking a no for an answer, whether or not there's a terminal to ask on
+    non_interactive: bool,
+    /// The sparse index read in place of crates.io's, whatever cargo's configuration says
+    crates_io_index: Option<String>,
+}
+
+/// Builds a [`Switcher`]. Anything left unset is discovered the same way [`Switcher::new`] does it.
+#[derive(Debug, Default)]
+pub struct SwitcherBuilder {
+    cargo_bin: Option<PathBuf>,
+    link_dir: Option<PathBuf>,
+    registry: Option<PathBuf>,
+    system: bool,
+    config: Option<Config>,
+    link_style: Option<LinkStyle>,
+    allow_overwrite_toolchain: bool,
+    ignore_smoke_test: bool,
+    installer: Option<Box<dyn Installer>>,
+    events: Option<Box<dyn EventSink>>,
+    verbose: bool,
+    quiet: bool,
+    refresh: bool,
+    no_evict: bool,
+    keep_suffixed: bool,
+    failure_mode: Option<FailureMode>,
+    pre: bool,
+    no_proxy: bool,
+    non_interactive: bool,
+    read_only: bool,
+    crates_io_index: Option<String>,
+}
+
+impl SwitcherBuilder {
+    /// The directory that links to the active binaries, as given through `--cargo-bin`. Takes precedence over the
+    /// `cargo-bin` config key and whatever [`install_root`] would find.
+    pub fn cargo_bin(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cargo_bin = Some(path.into());
+        self
+    }
+
+    /// The directory to link binaries into instead of the cargo bin directory, as given through `--link-dir`. Takes
+    /// precedence over the `link-dir` config key. Created if it doesn't exist.
+    pub fn link_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.link_dir = Some(path.into());
+        self
+    }
+
+    /// The directory holding every installed version, `cargo-switch-registry` inside of the cargo bin directory
+    /// by default, or inside of the link dir if there's one and the cargo bin directory has no registry. Created if
+    /// it doesn't exist.
+    pub fn registry(mut self, path: impl Into<PathBuf>) -> Self {
+        self.registry = Some(path.into());
+        self
+    }
+
+    /// Install into the shared registry rather than the user's own, see [`shared`]. Fails unless the `shared-registry`
+    /// config key is set and the shared registry may be written to.
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        self
+    }
+
+    /// The configuration to use instead of loading the user's configuration file
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// How to link to the registry, taking precedence over the `link-style` config key
+    pub fn link_style(mut self, link_style: LinkStyle) -> Self {
+        self.link_style = Some(link_style);
+        self
+    }
+
+    /// Let packages whose binaries are named like the toolchain's, such as `cargo` or `rustup`, replace them
+    pub fn allow_overwrite_toolchain(mut self, allow: bool) -> Self {
+        self.allow_overwrite_toolchain = allow;
+        self
+    }
+
+    /// Switch to versions without smoke testing them first, even to quarantined ones
+    pub fn ignore_smoke_test(mut self, ignore: bool) -> Self {
+        self.ignore_smoke_test = ignore;
+        self
+    }
+
+    /// What builds packages into the registry, running `cargo install` by default
+    pub fn installer(mut self, installer: impl Installer + 'static) -> Self {
+        self.installer = Some(Box::new(installer));
+        self
+    }
+
+    /// Where to emit [`Event`]s as things happen, nowhere by default
+    pub fn events(mut self, events: impl EventSink + 'static) -> Self {
+        self.events = Some(Box::new(events));
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Only show cargo's output when builds fail
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Ignore what crates.io said recently, see [`cache`]
+    pub fn refresh(mut self, refresh: bool) -> Self {
+        self.refresh = refresh;
+        self
+    }
+
+    /// Never evict anything to keep the registry under `max-size`, see [`quota`]
+    pub fn no_evict(mut self, no_evict: bool) -> Self {
+        self.no_evict = no_evict;
+        self
+    }
+
+    /// Keep versioned links, as in `rg-14`, for every package switched to, see [`versioned_links`]
+    pub fn keep_suffixed(mut self, keep_suffixed: bool) -> Self {
+        self.keep_suffixed = keep_suffixed;
+        self
+    }
+
+    /// Whether commands dealing with many specs at once keep going past failures, taking precedence over the
+    /// `failure-mode` config key, see [`summary`]
+    pub fn failure_mode(mut self, failure_mode: FailureMode) -> Self {
+        self.failure_mode = Some(failure_mode);
+        self
+    }
+
+    /// Let pre-releases, as in `2.0.0-beta.1`, be the newest version of every package, both on crates.io and among
+    /// the installed versions. Otherwise they only are for packages whose `pre` config key is set, or when there's
+    /// nothing else.
+    pub fn pre(mut self, pre: bool) -> Self {
+        self.pre = pre;
+        self
+    }
+
+    /// Reach servers directly, whatever proxy cargo's configuration or the environment name, see [`network`]
+    pub fn no_proxy(mut self, no_proxy: bool) -> Self {
+        self.no_proxy = no_proxy;
+        self
+    }
+
+    /// Never ask the user anything, taking a no for an answer as when there's no terminal to ask on
+    pub fn non_interactive(mut self, non_interactive: bool) -> Self {
+        self.non_interactive = non_interactive;
+        self
+    }
+
+    /// Only read what's there: fail rather than create the link dir or the registry, or migrate a registry that
+    /// isn't in the current format. Meant for prompts, which are rendered far too often to write anything.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// The sparse index to read what crates.io has from, as in `https://index.crates.io/`, rather than the one
+    /// cargo's configuration names, see [`sparse_index`]
+    pub fn crates_io_index(mut self, index: impl Into<String>) -> Self {
+        self.crates_io_index = Some(index.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Switcher> {
+        self.build_reporting_new()
+            .map(|(switcher, _new_registry)| switcher)
+    }
+
+    /// Build the switcher, telling whether its registry had to be created along the way
+    pub(crate) fn build_reporting_new(mut self) -> Result<(Switcher, bool)> {
+        let config = match self.config.take() {
+            Some(config) => config,
+            None => Config::load()?,
+        };
+
+        network::configure(self.no_proxy, self.verbose);
+
+        let resolution = self.resolve(&config);
+        let (cargo_bin, source) = resolution.links?;
+        match source {
+            BinSource::LinkDirFlag | BinSource::LinkDirConfigKey if self.read_only.not() => {
+                install_root::prepare_link_dir(&cargo_bin)?;
+                // Setup already says so, and every other command would say it again
+                let path = env::var_os("PATH").unwrap_or_default();
+                if self.verbose && install_root::in_path(&cargo_bin, &path).not() {
+                    eprintln!(
+                        "Warning: {} isn't in $PATH, so the binaries linked into it won't run by name",
+                        cargo_bin.display()
+                    );
+                }
+            }
+            _ => ensure!(cargo_bin.exists(), "{} does not exist", cargo_bin.display()),
+        }
+        if self.verbose {
+            eprintln!("Linking into {} (from {source})", cargo_bin.display());
+            // Doctor says so too, every other command would only be noisy about it
+            if source == BinSource::Path {
+                install_root::warn_about_several_cargo_bins(
+                    &env::var_os("PATH").unwrap_or_default(),
+                    &cargo_bin,
+                );
+            }
+        }
+
+        let (registry, _) = resolution.registry?;
+        let shared_registry = match self.system {
+            true => None,
+            false => config
+                .shared_registry
+                .clone()
+                .filter(|shared| shared.exists()),
+        };
+        let new_registry = registry.exists().not();
+        if self.read_only {
+            ensure!(new_registry.not(), "{} does not exist", registry.display());
+            let format_version = migrate::registry_format(&registry)?;
+            ensure!(
+                format_version == migrate::FORMAT_VERSION,
+                "{} is in format {format_version} rather than {}",
+                registry.display(),
+                migrate::FORMAT_VERSION
+            );
+        }
+        if new_registry {
+            fs::create_dir_all(&registry)
+                .with_context(|| format!("Failed to create {}", registry.display()))?;
+            migrate::stamp_new_registry(&registry)?;
+        }
+        if self.system {
+            ensure!(
+                install_root::is_writable_directory(&registry),
+                "Only those who may write to the shared registry at {} can pass --system",
+                registry.display()
+            );
+        }
+
+        let installer = self.installer.unwrap_or_else(|| {
+            Box::new(CargoInstaller {
+                cargo_path: config.cargo_path.clone(),
+                verbose: self.verbose,
+                quiet: self.quiet,
+                stderr_is_terminal: io::stderr().is_terminal(),
+            })
+        });
+
+        let switcher = Switcher {
+            link_style: resolution.link_style.0,
+            cargo_bin,
+            registry,
+            shared_registry,
+            system: self.system,
+            allow_overwrite_toolchain: self.allow_overwrite_toolchain,
+            ignore_smoke_test: self.ignore_smoke_test,
+            installer,
+            events: self.events,
+            refresh: self.refresh,
+            no_evict: self.no_evict,
+            keep_suffixed: self.keep_suffixed,
+            failure_mode: resolution.failure_mode.0,
+            pre: self.pre,
+            non_interactive: self.non_interactive,
+            crates_io_index: self.crates_io_index,
+            config,
+        };
+        if new_registry.not() && self.read_only.not() {
+            switcher.migrate_registry()?;
+        }
+
+        Ok((switcher, new_registry))
+    }
+}
+
+impl Switcher {
+    /// A switcher for the directory cargo installs binaries into, see [`install_root`], configured by the user's
+    /// configuration file
+    pub fn new(verbose: bool) -> Result<Self> {
+        Self::builder().verbose(verbose).build()
+    }
+
+    /// Start building a switcher that works against arbitrary directories
+    pub fn builder() -> SwitcherBuilder {
+        SwitcherBuilder::default()
+    }
+
+    /// Return the project name and version of a spec in the `name@semver` format, or `None` if it's malformed or
+    /// unsafe to use as a path. See [`spec::parse_spec`] for why it was refused.
+    pub fn get_version_tag(package: &str) -> Option<(&str, &str)> {
+        spec::parse_spec(package).ok()
+    }
+
+    fn build_target_path(&self, package: &str) -> Result<PathBuf> {
+        let (project_name, project_version) = spec::parse_spec(package)?;
+
+        Ok(self.version_path(project_name, project_version))
+    }
+
+    /// The name `package` is installed under: itself if it is, or else the one of an installed package that only
+    /// differs from it in which of `-` and `_` it uses, see [`spec::normalize_name`]. Packages that aren't installed
+    /// keep the name they were given, which is what they'll get installed under.
+    pub fn canonical_name(&self, package: &str) -> Result<String> {
+        if self.package_exists(package) {
+            return Ok(package.to_owned());
+        }
+
+        let normalized = spec::normalize_name(package);
+        Ok(self
+            .installed_packages()?
+            .into_iter()
+            .find(|installed| spec::normalize_name(installed) == normalized)
+            .unwrap_or_else(|| package.to_owned()))
+    }
+
+    /// The name `package`, about to be built from crates.io, is installed under: the one crates.io has for it. The
+    /// versions installed under other spellings of it are moved there, see [`Switcher::merge_spellings`].
+    pub(crate) fn adopt_real_name(&self, package: &str) -> Result<String> {
+        let real_name = self.real_crate_name(package);
+        let normalized = spec::normalize_name(&real_name);
+        let misspellings: Vec<_> = shared::directory_names(&self.registry)?
+            .into_iter()
+            .filter(|installed| {
+                *installed != real_name && spec::normalize_name(installed) == normalized
+            })
+            .collect();
+        if misspellings.is_empty().not() {
+            self.merge_spellings(&misspellings, &real_name)?;
+            println!(
+                "Moved {} to {real_name}, as crates.io spells it",
+                misspellings.join(", ")
+            );
+        }
+
+        Ok(real_name)
+    }
+
+    /// Whether pre-releases of `package` may be its newest version, through `--pre` or its `pre` config key
+    pub fn allows_prereleases(&self, package: &str) -> bool {
+        self.pre || self.config.pre(package)
+    }
+
+    /// `spec`, with or without a version, with its name replaced by the one it's installed under
+    pub fn canonical_spec(&self, spec: &str) -> Result<String> {
+        Ok(match spec.split_once('@') {
+            Some((name, version)) => format!("{}@{version}", self.canonical_name(name)?),
+            None => self.canonical_name(spec)?,
+        })
+    }
+
+    /// The names of every installed package, in any registry, sorted alphabetically
+    fn installed_packages(&self) -> Result<Vec<String>> {
+        let mut packages = Vec::new();
+        for registry in self.registries() {
+            packages.extend(shared::directory_names(registry)?);
+        }
+        packages.sort();
+        packages.dedup();
+
+        Ok(packages)
+    }
+
+    /// The installed versions of `package`, in any registry, from oldest to newest
+    fn installed_versions(&self, package: &str) -> Result<Vec<String>> {
+        spec::validate_name(package)?;
+        if self.package_exists(package).not() {
+            return Err(self.not_installed(package, None));
+        }
+
+        let mut versions = Vec::new();
+        for registry in self.registries() {
+            for version in shared::directory_names(&registry.join(package))? {
+                if versions.contains(&version).not() {
+                    versions.push(version);
+                }
+            }
+        }
+
+        version::sort_versions(&mut versions);
+
+        Ok(versions)
+    }
+
+    /// The binaries provided by `package@version`, as recorded when it was installed (by us or at least by cargo)
+    /// or, failing that, whatever can be found in its `bin` directory
+    fn version_binaries(&self, package: &str, version: &str) -> Result<Vec<PathBuf>> {
+        let version_path = self.version_path(package, version);
+        let bin_path = version_path.join("bin");
+        if bin_path.exists().not() {
+            return Err(self.not_installed(package, Some(version)));
+        }
+
+        if let Some(metadata) = VersionMetadata::load(&version_path)? {
+            // Metadata records names as UTF-8, so binaries whose names aren't can only be found by looking
+            let mangled = metadata
+                .binaries
+                .iter()
+                .any(|binary| binary.name.contains(char::REPLACEMENT_CHARACTER));
+            if metadata.binaries.is_empty().not() && mangled.not() {
+                return Ok(metadata
+                    .binaries
+                    .iter()
+                    .map(|binary| bin_path.join(&binary.name))
+                    .collect());
+            }
+        }
+
+        if let Some(crates_json) = CratesJson::load(&version_path) {
+            let recorded = crates_json.binaries();
+            if recorded.is_empty().not() {
+                return Ok(recorded.into_iter().map(|bin| bin_path.join(bin)).collect());
+            }
+        }
+
+        Self::scan_binaries(&bin_path)
+    }
+
+    /// Every file in the `bin` directory at `bin_path`
+    fn scan_binaries(bin_path: &Path) -> Result<Vec<PathBuf>> {
+        let mut binaries = Vec::new();
+        for maybe_entry in fs::read_dir(bin_path)? {
+            binaries.push(maybe_entry?.path());
+        }
+        binaries.sort();
+
+        Ok(binaries)
+    }
+
+    /// The current `$PATH` with the binaries of `package@version` prepended to it, so that they can be used without
+    /// touching the links in `.cargo/bin`
+    fn path_with_version(&self, package: &str, version: &str) -> Result<OsString> {
+        self.path_with_versions(&[(package, version)])
+    }
+
+    /// The current `$PATH` with the binaries of each `(package, version)` prepended to it, earlier ones first
+    fn path_with_versions(&self, versions: &[(&str, &str)]) -> Result<OsString> {
+        let mut components = Vec::new();
+        for (package, version) in versions {
+            let bin_path = self.version_path(package, version).join("bin");
+            if bin_path.exists().not() {
+                return Err(self.not_installed(package, Some(version)));
+            }
+            components.push(bin_path);
+        }
+
+        let path = env::var_os("PATH").unwrap_or_default();
+        components.extend(env::split_paths(&path));
+
+        env::join_paths(components).with_context(|| "Failed to build $PATH")
+    }
+
+    pub fn switch_package(&self, package: &str) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let switch_registry = self.build_target_path(package)?;
+
+        if switch_registry.exists().not() {
+            // A bare version can stand for its only variant, and errors point at the variants otherwise
+            let (name, version) = Self::get_version_tag(package).unwrap_or_default();
+            let variant = self.pick_variant(name, version)?;
+            return self.switch_package(&format!("{name}@{variant}"));
+        }
+
+        let (project_name, _) = Self::get_version_tag(package).unwrap_or_default();
+        let _lock = self.lock_package(project_name)?;
+        self.switch_package_unlocked(package)
+    }
+
+    /// Switch to `package` on behalf of a caller already holding its lock
+    pub(crate) fn switch_package_unlocked(&self, package: &str) -> Result<()> {
+        self.ensure_per_user(&format!("switch to {package}"))?;
+        let switch_registry = self.build_target_path(package)?;
+        let project_bin = switch_registry.join("bin");
+        ensure!(
+            switch_registry.exists(),
+            "Expected {} to exist",
+            switch_registry.display()
+        );
+
+        // Packages that need their environment set up get wrapper scripts rather than symlinks
+        let (project_name, project_version) = Self::get_version_tag(package).unwrap_or_default();
+        self.ensure_not_quarantined(project_name, project_version)?;
+        let env = self.wrapper_env(project_name, project_version);
+
+        let entries = read_dir(project_bin)?.collect::<io::Result<Vec<_>>>()?;
+        let previous = self.linked_version(project_name).ok().flatten();
+
+        // Find out before touching anything, so that a refused switch leaves every link as it was
+        let protected: Vec<_> = entries
+            .iter()
+            .map(|entry| entry.file_name())
+            .filter(|name| protected::is_protected(name))
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect();
+        if protected.is_empty().not() && self.allow_overwrite_toolchain.not() {
+            bail!(
+                "Refusing to switch to {package}: it provides {}, which would replace the Rust toolchain's own \
+                 binaries in {}. If that's really what you want, pass --allow-overwrite-toolchain",
+                protected.join(", "),
+                self.cargo_bin.display()
+            );
+        }
+
+        let _links = self.lock_links()?;
+        let names: Vec<_> = entries.iter().map(|entry| entry.file_name()).collect();
+        // Told before any link is replaced, since they lead to the new version afterwards
+        let switches_itself = self.switches_itself(project_name, project_version, &names);
+        for entry in entries {
+            let entry_path = entry.path();
+
+            // Assumes every binary will be in the form `$CARGO_BIN/bin/binary`. If it has subdirectories and such,
+            // I expect this logic to fail
+            let file_name = entry_path.components().next_back().unwrap().as_os_str();
+            let symlink_path = self.cargo_bin.join(file_name);
+
+            let link_to = self.link_style.link_to(&self.cargo_bin, &entry_path);
+            let replaced = symlink_path.symlink_metadata().is_ok();
+            let old_target = fs::read_link(&symlink_path).ok();
+
+            if let Some(env) = &env {
+                wrapper::write_wrapper(&symlink_path, &link_to, env)?;
+                println!(
+                    "Wrapped {} in {}",
+                    entry_path.display(),
+                    symlink_path.display()
+                );
+            } else {
+                self.replace_link(&symlink_path, &link_to)?;
+                println!(
+                    "Linked {} to {}",
+                    entry_path.display(),
+                    symlink_path.display()
+                );
+            }
+
+            self.emit(if replaced {
+                Event::LinkReplaced {
+                    link: symlink_path,
+                    old_target,
+                    new_target: link_to,
+                }
+            } else {
+                Event::LinkCreated {
+                    link: symlink_path,
+                    target: link_to,
+                }
+            });
+        }
+        // Forks of a crate provide the same binaries, and whatever else the one switched away from provides would be
+        // left pointing to it
+        let (crate_name, _) = spec::split_label(project_name);
+        for link in self.managed_links()? {
+            if link.versioned.not()
+                && link.package != project_name
+                && spec::split_label(&link.package).0 == crate_name
+            {
+                self.remove_link(&link.link)?;
+                println!("Removed {}", link.link.display());
+            }
+        }
+        self.record_switch(project_name, project_version, &names);
+
+        // What eviction goes by
+        let mut state = State::load(&self.registry)?;
+        state
+            .activated_at
+            .entry(project_name.to_owned())
+            .or_default()
+            .insert(
+                project_version.to_owned(),
+                metadata::timestamp(SystemTime::now()),
+            );
+        // Whatever versioned links the new binaries replaced aren't versioned links anymore
+        state
+            .versioned_links
+            .retain(|name, _| names.iter().all(|binary| binary != name.as_str()));
+        // The state is JSON, which can't hold paths that aren't UTF-8
+        if self.cargo_bin.to_str().is_some() {
+            state.link_dirs.insert(self.cargo_bin.clone());
+        }
+        state.save(&self.registry)?;
+        let detail = match previous {
+            Some(previous) if previous != project_version => format!("replaced {previous}"),
+            Some(_) => "active already".to_owned(),
+            None => String::new(),
+        };
+        self.log(
+            Operation::Switch,
+            Some(project_name),
+            Some(project_version),
+            detail,
+        );
+        if switches_itself {
+            println!(
+                "Note: {project_name} is what's running right now, {project_version} takes effect the next time it runs"
+            );
+        }
+
+        self.sync_versioned_links_unlocked(project_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Switcher;
+
+    #[test]
+    fn has_version_tag() {
+        assert!(Switcher::get_version_tag("sqlx-cli@0.7.2").is_some());
+        assert!(Switcher::get_version_tag("zig@1.0.0-rc0").is_some());
+        // Names a channel
+        assert!(Switcher::get_version_tag("zig@rc").is_some());
+
+        assert!(Switcher::get_version_tag("zig@.rc").is_none());
+        assert!(Switcher::get_version_tag("zig@").is_none());
+        assert!(Switcher::get_version_tag("@0.7.2").is_none());
+        assert!(Switcher::get_version_tag("../../evil@1.0").is_none());
+    }
+}
@@ -0,0 +1,138 @@
+use std::fs;
+use std::path::Component;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::wrapper;
+use crate::Switcher;
+
+/// How links in `.cargo/bin` point into the registry
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkStyle {
+    /// Through the target's absolute path
+    #[default]
+    Absolute,
+    /// Through a path relative to `.cargo/bin`, so that both can move around together, e.g. between machines
+    /// whose home directories differ
+    Relative,
+}
+
+impl LinkStyle {
+    /// What a link in `cargo_bin` should point to in order to reach `target`, an absolute path
+    pub fn link_to(self, cargo_bin: &Path, target: &Path) -> PathBuf {
+        match self {
+            LinkStyle::Absolute => target.to_owned(),
+            LinkStyle::Relative => relative_path(&normalize(cargo_bin), &normalize(target)),
+        }
+    }
+}
+
+/// Resolve the `.` and `..` in an absolute `path` without touching the file system, so that paths that went through
+/// `.cargo/bin` can be compared to the registry's
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// The path leading from the directory `from` to `to`, both of which are absolute and normalized
+fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from: Vec<_> = from.components().collect();
+    let to: Vec<_> = to.components().collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut relative = PathBuf::new();
+    for _ in common..from.len() {
+        relative.push("..");
+    }
+    for component in &to[common..] {
+        relative.push(component);
+    }
+
+    relative
+}
+
+impl Switcher {
+    /// Rewrite every managed link so that it follows `style`, returning how many had to change
+    pub fn convert_links(&self, style: LinkStyle) -> Result<usize> {
+        let _lock = self.lock_links()?;
+        let mut converted = 0;
+
+        for link in self.managed_links()? {
+            let Some(target) = self.link_target(&link.link) else {
+                continue;
+            };
+            let link_to = style.link_to(&self.cargo_bin, &target);
+
+            match fs::read_link(&link.link) {
+                Ok(current) if current == link_to => continue,
+                Ok(_) => self.replace_link(&link.link, &link_to)?,
+                // Not a symlink, so it's one of our wrappers
+                Err(_) => {
+                    if wrapper::wrapper_target(&link.link).as_ref() == Some(&link_to) {
+                        continue;
+                    }
+                    let env = self
+                        .wrapper_env(&link.package, &link.version)
+                        .unwrap_or_default();
+                    wrapper::write_wrapper(&link.link, &link_to, &env)?;
+                }
+            }
+
+            println!("Converted {} to {}", link.link.display(), link_to.display());
+            converted += 1;
+        }
+
+        Ok(converted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::normalize;
+    use super::LinkStyle;
+
+    #[test]
+    fn computes_relative_links() {
+        let cargo_bin = Path::new("/home/me/.cargo/bin");
+
+        assert_eq!(
+            LinkStyle::Relative.link_to(
+                cargo_bin,
+                Path::new("/home/me/.cargo/bin/cargo-switch-registry/rg/14.1.0/bin/rg")
+            ),
+            Path::new("cargo-switch-registry/rg/14.1.0/bin/rg")
+        );
+        assert_eq!(
+            LinkStyle::Relative.link_to(cargo_bin, Path::new("/opt/registry/rg/14.1.0/bin/rg")),
+            Path::new("../../../../opt/registry/rg/14.1.0/bin/rg")
+        );
+        assert_eq!(
+            LinkStyle::Absolute.link_to(cargo_bin, Path::new("/opt/registry/rg/14.1.0/bin/rg")),
+            Path::new("/opt/registry/rg/14.1.0/bin/rg")
+        );
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(
+            normalize(Path::new("/home/me/.cargo/bin/../../registry/./rg")),
+            Path::new("/home/me/registry/rg")
+        );
+    }
+}
@@ -0,0 +1,255 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::os::unix;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::format::Record;
+use crate::format::Template;
+use crate::link_style;
+use crate::wrapper;
+use crate::Switcher;
+
+/// How the entry in `.cargo/bin` for one of a version's binaries looks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum LinkState {
+    /// `.cargo/bin` links to this binary
+    Linked,
+    /// `.cargo/bin` links to this binary, but the binary itself is gone
+    Broken,
+    /// The version is active but the binary has no link in `.cargo/bin`
+    Missing,
+    /// The version is active but something else sits where the binary's link should be
+    Overridden { by: PathBuf },
+    /// The version isn't active
+    Inactive,
+}
+
+/// A link in `.cargo/bin` that points into the registry
+#[derive(Debug, Clone)]
+pub struct ManagedLink {
+    /// The name of the binary
+    pub name: OsString,
+    /// Where the link itself is
+    pub link: PathBuf,
+    pub package: String,
+    pub version: String,
+    /// Whether it's a versioned link, as in `rg-14`, rather than a link to the active version. See
+    /// [`versioned_links`].
+    ///
+    /// [`versioned_links`]: crate::versioned_links
+    pub versioned: bool,
+}
+
+/// Whether a package has an active version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "kebab-case")]
+pub enum ActiveState {
+    /// `.cargo/bin` links to the binaries of `version`
+    Active { version: String },
+    /// `.cargo/bin` links to the binaries of `version`, but they're gone
+    Broken { version: String },
+    /// Nothing in `.cargo/bin` links to the package, either because it was never activated or because it was
+    /// deactivated
+    Inactive,
+}
+
+impl Switcher {
+    /// Where the link for the binary called `name` lives
+    pub fn link_path(&self, name: &OsStr) -> PathBuf {
+        self.cargo_bin.join(name)
+    }
+
+    /// Where the link at `link` points to, if it is a link at all. Wrapper scripts count as links to the binary they
+    /// run.
+    pub fn link_target(&self, link: &Path) -> Option<PathBuf> {
+        self.link_target_in(&self.cargo_bin, link)
+    }
+
+    /// Where the link at `link`, inside of `directory`, points to, if it is a link at all
+    pub(crate) fn link_target_in(&self, directory: &Path, link: &Path) -> Option<PathBuf> {
+        let target = match fs::read_link(link) {
+            Ok(target) => target,
+            Err(_) => wrapper::wrapper_target(link)?,
+        };
+
+        // Relative links are relative to their directory, and pass through it with `..` when the registry lives
+        // elsewhere
+        Some(if target.is_relative() {
+            link_style::normalize(&directory.join(target))
+        } else {
+            target
+        })
+    }
+
+    /// Remove the link at `link`, be it a symlink or a wrapper script. Does nothing if there's nothing there.
+    pub fn remove_link(&self, link: &Path) -> Result<()> {
+        match fs::symlink_metadata(link) {
+            Ok(_) => fs::remove_file(link)
+                .with_context(|| format!("Failed to remove {}", link.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err).with_context(|| format!("Failed to inspect {}", link.display())),
+        }
+    }
+
+    /// Make `link` a symlink to `link_to`, swapping it in one go so that the binary never goes missing, not even for
+    /// whoever is running it right now
+    pub(crate) fn replace_link(&self, link: &Path, link_to: &Path) -> Result<()> {
+        let temporary = wrapper::temporary_path(link);
+        self.remove_link(&temporary)?;
+        unix::fs::symlink(link_to, &temporary)
+            .with_context(|| format!("Failed to create {}", temporary.display()))?;
+        fs::rename(&temporary, link)
+            .with_context(|| format!("Failed to replace {}", link.display()))
+    }
+
+    /// Every link in `.cargo/bin` that points into the registry, along with the package and version it belongs to,
+    /// sorted by binary name
+    pub fn managed_links(&self) -> Result<Vec<ManagedLink>> {
+        let mut links = Vec::new();
+        let versioned = self.versioned_link_names()?;
+
+        for maybe_entry in fs::read_dir(&self.cargo_bin)? {
+            let entry = maybe_entry?;
+            let Some((package, version)) = self.link_owner(&entry.file_name()) else {
+                continue;
+            };
+
+            links.push(ManagedLink {
+                versioned: versioned.contains(&entry.file_name()),
+                name: entry.file_name(),
+                link: entry.path(),
+                package,
+                version,
+            });
+        }
+        links.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(links)
+    }
+
+    /// Whether `package` has an active version, judging by the links in `.cargo/bin`
+    pub fn active_state(&self, package: &str) -> Result<ActiveState> {
+        let package_paths: Vec<_> = self
+            .registries()
+            .map(|registry| registry.join(package))
+            .collect();
+        let mut broken = None;
+        // Every installed version may have versioned links, which say nothing about which one is active
+        let versioned = self.versioned_link_names()?;
+
+        for maybe_entry in fs::read_dir(&self.cargo_bin)? {
+            let entry = maybe_entry?;
+            if versioned.contains(&entry.file_name()) {
+                continue;
+            }
+            let Some(target) = self.link_target(&entry.path()) else {
+                continue;
+            };
+
+            let Some(version) = package_paths
+                .iter()
+                .find_map(|package_path| target.strip_prefix(package_path).ok())
+                .and_then(|relative| relative.components().next())
+                .and_then(|version| version.as_os_str().to_str())
+            else {
+                continue;
+            };
+            let version = version.to_owned();
+
+            if target.exists() {
+                return Ok(ActiveState::Active { version });
+            }
+            broken = Some(version);
+        }
+
+        Ok(match broken {
+            Some(version) => ActiveState::Broken { version },
+            None => ActiveState::Inactive,
+        })
+    }
+
+    /// The version of `package` that the links in `.cargo/bin` point to, if any
+    pub fn linked_version(&self, package: &str) -> Result<Option<String>> {
+        Ok(match self.active_state(package)? {
+            ActiveState::Active { version } => Some(version),
+            ActiveState::Broken { .. } | ActiveState::Inactive => None,
+        })
+    }
+
+    /// The package and version that the link for the binary called `name` points into, if it's one of ours
+    pub fn link_owner(&self, name: &OsStr) -> Option<(String, String)> {
+        let target = self.link_target(&self.link_path(name))?;
+        let mut components = self
+            .registries()
+            .find_map(|registry| target.strip_prefix(registry).ok())?
+            .components();
+
+        // Packages and versions are always valid UTF-8, so anything else isn't ours
+        let package = components.next()?.as_os_str().to_str()?.to_owned();
+        let version = components.next()?.as_os_str().to_str()?.to_owned();
+
+        Some((package, version))
+    }
+
+    /// Print which package and version provide the binary called `name`
+    pub fn print_which(&self, name: &OsStr, format: Option<&Template>) -> Result<()> {
+        let display_name = Path::new(name).display();
+        let Some((package, version)) = self.link_owner(name) else {
+            let link = self.link_path(name);
+            ensure!(
+                link.symlink_metadata().is_ok(),
+                "{display_name} is not in {}",
+                self.cargo_bin.display()
+            );
+            bail!("{} is not managed by cargo-switch", link.display());
+        };
+
+        match format {
+            Some(template) => {
+                let listing = self.package_listing(&package)?;
+                let version = listing
+                    .versions
+                    .iter()
+                    .find(|listing| listing.version == version)
+                    .with_context(|| format!("Project {package}@{version} is not installed!"))?;
+                println!("{}", template.render(&Record::new(&package, version)));
+            }
+            None => println!(
+                "{display_name}: {package}@{version} ({})",
+                self.link_target(&self.link_path(name))
+                    .unwrap_or_default()
+                    .display()
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Inspect the link for `binary`, one of the binaries of a version that is `active` or not
+    pub fn link_state(&self, binary: &Path, active: bool) -> LinkState {
+        let Some(name) = binary.file_name() else {
+            return LinkState::Inactive;
+        };
+        let link = self.link_path(name);
+
+        match self.link_target(&link) {
+            Some(target) if target == binary && binary.is_file() => LinkState::Linked,
+            Some(target) if target == binary => LinkState::Broken,
+            _ if active.not() => LinkState::Inactive,
+            Some(target) => LinkState::Overridden { by: target },
+            None if link.exists() => LinkState::Overridden { by: link },
+            None => LinkState::Missing,
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fmt;
+use std::ops::Not;
+use std::path;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::links::ActiveState;
+use crate::links::LinkState;
+use crate::metadata::Source;
+use crate::metadata::SourceKind;
+use crate::metadata::VersionMetadata;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+#[derive(Debug, Serialize)]
+pub struct PackageListing {
+    pub name: String,
+    pub active_state: ActiveState,
+    pub versions: Vec<VersionListing>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionListing {
+    pub version: String,
+    pub active: bool,
+    pub path: PathBuf,
+    /// Where the version came from, if it was recorded
+    pub source: Option<Source>,
+    /// Whether the version is locked, see [`version_lock`]
+    ///
+    /// [`version_lock`]: crate::version_lock
+    pub locked: bool,
+    /// Whether the version is only installed in the shared registry, see [`shared`]
+    ///
+    /// [`shared`]: crate::shared
+    pub shared: bool,
+    pub binaries: Vec<BinaryListing>,
+}
+
+impl VersionListing {
+    /// The short tag of the version's source, `unknown` for versions predating source tracking
+    pub fn source_tag(&self) -> String {
+        self.source
+            .as_ref()
+            .map_or_else(|| "unknown".to_owned(), Source::short_tag)
+    }
+
+    /// The labels that apply out of `labels`, as in ` (locked, shared)`, or nothing if none does
+    fn marker<const N: usize>(&self, labels: [(bool, &str); N]) -> String {
+        let labels: Vec<_> = labels
+            .into_iter()
+            .filter(|(applies, _)| *applies)
+            .map(|(_, label)| label)
+            .collect();
+        if labels.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", labels.join(", "))
+        }
+    }
+
+    pub fn source_kind(&self) -> SourceKind {
+        self.source
+            .as_ref()
+            .map_or(SourceKind::Unknown, Source::kind)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BinaryListing {
+    pub name: String,
+    pub path: PathBuf,
+    pub link: LinkState,
+}
+
+/// A binary name, along with every installed version that provides a binary by that name
+#[derive(Debug, Serialize)]
+pub struct BinaryOwners {
+    pub name: String,
+    /// The version `.cargo/bin` links the binary to, if any
+    pub active: Option<Provider>,
+    /// Every other installed version that could provide the binary
+    pub alternatives: Vec<Provider>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Provider {
+    pub package: String,
+    pub version: String,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.package, self.version)
+    }
+}
+
+impl BinaryOwners {
+    /// Whether more than one package provides the binary
+    pub fn is_contested(&self) -> bool {
+        let mut packages = self
+            .active
+            .iter()
+            .chain(&self.alternatives)
+            .map(|provider| &provider.package);
+        let first = packages.next();
+
+        packages.any(|package| Some(package) != first)
+    }
+}
+
+impl Switcher {
+    pub fn package_listing(&self, package: &str) -> Result<PackageListing> {
+        let active_state = self.active_state(package)?;
+        let locked = self.locked_versions(package)?;
+
+        let mut versions = Vec::new();
+        for version in self.installed_versions(package)? {
+            let active = active_state
+                == ActiveState::Active {
+                    version: version.clone(),
+                };
+
+            let binaries = self
+                .version_binaries(package, &version)?
+                .into_iter()
+                .map(|path| BinaryListing {
+                    name: path
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into_owned(),
+                    link: self.link_state(&path, active),
+                    path,
+                })
+                .collect();
+
+            let path = self.version_path(package, &version);
+            // Older versions have no metadata to go by, and corrupt metadata shouldn't keep the rest from showing
+            let source = VersionMetadata::load(&path)
+                .ok()
+                .flatten()
+                .and_then(|metadata| metadata.source);
+
+            versions.push(VersionListing {
+                shared: self.is_shared(package, &version),
+                path,
+                source,
+                locked: locked.contains(&version),
+                version,
+                active,
+                binaries,
+            });
+        }
+
+        Ok(PackageListing {
+            name: package.to_owned(),
+            active_state,
+            versions,
+        })
+    }
+
+    /// Where `package@version` is installed, as an absolute path, or where its binary `binary` is if given. A
+    /// package without a version stands for its only version.
+    pub fn installed_path(
+        &self,
+        package: &str,
+        version: Option<&str>,
+        binary: Option<&str>,
+    ) -> Result<PathBuf> {
+        let package = &self.canonical_name(package)?;
+        let version = match version {
+            Some(version) => self.pick_variant(package, version)?,
+            None => match self.installed_versions(package)?.as_slice() {
+                [] => return Err(self.not_installed(package, None)),
+                [only] => only.clone(),
+                versions => bail!(
+                    "{package} has {} versions installed, ask for one of them as {package}@VERSION: {}",
+                    versions.len(),
+                    versions.join(", ")
+                ),
+            },
+        };
+
+        let path = match binary {
+            None => self.version_path(package, &version),
+            Some(binary) => {
+                let binaries = self.version_binaries(package, &version)?;
+                match binaries
+                    .iter()
+                    .find(|path| path.file_name() == Some(OsStr::new(binary)))
+                {
+                    Some(path) => path.clone(),
+                    None => {
+                        let names: Vec<_> = binaries
+                            .iter()
+                            .filter_map(|path| path.file_name())
+                            .map(|name| name.to_string_lossy())
+                            .collect();
+                        ensure!(
+                            names.is_empty().not(),
+                            "{package}@{version} provides no binaries"
+                        );
+                        bail!(
+                            "{package}@{version} has no binary named {binary}, only {}",
+                            names.join(", ")
+                        )
+                    }
+                }
+            }
+        };
+
+        Ok(path::absolute(&path)?)
+    }
+
+    /// List every installed package, or only the ones without an active version if `inactive_only` is set
+    pub fn listing(&self, inactive_only: bool) -> Result<Vec<PackageListing>> {
+        let mut listings = Vec::new();
+        for package in self.installed_packages()? {
+            let listing = self.package_listing(&package)?;
+            let active = matches!(listing.active_state, ActiveState::Active { .. });
+
+            if inactive_only.not() || active.not() {
+                listings.push(listing);
+            }
+        }
+
+        Ok(listings)
+    }
+
+    /// The inverse of `listings`: every binary they provide, sorted by name, along with the version that's linked to
+    /// in `.cargo/bin` and the ones that could replace it
+    pub fn binary_listing(&self, listings: &[PackageListing]) -> Vec<BinaryOwners> {
+        // Keyed by the actual file names, as the printable ones may have lost bytes that aren't valid UTF-8
+        let mut providers: BTreeMap<&OsStr, Vec<Provider>> = BTreeMap::new();
+        for package in listings {
+            for version in &package.versions {
+                for binary in &version.binaries {
+                    let name = binary.path.file_name().unwrap_or_default();
+                    providers.entry(name).or_default().push(Provider {
+                        package: package.name.clone(),
+                        version: version.version.clone(),
+                    });
+                }
+            }
+        }
+
+        providers
+            .into_iter()
+            .map(|(name, mut alternatives)| {
+                let active = self
+                    .link_owner(name)
+                    .map(|(package, version)| Provider { package, version });
+                alternatives.retain(|provider| Some(provider) != active.as_ref());
+                alternatives.sort();
+
+                BinaryOwners {
+                    name: name.to_string_lossy().into_owned(),
+                    active,
+                    alternatives,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Print every binary with the version that provides it, followed by the other versions that could
+pub fn print_binary_listing(binaries: &[BinaryOwners]) {
+    for binary in binaries {
+        match &binary.active {
+            Some(active) => println!("{} -> {active}", binary.name),
+            None => println!("{} (not linked)", binary.name),
+        }
+
+        if binary.alternatives.is_empty().not() {
+            let alternatives: Vec<_> = binary
+                .alternatives
+                .iter()
+                .map(Provider::to_string)
+                .collect();
+            let label = if binary.active.is_some() {
+                "also provided by"
+            } else {
+                "provided by"
+            };
+            println!("  {label}: {}", alternatives.join(", "));
+        }
+    }
+
+    let contested = binaries
+        .iter()
+        .filter(|binary| binary.is_contested())
+        .count();
+    println!(
+        "{} binar{}, {contested} provided by more than one package",
+        binaries.len(),
+        if binaries.len() == 1 { "y" } else { "ies" }
+    );
+}
+
+/// Only keep the versions that came from `kind` of source, along with the packages that still have versions left
+pub fn retain_source(listings: &mut Vec<PackageListing>, kind: SourceKind) {
+    for package in listings.iter_mut() {
+        package
+            .versions
+            .retain(|version| version.source_kind() == kind);
+    }
+    listings.retain(|package| package.versions.is_empty().not());
+}
+
+/// The default listing: every package and its versions, followed by a line counting them
+pub fn print_listing(listings: &[PackageListing], long: bool) {
+    let mut no_active_version = 0;
+    let mut broken = 0;
+
+    for package in listings {
+        let annotation = match &package.active_state {
+            ActiveState::Active { .. } => String::new(),
+            ActiveState::Broken { version } => {
+                no_active_version += 1;
+                broken += 1;
+                format!(" (broken links to {version})")
+            }
+            ActiveState::Inactive => {
+                no_active_version += 1;
+                String::new()
+            }
+        };
+        println!("{}:{annotation}", package.name);
+
+        // Variants are listed under their version, two columns further in
+        let indent = |version: &str| {
+            if split_variant(version).1.is_some() {
+                4
+            } else {
+                2
+            }
+        };
+        let width = package
+            .versions
+            .iter()
+            .map(|version| indent(&version.version) + version.version.len())
+            .max()
+            .unwrap_or_default();
+
+        let mut previous_version = None;
+        for version in &package.versions {
+            let (bare_version, variant) = split_variant(&version.version);
+            if variant.is_some() && previous_version != Some(bare_version) {
+                println!("  - {bare_version} (only variants)");
+            }
+            previous_version = Some(bare_version);
+
+            let entry = format!(
+                "{:indent$}- {}",
+                "",
+                version.version,
+                indent = indent(&version.version)
+            );
+            let marker = version.marker([(version.locked, "locked"), (version.shared, "shared")]);
+            if long {
+                println!(
+                    "{entry:width$}  {}{marker}",
+                    version.source_tag(),
+                    width = width + 2
+                );
+            } else {
+                println!("{entry}{marker}");
+            }
+        }
+    }
+
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let mut footer = format!(
+        "{} package{}, {no_active_version} with no active version",
+        listings.len(),
+        plural(listings.len())
+    );
+    if broken > 0 {
+        footer.push_str(&format!(" ({broken} with broken links)"));
+    }
+    println!("{footer}");
+}
+
+/// The directory of every version, each followed by its binaries, one absolute path per line for scripts to go by
+pub fn print_paths(listings: &[PackageListing]) {
+    let absolute = |path: &Path| path::absolute(path).unwrap_or_else(|_| path.to_owned());
+    for package in listings {
+        for version in &package.versions {
+            println!("{}", absolute(&version.path).display());
+            for binary in &version.binaries {
+                println!("{}", absolute(&binary.path).display());
+            }
+        }
+    }
+}
+
+/// Render packages as a tree of package → versions → binaries
+pub fn print_tree(listings: &[PackageListing]) {
+    for package in listings {
+        println!("{}", package.name);
+
+        for (index, version) in package.versions.iter().enumerate() {
+            let last_version = index + 1 == package.versions.len();
+            let (branch, indent) = if last_version {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            let marker = version.marker([(version.active, "active"), (version.shared, "shared")]);
+            println!("{branch}{}{marker}", version.version);
+
+            for (index, binary) in version.binaries.iter().enumerate() {
+                let branch = if index + 1 == version.binaries.len() {
+                    "└── "
+                } else {
+                    "├── "
+                };
+                let annotation = match &binary.link {
+                    LinkState::Linked => " (linked)".to_owned(),
+                    LinkState::Broken => " (broken link)".to_owned(),
+                    LinkState::Missing => " (not linked)".to_owned(),
+                    LinkState::Overridden { by } => format!(" (overridden by {})", by.display()),
+                    LinkState::Inactive => String::new(),
+                };
+                println!("{indent}{branch}{}{annotation}", binary.name);
+            }
+        }
+    }
+}
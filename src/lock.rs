@@ -0,0 +1,264 @@
+//! Locks keeping cargo-switch processes running side by side from stepping on each other's toes.
+//!
+//! Every package has a lock of its own, `registry/<package>/.lock`, held while one of its versions is installed,
+//! switched to or uninstalled, so that different packages can be worked on at the same time. The links in
+//! `.cargo/bin` and the state file are shared by every package, so they're guarded by a registry-wide lock that is
+//! only held for the moment it takes to update them.
+//!
+//! These are advisory locks on the lock files, which the OS releases when their holder goes away, so a process that
+//! crashed never leaves a package locked: its lock file is simply taken over. The files only hold the pid of their
+//! last holder, to tell who's in the way.
+//!
+//! Interruptions never happen while the links lock is held, see [`interrupt::defer`].
+
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs::TryLockError;
+use std::io::Write;
+use std::ops::Not;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::events;
+use crate::events::ErrorKind;
+use crate::interrupt;
+use crate::spec;
+use crate::Switcher;
+
+/// Name of the lock file inside of every package's directory
+pub const LOCK_FILE_NAME: &str = ".lock";
+/// Name of the lock file, inside the registry, guarding `.cargo/bin` and the state file
+pub const LINKS_LOCK_FILE_NAME: &str = ".links.lock";
+
+/// How long to wait for another process to be done with a package. Builds can take a while.
+const PACKAGE_LOCK_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// How long to wait for the links lock, which is never held for long
+const LINKS_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A held lock, released when dropped
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+    /// Directory to remove along with the lock file on release, if nothing else is left in it
+    cleanup: Option<PathBuf>,
+    _file: File,
+    /// Makes sure the lock is released even if interrupted
+    _release_on_interrupt: Option<interrupt::Registration>,
+    /// Keeps interruptions from leaving the links half updated
+    _uninterruptible: Option<interrupt::Deferred>,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if let Some(directory) = &self.cleanup {
+            release(&self.path, directory);
+        }
+    }
+}
+
+/// Remove the lock file at `path` along with `directory`, if nothing else is left in it. A package whose last
+/// version went away only holds its lock file, and shouldn't linger as an empty package.
+pub(crate) fn release(path: &Path, directory: &Path) {
+    let only_lock_left = fs::read_dir(directory).is_ok_and(|entries| {
+        entries
+            .filter_map(Result::ok)
+            .all(|entry| entry.file_name() == LOCK_FILE_NAME)
+    });
+    if only_lock_left {
+        let _ = fs::remove_file(path);
+        // Only succeeds if the directory is empty
+        let _ = fs::remove_dir(directory);
+    }
+}
+
+/// The pid recorded in the lock file at `path`, if any
+fn holder(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+/// Try to lock the file at `path` until `timeout` runs out, creating it if needed. `None` if it's still busy by then.
+/// `on_wait` is called once, with the pid of the holder, if the lock isn't immediately available.
+fn acquire(
+    path: &Path,
+    timeout: Duration,
+    mut on_wait: impl FnMut(Option<u32>),
+) -> Result<Option<File>> {
+    let deadline = Instant::now() + timeout;
+    let mut waited = false;
+
+    loop {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("Failed to open {}", path.display()))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                if waited.not() {
+                    on_wait(holder(path));
+                    waited = true;
+                }
+                if Instant::now() >= deadline {
+                    return Ok(None);
+                }
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(TryLockError::Error(err)) => {
+                return Err(err).with_context(|| format!("Failed to lock {}", path.display()))
+            }
+        }
+
+        // The previous holder may have removed the lock file after we opened it, leaving us with a lock on a file
+        // that nobody else will ever open
+        let locked = file.metadata()?;
+        let same_file = fs::metadata(path)
+            .is_ok_and(|current| current.ino() == locked.ino() && current.dev() == locked.dev());
+        if same_file.not() {
+            continue;
+        }
+
+        file.set_len(0)?;
+        write!(file, "{}", process::id())?;
+
+        return Ok(Some(file));
+    }
+}
+
+/// Who holds the lock at `path`, for error messages
+fn holder_description(path: &Path) -> String {
+    match holder(path) {
+        Some(pid) => format!("cargo-switch (pid {pid})"),
+        None => "another cargo-switch".to_owned(),
+    }
+}
+
+impl Switcher {
+    /// Lock `package` for as long as the returned lock lives, waiting for whoever is already working on it
+    pub fn lock_package(&self, package: &str) -> Result<Lock> {
+        spec::validate_name(package)?;
+
+        let package_path = self.registry.join(package);
+        fs::create_dir_all(&package_path)
+            .with_context(|| format!("Failed to create {}", package_path.display()))?;
+        let path = package_path.join(LOCK_FILE_NAME);
+
+        let file = acquire(&path, PACKAGE_LOCK_TIMEOUT, |pid| match pid {
+            Some(pid) => {
+                eprintln!("Waiting for cargo-switch (pid {pid}) to be done with {package}")
+            }
+            None => eprintln!("Waiting for another cargo-switch to be done with {package}"),
+        })?;
+        let Some(file) = file else {
+            return Err(events::classify(
+                anyhow!(
+                    "{package} is busy: {} is working on it. If it's stuck, stop it and try again",
+                    holder_description(&path)
+                ),
+                ErrorKind::Busy,
+            ));
+        };
+
+        Ok(Lock {
+            _release_on_interrupt: Some(interrupt::release_on_interrupt(&path, &package_path)),
+            _uninterruptible: None,
+            path,
+            cleanup: Some(package_path),
+            _file: file,
+        })
+    }
+
+    /// Lock the links in `.cargo/bin` and the state file for as long as the returned lock lives
+    pub fn lock_links(&self) -> Result<Lock> {
+        let path = self.registry.join(LINKS_LOCK_FILE_NAME);
+
+        let Some(file) = acquire(&path, LINKS_LOCK_TIMEOUT, |_| {})? else {
+            return Err(events::classify(
+                anyhow!(
+                    "{} is busy: {} is updating it. If it's stuck, stop it and try again",
+                    self.cargo_bin.display(),
+                    holder_description(&path)
+                ),
+                ErrorKind::Busy,
+            ));
+        };
+
+        Ok(Lock {
+            path,
+            cleanup: None,
+            _file: file,
+            _release_on_interrupt: None,
+            _uninterruptible: Some(interrupt::defer()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::ops::Not;
+    use std::process;
+    use std::time::Duration;
+
+    use super::acquire;
+    use super::holder;
+    use crate::config::Config;
+    use crate::Switcher;
+
+    #[test]
+    fn locks_packages_separately() {
+        let root = tempfile::tempdir().unwrap();
+        let switcher = Switcher::builder()
+            .cargo_bin(root.path())
+            .config(Config::default())
+            .build()
+            .unwrap();
+
+        let ripgrep = switcher.lock_package("ripgrep").unwrap();
+        let lock_path = switcher
+            .registry
+            .join("ripgrep")
+            .join(super::LOCK_FILE_NAME);
+        assert_eq!(holder(&lock_path), Some(process::id()));
+
+        // Locks are held per open file, so a second one can't be taken even from the same process
+        let mut waited_for = None;
+        let busy = acquire(&lock_path, Duration::ZERO, |pid| waited_for = pid).unwrap();
+        assert!(busy.is_none());
+        assert_eq!(waited_for, Some(process::id()));
+
+        // Other packages aren't held up
+        let fd = switcher.lock_package("fd-find").unwrap();
+        let links = switcher.lock_links().unwrap();
+        drop(links);
+        drop(fd);
+
+        // Nothing was installed, so nothing is left behind
+        drop(ripgrep);
+        assert!(switcher.registry.join("ripgrep").exists().not());
+        assert!(switcher.registry.join("fd-find").exists().not());
+
+        // A lock file left behind by a process that's gone is taken over
+        fs::create_dir_all(switcher.registry.join("ripgrep").join("14.1.0")).unwrap();
+        fs::write(&lock_path, "4194304").unwrap();
+        let ripgrep = switcher.lock_package("ripgrep").unwrap();
+        assert_eq!(holder(&lock_path), Some(process::id()));
+        drop(ripgrep);
+        assert!(lock_path.exists());
+    }
+}
@@ -0,0 +1,363 @@
+//! The `Cargo.lock` of every build, kept in the version's metadata directory, so that `deps` can tell which versions
+//! of which crates went into its binaries, and `rebuild --locked-from-original` can build them again from the very
+//! same ones.
+//!
+//! Unless given `--locked`, `cargo install` resolves dependencies afresh and leaves nothing of what it resolved
+//! behind. So the sources are fetched and copied into the directory the build goes into, resolved there, and built
+//! from that copy with `--locked`: the `Cargo.lock` kept is the very one the build used.
+
+use std::fs;
+use std::ops::Not;
+use std::os::unix;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::metadata::LockfileStatus;
+use crate::metadata::VersionMetadata;
+use crate::spec::split_label;
+use crate::table::print_table;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// Where sources are copied to be resolved and built, inside the directory a build goes into
+pub(crate) const STAGING_DIRECTORY_NAME: &str = ".lockfile";
+
+/// The package of the manifest that has cargo fetch the sources of a crate, see [`fetching_manifest`]
+const FETCHING_PACKAGE_NAME: &str = "cargo-switch-fetch";
+
+/// What crates.io is called in lockfiles, through either of its indexes
+pub(crate) const CRATES_IO_SOURCES: [&str; 2] = [
+    "registry+https://github.com/rust-lang/crates.io-index",
+    "sparse+https://index.crates.io/",
+];
+
+/// A package in a `Cargo.lock`, as far as we care about it
+#[derive(Debug, Clone, Deserialize)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// Missing for the crate itself and its path dependencies
+    pub source: Option<String>,
+}
+
+impl LockedPackage {
+    /// Where the package came from, as `deps` shows it
+    pub fn source_tag(&self) -> String {
+        match self.source.as_deref() {
+            None => "path".to_owned(),
+            Some(source) if CRATES_IO_SOURCES.contains(&source) => "crates.io".to_owned(),
+            // As in `git+https://github.com/owner/repository?branch=main#commit`
+            Some(source) if source.starts_with("git+") => {
+                let url = &source["git+".len()..];
+                let url = url.split(['?', '#']).next().unwrap_or(url);
+                match source.rsplit_once('#') {
+                    Some((_, rev)) => format!("git {url}@{}", rev.get(..7).unwrap_or(rev)),
+                    None => format!("git {url}"),
+                }
+            }
+            Some(source) => source
+                .split_once('+')
+                .map_or(source, |(_, url)| url)
+                .to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub package: Vec<LockedPackage>,
+}
+
+impl Lockfile {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+
+        toml::from_str(&contents).with_context(|| format!("{} is corrupt", path.display()))
+    }
+}
+
+/// Copy the sources of a crate from `from` to `to`, leaving out what builds and git left next to them
+fn copy_sources(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to).with_context(|| format!("Failed to create {}", to.display()))?;
+
+    for maybe_entry in fs::read_dir(from)? {
+        let entry = maybe_entry?;
+        let (source, destination) = (entry.path(), to.join(entry.file_name()));
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if matches!(entry.file_name().to_str(), Some("target" | ".git")) {
+                continue;
+            }
+            copy_sources(&source, &destination)?;
+        } else if file_type.is_symlink() {
+            unix::fs::symlink(fs::read_link(&source)?, &destination)?;
+        } else {
+            fs::copy(&source, &destination)
+                .with_context(|| format!("Failed to copy {}", source.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Make `sources` into a copy of the crate at `from`, to be built with `lockfile` rather than its own
+pub(crate) fn prepare_locked_sources(from: &Path, lockfile: &Path, sources: &Path) -> Result<()> {
+    let _ = fs::remove_dir_all(sources);
+    copy_sources(from, sources)?;
+    fs::copy(lockfile, sources.join("Cargo.lock"))
+        .with_context(|| format!("Failed to copy {}", lockfile.display()))?;
+
+    Ok(())
+}
+
+/// Where cargo left the sources it built `spec` from, under `cargo_home`: the extracted crate for builds from
+/// crates.io, the checkout of `git` at `rev` for builds from git, `path` itself for builds from a path
+pub(crate) fn built_sources(
+    cargo_home: &Path,
+    spec: &str,
+    path: Option<&Path>,
+    git: Option<&str>,
+    rev: Option<&str>,
+) -> Result<PathBuf> {
+    if let Some(path) = path {
+        return Ok(path.to_owned());
+    }
+
+    // Cargo keeps one directory per index or per repository, named after it along with a hash of its URL
+    let (parent, name) = match git {
+        Some(_) => {
+            let Some(rev) = rev else {
+                bail!("which commit was built isn't known");
+            };
+            (cargo_home.join("git/checkouts"), rev)
+        }
+        None => (cargo_home.join("registry/src"), spec),
+    };
+    let matches = |candidate: &str| match git {
+        // Checkouts are named after the start of the commit they're at
+        Some(_) => candidate.len() >= 7 && name.starts_with(candidate),
+        None => spec
+            .split_once('@')
+            .is_some_and(|(crate_name, version)| candidate == format!("{crate_name}-{version}")),
+    };
+
+    let mut found = Vec::new();
+    if let Ok(entries) = fs::read_dir(&parent) {
+        for maybe_entry in entries {
+            for candidate in fs::read_dir(maybe_entry?.path())? {
+                let candidate = candidate?;
+                if candidate.file_name().to_str().is_some_and(matches) {
+                    let modified = candidate.metadata()?.modified()?;
+                    found.push((modified, candidate.path()));
+                }
+            }
+        }
+    }
+
+    // The one cargo touched last is the one it just built
+    found
+        .into_iter()
+        .max()
+        .map(|(_, path)| path)
+        .with_context(|| format!("cargo left no sources of {spec} in {}", parent.display()))
+}
+
+/// A manifest depending on nothing but `crate_name`, at `version` from crates.io or at commit `rev` of `git`, which
+/// has cargo fetch and extract its sources where [`built_sources`] finds them. Cargo leaves binary-only crates out of
+/// what it resolves, but fetches them all the same.
+pub(crate) fn fetching_manifest(
+    crate_name: &str,
+    version: Option<&str>,
+    git: Option<&str>,
+    rev: Option<&str>,
+) -> String {
+    let quote = |value: &str| toml::Value::String(value.to_owned()).to_string();
+    let dependency = match (git, rev) {
+        (Some(url), Some(rev)) => format!("{{ git = {}, rev = {} }}", quote(url), quote(rev)),
+        (Some(url), None) => format!("{{ git = {} }}", quote(url)),
+        (None, _) => quote(&format!("={}", version.unwrap_or("*"))),
+    };
+
+    format!(
+        "[package]\n\
+         name = \"{FETCHING_PACKAGE_NAME}\"\n\
+         version = \"0.0.0\"\n\
+         edition = \"2021\"\n\
+         \n\
+         [lib]\n\
+         path = \"lib.rs\"\n\
+         \n\
+         [workspace]\n\
+         \n\
+         [dependencies]\n\
+         {} = {dependency}\n",
+        quote(crate_name)
+    )
+}
+
+/// The directory of the manifest of `crate_name` somewhere in `sources`, as in a git repository holding several
+/// crates
+pub(crate) fn find_crate(sources: &Path, crate_name: &str) -> Result<PathBuf> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        package: Option<Package>,
+    }
+    #[derive(Deserialize)]
+    struct Package {
+        name: String,
+    }
+
+    let manifest = sources.join("Cargo.toml");
+    let named = fs::read_to_string(&manifest)
+        .ok()
+        .and_then(|contents| toml::from_str::<Manifest>(&contents).ok())
+        .and_then(|manifest| manifest.package)
+        .is_some_and(|package| package.name == crate_name);
+    if named {
+        return Ok(sources.to_owned());
+    }
+
+    for maybe_entry in fs::read_dir(sources)? {
+        let entry = maybe_entry?;
+        if entry.file_type()?.is_dir().not()
+            || matches!(entry.file_name().to_str(), Some("target" | ".git"))
+        {
+            continue;
+        }
+        if let Ok(found) = find_crate(&entry.path(), crate_name) {
+            return Ok(found);
+        }
+    }
+
+    bail!(
+        "there's no crate named {crate_name} in {}",
+        sources.display()
+    )
+}
+
+/// Make `staging` into a copy of the workspace at `workspace`, to be resolved and built there. Its own `Cargo.lock`
+/// is only kept if `keep_lockfile` is set.
+pub(crate) fn stage_sources(workspace: &Path, staging: &Path, keep_lockfile: bool) -> Result<()> {
+    let _ = fs::remove_dir_all(staging);
+    copy_sources(workspace, staging)?;
+    // `cargo install` never reads the configuration sources come with, which the copy would otherwise be built with
+    let _ = fs::remove_dir_all(staging.join(".cargo"));
+    if keep_lockfile.not() {
+        let _ = fs::remove_file(staging.join("Cargo.lock"));
+    }
+
+    Ok(())
+}
+
+impl Switcher {
+    /// The `Cargo.lock` `package@version` was built from, once made sure it was kept
+    pub(crate) fn kept_lockfile(&self, package: &str, version: &str) -> Result<PathBuf> {
+        let version_path = self.version_path(package, version);
+        let metadata = VersionMetadata::load(&version_path)?.unwrap_or_default();
+        let path = VersionMetadata::lockfile_path(&version_path);
+
+        match metadata.lockfile {
+            Some(LockfileStatus::Kept) if path.exists() => Ok(path),
+            Some(LockfileStatus::Missing { reason }) => {
+                bail!("The Cargo.lock of {package}@{version} couldn't be kept when it was built: {reason}")
+            }
+            _ => bail!(
+                "No Cargo.lock was kept for {package}@{version}, which was most likely installed before lockfiles \
+                 were. Rebuild it to keep one"
+            ),
+        }
+    }
+
+    /// Print the dependencies `package@version`, or the active version of `package`, was built with, only the ones
+    /// whose name contains `filter` if given
+    pub fn deps(&self, package: &str, version: Option<&str>, filter: Option<&str>) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        let version = match version {
+            Some(version) => self.pick_variant(package, version)?,
+            None => match self.linked_version(package)? {
+                Some(version) => version,
+                None if self.package_exists(package) => {
+                    bail!("{package} has no active version, name the version as in {package}@1.0.0")
+                }
+                None => return Err(self.not_installed(package, None)),
+            },
+        };
+        let lockfile = Lockfile::load(&self.kept_lockfile(package, &version)?)?;
+
+        // The crate itself is in its own lockfile
+        let (crate_name, _) = split_label(package);
+        let (crate_version, _) = split_variant(&version);
+        let rows: Vec<_> = lockfile
+            .package
+            .iter()
+            .filter(|locked| {
+                (locked.source.is_none()
+                    && locked.name == crate_name
+                    && locked.version == crate_version)
+                    .not()
+            })
+            .filter(|locked| filter.is_none_or(|filter| locked.name.contains(filter)))
+            .map(|locked| {
+                [
+                    locked.name.clone(),
+                    locked.version.clone(),
+                    locked.source_tag(),
+                ]
+            })
+            .collect();
+
+        match filter {
+            Some(filter) if rows.is_empty() => {
+                println!("{package}@{version} has no dependency matching `{filter}`");
+            }
+            _ => print_table(["NAME", "VERSION", "SOURCE"], &rows),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LockedPackage;
+
+    #[test]
+    fn tags_locked_sources() {
+        let locked = |source: Option<&str>| LockedPackage {
+            name: "serde".to_owned(),
+            version: "1.0.0".to_owned(),
+            source: source.map(str::to_owned),
+        };
+
+        assert_eq!(locked(None).source_tag(), "path");
+        assert_eq!(
+            locked(Some(
+                "registry+https://github.com/rust-lang/crates.io-index"
+            ))
+            .source_tag(),
+            "crates.io"
+        );
+        assert_eq!(
+            locked(Some("sparse+https://index.crates.io/")).source_tag(),
+            "crates.io"
+        );
+        assert_eq!(
+            locked(Some(
+                "git+https://github.com/serde-rs/serde?branch=master#abc1234def5678"
+            ))
+            .source_tag(),
+            "git https://github.com/serde-rs/serde@abc1234"
+        );
+        assert_eq!(
+            locked(Some("sparse+https://registry.example.com/index/")).source_tag(),
+            "https://registry.example.com/index/"
+        );
+    }
+}
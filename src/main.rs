@@ -1,212 +1,1421 @@
-#![allow(clippy::manual_flatten)]
-
 use std::env;
-use std::fs;
-use std::fs::read_dir;
-use std::io;
-use std::io::BufRead;
+use std::ffi::OsString;
 use std::ops::Not;
-use std::os::unix;
-use std::path::Path;
 use std::path::PathBuf;
-use std::process::Command;
-use std::process::Stdio;
+use std::process;
+use std::time::Duration;
 
-use anyhow::ensure;
-use anyhow::Context;
+use anyhow::bail;
 use anyhow::Result;
-use clap::{Parser, Subcommand};
+use cargo_switch::check::DEFAULT_CHECK_TIMEOUT;
+use cargo_switch::copy::CopyOptions;
+use cargo_switch::direnv;
+use cargo_switch::events::error_kind;
+use cargo_switch::events::ErrorKind;
+use cargo_switch::events::Event;
+use cargo_switch::events::EventSink;
+use cargo_switch::events::JsonLines;
+use cargo_switch::events::MessageFormat;
+use cargo_switch::events::TIMED_OUT_EXIT_CODE;
+use cargo_switch::format::Record;
+use cargo_switch::format::Template;
+use cargo_switch::install::InstallOptions;
+use cargo_switch::interrupt;
+use cargo_switch::link_style::LinkStyle;
+use cargo_switch::listing;
+use cargo_switch::metadata::SourceKind;
+use cargo_switch::prompt::DEFAULT_PROMPT_FORMAT;
+use cargo_switch::run;
+use cargo_switch::run::Missing;
+use cargo_switch::spec;
+use cargo_switch::summary::FailureMode;
+use cargo_switch::test_matrix::VersionRange;
+use cargo_switch::variant::variant_directory;
+use cargo_switch::Switcher;
+use clap::{Args, Parser, Subcommand};
 
 #[derive(Parser)]
 #[command(name = "cargo-switch")]
 #[command(about = "Manage multiple versions of Cargo binaries", long_about = None)]
 struct Cli {
+    /// The versions to switch to, in order. With several of them, all are checked before any is switched to, and the
+    /// ones switched to already are switched back should a later one fail
     #[arg(value_name = "PACKAGE@VERSION", required = false)]
-    package_version: Option<String>,
+    package_versions: Vec<OsString>,
+
+    /// The version to switch to, for a PACKAGE given without one, as in `cargo switch ripgrep --version 14.1.0`
+    #[arg(long, value_name = "VERSION", requires = "package_versions")]
+    version: Option<String>,
+
+    /// Install PACKAGE@VERSION first if it isn't installed yet. Without it, you'll be asked whether to install it when
+    /// running in a terminal
+    #[arg(long, requires = "package_versions")]
+    install: bool,
+
+    /// Switch to the debug build of PACKAGE@VERSION, as installed with `install --debug`
+    #[arg(long, requires = "package_versions", conflicts_with = "profile")]
+    debug: bool,
+
+    /// Switch to the build of PACKAGE@VERSION made with this cargo profile, as installed with `install --profile`
+    #[arg(long, value_name = "NAME", requires = "package_versions")]
+    profile: Option<String>,
+
+    /// Also link the binaries of every installed version of the package under suffixed names, as in `rg-13` and
+    /// `rg-14`, from now on
+    #[arg(long, requires = "package_versions")]
+    keep_suffixed: bool,
+
+    /// Whether new links point into the registry through absolute or relative paths, overriding `link-style`
+    #[arg(long, global = true, value_name = "STYLE")]
+    link_style: Option<LinkStyle>,
+
+    /// The directory to link binaries into, overriding `cargo-bin` and the one cargo installs binaries into
+    #[arg(long, global = true, value_name = "DIR")]
+    cargo_bin: Option<PathBuf>,
+
+    /// The directory to link binaries into instead of the cargo bin directory, as in `~/.local/bin`, overriding
+    /// `link-dir`. Created if it doesn't exist.
+    #[arg(long, global = true, value_name = "DIR")]
+    link_dir: Option<PathBuf>,
+
+    /// Work on the shared registry set through `shared-registry` rather than your own, installing into it and
+    /// uninstalling from it, without switching to anything. Only for those who may write to it.
+    #[arg(long, global = true)]
+    system: bool,
+
+    /// Let packages replace the toolchain's binaries in .cargo/bin, such as `cargo`, `rustc` or `rustup`
+    #[arg(long, global = true)]
+    allow_overwrite_toolchain: bool,
+
+    /// Switch to freshly built versions without running their `smoke-test`, or to versions quarantined for failing it
+    #[arg(long, global = true)]
+    ignore_smoke_test: bool,
+
+    /// `json` to emit events as JSON lines on stdout while installing, linking or removing versions, everything else
+    /// going to stderr
+    #[arg(long, global = true, value_name = "FORMAT", default_value = "human")]
+    message_format: MessageFormat,
+
+    /// Print extra details about what's going on
+    #[arg(long, short, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only show cargo's output when a build fails
+    #[arg(long, short, global = true)]
+    quiet: bool,
+
+    /// Ask crates.io again rather than trusting what it said recently
+    #[arg(long, global = true)]
+    refresh: bool,
+
+    /// Let the registry grow past `max-size` rather than evicting old versions after installs
+    #[arg(long, global = true)]
+    no_evict: bool,
+
+    /// Stop at the first failure when dealing with many packages at once, overriding `failure-mode`
+    #[arg(long, global = true, conflicts_with = "keep_going")]
+    fail_fast: bool,
+
+    /// Carry on past failures when dealing with many packages at once, overriding `failure-mode`
+    #[arg(long, global = true)]
+    keep_going: bool,
+
+    /// Let pre-releases, as in 2.0.0-beta.1, count as the newest version of a package, be it on crates.io when
+    /// updating or among the installed versions when none was asked for
+    #[arg(long, global = true)]
+    pre: bool,
+
+    /// Reach crates.io and other servers directly, ignoring the proxy cargo's configuration or the environment
+    /// name. Builds still go through whatever proxy cargo is configured with.
+    #[arg(long, global = true)]
+    no_proxy: bool,
 
     #[command(subcommand)]
     command: Option<Commands>,
 }
 
+// Only ever parsed once, so that `install` takes many more options than the others doesn't matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
     Install {
+        /// The packages to install. `-` reads more of them from stdin, one per line. Packages installed with --git
+        /// are given without a version, since they're named after the commit they're built from
+        #[arg(value_name = "PACKAGE@VERSION", required_unless_present = "from_file")]
+        packages: Vec<String>,
+        /// The version to install, for a PACKAGE given without one, as `cargo install` takes it
+        #[arg(long, value_name = "VERSION", conflicts_with_all = ["from_file", "git"])]
+        version: Option<String>,
+        /// Install the packages listed in a file, one per line. Blank lines and `#` comments are ignored
+        #[arg(long, value_name = "PATH")]
+        from_file: Option<PathBuf>,
+        /// How many times to retry the install if it fails because of the network
+        #[arg(long)]
+        retries: Option<u32>,
+        /// Build with the debug profile. The result is kept apart from the release build, as VERSION+debug
+        #[arg(long, conflicts_with = "profile")]
+        debug: bool,
+        /// Build with a custom cargo profile. The result is kept apart from other builds, as VERSION+NAME
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+        /// Build the crate in this directory instead of fetching it, registering it as PACKAGE@VERSION
+        #[arg(long, value_name = "DIR", conflicts_with = "from_file")]
+        path: Option<PathBuf>,
+        /// Cross-compile for another target triple. Such builds aren't switched to, see `copy-to --target`
+        #[arg(long, value_name = "TRIPLE")]
+        target: Option<String>,
+        /// Download a prebuilt .tar.gz, .tar.xz, .zip or bare binary instead of building, registering its executables
+        /// as PACKAGE@VERSION
+        #[arg(
+            long,
+            value_name = "URL",
+            requires = "sha256",
+            conflicts_with_all = ["from_file", "path", "debug", "profile", "target"]
+        )]
+        from_url: Option<String>,
+        /// The SHA-256 checksum the artifact downloaded by --from-url must have
+        #[arg(long, value_name = "HEX", requires = "from_url")]
+        sha256: Option<String>,
+        /// Build the package from a git repository, pinned to the commit its branch points to. Each commit is kept
+        /// apart from the others, as BRANCH.REV, and `update --git` moves on to newer ones
+        #[arg(
+            long,
+            value_name = "URL",
+            conflicts_with_all = ["from_file", "path", "from_url"]
+        )]
+        git: Option<String>,
+        /// The branch of the --git repository to build, its default branch if not given
+        #[arg(long, value_name = "NAME", requires = "git")]
+        branch: Option<String>,
+        /// The commit of the --git repository to build, rather than the head of a branch
+        #[arg(long, value_name = "HASH", requires = "git", conflicts_with = "branch")]
+        rev: Option<String>,
+        /// Keep the build apart from other builds of the same crate, e.g. from a fork of it, as PACKAGE#LABEL. Only
+        /// for --git and --path installs
+        #[arg(long, value_name = "NAME")]
+        label: Option<String>,
+        /// Cargo features to build with, comma or space separated. Builds with different features are kept apart, as
+        /// VERSION+FEATURES
+        #[arg(long, value_name = "FEATURES", conflicts_with = "from_url")]
+        features: Vec<String>,
+        /// Strip the installed binaries of their symbols, whatever the `strip` config key says
+        #[arg(long, conflicts_with = "no_strip")]
+        strip: bool,
+        /// Leave the installed binaries unstripped, whatever the `strip` config key says
+        #[arg(long)]
+        no_strip: bool,
+        /// Keep every old version around this time, whatever the `keep-versions` config key says
+        #[arg(long)]
+        no_auto_prune: bool,
+        /// Build from what `cargo switch vendor` downloaded beforehand, without the network
+        #[arg(long, conflicts_with_all = ["path", "git", "from_url"])]
+        offline: bool,
+        /// Build with this rustup toolchain, as in `stable` or `1.75`, rather than the active one
+        #[arg(long, value_name = "NAME", conflicts_with = "from_url")]
+        toolchain: Option<String>,
+        /// Build even if crates.io says the version needs a newer rustc than the toolchain has
+        #[arg(long)]
+        skip_msrv_check: bool,
+        /// Fail unless the .crate cargo built from has the checksum crates.io's index lists for it
+        #[arg(long, conflicts_with_all = ["path", "git", "from_url", "offline"])]
+        require_checksum: bool,
+        /// Build without the crate's default features, whatever the package's `default-features` config key says
+        #[arg(long, conflicts_with_all = ["from_url", "default_features"])]
+        no_default_features: bool,
+        /// Build with the crate's default features, whatever the package's `default-features` config key says
+        #[arg(long, conflicts_with = "from_url")]
+        default_features: bool,
+        /// Build with the Cargo.lock the crate was published with, whatever the package's `locked` config key says
+        #[arg(long, conflicts_with_all = ["from_url", "no_locked"])]
+        locked: bool,
+        /// Resolve dependencies afresh, whatever the package's `locked` config key says
+        #[arg(long, conflicts_with = "from_url")]
+        no_locked: bool,
+        /// Leave out the features and flags the package's section of the config file adds, for this run
+        #[arg(long)]
+        no_config_flags: bool,
+        /// Kill builds still running after this long, e.g. 30m, whatever the `build-timeout` config key says. Builds
+        /// that seemed stuck on the network are retried
+        #[arg(long, value_name = "DURATION")]
+        timeout: Option<humantime::Duration>,
+        /// Build even if the `binary-cache` has the same build, and don't push to it either
+        #[arg(long)]
+        no_binary_cache: bool,
+    },
+    /// Copy installed versions built from crates.io to the `binary-cache`, for others to install without building
+    /// them. Versions built while it's set are pushed as they're installed
+    Push {
+        #[arg(value_name = "PACKAGE@VERSION", required = true)]
+        packages: Vec<String>,
+    },
+    /// Download the sources of packages and of all their dependencies, so that `install --offline` can build them
+    /// later without the network
+    Vendor {
+        #[arg(value_name = "PACKAGE@VERSION", required = true)]
+        packages: Vec<String>,
+        /// How many times to retry downloads that fail because of the network
+        #[arg(long)]
+        retries: Option<u32>,
+    },
+    /// Register binaries built or downloaded some other way as a version of a package
+    AddBinary {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+        /// The binaries to copy into the registry
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Copy the binaries of an installed version into a directory, e.g. to bundle them in a container image
+    CopyTo {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+        #[arg(value_name = "DIR")]
+        destination: PathBuf,
+        /// Strip the copied binaries of their symbols
+        #[arg(long)]
+        strip: bool,
+        /// Copy the build cross-compiled for this target triple, as installed with `install --target`
+        #[arg(long, value_name = "TRIPLE")]
+        target: Option<String>,
+        /// Overwrite files that already exist in DIR
+        #[arg(long)]
+        force: bool,
+    },
+    /// Strip the binaries of an installed version of their symbols, to save space
+    Strip {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+    },
+    /// Archive the whole registry into a .tar.zst file
+    Backup {
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+    },
+    /// Replace the registry with the contents of an archive made by `backup`, relinking the versions that were active
+    Restore {
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+        /// Replace the registry even if it isn't empty
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove an installed version, along with its links if it's the active one. It goes to the trash, from which
+    /// `undelete` can bring it back
+    Uninstall {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+        /// The version to uninstall, for a PACKAGE given without one
+        #[arg(long, value_name = "VERSION")]
+        version: Option<String>,
+        /// Uninstall the version even if it's locked
+        #[arg(long)]
+        force: bool,
+    },
+    /// Install or switch to sets of packages defined under [groups] in the config file
+    Group {
+        #[command(subcommand)]
+        command: GroupCommand,
+    },
+    /// Restore the most recently trashed copy of an uninstalled or pruned version, along with the default, channels
+    /// and lock that pointed to it
+    Undelete {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+    },
+    /// Manage the uninstalled and pruned versions kept in the trash
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+    /// Remove the oldest versions of a package, or of every package, beyond a retention count. The active version,
+    /// the default one, the ones channels point to and the locked ones are never removed
+    Prune {
         #[arg(value_name = "PACKAGE")]
+        package: Option<String>,
+        /// How many versions to keep, overriding the `keep-versions` config key
+        #[arg(long, value_name = "COUNT")]
+        keep: Option<usize>,
+    },
+    /// Stop using cargo-switch: replace every link it made with a copy of the binary it points to, so that the tools
+    /// keep working, and remove the registry along with the state, the caches and the wrappers
+    Purge {
+        /// Remove the links rather than replacing them, leaving none of the binaries behind
+        #[arg(long)]
+        keep_nothing: bool,
+        /// Purge without asking first
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Install the newest release of a package from crates.io, or the newest commit of the branch it was installed
+    /// from with --git, and switch to it. The versions already installed are kept
+    Update {
+        #[arg(required_unless_present = "all")]
+        package: Option<String>,
+        /// Follow the branch the active git install, or else the latest one, was built from
+        #[arg(long)]
+        git: bool,
+        /// Update every installed package from wherever its active version came from, carrying on past failures
+        /// unless --fail-fast is given
+        #[arg(long, conflicts_with_all = ["package", "git"])]
+        all: bool,
+    },
+    /// Build an installed version again, the way it was first built, replacing the old build only once the new one
+    /// succeeded
+    Rebuild {
+        #[arg(value_name = "PACKAGE@VERSION", required_unless_present = "all")]
+        package: Option<String>,
+        /// Rebuild every installed version, carrying on past failures
+        #[arg(long, conflicts_with = "package")]
+        all: bool,
+        /// Build from the very dependencies the version was first built with, as its kept Cargo.lock says
+        #[arg(long)]
+        locked_from_original: bool,
+    },
+    List {
+        /// Only list the versions of this package
+        #[arg(value_name = "PACKAGE", conflicts_with = "inactive")]
+        package: Option<String>,
+        /// Show the binaries provided by each version
+        #[arg(long)]
+        tree: bool,
+        /// Print the listing, binaries included, as JSON
+        #[arg(long, conflicts_with = "tree")]
+        json: bool,
+        /// Print one line per version following a template, e.g. '{package}\t{version}\t{active}'.
+        /// Available placeholders: {package}, {version}, {active}, {binaries}, {size}, {installed}, {source} and
+        /// {path}
+        #[arg(long, conflicts_with_all = ["tree", "json"])]
+        format: Option<Template>,
+        /// Only show the packages that have no active version
+        #[arg(long)]
+        inactive: bool,
+        /// Tag every version with where it came from: crates.io, git@REV, path, url, binary or unknown
+        #[arg(long, conflicts_with_all = ["tree", "json", "format", "by_binary"])]
+        long: bool,
+        /// Only show the versions that came from this kind of source
+        #[arg(long, value_name = "KIND")]
+        source: Option<SourceKind>,
+        /// List binaries instead of packages, with the version each one runs and the others that provide it
+        #[arg(long, conflicts_with_all = ["tree", "format"])]
+        by_binary: bool,
+        /// Print the absolute path of every version, each followed by the paths of its binaries, one per line
+        #[arg(long, conflicts_with_all = ["tree", "json", "format", "long", "by_binary"])]
+        paths: bool,
+    },
+    /// Print the absolute path of an installed version, or of one of its binaries. A package without a version stands
+    /// for its only version
+    Path {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
         package: String,
+        /// Print the path of this binary of the version rather than of the version itself
+        #[arg(long, value_name = "NAME")]
+        bin: Option<String>,
+    },
+    /// Find the first installed version of a package that exhibits a regression
+    Bisect {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        /// A version known not to have the regression
+        #[arg(long)]
+        good: String,
+        /// A version known to have the regression
+        #[arg(long)]
+        bad: String,
+        /// Command used to test each version. Exit code 0 means good, 125 means skip and anything else means bad.
+        /// If omitted, a shell is spawned for each version and you'll be asked whether it was good or bad
+        #[arg(last = true)]
+        command: Vec<OsString>,
+    },
+    /// Run a command once against every installed version of a package, each time with that version's binaries first
+    /// in $PATH, and show how it did against each of them
+    TestMatrix {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        /// Only the versions in this range, both bounds included, as in `0.9.50..0.9.66` or `0.9.50..`
+        #[arg(long, value_name = "RANGE", default_value = "..")]
+        versions: VersionRange,
+        /// How many versions to run the command against at once
+        #[arg(long, short, default_value_t = 1)]
+        jobs: usize,
+        /// Print the results as JSON, along with what the command printed
+        #[arg(long)]
+        json: bool,
+        #[arg(last = true, required = true)]
+        command: Vec<OsString>,
+    },
+    /// Show, set or unset the version used when nothing else picks one
+    Default {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// Remove the default version, falling back to the newest installed version
+        #[arg(long)]
+        unset: bool,
+    },
+    /// Lock a version so that prune, retention policies and eviction never remove it, and uninstall only does when
+    /// forced to
+    Lock {
+        #[arg(value_name = "PACKAGE@VERSION", required_unless_present = "list")]
+        package: Option<String>,
+        /// List the locked versions instead
+        #[arg(long, conflicts_with = "package")]
+        list: bool,
+    },
+    /// Unlock a version, letting cleanups remove it again
+    Unlock {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+    },
+    /// Point named channels of a package, like `testing` or `stable`, to installed versions. `PACKAGE@CHANNEL` can
+    /// then be switched to or run like any version
+    Channel {
+        #[command(subcommand)]
+        command: ChannelCommand,
+    },
+    /// Point the channel TO of a package to the version its channel FROM points to
+    Promote {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        #[arg(value_name = "FROM")]
+        from: String,
+        #[arg(value_name = "TO")]
+        to: String,
+    },
+    /// Show which version of each package is used, and why
+    Current {
+        #[arg(value_name = "PACKAGE")]
+        package: Option<String>,
+        /// Print the current versions following a template, see `list --format`
+        #[arg(long)]
+        format: Option<Template>,
+    },
+    /// Show what was recorded about the installed versions of a package
+    Info {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// Also ask crates.io about the package: its description, links, license, latest release and downloads. Shown
+        /// anyway when crates.io was asked recently
+        #[arg(long)]
+        remote: bool,
+    },
+    /// Show what cargo-switch did, from installs to migrations, along with the commands that failed, newest last
+    Log {
+        /// Only show what concerns this package
+        #[arg(long, value_name = "NAME")]
+        package: Option<String>,
+        /// How many of the latest entries to show
+        #[arg(long, short = 'n', value_name = "COUNT", default_value_t = 20)]
+        limit: usize,
+        /// Keep showing entries as they're added, until interrupted
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Report managed binaries that are shadowed by executables coming before .cargo/bin in $PATH
+    ShadowCheck {
+        /// Ask every binary involved for its version
+        #[arg(long)]
+        probe: bool,
+    },
+    /// Run the primary binary of every package with an active version, or only of PACKAGE, with `--version`, and
+    /// make sure it reports the active version. Tools that don't support `--version` can be given other arguments
+    /// through the `check-args` config key
+    Check {
+        #[arg(value_name = "PACKAGE")]
+        package: Option<String>,
+        /// How many seconds each binary gets to answer
+        #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_CHECK_TIMEOUT.as_secs())]
+        timeout: u64,
+        /// Also flag versions built with a rustc more releases behind the default toolchain's than the
+        /// `max-toolchain-drift` config key allows, 4 by default
+        #[arg(long)]
+        toolchain_drift: bool,
+        /// Run the `smoke-test` of every package that has one, or only of PACKAGE, instead: against the active
+        /// version and every quarantined one, lifting the quarantine of those that pass
+        #[arg(long)]
+        smoke_test: bool,
+    },
+    /// Get cargo-switch ready for a first run: check for cargo, create the link directory and the registry, and make
+    /// sure the link directory is in $PATH
+    Setup {
+        /// Add the link directory to $PATH in the rc file of the shell in $SHELL, rather than printing the line to add
+        #[arg(long)]
+        modify_shell_rc: bool,
+    },
+    /// Look into the configuration cargo-switch goes by
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// Check the registry and the links in .cargo/bin for problems
+    Doctor {
+        /// Ask shadowed binaries for their version
+        #[arg(long)]
+        probe: bool,
+        /// Rewrite the existing links so that they point into the registry through absolute or relative paths
+        #[arg(long, value_name = "STYLE")]
+        convert_links: Option<LinkStyle>,
+        /// Merge packages installed under spellings that only differ in `-` and `_` without asking
+        #[arg(long)]
+        merge_duplicates: bool,
+        /// Remove the links left in directories binaries were linked into before, such as an earlier --link-dir,
+        /// without asking
+        #[arg(long)]
+        remove_stale_links: bool,
+        /// Also flag versions built with a rustc more releases behind the default toolchain's than the
+        /// `max-toolchain-drift` config key allows, 4 by default
+        #[arg(long)]
+        toolchain_drift: bool,
+    },
+    /// Explain which version of a package, or of the package providing a binary, gets used here and why, and whether
+    /// running it by name gets to that version
+    Why {
+        #[arg(value_name = "PACKAGE|BINARY")]
+        name: String,
+    },
+    /// Show which package and version provide a binary
+    Which {
+        #[arg(value_name = "BINARY")]
+        binary: OsString,
+        /// Print the owning version following a template, see `list --format`
+        #[arg(long)]
+        format: Option<Template>,
+    },
+    /// Run a command with the binaries of the given versions first in $PATH, without switching to any of them
+    Exec {
+        /// A version whose binaries should be used, can be repeated
+        #[arg(long = "with", value_name = "PACKAGE@VERSION", required = true)]
+        with: Vec<String>,
+        /// Install the versions that aren't installed yet
+        #[arg(long)]
+        install: bool,
+        #[arg(last = true, required = true)]
+        command: Vec<OsString>,
+    },
+    /// Spawn $SHELL with the binaries of the given versions first in $PATH, without switching to any of them
+    Shell {
+        #[arg(value_name = "PACKAGE@VERSION", required = true)]
+        packages: Vec<String>,
+    },
+    /// Pin versions for the current project in its .cargo-switch.toml, created here if there's none
+    Pin {
+        /// A version to pin, or a package name to pin its active version
+        #[arg(value_name = "PACKAGE[@VERSION]", required = true)]
+        packages: Vec<String>,
+        /// Write the pins to the project's .tool-versions, as read by asdf and mise, instead
+        #[arg(long)]
+        tool_versions: bool,
+    },
+    /// Install the tools the current workspace declares under [workspace.metadata.bin] or
+    /// [workspace.metadata.cargo-switch] in its Cargo.toml, and pin them in .cargo-switch.toml
+    ProjectInstall,
+    /// Print a snippet for the project's .envrc that puts the versions it pins first in $PATH whenever direnv loads
+    /// it, linking them into .direnv/cargo-switch/bin
+    Direnv {
+        /// Append the snippet to the .envrc next to .cargo-switch.toml, unless it's there already
+        #[arg(long)]
+        write: bool,
+        /// Link the pinned versions into .direnv/cargo-switch/bin, as the snippet does whenever direnv loads it
+        #[arg(long, conflicts_with = "write")]
+        refresh: bool,
+    },
+    /// Print the versions pinned by the current project on a single line, e.g. for a shell prompt. Prints nothing
+    /// outside of projects or when anything goes wrong.
+    Prompt {
+        /// How to render each pinned package. Placeholders: {package}, {version} (the active one, `none` if
+        /// inactive), {pinned} and {mismatch} (`!` when the active version isn't the pinned one)
+        #[arg(long, default_value = DEFAULT_PROMPT_FORMAT)]
+        format: String,
+    },
+    /// Manage what cargo-switch remembers of crates.io's answers, and the sources downloaded by `vendor`
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommand,
+    },
+    /// Manage cargo-switch itself
+    #[command(name = "self")]
+    SelfCommand {
+        #[command(subcommand)]
+        command: SelfCommand,
+    },
+    /// Show what changed between the active version of a package and its latest release
+    Changelog {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        /// Print the version, the repository and the changelog's sections as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show the dependencies a version was built with, as the Cargo.lock kept when installing it says
+    Deps {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// Only show the dependencies whose name contains this
+        #[arg(value_name = "NAME")]
+        filter: Option<String>,
+    },
+    /// Check installed versions against the RustSec advisory database, failing if an active one is vulnerable
+    Audit {
+        /// Update every package whose active version is vulnerable
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Run a binary straight from the registry, without switching to its version
+    Run {
+        #[arg(value_name = "PACKAGE[@VERSION]")]
+        package: String,
+        /// The version to run, for a PACKAGE given without one
+        #[arg(long, value_name = "VERSION")]
+        version: Option<String>,
+        /// Which binary to run, for packages that provide several of them
+        #[arg(long)]
+        bin: Option<OsString>,
+        /// Install the version first if it isn't already, without switching to it, as the `ephemeral` config key
+        /// does. A PACKAGE that isn't installed at all gets its newest release.
+        #[arg(long)]
+        install_missing: bool,
+        /// Install a version that isn't installed yet into a directory of its own, removed once it's done running,
+        /// rather than into the registry
+        #[arg(long, conflicts_with = "install_missing")]
+        temp: bool,
+        #[arg(last = true)]
+        args: Vec<OsString>,
     },
-    List,
 }
 
-pub struct Switcher {
-    registry: PathBuf,
+#[derive(Subcommand)]
+enum ChannelCommand {
+    /// Point a channel to an installed version, creating the channel if needed
+    Set {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        #[arg(value_name = "CHANNEL")]
+        channel: String,
+        #[arg(value_name = "VERSION")]
+        version: String,
+    },
+    /// Remove a channel
+    Unset {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+        #[arg(value_name = "CHANNEL")]
+        channel: String,
+    },
+    /// Show the channels of a package and the versions they point to
+    List {
+        #[arg(value_name = "PACKAGE")]
+        package: String,
+    },
 }
 
-impl Switcher {
-    fn get_cargo_bin() -> Result<PathBuf> {
-        let path = env::var("PATH").expect("failed to find PATH");
+#[derive(Subcommand)]
+enum ConfigCommand {
+    /// Show every location and setting cargo-switch goes by, along with what decided it, such as a flag, a config
+    /// key or the environment
+    Show {
+        /// Print every location and setting as JSON, keyed by name
+        #[arg(long)]
+        json: bool,
+    },
+}
 
-        path.split(':')
-            .find(|component| component.contains(".cargo/bin"))
-            .map(Path::new)
-            .with_context(|| {
-                "Failed to find your .cargo/bin directory. Is Cargo configured in your PATH?"
-            })
-            .map(ToOwned::to_owned)
-    }
+#[derive(Subcommand)]
+enum CacheCommand {
+    /// Forget every cached answer. Vendored sources are kept
+    Clear,
+    /// Show what the cache holds, including the versions downloaded by `vendor`
+    List,
+    /// Remove the vendored sources of a version
+    Remove {
+        #[arg(value_name = "PACKAGE@VERSION")]
+        package: String,
+    },
+}
 
-    pub fn new() -> Result<Self> {
-        let cargo_path = Self::get_cargo_bin()?;
+#[derive(Subcommand)]
+enum GroupCommand {
+    /// Install every package of a group that isn't installed yet, without switching to them
+    Install {
+        #[arg(value_name = "GROUP")]
+        name: String,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Switch to every package of a group. Packages that belong to several groups end up at the version of the
+    /// group switched to last
+    Switch {
+        #[arg(value_name = "GROUP")]
+        name: String,
+        /// Install the packages that aren't installed yet without asking
+        #[arg(long)]
+        install: bool,
+        #[command(flatten)]
+        build: BuildArgs,
+    },
+    /// Show every group, with the packages it holds
+    List,
+}
 
-        ensure!(
-            cargo_path.exists(),
-            ".cargo/bin directory in $PATH does not exist"
-        );
+/// How the packages of a group get built, as `install` takes it
+#[derive(Args)]
+struct BuildArgs {
+    /// How many times to retry installs that fail because of the network
+    #[arg(long)]
+    retries: Option<u32>,
+    /// Strip the installed binaries of their symbols, whatever the `strip` config key says
+    #[arg(long, conflicts_with = "no_strip")]
+    strip: bool,
+    /// Leave the installed binaries unstripped, whatever the `strip` config key says
+    #[arg(long)]
+    no_strip: bool,
+    /// Keep every old version around this time, whatever the `keep-versions` config key says
+    #[arg(long)]
+    no_auto_prune: bool,
+    /// Build from what `cargo switch vendor` downloaded beforehand, without the network
+    #[arg(long)]
+    offline: bool,
+    /// Build with this rustup toolchain, as in `stable` or `1.75`, rather than the active one
+    #[arg(long, value_name = "NAME")]
+    toolchain: Option<String>,
+    /// Build even if crates.io says a version needs a newer rustc than the toolchain has
+    #[arg(long)]
+    skip_msrv_check: bool,
+    /// Fail unless the .crate cargo built from has the checksum crates.io's index lists for it
+    #[arg(long, conflicts_with = "offline")]
+    require_checksum: bool,
+    /// Build without the crates' default features, whatever their `default-features` config keys say
+    #[arg(long, conflicts_with = "default_features")]
+    no_default_features: bool,
+    /// Build with the crates' default features, whatever their `default-features` config keys say
+    #[arg(long)]
+    default_features: bool,
+    /// Build with the Cargo.lock the crates were published with, whatever their `locked` config keys say
+    #[arg(long, conflicts_with = "no_locked")]
+    locked: bool,
+    /// Resolve dependencies afresh, whatever the packages' `locked` config keys say
+    #[arg(long)]
+    no_locked: bool,
+    /// Leave out the features and flags the packages' sections of the config file add, for this run
+    #[arg(long)]
+    no_config_flags: bool,
+    /// Kill builds still running after this long, e.g. 30m, whatever the `build-timeout` config key says
+    #[arg(long, value_name = "DURATION")]
+    timeout: Option<humantime::Duration>,
+    /// Build even if the `binary-cache` has the same build, and don't push to it either
+    #[arg(long)]
+    no_binary_cache: bool,
+}
 
-        let switch_path = cargo_path.join("cargo-switch-registry");
-        if switch_path.exists().not() {
-            fs::create_dir(&switch_path).unwrap()
+impl BuildArgs {
+    fn options(&self) -> InstallOptions {
+        InstallOptions {
+            retries: self.retries,
+            strip: match (self.strip, self.no_strip) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            no_auto_prune: self.no_auto_prune,
+            offline: self.offline,
+            toolchain: self.toolchain.clone(),
+            skip_msrv_check: self.skip_msrv_check,
+            require_checksum: self.require_checksum,
+            no_default_features: match (self.no_default_features, self.default_features) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            locked: match (self.locked, self.no_locked) {
+                (true, _) => Some(true),
+                (_, true) => Some(false),
+                _ => None,
+            },
+            no_config_flags: self.no_config_flags,
+            timeout: self.timeout.map(Into::into),
+            no_binary_cache: self.no_binary_cache,
+            ..InstallOptions::default()
         }
-
-        Ok(Self {
-            registry: switch_path,
-        })
     }
+}
 
-    /// Perform some basic input checking and return the project name and version. Expects input to be in the
-    /// `name@semver` format.
-    fn get_version_tag(package: &str) -> Option<(&str, &str)> {
-        let (project_name, version) = package.split_once('@')?;
+#[derive(Subcommand)]
+enum TrashCommand {
+    /// Show what the trash holds and how much space it takes
+    List,
+    /// Remove what the trash holds for good
+    Empty {
+        /// Only remove what was trashed at least this long ago, e.g. 30d
+        #[arg(long, value_name = "DURATION")]
+        older_than: Option<humantime::Duration>,
+    },
+}
 
-        let good_enough =
-            project_name.is_empty().not() && version.chars().any(|ch| ch.is_ascii_digit());
+#[derive(Subcommand)]
+enum SelfCommand {
+    /// Replace cargo-switch with its newest release on crates.io
+    Update {
+        /// Only check whether there's a newer release
+        #[arg(long)]
+        check: bool,
+    },
+}
 
-        good_enough.then_some((project_name, version))
+impl Cli {
+    /// Whether the command installs, links or removes versions, and so has events to emit
+    fn emits_events(&self) -> bool {
+        self.package_versions.is_empty().not()
+            || matches!(
+                self.command,
+                Some(
+                    Commands::Install { .. }
+                        | Commands::AddBinary { .. }
+                        | Commands::Restore { .. }
+                        | Commands::Uninstall { .. }
+                        | Commands::Prune { .. }
+                        | Commands::Rebuild { .. }
+                        | Commands::Update { .. }
+                        | Commands::ProjectInstall
+                        | Commands::Group { .. }
+                )
+            )
     }
+}
 
-    fn build_target_path(&self, package: &str) -> Result<PathBuf> {
-        let (project_name, project_version) = Self::get_version_tag(package)
-            .with_context(|| "Expected input in the form `NAME@VERSION`")?;
-
-        Ok(self.registry.join(project_name).join(project_version))
+/// The arguments to parse, without the `switch` that cargo passes along when run as `cargo switch`
+fn subcommand_args(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<_> = args.collect();
+    if args.get(1).is_some_and(|arg| arg == "switch") {
+        args.remove(1);
     }
 
-    pub fn install_package(&self, package: &str) -> Result<()> {
-        let target_path = self.build_target_path(package)?;
-
-        let mut child = Command::new("cargo")
-            .arg("install")
-            .arg(package)
-            .arg("--root")
-            .arg(target_path)
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::piped())
-            .spawn()
-            .expect("Failed to execute cargo install");
-
-        let stderr = child.stderr.take().expect("Failed to capture stderr");
-        let reader = io::BufReader::new(stderr);
-
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                eprintln!("{}", line);
-            }
-        }
-
-        let status = child.wait().expect("Failed to wait on child process");
-
-        if status.success() {
-            println!("Successfully installed {}", package);
-        } else {
-            eprintln!("Failed to install {}", package);
-        }
+    args
+}
 
-        self.switch_package(package)?;
+fn main() -> Result<()> {
+    let cli = Cli::parse_from(subcommand_args(env::args_os()));
+    let events = match cli.message_format {
+        MessageFormat::Json if cli.emits_events() => Some(JsonLines::take_stdout()?),
+        _ => None,
+    };
 
-        Ok(())
+    let result = run(&cli, events.as_ref());
+    if let (Err(err), Some(events)) = (&result, &events) {
+        events.emit(&Event::error(err));
     }
-
-    fn list_packages(&self) -> Result<()> {
-        let readdir = fs::read_dir(&self.registry)?;
-
-        for maybe_entry in readdir {
-            let entry = match maybe_entry {
-                Ok(entry) => entry,
-                Err(err) => {
-                    eprintln!("{err}");
-                    continue;
-                }
-            };
-            let entry_path = entry.path();
-            // Should be a safe unwrap
-            let project_name = entry_path.components().last().unwrap().as_os_str();
-            println!("{}:", Path::new(project_name).display());
-
-            // Read dir again to fetch versions
-            let inner_readdir = fs::read_dir(&entry_path)?;
-            for maybe_entry in inner_readdir {
-                let entry = maybe_entry?;
-                let entry_path = entry.path();
-                let project_version = entry_path.components().last().unwrap().as_os_str();
-                println!("  - {}", Path::new(project_version).display());
-            }
+    // Provisioning scripts may want to retry hung builds differently from failed ones
+    if let Err(err) = &result {
+        if error_kind(err) == ErrorKind::TimedOut {
+            eprintln!("Error: {err:?}");
+            process::exit(TIMED_OUT_EXIT_CODE);
         }
-
-        Ok(())
     }
 
-    fn switch_package(&self, package: &str) -> Result<()> {
-        let switch_registry = self.build_target_path(package)?;
-        let cargo_bin = Self::get_cargo_bin()?;
-
-        ensure!(
-            switch_registry.exists(),
-            "Project {package} is not installed!"
-        );
-
-        let project_bin = switch_registry.join("bin");
-        ensure!(
-            switch_registry.exists(),
-            "Expected {} to exist",
-            project_bin.display()
-        );
-
-        for maybe_entry in read_dir(project_bin)? {
-            let entry = maybe_entry?;
-            let entry_path = entry.path();
+    result
+}
 
-            // Assumes every binary will be in the form `$CARGO_BIN/bin/binary`. If it has subdirectories and such,
-            // I expect this logic to fail
-            let file_name = entry_path.components().last().unwrap().as_os_str();
-            let symlink_path = cargo_bin.join(file_name);
-            if symlink_path.exists() {
-                fs::remove_file(&symlink_path)?;
+fn run(cli: &Cli, events: Option<&JsonLines>) -> Result<()> {
+    // What `run` installs along the way is beside the point, so cargo's output only shows if the build fails
+    let quiet = cli.quiet || matches!(cli.command, Some(Commands::Run { .. }));
+    let mut builder = Switcher::builder()
+        .verbose(cli.verbose)
+        .quiet(quiet)
+        .refresh(cli.refresh)
+        .no_evict(cli.no_evict)
+        .keep_suffixed(cli.keep_suffixed)
+        .system(cli.system)
+        .pre(cli.pre)
+        .no_proxy(cli.no_proxy)
+        .allow_overwrite_toolchain(cli.allow_overwrite_toolchain)
+        .ignore_smoke_test(cli.ignore_smoke_test);
+    if let Some(events) = events {
+        builder = builder.events(events.try_clone()?);
+    }
+    if cli.fail_fast {
+        builder = builder.failure_mode(FailureMode::FailFast);
+    } else if cli.keep_going {
+        builder = builder.failure_mode(FailureMode::KeepGoing);
+    }
+    if let Some(link_style) = cli.link_style {
+        builder = builder.link_style(link_style);
+    }
+    if let Some(cargo_bin) = &cli.cargo_bin {
+        builder = builder.cargo_bin(cargo_bin);
+    }
+    if let Some(link_dir) = &cli.link_dir {
+        builder = builder.link_dir(link_dir);
+    }
+    // Whatever goes wrong, a prompt is better off without our part than with an error in it
+    if let Some(Commands::Prompt { format }) = &cli.command {
+        if let (Ok(switcher), Ok(directory)) = (builder.read_only(true).build(), env::current_dir())
+        {
+            let summary = switcher.prompt(&directory, format);
+            if summary.is_empty().not() {
+                println!("{summary}");
             }
-
-            unix::fs::symlink(&entry_path, &symlink_path)?;
-            println!(
-                "Linked {} to {}",
-                entry_path.display(),
-                symlink_path.display()
-            );
         }
+        return Ok(());
+    }
+    // Prompts only read, and are rendered far too often to be worth a handler each time
+    interrupt::install_handler()?;
+    // Building fails over everything setup is there to fix
+    if let Some(Commands::Setup { modify_shell_rc }) = &cli.command {
+        return builder.setup(*modify_shell_rc);
+    }
+    // Whatever's broken is what it's there to show, so it can't wait on a switcher that builds
+    if let Some(Commands::Config {
+        command: ConfigCommand::Show { json },
+    }) = &cli.command
+    {
+        return builder.paths().print(*json);
+    }
+    let switcher = builder.build()?;
 
-        Ok(())
+    let result = run_command(cli, &switcher);
+    // Worth finding out about later on, whenever something looks off
+    if let Err(err) = &result {
+        let command: Vec<_> = env::args_os()
+            .skip(1)
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        switcher.log_failure(&format!("cargo switch {}", command.join(" ")), err);
+    } else if quiet.not() {
+        switcher.print_update_hint();
     }
+
+    result
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
-    let switcher = Switcher::new()?;
+fn run_command(cli: &Cli, switcher: &Switcher) -> Result<()> {
+    if cli.package_versions.is_empty().not() {
+        let profile = if cli.debug {
+            Some("dev")
+        } else {
+            cli.profile.as_deref()
+        };
 
-    if let Some(package_version) = &cli.package_version {
-        switcher.switch_package(package_version)?;
+        let mut package_versions = Vec::new();
+        for package_version in &cli.package_versions {
+            package_versions.push(spec::spec_str(package_version)?.to_owned());
+        }
+        let package_versions: Vec<_> =
+            spec::specs_with_version(&package_versions, cli.version.as_deref())?
+                .into_iter()
+                .map(|package_version| match profile {
+                    Some(profile) if package_version.contains('+').not() => {
+                        variant_directory(&package_version, profile, &[])
+                    }
+                    _ => package_version,
+                })
+                .collect();
+        // The variant, if any, is part of the version and tells the install which profile to build with
+        switcher.switch_packages(&package_versions, cli.install)?;
     } else if let Some(command) = &cli.command {
         match command {
-            Commands::Install { package } => {
-                switcher.install_package(package)?;
+            Commands::Install {
+                packages,
+                version,
+                from_file,
+                retries,
+                debug,
+                profile,
+                path,
+                target,
+                from_url,
+                sha256,
+                git,
+                branch,
+                rev,
+                label,
+                features,
+                strip,
+                no_strip,
+                no_auto_prune,
+                offline,
+                toolchain,
+                skip_msrv_check,
+                require_checksum,
+                no_default_features,
+                default_features,
+                locked,
+                no_locked,
+                no_config_flags,
+                timeout,
+                no_binary_cache,
+            } => {
+                let profile = if *debug {
+                    Some("dev".to_owned())
+                } else {
+                    profile.clone()
+                };
+                let options = InstallOptions {
+                    retries: *retries,
+                    profile,
+                    path: path.clone(),
+                    target: target.clone(),
+                    from_url: from_url.clone(),
+                    sha256: sha256.clone(),
+                    git: git.clone(),
+                    branch: branch.clone(),
+                    rev: rev.clone(),
+                    label: label.clone(),
+                    features: features.clone(),
+                    strip: match (strip, no_strip) {
+                        (true, _) => Some(true),
+                        (_, true) => Some(false),
+                        _ => None,
+                    },
+                    no_auto_prune: *no_auto_prune,
+                    offline: *offline,
+                    toolchain: toolchain.clone(),
+                    skip_msrv_check: *skip_msrv_check,
+                    require_checksum: *require_checksum,
+                    no_default_features: match (no_default_features, default_features) {
+                        (true, _) => Some(true),
+                        (_, true) => Some(false),
+                        _ => None,
+                    },
+                    locked: match (locked, no_locked) {
+                        (true, _) => Some(true),
+                        (_, true) => Some(false),
+                        _ => None,
+                    },
+                    no_config_flags: *no_config_flags,
+                    timeout: timeout.map(Into::into),
+                    no_binary_cache: *no_binary_cache,
+                    ..InstallOptions::default()
+                };
+                let packages = spec::specs_with_version(packages, version.as_deref())?;
+                switcher.install_from_args(&packages, from_file.as_deref(), &options)?;
+            }
+            Commands::Push { packages } => {
+                switcher.push(packages)?;
+            }
+            Commands::Vendor { packages, retries } => {
+                switcher.vendor(packages, *retries)?;
+            }
+            Commands::Strip { package } => {
+                switcher.strip(package)?;
+            }
+            Commands::AddBinary { package, files } => {
+                switcher.add_binary(package, files)?;
+            }
+            Commands::CopyTo {
+                package,
+                destination,
+                strip,
+                target,
+                force,
+            } => {
+                let options = CopyOptions {
+                    strip: *strip,
+                    target: target.clone(),
+                    force: *force,
+                };
+                switcher.copy_to(package, destination, &options)?;
             }
-            Commands::List => {
-                switcher.list_packages()?;
+            Commands::Backup { archive } => {
+                switcher.backup(archive)?;
+            }
+            Commands::Restore { archive, force } => {
+                switcher.restore(archive, *force)?;
+            }
+            Commands::Uninstall {
+                package,
+                version,
+                force,
+            } => {
+                switcher.uninstall(&spec::with_version(package, version.as_deref())?, *force)?;
+            }
+            Commands::Group { command } => match command {
+                GroupCommand::Install { name, build } => {
+                    switcher.group_install(name, &build.options())?
+                }
+                GroupCommand::Switch {
+                    name,
+                    install,
+                    build,
+                } => switcher.group_switch(name, *install, &build.options())?,
+                GroupCommand::List => switcher.print_groups()?,
+            },
+            Commands::Undelete { package } => {
+                switcher.undelete(package)?;
+            }
+            Commands::Trash {
+                command: TrashCommand::List,
+            } => {
+                switcher.print_trash()?;
+            }
+            Commands::Trash {
+                command: TrashCommand::Empty { older_than },
+            } => {
+                switcher.empty_trash(older_than.map(Into::into))?;
+            }
+            Commands::Prune { package, keep } => {
+                switcher.prune(package.as_deref(), *keep)?;
+            }
+            Commands::Purge { keep_nothing, yes } => {
+                switcher.purge(*keep_nothing, *yes)?;
+            }
+            Commands::Update { package, git, .. } => match package {
+                Some(package) => switcher.update(package, *git)?,
+                None => switcher.update_all()?,
+            },
+            Commands::Rebuild {
+                package,
+                locked_from_original,
+                ..
+            } => match package {
+                Some(package) => switcher.rebuild(package, *locked_from_original)?,
+                None => switcher.rebuild_all(*locked_from_original)?,
+            },
+            Commands::List {
+                package,
+                tree,
+                json,
+                format,
+                inactive,
+                long,
+                source,
+                by_binary,
+                paths,
+            } => {
+                let mut listings = match package {
+                    Some(package) => {
+                        let package = switcher.canonical_name(package)?;
+                        let listing = switcher.package_listing(&package)?;
+                        if listing.versions.is_empty() {
+                            return Err(switcher.not_installed(&package, None));
+                        }
+                        vec![listing]
+                    }
+                    None => switcher.listing(*inactive)?,
+                };
+                if let Some(kind) = source {
+                    listing::retain_source(&mut listings, *kind);
+                }
+
+                if *by_binary {
+                    let binaries = switcher.binary_listing(&listings);
+                    if *json {
+                        println!("{}", serde_json::to_string_pretty(&binaries)?);
+                    } else {
+                        listing::print_binary_listing(&binaries);
+                    }
+                } else if let Some(template) = format {
+                    for package in &listings {
+                        for version in &package.versions {
+                            println!("{}", template.render(&Record::new(&package.name, version)));
+                        }
+                    }
+                } else if *paths {
+                    listing::print_paths(&listings);
+                } else if *json {
+                    println!("{}", serde_json::to_string_pretty(&listings)?);
+                } else if *tree {
+                    listing::print_tree(&listings);
+                } else {
+                    listing::print_listing(&listings, *long);
+                }
+            }
+            Commands::Bisect {
+                package,
+                good,
+                bad,
+                command,
+            } => {
+                switcher.bisect(package, good, bad, command)?;
+            }
+            Commands::TestMatrix {
+                package,
+                versions,
+                jobs,
+                json,
+                command,
+            } => {
+                switcher.test_matrix(package, versions, *jobs, *json, command)?;
+            }
+            Commands::Default { package, unset } => match Switcher::get_version_tag(package) {
+                Some(_) if *unset => bail!("--unset expects a package name without a version"),
+                Some((package, version)) => switcher.set_default(package, version)?,
+                None if *unset => switcher.unset_default(package)?,
+                None => switcher.print_default(package)?,
+            },
+            Commands::Lock { package, .. } => match package {
+                Some(package) => switcher.lock_version(package)?,
+                None => switcher.print_locked()?,
+            },
+            Commands::Unlock { package } => {
+                switcher.unlock_version(package)?;
+            }
+            Commands::Channel { command } => match command {
+                ChannelCommand::Set {
+                    package,
+                    channel,
+                    version,
+                } => switcher.set_channel(package, channel, version)?,
+                ChannelCommand::Unset { package, channel } => {
+                    switcher.unset_channel(package, channel)?
+                }
+                ChannelCommand::List { package } => switcher.print_channels(package)?,
+            },
+            Commands::Promote { package, from, to } => {
+                switcher.promote(package, from, to)?;
+            }
+            Commands::Current { package, format } => {
+                switcher.print_current(package.as_deref(), format.as_ref())?;
+            }
+            Commands::Info { package, remote } => match Switcher::get_version_tag(package) {
+                Some((package, version)) => switcher.print_info(package, Some(version), *remote)?,
+                None => switcher.print_info(package, None, *remote)?,
+            },
+            Commands::ShadowCheck { probe } => {
+                switcher.shadow_check(*probe)?;
+            }
+            Commands::Check {
+                package,
+                timeout,
+                toolchain_drift,
+                smoke_test,
+            } => {
+                switcher.check(
+                    package.as_deref(),
+                    Duration::from_secs(*timeout),
+                    *toolchain_drift,
+                    *smoke_test,
+                )?;
+            }
+            Commands::Doctor {
+                probe,
+                convert_links,
+                merge_duplicates,
+                remove_stale_links,
+                toolchain_drift,
+            } => {
+                switcher.doctor(
+                    *probe,
+                    *convert_links,
+                    *merge_duplicates,
+                    *remove_stale_links,
+                    *toolchain_drift,
+                )?;
+            }
+            Commands::Log {
+                package,
+                limit,
+                follow,
+            } => {
+                switcher.print_log(package.as_deref(), *limit, *follow)?;
+            }
+            Commands::Why { name } => {
+                switcher.why(name)?;
+            }
+            Commands::Path { package, bin } => {
+                let path = match Switcher::get_version_tag(package) {
+                    Some((package, version)) => {
+                        switcher.installed_path(package, Some(version), bin.as_deref())?
+                    }
+                    None => switcher.installed_path(package, None, bin.as_deref())?,
+                };
+                println!("{}", path.display());
+            }
+            Commands::Which { binary, format } => {
+                switcher.print_which(binary, format.as_ref())?;
+            }
+            Commands::Exec {
+                with,
+                install,
+                command,
+            } => {
+                switcher.exec_with(with, *install, command)?;
+            }
+            Commands::Shell { packages } => {
+                let status = switcher.shell(packages)?;
+                if status.success().not() {
+                    process::exit(run::exit_code(status));
+                }
+            }
+            Commands::Pin {
+                packages,
+                tool_versions,
+            } => {
+                switcher.pin(packages, &env::current_dir()?, *tool_versions)?;
+            }
+            Commands::ProjectInstall => {
+                switcher.project_install(&env::current_dir()?)?;
+            }
+            Commands::Direnv { write, refresh } => {
+                let directory = env::current_dir()?;
+                if *refresh {
+                    switcher.refresh_direnv_links(&directory)?;
+                } else if *write {
+                    match direnv::write_envrc(&directory)? {
+                        (path, true) => println!(
+                            "Added the snippet to {}, run `direnv allow` to enable it",
+                            path.display()
+                        ),
+                        (path, false) => println!("{} has the snippet already", path.display()),
+                    }
+                } else {
+                    print!("{}", direnv::envrc_snippet());
+                }
+            }
+            // Handled before the switcher is even built, since it must never fail
+            Commands::Prompt { .. } => {}
+            // Handled before the switcher is built too, as it's needed for it to build at all
+            Commands::Setup { .. } | Commands::Config { .. } => {}
+            Commands::Cache {
+                command: CacheCommand::Clear,
+            } => {
+                switcher.clear_cache()?;
+            }
+            Commands::Cache {
+                command: CacheCommand::List,
+            } => {
+                switcher.print_cache()?;
+            }
+            Commands::Cache {
+                command: CacheCommand::Remove { package },
+            } => {
+                switcher.remove_vendored(package)?;
+            }
+            Commands::SelfCommand {
+                command: SelfCommand::Update { check },
+            } => {
+                switcher.self_update(*check)?;
+            }
+            Commands::Changelog { package, json } => {
+                switcher.print_changelog(package, *json)?;
+            }
+            Commands::Deps { package, filter } => match Switcher::get_version_tag(package) {
+                Some((package, version)) => {
+                    switcher.deps(package, Some(version), filter.as_deref())?
+                }
+                None => switcher.deps(package, None, filter.as_deref())?,
+            },
+            Commands::Audit { fix } => {
+                switcher.audit(*fix)?;
+            }
+            Commands::Run {
+                package,
+                version,
+                bin,
+                install_missing,
+                temp,
+                args,
+            } => {
+                let package = &spec::with_version(package, version.as_deref())?;
+                let (package, version) = match Switcher::get_version_tag(package) {
+                    Some((package, version)) => (package, Some(version)),
+                    None => (package.as_str(), None),
+                };
+                let missing = match (*install_missing, *temp) {
+                    (_, true) => Missing::Temporary,
+                    (true, false) => Missing::Install,
+                    (false, false) => Missing::Fail,
+                };
+                let status =
+                    switcher.run_package(package, version, bin.as_deref(), args, missing)?;
+                if status.success().not() {
+                    process::exit(run::exit_code(status));
+                }
             }
         }
     } else {
@@ -218,15 +1427,74 @@ fn main() -> Result<()> {
 
 #[cfg(test)]
 mod tests {
-    use crate::Switcher;
+    use std::ffi::OsString;
+
+    use clap::CommandFactory;
+    use clap::Parser;
+
+    use crate::subcommand_args;
+    use crate::Cli;
+    use crate::Commands;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(subcommand_args(
+            ["cargo-switch"].iter().chain(args).map(OsString::from),
+        ))
+    }
 
     #[test]
-    fn has_version_tag() {
-        assert!(Switcher::get_version_tag("sqlx-cli@0.7.2").is_some());
-        assert!(Switcher::get_version_tag("zig@1.0.0-rc0").is_some());
+    fn cli_is_well_formed() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn skips_the_subcommand_name_cargo_passes_along() {
+        let cli = parse(&["switch", "ripgrep@14.1.0", "fd-find@9.0.0"]).unwrap();
+        assert_eq!(cli.package_versions, ["ripgrep@14.1.0", "fd-find@9.0.0"]);
+        let cli = parse(&["ripgrep@14.1.0"]).unwrap();
+        assert_eq!(cli.package_versions, ["ripgrep@14.1.0"]);
+    }
+
+    #[test]
+    fn takes_versions_through_flags() {
+        let cli = parse(&["ripgrep", "--version", "14.1.0"]).unwrap();
+        assert_eq!(cli.package_versions, ["ripgrep"]);
+        assert_eq!(cli.version.as_deref(), Some("14.1.0"));
+        assert!(parse(&["--version", "14.1.0"]).is_err());
+
+        let cli = parse(&["install", "ripgrep", "--version", "14.1.0"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Install { version: Some(version), .. }) if version == "14.1.0"
+        ));
+        assert!(parse(&[
+            "install",
+            "tool",
+            "--git",
+            "https://example.com/tool",
+            "--version",
+            "1.0.0"
+        ])
+        .is_err());
+        assert!(parse(&["install", "--from-file", "tools.txt", "--version", "1.0.0"]).is_err());
+
+        let cli = parse(&["uninstall", "ripgrep", "--version", "14.1.0"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Uninstall { version: Some(version), .. }) if version == "14.1.0"
+        ));
 
-        assert!(Switcher::get_version_tag("zig@rc").is_none());
-        assert!(Switcher::get_version_tag("zig@").is_none());
-        assert!(Switcher::get_version_tag("@0.7.2").is_none());
+        // What comes after `--` goes to the binary, whatever it looks like
+        let cli = parse(&["run", "ripgrep", "--version", "14.1.0", "--", "--version"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Run { version: Some(version), args, .. })
+                if version == "14.1.0" && args == ["--version"]
+        ));
+        let cli = parse(&["run", "ripgrep@14.1.0", "--", "--version"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Some(Commands::Run { version: None, .. })
+        ));
     }
 }
@@ -0,0 +1,249 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::binary_cache::CacheProvenance;
+use crate::installer::BuildFlags;
+
+/// Directory, inside of a version's directory, holding what cargo-switch knows about that version
+const METADATA_DIRECTORY_NAME: &str = ".cargo-switch";
+const METADATA_FILE_NAME: &str = "metadata.json";
+/// The `Cargo.lock` the version was built from, inside its metadata directory, see [`lockfile`](crate::lockfile)
+const LOCKFILE_NAME: &str = "Cargo.lock";
+
+/// What was recorded about a version when it was installed.
+///
+/// Every field is optional so that metadata written by older versions of cargo-switch can still be read.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct VersionMetadata {
+    /// When the version was installed, in seconds since the Unix epoch
+    pub installed_at: Option<u64>,
+    /// How long `cargo install` took, in milliseconds
+    pub build_duration_ms: Option<u64>,
+    /// The cargo profile the binaries were built with
+    pub profile: Option<String>,
+    /// The cargo features the binaries were built with, on top of the default ones
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub features: Vec<String>,
+    /// Where the binaries came from
+    pub source: Option<Source>,
+    pub binaries: Vec<BinaryMetadata>,
+    /// Whether the `Cargo.lock` the binaries were built from was kept, unknown for versions installed before
+    /// lockfiles were
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lockfile: Option<LockfileStatus>,
+    /// What `cargo install` was told on top of the profile and features, unknown for versions installed before
+    /// flags were recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<BuildFlags>,
+    /// What `rustc --version` said for the compiler the binaries were built with, as in
+    /// `rustc 1.75.0 (82e1608df 2023-12-21)`, unknown for versions installed before it was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rustc: Option<String>,
+    /// The SHA-256 checksum crates.io's index lists for the `.crate` file the binaries were built from, for builds
+    /// from crates.io that looked it up
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    /// Set when the `.crate` file the binaries were built from was gone by the time `checksum` was looked up, so that
+    /// nothing was checked against it
+    #[serde(skip_serializing_if = "Not::not")]
+    pub checksum_unverified: bool,
+    /// Why the package's smoke test failed on the version, which isn't switched to until it passes. See
+    /// [`smoke_test`](crate::smoke_test).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quarantined: Option<String>,
+    /// Where the binaries were copied from, for versions that came from the binary cache rather than being built.
+    /// See [`binary_cache`](crate::binary_cache).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub binary_cache: Option<CacheProvenance>,
+}
+
+/// Whether the `Cargo.lock` of a build was kept next to its metadata
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum LockfileStatus {
+    Kept,
+    /// It couldn't be, for `reason`
+    Missing {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Source {
+    /// Built by `cargo install` from crates.io
+    CratesIo,
+    /// Built by `cargo install --git`, from `rev` if it's known
+    Git {
+        url: String,
+        rev: Option<String>,
+        /// The branch `rev` was the head of, unless the default branch was followed or `rev` was asked for
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+    },
+    /// Built by `cargo install --path`
+    Path { path: PathBuf },
+    /// A prebuilt artifact downloaded by `install --from-url`
+    Url { url: String, sha256: String },
+    /// Built or downloaded by the user, then registered through `add-binary`
+    External,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::CratesIo => write!(f, "crates.io"),
+            Source::Git { url, .. } => write!(f, "git {url}"),
+            Source::Path { path } => write!(f, "path {}", path.display()),
+            Source::Url { url, .. } => write!(f, "url {url}"),
+            Source::External => write!(f, "external"),
+        }
+    }
+}
+
+impl Source {
+    /// How `list --long` tags versions: just the kind of source, plus the revision for git builds
+    pub fn short_tag(&self) -> String {
+        match self {
+            Source::CratesIo => "crates.io".to_owned(),
+            Source::Git { rev: Some(rev), .. } => {
+                format!("git@{}", rev.get(..7).unwrap_or(rev))
+            }
+            Source::Git { rev: None, .. } => "git".to_owned(),
+            Source::Path { .. } => "path".to_owned(),
+            Source::Url { .. } => "url".to_owned(),
+            Source::External => "binary".to_owned(),
+        }
+    }
+
+    pub fn kind(&self) -> SourceKind {
+        match self {
+            Source::CratesIo => SourceKind::CratesIo,
+            Source::Git { .. } => SourceKind::Git,
+            Source::Path { .. } => SourceKind::Path,
+            Source::Url { .. } => SourceKind::Url,
+            Source::External => SourceKind::Binary,
+        }
+    }
+}
+
+/// Where versions came from, as given to `list --source`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SourceKind {
+    #[value(name = "crates.io")]
+    CratesIo,
+    Git,
+    Path,
+    Url,
+    /// Registered through `add-binary`
+    Binary,
+    /// Installed before sources were recorded
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BinaryMetadata {
+    pub name: String,
+    /// In bytes
+    pub size: u64,
+    /// The size before the binary was stripped, in bytes, if it was
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unstripped_size: Option<u64>,
+}
+
+impl VersionMetadata {
+    pub fn directory(version_path: &Path) -> PathBuf {
+        version_path.join(METADATA_DIRECTORY_NAME)
+    }
+
+    fn path(version_path: &Path) -> PathBuf {
+        Self::directory(version_path).join(METADATA_FILE_NAME)
+    }
+
+    /// Where the `Cargo.lock` of the version installed at `version_path` is kept
+    pub fn lockfile_path(version_path: &Path) -> PathBuf {
+        Self::directory(version_path).join(LOCKFILE_NAME)
+    }
+
+    /// Load the metadata of the version installed at `version_path`, if any was recorded
+    pub fn load(version_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path(version_path);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        serde_json::from_str(&contents)
+            .map(Some)
+            .with_context(|| format!("{} is corrupt", path.display()))
+    }
+
+    pub fn save(&self, version_path: &Path) -> Result<()> {
+        let path = Self::path(version_path);
+        fs::create_dir_all(Self::directory(version_path))?;
+
+        fs::write(&path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn installed_at(&self) -> Option<SystemTime> {
+        Some(UNIX_EPOCH + Duration::from_secs(self.installed_at?))
+    }
+
+    pub fn build_duration(&self) -> Option<Duration> {
+        Some(Duration::from_millis(self.build_duration_ms?))
+    }
+}
+
+/// Seconds since the Unix epoch, as stored in the metadata
+pub fn timestamp(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Source;
+
+    #[test]
+    fn tags_sources() {
+        let git = |rev: Option<&str>| Source::Git {
+            url: "https://github.com/BurntSushi/ripgrep".to_owned(),
+            rev: rev.map(str::to_owned),
+            branch: None,
+        };
+
+        assert_eq!(Source::CratesIo.short_tag(), "crates.io");
+        assert_eq!(
+            git(Some("abc1234def5678abc1234def5678abc1234def56")).short_tag(),
+            "git@abc1234"
+        );
+        assert_eq!(git(Some("abc")).short_tag(), "git@abc");
+        assert_eq!(git(None).short_tag(), "git");
+        assert_eq!(Source::External.short_tag(), "binary");
+
+        // Metadata written before sources were tracked still reads fine
+        let metadata: super::VersionMetadata =
+            serde_json::from_str(r#"{"installed-at": 1700000000}"#).unwrap();
+        assert_eq!(metadata.source, None);
+    }
+}
@@ -0,0 +1,296 @@
+//! Upgrading registries written by older versions of cargo-switch.
+//!
+//! The registry's format is versioned by the `format-version` of its state file, registries from before versioning
+//! being format 0. Whenever a switcher is built for a registry in an older format, the registry is upgraded one format
+//! at a time, after a copy of the state file is put aside. Registries in a newer format are refused outright, since
+//! there's no telling what this build would break in them.
+
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use anyhow::Context;
+use anyhow::Result;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::events;
+use crate::events::ErrorKind;
+use crate::metadata::timestamp;
+use crate::operation_log::Operation;
+use crate::state::STATE_FILE_NAME;
+use crate::Switcher;
+use crate::REGISTRY_DIRECTORY_NAME;
+
+/// The format of the registries this build reads and writes. Bumping it takes a new entry in [`MIGRATIONS`].
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Directory, inside the registry, where state files are kept before they're migrated
+const BACKUP_DIRECTORY_NAME: &str = ".migration-backups";
+
+/// An upgrade of the registry from one format to the next. Migrations work on the state file as JSON rather than
+/// through [`crate::state::State`], which only knows about the current format, and must leave a registry that's
+/// already in the next format as it is. They're given the registry and the directory its links went into.
+struct Migration {
+    description: &'static str,
+    run: fn(registry: &Path, link_dir: &Path, state: &mut Map<String, Value>) -> Result<()>,
+}
+
+/// The migration at index `n` upgrades format `n` to format `n + 1`
+const MIGRATIONS: [Migration; FORMAT_VERSION as usize] = [
+    Migration {
+        description:
+            "forget defaults and channels pointing to versions that aren't installed anymore",
+        run: forget_dangling_versions,
+    },
+    Migration {
+        description: "record the directory links went into",
+        run: record_link_dir,
+    },
+];
+
+/// Format 0 to 1: uninstalling a version used to leave the defaults pointing to it behind
+fn forget_dangling_versions(
+    registry: &Path,
+    _link_dir: &Path,
+    state: &mut Map<String, Value>,
+) -> Result<()> {
+    let installed = |package: &str, version: &Value| {
+        version
+            .as_str()
+            .is_some_and(|version| registry.join(package).join(version).is_dir())
+    };
+
+    if let Some(Value::Object(defaults)) = state.get_mut("defaults") {
+        defaults.retain(|package, version| installed(package, version));
+    }
+    if let Some(Value::Object(channels)) = state.get_mut("channels") {
+        for (package, channels) in channels.iter_mut() {
+            if let Value::Object(channels) = channels {
+                channels.retain(|_, version| installed(package, version));
+            }
+        }
+        channels.retain(|_, channels| {
+            channels
+                .as_object()
+                .is_none_or(|channels| channels.is_empty().not())
+        });
+    }
+
+    Ok(())
+}
+
+/// Format 1 to 2: the directories links went into used to be recorded only when switching, so the links left in
+/// `link_dir` after moving to another could go unnoticed
+fn record_link_dir(
+    _registry: &Path,
+    link_dir: &Path,
+    state: &mut Map<String, Value>,
+) -> Result<()> {
+    // The state is JSON, which can't hold paths that aren't UTF-8
+    let Some(link_dir) = link_dir.to_str() else {
+        return Ok(());
+    };
+
+    let link_dirs = state
+        .entry("link-dirs")
+        .or_insert_with(|| Value::Array(Vec::new()));
+    if let Value::Array(link_dirs) = link_dirs {
+        if link_dirs.iter().all(|recorded| recorded != link_dir) {
+            link_dirs.push(link_dir.into());
+            link_dirs.sort_by(|a, b| a.as_str().cmp(&b.as_str()));
+        }
+    }
+
+    Ok(())
+}
+
+/// The error for a registry in a format newer than this build understands
+pub(crate) fn newer_format(registry: &Path, format_version: u32) -> anyhow::Error {
+    events::classify(
+        anyhow!(
+            "The registry at {} is in format {format_version}, written by a newer cargo-switch, but this one only \
+             understands formats up to {FORMAT_VERSION}. Update cargo-switch, e.g. through `cargo switch self-update`",
+            registry.display()
+        ),
+        ErrorKind::UnsupportedFormat,
+    )
+}
+
+/// The state file of `registry` as JSON, if there's one
+fn read_state(registry: &Path) -> Result<Option<Map<String, Value>>> {
+    let path = registry.join(STATE_FILE_NAME);
+
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    serde_json::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("{} is corrupt", path.display()))
+}
+
+fn write_state(registry: &Path, state: &Map<String, Value>) -> Result<()> {
+    let path = registry.join(STATE_FILE_NAME);
+    let temporary_path = path.with_extension("json.tmp");
+
+    fs::write(&temporary_path, serde_json::to_string_pretty(state)?)?;
+    fs::rename(&temporary_path, &path)
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// The format `registry` is in
+pub fn registry_format(registry: &Path) -> Result<u32> {
+    let Some(state) = read_state(registry)? else {
+        return Ok(0);
+    };
+
+    match state.get("format-version") {
+        None => Ok(0),
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .with_context(|| {
+                format!(
+                    "{} has an invalid format-version",
+                    registry.join(STATE_FILE_NAME).display()
+                )
+            }),
+    }
+}
+
+/// Fail unless this build understands the format of `registry`
+pub(crate) fn ensure_supported_format(registry: &Path) -> Result<()> {
+    let format_version = registry_format(registry)?;
+    if format_version > FORMAT_VERSION {
+        return Err(newer_format(registry, format_version));
+    }
+
+    Ok(())
+}
+
+/// Copy the state file of `registry`, in `format_version`, aside, returning where it went
+fn back_up_state(registry: &Path, format_version: u32) -> Result<Option<PathBuf>> {
+    let path = registry.join(STATE_FILE_NAME);
+    if path.exists().not() {
+        return Ok(None);
+    }
+
+    let backups = registry.join(BACKUP_DIRECTORY_NAME);
+    fs::create_dir_all(&backups)
+        .with_context(|| format!("Failed to create {}", backups.display()))?;
+    let backup = backups.join(format!(
+        "format-{format_version}-{}{STATE_FILE_NAME}",
+        timestamp(SystemTime::now())
+    ));
+    fs::copy(&path, &backup).with_context(|| format!("Failed to back up {}", path.display()))?;
+
+    Ok(Some(backup))
+}
+
+/// Mark the brand new `registry` as being in the current format
+pub(crate) fn stamp_new_registry(registry: &Path) -> Result<()> {
+    let mut state = Map::new();
+    state.insert("format-version".to_owned(), FORMAT_VERSION.into());
+
+    write_state(registry, &state)
+}
+
+impl Switcher {
+    /// Upgrade the registry to the current format if it's in an older one
+    pub fn migrate_registry(&self) -> Result<()> {
+        let format_version = registry_format(&self.registry)?;
+        if format_version > FORMAT_VERSION {
+            return Err(newer_format(&self.registry, format_version));
+        }
+        if format_version == FORMAT_VERSION {
+            return Ok(());
+        }
+
+        let _links = self.lock_links()?;
+        // Someone else may have been at it while we waited for the lock
+        let format_version = registry_format(&self.registry)?;
+        if format_version == FORMAT_VERSION {
+            return Ok(());
+        }
+
+        // Links went next to the registry, unless it was put somewhere of its own
+        let link_dir = match self.registry.parent() {
+            Some(parent) if self.registry.ends_with(REGISTRY_DIRECTORY_NAME) => parent,
+            _ => &self.cargo_bin,
+        };
+        let backup = back_up_state(&self.registry, format_version)?;
+        for (from, migration) in MIGRATIONS.iter().enumerate().skip(format_version as usize) {
+            let mut state = read_state(&self.registry)?.unwrap_or_default();
+            (migration.run)(&self.registry, link_dir, &mut state).with_context(|| {
+                format!(
+                    "Failed to upgrade the registry to format {}: couldn't {}",
+                    from + 1,
+                    migration.description
+                )
+            })?;
+            state.insert("format-version".to_owned(), (from + 1).into());
+            write_state(&self.registry, &state)?;
+        }
+
+        self.log(
+            Operation::Migrate,
+            None,
+            None,
+            format!("from format {format_version} to {FORMAT_VERSION}"),
+        );
+        match backup {
+            Some(backup) => eprintln!(
+                "Upgraded the registry at {} from format {format_version} to {FORMAT_VERSION}, its old state is \
+                 backed up at {}",
+                self.registry.display(),
+                backup.display()
+            ),
+            None => eprintln!(
+                "Upgraded the registry at {} from format {format_version} to {FORMAT_VERSION}",
+                self.registry.display()
+            ),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use serde_json::Map;
+    use serde_json::Value;
+
+    use super::MIGRATIONS;
+
+    fn fixture(contents: &str) -> Map<String, Value> {
+        serde_json::from_str(contents).unwrap()
+    }
+
+    #[test]
+    fn migrations_are_idempotent() {
+        let registry = tempfile::tempdir().unwrap();
+        fs::create_dir_all(registry.path().join("tool/1.0.0")).unwrap();
+
+        for contents in [
+            include_str!("../tests/fixtures/state/defaults.json"),
+            include_str!("../tests/fixtures/state/channels.json"),
+        ] {
+            let mut state = fixture(contents);
+            for migration in &MIGRATIONS {
+                (migration.run)(registry.path(), registry.path(), &mut state).unwrap();
+                let once = state.clone();
+                (migration.run)(registry.path(), registry.path(), &mut state).unwrap();
+                assert_eq!(state, once);
+            }
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! Checking that the toolchain is recent enough for a crate's `rust-version` before building it, rather than finding
+//! out minutes into the build. crates.io tells which rustc a release needs, and the installer which rustc it would
+//! build with.
+//!
+//! Whatever can't be told, be it because the release doesn't say, crates.io can't be reached or the installer
+//! doesn't know its rustc, lets the build go ahead. `--skip-msrv-check` does so regardless.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+use anyhow::bail;
+use anyhow::Result;
+use semver::Version;
+
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::Switcher;
+
+/// A compiler, as `rustc --version` describes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RustcVersion {
+    pub version: Version,
+    /// The date of the commit it was built from, as in `2024-01-29`, which is what tells nightlies apart
+    pub date: Option<String>,
+}
+
+impl RustcVersion {
+    /// Read what `rustc --version` printed, as in `rustc 1.75.0 (82e1608df 2023-12-21)` or
+    /// `rustc 1.77.0-nightly (5518eaa94 2024-01-29)`
+    pub fn parse(output: &str) -> Option<Self> {
+        let output = output.trim().strip_prefix("rustc ")?;
+        let version = Version::parse(output.split_whitespace().next()?).ok()?;
+        // Compilers built outside of a git checkout don't know their commit, nor its date
+        let date = output
+            .split_once('(')
+            .and_then(|(_, rest)| rest.split_once(')'))
+            .and_then(|(commit, _)| commit.split_whitespace().last())
+            .filter(|date| {
+                date.len() == 10
+                    && date.bytes().enumerate().all(|(index, byte)| match index {
+                        4 | 7 => byte == b'-',
+                        _ => byte.is_ascii_digit(),
+                    })
+            })
+            .map(str::to_owned);
+
+        Some(Self { version, date })
+    }
+
+    /// Whether this compiler is older than `other`. Releases are told apart by their version, and builds of the same
+    /// one, as nightlies are, by their date if both have one.
+    pub fn is_older_than(&self, other: &RustcVersion) -> bool {
+        let release = |rustc: &RustcVersion| {
+            (
+                rustc.version.major,
+                rustc.version.minor,
+                rustc.version.patch,
+            )
+        };
+        match release(self).cmp(&release(other)) {
+            Ordering::Equal => matches!(
+                (&self.date, &other.date),
+                (Some(date), Some(other_date)) if date < other_date
+            ),
+            ordering => ordering == Ordering::Less,
+        }
+    }
+
+    /// How many releases, each a minor version, this compiler is behind `other`
+    pub fn releases_behind(&self, other: &RustcVersion) -> u64 {
+        if self.version.major < other.version.major {
+            return u64::MAX;
+        }
+
+        other.version.minor.saturating_sub(self.version.minor)
+    }
+}
+
+impl fmt::Display for RustcVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.date {
+            Some(date) => write!(f, "{} ({date})", self.version),
+            None => write!(f, "{}", self.version),
+        }
+    }
+}
+
+/// Whether `rustc` is at least `rust_version`, as in `1.70` or `1.70.0`, if that parses. Nightlies and betas count
+/// as the release they'll become, as cargo has them.
+pub fn satisfies(rustc: &Version, rust_version: &str) -> Option<bool> {
+    let mut components = rust_version.trim().split('.').map(str::parse::<u64>);
+    let major = components.next()?.ok()?;
+    let minor = components.next().transpose().ok()?.unwrap_or(0);
+    let patch = components.next().transpose().ok()?.unwrap_or(0);
+    if components.next().is_some() {
+        return None;
+    }
+
+    Some((rustc.major, rustc.minor, rustc.patch) >= (major, minor, patch))
+}
+
+impl Switcher {
+    /// Fail if `crate_name@version` needs a newer rustc than builds with `toolchain`, or the active toolchain, use
+    pub(crate) fn check_rust_version(
+        &self,
+        crate_name: &str,
+        version: &str,
+        toolchain: Option<&str>,
+    ) -> Result<()> {
+        // Nothing to compare with
+        let rustc = self.installer.rustc_version(toolchain);
+        let Some(rustc) = rustc.as_deref().and_then(RustcVersion::parse) else {
+            if let Some(toolchain) = toolchain {
+                eprintln!(
+                    "Warning: couldn't tell which rustc the {toolchain} toolchain has, building {crate_name}@{version} \
+                     without checking its `rust-version`"
+                );
+            }
+            return Ok(());
+        };
+
+        let spec = format!("{crate_name}@{version}");
+        // A pre-flight check isn't worth waiting on retries for
+        let versions =
+            RetryPolicy::new(0).run(&format!("look up {crate_name} on crates.io"), || {
+                self.crates_io()
+                    .map_err(Failure::Permanent)?
+                    .versions(crate_name)
+            });
+        let versions = match versions {
+            Ok(versions) => versions,
+            Err(err) => {
+                eprintln!(
+                    "Warning: couldn't find out which rustc {spec} needs, building it anyway: {err:#}"
+                );
+                return Ok(());
+            }
+        };
+        let Some(rust_version) = versions
+            .into_iter()
+            .find(|published| published.num == version)
+            .and_then(|published| published.rust_version)
+        else {
+            eprintln!(
+                "Note: {spec} doesn't say which rustc it needs, so it's built without knowing whether {} is recent \
+                 enough",
+                rustc.version
+            );
+            return Ok(());
+        };
+
+        if satisfies(&rustc.version, &rust_version) == Some(false) {
+            let toolchain = match toolchain {
+                Some(toolchain) => format!("the {toolchain} toolchain"),
+                None => "the active toolchain".to_owned(),
+            };
+            bail!(
+                "{spec} needs rustc {rust_version} or newer, but {toolchain} has rustc {}. Run `rustup update`, \
+                 pass --toolchain with a newer one, or pass --skip-msrv-check to try anyway",
+                rustc.version
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use semver::Version;
+
+    use super::satisfies;
+    use super::RustcVersion;
+
+    fn rustc(output: &str) -> RustcVersion {
+        RustcVersion::parse(output).unwrap()
+    }
+
+    #[test]
+    fn parses_rustc_versions() {
+        let stable = rustc("rustc 1.75.0 (82e1608df 2023-12-21)\n");
+        assert_eq!(stable.version, Version::new(1, 75, 0));
+        assert_eq!(stable.date.as_deref(), Some("2023-12-21"));
+
+        let nightly = rustc("rustc 1.77.0-nightly (5518eaa94 2024-01-29)");
+        assert_eq!(nightly.to_string(), "1.77.0-nightly (2024-01-29)");
+
+        // Distributions build rustc without its git history
+        assert_eq!(rustc("rustc 1.75.0").date, None);
+        assert_eq!(rustc("rustc 1.75.0 (Fedora 1.75.0-1.fc39)").date, None);
+        assert_eq!(RustcVersion::parse("cargo 1.75.0"), None);
+    }
+
+    #[test]
+    fn compares_compilers() {
+        let old = rustc("rustc 1.68.2 (9eb3afe9e 2023-03-27)");
+        let new = rustc("rustc 1.75.0 (82e1608df 2023-12-21)");
+        assert!(old.is_older_than(&new));
+        assert!(new.is_older_than(&old).not());
+        assert_eq!(old.releases_behind(&new), 7);
+        assert_eq!(new.releases_behind(&old), 0);
+
+        // Nightlies of the same version are told apart by date
+        let nightly = rustc("rustc 1.77.0-nightly (5518eaa94 2024-01-29)");
+        let later = rustc("rustc 1.77.0-nightly (c7a8f5c0b 2024-02-10)");
+        assert!(nightly.is_older_than(&later));
+        assert!(later.is_older_than(&nightly).not());
+        assert!(nightly.is_older_than(&nightly).not());
+        assert_eq!(nightly.releases_behind(&later), 0);
+    }
+
+    #[test]
+    fn compares_with_rust_versions() {
+        let rustc = Version::new(1, 74, 1);
+        assert_eq!(satisfies(&rustc, "1.70"), Some(true));
+        assert_eq!(satisfies(&rustc, "1.74.1"), Some(true));
+        assert_eq!(satisfies(&rustc, "1.75"), Some(false));
+        assert_eq!(satisfies(&rustc, "2"), Some(false));
+        assert_eq!(satisfies(&rustc, "1.x"), None);
+
+        let nightly = Version::parse("1.75.0-nightly").unwrap();
+        assert_eq!(satisfies(&nightly, "1.75"), Some(true));
+    }
+}
@@ -0,0 +1,471 @@
+//! `config show`: every location and setting cargo-switch goes by, as resolved from flags, the configuration file,
+//! cargo's configuration and the environment, along with what decided it. Nothing is created and nothing short of a
+//! panic aborts it: whatever can't be resolved is reported among the rest, so that it's there to tell what's broken.
+//! The settings building a switcher goes by are resolved by [`SwitcherBuilder::resolve`] for both, so that what's
+//! shown is what's used.
+
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use serde::ser::SerializeMap;
+use serde::Serialize;
+use serde::Serializer;
+
+use crate::cache::CACHE_DIRECTORY_NAME;
+use crate::cache::DEFAULT_CACHE_TTL;
+use crate::cargo::CargoInstaller;
+use crate::config::Config;
+use crate::format::human_duration;
+use crate::format::human_size;
+use crate::install_root;
+use crate::install_root::BinSource;
+use crate::install_root::CargoEnv;
+use crate::link_style::LinkStyle;
+use crate::network::NetworkSettings;
+use crate::operation_log::DEFAULT_LOG_MAX_SIZE;
+use crate::retry::DEFAULT_RETRIES;
+use crate::summary::FailureMode;
+use crate::table::print_table;
+use crate::SwitcherBuilder;
+use crate::REGISTRY_DIRECTORY_NAME;
+
+const DEFAULT: &str = "the default";
+
+/// A location or a setting, as resolved
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Resolved {
+    #[serde(skip)]
+    pub name: &'static str,
+    /// What it resolved to, if it could be told
+    pub value: Option<String>,
+    /// What decided it, as in `$PATH` or `the `keep-versions` config key`
+    pub source: Option<String>,
+    /// Anything else worth knowing about it, as in a directory that doesn't exist yet
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Why it couldn't be resolved, or why what it resolved to won't do
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Resolved {
+    fn new(name: &'static str, value: impl Into<String>, source: impl Into<String>) -> Self {
+        Self {
+            name,
+            value: Some(value.into()),
+            source: Some(source.into()),
+            note: None,
+            error: None,
+        }
+    }
+
+    /// Something that's left unset, as `source` has it
+    fn unset(name: &'static str, source: impl Into<String>) -> Self {
+        Self {
+            name,
+            value: None,
+            source: Some(source.into()),
+            note: None,
+            error: None,
+        }
+    }
+
+    fn failed(name: &'static str, error: &anyhow::Error) -> Self {
+        Self {
+            name,
+            value: None,
+            source: None,
+            note: None,
+            error: Some(format!("{error:#}")),
+        }
+    }
+
+    fn note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+
+    fn error(mut self, error: impl Into<String>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+
+    /// A config key's `value`, or `default` if it isn't set
+    fn key<T>(
+        name: &'static str,
+        value: Option<T>,
+        show: impl Fn(T) -> String,
+        default: impl Into<String>,
+    ) -> Self {
+        match value {
+            Some(value) => Self::new(name, show(value), format!("the `{name}` config key")),
+            None => Self::new(name, default, DEFAULT),
+        }
+    }
+
+    /// What `config show` shows of it: the value along with what else there is to know about it, or the error, and
+    /// where it came from
+    fn describe(&self) -> [String; 3] {
+        // Errors such as TOML's span several lines, which a table has no room for
+        let error = self
+            .error
+            .as_ref()
+            .map(|error| error.split_whitespace().collect::<Vec<_>>().join(" "));
+        let value = match (&self.value, &error) {
+            (None, Some(error)) => format!("error: {error}"),
+            (value, _) => {
+                let mut value = value.clone().unwrap_or_default();
+                for remark in [&self.note, &error].into_iter().flatten() {
+                    value = match value.is_empty() {
+                        true => remark.clone(),
+                        false => format!("{value} ({remark})"),
+                    };
+                }
+                value
+            }
+        };
+
+        [
+            self.name.to_owned(),
+            value,
+            self.source.clone().unwrap_or_default(),
+        ]
+    }
+}
+
+/// Everything `config show` reports, in order. As JSON, it's an object keyed by name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    pub entries: Vec<Resolved>,
+}
+
+impl Serialize for Paths {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for entry in &self.entries {
+            map.serialize_entry(entry.name, entry)?;
+        }
+        map.end()
+    }
+}
+
+impl Paths {
+    /// The entry named `name`, if there's one
+    pub fn get(&self, name: &str) -> Option<&Resolved> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    /// Print the entries as a table, or as JSON if `json` is set
+    pub fn print(&self, json: bool) -> Result<()> {
+        if json {
+            println!("{}", serde_json::to_string_pretty(self)?);
+            return Ok(());
+        }
+
+        let rows: Vec<_> = self.entries.iter().map(Resolved::describe).collect();
+        print_table(["SETTING", "VALUE", "FROM"], &rows);
+
+        Ok(())
+    }
+}
+
+/// The settings a switcher goes by that flags, the configuration file and the environment decide between, along with
+/// what decided them
+pub(crate) struct Resolution {
+    /// The directory links go into
+    pub links: Result<(PathBuf, BinSource)>,
+    pub registry: Result<(PathBuf, &'static str)>,
+    pub link_style: (LinkStyle, &'static str),
+    pub failure_mode: (FailureMode, &'static str),
+}
+
+impl SwitcherBuilder {
+    /// Resolve the settings `config` and the flags given so far make for, without creating or checking anything
+    pub(crate) fn resolve(&self, config: &Config) -> Resolution {
+        let found_bin = match (&self.cargo_bin, &config.cargo_bin) {
+            (Some(cargo_bin), _) => Ok((cargo_bin.clone(), BinSource::Flag)),
+            (None, Some(cargo_bin)) => Ok((cargo_bin.clone(), BinSource::ConfigKey)),
+            (None, None) => CargoEnv::current().cargo_bin(),
+        };
+        let link_dir = match (&self.link_dir, &config.link_dir) {
+            (Some(link_dir), _) => Some((link_dir.clone(), BinSource::LinkDirFlag)),
+            (None, Some(link_dir)) => Some((link_dir.clone(), BinSource::LinkDirConfigKey)),
+            (None, None) => None,
+        };
+        let (links, default_registry) = match link_dir {
+            Some((link_dir, source)) => {
+                // A registry that's already next to the cargo bin directory stays there
+                let next_to_cargo_bin = found_bin
+                    .ok()
+                    .map(|(cargo_bin, _)| cargo_bin.join(REGISTRY_DIRECTORY_NAME))
+                    .filter(|registry| registry.exists());
+                let registry = match next_to_cargo_bin {
+                    Some(registry) => (
+                        registry,
+                        "the default, kept next to the cargo bin directory",
+                    ),
+                    None => (link_dir.join(REGISTRY_DIRECTORY_NAME), DEFAULT),
+                };
+                (Ok((link_dir, source)), Some(registry))
+            }
+            None => match found_bin {
+                Ok((cargo_bin, source)) => {
+                    let registry = cargo_bin.join(REGISTRY_DIRECTORY_NAME);
+                    (Ok((cargo_bin, source)), Some((registry, DEFAULT)))
+                }
+                Err(err) => (Err(err), None),
+            },
+        };
+
+        let registry = match (self.system, &config.shared_registry, &self.registry) {
+            (true, Some(shared), _) => Ok((
+                shared.clone(),
+                "--system and the `shared-registry` config key",
+            )),
+            (true, None, _) => Err(anyhow!(
+                "--system installs into the shared registry, but there's none. Set the `shared-registry` config key"
+            )),
+            (false, _, Some(registry)) => Ok((registry.clone(), "the caller")),
+            (false, _, None) => default_registry
+                .ok_or_else(|| anyhow!("it goes next to the links, which couldn't be told")),
+        };
+
+        let link_style = match (self.link_style, config.link_style) {
+            (Some(link_style), _) => (link_style, "--link-style"),
+            (None, Some(link_style)) => (link_style, "the `link-style` config key"),
+            (None, None) => (LinkStyle::default(), DEFAULT),
+        };
+        let failure_mode = match (self.failure_mode, config.failure_mode) {
+            (Some(failure_mode), _) => (failure_mode, "--keep-going or --fail-fast"),
+            (None, Some(failure_mode)) => (failure_mode, "the `failure-mode` config key"),
+            (None, None) => (FailureMode::default(), DEFAULT),
+        };
+
+        Resolution {
+            links,
+            registry,
+            link_style,
+            failure_mode,
+        }
+    }
+
+    /// Resolve every location and setting the way [`SwitcherBuilder::build`] would, without creating anything or
+    /// failing over any of them
+    pub fn paths(&self) -> Paths {
+        let mut entries = Vec::new();
+
+        let loaded = match &self.config {
+            Some(_) => None,
+            None => Some(Config::load()),
+        };
+        entries.push(match (&loaded, Config::path()) {
+            (None, _) => Resolved::unset("config", "the caller").note("not read from a file"),
+            (Some(_), None) => Resolved::unset("config", DEFAULT)
+                .note("neither $CARGO_SWITCH_CONFIG nor $HOME is set, so the defaults apply"),
+            (Some(loaded), Some(path)) => {
+                let source = match env::var_os("CARGO_SWITCH_CONFIG") {
+                    Some(_) => "$CARGO_SWITCH_CONFIG",
+                    None => DEFAULT,
+                };
+                let resolved = Resolved::new("config", path.display().to_string(), source);
+                match loaded {
+                    Err(err) => resolved.error(format!("{err:#}, so the defaults apply")),
+                    Ok(_) if path.exists() => resolved.note("loaded"),
+                    Ok(_) => resolved.note("doesn't exist, so the defaults apply"),
+                }
+            }
+        });
+        let default_config;
+        let config = match (&self.config, &loaded) {
+            (Some(config), _) => config,
+            (None, Some(Ok(config))) => config,
+            (None, _) => {
+                default_config = Config::default();
+                &default_config
+            }
+        };
+
+        let resolution = self.resolve(config);
+        entries.push(match &resolution.links {
+            Ok((links, source)) => {
+                let resolved =
+                    Resolved::new("links", links.display().to_string(), source.to_string());
+                let path = env::var_os("PATH").unwrap_or_default();
+                match (links.is_dir(), install_root::in_path(links, &path)) {
+                    (false, _) => resolved.error("doesn't exist"),
+                    (true, false) => resolved.error("isn't in $PATH"),
+                    (true, true) => resolved,
+                }
+            }
+            Err(err) => Resolved::failed("links", err),
+        });
+
+        let registry = &resolution.registry;
+        entries.push(match registry {
+            Ok((registry, source)) => {
+                let resolved = Resolved::new("registry", registry.display().to_string(), *source);
+                match registry.exists() {
+                    true => resolved,
+                    false => resolved.note("doesn't exist yet"),
+                }
+            }
+            Err(err) => Resolved::failed("registry", err),
+        });
+        entries.push(match (&config.shared_registry, self.system) {
+            (_, true) => {
+                Resolved::unset("shared-registry", "--system").note("installed into directly")
+            }
+            (Some(shared), false) => {
+                let resolved = Resolved::new(
+                    "shared-registry",
+                    shared.display().to_string(),
+                    "the `shared-registry` config key",
+                );
+                match shared.exists() {
+                    true => resolved,
+                    false => resolved.note("doesn't exist, so it's ignored"),
+                }
+            }
+            (None, false) => Resolved::unset("shared-registry", DEFAULT).note("none"),
+        });
+        entries.push(Resolved::key(
+            "binary-cache",
+            config.binary_cache.as_ref(),
+            |cache| cache.display().to_string(),
+            "none",
+        ));
+        entries.push(match registry {
+            Ok((registry, _)) => Resolved::new(
+                "cache",
+                registry.join(CACHE_DIRECTORY_NAME).display().to_string(),
+                "inside the registry",
+            ),
+            Err(_) => Resolved::failed(
+                "cache",
+                &anyhow::anyhow!("it goes inside the registry, which couldn't be told"),
+            ),
+        });
+
+        let cargo = CargoInstaller {
+            cargo_path: config.cargo_path.clone(),
+            ..CargoInstaller::default()
+        }
+        .cargo();
+        let cargo_source = match (&config.cargo_path, env::var_os("CARGO")) {
+            (Some(_), _) => "the `cargo-path` config key",
+            (None, Some(_)) => "$CARGO",
+            (None, None) => "$PATH",
+        };
+        entries.push(match cargo {
+            Ok(cargo) => Resolved::new("cargo", cargo.display().to_string(), cargo_source),
+            Err(err) => Resolved::failed("cargo", &err),
+        });
+        entries.push(match NetworkSettings::current(self.no_proxy) {
+            Ok(settings) => Resolved::new(
+                "network",
+                format!("reaching servers {settings}"),
+                "cargo's configuration and the environment",
+            ),
+            Err(err) => Resolved::failed("network", &err),
+        });
+
+        let (link_style, source) = resolution.link_style;
+        let link_style = match link_style {
+            LinkStyle::Absolute => "absolute",
+            LinkStyle::Relative => "relative",
+        };
+        entries.push(Resolved::new("link-style", link_style, source));
+
+        let (failure_mode, source) = resolution.failure_mode;
+        let failure_mode = match failure_mode {
+            FailureMode::KeepGoing => "keep-going",
+            FailureMode::FailFast => "fail-fast",
+        };
+        entries.push(Resolved::new("failure-mode", failure_mode, source));
+
+        let overridden = config
+            .packages
+            .iter()
+            .filter(|(_, package)| package.keep_versions.is_some())
+            .count();
+        let keep_versions = Resolved::key(
+            "keep-versions",
+            config.keep_versions,
+            |keep| keep.to_string(),
+            "all",
+        );
+        entries.push(match overridden {
+            0 => keep_versions,
+            overridden => keep_versions.note(format!("{overridden} package(s) set their own")),
+        });
+        entries.push(Resolved::key(
+            "max-size",
+            config.max_size,
+            human_size,
+            "unlimited",
+        ));
+        entries.push(Resolved::key(
+            "cache-ttl",
+            config.cache_ttl,
+            human_duration,
+            human_duration(DEFAULT_CACHE_TTL),
+        ));
+        entries.push(Resolved::key(
+            "retries",
+            config.retries,
+            |retries| retries.to_string(),
+            DEFAULT_RETRIES.to_string(),
+        ));
+        entries.push(Resolved::key(
+            "build-timeout",
+            config.build_timeout,
+            human_duration,
+            "none",
+        ));
+        entries.push(Resolved::key(
+            "log-max-size",
+            config.log_max_size,
+            human_size,
+            human_size(DEFAULT_LOG_MAX_SIZE),
+        ));
+        entries.push(Resolved::new(
+            "update-hints",
+            match config.update_hints {
+                true => "on",
+                false => "off",
+            },
+            match config.update_hints {
+                true => "the `update-hints` config key",
+                false => DEFAULT,
+            },
+        ));
+
+        Paths { entries }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolved;
+
+    #[test]
+    fn describes_values_and_errors() {
+        let links = Resolved::new("links", "/home/user/.cargo/bin", "$PATH");
+        assert_eq!(
+            links.clone().error("isn't in $PATH").describe(),
+            ["links", "/home/user/.cargo/bin (isn't in $PATH)", "$PATH"].map(str::to_owned)
+        );
+        assert_eq!(
+            Resolved::failed("cargo", &anyhow::anyhow!("Failed to find cargo")).describe(),
+            ["cargo", "error: Failed to find cargo", ""].map(str::to_owned)
+        );
+        assert_eq!(
+            Resolved::unset("config", "the caller")
+                .note("not read from a file")
+                .describe(),
+            ["config", "not read from a file", "the caller"].map(str::to_owned)
+        );
+    }
+}
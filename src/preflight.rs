@@ -0,0 +1,208 @@
+//! The pre-flight check: before building a version from crates.io, making sure crates.io has it, so that a typo in a
+//! package name or version fails right away, pointing at what was probably meant, rather than once cargo got around
+//! to finding out itself.
+//!
+//! The check goes through the same [`CratesIo`](crate::crates_io::CratesIo) client and cache as everything else that
+//! asks crates.io, and is only worth a warning when crates.io can't be reached: the build is attempted anyway.
+
+use std::ops::Not;
+
+use anyhow::bail;
+use anyhow::Result;
+use semver::Version;
+
+use crate::crates_io::PublishedVersion;
+use crate::retry::RetryPolicy;
+use crate::suggest::edit_distance;
+use crate::Switcher;
+
+/// How many crates the search is asked for, to pick the closest names from
+const SEARCH_LIMIT: usize = 10;
+/// How many names, or versions, are suggested at most
+const MAX_SUGGESTIONS: usize = 5;
+
+/// The error for crates.io having no crate named `name`, with the closest of the names its search `found`
+fn no_such_crate(name: &str, found: &[String]) -> String {
+    let mut found: Vec<_> = found
+        .iter()
+        .filter(|found| found.as_str() != name)
+        .collect();
+    // The search ranks by relevance, which a typo only goes so far with
+    found.sort_by_key(|found| edit_distance(name, found));
+    found.truncate(MAX_SUGGESTIONS);
+
+    match found.as_slice() {
+        [] => format!("There's no crate named `{name}` on crates.io"),
+        found => {
+            let found: Vec<_> = found.iter().map(|found| format!("`{found}`")).collect();
+            format!(
+                "There's no crate named `{name}` on crates.io. Did you mean {}?",
+                found.join(", ")
+            )
+        }
+    }
+}
+
+/// The releases of `versions` closest to `version`, in order: the ones just below and just above it
+fn nearest_versions(versions: &[PublishedVersion], version: &Version) -> Vec<Version> {
+    let mut releases: Vec<_> = versions
+        .iter()
+        .filter(|published| published.yanked.not())
+        .filter_map(|published| Version::parse(&published.num).ok())
+        .collect();
+    releases.sort();
+
+    let position = releases.partition_point(|release| release < version);
+    let start = position
+        .saturating_sub(MAX_SUGGESTIONS / 2)
+        .min(releases.len().saturating_sub(MAX_SUGGESTIONS));
+    releases
+        .into_iter()
+        .skip(start)
+        .take(MAX_SUGGESTIONS)
+        .collect()
+}
+
+/// The error for `name` having no `version` of the published `versions`, `None` if it has
+fn no_such_version(name: &str, version: &str, versions: &[PublishedVersion]) -> Option<String> {
+    if versions.iter().any(|published| published.num == version) {
+        return None;
+    }
+    // Anything else is a requirement, which cargo resolves itself
+    let Ok(parsed) = Version::parse(version) else {
+        return None;
+    };
+
+    let nearest: Vec<_> = nearest_versions(versions, &parsed)
+        .iter()
+        .map(Version::to_string)
+        .collect();
+    Some(match nearest.as_slice() {
+        [] => format!("{name} has no release on crates.io that wasn't yanked"),
+        nearest => format!(
+            "{name} has no version {version} on crates.io. Nearest published versions: {}",
+            nearest.join(", ")
+        ),
+    })
+}
+
+impl Switcher {
+    /// The name `crate_name` has on crates.io, which may spell `-` and `_` differently, or `crate_name` itself when
+    /// crates.io doesn't know it or can't be asked. [`check_published`](Self::check_published) says which.
+    pub(crate) fn real_crate_name(&self, crate_name: &str) -> String {
+        let real_name = self.crates_io().ok().and_then(|crates_io| {
+            RetryPolicy::new(0)
+                .run(&format!("look up {crate_name} on crates.io"), || {
+                    crates_io.real_name(crate_name)
+                })
+                .ok()
+                .flatten()
+        });
+
+        real_name.unwrap_or_else(|| crate_name.to_owned())
+    }
+
+    /// Fail if crates.io has no crate named `crate_name`, or no `version` of it, suggesting what was probably meant.
+    /// When crates.io can't be asked, this only warns, leaving it to the build to find out.
+    pub(crate) fn check_published(&self, crate_name: &str, version: &str) -> Result<()> {
+        let spec = format!("{crate_name}@{version}");
+        let crates_io = match self.crates_io() {
+            Ok(crates_io) => crates_io,
+            Err(err) => {
+                eprintln!(
+                    "Warning: couldn't make sure {spec} is on crates.io, building it anyway: {err:#}"
+                );
+                return Ok(());
+            }
+        };
+
+        // A pre-flight check isn't worth waiting on retries for
+        let versions = RetryPolicy::new(0)
+            .run(&format!("look up {crate_name} on crates.io"), || {
+                crates_io.lookup(crate_name)
+            });
+        let versions = match versions {
+            Ok(Some(versions)) => versions,
+            Ok(None) => {
+                // Suggestions are only a nicety, not worth failing over
+                let found = crates_io
+                    .search(crate_name, SEARCH_LIMIT)
+                    .unwrap_or_default();
+                bail!(no_such_crate(crate_name, &found));
+            }
+            Err(err) => {
+                eprintln!(
+                    "Warning: couldn't make sure {spec} is on crates.io, building it anyway: {err:#}"
+                );
+                return Ok(());
+            }
+        };
+
+        match no_such_version(crate_name, version, &versions) {
+            Some(message) => bail!(message),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::no_such_crate;
+    use super::no_such_version;
+    use crate::crates_io::PublishedVersion;
+
+    fn published(versions: &[&str]) -> Vec<PublishedVersion> {
+        versions
+            .iter()
+            .map(|num| PublishedVersion {
+                name: None,
+                num: num.to_string(),
+                yanked: num.starts_with("0.1"),
+                checksum: None,
+                rust_version: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn suggests_the_closest_names_found() {
+        let found = ["ripgrep_all", "ripgrep", "grep"].map(str::to_owned);
+        assert_eq!(
+            no_such_crate("ripgerp", &found),
+            "There's no crate named `ripgerp` on crates.io. Did you mean `ripgrep`, `grep`, `ripgrep_all`?"
+        );
+        assert_eq!(
+            no_such_crate("ripgerp", &[]),
+            "There's no crate named `ripgerp` on crates.io"
+        );
+    }
+
+    #[test]
+    fn lists_the_nearest_published_versions() {
+        let versions = published(&[
+            "0.1.0", "13.0.0", "14.0.0", "14.0.1", "14.1.0", "14.1.1", "15.0.0", "15.1.0",
+        ]);
+
+        assert_eq!(no_such_version("ripgrep", "14.1.0", &versions), None);
+        assert_eq!(
+            no_such_version("ripgrep", "14.0.5", &versions).unwrap(),
+            "ripgrep has no version 14.0.5 on crates.io. Nearest published versions: 14.0.0, 14.0.1, 14.1.0, \
+             14.1.1, 15.0.0"
+        );
+        // Past either end, the closest releases are all on one side, yanked ones left out
+        assert_eq!(
+            no_such_version("ripgrep", "99.0.0", &versions).unwrap(),
+            "ripgrep has no version 99.0.0 on crates.io. Nearest published versions: 14.0.1, 14.1.0, 14.1.1, \
+             15.0.0, 15.1.0"
+        );
+        assert!(no_such_version("ripgrep", "0.0.1", &versions)
+            .unwrap()
+            .ends_with("13.0.0, 14.0.0, 14.0.1, 14.1.0, 14.1.1"));
+        // Requirements are for cargo to resolve
+        assert_eq!(no_such_version("ripgrep", "14", &versions), None);
+        assert_eq!(
+            no_such_version("ripgrep", "1.0.0", &published(&["0.1.0"])).unwrap(),
+            "ripgrep has no release on crates.io that wasn't yanked"
+        );
+    }
+}
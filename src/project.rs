@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::spec::validate_name;
+use crate::tool_versions::ToolVersions;
+use crate::tool_versions::TOOL_VERSIONS_FILE_NAME;
+use crate::Switcher;
+
+/// Name of the file a project uses to pin the versions of the tools it needs
+pub const PROJECT_FILE_NAME: &str = ".cargo-switch.toml";
+
+/// The contents of a project's `.cargo-switch.toml`, e.g.
+///
+/// ```toml
+/// [pins]
+/// sqlx-cli = "0.7.2"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+pub struct ProjectFile {
+    #[serde(default)]
+    pub pins: BTreeMap<String, String>,
+}
+
+impl ProjectFile {
+    /// Look for a project file in `directory` and in every one of its ancestors, returning the closest one found
+    pub fn discover(directory: &Path) -> Result<Option<(PathBuf, Self)>> {
+        for ancestor in directory.ancestors() {
+            let path = ancestor.join(PROJECT_FILE_NAME);
+            if path.is_file().not() {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let project_file = toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+
+            return Ok(Some((path, project_file)));
+        }
+
+        Ok(None)
+    }
+
+    /// Pin every one of `pins` in the project file at `path`, creating it if needed. Whatever else the file holds,
+    /// including other pins, is kept.
+    pub fn update_pins(path: &Path, pins: &BTreeMap<String, String>) -> Result<()> {
+        let mut document = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)
+                .with_context(|| format!("Failed to parse {}", path.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => toml::Table::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let table = document
+            .entry("pins")
+            .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+            .as_table_mut()
+            .with_context(|| format!("`pins` in {} isn't a table", path.display()))?;
+        for (package, version) in pins {
+            table.insert(package.clone(), toml::Value::String(version.clone()));
+        }
+
+        fs::write(path, toml::to_string(&document)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+/// A version a project pins, along with the file pinning it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub version: String,
+    pub path: PathBuf,
+}
+
+/// A package that a project's `.cargo-switch.toml` and `.tool-versions` pin to different versions, the former
+/// winning
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinConflict {
+    pub package: String,
+    pub winner: Pin,
+    pub loser: Pin,
+}
+
+impl fmt::Display for PinConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self {
+            package,
+            winner,
+            loser,
+        } = self;
+        write!(
+            f,
+            "{} pins {package}@{} but {} pins {package}@{}, using {package}@{} from {}",
+            winner.path.display(),
+            winner.version,
+            loser.path.display(),
+            loser.version,
+            winner.version,
+            winner.path.display()
+        )
+    }
+}
+
+/// Every pin of a project, from its `.cargo-switch.toml` and its `.tool-versions` alike
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectPins {
+    /// The directory of the project file if there's one, or else of the `.tool-versions`
+    pub root: PathBuf,
+    pub pins: BTreeMap<String, Pin>,
+    pub conflicts: Vec<PinConflict>,
+}
+
+impl Switcher {
+    /// The pins of the project `directory` belongs to, from the closest project file and the closest
+    /// `.tool-versions`, or `None` if there's neither. Only the lines of `.tool-versions` that name managed packages
+    /// count.
+    pub fn project_pins(&self, directory: &Path) -> Result<Option<ProjectPins>> {
+        let project_file = ProjectFile::discover(directory)?;
+        let tool_versions = ToolVersions::discover(directory)?;
+
+        let root = match (&project_file, &tool_versions) {
+            (Some((path, _)), _) | (None, Some((path, _))) => path.parent().unwrap_or(directory),
+            (None, None) => return Ok(None),
+        };
+        let mut project_pins = ProjectPins {
+            root: root.to_owned(),
+            pins: BTreeMap::new(),
+            conflicts: Vec::new(),
+        };
+
+        if let Some((path, tool_versions)) = tool_versions {
+            let installed = self.installed_packages()?;
+            for (tool, version) in tool_versions.tools {
+                if let Some(package) = self.config.tool_package(&tool, &installed) {
+                    let path = path.clone();
+                    project_pins.pins.insert(package, Pin { version, path });
+                }
+            }
+        }
+
+        if let Some((path, project_file)) = project_file {
+            for (package, version) in project_file.pins {
+                let package = self.canonical_name(&package)?;
+                let path = path.clone();
+                let pin = Pin { version, path };
+                match project_pins.pins.insert(package.clone(), pin.clone()) {
+                    Some(loser) if loser.version != pin.version => {
+                        project_pins.conflicts.push(PinConflict {
+                            package,
+                            winner: pin,
+                            loser,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        Ok(Some(project_pins))
+    }
+
+    /// Pin every one of `specs`, as in `tool@1.0.0` or `tool` for its active version, in the project file closest to
+    /// `directory`, or in its closest `.tool-versions` if `tool_versions` is set. Either is created in `directory`
+    /// if there's none.
+    pub fn pin(&self, specs: &[String], directory: &Path, tool_versions: bool) -> Result<()> {
+        let mut pins = BTreeMap::new();
+        let specs: Vec<_> = specs
+            .iter()
+            .map(|spec| self.canonical_spec(spec))
+            .collect::<Result<_>>()?;
+        for spec in &specs {
+            let (package, version) = match Self::get_version_tag(spec) {
+                Some((package, version)) => (package, version.to_owned()),
+                None => {
+                    validate_name(spec)?;
+                    let Some(version) = self.linked_version(spec)? else {
+                        bail!("{spec} has no active version, name the version to pin as in {spec}@1.0.0");
+                    };
+                    (spec.as_str(), version)
+                }
+            };
+            if self.installed_versions(package)?.contains(&version).not() {
+                return Err(self.not_installed(package, Some(&version)));
+            }
+            pins.insert(package.to_owned(), version);
+        }
+
+        let path = if tool_versions {
+            let path = ToolVersions::discover(directory)?
+                .map_or_else(|| directory.join(TOOL_VERSIONS_FILE_NAME), |(path, _)| path);
+            let tools = pins
+                .iter()
+                .map(|(package, version)| {
+                    (self.config.tool_name(package).to_owned(), version.clone())
+                })
+                .collect();
+            ToolVersions::update_pins(&path, &tools)?;
+            path
+        } else {
+            let path = ProjectFile::discover(directory)?
+                .map_or_else(|| directory.join(PROJECT_FILE_NAME), |(path, _)| path);
+            ProjectFile::update_pins(&path, &pins)?;
+            path
+        };
+        for (package, version) in &pins {
+            println!("Pinned {package}@{version} in {}", path.display());
+        }
+
+        // Pins written to `.tool-versions` don't count where the project file pins something else
+        if let Some(project_pins) = self.project_pins(directory)? {
+            for conflict in &project_pins.conflicts {
+                if pins.contains_key(&conflict.package) {
+                    eprintln!("Warning: {conflict}");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use super::ProjectFile;
+    use super::PROJECT_FILE_NAME;
+
+    #[test]
+    fn discovers_closest_project_file() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("crates").join("inner");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(
+            root.path().join(PROJECT_FILE_NAME),
+            "[pins]\nsqlx-cli = \"0.7.2\"\n",
+        )
+        .unwrap();
+
+        let (path, project_file) = ProjectFile::discover(&nested).unwrap().unwrap();
+        assert_eq!(path, root.path().join(PROJECT_FILE_NAME));
+        assert_eq!(project_file.pins["sqlx-cli"], "0.7.2");
+    }
+
+    #[test]
+    fn updates_pins_keeping_the_rest() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join(PROJECT_FILE_NAME);
+        fs::write(&path, "[pins]\nsqlx-cli = \"0.7.2\"\njust = \"1.0.0\"\n").unwrap();
+
+        let pins = BTreeMap::from([("sqlx-cli".to_owned(), "0.8.0".to_owned())]);
+        ProjectFile::update_pins(&path, &pins).unwrap();
+
+        let (_, project_file) = ProjectFile::discover(root.path()).unwrap().unwrap();
+        assert_eq!(project_file.pins["sqlx-cli"], "0.8.0");
+        assert_eq!(project_file.pins["just"], "1.0.0");
+
+        // A missing project file is created
+        fs::remove_file(&path).unwrap();
+        ProjectFile::update_pins(&path, &pins).unwrap();
+        let (_, project_file) = ProjectFile::discover(root.path()).unwrap().unwrap();
+        assert_eq!(project_file.pins, pins);
+    }
+}
@@ -0,0 +1,314 @@
+//! `project-install`: installing the CLI tools a workspace declares in its `Cargo.toml` and pinning them in the
+//! project file next to it. Tools are declared the way cargo-run-bin expects them:
+//!
+//! ```toml
+//! [workspace.metadata.bin]
+//! cargo-nextest = { version = "0.9.57", locked = true }
+//! sqlx-cli = "0.7"
+//! ```
+//!
+//! `[workspace.metadata.cargo-switch]` works just as well, and so do both tables under `[package.metadata]` for
+//! projects that aren't workspaces.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use semver::Op;
+use semver::Version;
+use semver::VersionReq;
+use serde::Deserialize;
+
+use crate::crates_io;
+use crate::crates_io::PublishedVersion;
+use crate::install::InstallOptions;
+use crate::project::ProjectFile;
+use crate::project::PROJECT_FILE_NAME;
+use crate::retry;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+const MANIFEST_FILE_NAME: &str = "Cargo.toml";
+
+#[derive(Debug, Default, Deserialize)]
+struct Manifest {
+    workspace: Option<MetadataHolder>,
+    package: Option<MetadataHolder>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MetadataHolder {
+    #[serde(default)]
+    metadata: ToolMetadata,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ToolMetadata {
+    /// cargo-run-bin's table
+    #[serde(default)]
+    bin: BTreeMap<String, ToolSpec>,
+    #[serde(default, rename = "cargo-switch")]
+    cargo_switch: BTreeMap<String, ToolSpec>,
+}
+
+/// A tool's version requirement, either bare or along with whatever else cargo-run-bin records about it
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ToolSpec {
+    Requirement(String),
+    Detailed { version: String },
+}
+
+impl ToolSpec {
+    fn requirement(&self) -> &str {
+        match self {
+            ToolSpec::Requirement(requirement)
+            | ToolSpec::Detailed {
+                version: requirement,
+            } => requirement,
+        }
+    }
+}
+
+impl Manifest {
+    /// Every declared tool along with its version requirement, `[…metadata.cargo-switch]` winning over
+    /// `[…metadata.bin]` and workspaces winning over packages
+    fn tools(self) -> BTreeMap<String, String> {
+        let mut tools = BTreeMap::new();
+        for holder in [self.package, self.workspace].into_iter().flatten() {
+            let metadata = holder.metadata;
+            for (name, spec) in metadata.bin.iter().chain(&metadata.cargo_switch) {
+                tools.insert(name.clone(), spec.requirement().to_owned());
+            }
+        }
+
+        tools
+    }
+}
+
+/// Look for a `Cargo.toml` declaring tools in `directory` and in every one of its ancestors, returning the closest
+/// one found along with its tools
+pub fn discover_tools(directory: &Path) -> Result<Option<(PathBuf, BTreeMap<String, String>)>> {
+    for ancestor in directory.ancestors() {
+        let path = ancestor.join(MANIFEST_FILE_NAME);
+        if path.is_file().not() {
+            continue;
+        }
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let manifest: Manifest = toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse {}", path.display()))?;
+        let tools = manifest.tools();
+        if tools.is_empty().not() {
+            return Ok(Some((path, tools)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The only version `requirement` can match, if it pins one exactly as in `=1.2.3`
+fn exact_version(requirement: &VersionReq) -> Option<Version> {
+    let [comparator] = requirement.comparators.as_slice() else {
+        return None;
+    };
+    if comparator.op != Op::Exact {
+        return None;
+    }
+
+    Some(Version {
+        major: comparator.major,
+        minor: comparator.minor?,
+        patch: comparator.patch?,
+        pre: comparator.pre.clone(),
+        build: Default::default(),
+    })
+}
+
+impl Switcher {
+    /// The newest installed release build of `package` that matches `requirement`
+    fn installed_match(&self, package: &str, requirement: &VersionReq) -> Option<String> {
+        self.installed_versions(package)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|version| split_variant(version).1.is_none())
+            .rfind(|version| {
+                Version::parse(version).is_ok_and(|version| requirement.matches(&version))
+            })
+    }
+
+    /// The version `requirement` resolves to without asking crates.io, along with whether it's installed already
+    fn resolve_locally(&self, package: &str, requirement: &VersionReq) -> Option<(String, bool)> {
+        if let Some(version) = self.installed_match(package, requirement) {
+            return Some((version, true));
+        }
+
+        exact_version(requirement).map(|version| (version.to_string(), false))
+    }
+
+    /// The version `requirement` resolves to, along with whether it's installed already, taking the releases of
+    /// `package` from `published` if it has to
+    fn resolve_requirement(
+        &self,
+        package: &str,
+        requirement: &str,
+        published: &mut HashMap<&str, Result<Vec<PublishedVersion>>>,
+    ) -> Result<(String, bool)> {
+        let requirement = VersionReq::parse(requirement)
+            .with_context(|| format!("Invalid version requirement `{requirement}`"))?;
+        if let Some(resolved) = self.resolve_locally(package, &requirement) {
+            return Ok(resolved);
+        }
+
+        let versions = published
+            .remove(package)
+            .unwrap_or_else(|| Err(anyhow!("{package} was never looked up on crates.io")))
+            .with_context(|| format!("Failed to resolve {package}@{requirement}"))?;
+        let Some(version) = crates_io::newest_matching(&versions, &requirement) else {
+            bail!("No release of {package} on crates.io matches {requirement}");
+        };
+
+        Ok((version.to_string(), false))
+    }
+
+    /// Install every tool declared by the closest workspace manifest to `directory` that isn't installed yet, and
+    /// pin them all in the project file next to it
+    pub fn project_install(&self, directory: &Path) -> Result<()> {
+        let Some((manifest_path, tools)) = discover_tools(directory)? else {
+            bail!(
+                "No {MANIFEST_FILE_NAME} in {} or any of its parents declares tools. Declare them under \
+                 [workspace.metadata.bin] or [workspace.metadata.cargo-switch]",
+                directory.display()
+            );
+        };
+
+        let options = InstallOptions {
+            // Tools are only meant for this project, the rest of the system keeps whatever it had
+            no_switch: true,
+            ..InstallOptions::default()
+        };
+
+        // Whatever has to be looked up is looked up all at once, rather than one tool after another
+        let lookups: Vec<&str> = tools
+            .iter()
+            .filter(|(package, requirement)| {
+                VersionReq::parse(requirement)
+                    .is_ok_and(|requirement| self.resolve_locally(package, &requirement).is_none())
+            })
+            .map(|(package, _)| package.as_str())
+            .collect();
+        let mut published: HashMap<_, _> = if lookups.is_empty() {
+            HashMap::new()
+        } else {
+            let retries = self.config.retries.unwrap_or(retry::DEFAULT_RETRIES);
+            let results = self.crates_io()?.versions_of_many(&lookups, retries);
+            lookups.iter().copied().zip(results).collect()
+        };
+
+        let mut pins = BTreeMap::new();
+        let mut summary = Summary::new(self.failure_mode);
+        for (package, requirement) in &tools {
+            if summary.skip_if_stopped(format!("{package}@{requirement}")) {
+                continue;
+            }
+
+            let outcome = self
+                .resolve_requirement(package, requirement, &mut published)
+                .and_then(|(version, installed)| {
+                    if installed.not() {
+                        self.install_package(&format!("{package}@{version}"), &options)?;
+                    }
+                    Ok((version, installed))
+                });
+
+            match outcome {
+                Ok((version, installed)) => {
+                    pins.insert(package.clone(), version.clone());
+                    let (outcome, detail) = if installed {
+                        (
+                            Outcome::Skipped,
+                            format!("{requirement} is installed already"),
+                        )
+                    } else {
+                        (Outcome::Installed, format!("matches {requirement}"))
+                    };
+                    summary.record(format!("{package}@{version}"), outcome, Some(detail));
+                }
+                Err(err) => {
+                    eprintln!("Failed to install {package}@{requirement}: {err:#}");
+                    summary.record_failure(format!("{package}@{requirement}"), &err);
+                }
+            }
+        }
+
+        let project_path = manifest_path.with_file_name(PROJECT_FILE_NAME);
+        if pins.is_empty().not() {
+            ProjectFile::update_pins(&project_path, &pins)?;
+            println!(
+                "Pinned {} tool{} in {}",
+                pins.len(),
+                if pins.len() == 1 { "" } else { "s" },
+                project_path.display()
+            );
+        }
+
+        self.finish_summary(summary, "tools failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use semver::VersionReq;
+
+    use super::exact_version;
+    use super::Manifest;
+
+    #[test]
+    fn reads_declared_tools() {
+        let manifest: Manifest = toml::from_str(
+            r#"
+            [workspace]
+            members = ["crates/*"]
+
+            [workspace.metadata.bin]
+            cargo-nextest = { version = "0.9.57", locked = true }
+            sqlx-cli = "0.7"
+
+            [workspace.metadata.cargo-switch]
+            sqlx-cli = "=0.7.2"
+            "#,
+        )
+        .unwrap();
+
+        let tools = manifest.tools();
+        assert_eq!(tools["cargo-nextest"], "0.9.57");
+        assert_eq!(tools["sqlx-cli"], "=0.7.2");
+
+        let manifest: Manifest = toml::from_str("[package]\nname = \"app\"\n").unwrap();
+        assert!(manifest.tools().is_empty());
+    }
+
+    #[test]
+    fn spots_exact_requirements() {
+        let exact = |requirement: &str| {
+            exact_version(&VersionReq::parse(requirement).unwrap())
+                .map(|version| version.to_string())
+        };
+
+        assert_eq!(exact("=1.2.3").as_deref(), Some("1.2.3"));
+        assert_eq!(exact("=1.0.0-rc.1").as_deref(), Some("1.0.0-rc.1"));
+        assert_eq!(exact("1.2.3"), None);
+        assert_eq!(exact("=1.2"), None);
+        assert_eq!(exact(">=1.2.3, <2"), None);
+    }
+}
@@ -0,0 +1,125 @@
+//! A one-line summary of the versions pinned by the current project, meant to be embedded in a shell prompt.
+//!
+//! Prompts are rendered before every command, so this only reads the project file and the links in `.cargo/bin`:
+//! never the registry itself, and never the network.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::Switcher;
+
+/// How every pinned package is rendered when `prompt --format` isn't given
+pub const DEFAULT_PROMPT_FORMAT: &str = "{package}:{version}{mismatch}";
+
+/// Render a single pinned package. `{version}` is the active version, `{pinned}` the one the project asks for and
+/// `{mismatch}` is `!` when they differ.
+fn render(format: &str, package: &str, pinned: &str, active: Option<&str>) -> String {
+    let mismatch = if active == Some(pinned) { "" } else { "!" };
+
+    format
+        .replace("{package}", package)
+        .replace("{version}", active.unwrap_or("none"))
+        .replace("{pinned}", pinned)
+        .replace("{mismatch}", mismatch)
+}
+
+impl Switcher {
+    /// The prompt summary for the project `directory` belongs to, rendering each pinned package through `format`.
+    /// Empty outside of projects, and whenever anything goes wrong, so that a prompt never breaks because of us.
+    pub fn prompt(&self, directory: &Path, format: &str) -> String {
+        self.try_prompt(directory, format).unwrap_or_default()
+    }
+
+    fn try_prompt(&self, directory: &Path, format: &str) -> Result<String> {
+        let Some(project) = self.project_pins(directory)? else {
+            return Ok(String::new());
+        };
+        if project.pins.is_empty() {
+            return Ok(String::new());
+        }
+
+        // A single pass over `.cargo/bin` tells which version of every package is active
+        let mut active = BTreeMap::new();
+        let versioned = self.versioned_link_names()?;
+        for maybe_entry in fs::read_dir(&self.cargo_bin)? {
+            let name = maybe_entry?.file_name();
+            if versioned.contains(&name) {
+                continue;
+            }
+            if let Some((package, version)) = self.link_owner(&name) {
+                active.entry(package).or_insert(version);
+            }
+        }
+
+        let rendered: Vec<_> = project
+            .pins
+            .iter()
+            .map(|(package, pin)| {
+                render(
+                    format,
+                    package,
+                    &pin.version,
+                    active.get(package).map(String::as_str),
+                )
+            })
+            .collect();
+
+        Ok(rendered.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::symlink;
+
+    use super::DEFAULT_PROMPT_FORMAT;
+    use crate::config::Config;
+    use crate::project::PROJECT_FILE_NAME;
+    use crate::Switcher;
+
+    #[test]
+    fn summarizes_pinned_versions() {
+        let root = tempfile::tempdir().unwrap();
+        let cargo_bin = root.path().join(".cargo").join("bin");
+        fs::create_dir_all(&cargo_bin).unwrap();
+        let switcher = Switcher::builder()
+            .cargo_bin(&cargo_bin)
+            .config(Config::default())
+            .build()
+            .unwrap();
+
+        for (package, version, binary) in
+            [("ripgrep", "14.1.0", "rg"), ("sqlx-cli", "0.7.1", "sqlx")]
+        {
+            let bin_path = switcher.registry.join(package).join(version).join("bin");
+            fs::create_dir_all(&bin_path).unwrap();
+            fs::write(bin_path.join(binary), "#!/bin/sh\n").unwrap();
+            symlink(bin_path.join(binary), cargo_bin.join(binary)).unwrap();
+        }
+
+        let project = root.path().join("project");
+        fs::create_dir_all(&project).unwrap();
+        assert_eq!(switcher.prompt(&project, DEFAULT_PROMPT_FORMAT), "");
+
+        fs::write(
+            project.join(PROJECT_FILE_NAME),
+            "[pins]\nripgrep = \"14.1.0\"\nsqlx-cli = \"0.7.2\"\nbat = \"0.24.0\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            switcher.prompt(&project, DEFAULT_PROMPT_FORMAT),
+            "bat:none! ripgrep:14.1.0 sqlx-cli:0.7.1!"
+        );
+        assert_eq!(
+            switcher.prompt(&project, "{package}={pinned}"),
+            "bat=0.24.0 ripgrep=14.1.0 sqlx-cli=0.7.2"
+        );
+
+        fs::write(project.join(PROJECT_FILE_NAME), "not toml").unwrap();
+        assert_eq!(switcher.prompt(&project, DEFAULT_PROMPT_FORMAT), "");
+    }
+}
@@ -0,0 +1,28 @@
+//! Binaries in `.cargo/bin` that belong to the toolchain rather than to any package, which switching must never
+//! replace by accident.
+
+use std::ffi::OsStr;
+
+/// The proxies rustup installs in `.cargo/bin`, rustup itself, and us
+pub const PROTECTED_NAMES: &[&str] = &[
+    "cargo",
+    "cargo-clippy",
+    "cargo-fmt",
+    "cargo-miri",
+    "cargo-switch",
+    "clippy-driver",
+    "rls",
+    "rust-analyzer",
+    "rust-gdb",
+    "rust-gdbgui",
+    "rust-lldb",
+    "rustc",
+    "rustdoc",
+    "rustfmt",
+    "rustup",
+];
+
+/// Whether a binary called `name` would overwrite part of the toolchain
+pub fn is_protected(name: &OsStr) -> bool {
+    PROTECTED_NAMES.iter().any(|protected| name == *protected)
+}
@@ -0,0 +1,169 @@
+//! Removing old versions beyond a retention count, either through `prune` or automatically after installs when
+//! `keep-versions` is configured.
+
+use std::collections::BTreeSet;
+use std::ops::Not;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::operation_log::Operation;
+use crate::state::State;
+use crate::Switcher;
+
+/// The versions to remove so that no more than `keep` of `installed`, sorted from oldest to newest, are left. The
+/// oldest go first, but `protected` versions are never removed, only counted as kept.
+pub fn retention_plan<'a>(
+    installed: &'a [String],
+    keep: usize,
+    protected: &BTreeSet<String>,
+) -> Vec<&'a str> {
+    let excess = installed.len().saturating_sub(keep);
+
+    installed
+        .iter()
+        .filter(|version| protected.contains(*version).not())
+        .take(excess)
+        .map(String::as_str)
+        .collect()
+}
+
+impl Switcher {
+    /// The versions of `package` that are never pruned: the active one, the default one, the ones channels point to
+    /// and the locked ones
+    pub(crate) fn protected_versions(&self, package: &str) -> Result<BTreeSet<String>> {
+        let mut state = State::load(&self.registry)?;
+        let mut protected: BTreeSet<_> = state
+            .channels
+            .remove(package)
+            .unwrap_or_default()
+            .into_values()
+            .collect();
+        protected.extend(state.defaults.remove(package));
+        protected.extend(self.linked_version(package)?);
+        protected.extend(state.locked.remove(package).unwrap_or_default());
+
+        Ok(protected)
+    }
+
+    /// Prune `package`, or every package with a retention count, down to `keep` versions or to its `keep-versions`
+    /// otherwise
+    pub fn prune(&self, package: Option<&str>, keep: Option<usize>) -> Result<()> {
+        let explicit = package.is_some();
+        let packages = match package {
+            Some(package) => vec![self.canonical_name(package)?],
+            None => self.installed_packages()?,
+        };
+
+        let mut pruned = 0;
+        for package in &packages {
+            let Some(keep) = keep.or_else(|| self.config.keep_versions(package)) else {
+                ensure!(
+                    explicit.not(),
+                    "No retention count applies to {package}, pass --keep or set `keep-versions`"
+                );
+                continue;
+            };
+
+            let _lock = self.lock_package(package)?;
+            for version in self.prune_unlocked(package, keep)? {
+                println!("Pruned {package}@{version}");
+                pruned += 1;
+            }
+        }
+
+        if pruned == 0 {
+            println!("Nothing to prune");
+        }
+
+        Ok(())
+    }
+
+    /// Remove the oldest versions of `package` beyond `keep`, on behalf of a caller already holding its lock,
+    /// returning the versions that were removed
+    pub(crate) fn prune_unlocked(&self, package: &str, keep: usize) -> Result<Vec<String>> {
+        ensure!(keep > 0, "At least one version of {package} must be kept");
+
+        // The shared registry's versions aren't ours to prune, nor do they count toward what's kept
+        let installed: Vec<_> = self
+            .installed_versions(package)?
+            .into_iter()
+            .filter(|version| self.is_shared(package, version).not())
+            .collect();
+        let mut protected = self.protected_versions(package)?;
+
+        // Locked versions are worth a mention when they're old enough to have gone otherwise
+        let locked = self.locked_versions(package)?;
+        let unlocked: BTreeSet<_> = protected.difference(&locked).cloned().collect();
+        for version in retention_plan(&installed, keep, &unlocked) {
+            if locked.contains(version) {
+                println!("Kept {package}@{version}, which is locked");
+            }
+        }
+        // As is the version this very process runs from, which is only for a later prune to remove
+        if let Some(running) = self.running_version(package) {
+            if retention_plan(&installed, keep, &protected).contains(&running.as_str()) {
+                println!(
+                    "Kept {package}@{running}, which is running right now. Run `cargo switch prune {package}` \
+                     to remove it"
+                );
+            }
+            protected.insert(running);
+        }
+
+        let mut pruned = Vec::new();
+        for version in retention_plan(&installed, keep, &protected) {
+            self.uninstall_unlocked(package, version, true)
+                .with_context(|| format!("Failed to prune {package}@{version}"))?;
+            self.log(
+                Operation::Prune,
+                Some(package),
+                Some(version),
+                format!("keeping the newest {keep}"),
+            );
+            pruned.push(version.to_owned());
+        }
+        // Or else the trash would only ever grow
+        if let Err(err) = self.expire_trash() {
+            eprintln!(
+                "Warning: failed to remove what's past its trash-retention from the trash: {err:#}"
+            );
+        }
+
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::retention_plan;
+
+    #[test]
+    fn prunes_the_oldest_unprotected_versions() {
+        let installed: Vec<String> = ["1.0.0", "1.1.0", "1.2.0", "2.0.0", "2.1.0"]
+            .map(str::to_owned)
+            .into();
+        let protected = |versions: &[&str]| -> BTreeSet<String> {
+            versions.iter().map(|&version| version.to_owned()).collect()
+        };
+
+        assert_eq!(
+            retention_plan(&installed, 3, &protected(&[])),
+            ["1.0.0", "1.1.0"]
+        );
+        // Protected versions are kept, but still count
+        assert_eq!(
+            retention_plan(&installed, 3, &protected(&["1.0.0"])),
+            ["1.1.0", "1.2.0"]
+        );
+        assert_eq!(
+            retention_plan(&installed, 2, &protected(&["1.0.0", "1.1.0", "2.1.0"])),
+            ["1.2.0", "2.0.0"]
+        );
+        assert!(retention_plan(&installed, 5, &protected(&[])).is_empty());
+        assert!(retention_plan(&installed, 10, &protected(&[])).is_empty());
+    }
+}
@@ -0,0 +1,396 @@
+//! `purge`: taking cargo-switch out of the picture while leaving the tools it installed working. Every link it made
+//! is replaced with a copy of the binary it points to, or removed with `--keep-nothing`, and then the registry goes,
+//! along with everything kept in it: the state file, the caches, the trash, the logs and the locks.
+//!
+//! Only what cargo-switch made is touched. Links count when they point into a registry, and the registry is only
+//! removed if it holds nothing but packages and cargo-switch's own files. A purge that fails midway can be run again:
+//! links are replaced one at a time, each in one go, and the registry is marked as being purged before anything in
+//! it is removed, so that it's recognized as such even once half of it is gone.
+
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::shadow::same_directory;
+use crate::state::State;
+use crate::wrapper;
+use crate::Switcher;
+
+/// Left in the registry while it's being removed, so that a purge that failed midway knows the rest is ours
+const PURGE_MARKER_NAME: &str = ".purge-incomplete";
+
+/// What [`purge`](Switcher::purge) did
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    /// The links replaced with a copy of the binary they pointed to, along with the version that binary is from
+    pub materialized: Vec<(PathBuf, String)>,
+    /// The wrappers among them, whose copies run without the environment variables the wrappers set
+    pub unwrapped: Vec<PathBuf>,
+    /// The links removed, since there was nothing to keep or nothing to copy
+    pub removed_links: Vec<PathBuf>,
+    /// The registry removed, if there was one
+    pub registry: Option<PathBuf>,
+}
+
+/// A link cargo-switch made, into one of the registries
+struct PurgedLink {
+    link: PathBuf,
+    /// The binary it runs
+    target: PathBuf,
+    /// As in `ripgrep@14.1.0`, if it's a link to a version
+    version: Option<String>,
+}
+
+/// Whether `name` is one of the hidden siblings links are written to before they're moved into place, see
+/// [`temporary_path`](wrapper::temporary_path)
+fn is_temporary(name: &OsStr) -> bool {
+    let name = name.as_encoded_bytes();
+    name.starts_with(b".") && name.ends_with(b".cargo-switch-tmp")
+}
+
+/// Whether `path` is hidden, as cargo-switch's own files in the registry are
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_encoded_bytes().starts_with(b"."))
+}
+
+/// Whatever in `registry` cargo-switch wouldn't have put there: anything but directories of packages holding
+/// directories of versions, besides hidden files
+fn foreign_entries(registry: &Path) -> Result<Vec<PathBuf>> {
+    let mut foreign = Vec::new();
+    let entries = |directory: &Path| -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for maybe_entry in fs::read_dir(directory)
+            .with_context(|| format!("Failed to read {}", directory.display()))?
+        {
+            let path = maybe_entry?.path();
+            if is_hidden(&path).not() {
+                paths.push(path);
+            }
+        }
+
+        Ok(paths)
+    };
+
+    for package in entries(registry)? {
+        if package.is_symlink() || package.is_dir().not() {
+            foreign.push(package);
+            continue;
+        }
+        for version in entries(&package)? {
+            if version.is_symlink() || version.is_dir().not() {
+                foreign.push(version);
+            }
+        }
+    }
+    foreign.sort();
+
+    Ok(foreign)
+}
+
+/// Replace the link at `link` with a copy of `target`, in one go
+fn materialize(link: &Path, target: &Path) -> Result<()> {
+    let temporary = wrapper::temporary_path(link);
+    let copied = fs::copy(target, &temporary)
+        .with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                target.display(),
+                temporary.display()
+            )
+        })
+        .and_then(|_| {
+            fs::rename(&temporary, link)
+                .with_context(|| format!("Failed to replace {}", link.display()))
+        });
+    // Nothing would tell a half-written copy from the user's own files later on
+    if copied.is_err() {
+        let _ = fs::remove_file(&temporary);
+    }
+
+    copied
+}
+
+impl Switcher {
+    /// The links into the registries in `.cargo/bin` and in every directory links were made into before, along with
+    /// the leftovers of links that were being replaced
+    fn purged_links(&self) -> Result<(Vec<PurgedLink>, Vec<PathBuf>)> {
+        let state = State::load(&self.registry)?;
+        let mut directories = vec![self.cargo_bin.clone()];
+        for directory in state.link_dirs {
+            if directories
+                .iter()
+                .any(|known| same_directory(known, &directory))
+                .not()
+            {
+                directories.push(directory);
+            }
+        }
+
+        let mut links = Vec::new();
+        let mut leftovers = Vec::new();
+        for directory in &directories {
+            let entries = match fs::read_dir(directory) {
+                Ok(entries) => entries,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => {
+                    return Err(err)
+                        .with_context(|| format!("Failed to read {}", directory.display()))
+                }
+            };
+            for maybe_entry in entries {
+                let link = maybe_entry?.path();
+                let Some(target) = self.link_target_in(directory, &link) else {
+                    continue;
+                };
+                let Some(relative) = self
+                    .registries()
+                    .find_map(|registry| target.strip_prefix(registry).ok())
+                else {
+                    continue;
+                };
+
+                if is_temporary(link.file_name().unwrap_or_default()) {
+                    leftovers.push(link);
+                    continue;
+                }
+                let mut components = relative.components();
+                let version = match (components.next(), components.next()) {
+                    (Some(package), Some(version)) if is_hidden(package.as_ref()).not() => {
+                        Some(format!(
+                            "{}@{}",
+                            package.as_os_str().to_string_lossy(),
+                            version.as_os_str().to_string_lossy()
+                        ))
+                    }
+                    _ => None,
+                };
+                links.push(PurgedLink {
+                    link,
+                    target,
+                    version,
+                });
+            }
+        }
+        links.sort_by(|a, b| a.link.cmp(&b.link));
+
+        Ok((links, leftovers))
+    }
+
+    /// Remove everything in the registry and then the registry itself, marking it as being purged first
+    fn remove_registry(&self) -> Result<()> {
+        let marker = self.registry.join(PURGE_MARKER_NAME);
+        fs::write(&marker, "").with_context(|| format!("Failed to create {}", marker.display()))?;
+
+        for maybe_entry in fs::read_dir(&self.registry)
+            .with_context(|| format!("Failed to read {}", self.registry.display()))?
+        {
+            let path = maybe_entry?.path();
+            if path == marker {
+                continue;
+            }
+            let removed = match path.is_symlink() || path.is_dir().not() {
+                true => fs::remove_file(&path),
+                false => fs::remove_dir_all(&path),
+            };
+            removed.with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+
+        fs::remove_file(&marker)
+            .with_context(|| format!("Failed to remove {}", marker.display()))?;
+        fs::remove_dir(&self.registry)
+            .with_context(|| format!("Failed to remove {}", self.registry.display()))
+    }
+
+    /// Replace every link made into the registries with a copy of the binary it points to, or remove them if
+    /// `keep_nothing`, and then remove the registry, asking first unless `yes`. Stops short of the registry if any
+    /// link couldn't be taken care of, so that running it again picks up where it left off.
+    pub fn purge(&self, keep_nothing: bool, yes: bool) -> Result<PurgeReport> {
+        // Everyone else's links point into it
+        if self.system {
+            bail!(
+                "--system would purge the shared registry at {}, which every user's links may point into. Purge \
+                 without --system to stop using cargo-switch yourself",
+                self.registry.display()
+            );
+        }
+        if self.cargo_bin.starts_with(&self.registry) {
+            bail!(
+                "{} is inside of the registry at {}, refusing to purge it",
+                self.cargo_bin.display(),
+                self.registry.display()
+            );
+        }
+        let registry_exists = self.registry.exists();
+        let resuming = self.registry.join(PURGE_MARKER_NAME).exists();
+        if registry_exists && resuming.not() {
+            let foreign = foreign_entries(&self.registry)?;
+            if let Some(first) = foreign.first() {
+                bail!(
+                    "{} holds {} file(s) cargo-switch didn't put there, such as {}, refusing to purge it. Move them \
+                     elsewhere first",
+                    self.registry.display(),
+                    foreign.len(),
+                    first.display()
+                );
+            }
+        }
+
+        let _links = match registry_exists {
+            true => Some(self.lock_links()?),
+            false => None,
+        };
+        let (links, leftovers) = self.purged_links()?;
+        if links.is_empty() && leftovers.is_empty() && registry_exists.not() {
+            println!(
+                "Nothing to purge, there's no registry at {}",
+                self.registry.display()
+            );
+            return Ok(PurgeReport::default());
+        }
+
+        let question = match keep_nothing {
+            true => format!(
+                "Remove {} link(s) to the binaries cargo-switch installed, and the registry at {} along with every \
+                 version in it? The binaries will be gone",
+                links.len(),
+                self.registry.display()
+            ),
+            false => format!(
+                "Replace {} link(s) with copies of the binaries they point to, and remove the registry at {} along \
+                 with every version in it?",
+                links.len(),
+                self.registry.display()
+            ),
+        };
+        if yes.not() && self.confirm(&question)?.not() {
+            bail!("Not purging. Pass --yes to purge without being asked");
+        }
+
+        let mut report = PurgeReport::default();
+        let mut failures = 0;
+        let mut packages = BTreeSet::new();
+        for PurgedLink {
+            link,
+            target,
+            version,
+        } in links
+        {
+            // Links to versions already gone have nothing to copy, and wouldn't work anyway
+            let outcome = match (keep_nothing, &version) {
+                (false, Some(version)) if target.exists() => {
+                    let wrapped = wrapper::wrapper_target(&link).is_some();
+                    materialize(&link, &target).map(|()| {
+                        if wrapped {
+                            report.unwrapped.push(link.clone());
+                        }
+                        report.materialized.push((link.clone(), version.clone()));
+                    })
+                }
+                _ => self
+                    .remove_link(&link)
+                    .map(|()| report.removed_links.push(link.clone())),
+            };
+
+            match outcome {
+                Ok(()) => {
+                    if let Some((package, _)) = version.as_deref().and_then(|v| v.split_once('@')) {
+                        packages.insert(package.to_owned());
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Warning: {err:#}");
+                    failures += 1;
+                }
+            }
+        }
+        for leftover in leftovers {
+            if let Err(err) = self.remove_link(&leftover) {
+                eprintln!("Warning: {err:#}");
+                failures += 1;
+            }
+        }
+
+        // cargo still knows about the binaries that were kept, but the ones removed are gone for good
+        if keep_nothing {
+            for package in &packages {
+                self.forget_cargo_records(package);
+            }
+        }
+
+        if failures > 0 {
+            print_report(&report, self);
+            bail!(
+                "Failed to take care of {failures} link(s), so the registry at {} was left in place. Run `cargo \
+                 switch purge` again once that's fixed",
+                self.registry.display()
+            );
+        }
+
+        if registry_exists {
+            self.remove_registry().with_context(|| {
+                format!(
+                    "Failed to remove the registry, run `cargo switch purge` again to remove what's left of it at {}",
+                    self.registry.display()
+                )
+            })?;
+            report.registry = Some(self.registry.clone());
+        }
+        print_report(&report, self);
+
+        Ok(report)
+    }
+}
+
+/// Print what was kept, what was removed and what purging leaves alone
+fn print_report(report: &PurgeReport, switcher: &Switcher) {
+    if report.materialized.is_empty().not() {
+        println!("Kept, as copies of the binaries they linked to:");
+        for (link, version) in &report.materialized {
+            let note = match report.unwrapped.contains(link) {
+                true => ", no longer setting the package's `env`",
+                false => "",
+            };
+            println!("  {} ({version}{note})", link.display());
+        }
+    }
+
+    if report.removed_links.is_empty().not() || report.registry.is_some() {
+        println!("Removed:");
+        for link in &report.removed_links {
+            println!("  {}", link.display());
+        }
+        if let Some(registry) = &report.registry {
+            println!(
+                "  {}, with every version, the state, the caches, the trash and the logs",
+                registry.display()
+            );
+        }
+    }
+
+    let mut left_alone = Vec::new();
+    if let Some(shared) = &switcher.shared_registry {
+        left_alone.push(format!("the shared registry at {}", shared.display()));
+    }
+    if let Some(binary_cache) = &switcher.config.binary_cache {
+        left_alone.push(format!("the binary cache at {}", binary_cache.display()));
+    }
+    if let Some(config) = Config::path().filter(|path| path.exists()) {
+        left_alone.push(format!("the config file at {}", config.display()));
+    }
+    if left_alone.is_empty().not() {
+        println!("Left alone:");
+        for entry in left_alone {
+            println!("  {entry}");
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Keeping the registry under `max-size`. After every install, if the registry grew past it, the versions that were
+//! switched to the longest ago, or else installed the longest ago, are evicted until it fits again.
+//!
+//! Whatever is in the trash goes first, see [`trash`]. The versions that are never pruned, the active one, the
+//! default one, the ones channels point to and the locked ones, are never evicted either, nor is the version that was
+//! just installed. If the registry can't fit without them, it's left over its size with a warning.
+//!
+//! [`trash`]: crate::trash
+
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::time::Duration;
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::format::human_size;
+use crate::metadata::VersionMetadata;
+use crate::operation_log::Operation;
+use crate::state::State;
+use crate::Switcher;
+
+/// How much space everything under `path` takes, in bytes. Symlinks count for themselves, not for what they point
+/// to.
+pub fn directory_size(path: &Path) -> Result<u64> {
+    let metadata = match path.symlink_metadata() {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    if metadata.is_dir().not() {
+        return Ok(metadata.len());
+    }
+
+    let mut size = 0;
+    for maybe_entry in
+        fs::read_dir(path).with_context(|| format!("Failed to read {}", path.display()))?
+    {
+        size += directory_size(&maybe_entry?.path())?;
+    }
+
+    Ok(size)
+}
+
+/// A version that may be evicted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub package: String,
+    pub version: String,
+    /// In bytes
+    pub size: u64,
+    /// When the version was last switched to, in seconds since the Unix epoch
+    pub activated_at: Option<u64>,
+    /// When the version was installed, in seconds since the Unix epoch
+    pub installed_at: Option<u64>,
+}
+
+impl Candidate {
+    /// When the version was last of use, as far as we know. Versions nothing is known about count as the oldest.
+    fn last_used(&self) -> u64 {
+        self.activated_at.or(self.installed_at).unwrap_or(0)
+    }
+
+    /// Why the version was picked for eviction
+    fn reason(&self) -> String {
+        let at = |timestamp: u64| {
+            humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(timestamp))
+        };
+
+        match (self.activated_at, self.installed_at) {
+            (Some(activated_at), _) => format!("last switched to {}", at(activated_at)),
+            (None, Some(installed_at)) => {
+                format!("never switched to, installed {}", at(installed_at))
+            }
+            (None, None) => "never switched to".to_owned(),
+        }
+    }
+}
+
+/// The versions among `candidates` to evict so that a registry of `total` bytes fits in `max_size`, the ones that
+/// went unused the longest first, along with the size the registry is left with. That size is still over
+/// `max_size` when evicting every candidate isn't enough.
+pub fn eviction_plan(
+    mut candidates: Vec<Candidate>,
+    total: u64,
+    max_size: u64,
+) -> (Vec<Candidate>, u64) {
+    candidates.sort_by(|a, b| {
+        (a.last_used(), &a.package, &a.version).cmp(&(b.last_used(), &b.package, &b.version))
+    });
+
+    let mut left = total;
+    let mut evicted = Vec::new();
+    for candidate in candidates {
+        if left <= max_size {
+            break;
+        }
+        left = left.saturating_sub(candidate.size);
+        evicted.push(candidate);
+    }
+
+    (evicted, left)
+}
+
+impl Switcher {
+    /// Every version that may be evicted, which leaves out protected ones and `package@version`
+    fn eviction_candidates(&self, package: &str, version: &str) -> Result<Vec<Candidate>> {
+        let state = State::load(&self.registry)?;
+
+        let mut candidates = Vec::new();
+        for installed_package in self.installed_packages()? {
+            let mut protected = self.protected_versions(&installed_package)?;
+            // Removing what the running executable was started from is left for once it's done
+            protected.extend(self.running_version(&installed_package));
+            let activated_at = state.activated_at.get(&installed_package);
+            for installed_version in self.installed_versions(&installed_package)? {
+                if protected.contains(&installed_version)
+                    || (installed_package == package && installed_version == version)
+                    || self.is_shared(&installed_package, &installed_version)
+                {
+                    continue;
+                }
+
+                let path = self
+                    .registry
+                    .join(&installed_package)
+                    .join(&installed_version);
+                let metadata = VersionMetadata::load(&path)?;
+                candidates.push(Candidate {
+                    size: directory_size(&path)?,
+                    activated_at: activated_at
+                        .and_then(|activated_at| activated_at.get(&installed_version))
+                        .copied(),
+                    installed_at: metadata.and_then(|metadata| metadata.installed_at),
+                    package: installed_package.clone(),
+                    version: installed_version,
+                });
+            }
+        }
+
+        Ok(candidates)
+    }
+
+    /// Evict versions until the registry fits in `max-size`, if it's configured, sparing `package@version` on top
+    /// of the protected versions
+    pub(crate) fn enforce_max_size(&self, package: &str, version: &str) -> Result<()> {
+        let Some(max_size) = self.config.max_size else {
+            return Ok(());
+        };
+        let total = directory_size(&self.registry)?;
+        if total <= max_size {
+            return Ok(());
+        }
+        // What was already uninstalled goes before anything that's still installed
+        let total = self.purge_trash_to_fit(total, max_size)?;
+        if total <= max_size {
+            return Ok(());
+        }
+
+        let state = State::load(&self.registry)?;
+        let locked: usize = state.locked.values().map(|versions| versions.len()).sum();
+        if locked > 0 {
+            println!("Kept {locked} locked version(s), which are never evicted");
+        }
+
+        let candidates = self.eviction_candidates(package, version)?;
+        let (evicted, _) = eviction_plan(candidates, total, max_size);
+        let mut size = total;
+        for candidate in evicted {
+            let Candidate {
+                package, version, ..
+            } = &candidate;
+
+            let _lock = self.lock_package(package)?;
+            // Whatever was switched to in the meantime isn't up for eviction anymore
+            if self.protected_versions(package)?.contains(version)
+                || self.running_version(package).as_ref() == Some(version)
+                || self.installed_versions(package)?.contains(version).not()
+            {
+                continue;
+            }
+            // Going to the trash wouldn't free anything
+            self.uninstall_unlocked(package, version, false)
+                .with_context(|| format!("Failed to evict {package}@{version}"))?;
+            size = size.saturating_sub(candidate.size);
+            self.log(
+                Operation::Prune,
+                Some(package),
+                Some(version),
+                format!(
+                    "evicted to keep the registry under its max-size of {}",
+                    human_size(max_size)
+                ),
+            );
+            println!(
+                "Evicted {package}@{version} ({}, {}) to keep the registry under its max-size of {}",
+                human_size(candidate.size),
+                candidate.reason(),
+                human_size(max_size)
+            );
+        }
+
+        if size > max_size {
+            eprintln!(
+                "Warning: the registry still takes {}, over its max-size of {}. What's left is active, a default, \
+                 pointed to by a channel, locked or was just installed, which is never evicted. Uninstall something, or raise \
+                 `max-size`",
+                human_size(size),
+                human_size(max_size)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::directory_size;
+    use super::eviction_plan;
+    use super::Candidate;
+
+    fn candidate(
+        version: &str,
+        size: u64,
+        activated_at: Option<u64>,
+        installed_at: u64,
+    ) -> Candidate {
+        Candidate {
+            package: "tool".to_owned(),
+            version: version.to_owned(),
+            size,
+            activated_at,
+            installed_at: Some(installed_at),
+        }
+    }
+
+    #[test]
+    fn evicts_what_went_unused_the_longest() {
+        let candidates = vec![
+            candidate("1.0.0", 10, Some(300), 100),
+            candidate("1.1.0", 10, None, 200),
+            candidate("1.2.0", 10, Some(250), 150),
+        ];
+        let versions = |evicted: &[Candidate]| -> Vec<String> {
+            evicted
+                .iter()
+                .map(|candidate| candidate.version.clone())
+                .collect()
+        };
+
+        let (evicted, left) = eviction_plan(candidates.clone(), 50, 35);
+        assert_eq!(versions(&evicted), ["1.1.0", "1.2.0"]);
+        assert_eq!(left, 30);
+
+        assert!(eviction_plan(candidates.clone(), 50, 50).0.is_empty());
+
+        // Everything goes when it's still not enough
+        let (evicted, left) = eviction_plan(candidates, 50, 5);
+        assert_eq!(versions(&evicted), ["1.1.0", "1.2.0", "1.0.0"]);
+        assert_eq!(left, 20);
+    }
+
+    #[test]
+    fn measures_directories() {
+        let root = tempfile::tempdir().unwrap();
+        let nested = root.path().join("bin");
+        fs::create_dir(&nested).unwrap();
+        fs::write(root.path().join("a"), [0; 100]).unwrap();
+        fs::write(nested.join("b"), [0; 50]).unwrap();
+
+        assert_eq!(directory_size(root.path()).unwrap(), 150);
+        assert_eq!(directory_size(&root.path().join("missing")).unwrap(), 0);
+    }
+}
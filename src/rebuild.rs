@@ -0,0 +1,258 @@
+//! `rebuild`: building an installed version again, the way it was first built, e.g. after a system upgrade left its
+//! binaries unable to run. The new build is staged next to the registry and only swapped in once it succeeded, so a
+//! failed rebuild leaves the version as it was.
+
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::format::human_duration;
+use crate::install::discard_install;
+use crate::install::BuildPlan;
+use crate::install::InstallOptions;
+use crate::interrupt;
+use crate::lockfile::prepare_locked_sources;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::retry;
+use crate::spec::parse_spec;
+use crate::spec::split_label;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::variant::split_variant;
+use crate::variant::variant_profile;
+use crate::Switcher;
+
+/// Directory, inside the registry, that versions are rebuilt into before replacing the old builds
+const STAGING_DIRECTORY_NAME: &str = ".rebuild";
+
+impl Switcher {
+    fn rebuild_staging(&self, package: &str, version: &str) -> PathBuf {
+        self.registry
+            .join(STAGING_DIRECTORY_NAME)
+            .join(package)
+            .join(version)
+    }
+
+    /// Rebuild `package@version` in place, from the very dependencies it was first built with if
+    /// `locked_from_original` is set, returning how long the build took
+    fn rebuild_version(
+        &self,
+        package: &str,
+        version: &str,
+        locked_from_original: bool,
+    ) -> Result<Duration> {
+        let version_path = self.version_path(package, version);
+        if version_path.exists().not() {
+            return Err(self.not_installed(package, Some(version)));
+        }
+        self.ensure_not_shared(package, version, "rebuild")?;
+
+        let _lock = self.lock_package(package)?;
+        let metadata = VersionMetadata::load(&version_path)?.unwrap_or_else(|| {
+            eprintln!(
+                "Warning: nothing was recorded about how {package}@{version} was built, rebuilding it from crates.io \
+                 with the default options"
+            );
+            VersionMetadata::default()
+        });
+
+        let (bare_version, variant) = split_variant(version);
+        let profile = metadata
+            .profile
+            .clone()
+            .unwrap_or_else(|| variant_profile(variant).to_owned());
+        // Stripped binaries recorded the size they had before
+        let strip = metadata
+            .binaries
+            .iter()
+            .any(|binary| binary.unstripped_size.is_some());
+        // Builds from before flags were recorded get the ones the config file has for the package now
+        let flags = metadata
+            .flags
+            .clone()
+            .unwrap_or_else(|| self.build_flags(package, &InstallOptions::default()).1);
+        let mut plan = BuildPlan {
+            name: package,
+            version: bare_version,
+            profile: &profile,
+            features: &metadata.features,
+            path: None,
+            target: None,
+            from_url: None,
+            sha256: None,
+            git: None,
+            rev: None,
+            vendored: None,
+            locked: None,
+            branch: None,
+            flags: &flags,
+            timeout: self.config.build_timeout,
+            require_checksum: false,
+            strip,
+            retries: self.config.retries.unwrap_or(retry::DEFAULT_RETRIES),
+        };
+        match &metadata.source {
+            None | Some(Source::CratesIo) => {}
+            Some(Source::Path { path }) => plan.path = Some(path),
+            Some(Source::Url { url, sha256 }) => {
+                plan.from_url = Some(url);
+                plan.sha256 = Some(sha256);
+            }
+            // The same commit, wherever the branch went since
+            Some(Source::Git {
+                url,
+                rev: Some(rev),
+                branch,
+            }) => {
+                plan.git = Some(url);
+                plan.rev = Some(rev);
+                plan.branch = branch.as_deref();
+            }
+            Some(Source::Git { rev: None, .. }) => bail!(
+                "{package}@{version} was built from git, but which commit it was built from wasn't recorded"
+            ),
+            Some(Source::External) => bail!(
+                "{package}@{version} was registered through add-binary, so there's nothing to rebuild it from"
+            ),
+        }
+
+        let staging = self.rebuild_staging(package, version);
+        // Whatever an earlier rebuild left behind is of no use
+        discard_install(&staging);
+        let _remove_on_interrupt = interrupt::remove_on_interrupt(&staging);
+        fs::create_dir_all(&staging)
+            .with_context(|| format!("Failed to create {}", staging.display()))?;
+
+        // Cargo only builds from a lockfile sitting next to the sources, so they get a copy of their own
+        let mut locked_sources = staging.as_os_str().to_owned();
+        locked_sources.push(".sources");
+        let locked_sources = PathBuf::from(locked_sources);
+        let _remove_sources_on_interrupt = interrupt::remove_on_interrupt(&locked_sources);
+        if locked_from_original {
+            let prepared = self.prepare_original_sources(package, version, &plan, &locked_sources);
+            if let Err(err) = prepared {
+                let _ = fs::remove_dir_all(&locked_sources);
+                discard_install(&staging);
+                return Err(err);
+            }
+            plan.locked = Some(&locked_sources);
+        }
+        let rebuilt = self.build_version(&plan, &staging, true);
+        let _ = fs::remove_dir_all(&locked_sources);
+        let rebuilt = rebuilt?;
+
+        let was_active = self.linked_version(package)?.as_deref() == Some(version);
+        self.swap_in(&staging, &version_path)?;
+        discard_install(&staging);
+        self.emit_install_finished(package, version, &version_path, &rebuilt);
+
+        // The new build may not provide the same binaries as the old one
+        if was_active {
+            self.switch_package_unlocked(&format!("{package}@{version}"))?;
+        }
+
+        Ok(rebuilt.build_duration().unwrap_or_default())
+    }
+
+    /// Make `sources` into what `package@version`, to be rebuilt as `plan` says, was built from, along with the
+    /// `Cargo.lock` it was built with
+    fn prepare_original_sources(
+        &self,
+        package: &str,
+        version: &str,
+        plan: &BuildPlan,
+        sources: &Path,
+    ) -> Result<()> {
+        let lockfile = self.kept_lockfile(package, version)?;
+        match plan.path {
+            Some(path) => prepare_locked_sources(path, &lockfile, sources),
+            None if plan.git.is_none() => {
+                let (crate_name, _) = split_label(package);
+                let downloaded = sources.with_extension("crate");
+                let result = self
+                    .download_crate(crate_name, plan.version, &downloaded, plan.retries)
+                    .and_then(|_| prepare_locked_sources(&downloaded, &lockfile, sources));
+                let _ = fs::remove_dir_all(&downloaded);
+                result
+            }
+            None => bail!(
+                "{package}@{version} was built from git, which can't be rebuilt from its original Cargo.lock yet"
+            ),
+        }
+    }
+
+    /// Replace the version at `version_path` with the build at `staging`, leaving the old build at `staging`
+    fn swap_in(&self, staging: &Path, version_path: &Path) -> Result<()> {
+        let mut old = staging.as_os_str().to_owned();
+        old.push(".old");
+        let old = PathBuf::from(old);
+        discard_install(&old);
+
+        // Links keep pointing into the version's directory, so they only go missing for the moment between renames
+        let _links = self.lock_links()?;
+        fs::rename(version_path, &old)
+            .with_context(|| format!("Failed to move {} out of the way", version_path.display()))?;
+        if let Err(err) = fs::rename(staging, version_path) {
+            let _ = fs::rename(&old, version_path);
+            return Err(err).with_context(|| {
+                format!("Failed to move the rebuild to {}", version_path.display())
+            });
+        }
+        fs::rename(&old, staging)?;
+
+        Ok(())
+    }
+
+    /// Rebuild `package`, as in `name@version`, in place, from the dependencies it was first built with if
+    /// `locked_from_original` is set
+    pub fn rebuild(&self, package: &str, locked_from_original: bool) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let (name, version) = parse_spec(package)?;
+        let version = self.pick_variant(name, version)?;
+        let build_duration = self.rebuild_version(name, &version, locked_from_original)?;
+
+        println!(
+            "Rebuilt {name}@{version} in {}",
+            human_duration(build_duration)
+        );
+
+        Ok(())
+    }
+
+    /// Rebuild every installed version, carrying on past failures unless failing fast, and summing it all up in a
+    /// table
+    pub fn rebuild_all(&self, locked_from_original: bool) -> Result<()> {
+        let mut summary = Summary::new(self.failure_mode);
+        for package in self.installed_packages()? {
+            for version in self.installed_versions(&package)? {
+                // The shared registry's versions are for its admins to rebuild
+                if self.is_shared(&package, &version) {
+                    continue;
+                }
+                let spec = format!("{package}@{version}");
+                if summary.skip_if_stopped(&spec) {
+                    continue;
+                }
+
+                match self.rebuild_version(&package, &version, locked_from_original) {
+                    Ok(build_duration) => {
+                        summary.record(spec, Outcome::Rebuilt, Some(human_duration(build_duration)))
+                    }
+                    Err(err) => {
+                        eprintln!("Failed to rebuild {spec}: {err:#}");
+                        summary.record_failure(spec, &err);
+                    }
+                }
+            }
+        }
+
+        self.finish_summary(summary, "versions failed to rebuild")
+    }
+}
@@ -0,0 +1,333 @@
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::ops::Not;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::format::Record;
+use crate::format::Template;
+use crate::links::ActiveState;
+use crate::project::Pin;
+use crate::project::PROJECT_FILE_NAME;
+use crate::state::State;
+use crate::tool_versions::TOOL_VERSIONS_FILE_NAME;
+use crate::version;
+use crate::Switcher;
+
+/// What decided which version of a package gets used
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// A per-shell override through an environment variable
+    EnvOverride { variable: String },
+    /// A pin in a project's `.cargo-switch.toml` or `.tool-versions`
+    ProjectPin { path: PathBuf },
+    /// The version set through `cargo switch default`
+    Default,
+    /// Nothing else applied, so the newest installed version was picked
+    NewestInstalled,
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::EnvOverride { variable } => write!(f, "overridden by ${variable}"),
+            Rule::ProjectPin { path } => write!(f, "pinned by {}", path.display()),
+            Rule::Default => write!(f, "default version"),
+            Rule::NewestInstalled => write!(f, "newest installed version"),
+        }
+    }
+}
+
+/// A source of versions consulted while resolving one, and what it said
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    /// What was consulted, as in `$CARGO_SWITCH_TOOL_VERSION` or the path of a project file
+    pub source: String,
+    pub finding: String,
+    /// Whether it decided the version
+    pub matched: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resolution {
+    pub version: String,
+    pub rule: Rule,
+}
+
+/// The environment variable that overrides the version of `package` used by the current shell, e.g.
+/// `CARGO_SWITCH_SQLX_CLI_VERSION` for `sqlx-cli`. Anything shells don't take in a variable name, like the `#` of
+/// labels, becomes a `_`
+pub fn override_variable(package: &str) -> String {
+    let name: String = package
+        .chars()
+        .map(|ch| match ch.is_ascii_alphanumeric() {
+            true => ch.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+
+    format!("CARGO_SWITCH_{name}_VERSION")
+}
+
+impl Switcher {
+    /// Decide which version of `package` should be used when none was explicitly asked for.
+    ///
+    /// The first rule that applies wins: the per-shell environment override, then the closest project pin, then the
+    /// default version and finally the newest installed version.
+    pub fn resolve_version(&self, package: &str) -> Result<Resolution> {
+        Ok(self.trace_version(package, |name| env::var_os(name))?.0)
+    }
+
+    /// [`Switcher::resolve_version`], along with every source of versions consulted on the way, in order. `variable`
+    /// looks up environment variables.
+    pub fn trace_version(
+        &self,
+        package: &str,
+        variable: impl Fn(&str) -> Option<OsString>,
+    ) -> Result<(Resolution, Vec<Step>)> {
+        let package = &self.canonical_name(package)?;
+        let installed = self.installed_versions(package)?;
+        let mut steps = Vec::new();
+
+        let override_variable = override_variable(package);
+        let directory = env::current_dir()?;
+        let project_pins = self.project_pins(&directory)?;
+        let state = State::load(&self.registry)?;
+
+        let override_version = variable(&override_variable);
+        steps.push(Step {
+            source: format!("${override_variable}"),
+            finding: match &override_version {
+                Some(version) => format!("set to {}", version.to_string_lossy()),
+                None => "not set".to_owned(),
+            },
+            matched: override_version.is_some(),
+        });
+
+        let pin = match (&override_version, project_pins) {
+            (Some(_), _) => None,
+            (None, None) => {
+                steps.push(Step {
+                    source: "project pins".to_owned(),
+                    finding: format!(
+                        "no {PROJECT_FILE_NAME} or {TOOL_VERSIONS_FILE_NAME} in {} or its parents",
+                        directory.display()
+                    ),
+                    matched: false,
+                });
+                None
+            }
+            (None, Some(mut project_pins)) => {
+                for conflict in &project_pins.conflicts {
+                    if conflict.package == *package {
+                        eprintln!("Warning: {conflict}");
+                    }
+                }
+                let pin = project_pins.pins.remove(package);
+                steps.push(Step {
+                    source: match &pin {
+                        Some(Pin { path, .. }) => path.display().to_string(),
+                        None => format!("project pins under {}", project_pins.root.display()),
+                    },
+                    finding: match &pin {
+                        Some(Pin { version, .. }) => format!("pins {version}"),
+                        None => format!("nothing pins {package}"),
+                    },
+                    matched: pin.is_some(),
+                });
+                pin
+            }
+        };
+
+        let default = match (&override_version, &pin) {
+            (None, None) => {
+                let default = state.defaults.get(package);
+                steps.push(Step {
+                    source: "default version".to_owned(),
+                    finding: match default {
+                        Some(version) => format!("set to {version}"),
+                        None => "not set".to_owned(),
+                    },
+                    matched: default.is_some(),
+                });
+                default
+            }
+            _ => None,
+        };
+
+        let resolution = if let Some(version) = override_version {
+            Resolution {
+                version: version.to_string_lossy().into_owned(),
+                rule: Rule::EnvOverride {
+                    variable: override_variable,
+                },
+            }
+        } else if let Some(Pin { version, path }) = pin {
+            Resolution {
+                version,
+                rule: Rule::ProjectPin { path },
+            }
+        } else if let Some(version) = default {
+            Resolution {
+                version: version.clone(),
+                rule: Rule::Default,
+            }
+        } else {
+            let pre = self.allows_prereleases(package);
+            let newest = version::newest_version(&installed, pre)
+                .with_context(|| format!("Project {package} has no installed versions"))?;
+            steps.push(Step {
+                source: "installed versions".to_owned(),
+                finding: if installed.last() == Some(newest) {
+                    format!("{newest} is the newest")
+                } else {
+                    format!("{newest} is the newest that isn't a pre-release, pass --pre to consider those")
+                },
+                matched: true,
+            });
+            Resolution {
+                version: newest.clone(),
+                rule: Rule::NewestInstalled,
+            }
+        };
+
+        if installed.contains(&resolution.version).not() {
+            let Resolution { version, rule } = resolution;
+            match rule {
+                Rule::Default => bail!(
+                    "The default version of {package} is {version}, which is no longer installed. \
+                     Reinstall it or run `cargo switch default {package} --unset`"
+                ),
+                rule => bail!("{package}@{version} ({rule}) is not installed!"),
+            }
+        }
+
+        Ok((resolution, steps))
+    }
+
+    pub fn set_default(&self, package: &str, version: &str) -> Result<()> {
+        self.ensure_per_user("set default versions")?;
+        let package = &self.canonical_name(package)?;
+        if self
+            .installed_versions(package)?
+            .iter()
+            .any(|installed| installed == version)
+            .not()
+        {
+            return Err(self.not_installed(package, Some(version)));
+        }
+
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        state
+            .defaults
+            .insert(package.to_owned(), version.to_owned());
+        state.save(&self.registry)?;
+
+        println!("The default version of {package} is now {version}");
+
+        Ok(())
+    }
+
+    pub fn unset_default(&self, package: &str) -> Result<()> {
+        self.ensure_per_user("unset default versions")?;
+        let package = &self.canonical_name(package)?;
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        ensure!(
+            state.defaults.remove(package).is_some(),
+            "No default version is set for {package}"
+        );
+        state.save(&self.registry)?;
+
+        println!("Unset the default version of {package}");
+
+        Ok(())
+    }
+
+    pub fn print_default(&self, package: &str) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        let state = State::load(&self.registry)?;
+
+        match state.defaults.get(package) {
+            Some(version) if self.installed_versions(package)?.contains(version) => {
+                println!("{package}@{version}")
+            }
+            Some(version) => println!("{package}@{version} (no longer installed)"),
+            None => println!("No default version is set for {package}"),
+        }
+
+        Ok(())
+    }
+
+    /// Print which version of each package (or only of `package`) would be used, and why
+    pub fn print_current(&self, package: Option<&str>, format: Option<&Template>) -> Result<()> {
+        let explicit = package.is_some();
+        let packages = match package {
+            Some(package) => vec![package.to_owned()],
+            None => self.installed_packages()?,
+        };
+
+        for package in packages {
+            let resolution = match self.resolve_version(&package) {
+                Ok(resolution) => resolution,
+                // Report the broken package but keep on going if we were asked about all of them
+                Err(err) if explicit.not() => {
+                    eprintln!("{package}: {err}");
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            if let Some(template) = format {
+                let listing = self.package_listing(&package)?;
+                if let Some(version) = listing
+                    .versions
+                    .iter()
+                    .find(|listing| listing.version == resolution.version)
+                {
+                    println!("{}", template.render(&Record::new(&package, version)));
+                }
+                continue;
+            }
+
+            println!("{package}@{} ({})", resolution.version, resolution.rule);
+
+            match self.active_state(&package)? {
+                ActiveState::Active { version } if version != resolution.version => {
+                    println!("  note: .cargo/bin currently links to {package}@{version}")
+                }
+                ActiveState::Active { .. } => {}
+                ActiveState::Broken { version } => {
+                    println!("  note: the links to {package}@{version} in .cargo/bin are broken")
+                }
+                ActiveState::Inactive => println!("  note: .cargo/bin has no links to {package}"),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::override_variable;
+
+    #[test]
+    fn names_override_variables_shells_can_export() {
+        assert_eq!(override_variable("ripgrep"), "CARGO_SWITCH_RIPGREP_VERSION");
+        assert_eq!(
+            override_variable("sqlx-cli"),
+            "CARGO_SWITCH_SQLX_CLI_VERSION"
+        );
+        assert_eq!(
+            override_variable("ripgrep#fork"),
+            "CARGO_SWITCH_RIPGREP_FORK_VERSION"
+        );
+    }
+}
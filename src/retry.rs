@@ -0,0 +1,176 @@
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+
+/// How many times failed operations are retried when neither `--retries` nor the `retries` config key say otherwise
+pub const DEFAULT_RETRIES: u32 = 2;
+
+/// Bits of cargo's (and curl's) error messages that mean the failure was caused by the network rather than by the
+/// package itself
+const NETWORK_ERROR_PATTERNS: &[&str] = &[
+    "spurious network error",
+    "network failure",
+    "failed to download",
+    "failed to fetch",
+    "failed to update registry",
+    "failed to query replaced source registry",
+    "couldn't resolve host",
+    "could not resolve host",
+    "failed to connect",
+    "connection reset",
+    "connection refused",
+    "operation timed out",
+    "ssl connect error",
+    "[28] timeout",
+    "[35] ssl",
+    "[56] failure when receiving data",
+];
+
+/// Whether the stderr of a failed cargo invocation suggests it failed because of the network
+pub fn looks_like_network_error(stderr: &str) -> bool {
+    let stderr = stderr.to_ascii_lowercase();
+
+    NETWORK_ERROR_PATTERNS
+        .iter()
+        .any(|pattern| stderr.contains(pattern))
+}
+
+/// Why an attempt failed, which decides whether it's worth trying again
+pub enum Failure {
+    /// Might succeed if tried again, e.g. a network hiccup
+    Transient(anyhow::Error),
+    /// Will fail again no matter what, e.g. a compile error
+    Permanent(anyhow::Error),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub retries: u32,
+    /// How long to wait before the first retry. Every following retry waits twice as long as the previous one
+    pub initial_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            initial_delay: Duration::from_secs(2),
+        }
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+    }
+
+    /// Run `operation` until it succeeds, fails permanently or runs out of retries. The final error says how many
+    /// attempts were made.
+    pub fn run<T>(
+        &self,
+        what: &str,
+        mut operation: impl FnMut() -> Result<T, Failure>,
+    ) -> Result<T> {
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let err = match operation() {
+                Ok(value) => return Ok(value),
+                Err(Failure::Transient(err)) if attempts <= self.retries => err,
+                Err(Failure::Transient(err) | Failure::Permanent(err)) => {
+                    return Err(err.context(format!(
+                        "Failed to {what} after {attempts} attempt{}",
+                        if attempts == 1 { "" } else { "s" }
+                    )))
+                }
+            };
+
+            let delay = self.delay(attempts - 1);
+            eprintln!(
+                "Failed to {what} ({err:#}), retrying in {}s ({} of {} retries)",
+                delay.as_secs(),
+                attempts,
+                self.retries
+            );
+            thread::sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+    use std::time::Duration;
+
+    use anyhow::anyhow;
+
+    use super::looks_like_network_error;
+    use super::Failure;
+    use super::RetryPolicy;
+
+    const POLICY: RetryPolicy = RetryPolicy {
+        retries: 2,
+        initial_delay: Duration::ZERO,
+    };
+
+    #[test]
+    fn detects_network_errors() {
+        assert!(looks_like_network_error(
+            "warning: spurious network error (2 tries remaining): [28] Timeout was reached"
+        ));
+        assert!(looks_like_network_error(
+            "error: failed to download from `https://static.crates.io/crates/ripgrep/14.1.0/download`"
+        ));
+        assert!(looks_like_network_error(
+            "Caused by:\n  [6] Couldn't resolve host name (Could not resolve host: index.crates.io)"
+        ));
+
+        assert!(looks_like_network_error("error[E0308]: mismatched types").not());
+        assert!(looks_like_network_error(
+            "error: could not find `ripgerp` in registry `crates-io` with version `=14.1.0`"
+        )
+        .not());
+    }
+
+    #[test]
+    fn retries_transient_failures() {
+        let mut attempts = 0;
+        let err = POLICY
+            .run("install", || -> Result<(), _> {
+                attempts += 1;
+                Err(Failure::Transient(anyhow!("network is down")))
+            })
+            .unwrap_err();
+
+        assert_eq!(attempts, 3);
+        assert_eq!(err.to_string(), "Failed to install after 3 attempts");
+
+        let mut attempts = 0;
+        let value = POLICY
+            .run("install", || {
+                attempts += 1;
+                match attempts {
+                    1 => Err(Failure::Transient(anyhow!("network is down"))),
+                    _ => Ok(attempts),
+                }
+            })
+            .unwrap();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn does_not_retry_permanent_failures() {
+        let mut attempts = 0;
+        let err = POLICY
+            .run("install", || -> Result<(), _> {
+                attempts += 1;
+                Err(Failure::Permanent(anyhow!("compile error")))
+            })
+            .unwrap_err();
+
+        assert_eq!(attempts, 1);
+        assert_eq!(err.to_string(), "Failed to install after 1 attempt");
+    }
+}
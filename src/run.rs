@@ -0,0 +1,324 @@
+use std::env;
+use std::ffi::CString;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::process::CommandExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::ExitStatus;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::events::error_kind;
+use crate::events::ErrorKind;
+use crate::install::InstallOptions;
+use crate::installer::BuildOptions;
+use crate::interrupt;
+use crate::retry;
+use crate::retry::RetryPolicy;
+use crate::spec::split_label;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// What `run` does about a version that isn't installed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Missing {
+    /// Fail, unless the `ephemeral` config key says to install it
+    #[default]
+    Fail,
+    /// Install it into the registry without switching to it, so that it's there for the next run
+    Install,
+    /// Install it into a directory of its own, removed once it's done running
+    Temporary,
+}
+
+/// Pick which of `binaries` of `package@version` to run: the one named `bin` if given, the only one if there's a
+/// single binary, or the one named after the package otherwise
+fn pick_binary(
+    package: &str,
+    version: &str,
+    binaries: &[PathBuf],
+    bin: Option<&OsStr>,
+) -> Result<PathBuf> {
+    let named = |name: &OsStr| {
+        binaries.iter().find(|binary| {
+            binary
+                .file_name()
+                .is_some_and(|file_name| file_name == name)
+        })
+    };
+
+    if let Some(bin) = bin {
+        return named(bin).cloned().with_context(|| {
+            format!(
+                "{package}@{version} has no binary named {}",
+                Path::new(bin).display()
+            )
+        });
+    }
+
+    match binaries {
+        [] => bail!("{package}@{version} has no binaries"),
+        [binary] => Ok(binary.clone()),
+        _ => named(OsStr::new(package)).cloned().with_context(|| {
+            let names: Vec<_> = binaries
+                .iter()
+                .filter_map(|binary| binary.file_name())
+                .map(|file_name| file_name.to_string_lossy())
+                .collect();
+            format!(
+                "{package}@{version} has several binaries, pick one with --bin: {}",
+                names.join(", ")
+            )
+        }),
+    }
+}
+
+/// The code to exit with after a child exited with `status`: its own, or 128 plus the signal that killed it, as shells
+/// have it
+pub fn exit_code(status: ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => 1,
+    }
+}
+
+/// The versions listed, or none if listing them failed because the package was never installed at all. Anything else,
+/// such as an unreadable registry, is no reason to install it again.
+fn none_if_not_installed(versions: Result<Vec<String>>) -> Result<Vec<String>> {
+    match versions {
+        Err(err) if error_kind(&err) == ErrorKind::NotInstalled => Ok(Vec::new()),
+        versions => versions,
+    }
+}
+
+/// Create a directory for `run --temp` under the temporary directory, which is shared with everyone else: with a
+/// random name, failing rather than reusing anything already there, and only accessible to us
+fn private_temp_dir() -> Result<PathBuf> {
+    let template = env::temp_dir().join("cargo-switch-run-XXXXXX");
+    let mut template = CString::new(template.into_os_string().into_vec())
+        .with_context(|| "The temporary directory has a NUL byte in its path")?
+        .into_bytes_with_nul();
+    // Replaces the Xs and creates the directory with mode 0700
+    if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+        return Err(io::Error::last_os_error()).with_context(|| {
+            format!(
+                "Failed to create a directory in {}",
+                env::temp_dir().display()
+            )
+        });
+    }
+    template.pop();
+
+    Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+impl Switcher {
+    /// Pick which binary of `package@version` to run, see [`pick_binary`]
+    fn binary_to_run(&self, package: &str, version: &str, bin: Option<&OsStr>) -> Result<PathBuf> {
+        pick_binary(
+            package,
+            version,
+            &self.version_binaries(package, version)?,
+            bin,
+        )
+    }
+
+    /// The version of `package` to run when none was given and it isn't installed at all: its newest release
+    fn newest_release(&self, package: &str) -> Result<String> {
+        let policy = RetryPolicy::new(self.config.retries.unwrap_or(retry::DEFAULT_RETRIES));
+        let crates_io = self.crates_io()?;
+        let newest = policy
+            .run(&format!("look up the newest {package}"), || {
+                crates_io.newest_version(split_label(package).0, self.allows_prereleases(package))
+            })
+            .with_context(|| {
+                format!("Couldn't reach crates.io to look for the newest {package}")
+            })?;
+
+        Ok(newest.to_string())
+    }
+
+    /// Run a binary of `package` straight from the registry, without touching the links in `.cargo/bin`.
+    ///
+    /// If `version` isn't given, the version is chosen by [`Switcher::resolve_version`], or is the newest release of
+    /// a package that isn't installed at all. What happens to versions that aren't installed is up to `missing`.
+    ///
+    /// On success, this only returns for versions installed with [`Missing::Temporary`], once the binary exited.
+    /// Otherwise the current process is replaced by the binary, which therefore keeps its exit code and signals.
+    pub fn run_package(
+        &self,
+        package: &str,
+        version: Option<&str>,
+        bin: Option<&OsStr>,
+        args: &[OsString],
+        missing: Missing,
+    ) -> Result<ExitStatus> {
+        let package = &self.canonical_name(package)?;
+        let missing = match missing {
+            Missing::Fail if self.config.ephemeral => Missing::Install,
+            missing => missing,
+        };
+        let installed = match version {
+            Some(version) => match self.pick_variant(package, version) {
+                Ok(version) => Some(version),
+                // Only what isn't installed at all is worth installing, not a variant that wasn't picked
+                Err(err) if missing != Missing::Fail => {
+                    match none_if_not_installed(self.installed_variants(package, version))?
+                        .is_empty()
+                    {
+                        true => None,
+                        false => return Err(err),
+                    }
+                }
+                Err(err) => return Err(err),
+            },
+            None if missing != Missing::Fail
+                && none_if_not_installed(self.installed_versions(package))?.is_empty() =>
+            {
+                None
+            }
+            None => Some(self.resolve_version(package)?.version),
+        };
+
+        let version = match (installed, missing) {
+            (Some(version), _) => version,
+            (None, Missing::Fail) => {
+                unreachable!("only versions that may be installed are missing")
+            }
+            (None, Missing::Install) => {
+                let version = match version {
+                    Some(version) => version.to_owned(),
+                    None => self.newest_release(package)?,
+                };
+                eprintln!("Installing {package}@{version} to run it");
+                let options = InstallOptions {
+                    no_switch: true,
+                    ..InstallOptions::default()
+                };
+                self.install_package(&format!("{package}@{version}"), &options)?
+                    .version
+            }
+            (None, Missing::Temporary) => {
+                let version = match version {
+                    Some(version) => version.to_owned(),
+                    None => self.newest_release(package)?,
+                };
+                return self.run_temporarily(package, &version, bin, args);
+            }
+        };
+
+        let binary = self.binary_to_run(package, &version, bin)?;
+        let err = Command::new(&binary)
+            .args(args)
+            .env("PATH", self.path_with_version(package, &version)?)
+            .exec();
+
+        Err(err).with_context(|| format!("Failed to run {}", binary.display()))
+    }
+
+    /// Build `package@version` into a directory of its own, outside of the registry, run it and remove it
+    fn run_temporarily(
+        &self,
+        package: &str,
+        version: &str,
+        bin: Option<&OsStr>,
+        args: &[OsString],
+    ) -> Result<ExitStatus> {
+        ensure!(
+            split_label(package).1.is_none(),
+            "{package} is labeled, so it can only be built from git or a path with `install`"
+        );
+        let (bare_version, variant) = split_variant(version);
+        ensure!(
+            variant.is_none(),
+            "--temp builds with the default profile and features, so it can't build {package}@{version}"
+        );
+
+        let root = private_temp_dir()?;
+        let remove_on_interrupt = interrupt::remove_on_interrupt(&root);
+        eprintln!(
+            "Installing {package}@{version} into {} to run it",
+            root.display()
+        );
+        let result = self.install_and_run(&root, package, bare_version, bin, args);
+        let _ = fs::remove_dir_all(&root);
+        drop(remove_on_interrupt);
+
+        result
+    }
+
+    fn install_and_run(
+        &self,
+        root: &Path,
+        package: &str,
+        version: &str,
+        bin: Option<&OsStr>,
+        args: &[OsString],
+    ) -> Result<ExitStatus> {
+        let (features, flags) = self.build_flags(package, &InstallOptions::default());
+        let build = BuildOptions {
+            features: &features,
+            flags: &flags,
+            timeout: self.config.build_timeout,
+            events: self.events.as_deref(),
+            ..BuildOptions::default()
+        };
+        let spec = format!("{package}@{version}");
+        RetryPolicy::new(self.config.retries.unwrap_or(retry::DEFAULT_RETRIES))
+            .run(&format!("install {spec}"), || {
+                self.installer.install(&spec, root, &build)
+            })?;
+
+        let bin_path = root.join("bin");
+        let binary = pick_binary(package, version, &Self::scan_binaries(&bin_path)?, bin)?;
+        let path = env::var_os("PATH").unwrap_or_default();
+        let path = env::join_paths([bin_path].into_iter().chain(env::split_paths(&path)))
+            .with_context(|| "Failed to build $PATH")?;
+
+        Command::new(&binary)
+            .args(args)
+            .env("PATH", path)
+            .status()
+            .with_context(|| format!("Failed to run {}", binary.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use super::exit_code;
+    use super::private_temp_dir;
+
+    #[test]
+    fn exits_like_shells_do() {
+        assert_eq!(exit_code(ExitStatus::from_raw(3 << 8)), 3);
+        // Killed by SIGKILL
+        assert_eq!(exit_code(ExitStatus::from_raw(9)), 137);
+    }
+
+    #[test]
+    fn creates_temporary_directories_only_we_may_use() {
+        let first = private_temp_dir().unwrap();
+        let second = private_temp_dir().unwrap();
+        assert_ne!(first, second);
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        fs::remove_dir(first).unwrap();
+        fs::remove_dir(second).unwrap();
+    }
+}
@@ -0,0 +1,51 @@
+//! Switching cargo-switch itself, when it's one of the packages it manages. The running executable is left alone
+//! until the process exits: links are swapped in one go rather than removed and made again, and the version it was
+//! started from is neither pruned nor evicted while it runs, leaving that to a later `prune`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::Switcher;
+
+/// Where the running executable really is, links resolved
+fn running_executable() -> Option<PathBuf> {
+    fs::canonicalize(env::current_exe().ok()?).ok()
+}
+
+impl Switcher {
+    /// The version of `package` that the running executable was started from, if it's one
+    pub(crate) fn running_version(&self, package: &str) -> Option<String> {
+        let executable = running_executable()?;
+        let relative = self.registries().find_map(|registry| {
+            let registry = fs::canonicalize(registry).ok()?;
+            executable.strip_prefix(registry).ok().map(PathBuf::from)
+        })?;
+
+        let mut components = relative.components();
+        let running = components.next()?.as_os_str().to_str()?;
+        let version = components.next()?.as_os_str().to_str()?;
+        (running == package).then(|| version.to_owned())
+    }
+
+    /// Whether switching to `package@version`, which provides `binaries`, replaces the running executable: it was
+    /// started from another version of `package`, or from one of the links about to be replaced
+    pub(crate) fn switches_itself(
+        &self,
+        package: &str,
+        version: &str,
+        binaries: &[OsString],
+    ) -> bool {
+        if let Some(running) = self.running_version(package) {
+            return running != version;
+        }
+        let Some(executable) = running_executable() else {
+            return false;
+        };
+
+        binaries.iter().any(|binary| {
+            fs::canonicalize(self.cargo_bin.join(binary)).is_ok_and(|target| target == executable)
+        })
+    }
+}
@@ -0,0 +1,81 @@
+//! `self update`: replacing the running cargo-switch with its newest release.
+
+use std::env;
+use std::fs;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use semver::Version;
+
+use crate::installer::BuildOptions;
+use crate::interrupt;
+use crate::retry;
+use crate::retry::RetryPolicy;
+use crate::Switcher;
+
+const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// Directory, next to the running executable, that the new release is built into before replacing it
+const STAGING_DIRECTORY_NAME: &str = ".cargo-switch-self-update";
+
+impl Switcher {
+    /// Update cargo-switch itself if crates.io has a newer release, or only say whether there's one if `check_only`
+    pub fn self_update(&self, check_only: bool) -> Result<()> {
+        let current = Version::parse(env!("CARGO_PKG_VERSION"))?;
+        let policy = RetryPolicy::new(self.config.retries.unwrap_or(retry::DEFAULT_RETRIES));
+        let crates_io = self.crates_io()?;
+
+        let newest = policy
+            .run("look up the newest cargo-switch", || {
+                crates_io.newest_version(PACKAGE_NAME, false)
+            })
+            .with_context(|| "Couldn't reach crates.io to check for a newer cargo-switch")?;
+
+        if newest <= current {
+            println!("cargo-switch {current} is already the newest version");
+            return Ok(());
+        }
+        if check_only {
+            println!(
+                "cargo-switch {newest} is available, this is {current}. Run `cargo switch self update` to get it"
+            );
+            return Ok(());
+        }
+
+        // Replacing the file a running executable was started from is fine on Unix as long as it's done with a
+        // rename, so the new release is built elsewhere and moved over it once complete
+        let executable = fs::canonicalize(env::current_exe()?)
+            .with_context(|| "Failed to find the running cargo-switch executable")?;
+        let directory = executable
+            .parent()
+            .with_context(|| format!("{} has no parent directory", executable.display()))?;
+        let staging = directory.join(STAGING_DIRECTORY_NAME);
+        let _ = fs::remove_dir_all(&staging);
+        let _remove_on_interrupt = interrupt::remove_on_interrupt(&staging);
+
+        let spec = format!("{PACKAGE_NAME}@{newest}");
+        let result = policy
+            .run(&format!("install {spec}"), || {
+                self.installer
+                    .install(&spec, &staging, &BuildOptions::default())
+            })
+            .and_then(|_| {
+                let built = staging.join("bin").join(PACKAGE_NAME);
+                ensure!(
+                    built.is_file(),
+                    "cargo install didn't produce {}",
+                    built.display()
+                );
+
+                fs::rename(&built, &executable)
+                    .with_context(|| format!("Failed to replace {}", executable.display()))
+            });
+        let _ = fs::remove_dir_all(&staging);
+        result?;
+
+        println!("Updated cargo-switch from {current} to {newest}");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+//! `setup`: getting a first run off the ground. Everything [`SwitcherBuilder::build`] would otherwise fail over, or
+//! only warn about, is taken care of or explained: a missing cargo, a missing `.cargo/bin` and, above all, one that
+//! isn't in `$PATH`, for which the line to add to the user's shell rc is printed, or added to it if they ask.
+//!
+//! Running it again over a setup that's already fine changes nothing.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cargo::CargoInstaller;
+use crate::config::Config;
+use crate::install_root;
+use crate::install_root::CargoEnv;
+use crate::SwitcherBuilder;
+
+/// Written above the lines `--modify-shell-rc` adds, so that the user can tell where they came from
+const RC_COMMENT: &str = "# Added by `cargo switch setup`";
+
+/// The rc file of `shell`, as in `$SHELL`, and the line that puts `directory` in `$PATH` when added to it. Shells
+/// that aren't known get `~/.profile`, which any POSIX shell reads at login.
+fn shell_rc(shell: &OsStr, home: &Path, directory: &Path) -> (PathBuf, String) {
+    let export = format!("export PATH=\"{}:$PATH\"", directory.display());
+    match Path::new(shell).file_name().and_then(OsStr::to_str) {
+        Some("bash") => (home.join(".bashrc"), export),
+        Some("zsh") => (home.join(".zshrc"), export),
+        Some("fish") => (
+            home.join(".config/fish/config.fish"),
+            format!("fish_add_path \"{}\"", directory.display()),
+        ),
+        _ => (home.join(".profile"), export),
+    }
+}
+
+/// Append `line` to the rc file at `rc`, creating it if needed. Returns whether it had to, `line` being added only
+/// once however many times this runs.
+fn add_to_rc(rc: &Path, line: &str) -> Result<bool> {
+    let contents = match fs::read_to_string(rc) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", rc.display())),
+    };
+    if contents.lines().any(|existing| existing.trim() == line) {
+        return Ok(false);
+    }
+
+    if let Some(parent) = rc.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+    let separator = match contents.is_empty() || contents.ends_with('\n') {
+        true => "",
+        false => "\n",
+    };
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(rc)
+        .and_then(|mut file| write!(file, "{separator}\n{RC_COMMENT}\n{line}\n"))
+        .with_context(|| format!("Failed to write to {}", rc.display()))?;
+
+    Ok(true)
+}
+
+/// The cargo bin directory to link into when nothing was configured: whatever cargo installs into, or
+/// `$CARGO_HOME/bin` if that's yet to be created
+fn default_cargo_bin(env: &CargoEnv) -> Result<PathBuf> {
+    match env.cargo_bin() {
+        Ok((cargo_bin, _)) => Ok(cargo_bin),
+        Err(err) => match env.cargo_home() {
+            Some(cargo_home) => Ok(cargo_home.join("bin")),
+            None => Err(err),
+        },
+    }
+}
+
+impl SwitcherBuilder {
+    /// Check for cargo, create the link directory and the registry if they don't exist, and make sure the link
+    /// directory is in `$PATH`, adding it to the user's shell rc if `modify_shell_rc` is set. Finishes with a summary
+    /// of what's configured, failing if anything is still in the way.
+    pub fn setup(mut self, modify_shell_rc: bool) -> Result<()> {
+        let config = match self.config.take() {
+            Some(config) => config,
+            None => Config::load()?,
+        };
+        let mut changed = false;
+        let mut problems = Vec::new();
+
+        let cargo = CargoInstaller {
+            cargo_path: config.cargo_path.clone(),
+            ..CargoInstaller::default()
+        }
+        .cargo();
+        if let Err(err) = &cargo {
+            problems.push(format!("{err:#}"));
+        }
+
+        // Building would only fail over a cargo bin directory missing, so it's created first
+        let links = match self.link_dir.clone().or_else(|| config.link_dir.clone()) {
+            Some(link_dir) => link_dir,
+            None => {
+                let cargo_bin = match self.cargo_bin.clone().or_else(|| config.cargo_bin.clone()) {
+                    Some(cargo_bin) => cargo_bin,
+                    None => default_cargo_bin(&CargoEnv::current())?,
+                };
+                self.cargo_bin = Some(cargo_bin.clone());
+                cargo_bin
+            }
+        };
+        if links.exists().not() {
+            fs::create_dir_all(&links)
+                .with_context(|| format!("Failed to create {}", links.display()))?;
+            println!("Created {}", links.display());
+            changed = true;
+        }
+
+        self.config = Some(config);
+        let (switcher, new_registry) = self.build_reporting_new()?;
+        if new_registry {
+            println!("Created the registry at {}", switcher.registry.display());
+            changed = true;
+        }
+
+        let path = env::var_os("PATH").unwrap_or_default();
+        let in_path = install_root::in_path(&switcher.cargo_bin, &path);
+        let rc = env::var_os("HOME").map(|home| {
+            let shell = env::var_os("SHELL").unwrap_or_default();
+            shell_rc(&shell, Path::new(&home), &switcher.cargo_bin)
+        });
+        match rc {
+            _ if in_path => {}
+            Some((rc, line)) if modify_shell_rc => {
+                match add_to_rc(&rc, &line)? {
+                    true => println!("Added `{line}` to {}", rc.display()),
+                    false => println!("{} already has `{line}`", rc.display()),
+                }
+                println!("Open a new shell, or run `{line}`, for it to apply");
+                changed = true;
+            }
+            Some((rc, line)) => {
+                println!(
+                    "{} isn't in $PATH, so nothing cargo-switch links into it runs by name. Add this line to {}:\n\n    \
+                     {line}\n\nor run `cargo switch setup --modify-shell-rc` to have it added",
+                    switcher.cargo_bin.display(),
+                    rc.display()
+                );
+                problems.push(format!("{} isn't in $PATH", switcher.cargo_bin.display()));
+            }
+            None => problems.push(format!(
+                "{} isn't in $PATH, and $HOME isn't set to find a shell rc to add it to",
+                switcher.cargo_bin.display()
+            )),
+        }
+
+        println!();
+        match &cargo {
+            Ok(cargo) => println!("Cargo:     {}", cargo.display()),
+            Err(_) => println!("Cargo:     not found"),
+        }
+        println!(
+            "Links:     {}{}",
+            switcher.cargo_bin.display(),
+            match in_path {
+                true => "",
+                false => " (not in $PATH)",
+            }
+        );
+        println!(
+            "Registry:  {} ({} package(s) installed)",
+            switcher.registry.display(),
+            switcher.installed_packages()?.len()
+        );
+        match Config::path() {
+            Some(path) if path.exists() => println!("Config:    {}", path.display()),
+            Some(path) => println!(
+                "Config:    none, the defaults apply ({} doesn't exist)",
+                path.display()
+            ),
+            None => println!("Config:    none, the defaults apply"),
+        }
+
+        match problems.as_slice() {
+            [] if changed.not() => println!("\nEverything looks good"),
+            [] => println!("\nAll set"),
+            problems => {
+                println!();
+                for problem in problems {
+                    println!("Problem: {problem}");
+                }
+                bail!("{} problem(s) left to fix", problems.len());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::ops::Not;
+    use std::path::Path;
+
+    use super::add_to_rc;
+    use super::shell_rc;
+
+    #[test]
+    fn picks_rc_files_by_shell() {
+        let home = Path::new("/home/user");
+        let directory = Path::new("/home/user/.cargo/bin");
+        let rc = |shell: &str| shell_rc(OsStr::new(shell), home, directory);
+
+        let export = "export PATH=\"/home/user/.cargo/bin:$PATH\"".to_owned();
+        assert_eq!(rc("/bin/bash"), (home.join(".bashrc"), export.clone()));
+        assert_eq!(rc("/usr/bin/zsh"), (home.join(".zshrc"), export.clone()));
+        assert_eq!(
+            rc("/usr/local/bin/fish"),
+            (
+                home.join(".config/fish/config.fish"),
+                "fish_add_path \"/home/user/.cargo/bin\"".to_owned()
+            )
+        );
+        assert_eq!(rc("/bin/dash"), (home.join(".profile"), export.clone()));
+        assert_eq!(rc(""), (home.join(".profile"), export));
+    }
+
+    #[test]
+    fn adds_to_rc_files_once() {
+        let home = tempfile::tempdir().unwrap();
+        let rc = home.path().join(".config/fish/config.fish");
+        fs::create_dir_all(rc.parent().unwrap()).unwrap();
+        fs::write(&rc, "set -x EDITOR vim").unwrap();
+
+        assert!(add_to_rc(&rc, "fish_add_path \"/bin\"").unwrap());
+        assert!(add_to_rc(&rc, "fish_add_path \"/bin\"").unwrap().not());
+        assert_eq!(
+            fs::read_to_string(&rc).unwrap(),
+            "set -x EDITOR vim\n\n# Added by `cargo switch setup`\nfish_add_path \"/bin\"\n"
+        );
+
+        let fresh = home.path().join(".bashrc");
+        assert!(add_to_rc(&fresh, "export PATH=\"/bin:$PATH\"").unwrap());
+        assert_eq!(
+            fs::read_to_string(&fresh).unwrap(),
+            "\n# Added by `cargo switch setup`\nexport PATH=\"/bin:$PATH\"\n"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::cargo::is_executable;
+use crate::links::ManagedLink;
+use crate::Switcher;
+
+/// A managed binary that won't run when invoked by name, since another executable comes before it in `$PATH`
+#[derive(Debug)]
+pub struct Shadowing {
+    pub link: ManagedLink,
+    pub shadowed_by: PathBuf,
+}
+
+/// Whether `a` and `b` are the same directory, even if reached through different paths
+pub(crate) fn same_directory(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// The first line `binary --version` prints, if it prints anything at all
+fn probe_version(binary: &Path) -> Option<String> {
+    let output = Command::new(binary)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    stdout
+        .lines()
+        .next()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| line.is_empty().not())
+}
+
+/// Find the executable called `name` that comes before `cargo_bin` in `path`, formatted like `$PATH`
+pub(crate) fn find_shadowing_executable(
+    name: &OsStr,
+    cargo_bin: &Path,
+    path: &OsStr,
+) -> Option<PathBuf> {
+    for directory in env::split_paths(path) {
+        if same_directory(&directory, cargo_bin) {
+            return None;
+        }
+
+        let candidate = directory.join(name);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+    }
+
+    None
+}
+
+impl Switcher {
+    /// Every managed binary that is shadowed by an executable coming before `.cargo/bin` in `$PATH`
+    pub fn find_shadowing(&self) -> Result<Vec<Shadowing>> {
+        let path = env::var_os("PATH").unwrap_or_default();
+
+        Ok(self
+            .managed_links()?
+            .into_iter()
+            .filter_map(|link| {
+                let shadowed_by = find_shadowing_executable(&link.name, &self.cargo_bin, &path)?;
+                Some(Shadowing { link, shadowed_by })
+            })
+            .collect())
+    }
+
+    /// Report shadowed binaries, returning how many were found. With `probe`, every binary involved is asked for
+    /// its version.
+    pub fn report_shadowing(&self, probe: bool) -> Result<usize> {
+        let shadowings = self.find_shadowing()?;
+
+        for shadowing in &shadowings {
+            let Shadowing { link, shadowed_by } = shadowing;
+
+            println!(
+                "{} from {}@{} is shadowed by {}",
+                link.name.to_string_lossy(),
+                link.package,
+                link.version,
+                shadowed_by.display()
+            );
+            println!("  managed: {}", link.link.display());
+            println!("  runs:    {}", shadowed_by.display());
+
+            if probe {
+                let unknown = || "unknown version".to_owned();
+                println!(
+                    "  managed version: {}",
+                    probe_version(&link.link).unwrap_or_else(unknown)
+                );
+                println!(
+                    "  running version: {}",
+                    probe_version(shadowed_by).unwrap_or_else(unknown)
+                );
+            }
+        }
+
+        Ok(shadowings.len())
+    }
+
+    pub fn shadow_check(&self, probe: bool) -> Result<()> {
+        let shadowed = self.report_shadowing(probe)?;
+        if shadowed > 0 {
+            bail!(
+                "{shadowed} managed binar{} shadowed by earlier $PATH entries",
+                if shadowed == 1 { "y is" } else { "ies are" }
+            );
+        }
+
+        println!("No managed binary is shadowed");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::find_shadowing_executable;
+
+    #[test]
+    fn finds_executables_before_cargo_bin() {
+        let root = tempfile::tempdir().unwrap();
+        let system_bin = root.path().join("usr-bin");
+        let cargo_bin = root.path().join(".cargo").join("bin");
+        let late_bin = root.path().join("late-bin");
+        for directory in [&system_bin, &cargo_bin, &late_bin] {
+            fs::create_dir_all(directory).unwrap();
+        }
+
+        let make_executable = |path: &std::path::Path, mode| {
+            fs::write(path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+        make_executable(&system_bin.join("rg"), 0o755);
+        make_executable(&system_bin.join("not-executable"), 0o644);
+        make_executable(&late_bin.join("fd"), 0o755);
+
+        let path = env::join_paths([&system_bin, &cargo_bin, &late_bin]).unwrap();
+
+        assert_eq!(
+            find_shadowing_executable(OsStr::new("rg"), &cargo_bin, &path),
+            Some(system_bin.join("rg"))
+        );
+        assert_eq!(
+            find_shadowing_executable(OsStr::new("not-executable"), &cargo_bin, &path),
+            None
+        );
+        // Coming after .cargo/bin doesn't shadow anything
+        assert_eq!(
+            find_shadowing_executable(OsStr::new("fd"), &cargo_bin, &path),
+            None
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! The shared registry, set through the `shared-registry` config key, as in `/opt/cargo-switch/registry`: versions
+//! installed once for every user of a machine, which anyone can switch to or run without building them again.
+//!
+//! It is consulted after the user's own registry, which wins whenever both hold the same version, and is never
+//! written to unless `--system` is passed, in which case it becomes the registry everything is installed into.
+//! Links into it still go to each user's own `.cargo/bin`, and what was switched to when is kept in each user's own
+//! registry.
+
+use std::fs;
+use std::io;
+use std::iter;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Result;
+
+use crate::install_root::is_writable_directory;
+use crate::Switcher;
+
+/// The names of the directories in `directory`, which may not exist, that don't start with a dot and are valid UTF-8
+pub(crate) fn directory_names(directory: &Path) -> Result<Vec<String>> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut names = Vec::new();
+    for maybe_entry in entries {
+        let entry = maybe_entry?;
+        // Whatever isn't valid UTF-8 can't have been installed by us, as specs must be
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        // The registry's own bookkeeping lives in dotfiles
+        if entry.file_type()?.is_dir() && name.starts_with('.').not() {
+            names.push(name);
+        }
+    }
+
+    Ok(names)
+}
+
+impl Switcher {
+    /// The registries versions are looked for in, the user's own first
+    pub(crate) fn registries(&self) -> impl Iterator<Item = &Path> {
+        iter::once(self.registry.as_path()).chain(self.shared_registry.as_deref())
+    }
+
+    /// Where `package@version` is installed: in the user's registry if it's there, or else in the shared registry if
+    /// it's there. Versions installed in neither would go in the user's registry.
+    pub(crate) fn version_path(&self, package: &str, version: &str) -> PathBuf {
+        self.registries()
+            .map(|registry| registry.join(package).join(version))
+            .find(|path| path.exists())
+            .unwrap_or_else(|| self.registry.join(package).join(version))
+    }
+
+    /// Whether `package` has versions in any registry
+    pub(crate) fn package_exists(&self, package: &str) -> bool {
+        self.registries()
+            .any(|registry| registry.join(package).exists())
+    }
+
+    /// Whether `package@version` is only installed in the shared registry
+    pub fn is_shared(&self, package: &str, version: &str) -> bool {
+        self.shared_registry.as_ref().is_some_and(|shared| {
+            self.registry.join(package).join(version).exists().not()
+                && shared.join(package).join(version).exists()
+        })
+    }
+
+    /// Refuse to `what`, as in `uninstall`, `package@version` when it's only in the shared registry, which takes
+    /// `--system` to change
+    pub(crate) fn ensure_not_shared(&self, package: &str, version: &str, what: &str) -> Result<()> {
+        if self.is_shared(package, version).not() {
+            return Ok(());
+        }
+        match self.shared_registry_writable() {
+            true => bail!("{package}@{version} is in the shared registry, pass --system to {what} it there"),
+            false => bail!("{package}@{version} is in the shared registry, where only its admins can {what} it"),
+        }
+    }
+
+    /// Refuse to `what` under `--system`, which only installs into and uninstalls from the shared registry: what's
+    /// switched to and the defaults and channels it's picked by are up to each user, in their own registry
+    pub(crate) fn ensure_per_user(&self, what: &str) -> Result<()> {
+        ensure!(
+            self.system.not(),
+            "--system only installs into and uninstalls from the shared registry, so it can't {what}. Run it without \
+             --system instead"
+        );
+        Ok(())
+    }
+
+    /// Whether the shared registry, if there's one, may be written to by whoever is running us
+    pub fn shared_registry_writable(&self) -> bool {
+        self.shared_registry
+            .as_deref()
+            .is_some_and(is_writable_directory)
+    }
+}
@@ -0,0 +1,60 @@
+use std::env;
+use std::process::Command;
+use std::process::ExitStatus;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::exec::unique_versions;
+use crate::Switcher;
+
+/// Set in the environment of the shells spawned by `cargo switch shell`, to the versions they activate
+pub const SHELL_MARKER_VARIABLE: &str = "CARGO_SWITCH_SHELL";
+
+impl Switcher {
+    /// Spawn `$SHELL` with the binaries of every one of `specs` first in `$PATH`. Everything goes back to how it was
+    /// once the shell exits, with nothing in `.cargo/bin` touched along the way.
+    pub fn shell(&self, specs: &[String]) -> Result<ExitStatus> {
+        let specs: Vec<_> = specs
+            .iter()
+            .map(|spec| self.canonical_spec(spec))
+            .collect::<Result<_>>()?;
+        let versions = unique_versions(&specs)?;
+        let path = self.path_with_versions(&versions)?;
+        let active: Vec<_> = versions
+            .iter()
+            .map(|(package, version)| format!("{package}@{version}"))
+            .collect();
+        let active = active.join(" ");
+
+        // The shell's PATH would still work, but the outer versions come second to the new ones
+        if let Some(outer) = env::var_os(SHELL_MARKER_VARIABLE) {
+            eprintln!(
+                "Warning: already inside a cargo-switch shell with {}, this one will be nested in it",
+                outer.to_string_lossy()
+            );
+        }
+
+        let shell = env::var_os("SHELL").unwrap_or_else(|| "/bin/sh".into());
+        let mut command = Command::new(&shell);
+        command
+            .env("PATH", path)
+            .env(SHELL_MARKER_VARIABLE, &active);
+        for (package, version) in &versions {
+            for (name, value) in self.wrapper_env(package, version).unwrap_or_default() {
+                command.env(name, value);
+            }
+        }
+
+        println!(
+            "Spawning {} with {active}. Exit it to go back.",
+            shell.to_string_lossy()
+        );
+        let status = command
+            .status()
+            .with_context(|| format!("Failed to spawn {}", shell.to_string_lossy()))?;
+        println!("Left the shell with {active}");
+
+        Ok(status)
+    }
+}
@@ -0,0 +1,253 @@
+//! Smoke tests: a command of the user's choosing, set through a package's `smoke-test`, that freshly built versions
+//! must pass before anything links to them. The command runs against the version's binaries right where they are in
+//! the registry, and a version that fails it is quarantined: recorded as such in its metadata and refused by
+//! switches until `check --smoke-test` sees it pass, unless `--ignore-smoke-test` says otherwise.
+
+use std::env;
+use std::iter;
+use std::ops::Not;
+use std::process::Command;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::check::run_with_timeout;
+use crate::metadata::VersionMetadata;
+use crate::table::print_table;
+use crate::Switcher;
+
+/// How long a smoke test may run, unless `smoke-test-timeout` says otherwise
+pub const DEFAULT_SMOKE_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How smoke testing a version went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SmokeTestResult {
+    pub package: String,
+    pub version: String,
+    /// Why the smoke test failed, if it did
+    pub failure: Option<String>,
+    /// Whether the version was quarantined before it passed
+    pub lifted: bool,
+}
+
+/// Why a smoke test that ran to the end failed, along with whatever it printed
+fn describe_failure(status: ExitStatus, output: &str) -> String {
+    match output.trim_end() {
+        "" => format!("exited with {status}"),
+        output => format!("exited with {status}:\n{output}"),
+    }
+}
+
+impl Switcher {
+    /// Run the smoke test of `package` against `version`, `None` if the package has none, or else why it failed, if
+    /// it did
+    pub fn smoke_test(&self, package: &str, version: &str) -> Option<Result<(), String>> {
+        let config = self.config.package(package)?;
+        let script = config.smoke_test.as_deref()?;
+        let timeout = config
+            .smoke_test_timeout
+            .unwrap_or(DEFAULT_SMOKE_TEST_TIMEOUT);
+
+        // The version's own binaries come first, so that the command runs them rather than the active ones
+        let bin = self.version_path(package, version).join("bin");
+        let path = env::var_os("PATH").unwrap_or_default();
+        let path = match env::join_paths(iter::once(bin).chain(env::split_paths(&path))) {
+            Ok(path) => path,
+            Err(err) => return Some(Err(format!("couldn't set up $PATH: {err}"))),
+        };
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script).env("PATH", path);
+        // As the package's wrappers would
+        if let Some(env) = self.wrapper_env(package, version) {
+            command.envs(env);
+        }
+
+        Some(match run_with_timeout(command, timeout) {
+            Ok((status, _)) if status.success() => Ok(()),
+            Ok((status, output)) => Err(describe_failure(status, &output)),
+            Err(failure) => Err(failure),
+        })
+    }
+
+    /// Quarantine `package@version` for `reason`, or lift its quarantine if there's none
+    fn set_quarantine(&self, package: &str, version: &str, reason: Option<String>) -> Result<()> {
+        let path = self.version_path(package, version);
+        let mut metadata = VersionMetadata::load(&path)?.unwrap_or_default();
+        if metadata.quarantined == reason {
+            return Ok(());
+        }
+        metadata.quarantined = reason;
+
+        metadata.save(&path)
+    }
+
+    /// Smoke test the freshly built `package@version` before it's switched to, quarantining it and failing with what
+    /// the smoke test printed if it doesn't pass
+    pub(crate) fn smoke_test_fresh(&self, package: &str, version: &str) -> Result<()> {
+        if self.ignore_smoke_test {
+            return Ok(());
+        }
+        let Some(Err(failure)) = self.smoke_test(package, version) else {
+            return Ok(());
+        };
+
+        self.set_quarantine(package, version, Some(failure.clone()))?;
+        bail!(
+            "{package}@{version} failed its smoke test and was quarantined, keeping what was active before. It \
+             {failure}"
+        )
+    }
+
+    /// Refuse to switch to `package@version` while it's quarantined, unless smoke tests are to be ignored
+    pub(crate) fn ensure_not_quarantined(&self, package: &str, version: &str) -> Result<()> {
+        if self.ignore_smoke_test {
+            return Ok(());
+        }
+        // Metadata that can't be read can't say it's quarantined, and shouldn't stop switching to it either
+        let metadata = match VersionMetadata::load(&self.version_path(package, version)) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!(
+                    "Warning: couldn't tell whether {package}@{version} is quarantined: {err:#}"
+                );
+                None
+            }
+        };
+        let Some(reason) = metadata.and_then(|metadata| metadata.quarantined) else {
+            return Ok(());
+        };
+
+        bail!(
+            "{package}@{version} is quarantined, since its smoke test failed. Run `cargo switch check --smoke-test \
+             {package}` to test it again, or pass --ignore-smoke-test to switch to it anyway. It {reason}"
+        )
+    }
+
+    /// Smoke test the active version of `package`, or of every package with a smoke test, along with every one of
+    /// their quarantined versions, lifting the quarantine of those that pass now
+    pub fn smoke_test_versions(&self, package: Option<&str>) -> Result<Vec<SmokeTestResult>> {
+        let packages = match package {
+            Some(package) => {
+                let package = self.canonical_name(package)?;
+                if self.package_exists(&package).not() {
+                    return Err(self.not_installed(&package, None));
+                }
+                if self
+                    .config
+                    .package(&package)
+                    .is_none_or(|config| config.smoke_test.is_none())
+                {
+                    bail!("{package} has no smoke test, set its `smoke-test` in the config file");
+                }
+                vec![package]
+            }
+            None => self
+                .installed_packages()?
+                .into_iter()
+                .filter(|package| {
+                    self.config
+                        .package(package)
+                        .is_some_and(|config| config.smoke_test.is_some())
+                })
+                .collect(),
+        };
+
+        let mut results = Vec::new();
+        for package in packages {
+            let active = self.linked_version(&package)?;
+            for version in self.installed_versions(&package)? {
+                let quarantined = VersionMetadata::load(&self.version_path(&package, &version))?
+                    .is_some_and(|metadata| metadata.quarantined.is_some());
+                if quarantined.not() && active.as_ref() != Some(&version) {
+                    continue;
+                }
+                let Some(outcome) = self.smoke_test(&package, &version) else {
+                    continue;
+                };
+
+                // A failing active version is there to be looked into, not to be quarantined behind the user's back
+                let failure = outcome.err();
+                if quarantined {
+                    self.set_quarantine(&package, &version, failure.clone())?;
+                }
+                results.push(SmokeTestResult {
+                    lifted: quarantined && failure.is_none(),
+                    package: package.clone(),
+                    version,
+                    failure,
+                });
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// `check --smoke-test`: smoke test what [`smoke_test_versions`](Self::smoke_test_versions) does, printing how
+    /// it went, and return how many smoke tests failed out of how many
+    pub(crate) fn report_smoke_tests(&self, package: Option<&str>) -> Result<(usize, usize)> {
+        let results = self.smoke_test_versions(package)?;
+        if results.is_empty() {
+            println!("Nothing to smoke test, no package with a `smoke-test` has an active or quarantined version");
+            return Ok((0, 0));
+        }
+
+        let rows: Vec<_> = results
+            .iter()
+            .map(|result| {
+                let (outcome, details) = match (&result.failure, result.lifted) {
+                    (Some(failure), _) => ("fail", failure.lines().next().unwrap_or_default()),
+                    (None, true) => ("pass", "no longer quarantined"),
+                    (None, false) => ("pass", ""),
+                };
+                [
+                    result.package.clone(),
+                    result.version.clone(),
+                    outcome.to_owned(),
+                    details.to_owned(),
+                ]
+            })
+            .collect();
+        print_table(["PACKAGE", "VERSION", "RESULT", "DETAILS"], &rows);
+
+        // What the failed ones printed doesn't fit in a table
+        for result in &results {
+            let Some(failure) = &result.failure else {
+                continue;
+            };
+            if let Some((_, output)) = failure.split_once('\n') {
+                println!("\n{}@{} printed:\n{output}", result.package, result.version);
+            }
+        }
+
+        let failed = results
+            .iter()
+            .filter(|result| result.failure.is_some())
+            .count();
+
+        Ok((failed, results.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::ExitStatus;
+
+    use super::describe_failure;
+
+    #[test]
+    fn describes_failures_with_their_output() {
+        let status = ExitStatus::from_raw(1 << 8);
+        assert_eq!(
+            describe_failure(status, "error: missing libssl.so.3\n"),
+            "exited with exit status: 1:\nerror: missing libssl.so.3"
+        );
+        assert_eq!(
+            describe_failure(status, " \n"),
+            "exited with exit status: 1"
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Reading crates.io's sparse index, `https://index.crates.io`, which is what cargo itself reads to resolve versions.
+//! It's served from a CDN, so it's cheaper to ask and less likely to be rate limited than the API, and tells whether
+//! what we cached is still current through HTTP validators.
+//!
+//! Sources replaced in cargo's configuration, as with
+//!
+//! ```toml
+//! [source.crates-io]
+//! replace-with = "mirror"
+//!
+//! [source.mirror]
+//! registry = "sparse+https://mirror.example.com/index/"
+//! ```
+//!
+//! are read instead of crates.io, as long as they're sparse indexes themselves.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::crates_io::PublishedVersion;
+
+/// crates.io's own sparse index
+pub const CRATES_IO_INDEX: &str = "https://index.crates.io/";
+
+/// Where crates.io's index is, according to cargo's configuration
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexSource {
+    /// A sparse index, crates.io's own or a replacement, at this URL
+    Sparse(String),
+    /// crates.io was replaced by something that isn't a sparse index, such as a git index, a local registry or
+    /// vendored sources, named as cargo's configuration names it
+    Unsupported(String),
+}
+
+/// Where the index file of `name` is, relative to the index's root, as in `ri/pg/ripgrep` or `3/s/syn`
+pub fn index_path(name: &str) -> String {
+    let name = name.to_ascii_lowercase();
+
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
+
+/// A line of an index file, as far as we care about it
+#[derive(Debug, Deserialize)]
+struct IndexLine {
+    name: Option<String>,
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+    cksum: Option<String>,
+    rust_version: Option<String>,
+}
+
+/// The versions listed in the index file `contents`, one JSON object per line. Lines that can't be read, say
+/// because they were written in a newer format, are left out.
+pub fn parse_index_file(contents: &str) -> Vec<PublishedVersion> {
+    contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<IndexLine>(line).ok())
+        .map(|line| PublishedVersion {
+            name: line.name,
+            num: line.vers,
+            yanked: line.yanked,
+            checksum: line.cksum,
+            rust_version: line.rust_version,
+        })
+        .collect()
+}
+
+pub(crate) fn read_config(path: &Path) -> Result<Option<toml::Table>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read {}", path.display())),
+    };
+
+    toml::from_str(&contents)
+        .map(Some)
+        .with_context(|| format!("Failed to parse {}", path.display()))
+}
+
+/// `table.key.name.field`, where `key` is `source` or `registries`
+fn lookup<'a>(
+    table: &'a toml::Table,
+    key: &str,
+    name: &str,
+    field: &str,
+) -> Option<&'a toml::Value> {
+    table.get(key)?.get(name)?.get(field)
+}
+
+/// Where crates.io's index is according to `config_files`, cargo's configuration files from the one that takes
+/// precedence to the one that doesn't
+pub fn configured_index(config_files: &[PathBuf]) -> Result<IndexSource> {
+    let mut configs = Vec::new();
+    for path in config_files {
+        if let Some(config) = read_config(path)? {
+            configs.push((path, config));
+        }
+    }
+
+    let replacement = configs.iter().find_map(|(path, config)| {
+        lookup(config, "source", "crates-io", "replace-with").map(|name| (path, name))
+    });
+    let Some((path, replacement)) = replacement else {
+        return Ok(IndexSource::Sparse(CRATES_IO_INDEX.to_owned()));
+    };
+    let Some(replacement) = replacement.as_str() else {
+        bail!(
+            "source.crates-io.replace-with in {} must be a string",
+            path.display()
+        );
+    };
+
+    // A replacement can name a source or a registry
+    let url = configs.iter().find_map(|(_, config)| {
+        lookup(config, "source", replacement, "registry")
+            .or_else(|| lookup(config, "registries", replacement, "index"))
+            .and_then(toml::Value::as_str)
+    });
+
+    Ok(match url.and_then(|url| url.strip_prefix("sparse+")) {
+        Some(url) if url.ends_with('/') => IndexSource::Sparse(url.to_owned()),
+        Some(url) => IndexSource::Sparse(format!("{url}/")),
+        None => IndexSource::Unsupported(replacement.to_owned()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::configured_index;
+    use super::index_path;
+    use super::parse_index_file;
+    use super::IndexSource;
+    use super::CRATES_IO_INDEX;
+
+    #[test]
+    fn lays_out_index_paths() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("syn"), "3/s/syn");
+        assert_eq!(index_path("ripgrep"), "ri/pg/ripgrep");
+        assert_eq!(index_path("Inflector"), "in/fl/inflector");
+    }
+
+    #[test]
+    fn reads_index_files() {
+        let contents = concat!(
+            r#"{"name":"tool","vers":"1.0.0","deps":[],"cksum":"abc","features":{},"yanked":false}"#,
+            "\n",
+            r#"{"name":"tool","vers":"1.1.0","deps":[],"cksum":"def","features":{},"yanked":true,"v":2,"features2":{},"rust_version":"1.70"}"#,
+            "\n",
+            "garbage\n",
+        );
+
+        let versions = parse_index_file(contents);
+        let versions: Vec<_> = versions
+            .iter()
+            .map(|version| {
+                (
+                    version.num.as_str(),
+                    version.yanked,
+                    version.checksum.as_deref(),
+                    version.rust_version.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            versions,
+            [
+                ("1.0.0", false, Some("abc"), None),
+                ("1.1.0", true, Some("def"), Some("1.70"))
+            ]
+        );
+    }
+
+    #[test]
+    fn follows_replaced_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let write = |name: &str, contents: &str| {
+            let path = directory.path().join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+
+        let plain = write("plain.toml", "[build]\njobs = 2\n");
+        assert_eq!(
+            configured_index(std::slice::from_ref(&plain)).unwrap(),
+            IndexSource::Sparse(CRATES_IO_INDEX.to_owned())
+        );
+
+        let mirror = write(
+            "mirror.toml",
+            "[source.crates-io]\nreplace-with = \"mirror\"\n\n[source.mirror]\nregistry = \"sparse+https://mirror.example.com/index\"\n",
+        );
+        assert_eq!(
+            configured_index(&[plain, mirror.clone()]).unwrap(),
+            IndexSource::Sparse("https://mirror.example.com/index/".to_owned())
+        );
+
+        // Closer files take precedence, and may name a registry defined elsewhere
+        let registry = write(
+            "registry.toml",
+            "[source.crates-io]\nreplace-with = \"company\"\n\n[registries.company]\nindex = \"sparse+https://crates.company.com/\"\n",
+        );
+        assert_eq!(
+            configured_index(&[registry, mirror]).unwrap(),
+            IndexSource::Sparse("https://crates.company.com/".to_owned())
+        );
+
+        let vendored = write(
+            "vendored.toml",
+            "[source.crates-io]\nreplace-with = \"vendored\"\n\n[source.vendored]\ndirectory = \"vendor\"\n",
+        );
+        assert_eq!(
+            configured_index(&[vendored]).unwrap(),
+            IndexSource::Unsupported("vendored".to_owned())
+        );
+
+        let broken = write("broken.toml", "[source.crates-io]\nreplace-with = 3\n");
+        assert!(configured_index(&[broken]).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! Parsing `NAME@VERSION` specs.
+//!
+//! Both halves of a spec end up as directory names inside the registry, so whatever could point somewhere else, like
+//! `..`, a `/` or a leading dot, is refused before it gets anywhere near a path.
+
+use std::ffi::OsStr;
+use std::ops::Not;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::channel::is_channel_name;
+use crate::git::is_snapshot_directory;
+use crate::variant::split_variant;
+
+/// crates.io doesn't accept longer package names
+pub const MAX_NAME_LENGTH: usize = 64;
+/// Generous enough for pre-releases, build metadata and variant labels
+pub const MAX_VERSION_LENGTH: usize = 128;
+
+/// Separates the name of the crate a package is built from from its label, as in `tool#myfork`
+pub const LABEL_SEPARATOR: char = '#';
+
+/// Split `package` into the name of the crate it's built from and its label, if it has one. Labels keep builds of the
+/// same crate from different forks apart, as `tool` and `tool#myfork`.
+pub fn split_label(package: &str) -> (&str, Option<&str>) {
+    match package.split_once(LABEL_SEPARATOR) {
+        Some((name, label)) => (name, Some(label)),
+        None => (package, None),
+    }
+}
+
+/// What `package` is compared as: crates.io takes `-` and `_` to be the same in crate names, so `fd_find` and
+/// `fd-find` are one crate. Labels are compared as they are.
+pub fn normalize_name(package: &str) -> String {
+    let (name, label) = split_label(package);
+    let name = name.replace('_', "-");
+
+    match label {
+        Some(label) => format!("{name}{LABEL_SEPARATOR}{label}"),
+        None => name,
+    }
+}
+
+/// Make sure `name` follows the crates.io naming rules, an ASCII letter followed by ASCII letters, digits, `-` and
+/// `_`, optionally followed by a label, as in `tool#myfork`
+pub fn validate_name(name: &str) -> Result<()> {
+    let (crate_name, label) = split_label(name);
+    validate_crate_name(crate_name)?;
+    if let Some(label) = label {
+        validate_label(label)?;
+    }
+
+    Ok(())
+}
+
+fn validate_crate_name(name: &str) -> Result<()> {
+    ensure!(name.is_empty().not(), "Package names can't be empty");
+    ensure!(
+        name.len() <= MAX_NAME_LENGTH,
+        "Invalid package name `{}`: longer than {MAX_NAME_LENGTH} characters",
+        name.escape_debug()
+    );
+
+    if let Some(ch) = name
+        .chars()
+        .find(|&ch| ch.is_ascii_alphanumeric().not() && ch != '-' && ch != '_')
+    {
+        bail!(
+            "Invalid package name `{}`: `{}` is not allowed, only ASCII letters, digits, `-` and `_` are",
+            name.escape_debug(),
+            ch.escape_debug()
+        );
+    }
+    ensure!(
+        name.starts_with(|ch: char| ch.is_ascii_alphabetic()),
+        "Invalid package name `{name}`: it must start with a letter"
+    );
+
+    Ok(())
+}
+
+/// Make sure `label` is made of ASCII letters, digits, `-` and `_`
+pub fn validate_label(label: &str) -> Result<()> {
+    ensure!(label.is_empty().not(), "Labels can't be empty");
+    ensure!(
+        label.len() <= MAX_NAME_LENGTH,
+        "Invalid label `{}`: longer than {MAX_NAME_LENGTH} characters",
+        label.escape_debug()
+    );
+
+    if let Some(ch) = label
+        .chars()
+        .find(|&ch| ch.is_ascii_alphanumeric().not() && ch != '-' && ch != '_')
+    {
+        bail!(
+            "Invalid label `{}`: `{}` is not allowed, only ASCII letters, digits, `-` and `_` are",
+            label.escape_debug(),
+            ch.escape_debug()
+        );
+    }
+
+    Ok(())
+}
+
+/// Make sure `version` is made of the characters versions and variant labels are made of, as in `1.0.0-rc.1+debug`,
+/// or names a channel, as in `stable`, or a git snapshot, as in `main.abcdef0`
+pub fn validate_version(version: &str) -> Result<()> {
+    ensure!(version.is_empty().not(), "Versions can't be empty");
+    ensure!(
+        version.len() <= MAX_VERSION_LENGTH,
+        "Invalid version `{}`: longer than {MAX_VERSION_LENGTH} characters",
+        version.escape_debug()
+    );
+
+    if let Some(ch) = version
+        .chars()
+        .find(|&ch| ch.is_ascii_alphanumeric().not() && matches!(ch, '.' | '-' | '_' | '+').not())
+    {
+        bail!(
+            "Invalid version `{}`: `{}` is not allowed, only ASCII letters, digits, `.`, `-`, `_` and `+` are",
+            version.escape_debug(),
+            ch.escape_debug()
+        );
+    }
+    ensure!(
+        version.starts_with('.').not(),
+        "Invalid version `{version}`: it can't start with a dot"
+    );
+    ensure!(
+        version.contains("..").not(),
+        "Invalid version `{version}`: it can't contain `..`"
+    );
+    ensure!(
+        version.chars().any(|ch| ch.is_ascii_digit())
+            || is_channel_name(version)
+            || is_snapshot_directory(split_variant(version).0),
+        "Invalid version `{version}`: it doesn't look like a version"
+    );
+
+    Ok(())
+}
+
+/// `spec` as a string, as given on the command line. Specs are the only thing that must be valid UTF-8, since they
+/// name packages on crates.io; paths, binary names and arguments passed along to binaries can be anything.
+pub fn spec_str(spec: &OsStr) -> Result<&str> {
+    spec.to_str().with_context(|| {
+        format!(
+            "Invalid spec `{}`: package names and versions must be valid UTF-8",
+            spec.to_string_lossy()
+        )
+    })
+}
+
+/// Split `spec` into its package name and version, making sure both are safe to use as directory names
+pub fn parse_spec(spec: &str) -> Result<(&str, &str)> {
+    let Some((name, version)) = spec.split_once('@') else {
+        bail!("Expected `NAME@VERSION`, found `{}`", spec.escape_debug());
+    };
+    ensure!(
+        name.is_empty().not() && version.is_empty().not(),
+        "Expected `NAME@VERSION`, found `{}`",
+        spec.escape_debug()
+    );
+
+    validate_name(name)?;
+    validate_version(version)?;
+
+    Ok((name, version))
+}
+
+/// The spec `package`, as in `NAME` or `NAME@VERSION`, stands for once joined with the `version` given through
+/// `--version`, the way `cargo install` takes it. The version can be given either way, but when given both ways they
+/// must agree.
+pub fn with_version(package: &str, version: Option<&str>) -> Result<String> {
+    let Some(version) = version else {
+        return Ok(package.to_owned());
+    };
+
+    match package.split_once('@') {
+        None => Ok(format!("{package}@{version}")),
+        Some((_, given)) if given == version => Ok(package.to_owned()),
+        Some((name, given)) => bail!(
+            "{package} asks for version {given}, but --version asks for {version}. Give the version only once, \
+             as in `{name}@{version}` or `{name} --version {version}`"
+        ),
+    }
+}
+
+/// [`with_version`] for commands taking several packages, which `--version` is only given along with one of
+pub fn specs_with_version(packages: &[String], version: Option<&str>) -> Result<Vec<String>> {
+    ensure!(
+        version.is_none() || packages.len() == 1,
+        "--version can only be given along with a single package"
+    );
+
+    packages
+        .iter()
+        .map(|package| with_version(package, version))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    use super::normalize_name;
+    use super::parse_spec;
+    use super::spec_str;
+    use super::split_label;
+    use super::validate_name;
+    use super::with_version;
+
+    #[test]
+    fn requires_utf8_specs() {
+        assert_eq!(
+            spec_str(OsStr::new("ripgrep@14.1.0")).unwrap(),
+            "ripgrep@14.1.0"
+        );
+
+        let err = spec_str(OsStr::from_bytes(b"rip\xffgrep@14.1.0")).unwrap_err();
+        assert!(err.to_string().contains("must be valid UTF-8"), "{err}");
+    }
+
+    #[test]
+    fn joins_versions_given_through_flags() {
+        assert_eq!(with_version("ripgrep", None).unwrap(), "ripgrep");
+        assert_eq!(
+            with_version("ripgrep@14.1.0", None).unwrap(),
+            "ripgrep@14.1.0"
+        );
+        assert_eq!(
+            with_version("ripgrep", Some("14.1.0")).unwrap(),
+            "ripgrep@14.1.0"
+        );
+        assert_eq!(
+            with_version("ripgrep@14.1.0", Some("14.1.0")).unwrap(),
+            "ripgrep@14.1.0"
+        );
+
+        let err = with_version("ripgrep@14.1.0", Some("13.0.0")).unwrap_err();
+        assert!(err.to_string().contains("ripgrep@13.0.0"), "{err}");
+    }
+
+    #[test]
+    fn parses_specs() {
+        assert_eq!(parse_spec("sqlx-cli@0.7.2").unwrap(), ("sqlx-cli", "0.7.2"));
+        assert_eq!(
+            parse_spec("wasm_bindgen@1.0.0-rc.1+debug").unwrap(),
+            ("wasm_bindgen", "1.0.0-rc.1+debug")
+        );
+        assert_eq!(parse_spec("a@1").unwrap(), ("a", "1"));
+        assert_eq!(
+            parse_spec("tool#my-fork@1.0.0").unwrap(),
+            ("tool#my-fork", "1.0.0")
+        );
+        assert_eq!(split_label("tool#my-fork"), ("tool", Some("my-fork")));
+        assert_eq!(split_label("tool"), ("tool", None));
+        assert_eq!(parse_spec("mytool@testing").unwrap(), ("mytool", "testing"));
+    }
+
+    #[test]
+    fn normalizes_separators() {
+        assert_eq!(normalize_name("fd_find"), normalize_name("fd-find"));
+        assert_eq!(normalize_name("fd_find#my_fork"), "fd-find#my_fork");
+        assert_ne!(
+            normalize_name("fd-find#my_fork"),
+            normalize_name("fd-find#my-fork")
+        );
+    }
+
+    #[test]
+    fn rejects_path_traversal() {
+        for spec in [
+            "../../evil@1.0.0",
+            "..@1.0.0",
+            ".hidden@1.0.0",
+            "evil/../../x@1.0.0",
+            "/etc@1.0.0",
+            "evil\\..\\x@1.0.0",
+            "evil@../../1.0.0",
+            "evil@..",
+            "evil@1.0..0",
+            "evil@.1.0.0",
+            "evil@1.0.0/../../x",
+            "evil@/1.0.0",
+            "evil@1.0.0\\x",
+            "evil@1.0.0@2.0.0",
+        ] {
+            assert!(parse_spec(spec).is_err(), "{spec} was accepted");
+        }
+    }
+
+    #[test]
+    fn rejects_bogus_specs() {
+        for spec in [
+            "",
+            "@",
+            "@1.0.0",
+            "evil@",
+            "evil",
+            "evil@.rc",
+            "evil@+rc",
+            "1evil@1.0.0",
+            "-evil@1.0.0",
+            "ev il@1.0.0",
+            "evil\n@1.0.0",
+            "evil@1.0.0\n",
+            "evil\0@1.0.0",
+            "evil@1.0.0\u{1b}[31m",
+            "évil@1.0.0",
+            "evil@1.0.0 ",
+            "evil#@1.0.0",
+            "#fork@1.0.0",
+            "evil#fo/rk@1.0.0",
+            "evil#fork#again@1.0.0",
+            "evil#../x@1.0.0",
+        ] {
+            assert!(parse_spec(spec).is_err(), "{spec:?} was accepted");
+        }
+
+        assert!(validate_name(&"a".repeat(64)).is_ok());
+        assert!(validate_name(&"a".repeat(65)).is_err());
+        assert!(parse_spec(&format!("evil@1.{}", "0".repeat(200))).is_err());
+    }
+
+    #[test]
+    fn explains_what_is_wrong() {
+        assert_eq!(
+            parse_spec("../evil@1.0.0").unwrap_err().to_string(),
+            "Invalid package name `../evil`: `.` is not allowed, only ASCII letters, digits, `-` and `_` are"
+        );
+        assert_eq!(
+            parse_spec("evil@1.0\n").unwrap_err().to_string(),
+            "Invalid version `1.0\\n`: `\\n` is not allowed, only ASCII letters, digits, `.`, `-`, `_` and `+` are"
+        );
+        assert_eq!(
+            parse_spec("tool#my.fork@1.0.0").unwrap_err().to_string(),
+            "Invalid label `my.fork`: `.` is not allowed, only ASCII letters, digits, `-` and `_` are"
+        );
+        assert_eq!(
+            parse_spec("evil").unwrap_err().to_string(),
+            "Expected `NAME@VERSION`, found `evil`"
+        );
+    }
+}
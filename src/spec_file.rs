@@ -0,0 +1,65 @@
+//! Lists of package specs, one per line, as accepted by `install --from-file` and `install -`.
+
+use std::io::BufRead;
+
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::spec::parse_spec;
+
+/// The outcome of reading a list of specs: the well-formed ones, and an error message for each malformed line
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SpecList {
+    pub specs: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// Read specs from `reader`, skipping blank lines and `#` comments. `source` is used to point at malformed lines.
+pub fn read_specs(reader: impl BufRead, source: &str) -> Result<SpecList> {
+    let mut list = SpecList::default();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line.with_context(|| format!("Failed to read {source}"))?;
+        let spec = line.split('#').next().unwrap_or_default().trim();
+
+        if spec.is_empty() {
+            continue;
+        }
+
+        match parse_spec(spec) {
+            Ok(_) => list.specs.push(spec.to_owned()),
+            Err(err) => list.errors.push(format!("{source}:{}: {err}", index + 1)),
+        }
+    }
+
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_specs;
+    use super::SpecList;
+
+    #[test]
+    fn reads_specs() {
+        let contents = "\
+            # Tools for the web team\n\
+            sqlx-cli@0.7.2\n\
+            \n\
+            trunk@0.18.8   # the newest one breaks our build\n\
+            wasm-bindgen-cli\n\
+            \t  \n\
+            @1.0.0\n";
+
+        assert_eq!(
+            read_specs(contents.as_bytes(), "tools.txt").unwrap(),
+            SpecList {
+                specs: vec!["sqlx-cli@0.7.2".to_owned(), "trunk@0.18.8".to_owned()],
+                errors: vec![
+                    "tools.txt:5: Expected `NAME@VERSION`, found `wasm-bindgen-cli`".to_owned(),
+                    "tools.txt:7: Expected `NAME@VERSION`, found `@1.0.0`".to_owned(),
+                ],
+            }
+        );
+    }
+}
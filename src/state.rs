@@ -0,0 +1,108 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::migrate::newer_format;
+use crate::migrate::FORMAT_VERSION;
+
+/// Name of the file, inside the registry, where the state that isn't derivable from the registry's layout is kept.
+///
+/// Starts with a dot so it can never be mistaken for an installed package.
+pub(crate) const STATE_FILE_NAME: &str = ".state.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct State {
+    /// The format of the whole registry, see [`crate::migrate`]
+    #[serde(default)]
+    pub format_version: u32,
+    /// Versions explicitly chosen through `cargo switch default`, keyed by package name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defaults: BTreeMap<String, String>,
+    /// Versions assigned to named channels through `cargo switch channel set`, keyed by package name then channel
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub channels: BTreeMap<String, BTreeMap<String, String>>,
+    /// When each version was last switched to, in seconds since the Unix epoch, keyed by package name then version
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub activated_at: BTreeMap<String, BTreeMap<String, u64>>,
+    /// Versions locked through `cargo switch lock`, which no cleanup ever removes, keyed by package name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub locked: BTreeMap<String, BTreeSet<String>>,
+    /// The versioned links in `.cargo/bin`, as in `rg-14`, keyed by link name. See [`crate::versioned_links`].
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub versioned_links: BTreeMap<String, LinkOwner>,
+    /// The packages switched to with `--keep-suffixed`, which keep getting versioned links from then on, whether
+    /// they have any right now or not
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub keep_suffixed: BTreeSet<String>,
+    /// Every directory links were made into, which changes along with `--link-dir`, so that `doctor` can find the
+    /// links left behind in the others
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    pub link_dirs: BTreeSet<PathBuf>,
+}
+
+/// The version a versioned link belongs to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LinkOwner {
+    pub package: String,
+    pub version: String,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            defaults: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            activated_at: BTreeMap::new(),
+            locked: BTreeMap::new(),
+            versioned_links: BTreeMap::new(),
+            keep_suffixed: BTreeSet::new(),
+            link_dirs: BTreeSet::new(),
+        }
+    }
+}
+
+impl State {
+    fn path(registry: &Path) -> PathBuf {
+        registry.join(STATE_FILE_NAME)
+    }
+
+    pub fn load(registry: &Path) -> Result<Self> {
+        let path = Self::path(registry);
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let state: Self = serde_json::from_str(&contents)
+            .with_context(|| format!("{} is corrupt", path.display()))?;
+        if state.format_version > FORMAT_VERSION {
+            return Err(newer_format(registry, state.format_version));
+        }
+
+        Ok(state)
+    }
+
+    pub fn save(&self, registry: &Path) -> Result<()> {
+        let path = Self::path(registry);
+        let temporary_path = path.with_extension("json.tmp");
+
+        // Write to a temporary file and rename it over the old one so that the state file is never left half-written
+        fs::write(&temporary_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&temporary_path, &path)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
@@ -0,0 +1,144 @@
+//! Stripping binaries of their symbols and debug info, which are often most of their size.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::cargo::find_in_path;
+use crate::format::human_size;
+use crate::metadata::BinaryMetadata;
+use crate::metadata::VersionMetadata;
+use crate::spec::parse_spec;
+use crate::Switcher;
+
+/// The `strip` able to handle binaries built for `target`: the cross toolchain's own if there's one, the host's
+/// otherwise
+pub fn strip_for(target: Option<&str>) -> Result<PathBuf> {
+    let path = env::var_os("PATH").unwrap_or_default();
+
+    target
+        .and_then(|target| find_in_path(format!("{target}-strip"), &path))
+        .or_else(|| find_in_path("strip", &path))
+        .with_context(|| "Failed to find `strip` in $PATH")
+}
+
+/// Strip the binary at `path` in place
+pub fn strip_binary(strip: &Path, path: &Path) -> Result<()> {
+    let status = Command::new(strip)
+        .arg(path)
+        .status()
+        .with_context(|| format!("Failed to run {}", strip.display()))?;
+    ensure!(
+        status.success(),
+        "Failed to strip {}: {} exited with {status}",
+        path.display(),
+        strip.display()
+    );
+
+    Ok(())
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// Strip every one of `binaries`, warning about the ones that can't be, and print how much space it saved. Returns
+/// the size every stripped binary had before, keyed by name.
+pub fn strip_binaries(strip: &Path, binaries: &[PathBuf]) -> BTreeMap<String, u64> {
+    let mut unstripped_sizes = BTreeMap::new();
+    let (mut before, mut after) = (0, 0);
+
+    for binary in binaries {
+        let size = file_size(binary);
+        if let Err(err) = strip_binary(strip, binary) {
+            eprintln!("Warning: {err:#}");
+            continue;
+        }
+
+        before += size;
+        after += file_size(binary);
+        let name = binary.file_name().unwrap_or_default().to_string_lossy();
+        unstripped_sizes.insert(name.into_owned(), size);
+    }
+
+    if unstripped_sizes.is_empty().not() {
+        println!(
+            "Stripped {} binar{}: {} -> {}, saving {}",
+            unstripped_sizes.len(),
+            if unstripped_sizes.len() == 1 {
+                "y"
+            } else {
+                "ies"
+            },
+            human_size(before),
+            human_size(after),
+            human_size(before.saturating_sub(after))
+        );
+    }
+
+    unstripped_sizes
+}
+
+/// Strip the binaries freshly installed in `bin_path` for `target`, or for the host if not given. Only warns if
+/// there's no `strip` around, since the binaries are perfectly usable as they are.
+pub fn strip_installed(bin_path: &Path, target: Option<&str>) -> Result<BTreeMap<String, u64>> {
+    let strip = match strip_for(target) {
+        Ok(strip) => strip,
+        Err(err) => {
+            eprintln!("Warning: not stripping the binaries. {err:#}");
+            return Ok(BTreeMap::new());
+        }
+    };
+
+    Ok(strip_binaries(&strip, &Switcher::scan_binaries(bin_path)?))
+}
+
+impl Switcher {
+    /// Strip the binaries of an installed `package@version`, updating what its metadata says about them
+    pub fn strip(&self, package: &str) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let (name, version) = parse_spec(package)?;
+        let version_path = self.version_path(name, version);
+        if version_path.exists().not() {
+            return Err(self.not_installed(name, Some(version)));
+        }
+        self.ensure_not_shared(name, version, "strip")?;
+
+        let _lock = self.lock_package(name)?;
+        let strip = strip_for(None)?;
+        let binaries = self.version_binaries(name, version)?;
+        let unstripped_sizes = strip_binaries(&strip, &binaries);
+
+        let mut metadata = VersionMetadata::load(&version_path)?.unwrap_or_default();
+        // Stripping twice doesn't make the original size any smaller
+        let recorded: BTreeMap<_, _> = metadata
+            .binaries
+            .iter()
+            .filter_map(|binary| Some((binary.name.clone(), binary.unstripped_size?)))
+            .collect();
+        metadata.binaries = binaries
+            .iter()
+            .map(|path| {
+                let name = path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned();
+                BinaryMetadata {
+                    unstripped_size: recorded.get(&name).or(unstripped_sizes.get(&name)).copied(),
+                    size: file_size(path),
+                    name,
+                }
+            })
+            .collect();
+        metadata.save(&version_path)
+    }
+}
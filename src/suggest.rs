@@ -0,0 +1,174 @@
+//! "Did you mean" suggestions for packages and versions that aren't installed.
+
+use std::ops::Not;
+
+use anyhow::anyhow;
+
+use crate::events;
+use crate::events::ErrorKind;
+use crate::Switcher;
+
+/// How many single-character edits (insertions, deletions, substitutions and swaps of adjacent characters) it takes
+/// to turn `a` into `b`, ignoring case
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+
+    // distances[i][j] is the distance between the first i characters of `a` and the first j of `b`
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + substitution);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(distances[i - 2][j - 2] + 1);
+            }
+            distances[i][j] = distance;
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// The candidate closest to `input`, if any is close enough to plausibly be what was meant. Ties go to the
+/// earliest candidate.
+pub fn closest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    // Short names have little room for typos before they start looking like other names entirely
+    let threshold = (input.chars().count() / 3).max(1);
+
+    nearest(input, candidates).filter(|candidate| edit_distance(input, candidate) <= threshold)
+}
+
+/// The candidate closest to `input`, however far it is
+pub fn nearest<'a>(input: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let mut nearest: Option<(usize, &str)> = None;
+    for candidate in candidates {
+        let distance = edit_distance(input, candidate);
+        if nearest.is_none_or(|(nearest, _)| distance < nearest) {
+            nearest = Some((distance, candidate));
+        }
+    }
+
+    nearest.map(|(_, candidate)| candidate)
+}
+
+impl Switcher {
+    /// The package whose name, or the name of one of whose binaries, is closest to `package`
+    fn suggest_package(&self, package: &str) -> Option<String> {
+        let packages = self.installed_packages().ok()?;
+        if let Some(suggestion) = closest(package, packages.iter().map(String::as_str)) {
+            return Some(suggestion.to_owned());
+        }
+
+        // People often know a tool by its binary, like `rg` for ripgrep
+        let mut binaries = Vec::new();
+        for installed in &packages {
+            for version in self.installed_versions(installed).unwrap_or_default() {
+                for binary in self
+                    .version_binaries(installed, &version)
+                    .unwrap_or_default()
+                {
+                    let name = binary.file_name().unwrap_or_default().to_string_lossy();
+                    binaries.push((name.into_owned(), installed));
+                }
+            }
+        }
+        let binary = closest(package, binaries.iter().map(|(binary, _)| binary.as_str()))?;
+        binaries
+            .iter()
+            .find(|(name, _)| name == binary)
+            .map(|(_, installed)| (*installed).clone())
+    }
+
+    /// The error for `package`, or `package@version`, not being installed, pointing at what was probably meant
+    pub fn not_installed(&self, package: &str, version: Option<&str>) -> anyhow::Error {
+        events::classify(
+            self.not_installed_message(package, version),
+            ErrorKind::NotInstalled,
+        )
+    }
+
+    fn not_installed_message(&self, package: &str, version: Option<&str>) -> anyhow::Error {
+        let spec = match version {
+            Some(version) => format!("{package}@{version}"),
+            None => package.to_owned(),
+        };
+
+        if self.package_exists(package).not() {
+            return match self.suggest_package(package) {
+                Some(suggestion) => {
+                    let suggestion = match version {
+                        Some(version) => format!("{suggestion}@{version}"),
+                        None => suggestion,
+                    };
+                    anyhow!("Project {spec} is not installed! Did you mean `{suggestion}`?")
+                }
+                None => anyhow!("Project {spec} is not installed!"),
+            };
+        }
+
+        let versions = self.installed_versions(package).unwrap_or_default();
+        match version.and_then(|version| nearest(version, versions.iter().map(String::as_str))) {
+            Some(suggestion) => anyhow!(
+                "Project {spec} is not installed! Did you mean `{package}@{suggestion}`? Installed versions: {}",
+                versions.join(", ")
+            ),
+            None => anyhow!("Project {spec} is not installed!"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::closest;
+    use super::edit_distance;
+    use super::nearest;
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(edit_distance("ripgrep", "ripgrep"), 0);
+        // Swapped letters are a single typo
+        assert_eq!(edit_distance("ripgerp", "ripgrep"), 1);
+        assert_eq!(edit_distance("RipGrep", "ripgrep"), 0);
+        assert_eq!(edit_distance("4.1.0", "14.1.0"), 1);
+        assert_eq!(edit_distance("", "fd"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggests_near_misses_only() {
+        let packages = ["bat", "cargo-watch", "fd-find", "ripgrep", "sqlx-cli"];
+
+        assert_eq!(closest("ripgerp", packages), Some("ripgrep"));
+        assert_eq!(closest("sqlx_cli", packages), Some("sqlx-cli"));
+        assert_eq!(closest("cargo-wacth", packages), Some("cargo-watch"));
+        assert_eq!(closest("bta", packages), Some("bat"));
+        assert_eq!(closest("fdfind", packages), Some("fd-find"));
+
+        // Short names are too easy to confuse, and unrelated ones shouldn't be suggested at all
+        assert_eq!(closest("cat", ["bat"]), Some("bat"));
+        assert_eq!(closest("zig", packages), None);
+        assert_eq!(closest("bacon", packages), None);
+        assert_eq!(closest("ripgrep", []), None);
+    }
+
+    #[test]
+    fn finds_nearest_version() {
+        let versions = ["0.9.0", "13.0.0", "14.1.0"];
+
+        assert_eq!(nearest("4.1.0", versions), Some("14.1.0"));
+        assert_eq!(nearest("13.0", versions), Some("13.0.0"));
+        assert_eq!(nearest("0.9.1", versions), Some("0.9.0"));
+        assert_eq!(nearest("1.0.0", []), None);
+    }
+}
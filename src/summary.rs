@@ -0,0 +1,188 @@
+//! How commands dealing with many specs at once, such as installing several packages, `project-install`,
+//! `update --all` and `rebuild --all`, go about failures and sum up how it all went.
+//!
+//! By default they keep going past failures, which can be changed through the `failure-mode` config key or
+//! `--fail-fast` and `--keep-going`. Either way, they end with a table of every spec and its outcome, also emitted as
+//! a `summary` event, and fail if anything did.
+
+use std::fmt;
+
+use anyhow::bail;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::events::Event;
+use crate::table::print_table;
+use crate::Switcher;
+
+/// What to do when one of many specs fails
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureMode {
+    /// Carry on with the others, failing once they're all done
+    #[default]
+    KeepGoing,
+    /// Stop at the first failure, skipping whatever was left
+    FailFast,
+}
+
+/// What became of a spec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// A version was installed
+    Installed,
+    /// A version that was installed already was switched to
+    Switched,
+    /// A version was built again in place
+    Rebuilt,
+    /// A version was switched to, then switched away from again as another one of the same command couldn't be
+    RolledBack,
+    /// Nothing had to be done, or nothing was tried after an earlier failure
+    Skipped,
+    Failed,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Installed => "installed",
+            Outcome::Switched => "switched",
+            Outcome::Rebuilt => "rebuilt",
+            Outcome::RolledBack => "rolled back",
+            Outcome::Skipped => "skipped",
+            Outcome::Failed => "failed",
+        })
+    }
+}
+
+/// A spec and what became of it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SpecOutcome {
+    pub spec: String,
+    pub outcome: Outcome,
+    /// Why it was skipped or failed, or else something worth knowing about how it went, such as how long it took
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+/// The outcomes of many specs, gathered as they're dealt with
+#[derive(Debug, Clone)]
+pub struct Summary {
+    mode: FailureMode,
+    outcomes: Vec<SpecOutcome>,
+}
+
+impl Summary {
+    pub fn new(mode: FailureMode) -> Self {
+        Self {
+            mode,
+            outcomes: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, spec: impl Into<String>, outcome: Outcome, detail: Option<String>) {
+        self.outcomes.push(SpecOutcome {
+            spec: spec.into(),
+            outcome,
+            detail,
+        });
+    }
+
+    pub fn record_failure(&mut self, spec: impl Into<String>, err: &anyhow::Error) {
+        self.record(spec, Outcome::Failed, Some(format!("{err:#}")));
+    }
+
+    /// Whether the specs left should be skipped, as something failed and failures stop everything
+    pub fn stopped(&self) -> bool {
+        self.mode == FailureMode::FailFast && self.failures() > 0
+    }
+
+    /// Record that `spec` was skipped, if [`Summary::stopped`], returning whether it was
+    pub fn skip_if_stopped(&mut self, spec: impl Into<String>) -> bool {
+        let stopped = self.stopped();
+        if stopped {
+            self.record(
+                spec,
+                Outcome::Skipped,
+                Some("not tried, as something failed before it".to_owned()),
+            );
+        }
+
+        stopped
+    }
+
+    pub fn failures(&self) -> usize {
+        self.outcomes
+            .iter()
+            .filter(|outcome| outcome.outcome == Outcome::Failed)
+            .count()
+    }
+
+    pub fn outcomes(&self) -> &[SpecOutcome] {
+        &self.outcomes
+    }
+
+    pub fn print(&self) {
+        let rows: Vec<_> = self
+            .outcomes
+            .iter()
+            .map(|outcome| {
+                [
+                    outcome.spec.clone(),
+                    outcome.outcome.to_string(),
+                    outcome.detail.clone().unwrap_or_else(|| "-".to_owned()),
+                ]
+            })
+            .collect();
+
+        println!();
+        print_table(["SPEC", "RESULT", "DETAIL"], &rows);
+    }
+}
+
+impl Switcher {
+    /// Print `summary` and emit it as an event, failing if any of its specs did, as in "2 of 5 `failed`", e.g.
+    /// "packages failed"
+    pub(crate) fn finish_summary(&self, summary: Summary, failed: &str) -> Result<()> {
+        summary.print();
+        self.emit(Event::Summary {
+            specs: summary.outcomes.clone(),
+        });
+
+        let failures = summary.failures();
+        if failures > 0 {
+            bail!("{failures} of {} {failed}", summary.outcomes.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use anyhow::anyhow;
+
+    use super::FailureMode;
+    use super::Outcome;
+    use super::Summary;
+
+    #[test]
+    fn stops_at_the_first_failure_only_when_failing_fast() {
+        for mode in [FailureMode::KeepGoing, FailureMode::FailFast] {
+            let mut summary = Summary::new(mode);
+            summary.record("a@1.0.0", Outcome::Installed, None);
+            assert!(summary.skip_if_stopped("b@1.0.0").not());
+            summary.record_failure("b@1.0.0", &anyhow!("could not compile"));
+
+            let skipped = summary.skip_if_stopped("c@1.0.0");
+            assert_eq!(skipped, mode == FailureMode::FailFast);
+            assert_eq!(summary.failures(), 1);
+            assert_eq!(summary.outcomes().len(), 2 + usize::from(skipped));
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Switching several packages at once, as in `cargo switch ripgrep@14.1.0 fd-find@9.0.0 just@1.25.0`.
+//!
+//! Every spec is checked, and whatever is missing installed, before anything is switched, so that a typo in the last
+//! one doesn't leave the others switched. Should a switch still fail halfway through, the packages switched before it
+//! are switched back to where they were, and the summary tells which ones couldn't be.
+
+use std::collections::BTreeSet;
+use std::ops::Not;
+
+use anyhow::ensure;
+use anyhow::Result;
+
+use crate::channel::is_channel_name;
+use crate::install::InstallOptions;
+use crate::spec::parse_spec;
+use crate::summary::FailureMode;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::Switcher;
+
+impl Switcher {
+    /// Switch to every one of `specs`, in order, installing the missing ones first if `install` is set or the user
+    /// agrees to it when asked. Either all of them are switched to or, as far as can be helped, none of them is.
+    pub fn switch_packages(&self, specs: &[String], install: bool) -> Result<()> {
+        if let [spec] = specs {
+            return self.switch_or_install(spec, install, &InstallOptions::default());
+        }
+
+        let mut packages = BTreeSet::new();
+        let mut wanted = Vec::new();
+        for spec in specs {
+            let spec = self.canonical_spec(spec)?;
+            let (package, version) = parse_spec(&spec)?;
+            ensure!(
+                packages.insert(package.to_owned()),
+                "{package} was given more than once"
+            );
+            wanted.push((package.to_owned(), version.to_owned()));
+        }
+
+        // Channels can only point to what's installed already, so there's nothing to offer installing
+        let missing: Vec<_> = wanted
+            .iter()
+            .filter(|(package, version)| {
+                self.pick_variant(package, version).is_err() && is_channel_name(version).not()
+            })
+            .collect();
+        if let Some((package, version)) = missing.first() {
+            let specs: Vec<_> = missing
+                .iter()
+                .map(|(package, version)| format!("{package}@{version}"))
+                .collect();
+            let question = format!("{} not installed. Install now?", specs.join(", "));
+            if install.not() && self.confirm(&question)?.not() {
+                return Err(self.not_installed(package, Some(version)));
+            }
+
+            let options = InstallOptions {
+                no_switch: true,
+                ..InstallOptions::default()
+            };
+            for spec in &specs {
+                self.install_package(spec, &options)?.print();
+            }
+        }
+
+        // Only now can every version tell which build it stands for
+        let mut targets = Vec::new();
+        for (package, version) in wanted {
+            let version = self.pick_variant(&package, &version)?;
+            targets.push((package, version));
+        }
+
+        let mut switched = Vec::new();
+        let mut failure = None;
+        for (index, (package, version)) in targets.iter().enumerate() {
+            let spec = format!("{package}@{version}");
+            let previous = self.linked_version(package)?;
+            if previous.as_ref() == Some(version) {
+                switched.push((spec, previous, false));
+                continue;
+            }
+            if let Err(err) = self.switch_package(&spec) {
+                eprintln!("Failed to switch to {spec}: {err:#}");
+                failure = Some((index, spec, err));
+                break;
+            }
+            switched.push((spec, previous, true));
+        }
+
+        // Halfway through is no place to stay
+        let mut summary = Summary::new(FailureMode::FailFast);
+        let Some((failed, spec, err)) = failure else {
+            for (spec, previous, changed) in switched {
+                match (changed, previous) {
+                    (false, _) => {
+                        summary.record(spec, Outcome::Skipped, Some("active already".to_owned()))
+                    }
+                    (true, Some(previous)) => summary.record(
+                        spec,
+                        Outcome::Switched,
+                        Some(format!("replaced {previous}")),
+                    ),
+                    (true, None) => summary.record(spec, Outcome::Switched, None),
+                }
+            }
+            return self.finish_summary(summary, "packages failed");
+        };
+
+        let mut outcomes = Vec::new();
+        for (spec, previous, changed) in switched.into_iter().rev() {
+            if changed.not() {
+                outcomes.push((spec, Outcome::Skipped, "active already".to_owned()));
+                continue;
+            }
+
+            let (package, _) = parse_spec(&spec)?;
+            let rolled_back = match &previous {
+                Some(previous) => self.switch_package(&format!("{package}@{previous}")),
+                None => self.unlink_package(package),
+            };
+            outcomes.push(match (rolled_back, previous) {
+                (Ok(()), Some(previous)) => (
+                    spec,
+                    Outcome::RolledBack,
+                    format!("switched back to {previous}"),
+                ),
+                (Ok(()), None) => (spec, Outcome::RolledBack, "unlinked again".to_owned()),
+                (Err(err), _) => {
+                    eprintln!("Failed to switch {package} back: {err:#}");
+                    (
+                        spec,
+                        Outcome::Switched,
+                        format!("left switched, as switching it back failed: {err:#}"),
+                    )
+                }
+            });
+        }
+        for (spec, outcome, detail) in outcomes.into_iter().rev() {
+            summary.record(spec, outcome, Some(detail));
+        }
+        summary.record_failure(spec, &err);
+        for (package, version) in &targets[failed + 1..] {
+            summary.skip_if_stopped(format!("{package}@{version}"));
+        }
+
+        self.finish_summary(summary, "packages failed")
+    }
+
+    /// Remove the links to whichever version of `package` is active, leaving it without one
+    fn unlink_package(&self, package: &str) -> Result<()> {
+        let _lock = self.lock_package(package)?;
+        let _links = self.lock_links()?;
+        for link in self.managed_links()? {
+            if link.package == package && link.versioned.not() {
+                self.remove_link(&link.link)?;
+                println!("Removed {}", link.link.display());
+            }
+        }
+        self.forget_cargo_records(package);
+
+        Ok(())
+    }
+}
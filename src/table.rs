@@ -0,0 +1,60 @@
+/// Print `rows` as a table whose columns are aligned, below a header
+pub fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    for line in render_table(header, rows) {
+        println!("{line}");
+    }
+}
+
+fn render_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) -> Vec<String> {
+    let mut widths = header.map(|column| column.chars().count());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let render_row = |cells: [&str; N]| {
+        let mut line = String::new();
+        for (index, (cell, width)) in cells.iter().zip(widths).enumerate() {
+            line.push_str(cell);
+            if index + 1 < N {
+                let padding = width - cell.chars().count() + 2;
+                line.extend(std::iter::repeat_n(' ', padding));
+            }
+        }
+        line
+    };
+
+    std::iter::once(render_row(header))
+        .chain(
+            rows.iter()
+                .map(|row| render_row(row.each_ref().map(String::as_str))),
+        )
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_table;
+
+    #[test]
+    fn aligns_columns() {
+        let rows = [
+            ["ripgrep".to_owned(), "14.1.0".to_owned(), "ok".to_owned()],
+            [
+                "fd-find".to_owned(),
+                "9.0.0".to_owned(),
+                "failed".to_owned(),
+            ],
+        ];
+
+        assert_eq!(
+            render_table(["PACKAGE", "VERSION", "RESULT"], &rows),
+            [
+                "PACKAGE  VERSION  RESULT",
+                "ripgrep  14.1.0   ok",
+                "fd-find  9.0.0    failed",
+            ]
+        );
+    }
+}
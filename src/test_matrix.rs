@@ -0,0 +1,285 @@
+//! `test-matrix`: running a command once against every installed version of a package, to see how it behaves across
+//! all of them. As with `bisect`, each version's binaries are put first in `$PATH` for the command alone, so the
+//! links in `.cargo/bin` are never touched.
+
+use std::cmp::Ordering;
+use std::ffi::OsString;
+use std::ops::Not;
+use std::path::Path;
+use std::process::Command;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering as AtomicOrdering;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Error;
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::table::print_table;
+use crate::variant::split_variant;
+use crate::version::compare_versions;
+use crate::Switcher;
+
+/// Versions between two bounds, both included, as in `0.9.50..0.9.66`. Either bound can be left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VersionRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+impl FromStr for VersionRange {
+    type Err = Error;
+
+    fn from_str(range: &str) -> Result<Self> {
+        let Some((start, end)) = range.split_once("..") else {
+            bail!("Invalid version range `{range}`, expected something like `0.9.50..0.9.66`");
+        };
+        // `..=` means the same, as both bounds are included anyway
+        let end = end.strip_prefix('=').unwrap_or(end);
+        let bound = |bound: &str| (bound.is_empty().not()).then(|| bound.to_owned());
+
+        Ok(Self {
+            start: bound(start),
+            end: bound(end),
+        })
+    }
+}
+
+impl VersionRange {
+    /// Whether `version`, or the version it's a variant of, is in the range
+    pub fn contains(&self, version: &str) -> bool {
+        let (version, _) = split_variant(version);
+
+        self.start
+            .as_deref()
+            .is_none_or(|start| compare_versions(version, start) != Ordering::Less)
+            && self
+                .end
+                .as_deref()
+                .is_none_or(|end| compare_versions(version, end) != Ordering::Greater)
+    }
+}
+
+/// How the command did against one version
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    /// Exited with 0
+    Pass,
+    /// Exited with anything else
+    Fail,
+    /// Was terminated by a signal
+    Killed,
+    /// Couldn't be run at all
+    Error,
+}
+
+/// What happened when running the command against one version
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MatrixEntry {
+    pub version: String,
+    pub outcome: Outcome,
+    pub exit_code: Option<i32>,
+    pub duration_ms: u64,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Everything one run of the command needs, settled before anything runs
+struct Run {
+    version: String,
+    path: OsString,
+    env: Vec<(String, String)>,
+}
+
+fn run_command(run: &Run, command: &[OsString]) -> MatrixEntry {
+    let started = Instant::now();
+    let output = Command::new(&command[0])
+        .args(&command[1..])
+        .env("PATH", &run.path)
+        .envs(run.env.iter().map(|(name, value)| (name, value)))
+        .stdin(Stdio::null())
+        .output();
+    let duration_ms = started.elapsed().as_millis() as u64;
+
+    let (outcome, exit_code, stdout, stderr) = match output {
+        Ok(output) => {
+            let outcome = match output.status.code() {
+                Some(0) => Outcome::Pass,
+                Some(_) => Outcome::Fail,
+                None => Outcome::Killed,
+            };
+            (
+                outcome,
+                output.status.code(),
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            )
+        }
+        Err(err) => (
+            Outcome::Error,
+            None,
+            String::new(),
+            format!(
+                "Failed to run `{}`: {err}",
+                Path::new(&command[0]).display()
+            ),
+        ),
+    };
+
+    MatrixEntry {
+        version: run.version.clone(),
+        outcome,
+        exit_code,
+        duration_ms,
+        stdout,
+        stderr,
+    }
+}
+
+/// Run `command` for every one of `runs`, `jobs` of them at a time, returning what happened in the same order
+fn run_all(runs: &[Run], command: &[OsString], jobs: usize) -> Vec<MatrixEntry> {
+    let next = AtomicUsize::new(0);
+    let entries: Vec<_> = runs.iter().map(|_| Mutex::new(None)).collect();
+
+    thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, runs.len().max(1)) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, AtomicOrdering::Relaxed);
+                let Some(run) = runs.get(index) else {
+                    break;
+                };
+                *entries[index].lock().unwrap() = Some(run_command(run, command));
+            });
+        }
+    });
+
+    entries
+        .into_iter()
+        .filter_map(|entry| entry.into_inner().unwrap())
+        .collect()
+}
+
+impl Switcher {
+    /// Run `command` once against every installed version of `package` in `range`, `jobs` versions at a time, and
+    /// print how it did against each of them, as JSON if `json` is set. Fails if the command failed against any.
+    pub fn test_matrix(
+        &self,
+        package: &str,
+        range: &VersionRange,
+        jobs: usize,
+        json: bool,
+        command: &[OsString],
+    ) -> Result<()> {
+        let package = &self.canonical_name(package)?;
+        ensure!(
+            command.is_empty().not(),
+            "No command given, pass it after `--`"
+        );
+
+        let mut runs = Vec::new();
+        for version in self.installed_versions(package)? {
+            if range.contains(&version).not() {
+                continue;
+            }
+            runs.push(Run {
+                path: self.path_with_version(package, &version)?,
+                env: self
+                    .wrapper_env(package, &version)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .collect(),
+                version,
+            });
+        }
+        ensure!(
+            runs.is_empty().not(),
+            "No installed version of {package} is in the range"
+        );
+
+        let entries = run_all(&runs, command, jobs);
+        let failures = entries
+            .iter()
+            .filter(|entry| entry.outcome != Outcome::Pass)
+            .count();
+
+        if json {
+            println!("{}", serde_json::to_string_pretty(&entries)?);
+        } else {
+            let rows: Vec<_> = entries
+                .iter()
+                .map(|entry| {
+                    let outcome = match entry.outcome {
+                        Outcome::Pass => "pass",
+                        Outcome::Fail => "fail",
+                        Outcome::Killed => "killed",
+                        Outcome::Error => "error",
+                    };
+                    [
+                        entry.version.clone(),
+                        outcome.to_owned(),
+                        entry
+                            .exit_code
+                            .map_or_else(|| "-".to_owned(), |code| code.to_string()),
+                        format!(
+                            "{:.2}s",
+                            Duration::from_millis(entry.duration_ms).as_secs_f64()
+                        ),
+                    ]
+                })
+                .collect();
+            print_table(["VERSION", "RESULT", "EXIT", "TIME"], &rows);
+
+            // What went wrong is only worth showing where something did
+            for entry in &entries {
+                if entry.outcome != Outcome::Pass && entry.stderr.trim().is_empty().not() {
+                    println!("\n{package}@{}:", entry.version);
+                    for line in entry.stderr.lines() {
+                        println!("  {line}");
+                    }
+                }
+            }
+        }
+
+        if failures > 0 {
+            bail!("{failures} of {} versions failed", entries.len());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::VersionRange;
+
+    #[test]
+    fn filters_versions_through_ranges() {
+        let range: VersionRange = "0.9.50..0.9.66".parse().unwrap();
+        assert!(range.contains("0.9.50"));
+        assert!(range.contains("0.9.66"));
+        assert!(range.contains("0.9.60+debug"));
+        assert!(range.contains("0.9.49").not());
+        assert!(range.contains("0.10.0").not());
+
+        let range: VersionRange = "0.9.60..".parse().unwrap();
+        assert!(range.contains("1.0.0"));
+        assert!(range.contains("0.9.59").not());
+
+        let range: VersionRange = "..=0.9.60".parse().unwrap();
+        assert!(range.contains("0.1.0"));
+        assert!(range.contains("0.9.61").not());
+
+        assert!("0.9.50".parse::<VersionRange>().is_err());
+    }
+}
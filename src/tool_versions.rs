@@ -0,0 +1,170 @@
+//! asdf and mise's `.tool-versions`, which pins one tool per line, as in
+//!
+//! ```text
+//! nodejs 20.11.0
+//! rg 14.1.0 # ripgrep
+//! ```
+//!
+//! Lines naming packages cargo-switch manages count as project pins, unless the project's `.cargo-switch.toml` pins
+//! the same package. Packages go by their own name unless the `tool-versions` config table says otherwise.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+
+/// Name of the file asdf and mise read pins from
+pub const TOOL_VERSIONS_FILE_NAME: &str = ".tool-versions";
+
+/// The part of `line` that isn't a comment
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(code, _)| code)
+}
+
+/// The pins of a `.tool-versions`
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ToolVersions {
+    /// The version each tool is pinned to. Fallback versions after the first one are left out.
+    pub tools: BTreeMap<String, String>,
+}
+
+impl ToolVersions {
+    pub fn parse(contents: &str) -> Self {
+        let mut tools = BTreeMap::new();
+        for line in contents.lines() {
+            let mut words = strip_comment(line).split_whitespace();
+            let (Some(tool), Some(version)) = (words.next(), words.next()) else {
+                continue;
+            };
+            // The first line pinning a tool is the one asdf goes by
+            tools
+                .entry(tool.to_owned())
+                .or_insert_with(|| version.to_owned());
+        }
+
+        Self { tools }
+    }
+
+    /// Look for a `.tool-versions` in `directory` and in every one of its ancestors, returning the closest one found
+    pub fn discover(directory: &Path) -> Result<Option<(PathBuf, Self)>> {
+        for ancestor in directory.ancestors() {
+            let path = ancestor.join(TOOL_VERSIONS_FILE_NAME);
+            if path.is_file().not() {
+                continue;
+            }
+
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+
+            return Ok(Some((path, Self::parse(&contents))));
+        }
+
+        Ok(None)
+    }
+
+    /// Pin every one of `pins`, keyed by tool name, in the `.tool-versions` at `path`, creating it if needed. Only
+    /// the versions being pinned change, every other line and whatever surrounds those versions is kept as is.
+    pub fn update_pins(path: &Path, pins: &BTreeMap<String, String>) -> Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", path.display()))
+            }
+        };
+
+        let mut pinned = Vec::new();
+        let mut updated = String::new();
+        for line in contents.split_inclusive('\n') {
+            let code = strip_comment(line);
+            let mut words = code.split_whitespace();
+            let version = match (words.next(), words.next()) {
+                (Some(tool), Some(version)) if pinned.contains(&tool).not() => pins
+                    .get_key_value(tool)
+                    .map(|(tool, pin)| (tool.as_str(), version, pin)),
+                _ => None,
+            };
+            let Some((tool, version, pin)) = version else {
+                updated.push_str(line);
+                continue;
+            };
+
+            // `version` borrows from `line`, so its offset tells where it is
+            let start = version.as_ptr() as usize - line.as_ptr() as usize;
+            updated.push_str(&line[..start]);
+            updated.push_str(pin);
+            updated.push_str(&line[start + version.len()..]);
+            pinned.push(tool);
+        }
+
+        for (tool, version) in pins {
+            if pinned.contains(&tool.as_str()) {
+                continue;
+            }
+            if updated.is_empty().not() && updated.ends_with('\n').not() {
+                updated.push('\n');
+            }
+            updated.push_str(&format!("{tool} {version}\n"));
+        }
+
+        fs::write(path, updated).with_context(|| format!("Failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use super::ToolVersions;
+    use super::TOOL_VERSIONS_FILE_NAME;
+
+    #[test]
+    fn reads_the_first_version_of_each_tool() {
+        let tool_versions = ToolVersions::parse(
+            "# runtimes\nnodejs 20.11.0 18.19.0\n\n  rg   14.1.0 # ripgrep\nnodejs 21.0.0\nbroken\n",
+        );
+
+        assert_eq!(
+            tool_versions.tools,
+            BTreeMap::from([
+                ("nodejs".to_owned(), "20.11.0".to_owned()),
+                ("rg".to_owned(), "14.1.0".to_owned()),
+            ])
+        );
+    }
+
+    #[test]
+    fn updates_pins_keeping_the_rest() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path().join(TOOL_VERSIONS_FILE_NAME);
+        fs::write(
+            &path,
+            "# runtimes\nnodejs 20.11.0\n  rg   14.0.0 13.0.0 # ripgrep\njust 1.0.0",
+        )
+        .unwrap();
+
+        let pins = BTreeMap::from([
+            ("rg".to_owned(), "14.1.0".to_owned()),
+            ("sqlx-cli".to_owned(), "0.7.2".to_owned()),
+        ]);
+        ToolVersions::update_pins(&path, &pins).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# runtimes\nnodejs 20.11.0\n  rg   14.1.0 13.0.0 # ripgrep\njust 1.0.0\nsqlx-cli 0.7.2\n"
+        );
+
+        // A missing file is created
+        fs::remove_file(&path).unwrap();
+        ToolVersions::update_pins(&path, &pins).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "rg 14.1.0\nsqlx-cli 0.7.2\n"
+        );
+    }
+}
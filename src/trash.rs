@@ -0,0 +1,464 @@
+//! The trash, where uninstalled and pruned versions go instead of being deleted right away, so that `undelete` can
+//! bring them back. Each trashed copy lives at `.trash/<package>/<version>-<timestamp>`, the timestamp being when it
+//! was trashed, in seconds since the Unix epoch.
+//!
+//! The trash counts toward the registry's size, and is the first to go when the registry grows past `max-size`:
+//! trashed copies are removed, oldest first, before any installed version is considered for eviction. Whatever was
+//! trashed longer than `trash-retention` ago is removed on every prune.
+//!
+//! The default, channels and lock that pointed to a version when it was trashed are recorded in its trashed copy, so
+//! that undeleting it brings them back.
+
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::format::human_duration;
+use crate::format::human_size;
+use crate::metadata::timestamp;
+use crate::quota::directory_size;
+use crate::spec::normalize_name;
+use crate::spec::parse_spec;
+use crate::state::State;
+use crate::table::print_table;
+use crate::Switcher;
+
+/// Directory, inside the registry, that holds the trash
+pub const TRASH_DIRECTORY_NAME: &str = ".trash";
+
+/// File, inside a trashed copy, that records what pointed to the version when it was trashed
+const POINTERS_FILE_NAME: &str = ".trashed-pointers.json";
+
+/// How long trashed copies are kept when `trash-retention` isn't set
+pub const DEFAULT_TRASH_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// A copy of a version in the trash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrashedVersion {
+    pub package: String,
+    pub version: String,
+    /// Seconds since the Unix epoch
+    pub trashed_at: u64,
+    pub path: PathBuf,
+}
+
+impl TrashedVersion {
+    fn trashed_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.trashed_at)
+    }
+}
+
+/// What pointed to a version in the state when it was trashed
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Pointers {
+    /// Whether it was its package's default version
+    #[serde(default)]
+    pub default: bool,
+    /// The channels of its package that pointed to it
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+impl Pointers {
+    /// Take whatever points to `package@version` out of `state`
+    pub(crate) fn take(state: &mut State, package: &str, version: &str) -> Self {
+        let default = state
+            .defaults
+            .get(package)
+            .is_some_and(|default| default == version);
+        if default {
+            state.defaults.remove(package);
+        }
+
+        let mut channels = Vec::new();
+        if let Some(package_channels) = state.channels.get_mut(package) {
+            package_channels.retain(|channel, channel_version| {
+                let keep = channel_version != version;
+                if keep.not() {
+                    channels.push(channel.clone());
+                }
+                keep
+            });
+            if package_channels.is_empty() {
+                state.channels.remove(package);
+            }
+        }
+
+        let mut locked = false;
+        if let Some(locked_versions) = state.locked.get_mut(package) {
+            locked = locked_versions.remove(version);
+            if locked_versions.is_empty() {
+                state.locked.remove(package);
+            }
+        }
+
+        Self {
+            default,
+            channels,
+            locked,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// What was recorded in the trashed copy at `path`, which is forgotten along the way
+    fn take_recorded(path: &Path) -> Result<Self> {
+        let file = path.join(POINTERS_FILE_NAME);
+        let contents = match fs::read_to_string(&file) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", file.display()))
+            }
+        };
+        let pointers = serde_json::from_str(&contents)
+            .with_context(|| format!("{} is corrupt", file.display()))?;
+        fs::remove_file(&file).with_context(|| format!("Failed to remove {}", file.display()))?;
+
+        Ok(pointers)
+    }
+}
+
+/// The version and timestamp a trashed copy is named after, as in `1.0.0-1700000000`. Versions may hold dashes of
+/// their own, timestamps never do.
+fn parse_entry_name(name: &str) -> Option<(&str, u64)> {
+    let (version, trashed_at) = name.rsplit_once('-')?;
+    Some((version, trashed_at.parse().ok()?))
+}
+
+impl Switcher {
+    fn trash_directory(&self) -> PathBuf {
+        self.registry.join(TRASH_DIRECTORY_NAME)
+    }
+
+    /// Move `package@version` into the trash, on behalf of a caller already holding the package's lock, recording
+    /// the `pointers` that were taken from it
+    pub(crate) fn trash_version(
+        &self,
+        package: &str,
+        version: &str,
+        pointers: &Pointers,
+    ) -> Result<()> {
+        let version_path = self.version_path(package, version);
+        let directory = self.trash_directory().join(package);
+        fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+
+        // Trashing the same version twice within a second shouldn't lose the first copy
+        let mut trashed_at = timestamp(SystemTime::now());
+        while directory.join(format!("{version}-{trashed_at}")).exists() {
+            trashed_at += 1;
+        }
+        let path = directory.join(format!("{version}-{trashed_at}"));
+        fs::rename(&version_path, &path).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                version_path.display(),
+                path.display()
+            )
+        })?;
+
+        // Losing them only means undeleting the version doesn't point to it again
+        if pointers.is_empty().not() {
+            let file = path.join(POINTERS_FILE_NAME);
+            let written = serde_json::to_string_pretty(pointers)
+                .map_err(anyhow::Error::from)
+                .and_then(|contents| Ok(fs::write(&file, contents)?));
+            if let Err(err) = written {
+                eprintln!(
+                    "Warning: failed to record what pointed to {package}@{version} in {}: {err:#}",
+                    file.display()
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Every copy in the trash, by package, version and from the oldest to the newest
+    pub fn trashed_versions(&self) -> Result<Vec<TrashedVersion>> {
+        let packages = match fs::read_dir(self.trash_directory()) {
+            Ok(packages) => packages,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut trashed = Vec::new();
+        for maybe_package in packages {
+            let package = maybe_package?;
+            let Ok(package_name) = package.file_name().into_string() else {
+                continue;
+            };
+            for maybe_entry in fs::read_dir(package.path())? {
+                let entry = maybe_entry?;
+                let Ok(entry_name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let Some((version, trashed_at)) = parse_entry_name(&entry_name) else {
+                    continue;
+                };
+                trashed.push(TrashedVersion {
+                    package: package_name.clone(),
+                    version: version.to_owned(),
+                    trashed_at,
+                    path: entry.path(),
+                });
+            }
+        }
+        trashed.sort_by(|a, b| {
+            (&a.package, &a.version, a.trashed_at).cmp(&(&b.package, &b.version, b.trashed_at))
+        });
+
+        Ok(trashed)
+    }
+
+    /// Remove `trashed` for good, along with its package's directory in the trash if nothing else is left in it
+    fn purge_trashed(&self, trashed: &TrashedVersion) -> Result<()> {
+        fs::remove_dir_all(&trashed.path)
+            .with_context(|| format!("Failed to remove {}", trashed.path.display()))?;
+        // Only succeeds if the directory is empty
+        let _ = fs::remove_dir(self.trash_directory().join(&trashed.package));
+
+        Ok(())
+    }
+
+    /// Bring back the most recently trashed copy of `spec`, as in `name@version`. It isn't switched to.
+    pub fn undelete(&self, spec: &str) -> Result<()> {
+        let (name, version) = parse_spec(spec)?;
+        let Some(trashed) = self
+            .trashed_versions()?
+            .into_iter()
+            .filter(|trashed| {
+                normalize_name(&trashed.package) == normalize_name(name)
+                    && trashed.version == version
+            })
+            .max_by_key(|trashed| trashed.trashed_at)
+        else {
+            bail!(
+                "{name}@{version} isn't in the trash. Run `cargo switch trash list` to see what is"
+            );
+        };
+
+        // Other versions may have been installed under another spelling since
+        let canonical = self.canonical_name(name)?;
+        let package = if self.package_exists(&canonical) {
+            canonical
+        } else {
+            trashed.package.clone()
+        };
+
+        let _lock = self.lock_package(&package)?;
+        let version_path = self.version_path(&package, version);
+        if version_path.exists() {
+            bail!("{package}@{version} is installed already. Uninstall it first to restore the trashed copy");
+        }
+        fs::rename(&trashed.path, &version_path).with_context(|| {
+            format!(
+                "Failed to move {} to {}",
+                trashed.path.display(),
+                version_path.display()
+            )
+        })?;
+        let _ = fs::remove_dir(self.trash_directory().join(&trashed.package));
+        self.sync_versioned_links(&package)?;
+
+        println!(
+            "Restored {package}@{version}, trashed {}. Run `cargo switch {package}@{version}` to switch to it",
+            humantime::format_rfc3339_seconds(trashed.trashed_at())
+        );
+        let restored = Pointers::take_recorded(&version_path)
+            .and_then(|pointers| self.restore_pointers(&package, version, &pointers));
+        if let Err(err) = restored {
+            eprintln!("Warning: failed to point to {package}@{version} again as before it was trashed: {err:#}");
+        }
+
+        Ok(())
+    }
+
+    /// Point to `package@version` again as `pointers` did before it was trashed, leaving alone the default and
+    /// channels that were set to something else since
+    fn restore_pointers(&self, package: &str, version: &str, pointers: &Pointers) -> Result<()> {
+        if pointers.is_empty() {
+            return Ok(());
+        }
+
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        if pointers.default {
+            match state.defaults.get(package) {
+                Some(default) => println!(
+                    "Left {default} the default version of {package}, which it became after {version} was trashed"
+                ),
+                None => {
+                    state
+                        .defaults
+                        .insert(package.to_owned(), version.to_owned());
+                    println!("The default version of {package} is {version} again");
+                }
+            }
+        }
+        for channel in &pointers.channels {
+            let channels = state.channels.entry(package.to_owned()).or_default();
+            match channels.get(channel) {
+                Some(current) => println!(
+                    "Left the {channel} channel of {package} pointing to {current}, as it did after {version} was \
+                     trashed"
+                ),
+                None => {
+                    channels.insert(channel.clone(), version.to_owned());
+                    println!("The {channel} channel of {package} points to {version} again");
+                }
+            }
+        }
+        if pointers.locked {
+            state
+                .locked
+                .entry(package.to_owned())
+                .or_default()
+                .insert(version.to_owned());
+            println!("Locked {package}@{version} again");
+        }
+
+        state.save(&self.registry)
+    }
+
+    pub fn print_trash(&self) -> Result<()> {
+        let trashed = self.trashed_versions()?;
+        if trashed.is_empty() {
+            println!("The trash is empty");
+            return Ok(());
+        }
+
+        let mut rows = Vec::new();
+        let mut total = 0;
+        for trashed in &trashed {
+            let size = directory_size(&trashed.path)?;
+            total += size;
+            rows.push([
+                trashed.package.clone(),
+                trashed.version.clone(),
+                humantime::format_rfc3339_seconds(trashed.trashed_at()).to_string(),
+                human_size(size),
+            ]);
+        }
+        print_table(["PACKAGE", "VERSION", "TRASHED", "SIZE"], &rows);
+        println!();
+        println!(
+            "{} trashed version(s), taking {}",
+            trashed.len(),
+            human_size(total)
+        );
+
+        Ok(())
+    }
+
+    /// Remove everything from the trash, or only what was trashed at least `older_than` ago
+    pub fn empty_trash(&self, older_than: Option<Duration>) -> Result<()> {
+        let now = SystemTime::now();
+        let mut removed = 0;
+        let mut freed = 0;
+        for trashed in self.trashed_versions()? {
+            let age = now.duration_since(trashed.trashed_at()).unwrap_or_default();
+            if older_than.is_some_and(|older_than| age < older_than) {
+                continue;
+            }
+
+            freed += directory_size(&trashed.path)?;
+            self.purge_trashed(&trashed)?;
+            removed += 1;
+        }
+
+        println!(
+            "Removed {removed} trashed version(s), freeing {}",
+            human_size(freed)
+        );
+
+        Ok(())
+    }
+
+    /// Remove the trashed copies that were trashed longer than `trash-retention` ago, as every prune does
+    pub(crate) fn expire_trash(&self) -> Result<()> {
+        let retention = self
+            .config
+            .trash_retention
+            .unwrap_or(DEFAULT_TRASH_RETENTION);
+        let now = SystemTime::now();
+        for trashed in self.trashed_versions()? {
+            let age = now.duration_since(trashed.trashed_at()).unwrap_or_default();
+            if age < retention {
+                continue;
+            }
+
+            self.purge_trashed(&trashed)?;
+            println!(
+                "Removed the trashed copy of {}@{}, trashed over {} ago",
+                trashed.package,
+                trashed.version,
+                human_duration(retention)
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Remove trashed copies, oldest first, until a registry of `total` bytes fits in `max_size` or the trash is
+    /// empty, returning the size the registry is left with
+    pub(crate) fn purge_trash_to_fit(&self, total: u64, max_size: u64) -> Result<u64> {
+        let mut trashed = self.trashed_versions()?;
+        trashed.sort_by_key(|trashed| trashed.trashed_at);
+
+        let mut size = total;
+        for trashed in trashed {
+            if size <= max_size {
+                break;
+            }
+
+            let trashed_size = directory_size(&trashed.path)?;
+            self.purge_trashed(&trashed)?;
+            size = size.saturating_sub(trashed_size);
+            println!(
+                "Removed the trashed copy of {}@{} ({}) to keep the registry under its max-size of {}",
+                trashed.package,
+                trashed.version,
+                human_size(trashed_size),
+                human_size(max_size)
+            );
+        }
+
+        Ok(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_entry_name;
+
+    #[test]
+    fn parses_entry_names() {
+        assert_eq!(
+            parse_entry_name("13.0.0-1700000000"),
+            Some(("13.0.0", 1700000000))
+        );
+        assert_eq!(
+            parse_entry_name("1.0.0-rc.1+debug-1700000000"),
+            Some(("1.0.0-rc.1+debug", 1700000000))
+        );
+        assert_eq!(parse_entry_name("13.0.0"), None);
+        assert_eq!(parse_entry_name("13.0.0-later"), None);
+    }
+}
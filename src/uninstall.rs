@@ -0,0 +1,91 @@
+use std::fs;
+use std::ops::Not;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::operation_log::Operation;
+use crate::spec::parse_spec;
+use crate::state::State;
+use crate::trash::Pointers;
+use crate::Switcher;
+
+impl Switcher {
+    /// Move `package@version` from the registry into the trash, removing the links pointing to it. The package itself
+    /// goes away once its last version does. Locked versions are only removed if `force` is set.
+    pub fn uninstall(&self, package: &str, force: bool) -> Result<()> {
+        let package = &self.canonical_spec(package)?;
+        let (name, version) = parse_spec(package)?;
+        let version_path = self.registry.join(name).join(version);
+        self.ensure_not_shared(name, version, "uninstall")?;
+        if version_path.exists().not() {
+            return Err(self.not_installed(name, Some(version)));
+        }
+        ensure!(
+            force || self.locked_versions(name)?.contains(version).not(),
+            "{package} is locked. Pass --force to uninstall it anyway, or run `cargo switch unlock {package}` first"
+        );
+
+        // The package's directory goes away along with the lock once its last version is gone
+        let _lock = self.lock_package(name)?;
+        self.uninstall_unlocked(name, version, true)?;
+        self.log(
+            Operation::Uninstall,
+            Some(name),
+            Some(version),
+            "moved to the trash",
+        );
+
+        println!(
+            "Uninstalled {package}, which went to the trash. Run `cargo switch undelete {package}` to restore it"
+        );
+
+        Ok(())
+    }
+
+    /// Uninstall `name@version` on behalf of a caller already holding the package's lock, moving it into the trash
+    /// if `trash` is set or else deleting it for good
+    pub(crate) fn uninstall_unlocked(&self, name: &str, version: &str, trash: bool) -> Result<()> {
+        let version_path = self.registry.join(name).join(version);
+        let _links = self.lock_links()?;
+        let mut was_active = false;
+        for link in self.managed_links()? {
+            if link.package == name && link.version == version {
+                self.remove_link(&link.link)?;
+                println!("Removed {}", link.link.display());
+                was_active |= link.versioned.not();
+            }
+        }
+        if was_active {
+            self.forget_cargo_records(name);
+        }
+
+        // A default or channel that isn't installed anymore would only get in the way, until the version is undeleted
+        let mut state = State::load(&self.registry)?;
+        let pointers = Pointers::take(&mut state, name, version);
+        for channel in &pointers.channels {
+            println!("Removed the {channel} channel of {name}, which pointed to {version}");
+        }
+        if trash {
+            self.trash_version(name, version, &pointers)?;
+        } else {
+            fs::remove_dir_all(&version_path)
+                .with_context(|| format!("Failed to remove {}", version_path.display()))?;
+        }
+
+        let mut changed = pointers != Pointers::default();
+        if let Some(activated_at) = state.activated_at.get_mut(name) {
+            changed |= activated_at.remove(version).is_some();
+            if activated_at.is_empty() {
+                state.activated_at.remove(name);
+            }
+        }
+        if changed {
+            state.save(&self.registry)?;
+        }
+
+        // Another version may take over the versioned links of this one
+        self.sync_versioned_links_unlocked(name)
+    }
+}
@@ -0,0 +1,260 @@
+//! `update`: moving a package to its newest release on crates.io or, for git installs, to the head of the branch
+//! they follow. The new version is installed alongside the old ones, which stay around to switch back to.
+//!
+//! `update --all` does it for every package, following wherever its active version came from.
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::format::human_duration;
+use crate::git;
+use crate::install::InstallOptions;
+use crate::install::InstallReport;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::retry;
+use crate::retry::RetryPolicy;
+use crate::spec::split_label;
+use crate::spec::validate_name;
+use crate::summary::Outcome;
+use crate::summary::Summary;
+use crate::variant::variant_directory;
+use crate::Switcher;
+
+/// Options building the new version the way `metadata` says the old one was built
+fn options_like(metadata: Option<&VersionMetadata>) -> InstallOptions {
+    let Some(metadata) = metadata else {
+        return InstallOptions::default();
+    };
+    let flags = metadata.flags.as_ref();
+
+    InstallOptions {
+        profile: metadata.profile.clone(),
+        features: metadata.features.clone(),
+        // Stripped binaries recorded the size they had before
+        strip: Some(
+            metadata
+                .binaries
+                .iter()
+                .any(|binary| binary.unstripped_size.is_some()),
+        ),
+        toolchain: flags.and_then(|flags| flags.toolchain.clone()),
+        // The package's section of the config file is applied again, as it reads now, though what was turned on for
+        // the old version stays on
+        no_default_features: flags
+            .is_some_and(|flags| flags.no_default_features)
+            .then_some(true),
+        locked: flags.is_some_and(|flags| flags.locked).then_some(true),
+        ..InstallOptions::default()
+    }
+}
+
+/// What updating a package came to
+enum Updated {
+    /// The newest version was active already
+    Newest(String),
+    /// The newest version was installed already, and switched to
+    Switched(String),
+    Installed(InstallReport),
+}
+
+/// Where `update --all` looks for a newer version of a package, following wherever its active version came from
+enum Upstream {
+    CratesIo,
+    Git,
+    /// Nowhere, for this reason
+    Nowhere(&'static str),
+}
+
+impl Switcher {
+    /// Update `package` to its newest release on crates.io or, if `git` is set, to the head of the branch its
+    /// active git install, or else its latest one, follows
+    pub fn update(&self, package: &str, git: bool) -> Result<()> {
+        match self.update_package(package, git)? {
+            Updated::Newest(spec) => println!("{spec} is already the newest"),
+            Updated::Switched(_) => {}
+            Updated::Installed(report) => {
+                report.print();
+                if let Some(err) = report.switch_error {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Update every installed package from wherever its active version came from, crates.io or the branch of a git
+    /// repository, carrying on past failures unless failing fast, and summing it all up in a table
+    pub fn update_all(&self) -> Result<()> {
+        let mut summary = Summary::new(self.failure_mode);
+        for package in self.installed_packages()? {
+            if summary.skip_if_stopped(&package) {
+                continue;
+            }
+
+            let updated = self.upstream(&package).and_then(|upstream| match upstream {
+                Upstream::CratesIo => self.update_package(&package, false).map(Some),
+                Upstream::Git => self.update_package(&package, true).map(Some),
+                Upstream::Nowhere(reason) => {
+                    summary.record(&package, Outcome::Skipped, Some(reason.to_owned()));
+                    Ok(None)
+                }
+            });
+            match updated {
+                Ok(None) => {}
+                Ok(Some(Updated::Newest(spec))) => summary.record(
+                    spec,
+                    Outcome::Skipped,
+                    Some("already the newest".to_owned()),
+                ),
+                Ok(Some(Updated::Switched(spec))) => summary.record(spec, Outcome::Switched, None),
+                Ok(Some(Updated::Installed(report))) => {
+                    let spec = format!("{}@{}", report.package, report.version);
+                    match report.switch_error {
+                        None => summary.record(
+                            spec,
+                            Outcome::Installed,
+                            Some(human_duration(report.build_duration)),
+                        ),
+                        Some(err) => {
+                            eprintln!("Failed to switch to {spec}: {err:#}");
+                            let err = anyhow!("installed, but not switched to: {err:#}");
+                            summary.record_failure(spec, &err);
+                        }
+                    }
+                }
+                Err(err) => {
+                    eprintln!("Failed to update {package}: {err:#}");
+                    summary.record_failure(&package, &err);
+                }
+            }
+        }
+
+        self.finish_summary(summary, "packages failed to update")
+    }
+
+    /// Where to look for a newer version of `package`, going by its active version, or by crates.io if none is
+    fn upstream(&self, package: &str) -> Result<Upstream> {
+        let Some(active) = self.linked_version(package)? else {
+            return Ok(Upstream::CratesIo);
+        };
+        let metadata = VersionMetadata::load(&self.version_path(package, &active))?;
+
+        Ok(match metadata.and_then(|metadata| metadata.source) {
+            None | Some(Source::CratesIo) => Upstream::CratesIo,
+            Some(Source::Git { .. }) => Upstream::Git,
+            Some(Source::Path { .. }) => {
+                Upstream::Nowhere("the active version was built from a local path")
+            }
+            Some(Source::Url { .. }) => {
+                Upstream::Nowhere("the active version was downloaded from a URL")
+            }
+            Some(Source::External) => {
+                Upstream::Nowhere("the active version was registered through add-binary")
+            }
+        })
+    }
+
+    fn update_package(&self, package: &str, git: bool) -> Result<Updated> {
+        let package = &self.canonical_name(package)?;
+        validate_name(package)?;
+        let versions = self.installed_versions(package)?;
+        let active = self.linked_version(package)?;
+
+        let mut metadata = Vec::new();
+        for version in versions {
+            let version_metadata = VersionMetadata::load(&self.version_path(package, &version))?;
+            metadata.push((version, version_metadata));
+        }
+
+        let (version, options) = if git {
+            // The active version if it came from git, or else the last one installed from git
+            let followed = metadata
+                .iter()
+                .filter_map(|(version, metadata)| Some((version, metadata.as_ref()?)))
+                .filter(|(_, metadata)| matches!(metadata.source, Some(Source::Git { .. })))
+                .max_by_key(|(version, metadata)| {
+                    (active.as_ref() == Some(*version), metadata.installed_at)
+                });
+            let Some((_, followed)) = followed else {
+                bail!("{package} has no version installed from git to update");
+            };
+            let Some(Source::Git { url, rev, branch }) = &followed.source else {
+                unreachable!("only versions installed from git are followed");
+            };
+
+            let head = git::resolve_branch(url, branch.as_deref())?;
+            let reference = head.branch.as_deref().unwrap_or("head");
+            let options = options_like(Some(followed));
+            let version = variant_directory(
+                &git::snapshot_directory(reference, &head.rev),
+                options.profile.as_deref().unwrap_or("release"),
+                &self.build_flags(package, &options).0,
+            );
+            if rev.as_ref() != Some(&head.rev) {
+                eprintln!(
+                    "{reference} of {url} moved from {} to {}",
+                    rev.as_deref().map_or("an unknown commit", |rev| rev
+                        .get(..git::SHORT_REV_LENGTH)
+                        .unwrap_or(rev)),
+                    head.rev.get(..git::SHORT_REV_LENGTH).unwrap_or(&head.rev)
+                );
+            }
+
+            (
+                version,
+                InstallOptions {
+                    git: Some(url.clone()),
+                    branch: head.branch,
+                    rev: Some(head.rev),
+                    ..options
+                },
+            )
+        } else {
+            let policy = RetryPolicy::new(self.config.retries.unwrap_or(retry::DEFAULT_RETRIES));
+            let crates_io = self.crates_io()?;
+            let newest = policy
+                .run(&format!("look up the newest {package}"), || {
+                    crates_io
+                        .newest_version(split_label(package).0, self.allows_prereleases(package))
+                })
+                .with_context(|| {
+                    format!("Couldn't reach crates.io to look for a newer {package}")
+                })?;
+
+            let active_metadata = metadata
+                .iter()
+                .find(|(version, _)| active.as_ref() == Some(version))
+                .and_then(|(_, metadata)| metadata.as_ref());
+            let options = options_like(active_metadata);
+            let version = variant_directory(
+                &newest.to_string(),
+                options.profile.as_deref().unwrap_or("release"),
+                &self.build_flags(package, &options).0,
+            );
+
+            (version, options)
+        };
+
+        let newest = format!("{package}@{version}");
+        if metadata.iter().any(|(installed, _)| *installed == version) {
+            if active.as_ref() == Some(&version) {
+                return Ok(Updated::Newest(newest));
+            }
+            self.switch_package(&newest)?;
+            return Ok(Updated::Switched(newest));
+        }
+
+        // Git installs are named after their commit by the install itself
+        let spec = if options.git.is_some() {
+            package.to_owned()
+        } else {
+            newest
+        };
+
+        Ok(Updated::Installed(self.install_package(&spec, &options)?))
+    }
+}
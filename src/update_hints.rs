@@ -0,0 +1,130 @@
+//! Update hints: with the `update-hints` config key set, commands that went fine are followed, at most once a day, by
+//! a line telling how many active versions have newer releases. Only what crates.io said last, as kept in the
+//! [`cache`](crate::cache), is looked at, however stale, so the hint never waits on the network. Whatever goes wrong
+//! along the way only means there's no hint.
+
+use std::fs;
+use std::ops::Not;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use semver::Version;
+
+use crate::cache::CACHE_DIRECTORY_NAME;
+use crate::metadata;
+use crate::metadata::Source;
+use crate::metadata::VersionMetadata;
+use crate::spec::split_label;
+use crate::variant::split_variant;
+use crate::Switcher;
+
+/// Name of the file, in the cache directory, holding when the last hint was considered
+const HINT_STAMP_FILE_NAME: &str = "update-hint";
+/// How long to wait between hints
+pub const HINT_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Whether a hint was considered less than `HINT_INTERVAL` before `now`, going by the stamp `contents`
+fn hinted_recently(contents: &str, now: u64) -> bool {
+    contents
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|hinted_at| now.checked_sub(hinted_at))
+        .is_some_and(|elapsed| elapsed < HINT_INTERVAL.as_secs())
+}
+
+impl Switcher {
+    fn hint_stamp_path(&self) -> PathBuf {
+        self.registry
+            .join(CACHE_DIRECTORY_NAME)
+            .join(HINT_STAMP_FILE_NAME)
+    }
+
+    /// The active versions with newer releases, as `(package, active, newest)`, going by what crates.io said last.
+    /// Only versions installed from crates.io can have newer releases there.
+    pub fn cached_updates(&self) -> Vec<(String, String, Version)> {
+        let Ok(crates_io) = self.crates_io() else {
+            return Vec::new();
+        };
+
+        let mut updates = Vec::new();
+        for package in self.installed_packages().unwrap_or_default() {
+            if split_label(&package).1.is_some() {
+                continue;
+            }
+            let Ok(Some(active)) = self.linked_version(&package) else {
+                continue;
+            };
+            let from_crates_io = VersionMetadata::load(&self.version_path(&package, &active))
+                .ok()
+                .flatten()
+                .and_then(|metadata| metadata.source)
+                .is_none_or(|source| matches!(source, Source::CratesIo));
+            let Ok(current) = Version::parse(split_variant(&active).0) else {
+                continue;
+            };
+            let newest =
+                crates_io.cached_newest_version(&package, self.allows_prereleases(&package));
+            match newest {
+                Some(newest) if from_crates_io && newest > current => {
+                    updates.push((package, active, newest))
+                }
+                _ => {}
+            }
+        }
+
+        updates
+    }
+
+    /// Print how many active versions have newer releases, if the `update-hints` config key asks for it and no hint
+    /// was considered within the last day. Never fails, and never reaches the network.
+    pub fn print_update_hint(&self) {
+        if self.config.update_hints.not() {
+            return;
+        }
+
+        let now = metadata::timestamp(SystemTime::now());
+        let stamp = self.hint_stamp_path();
+        if fs::read_to_string(&stamp).is_ok_and(|contents| hinted_recently(&contents, now)) {
+            return;
+        }
+        // Stamped first, so that a registry that can't be written to doesn't get a hint after every command
+        let stamped = stamp
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&stamp, now.to_string()));
+        if stamped.is_err() {
+            return;
+        }
+
+        match self.cached_updates().len() {
+            0 => {}
+            1 => eprintln!(
+                "1 package has a newer version available, run `cargo switch update --all` to update it"
+            ),
+            count => eprintln!(
+                "{count} packages have newer versions available, run `cargo switch update --all` to update them"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Not;
+
+    use super::hinted_recently;
+    use super::HINT_INTERVAL;
+
+    #[test]
+    fn hints_at_most_once_a_day() {
+        let day = HINT_INTERVAL.as_secs();
+        assert!(hinted_recently("1000", 1000));
+        assert!(hinted_recently("1000\n", 1000 + day - 1));
+        assert!(hinted_recently("1000", 1000 + day).not());
+        // Stamps from the future, or that can't be read, don't hold hints back
+        assert!(hinted_recently("2000", 1000).not());
+        assert!(hinted_recently("garbage", 1000).not());
+    }
+}
@@ -0,0 +1,219 @@
+//! Builds of the same version made with different cargo profiles or feature sets are kept side by side in the
+//! registry, under `VERSION+LABEL`. Release builds with the default features get no label at all.
+//!
+//! Labels are made of dot-separated parts: the profile's, then the features', as in `0.7.2+debug.postgres`.
+
+use std::collections::BTreeSet;
+use std::ops::Not;
+use std::path::PathBuf;
+
+use anyhow::anyhow;
+use anyhow::Result;
+use sha2::Digest;
+use sha2::Sha256;
+
+use crate::events;
+use crate::events::ErrorKind;
+use crate::Switcher;
+
+/// Directory, inside the registry, holding builds cross-compiled for other targets, as `TRIPLE/PACKAGE/VERSION`.
+///
+/// Kept apart from the regular builds since they can't be switched to.
+const TARGETS_DIRECTORY_NAME: &str = ".targets";
+
+/// The variant label given to versions built with `--debug`, so that they never get mistaken for release builds
+pub const DEBUG_VARIANT: &str = "debug";
+
+/// Split a version like `1.0.0+debug` into the actual version and the label of the variant it refers to
+pub fn split_variant(version: &str) -> (&str, Option<&str>) {
+    match version.split_once('+') {
+        Some((version, variant)) => (version, Some(variant)),
+        None => (version, None),
+    }
+}
+
+/// The label of the variant built with `profile`
+pub fn profile_variant(profile: &str) -> Option<&str> {
+    match profile {
+        "release" => None,
+        "dev" => Some(DEBUG_VARIANT),
+        profile => Some(profile),
+    }
+}
+
+/// The profile that the variant labeled `variant` is built with
+pub fn variant_profile(variant: Option<&str>) -> &str {
+    match variant {
+        None => "release",
+        Some(DEBUG_VARIANT) => "dev",
+        Some(profile) => profile,
+    }
+}
+
+/// Longest feature label spelled out in full. Longer ones are replaced by a hash of the features.
+const MAX_FEATURES_LABEL_LENGTH: usize = 32;
+
+/// The features in `features`, as given to `--features`, sorted and without duplicates. Both commas and spaces
+/// separate features, as they do for cargo.
+pub fn normalize_features(features: &[String]) -> Vec<String> {
+    let features: BTreeSet<_> = features
+        .iter()
+        .flat_map(|features| features.split([',', ' ']))
+        .filter(|feature| feature.is_empty().not())
+        .map(str::to_owned)
+        .collect();
+
+    features.into_iter().collect()
+}
+
+/// The label part telling apart builds made with `features`, which must be normalized. Features that wouldn't make
+/// for a readable label, because of their length or of characters like the `/` in `dep/feature`, are hashed.
+fn features_variant(features: &[String]) -> Option<String> {
+    if features.is_empty() {
+        return None;
+    }
+
+    let label = features.join(".");
+    let readable = label.len() <= MAX_FEATURES_LABEL_LENGTH
+        && label
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '.');
+    if readable {
+        return Some(label);
+    }
+
+    let digest = Sha256::digest(label.as_bytes());
+    let hash: String = digest[..4]
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Some(format!("features-{hash}"))
+}
+
+/// The name of the registry directory holding `version` built with `profile` and `features`, which must be
+/// normalized
+pub fn variant_directory(version: &str, profile: &str, features: &[String]) -> String {
+    let parts: Vec<_> = profile_variant(profile)
+        .map(str::to_owned)
+        .into_iter()
+        .chain(features_variant(features))
+        .collect();
+
+    if parts.is_empty() {
+        version.to_owned()
+    } else {
+        format!("{version}+{}", parts.join("."))
+    }
+}
+
+impl Switcher {
+    /// Where the builds cross-compiled for `target` are kept, laid out like the registry itself
+    pub fn target_registry(&self, target: &str) -> PathBuf {
+        self.registry.join(TARGETS_DIRECTORY_NAME).join(target)
+    }
+
+    /// Every installed build of `version` of `package`, whatever its profile
+    pub fn installed_variants(&self, package: &str, version: &str) -> Result<Vec<String>> {
+        let (version, _) = split_variant(version);
+
+        Ok(self
+            .installed_versions(package)?
+            .into_iter()
+            .filter(|installed| split_variant(installed).0 == version)
+            .collect())
+    }
+
+    /// The installed build that `version` of `package` refers to: the exact one if it's installed, the one a channel
+    /// by that name points to or, for a version without a label, the only variant of that version
+    pub fn pick_variant(&self, package: &str, version: &str) -> Result<String> {
+        if self.version_path(package, version).exists() {
+            return Ok(version.to_owned());
+        }
+        if let Some(version) = self.channel_version(package, version)? {
+            return Ok(version);
+        }
+
+        let (bare_version, variant) = split_variant(version);
+        let variants = self
+            .installed_variants(package, version)
+            .unwrap_or_default();
+        match variants.as_slice() {
+            [] => Err(self.not_installed(package, Some(version))),
+            [only] if variant.is_none() => Ok(only.clone()),
+            variants => Err(events::classify(
+                anyhow!(
+                    "Project {package}@{version} is not installed! Installed variants of {package}@{bare_version}: {}",
+                    variants.join(", ")
+                ),
+                ErrorKind::NotInstalled,
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_features;
+    use super::profile_variant;
+    use super::split_variant;
+    use super::variant_directory;
+    use super::variant_profile;
+
+    #[test]
+    fn splits_variants() {
+        assert_eq!(split_variant("1.0.0"), ("1.0.0", None));
+        assert_eq!(split_variant("1.0.0+debug"), ("1.0.0", Some("debug")));
+        assert_eq!(
+            split_variant("1.0.0-rc1+release-lto"),
+            ("1.0.0-rc1", Some("release-lto"))
+        );
+    }
+
+    #[test]
+    fn maps_profiles_to_variants() {
+        for profile in ["release", "dev", "release-lto"] {
+            assert_eq!(variant_profile(profile_variant(profile)), profile);
+        }
+
+        assert_eq!(variant_directory("1.0.0", "release", &[]), "1.0.0");
+        assert_eq!(variant_directory("1.0.0", "dev", &[]), "1.0.0+debug");
+        assert_eq!(
+            variant_directory("1.0.0", "release-lto", &[]),
+            "1.0.0+release-lto"
+        );
+    }
+
+    #[test]
+    fn labels_feature_sets() {
+        let features = |features: &[&str]| {
+            normalize_features(
+                &features
+                    .iter()
+                    .map(|&feature| feature.to_owned())
+                    .collect::<Vec<_>>(),
+            )
+        };
+
+        assert_eq!(
+            variant_directory("0.7.2", "release", &features(&["postgres"])),
+            "0.7.2+postgres"
+        );
+        assert_eq!(
+            variant_directory("0.7.2", "dev", &features(&["postgres,mysql"])),
+            "0.7.2+debug.mysql.postgres"
+        );
+        // The same features in another order are the same build
+        assert_eq!(
+            features(&["mysql postgres", "mysql"]),
+            features(&["postgres", "mysql"])
+        );
+
+        let hashed = variant_directory("0.7.2", "release", &features(&["sqlx/runtime-tokio"]));
+        assert!(hashed.starts_with("0.7.2+features-"), "{hashed}");
+        assert_ne!(
+            hashed,
+            variant_directory("0.7.2", "release", &features(&["sqlx/runtime-async-std"]))
+        );
+        assert!(semver::Version::parse(&hashed).is_ok());
+    }
+}
@@ -0,0 +1,418 @@
+//! `vendor`: downloading everything a version needs to be built ahead of time, so that `install --offline` can build
+//! it without the network, say on a plane or in an air-gapped machine.
+//!
+//! Each vendored version gets a directory in the cache, `.cache/vendor/PACKAGE/VERSION`, holding:
+//!
+//! - `source`, the crate as published on crates.io, with a `Cargo.lock` generated if it wasn't published with one
+//! - `vendor`, every one of its dependencies, as `cargo vendor --versioned-dirs` lays them out
+//! - `cargo-config.toml`, which tells cargo to read its dependencies from `vendor` rather than from the network
+//! - `vendored.json`, written once everything else is there, so that an interrupted `vendor` is never mistaken for
+//!   a complete one
+//!
+//! Crates are downloaded from crates.io itself, whatever replaces it in cargo's configuration.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::Not;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::time::SystemTime;
+
+use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::cache::CACHE_DIRECTORY_NAME;
+use crate::cargo::CargoInstaller;
+use crate::checksum::sha256_file;
+use crate::download;
+use crate::extract::extract;
+use crate::extract::ArtifactKind;
+use crate::format::human_size;
+use crate::lockfile::Lockfile;
+use crate::metadata::timestamp;
+use crate::quota::directory_size;
+use crate::retry;
+use crate::retry::Failure;
+use crate::retry::RetryPolicy;
+use crate::spec::parse_spec;
+use crate::table::print_table;
+use crate::Switcher;
+
+/// Where crates.io serves `.crate` files from
+const DOWNLOAD_URL: &str = "https://static.crates.io/crates";
+
+/// Directory, inside the cache, where vendored versions live
+const VENDOR_DIRECTORY_NAME: &str = "vendor";
+
+/// The crate itself, inside a vendored version's directory
+pub const SOURCE_DIRECTORY_NAME: &str = "source";
+/// The crate's dependencies, inside a vendored version's directory
+const DEPENDENCIES_DIRECTORY_NAME: &str = "vendor";
+/// What cargo is given through `--config` to build from the vendored dependencies
+pub const CARGO_CONFIG_FILE_NAME: &str = "cargo-config.toml";
+/// Written last, telling complete vendored versions apart from interrupted ones
+const RECORD_FILE_NAME: &str = "vendored.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct VendorRecord {
+    /// The SHA-256 checksum of the `.crate` file, as crates.io gave it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<String>,
+    /// Seconds since the Unix epoch
+    vendored_at: u64,
+}
+
+/// Make sure everything a build from the vendored version at `path` needs is there, returning how many dependencies
+/// it has. Errors say what's missing.
+pub fn check_vendored(path: &Path) -> Result<usize> {
+    ensure!(
+        path.join(RECORD_FILE_NAME).exists(),
+        "vendoring it never finished"
+    );
+    let source = path.join(SOURCE_DIRECTORY_NAME);
+    ensure!(
+        source.join("Cargo.toml").exists(),
+        "its sources are missing"
+    );
+    ensure!(
+        path.join(CARGO_CONFIG_FILE_NAME).exists(),
+        "{CARGO_CONFIG_FILE_NAME} is missing"
+    );
+
+    let lockfile = Lockfile::load(&source.join("Cargo.lock"))?;
+
+    let dependencies = path.join(DEPENDENCIES_DIRECTORY_NAME);
+    let mut count = 0;
+    for package in lockfile.package {
+        if package.source.is_none() {
+            continue;
+        }
+        let directory = dependencies.join(format!("{}-{}", package.name, package.version));
+        ensure!(
+            directory.join(".cargo-checksum.json").exists(),
+            "{}@{} is missing from {}",
+            package.name,
+            package.version,
+            dependencies.display()
+        );
+        count += 1;
+    }
+
+    Ok(count)
+}
+
+/// Run `cargo` with `args`, returning what it printed to stdout. What it printed to stderr is only shown if it fails.
+pub(crate) fn run_cargo(cargo: &Path, args: &[&OsStr]) -> Result<String> {
+    let output = Command::new(cargo)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("Failed to execute {}", cargo.display()))?;
+
+    if output.status.success().not() {
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        bail!(
+            "cargo {} exited with {}",
+            args.first()
+                .map(|arg| arg.to_string_lossy())
+                .unwrap_or_default(),
+            output.status
+        );
+    }
+
+    String::from_utf8(output.stdout).context("cargo printed something that isn't UTF-8")
+}
+
+impl Switcher {
+    fn vendor_directory(&self) -> PathBuf {
+        self.registry
+            .join(CACHE_DIRECTORY_NAME)
+            .join(VENDOR_DIRECTORY_NAME)
+    }
+
+    /// Where the vendored sources of `name@version` are, once made sure they're all there
+    pub(crate) fn vendored(&self, name: &str, version: &str) -> Result<PathBuf> {
+        let path = self.vendor_directory().join(name).join(version);
+        ensure!(
+            path.exists(),
+            "{name}@{version} wasn't vendored, so it can't be installed offline. Run `cargo switch vendor \
+             {name}@{version}` while online first"
+        );
+        check_vendored(&path).with_context(|| {
+            format!(
+                "The vendored sources of {name}@{version} are incomplete. Run `cargo switch vendor {name}@{version}` \
+                 again while online"
+            )
+        })?;
+
+        Ok(path)
+    }
+
+    /// Download everything needed to later install every one of `specs`, as in `name@version`, offline, carrying on
+    /// past failures
+    pub fn vendor(&self, specs: &[String], retries: Option<u32>) -> Result<()> {
+        let retries = retries
+            .or(self.config.retries)
+            .unwrap_or(retry::DEFAULT_RETRIES);
+
+        let mut failures = 0;
+        for spec in specs {
+            if let Err(err) = self.vendor_one(spec, retries) {
+                eprintln!("Failed to vendor {spec}: {err:#}");
+                failures += 1;
+            }
+        }
+        if failures > 0 {
+            bail!("{failures} of {} packages failed", specs.len());
+        }
+
+        Ok(())
+    }
+
+    fn vendor_one(&self, spec: &str, retries: u32) -> Result<()> {
+        let (name, version) = parse_spec(spec)?;
+        ensure!(
+            semver::Version::parse(version).is_ok(),
+            "Only versions published on crates.io can be vendored, which `{version}` can't be"
+        );
+
+        let path = self.vendor_directory().join(name).join(version);
+        if let Ok(dependencies) = check_vendored(&path) {
+            println!("{spec} is vendored already, with {dependencies} dependencies");
+            return Ok(());
+        }
+
+        // Whatever an interrupted `vendor` left is of no use
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        let result = self.vendor_into(name, version, &path, retries);
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&path);
+            if let Some(package_path) = path.parent() {
+                // Only succeeds if the directory is empty
+                let _ = fs::remove_dir(package_path);
+            }
+        }
+        let dependencies = result?;
+
+        println!(
+            "Vendored {spec} with {dependencies} dependencies ({}) into {}",
+            human_size(directory_size(&path)?),
+            path.display()
+        );
+
+        Ok(())
+    }
+
+    /// Download `name@version` from crates.io and extract it into `destination`, returning the SHA-256 checksum it
+    /// was verified against, if crates.io gave one
+    pub(crate) fn download_crate(
+        &self,
+        name: &str,
+        version: &str,
+        destination: &Path,
+        retries: u32,
+    ) -> Result<Option<String>> {
+        let published = RetryPolicy::new(retries)
+            .run(&format!("look up {name} on crates.io"), || {
+                self.crates_io().map_err(Failure::Permanent)?.versions(name)
+            })?
+            .into_iter()
+            .find(|published| published.num == version)
+            .with_context(|| format!("{name} has no version {version} on crates.io"))?;
+
+        let parent = destination.parent().unwrap_or(destination);
+        fs::create_dir_all(parent)?;
+        let archive = parent.join(format!("{name}-{version}.crate"));
+        let url = format!("{DOWNLOAD_URL}/{name}/{name}-{version}.crate");
+        RetryPolicy::new(retries).run(&format!("download {name}@{version}"), || {
+            download::download(&url, &archive)
+        })?;
+        if let Some(checksum) = &published.checksum {
+            let actual = sha256_file(&archive)?;
+            ensure!(
+                actual.eq_ignore_ascii_case(checksum),
+                "Checksum mismatch for {url}: expected {checksum}, got {actual}"
+            );
+        }
+
+        // Crates hold a single directory named after them
+        extract(&archive, ArtifactKind::TarGz, name, parent)?;
+        fs::remove_file(&archive)?;
+        let _ = fs::remove_dir_all(destination);
+        fs::rename(parent.join(format!("{name}-{version}")), destination)
+            .with_context(|| format!("{url} doesn't hold {name}-{version}"))?;
+
+        Ok(published.checksum)
+    }
+
+    /// Download `name@version` and its dependencies into `path`, returning how many dependencies it has
+    fn vendor_into(&self, name: &str, version: &str, path: &Path, retries: u32) -> Result<usize> {
+        let source = path.join(SOURCE_DIRECTORY_NAME);
+        let checksum = self.download_crate(name, version, &source, retries)?;
+
+        let cargo = CargoInstaller {
+            cargo_path: self.config.cargo_path.clone(),
+            ..CargoInstaller::default()
+        }
+        .cargo()?;
+        let manifest = source.join("Cargo.toml");
+        if source.join("Cargo.lock").exists().not() {
+            eprintln!(
+                "Warning: {name}@{version} was published without a Cargo.lock, so its dependencies are resolved now"
+            );
+            run_cargo(
+                &cargo,
+                &[
+                    "generate-lockfile".as_ref(),
+                    "--manifest-path".as_ref(),
+                    manifest.as_os_str(),
+                ],
+            )?;
+        }
+
+        let dependencies = path.join(DEPENDENCIES_DIRECTORY_NAME);
+        let config = run_cargo(
+            &cargo,
+            &[
+                "vendor".as_ref(),
+                "--locked".as_ref(),
+                "--versioned-dirs".as_ref(),
+                "--manifest-path".as_ref(),
+                manifest.as_os_str(),
+                dependencies.as_os_str(),
+            ],
+        )?;
+        fs::write(path.join(CARGO_CONFIG_FILE_NAME), config)?;
+
+        let record = VendorRecord {
+            checksum,
+            vendored_at: timestamp(SystemTime::now()),
+        };
+        fs::write(
+            path.join(RECORD_FILE_NAME),
+            serde_json::to_string_pretty(&record)?,
+        )?;
+
+        check_vendored(path).map_err(|err| anyhow!("cargo vendor left things out: {err:#}"))
+    }
+
+    /// Print what the cache holds: how many answers of crates.io, and every vendored version
+    pub fn print_cache(&self) -> Result<()> {
+        println!(
+            "{} cached response(s) from crates.io",
+            self.crates_io_cache().entry_count()
+        );
+
+        let mut rows = Vec::new();
+        let packages = match fs::read_dir(self.vendor_directory()) {
+            Ok(packages) => packages,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                println!("No version is vendored");
+                return Ok(());
+            }
+            Err(err) => return Err(err.into()),
+        };
+        for maybe_package in packages {
+            let package = maybe_package?;
+            for maybe_version in fs::read_dir(package.path())? {
+                let version = maybe_version?;
+                let status = match check_vendored(&version.path()) {
+                    Ok(dependencies) => format!("complete, {dependencies} dependencies"),
+                    Err(err) => format!("incomplete: {err:#}"),
+                };
+                rows.push([
+                    package.file_name().to_string_lossy().into_owned(),
+                    version.file_name().to_string_lossy().into_owned(),
+                    human_size(directory_size(&version.path())?),
+                    status,
+                ]);
+            }
+        }
+        if rows.is_empty() {
+            println!("No version is vendored");
+            return Ok(());
+        }
+        rows.sort();
+
+        println!();
+        print_table(["PACKAGE", "VERSION", "SIZE", "STATUS"], &rows);
+
+        Ok(())
+    }
+
+    /// Remove the vendored sources of `spec`, as in `name@version`
+    pub fn remove_vendored(&self, spec: &str) -> Result<()> {
+        let (name, version) = parse_spec(spec)?;
+        let package_path = self.vendor_directory().join(name);
+        let path = package_path.join(version);
+        ensure!(path.exists(), "{spec} isn't vendored");
+
+        fs::remove_dir_all(&path)
+            .with_context(|| format!("Failed to remove {}", path.display()))?;
+        // Only succeeds if the directory is empty
+        let _ = fs::remove_dir(&package_path);
+        println!("Removed the vendored sources of {spec}");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::check_vendored;
+
+    #[test]
+    fn detects_incomplete_vendoring() {
+        let root = tempfile::tempdir().unwrap();
+        let path = root.path();
+        let error = |path| format!("{:#}", check_vendored(path).unwrap_err());
+
+        fs::create_dir_all(path.join("source")).unwrap();
+        fs::write(path.join("source/Cargo.toml"), "").unwrap();
+        fs::write(
+            path.join("source/Cargo.lock"),
+            r#"
+                version = 3
+
+                [[package]]
+                name = "tool"
+                version = "1.0.0"
+
+                [[package]]
+                name = "dependency"
+                version = "0.2.0"
+                source = "registry+https://github.com/rust-lang/crates.io-index"
+            "#,
+        )
+        .unwrap();
+        fs::write(path.join("cargo-config.toml"), "").unwrap();
+        assert!(error(path).contains("never finished"));
+
+        fs::write(path.join("vendored.json"), "{}").unwrap();
+        assert!(error(path).contains("dependency@0.2.0 is missing"));
+
+        fs::create_dir_all(path.join("vendor/dependency-0.2.0")).unwrap();
+        fs::write(
+            path.join("vendor/dependency-0.2.0/.cargo-checksum.json"),
+            "{}",
+        )
+        .unwrap();
+        assert_eq!(check_vendored(path).unwrap(), 1);
+
+        fs::remove_dir_all(path.join("source")).unwrap();
+        assert!(error(path).contains("sources are missing"));
+    }
+}
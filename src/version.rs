@@ -0,0 +1,85 @@
+use std::cmp::Ordering;
+use std::ops::Not;
+
+/// Compare two version strings, ordering them by semver when both parse as such.
+///
+/// Anything that isn't valid semver sorts after the valid versions, and lexicographically among itself.
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+    }
+}
+
+pub fn sort_versions(versions: &mut [String]) {
+    versions.sort_by(|a, b| compare_versions(a, b));
+}
+
+/// Whether `version` is a pre-release, as in `2.0.0-beta.1`. Variants of one, as in `2.0.0-beta.1+debug`, are too.
+pub fn is_prerelease(version: &str) -> bool {
+    semver::Version::parse(version).is_ok_and(|version| version.pre.is_empty().not())
+}
+
+/// The newest of `versions`, sorted from oldest to newest, skipping pre-releases unless `pre` is set or there's
+/// nothing else
+pub fn newest_version(versions: &[String], pre: bool) -> Option<&String> {
+    versions
+        .iter()
+        .rev()
+        .find(|version| pre || is_prerelease(version).not())
+        .or_else(|| versions.last())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::newest_version;
+    use super::sort_versions;
+
+    #[test]
+    fn sorts_by_semver() {
+        let mut versions: Vec<String> = [
+            "0.9.66",
+            "0.10.0",
+            "0.9.50",
+            "garbage",
+            "0.9.60-rc1",
+            "0.9.60",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        sort_versions(&mut versions);
+
+        assert_eq!(
+            versions,
+            [
+                "0.9.50",
+                "0.9.60-rc1",
+                "0.9.60",
+                "0.9.66",
+                "0.10.0",
+                "garbage"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_prereleases_unless_asked_to() {
+        let versions: Vec<String> = ["1.9.3", "2.0.0-beta.1", "2.0.0-beta.1+debug"]
+            .map(str::to_owned)
+            .into();
+        assert_eq!(newest_version(&versions, false).unwrap(), "1.9.3");
+        assert_eq!(
+            newest_version(&versions, true).unwrap(),
+            "2.0.0-beta.1+debug"
+        );
+        assert_eq!(
+            newest_version(&versions[1..], false).unwrap(),
+            "2.0.0-beta.1+debug"
+        );
+        assert_eq!(newest_version(&[], false), None);
+    }
+}
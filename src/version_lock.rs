@@ -0,0 +1,95 @@
+//! Locked versions, which `prune`, retention policies and eviction never remove, and `uninstall` only removes when
+//! forced to. Locks live in the registry's state, see [`State::locked`].
+//!
+//! Not to be confused with the file locks of [`lock`], which keep cargo-switch processes from stepping on each other.
+//!
+//! [`lock`]: crate::lock
+
+use std::collections::BTreeSet;
+use std::ops::Not;
+
+use anyhow::ensure;
+use anyhow::Result;
+
+use crate::spec::parse_spec;
+use crate::state::State;
+use crate::Switcher;
+
+impl Switcher {
+    /// The versions of `package` that are locked
+    pub(crate) fn locked_versions(&self, package: &str) -> Result<BTreeSet<String>> {
+        let mut state = State::load(&self.registry)?;
+
+        Ok(state.locked.remove(package).unwrap_or_default())
+    }
+
+    /// Lock `spec`, as in `tool@1.0.0`, so that no cleanup ever removes it
+    pub fn lock_version(&self, spec: &str) -> Result<()> {
+        let spec = &self.canonical_spec(spec)?;
+        let (package, version) = parse_spec(spec)?;
+        if self
+            .installed_versions(package)?
+            .iter()
+            .any(|installed| installed == version)
+            .not()
+        {
+            return Err(self.not_installed(package, Some(version)));
+        }
+
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        let newly_locked = state
+            .locked
+            .entry(package.to_owned())
+            .or_default()
+            .insert(version.to_owned());
+        state.save(&self.registry)?;
+
+        if newly_locked {
+            println!("Locked {spec}, which cleanups will leave alone");
+        } else {
+            println!("{spec} is already locked");
+        }
+
+        Ok(())
+    }
+
+    /// Unlock `spec`, as in `tool@1.0.0`, which cleanups may then remove again
+    pub fn unlock_version(&self, spec: &str) -> Result<()> {
+        let spec = &self.canonical_spec(spec)?;
+        let (package, version) = parse_spec(spec)?;
+
+        let _lock = self.lock_links()?;
+        let mut state = State::load(&self.registry)?;
+        let locked = state.locked.get_mut(package);
+        ensure!(
+            locked.is_some_and(|locked| locked.remove(version)),
+            "{spec} isn't locked"
+        );
+        if state.locked.get(package).is_some_and(BTreeSet::is_empty) {
+            state.locked.remove(package);
+        }
+        state.save(&self.registry)?;
+
+        println!("Unlocked {spec}");
+
+        Ok(())
+    }
+
+    /// Print every locked version, one per line
+    pub fn print_locked(&self) -> Result<()> {
+        let state = State::load(&self.registry)?;
+        if state.locked.is_empty() {
+            println!("No version is locked");
+            return Ok(());
+        }
+
+        for (package, versions) in &state.locked {
+            for version in versions {
+                println!("{package}@{version}");
+            }
+        }
+
+        Ok(())
+    }
+}
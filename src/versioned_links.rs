@@ -0,0 +1,236 @@
+//! Versioned links: on top of the links to the active version, links in `.cargo/bin` to the binaries of every
+//! installed version under suffixed names, as in `rg-13` and `rg-14` next to `rg`, so that any of them can be run at
+//! once.
+//!
+//! Packages get them through `versioned-links = true` in their configuration, or once switched to with
+//! `--keep-suffixed`, after which they're kept up to date on every switch and uninstall. They're recorded in the
+//! registry's state, see [`State::versioned_links`], which is how they're told apart from the links to the active
+//! version and how nothing else in `.cargo/bin` ever gets overwritten by them. So is `--keep-suffixed`, see
+//! [`State::keep_suffixed`], which lasts until the package is uninstalled.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::ops::Not;
+use std::os::unix;
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::state::LinkOwner;
+use crate::state::State;
+use crate::variant::split_variant;
+use crate::wrapper;
+use crate::Switcher;
+
+/// What versioned links are suffixed with
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LinkSuffix {
+    /// The major version, as in `rg-14`, or the minor one for `0.x` versions, as in `sqlx-0.7`. The newest version
+    /// sharing it gets the link.
+    #[default]
+    Major,
+    /// The whole version, as in `rg-14.1.0`
+    Version,
+}
+
+/// What the versioned links to the binaries of `version` are suffixed with, if it gets any. Only release builds
+/// get major suffixes, as `rg-14` had better not be a debug build.
+pub fn link_suffix(version: &str, suffix: LinkSuffix) -> Option<String> {
+    if suffix == LinkSuffix::Version {
+        return Some(version.to_owned());
+    }
+
+    let (release, variant) = split_variant(version);
+    if variant.is_some() {
+        return None;
+    }
+
+    Some(match semver::Version::parse(release) {
+        Ok(parsed) if parsed.major > 0 => parsed.major.to_string(),
+        Ok(parsed) => format!("0.{}", parsed.minor),
+        // Versions that aren't semver have no major to speak of
+        Err(_) => version.to_owned(),
+    })
+}
+
+impl Switcher {
+    /// The names of every versioned link in `.cargo/bin`
+    pub(crate) fn versioned_link_names(&self) -> Result<BTreeSet<OsString>> {
+        let state = State::load(&self.registry)?;
+
+        Ok(state
+            .versioned_links
+            .into_keys()
+            .map(OsString::from)
+            .collect())
+    }
+
+    /// What the versioned links of `package` are suffixed with, if it gets any
+    fn versioned_links_suffix(&self, package: &str, state: &State) -> Option<LinkSuffix> {
+        // Registries from before the opt-in was recorded only tell by the links it made
+        let kept = self.keep_suffixed
+            || state.keep_suffixed.contains(package)
+            || state
+                .versioned_links
+                .values()
+                .any(|owner| owner.package == package);
+
+        self.config.versioned_links(package).or_else(|| {
+            kept.then(|| {
+                self.config
+                    .package(package)
+                    .map(|config| config.versioned_links_suffix)
+                    .unwrap_or_default()
+            })
+        })
+    }
+
+    /// Bring the versioned links of `package` up to date, see [`sync_versioned_links_unlocked`]
+    ///
+    /// [`sync_versioned_links_unlocked`]: Switcher::sync_versioned_links_unlocked
+    pub fn sync_versioned_links(&self, package: &str) -> Result<()> {
+        let _lock = self.lock_links()?;
+        self.sync_versioned_links_unlocked(package)
+    }
+
+    /// Link the binaries of every installed version of `package` under suffixed names, if it gets versioned links,
+    /// and remove the ones of versions that are gone, on behalf of a caller already holding the links lock
+    pub(crate) fn sync_versioned_links_unlocked(&self, package: &str) -> Result<()> {
+        // Like any other link, they're each user's own
+        if self.system {
+            return Ok(());
+        }
+        let mut state = State::load(&self.registry)?;
+        let installed = if self.package_exists(package) {
+            self.installed_versions(package)?
+        } else {
+            Vec::new()
+        };
+
+        let mut changed = match (installed.is_empty(), self.keep_suffixed) {
+            (true, _) => state.keep_suffixed.remove(package),
+            (false, true) => state.keep_suffixed.insert(package.to_owned()),
+            (false, false) => false,
+        };
+
+        let mut wanted = BTreeMap::new();
+        if let Some(suffix) = self.versioned_links_suffix(package, &state) {
+            for version in &installed {
+                let Some(suffix) = link_suffix(version, suffix) else {
+                    continue;
+                };
+                for binary in self.version_binaries(package, version)? {
+                    let Some(name) = binary.file_name().and_then(OsStr::to_str) else {
+                        continue;
+                    };
+                    // Versions go from oldest to newest, so the newest of those sharing a suffix wins
+                    wanted.insert(format!("{name}-{suffix}"), (version.clone(), binary));
+                }
+            }
+        }
+
+        // Whatever points to a version it shouldn't anymore goes, and whatever else took over a link isn't ours
+        let owned: Vec<_> = state
+            .versioned_links
+            .iter()
+            .filter(|(_, owner)| owner.package == package)
+            .map(|(name, owner)| (name.clone(), owner.version.clone()))
+            .collect();
+        for (name, version) in owned {
+            let ours =
+                self.link_owner(OsStr::new(&name)) == Some((package.to_owned(), version.clone()));
+            let still_wanted = wanted
+                .get(&name)
+                .is_some_and(|(wanted_version, _)| *wanted_version == version);
+            if ours && still_wanted {
+                continue;
+            }
+
+            if ours {
+                let link = self.cargo_bin.join(&name);
+                self.remove_link(&link)?;
+                println!("Removed {}", link.display());
+            }
+            state.versioned_links.remove(&name);
+            changed = true;
+        }
+
+        for (name, (version, binary)) in wanted {
+            let link = self.cargo_bin.join(&name);
+            let link_to = self.link_style.link_to(&self.cargo_bin, &binary);
+            let env = self.wrapper_env(package, &version);
+
+            let recorded = match state.versioned_links.get(&name) {
+                Some(owner) if owner.package != package => {
+                    eprintln!(
+                        "Warning: not linking {} to {}, which is a versioned link of {}",
+                        binary.display(),
+                        link.display(),
+                        owner.package
+                    );
+                    continue;
+                }
+                Some(_) => true,
+                None if link.symlink_metadata().is_ok() => {
+                    eprintln!(
+                        "Warning: not linking {} to {}, which is already taken",
+                        binary.display(),
+                        link.display()
+                    );
+                    continue;
+                }
+                None => false,
+            };
+
+            match &env {
+                Some(env) => wrapper::write_wrapper(&link, &link_to, env)?,
+                None if fs::read_link(&link).is_ok_and(|target| target == link_to) => {}
+                None => {
+                    self.remove_link(&link)?;
+                    unix::fs::symlink(&link_to, &link)?;
+                }
+            }
+            if recorded.not() {
+                println!("Linked {} to {}", binary.display(), link.display());
+                state.versioned_links.insert(
+                    name,
+                    LinkOwner {
+                        package: package.to_owned(),
+                        version,
+                    },
+                );
+                changed = true;
+            }
+        }
+
+        if changed {
+            state.save(&self.registry)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::link_suffix;
+    use super::LinkSuffix;
+
+    #[test]
+    fn suffixes_links() {
+        let major = |version| link_suffix(version, LinkSuffix::Major);
+        assert_eq!(major("14.1.0").as_deref(), Some("14"));
+        assert_eq!(major("0.7.2").as_deref(), Some("0.7"));
+        assert_eq!(major("nightly").as_deref(), Some("nightly"));
+        assert_eq!(major("14.1.0+debug"), None);
+
+        assert_eq!(
+            link_suffix("14.1.0+debug", LinkSuffix::Version).as_deref(),
+            Some("14.1.0+debug")
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! `why`: explaining which version of a package gets used and how it was decided, step by step, through the very
+//! resolution `run` and `current` go through. Whatever `.cargo/bin` and `$PATH` actually lead to is checked against
+//! it, so that a shadowed binary or a stale link doesn't go unnoticed.
+
+use std::env;
+use std::ffi::OsStr;
+use std::ops::Not;
+use std::path::PathBuf;
+
+use anyhow::bail;
+use anyhow::Result;
+
+use crate::links::LinkState;
+use crate::shadow::find_shadowing_executable;
+use crate::Switcher;
+
+impl Switcher {
+    /// The package `name` stands for: itself if it's an installed package, or else the package providing the binary
+    /// by that name, along with that binary
+    fn package_or_binary(&self, name: &str) -> Result<(String, Option<String>)> {
+        let package = self.canonical_name(name)?;
+        if self.installed_packages()?.contains(&package) {
+            return Ok((package, None));
+        }
+
+        if let Some((package, _)) = self.link_owner(OsStr::new(name)) {
+            return Ok((package, Some(name.to_owned())));
+        }
+        for package in self.installed_packages()? {
+            for version in self.installed_versions(&package)? {
+                let provides = self
+                    .version_binaries(&package, &version)?
+                    .iter()
+                    .any(|binary| binary.file_name() == Some(OsStr::new(name)));
+                if provides {
+                    return Ok((package, Some(name.to_owned())));
+                }
+            }
+        }
+
+        bail!("{name} is neither an installed package nor a binary one of them provides")
+    }
+
+    /// Explain which version of `name`, a package or one of the binaries it provides, gets used in the current
+    /// directory and environment, and whether running it by name really gets to that version
+    pub fn why(&self, name: &str) -> Result<()> {
+        let (package, binary) = self.package_or_binary(name)?;
+        let (resolution, steps) = self.trace_version(&package, |name| env::var_os(name))?;
+
+        println!("Resolving {package} in {}:", env::current_dir()?.display());
+        for (index, step) in steps.iter().enumerate() {
+            let marker = if step.matched { "  <- used" } else { "" };
+            println!("  {}. {}: {}{marker}", index + 1, step.source, step.finding);
+        }
+        println!(
+            "Resolved to {package}@{} ({})",
+            resolution.version, resolution.rule
+        );
+
+        let binaries: Vec<PathBuf> = self
+            .version_binaries(&package, &resolution.version)?
+            .into_iter()
+            .filter(|path| {
+                binary
+                    .as_deref()
+                    .is_none_or(|binary| path.file_name() == Some(OsStr::new(binary)))
+            })
+            .collect();
+        if binaries.is_empty() {
+            match &binary {
+                Some(binary) => println!(
+                    "  {package}@{} doesn't provide {binary}",
+                    resolution.version
+                ),
+                None => println!("  {package}@{} provides no binaries", resolution.version),
+            }
+        }
+
+        let path = env::var_os("PATH").unwrap_or_default();
+        let mut mismatches = 0;
+        for binary in &binaries {
+            let Some(name) = binary.file_name() else {
+                continue;
+            };
+            let display_name = name.to_string_lossy();
+            println!("  {display_name}: {}", binary.display());
+
+            let link = self.link_path(name);
+            match self.link_state(binary, true) {
+                LinkState::Linked => println!("    {} links to it", link.display()),
+                LinkState::Broken => {
+                    println!(
+                        "    {} links to it, but it's missing from the registry",
+                        link.display()
+                    );
+                    mismatches += 1;
+                }
+                LinkState::Missing => {
+                    println!(
+                        "    {} doesn't exist, so running {display_name} by name doesn't get to it",
+                        link.display()
+                    );
+                    mismatches += 1;
+                }
+                LinkState::Overridden { by } => {
+                    match self.link_owner(name) {
+                        Some((owner, version)) => println!(
+                            "    but {} links to {owner}@{version} instead. Run `cargo switch {package}@{}` to \
+                             link to it",
+                            link.display(),
+                            resolution.version
+                        ),
+                        None => println!(
+                            "    but {} is {}, which isn't managed by cargo-switch",
+                            link.display(),
+                            by.display()
+                        ),
+                    }
+                    mismatches += 1;
+                }
+                LinkState::Inactive => {}
+            }
+
+            if let Some(shadowed_by) = find_shadowing_executable(name, &self.cargo_bin, &path) {
+                println!(
+                    "    but {} comes first in $PATH, so that's what running {display_name} by name runs",
+                    shadowed_by.display()
+                );
+                mismatches += 1;
+            }
+        }
+
+        let running = match &binary {
+            Some(binary) => binary.clone(),
+            None => format!("the binaries of {package}"),
+        };
+        if mismatches > 0 {
+            println!(
+                "Running {running} by name doesn't get to {package}@{}, but `cargo switch run {package}` does",
+                resolution.version
+            );
+        } else if binaries.is_empty().not() {
+            println!(
+                "Running {running} by name gets to {package}@{}",
+                resolution.version
+            );
+        }
+
+        Ok(())
+    }
+}
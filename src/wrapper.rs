@@ -0,0 +1,214 @@
+//! Wrapper scripts, generated in `.cargo/bin` in place of symlinks for packages that have environment variables
+//! configured. They export the variables and then `exec` the binary in the registry, so that exit codes and signals
+//! are the binary's own.
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::ops::Not;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::ensure;
+use anyhow::Context;
+use anyhow::Result;
+
+use crate::Switcher;
+
+/// The first line after the shebang of every wrapper, telling them apart from executables we didn't generate
+const WRAPPER_MARKER: &str =
+    "# Generated by cargo-switch, regenerated on every switch. Do not edit.";
+/// Prefix of the line recording the binary a wrapper runs
+const TARGET_PREFIX: &str = "# cargo-switch-target: ";
+/// Wrappers are tiny, so anything bigger than this isn't worth reading
+const MAX_WRAPPER_SIZE: u64 = 64 * 1024;
+
+impl Switcher {
+    /// The variables the wrappers for `package@version` should set, if the package's binaries need wrappers at all
+    pub fn wrapper_env(&self, package: &str, version: &str) -> Option<BTreeMap<String, String>> {
+        let env = self.config.package_env(package)?;
+
+        Some(
+            env.iter()
+                .map(|(name, value)| {
+                    let value = value
+                        .replace("{package}", package)
+                        .replace("{version}", version);
+                    (name.clone(), value)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Quote `value` for a POSIX shell. Shells don't care about encodings, so neither does this.
+fn shell_quote(value: &[u8]) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &byte in value {
+        if byte == b'\'' {
+            quoted.extend_from_slice(br"'\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+
+    quoted
+}
+
+fn is_variable_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+}
+
+/// The contents of a wrapper that runs `target` with `env` set. A relative `target` is relative to the directory the
+/// wrapper is in.
+pub fn render_wrapper(target: &Path, env: &BTreeMap<String, String>) -> Result<Vec<u8>> {
+    let target_bytes = target.as_os_str().as_bytes();
+    ensure!(
+        target_bytes.contains(&b'\n').not(),
+        "Can't generate a wrapper for a path with a newline in it"
+    );
+
+    let mut script = format!("#!/bin/sh\n{WRAPPER_MARKER}\n{TARGET_PREFIX}").into_bytes();
+    script.extend_from_slice(target_bytes);
+    script.push(b'\n');
+    for (name, value) in env {
+        ensure!(
+            is_variable_name(name),
+            "`{name}` is not a valid environment variable name"
+        );
+        script.extend_from_slice(format!("export {name}=").as_bytes());
+        script.extend_from_slice(&shell_quote(value.as_bytes()));
+        script.push(b'\n');
+    }
+    script.extend_from_slice(b"exec ");
+    if target.is_relative() {
+        script.extend_from_slice(br#""${0%/*}"/"#);
+    }
+    script.extend_from_slice(&shell_quote(target_bytes));
+    script.extend_from_slice(b" \"$@\"\n");
+
+    Ok(script)
+}
+
+/// The binary run by the wrapper at `path`, if it's a wrapper we generated
+pub fn wrapper_target(path: &Path) -> Option<PathBuf> {
+    let metadata = fs::symlink_metadata(path).ok()?;
+    if metadata.is_file().not() || metadata.len() > MAX_WRAPPER_SIZE {
+        return None;
+    }
+
+    let contents = fs::read(path).ok()?;
+    let mut lines = contents.split(|&byte| byte == b'\n');
+    if lines.next()? != b"#!/bin/sh" || lines.next()? != WRAPPER_MARKER.as_bytes() {
+        return None;
+    }
+
+    let target = lines.next()?.strip_prefix(TARGET_PREFIX.as_bytes())?;
+    Some(PathBuf::from(OsStr::from_bytes(target)))
+}
+
+/// A hidden sibling of `path` to write to before moving it into place, keeping its file name as it is even if it
+/// isn't valid UTF-8
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let mut file_name = OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_default());
+    file_name.push(".cargo-switch-tmp");
+
+    path.with_file_name(file_name)
+}
+
+/// Write a wrapper at `path` that runs `target` with `env` set, replacing whatever was there
+pub fn write_wrapper(path: &Path, target: &Path, env: &BTreeMap<String, String>) -> Result<()> {
+    let script = render_wrapper(target, env)?;
+
+    // Write somewhere else first so that the binary never disappears from `.cargo/bin` mid-switch
+    let temporary = temporary_path(path);
+    fs::write(&temporary, script)
+        .with_context(|| format!("Failed to write {}", temporary.display()))?;
+    fs::set_permissions(&temporary, fs::Permissions::from_mode(0o755))?;
+    fs::rename(&temporary, path)
+        .with_context(|| format!("Failed to move the wrapper to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::process::Command;
+
+    use super::wrapper_target;
+    use super::write_wrapper;
+
+    #[test]
+    fn wrappers_set_env_and_keep_exit_codes() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join("it's a tool");
+        fs::write(&target, "#!/bin/sh\necho \"$TOOL_CONFIG_DIR|$1\"\nexit 3\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let env = BTreeMap::from([("TOOL_CONFIG_DIR".to_owned(), "/etc/tool's $HOME".to_owned())]);
+        let wrapper = root.path().join("tool");
+        write_wrapper(&wrapper, &target, &env).unwrap();
+
+        assert_eq!(wrapper_target(&wrapper), Some(target.clone()));
+        assert_eq!(wrapper_target(&target), None);
+
+        let output = Command::new(&wrapper).arg("a b").output().unwrap();
+        assert_eq!(output.status.code(), Some(3));
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "/etc/tool's $HOME|a b\n"
+        );
+    }
+
+    #[test]
+    fn wrappers_run_relative_targets() {
+        let root = tempfile::tempdir().unwrap();
+        let registry = root.path().join("registry");
+        fs::create_dir_all(&registry).unwrap();
+        let target = registry.join("tool");
+        fs::write(&target, "#!/bin/sh\necho relative\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let bin = root.path().join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let wrapper = bin.join("tool");
+        write_wrapper(&wrapper, "../registry/tool".as_ref(), &BTreeMap::new()).unwrap();
+
+        assert_eq!(wrapper_target(&wrapper), Some("../registry/tool".into()));
+        let output = Command::new(&wrapper).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "relative\n");
+    }
+
+    #[test]
+    fn wrappers_run_targets_that_are_not_utf8() {
+        let root = tempfile::tempdir().unwrap();
+        let target = root.path().join(OsStr::from_bytes(b"t\xe9l\xe9"));
+        fs::write(&target, "#!/bin/sh\necho latin-1\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let wrapper = root.path().join("tool");
+        write_wrapper(&wrapper, &target, &BTreeMap::new()).unwrap();
+
+        assert_eq!(wrapper_target(&wrapper), Some(target));
+        let output = Command::new(&wrapper).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "latin-1\n");
+    }
+
+    #[test]
+    fn rejects_bad_variable_names() {
+        let env = BTreeMap::from([("NOT-VALID".to_owned(), String::new())]);
+        assert!(super::render_wrapper("/bin/true".as_ref(), &env).is_err());
+    }
+}
@@ -0,0 +1,3720 @@
+//! Runs cargo-switch against a registry living in a temporary directory, installing a tiny crate from a local path
+//! so that nothing needs the network.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::env;
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::ops::Not;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::process::Stdio;
+use std::rc::Rc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use anyhow::anyhow;
+
+use cargo_switch::config::Config;
+use cargo_switch::config::PackageConfig;
+use cargo_switch::copy::CopyOptions;
+use cargo_switch::drift::Drift;
+use cargo_switch::events::Event;
+use cargo_switch::events::EventSink;
+use cargo_switch::install::InstallOptions;
+use cargo_switch::installer::build_env;
+use cargo_switch::installer::BuildFlags;
+use cargo_switch::installer::BuildOptions;
+use cargo_switch::installer::InstallOutcome;
+use cargo_switch::installer::Installer;
+use cargo_switch::links::ActiveState;
+use cargo_switch::metadata::LockfileStatus;
+use cargo_switch::metadata::Source;
+use cargo_switch::metadata::VersionMetadata;
+use cargo_switch::migrate;
+use cargo_switch::msrv::RustcVersion;
+use cargo_switch::operation_log::Operation;
+use cargo_switch::retry::Failure;
+use cargo_switch::run::Missing;
+use cargo_switch::state::State;
+use cargo_switch::summary::FailureMode;
+use cargo_switch::Switcher;
+use cargo_switch::SwitcherBuilder;
+use tempfile::TempDir;
+
+struct Sandbox {
+    root: TempDir,
+    switcher: Switcher,
+}
+
+impl Sandbox {
+    fn new() -> Self {
+        Self::with_builder(|builder| builder)
+    }
+
+    /// A sandbox whose switcher was tweaked through `customize`
+    fn with_builder(customize: impl FnOnce(SwitcherBuilder) -> SwitcherBuilder) -> Self {
+        let root = tempfile::tempdir().unwrap();
+        let cargo_bin = root.path().join(".cargo").join("bin");
+        fs::create_dir_all(&cargo_bin).unwrap();
+
+        let builder = Switcher::builder()
+            .non_interactive(true)
+            .cargo_bin(&cargo_bin)
+            .registry(cargo_bin.join("cargo-switch-registry"))
+            .config(Config::default());
+        let switcher = customize(builder).build().unwrap();
+
+        Self { root, switcher }
+    }
+
+    fn cargo_bin(&self) -> PathBuf {
+        self.root.path().join(".cargo").join("bin")
+    }
+
+    /// A crate called `hello` whose binary prints `version`
+    fn write_crate(&self, version: &str) -> PathBuf {
+        let path = self.root.path().join(format!("hello-{version}"));
+        fs::create_dir_all(path.join("src")).unwrap();
+        fs::write(
+            path.join("Cargo.toml"),
+            format!(
+                "[package]\nname = \"hello\"\nversion = \"{version}\"\nedition = \"2021\"\n\n[features]\nloud = []\n\n[workspace]\n"
+            ),
+        )
+        .unwrap();
+        fs::write(
+            path.join("src").join("main.rs"),
+            format!("fn main() {{ println!(\"hello {version}\"); }}\n"),
+        )
+        .unwrap();
+
+        path
+    }
+
+    fn install(&self, version: &str) {
+        let options = InstallOptions {
+            retries: Some(0),
+            path: Some(self.write_crate(version)),
+            ..InstallOptions::default()
+        };
+        let report = self
+            .switcher
+            .install_package(&format!("hello@{version}"), &options)
+            .unwrap();
+        assert!(report.switch_error.is_none());
+    }
+
+    /// Put an executable script at `path` that prints `message`
+    fn write_script(&self, path: &Path, message: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, format!("#!/bin/sh\necho {message}\n")).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Run `hello` from `.cargo/bin`, as a user would
+    fn run_hello(&self) -> String {
+        let output = Command::new(self.cargo_bin().join("hello"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    }
+}
+
+/// How the next build of a [`FakeInstaller`] goes
+#[derive(Debug, Clone, Copy)]
+enum FakeBuild {
+    Succeed,
+    /// Fail as cargo does when the network is down
+    Transient,
+    /// Fail as cargo does on compile errors, after writing some of the binaries
+    Permanent,
+}
+
+/// Stands in for cargo: "builds" a package by writing a script for each of its binaries, printing the spec and
+/// profile it was built for
+#[derive(Debug, Default, Clone)]
+struct FakeInstaller {
+    /// The binaries of every package, the ones not listed having a single binary named after them
+    binaries: BTreeMap<String, Vec<String>>,
+    /// How the next builds go, succeeding once they run out
+    outcomes: Rc<RefCell<VecDeque<FakeBuild>>>,
+    /// Every spec built so far
+    builds: Rc<RefCell<Vec<String>>>,
+    /// Where every build went
+    roots: Rc<RefCell<Vec<PathBuf>>>,
+    /// What `rustc --version` says, if anything
+    rustc: Rc<RefCell<Option<String>>>,
+    /// Whether builds look like they fetch from crates.io, so that crates.io is asked about them first
+    fetches_from_crates_io: bool,
+}
+
+impl FakeInstaller {
+    fn with_binaries(mut self, package: &str, binaries: &[&str]) -> Self {
+        self.binaries.insert(
+            package.to_owned(),
+            binaries.iter().map(|binary| binary.to_string()).collect(),
+        );
+        self
+    }
+
+    /// Make the next builds go as `outcomes` say
+    fn then(&self, outcomes: &[FakeBuild]) {
+        self.outcomes.borrow_mut().extend(outcomes);
+    }
+}
+
+impl Installer for FakeInstaller {
+    fn rustc_version(&self, _toolchain: Option<&str>) -> Option<String> {
+        self.rustc.borrow().clone()
+    }
+
+    fn fetches_from_crates_io(&self) -> bool {
+        self.fetches_from_crates_io
+    }
+
+    fn install(
+        &self,
+        spec: &str,
+        root: &Path,
+        options: &BuildOptions,
+    ) -> Result<InstallOutcome, Failure> {
+        self.builds.borrow_mut().push(spec.to_owned());
+        self.roots.borrow_mut().push(root.to_owned());
+        options.output(&format!("  Installing {spec}"));
+        let outcome = self
+            .outcomes
+            .borrow_mut()
+            .pop_front()
+            .unwrap_or(FakeBuild::Succeed);
+        if let FakeBuild::Transient = outcome {
+            return Err(Failure::Transient(anyhow!("Couldn't resolve host")));
+        }
+
+        let (package, _) = spec.split_once('@').unwrap();
+        let binaries = self
+            .binaries
+            .get(package)
+            .cloned()
+            .unwrap_or_else(|| vec![package.to_owned()]);
+        for binary in &binaries {
+            let path = root.join("bin").join(binary);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(
+                &path,
+                format!("#!/bin/sh\necho {spec} {}\n", options.profile),
+            )
+            .unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+            if let FakeBuild::Permanent = outcome {
+                return Err(Failure::Permanent(anyhow!("could not compile `{package}`")));
+            }
+        }
+
+        // Cargo records which commit it built git installs from
+        if let (Some(url), Some(rev)) = (options.git, options.rev) {
+            fs::write(
+                root.join(".crates2.json"),
+                format!(
+                    r#"{{"installs":{{"{package} 0.1.0 (git+{url}?rev={rev}#{rev})":{{"bins":{binaries:?}}}}}}}"#
+                ),
+            )
+            .unwrap();
+        }
+
+        // Builds reproducing an earlier one get what they were given, while others resolve a dependency that
+        // releases a new version for every build
+        let lockfile = match options.locked {
+            Some(sources) => fs::read_to_string(sources.join("Cargo.lock")).unwrap(),
+            None => format!(
+                "[[package]]\nname = \"{package}\"\nversion = \"{}\"\n\n\
+                 [[package]]\nname = \"helper\"\nversion = \"1.0.{}\"\n\
+                 source = \"registry+https://github.com/rust-lang/crates.io-index\"\n",
+                spec.split_once('@').unwrap().1,
+                self.builds.borrow().len()
+            ),
+        };
+
+        Ok(InstallOutcome {
+            build_duration: Duration::from_millis(1),
+            lockfile: Ok(lockfile),
+            crate_checksum: None,
+        })
+    }
+}
+
+/// Serve a sparse index listing `crates`, by name and versions, answering 404 for everything else. Returns the
+/// index's URL.
+fn serve_index(crates: &[(&str, &[&str])]) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/", listener.local_addr().unwrap());
+    let files: BTreeMap<_, _> = crates
+        .iter()
+        .map(|(name, versions)| {
+            let lines: String = versions
+                .iter()
+                .map(|version| {
+                    format!("{{\"name\":\"{name}\",\"vers\":\"{version}\",\"cksum\":\"abc\"}}\n")
+                })
+                .collect();
+            (name.to_ascii_lowercase(), lines)
+        })
+        .collect();
+
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut request_line = String::new();
+            let mut reader = BufReader::new(&stream);
+            reader.read_line(&mut request_line).unwrap();
+            for line in reader.lines() {
+                if line.unwrap().is_empty() {
+                    break;
+                }
+            }
+
+            let path = request_line.split(' ').nth(1).unwrap_or_default();
+            let name = path.rsplit('/').next().unwrap_or_default();
+            let response = match files.get(name) {
+                Some(body) => format!(
+                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{body}",
+                    body.len()
+                ),
+                None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n".to_owned(),
+            };
+            let _ = stream.write_all(response.as_bytes());
+        }
+    });
+
+    url
+}
+
+/// Run the binary called `name` from `.cargo/bin`, as a user would
+fn run_binary(cargo_bin: &Path, name: &str) -> String {
+    let output = Command::new(cargo_bin.join(name)).output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// cargo-switch itself, to run `args` against the registry of `sandbox` as `cargo switch` would, for the commands
+/// that replace the process or spawn others
+fn cargo_switch_in(sandbox: &Sandbox, args: &[&str]) -> Command {
+    let path = env::join_paths(
+        [sandbox.cargo_bin()]
+            .into_iter()
+            .chain(env::split_paths(&env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-switch"));
+    command
+        .args(args)
+        .env("PATH", path)
+        .env("HOME", sandbox.root.path())
+        .env("XDG_CONFIG_HOME", sandbox.root.path().join(".config"))
+        .env_remove("CARGO_HOME")
+        .env_remove("CARGO_INSTALL_ROOT")
+        .env_remove("CARGO_SWITCH_CONFIG")
+        .env_remove("CARGO_SWITCH_SHELL")
+        .stdin(Stdio::null());
+    command
+}
+
+fn is_link_to(link: &Path, target: &Path) -> bool {
+    fs::read_link(link).is_ok_and(|link_target| link_target == target)
+}
+
+#[test]
+fn install_switch_list_and_uninstall() {
+    let sandbox = Sandbox::new();
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+
+    sandbox.install("0.1.0");
+    sandbox.install("0.2.0");
+    assert!(is_link_to(
+        &sandbox.cargo_bin().join("hello"),
+        &registry.join("hello/0.2.0/bin/hello")
+    ));
+    assert_eq!(sandbox.run_hello(), "hello 0.2.0\n");
+
+    sandbox.switcher.switch_package("hello@0.1.0").unwrap();
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+
+    let listings = sandbox.switcher.listing(false).unwrap();
+    assert_eq!(listings.len(), 1);
+    assert_eq!(listings[0].name, "hello");
+    assert_eq!(
+        listings[0].active_state,
+        ActiveState::Active {
+            version: "0.1.0".to_owned()
+        }
+    );
+    let versions: Vec<_> = listings[0]
+        .versions
+        .iter()
+        .map(|version| version.version.as_str())
+        .collect();
+    assert_eq!(versions, ["0.1.0", "0.2.0"]);
+
+    let binaries = sandbox.switcher.binary_listing(&listings);
+    assert_eq!(binaries.len(), 1);
+    assert_eq!(binaries[0].name, "hello");
+    assert_eq!(
+        binaries[0]
+            .active
+            .as_ref()
+            .map(ToString::to_string)
+            .as_deref(),
+        Some("hello@0.1.0")
+    );
+    assert_eq!(binaries[0].alternatives.len(), 1);
+    assert!(binaries[0].is_contested().not());
+
+    // Uninstalling the active version takes its links along
+    sandbox.switcher.uninstall("hello@0.1.0", false).unwrap();
+    assert!(sandbox
+        .cargo_bin()
+        .join("hello")
+        .symlink_metadata()
+        .is_err());
+    assert_eq!(
+        sandbox.switcher.active_state("hello").unwrap(),
+        ActiveState::Inactive
+    );
+
+    sandbox.switcher.uninstall("hello@0.2.0", false).unwrap();
+    assert!(registry.join("hello").exists().not());
+    assert!(sandbox.switcher.listing(false).unwrap().is_empty());
+}
+
+#[test]
+fn copies_binaries_out_of_the_registry() {
+    let sandbox = Sandbox::new();
+    sandbox.install("0.1.0");
+
+    let destination = sandbox.root.path().join("dist").join("bin");
+    let options = CopyOptions::default();
+    sandbox
+        .switcher
+        .copy_to("hello@0.1.0", &destination, &options)
+        .unwrap();
+
+    let copy = destination.join("hello");
+    assert!(copy.symlink_metadata().unwrap().file_type().is_file());
+    let output = Command::new(&copy).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hello 0.1.0\n");
+
+    // Existing files are left alone unless forced
+    assert!(sandbox
+        .switcher
+        .copy_to("hello@0.1.0", &destination, &options)
+        .is_err());
+    let force = CopyOptions {
+        force: true,
+        ..CopyOptions::default()
+    };
+    sandbox
+        .switcher
+        .copy_to("hello@0.1.0", &destination, &force)
+        .unwrap();
+
+    let missing_target = CopyOptions {
+        target: Some("aarch64-unknown-linux-musl".to_owned()),
+        ..CopyOptions::default()
+    };
+    assert!(sandbox
+        .switcher
+        .copy_to("hello@0.1.0", &destination, &missing_target)
+        .is_err());
+    assert!(sandbox
+        .switcher
+        .copy_to("hello@9.9.9", &destination, &force)
+        .is_err());
+}
+
+#[test]
+fn backs_up_and_restores_the_registry() {
+    let sandbox = Sandbox::new();
+    sandbox.install("0.1.0");
+    sandbox.install("0.2.0");
+    sandbox.switcher.switch_package("hello@0.1.0").unwrap();
+
+    let archive = sandbox.root.path().join("backup.tar.zst");
+    sandbox.switcher.backup(&archive).unwrap();
+
+    // The registry isn't empty, so nothing happens without --force
+    assert!(sandbox.switcher.restore(&archive, false).is_err());
+
+    sandbox.switcher.uninstall("hello@0.1.0", false).unwrap();
+    sandbox.switcher.uninstall("hello@0.2.0", false).unwrap();
+    sandbox.switcher.restore(&archive, false).unwrap();
+
+    let versions: Vec<_> = sandbox.switcher.listing(false).unwrap()[0]
+        .versions
+        .iter()
+        .map(|version| version.version.clone())
+        .collect();
+    assert_eq!(versions, ["0.1.0", "0.2.0"]);
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+}
+
+#[test]
+fn leaves_toolchain_binaries_alone() {
+    for allow in [false, true] {
+        let sandbox = Sandbox::with_builder(|builder| builder.allow_overwrite_toolchain(allow));
+        let proxy = sandbox.cargo_bin().join("rustfmt");
+        sandbox.write_script(&proxy, "rustup proxy");
+
+        let bin = sandbox
+            .cargo_bin()
+            .join("cargo-switch-registry/fake-rustfmt/1.0.0/bin");
+        sandbox.write_script(&bin.join("rustfmt"), "fake rustfmt");
+        sandbox.write_script(&bin.join("fake-helper"), "fake helper");
+
+        let result = sandbox.switcher.switch_package("fake-rustfmt@1.0.0");
+        let output = Command::new(&proxy).output().unwrap();
+        if allow {
+            result.unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "fake rustfmt\n");
+        } else {
+            let err = result.unwrap_err().to_string();
+            assert!(err.contains("--allow-overwrite-toolchain"), "{err}");
+            assert_eq!(String::from_utf8_lossy(&output.stdout), "rustup proxy\n");
+            // Nothing at all was linked
+            assert!(sandbox
+                .cargo_bin()
+                .join("fake-helper")
+                .symlink_metadata()
+                .is_err());
+        }
+    }
+}
+
+#[test]
+fn switching_can_install_missing_versions() {
+    let sandbox = Sandbox::new();
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+
+    // Not asked to install and nobody to ask, since tests don't run in a terminal
+    assert!(sandbox
+        .switcher
+        .switch_or_install("hello@0.1.0", false, &InstallOptions::default())
+        .is_err());
+
+    // A failed install leaves nothing behind
+    let broken = InstallOptions {
+        retries: Some(0),
+        path: Some(sandbox.root.path().join("no-such-crate")),
+        ..InstallOptions::default()
+    };
+    assert!(sandbox
+        .switcher
+        .switch_or_install("hello@0.1.0", true, &broken)
+        .is_err());
+    assert!(registry.join("hello").exists().not());
+
+    let options = InstallOptions {
+        retries: Some(0),
+        path: Some(sandbox.write_crate("0.1.0")),
+        ..InstallOptions::default()
+    };
+    sandbox
+        .switcher
+        .switch_or_install("hello@0.1.0", true, &options)
+        .unwrap();
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+}
+
+#[test]
+fn strips_installed_binaries() {
+    let sandbox = Sandbox::new();
+    sandbox.install("0.1.0");
+
+    let version_path = sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/hello/0.1.0");
+    let size_before = fs::metadata(version_path.join("bin/hello")).unwrap().len();
+
+    sandbox.switcher.strip("hello@0.1.0").unwrap();
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+
+    let metadata = VersionMetadata::load(&version_path).unwrap().unwrap();
+    let binary = &metadata.binaries[0];
+    assert_eq!(binary.unstripped_size, Some(size_before));
+    assert!(binary.size < size_before);
+    assert_eq!(
+        binary.size,
+        fs::metadata(version_path.join("bin/hello")).unwrap().len()
+    );
+
+    // Stripping again doesn't forget how big the binary used to be
+    sandbox.switcher.strip("hello@0.1.0").unwrap();
+    let metadata = VersionMetadata::load(&version_path).unwrap().unwrap();
+    assert_eq!(metadata.binaries[0].unstripped_size, Some(size_before));
+}
+
+#[test]
+fn keeps_feature_builds_apart() {
+    let sandbox = Sandbox::new();
+    sandbox.install("0.1.0");
+
+    let options = InstallOptions {
+        retries: Some(0),
+        path: Some(sandbox.write_crate("0.1.0")),
+        features: vec!["loud".to_owned()],
+        ..InstallOptions::default()
+    };
+    for _ in 0..2 {
+        let report = sandbox
+            .switcher
+            .install_package("hello@0.1.0", &options)
+            .unwrap();
+        assert_eq!(report.version, "0.1.0+loud");
+    }
+
+    let versions: Vec<_> = sandbox.switcher.listing(false).unwrap()[0]
+        .versions
+        .iter()
+        .map(|version| version.version.clone())
+        .collect();
+    assert_eq!(versions, ["0.1.0", "0.1.0+loud"]);
+
+    let metadata = VersionMetadata::load(
+        &sandbox
+            .cargo_bin()
+            .join("cargo-switch-registry/hello/0.1.0+loud"),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(metadata.features, ["loud"]);
+
+    // With the plain build around, a bare version means that one
+    sandbox.switcher.switch_package("hello@0.1.0").unwrap();
+    assert_eq!(
+        sandbox.switcher.linked_version("hello").unwrap().as_deref(),
+        Some("0.1.0")
+    );
+    sandbox.switcher.uninstall("hello@0.1.0", false).unwrap();
+    sandbox.switcher.switch_package("hello@0.1.0").unwrap();
+    assert_eq!(
+        sandbox.switcher.linked_version("hello").unwrap().as_deref(),
+        Some("0.1.0+loud")
+    );
+}
+
+#[test]
+fn handles_names_that_are_not_utf8() {
+    let root = tempfile::tempdir().unwrap();
+    let cargo_bin = root
+        .path()
+        .join(OsStr::from_bytes(b"h\xf6me"))
+        .join(".cargo")
+        .join("bin");
+    fs::create_dir_all(&cargo_bin).unwrap();
+    let registry = cargo_bin.join("cargo-switch-registry");
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .registry(&registry)
+        .config(Config::default())
+        .build()
+        .unwrap();
+
+    let name = OsStr::from_bytes(b"t\xe9l\xe9");
+    let binary = root.path().join("downloads").join(name);
+    fs::create_dir_all(binary.parent().unwrap()).unwrap();
+    fs::write(&binary, "#!/bin/sh\necho hi\n").unwrap();
+    fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+
+    switcher.add_binary("weird@1.0.0", &[binary]).unwrap();
+    switcher.switch_package("weird@1.0.0").unwrap();
+
+    let link = cargo_bin.join(name);
+    assert!(is_link_to(
+        &link,
+        &registry.join("weird/1.0.0/bin").join(name)
+    ));
+    let output = Command::new(&link).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "hi\n");
+
+    assert_eq!(
+        switcher.link_owner(name),
+        Some(("weird".to_owned(), "1.0.0".to_owned()))
+    );
+    assert_eq!(
+        switcher.linked_version("weird").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+
+    let listings = switcher.listing(false).unwrap();
+    let binaries = &listings[0].versions[0].binaries;
+    assert_eq!(binaries.len(), 1);
+    assert_eq!(binaries[0].path.file_name(), Some(name));
+    let owners = switcher.binary_listing(&listings);
+    assert_eq!(
+        owners[0].active.as_ref().unwrap().to_string(),
+        "weird@1.0.0"
+    );
+
+    switcher.uninstall("weird@1.0.0", false).unwrap();
+    assert!(link.symlink_metadata().is_err());
+}
+
+/// Options for installs that go through a [`FakeInstaller`], which never fail transiently unless told to
+fn fake_options() -> InstallOptions {
+    InstallOptions {
+        retries: Some(0),
+        ..InstallOptions::default()
+    }
+}
+
+#[test]
+fn installs_switches_and_uninstalls_without_cargo() {
+    let installer = FakeInstaller::default().with_binaries("tool", &["tool", "tool-helper"]);
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let cargo_bin = sandbox.cargo_bin();
+
+    for version in ["1.0.0", "2.0.0"] {
+        let report = sandbox
+            .switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+        assert!(report.switched);
+        assert_eq!(report.binaries.len(), 2);
+    }
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "tool@2.0.0"]);
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@2.0.0 release\n");
+    assert_eq!(
+        run_binary(&cargo_bin, "tool-helper"),
+        "tool@2.0.0 release\n"
+    );
+
+    sandbox.switcher.switch_package("tool@1.0.0").unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+
+    let listings = sandbox.switcher.listing(false).unwrap();
+    let versions: Vec<_> = listings[0]
+        .versions
+        .iter()
+        .map(|version| (version.version.as_str(), version.active))
+        .collect();
+    assert_eq!(versions, [("1.0.0", true), ("2.0.0", false)]);
+
+    // Debug builds are a variant of their own
+    let options = InstallOptions {
+        profile: Some("dev".to_owned()),
+        ..fake_options()
+    };
+    let report = sandbox
+        .switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+    assert_eq!(report.version, "1.0.0+debug");
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 dev\n");
+
+    for version in ["1.0.0", "1.0.0+debug", "2.0.0"] {
+        sandbox
+            .switcher
+            .uninstall(&format!("tool@{version}"), false)
+            .unwrap();
+    }
+    assert!(cargo_bin.join("tool").symlink_metadata().is_err());
+    assert!(cargo_bin.join("cargo-switch-registry/tool").exists().not());
+}
+
+#[test]
+fn failed_installs_leave_the_registry_as_it_was() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+
+    // Whatever a failed build wrote is thrown away
+    installer.then(&[FakeBuild::Permanent]);
+    let err = sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("could not compile"), "{err:#}");
+    assert!(registry.join("tool/1.0.0").exists().not());
+
+    installer.then(&[FakeBuild::Transient]);
+    let err = sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap_err();
+    assert!(err.to_string().contains("after 1 attempt"), "{err:#}");
+    assert!(registry.join("tool/1.0.0").exists().not());
+
+    // A failed reinstall keeps the version that was already there
+    sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    installer.then(&[FakeBuild::Transient]);
+    assert!(sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .is_err());
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@1.0.0 release\n"
+    );
+    assert_eq!(
+        sandbox.switcher.linked_version("tool").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+}
+
+#[test]
+fn switches_and_promotes_through_channels() {
+    let installer = FakeInstaller::default().with_binaries("tool", &["tool"]);
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer));
+    let cargo_bin = sandbox.cargo_bin();
+    let switcher = &sandbox.switcher;
+
+    for version in ["1.9.3", "2.0.0-rc1"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    switcher
+        .set_channel("tool", "testing", "2.0.0-rc1")
+        .unwrap();
+    switcher.set_channel("tool", "stable", "1.9.3").unwrap();
+    // Channels only ever point to installed versions, and can't pass for versions
+    assert!(switcher.set_channel("tool", "beta", "3.0.0").is_err());
+    assert!(switcher.set_channel("tool", "3.0.0", "1.9.3").is_err());
+
+    switcher.switch_package("tool@stable").unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.9.3 release\n");
+    switcher
+        .switch_or_install("tool@testing", false, &fake_options())
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@2.0.0-rc1 release\n");
+    assert!(switcher.switch_package("tool@beta").is_err());
+
+    switcher.promote("tool", "testing", "stable").unwrap();
+    let channels = switcher.channels("tool").unwrap();
+    assert_eq!(
+        channels,
+        BTreeMap::from([
+            ("stable".to_owned(), "2.0.0-rc1".to_owned()),
+            ("testing".to_owned(), "2.0.0-rc1".to_owned()),
+        ])
+    );
+    assert!(switcher.promote("tool", "beta", "stable").is_err());
+
+    // Uninstalling a version takes the channels pointing to it along
+    switcher.uninstall("tool@2.0.0-rc1", false).unwrap();
+    assert!(switcher.channels("tool").unwrap().is_empty());
+}
+
+#[test]
+fn prunes_old_versions_after_installs() {
+    let installer = FakeInstaller::default().with_binaries("tool", &["tool"]);
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer).config(Config {
+            keep_versions: Some(2),
+            ..Config::default()
+        })
+    });
+    let switcher = &sandbox.switcher;
+    let install = |version: &str, options: &InstallOptions| {
+        switcher
+            .install_package(&format!("tool@{version}"), options)
+            .unwrap()
+            .pruned
+    };
+    let installed = || {
+        switcher.listing(false).unwrap()[0]
+            .versions
+            .iter()
+            .map(|version| version.version.clone())
+            .collect::<Vec<_>>()
+    };
+
+    assert!(install("1.0.0", &fake_options()).is_empty());
+    switcher.set_default("tool", "1.0.0").unwrap();
+    assert!(install("2.0.0", &fake_options()).is_empty());
+    // The default version is held, so the next oldest one goes
+    assert_eq!(install("3.0.0", &fake_options()), ["2.0.0"]);
+    assert_eq!(installed(), ["1.0.0", "3.0.0"]);
+
+    let options = InstallOptions {
+        no_auto_prune: true,
+        ..fake_options()
+    };
+    assert!(install("4.0.0", &options).is_empty());
+    assert_eq!(installed(), ["1.0.0", "3.0.0", "4.0.0"]);
+
+    // Neither the default nor the active version can go
+    switcher.prune(Some("tool"), Some(1)).unwrap();
+    assert_eq!(installed(), ["1.0.0", "4.0.0"]);
+}
+
+#[test]
+fn keeps_locked_versions_out_of_cleanups() {
+    let config = Config {
+        max_size: Some(1),
+        ..Config::default()
+    };
+    let sandbox =
+        Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()).config(config));
+    let switcher = &sandbox.switcher;
+    let installed = || {
+        switcher.listing(false).unwrap()[0]
+            .versions
+            .iter()
+            .map(|version| (version.version.clone(), version.locked))
+            .collect::<Vec<_>>()
+    };
+
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    assert!(switcher.lock_version("tool@2.0.0").is_err());
+    switcher.lock_version("tool@1.0.0").unwrap();
+    for version in ["2.0.0", "3.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    // Eviction leaves the locked version alone
+    assert_eq!(
+        installed(),
+        [("1.0.0".to_owned(), true), ("3.0.0".to_owned(), false)]
+    );
+
+    switcher.unlock_version("tool@1.0.0").unwrap();
+    assert!(switcher.unlock_version("tool@1.0.0").is_err());
+    switcher.lock_version("tool@1.0.0").unwrap();
+    switcher.prune(Some("tool"), Some(1)).unwrap();
+    assert_eq!(installed().len(), 2);
+
+    assert!(switcher.uninstall("tool@1.0.0", false).is_err());
+    switcher.uninstall("tool@1.0.0", true).unwrap();
+    assert_eq!(installed(), [("3.0.0".to_owned(), false)]);
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    assert!(State::load(&registry).unwrap().locked.is_empty());
+}
+
+#[test]
+fn evicts_old_versions_past_the_max_size() {
+    let config = || Config {
+        max_size: Some(1),
+        ..Config::default()
+    };
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(config())
+    });
+    let switcher = &sandbox.switcher;
+    let installed = |switcher: &Switcher| {
+        switcher.listing(false).unwrap()[0]
+            .versions
+            .iter()
+            .map(|version| version.version.clone())
+            .collect::<Vec<_>>()
+    };
+
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(installed(switcher), ["2.0.0"]);
+
+    // Protected versions stay, however far over the registry is
+    switcher.set_default("tool", "2.0.0").unwrap();
+    switcher
+        .install_package("tool@3.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(installed(switcher), ["2.0.0", "3.0.0"]);
+
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder
+            .installer(FakeInstaller::default())
+            .config(config())
+            .no_evict(true)
+    });
+    for version in ["1.0.0", "2.0.0"] {
+        sandbox
+            .switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    assert_eq!(installed(&sandbox.switcher), ["1.0.0", "2.0.0"]);
+}
+
+#[test]
+fn runs_commands_against_every_version() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    for version in ["1.0.0", "2.0.0", "3.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    switcher.switch_package("tool@1.0.0").unwrap();
+
+    // Passes for every version but the first, which is still the one in .cargo/bin
+    let command = ["sh", "-c", "! tool | grep -q 1.0.0"].map(OsString::from);
+    let all = "..".parse().unwrap();
+    assert!(switcher
+        .test_matrix("tool", &all, 2, false, &command)
+        .is_err());
+    let newer = "2.0.0..".parse().unwrap();
+    switcher
+        .test_matrix("tool", &newer, 1, true, &command)
+        .unwrap();
+    assert!(switcher
+        .test_matrix("tool", &"4.0.0..".parse().unwrap(), 1, false, &command)
+        .is_err());
+
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@1.0.0 release\n"
+    );
+}
+
+#[test]
+fn keeps_versioned_links_to_every_version() {
+    let mut packages = BTreeMap::new();
+    packages.insert(
+        "tool".to_owned(),
+        PackageConfig {
+            versioned_links: true,
+            ..PackageConfig::default()
+        },
+    );
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(Config {
+            packages,
+            ..Config::default()
+        })
+    });
+    let switcher = &sandbox.switcher;
+    for version in ["1.0.0", "1.2.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    switcher.switch_package("tool@1.0.0").unwrap();
+
+    let cargo_bin = sandbox.cargo_bin();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+    // The newest version of each major gets its link
+    assert_eq!(run_binary(&cargo_bin, "tool-1"), "tool@1.2.0 release\n");
+    assert_eq!(run_binary(&cargo_bin, "tool-2"), "tool@2.0.0 release\n");
+    // Which say nothing about the active version
+    assert_eq!(
+        switcher.active_state("tool").unwrap(),
+        ActiveState::Active {
+            version: "1.0.0".to_owned()
+        }
+    );
+
+    switcher.uninstall("tool@1.2.0", false).unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool-1"), "tool@1.0.0 release\n");
+    switcher.uninstall("tool@2.0.0", false).unwrap();
+    assert!(cargo_bin.join("tool-2").symlink_metadata().is_err());
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+    assert!(cargo_bin.join("tool-1").symlink_metadata().is_err());
+}
+
+#[test]
+fn keeps_versioned_links_once_asked_to() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let cargo_bin = sandbox.cargo_bin();
+    let options = InstallOptions {
+        profile: Some("dev".to_owned()),
+        ..fake_options()
+    };
+    sandbox
+        .switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+
+    // Debug builds get no versioned links, which doesn't make the opt-in go away
+    let keeping = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .config(Config::default())
+        .installer(FakeInstaller::default())
+        .keep_suffixed(true)
+        .build()
+        .unwrap();
+    keeping.switch_package("tool@1.0.0+debug").unwrap();
+    assert!(cargo_bin.join("tool-1").symlink_metadata().is_err());
+
+    sandbox
+        .switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool-2"), "tool@2.0.0 release\n");
+
+    // Until the package is gone
+    for version in ["1.0.0+debug", "2.0.0"] {
+        sandbox
+            .switcher
+            .uninstall(&format!("tool@{version}"), false)
+            .unwrap();
+    }
+    sandbox
+        .switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    assert!(cargo_bin.join("tool-2").symlink_metadata().is_err());
+}
+
+#[test]
+fn installs_offline_only_what_was_vendored() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    let options = InstallOptions {
+        offline: true,
+        ..fake_options()
+    };
+
+    let err = switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("wasn't vendored"));
+
+    // As `vendor` would have left it, but for the record it writes last
+    let vendored = sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/.cache/vendor/tool/1.0.0");
+    fs::create_dir_all(vendored.join("source")).unwrap();
+    fs::write(vendored.join("source/Cargo.toml"), "").unwrap();
+    fs::write(
+        vendored.join("source/Cargo.lock"),
+        "[[package]]\nname = \"tool\"\nversion = \"1.0.0\"\n",
+    )
+    .unwrap();
+    fs::write(vendored.join("cargo-config.toml"), "").unwrap();
+    let err = switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("incomplete"));
+    assert!(sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/tool")
+        .exists()
+        .not());
+
+    fs::write(vendored.join("vendored.json"), "{}").unwrap();
+    switcher.install_package("tool@1.0.0", &options).unwrap();
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@1.0.0 release\n"
+    );
+}
+
+#[test]
+fn fails_audits_only_when_an_active_version_is_affected() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    for version in ["1.0.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+
+    // A fresh copy of the database, as if it had just been fetched
+    let database = sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/.cache/advisory-db");
+    fs::create_dir_all(database.join("crates/tool")).unwrap();
+    fs::write(
+        database.join("fetched-at"),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            .to_string(),
+    )
+    .unwrap();
+    fs::write(
+        database.join("crates/tool/RUSTSEC-2024-0001.md"),
+        "```toml\n[advisory]\nid = \"RUSTSEC-2024-0001\"\npackage = \"tool\"\n\n\
+         [versions]\npatched = [\">= 2.0.0\"]\n```\n\n# Something bad\n",
+    )
+    .unwrap();
+    // One that can't be parsed is skipped, rather than hiding the others
+    fs::write(
+        database.join("crates/tool/RUSTSEC-2024-0002.md"),
+        "```toml\n[advisory\n```\n",
+    )
+    .unwrap();
+
+    switcher.switch_package("tool@1.0.0").unwrap();
+    let err = switcher.audit(false).unwrap_err();
+    assert!(format!("{err:#}").contains("tool"));
+
+    // The old version is still affected, but it isn't the one in use
+    switcher.switch_package("tool@2.0.0").unwrap();
+    switcher.audit(false).unwrap();
+}
+
+#[test]
+fn keeps_the_cargo_lock_of_every_build() {
+    let sandbox = Sandbox::new();
+    sandbox.install("0.1.0");
+
+    let version_path = sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/hello/0.1.0");
+    let metadata = VersionMetadata::load(&version_path).unwrap().unwrap();
+    assert_eq!(metadata.lockfile, Some(LockfileStatus::Kept));
+    let lockfile_path = VersionMetadata::lockfile_path(&version_path);
+    assert!(fs::read_to_string(&lockfile_path)
+        .unwrap()
+        .contains("name = \"hello\""));
+    sandbox.switcher.deps("hello", None, None).unwrap();
+
+    // Cargo builds the very same sources again, along with the lockfile
+    sandbox.switcher.rebuild("hello@0.1.0", true).unwrap();
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+    assert!(lockfile_path.exists());
+
+    // Versions installed before lockfiles were kept have none to go by
+    fs::remove_file(&lockfile_path).unwrap();
+    let err = sandbox.switcher.rebuild("hello@0.1.0", true).unwrap_err();
+    assert!(format!("{err:#}").contains("No Cargo.lock was kept"));
+    assert_eq!(sandbox.run_hello(), "hello 0.1.0\n");
+}
+
+#[test]
+fn rebuilds_from_the_original_cargo_lock() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    let source = sandbox.root.path().join("tool");
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("Cargo.toml"), "").unwrap();
+    let options = InstallOptions {
+        path: Some(source),
+        ..fake_options()
+    };
+    switcher.install_package("tool@1.0.0", &options).unwrap();
+
+    let lockfile_path = VersionMetadata::lockfile_path(
+        &sandbox.cargo_bin().join("cargo-switch-registry/tool/1.0.0"),
+    );
+    let original = fs::read_to_string(&lockfile_path).unwrap();
+    assert!(original.contains("version = \"1.0.1\""));
+    switcher.deps("tool", Some("1.0.0"), Some("help")).unwrap();
+
+    switcher.rebuild("tool@1.0.0", true).unwrap();
+    assert_eq!(fs::read_to_string(&lockfile_path).unwrap(), original);
+
+    // Otherwise dependencies are resolved afresh
+    switcher.rebuild("tool@1.0.0", false).unwrap();
+    assert_ne!(fs::read_to_string(&lockfile_path).unwrap(), original);
+}
+
+#[test]
+fn links_pinned_versions_for_direnv() {
+    let installer = FakeInstaller::default().with_binaries("tool", &["tool"]);
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer));
+    for version in ["1.0.0", "2.0.0"] {
+        sandbox
+            .switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+
+    let project = sandbox.root.path().join("project");
+    let nested = project.join("src");
+    fs::create_dir_all(&nested).unwrap();
+    let pin = |pins: &str| fs::write(project.join(".cargo-switch.toml"), pins).unwrap();
+    let links = project.join(".direnv/cargo-switch/bin");
+
+    // Versions that aren't installed are left out rather than linked to nothing
+    pin("[pins]\ntool = \"1.0.0\"\nother = \"3.0.0\"\n");
+    sandbox.switcher.refresh_direnv_links(&nested).unwrap();
+    assert_eq!(run_binary(&links, "tool"), "tool@1.0.0 release\n");
+    assert_eq!(fs::read_dir(&links).unwrap().count(), 1);
+    // The links in .cargo/bin are left alone
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@2.0.0 release\n"
+    );
+
+    pin("[pins]\ntool = \"2.0.0\"\n");
+    sandbox.switcher.refresh_direnv_links(&project).unwrap();
+    assert_eq!(run_binary(&links, "tool"), "tool@2.0.0 release\n");
+
+    pin("");
+    sandbox.switcher.refresh_direnv_links(&project).unwrap();
+    assert_eq!(fs::read_dir(&links).unwrap().count(), 0);
+}
+
+#[test]
+fn reads_and_writes_pins_in_tool_versions() {
+    let installer = FakeInstaller::default().with_binaries("ripgrep", &["rg"]);
+    let config = Config {
+        tool_versions: BTreeMap::from([("ripgrep".to_owned(), "rg".to_owned())]),
+        ..Config::default()
+    };
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer).config(config));
+    for spec in [
+        "tool@1.0.0",
+        "tool@2.0.0",
+        "ripgrep@13.0.0",
+        "ripgrep@14.0.0",
+    ] {
+        sandbox
+            .switcher
+            .install_package(spec, &fake_options())
+            .unwrap();
+    }
+
+    let project = sandbox.root.path().join("project");
+    let nested = project.join("src");
+    fs::create_dir_all(&nested).unwrap();
+    let tool_versions = project.join(".tool-versions");
+    fs::write(
+        &tool_versions,
+        "nodejs 20.11.0
+rg 13.0.0 # ripgrep
+tool 1.0.0
+",
+    )
+    .unwrap();
+    let pinned = || -> BTreeMap<String, String> {
+        let project_pins = sandbox.switcher.project_pins(&nested).unwrap().unwrap();
+        assert_eq!(project_pins.root, project);
+        project_pins
+            .pins
+            .into_iter()
+            .map(|(package, pin)| (package, pin.version))
+            .collect()
+    };
+
+    // Tools that aren't managed packages are left to asdf
+    assert_eq!(
+        pinned(),
+        BTreeMap::from([
+            ("ripgrep".to_owned(), "13.0.0".to_owned()),
+            ("tool".to_owned(), "1.0.0".to_owned()),
+        ])
+    );
+
+    // The project file wins where both pin a package
+    fs::write(
+        project.join(".cargo-switch.toml"),
+        "[pins]\ntool = \"2.0.0\"\n",
+    )
+    .unwrap();
+    assert_eq!(pinned()["tool"], "2.0.0");
+    let project_pins = sandbox.switcher.project_pins(&nested).unwrap().unwrap();
+    assert_eq!(project_pins.conflicts.len(), 1);
+    assert_eq!(project_pins.conflicts[0].loser.path, tool_versions);
+
+    sandbox
+        .switcher
+        .pin(&["ripgrep@14.0.0".to_owned()], &nested, true)
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(&tool_versions).unwrap(),
+        "nodejs 20.11.0\nrg 14.0.0 # ripgrep\ntool 1.0.0\n"
+    );
+    // Bare names pin the active version, and only installed versions can be pinned
+    sandbox
+        .switcher
+        .pin(&["tool".to_owned()], &nested, false)
+        .unwrap();
+    assert!(sandbox
+        .switcher
+        .pin(&["tool@3.0.0".to_owned()], &nested, true)
+        .is_err());
+    assert_eq!(
+        pinned(),
+        BTreeMap::from([
+            ("ripgrep".to_owned(), "14.0.0".to_owned()),
+            ("tool".to_owned(), "2.0.0".to_owned()),
+        ])
+    );
+}
+
+#[test]
+fn installs_the_tools_a_workspace_declares() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    sandbox
+        .switcher
+        .install_package("tool@1.2.0", &fake_options())
+        .unwrap();
+    installer.builds.borrow_mut().clear();
+
+    let workspace = sandbox.root.path().join("workspace");
+    let member = workspace.join("crates/app");
+    fs::create_dir_all(&member).unwrap();
+    fs::write(
+        member.join("Cargo.toml"),
+        "[package]\nname = \"app\"\nversion = \"0.1.0\"\n",
+    )
+    .unwrap();
+    fs::write(
+        workspace.join("Cargo.toml"),
+        "[workspace]\nmembers = [\"crates/*\"]\n\n\
+         [workspace.metadata.bin]\ntool = { version = \"1.1\", locked = true }\nother = \"=2.0.0\"\n",
+    )
+    .unwrap();
+
+    sandbox.switcher.project_install(&member).unwrap();
+    // Satisfied requirements are left alone, and nothing gets switched to
+    assert_eq!(*installer.builds.borrow(), ["other@2.0.0"]);
+    assert!(sandbox.cargo_bin().join("other").exists().not());
+    assert_eq!(
+        fs::read_to_string(workspace.join(".cargo-switch.toml")).unwrap(),
+        "[pins]\nother = \"2.0.0\"\ntool = \"1.2.0\"\n"
+    );
+
+    sandbox.switcher.project_install(&workspace).unwrap();
+    assert_eq!(installer.builds.borrow().len(), 1);
+}
+
+#[test]
+fn keeps_cargo_records_in_line_with_links() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let root = sandbox.root.path().join(".cargo");
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    let id = |spec: &str| {
+        let (name, version) = spec.split_once('@').unwrap();
+        format!("{name} {version} (registry+https://github.com/rust-lang/crates.io-index)")
+    };
+    let write_records = |directory: &Path, installs: &[(&str, &str)]| {
+        let v1: Vec<_> = installs
+            .iter()
+            .map(|(spec, bin)| format!("\"{}\" = [\"{bin}\"]\n", id(spec)))
+            .collect();
+        fs::write(
+            directory.join(".crates.toml"),
+            format!("[v1]\n{}", v1.concat()),
+        )
+        .unwrap();
+        let v2: Vec<_> = installs
+            .iter()
+            .map(|(spec, bin)| {
+                format!(
+                    "\"{}\": {{\"version_req\": null, \"bins\": [\"{bin}\"], \"features\": [], \
+                     \"all_features\": false, \"no_default_features\": false, \"profile\": \"release\", \
+                     \"target\": null, \"rustc\": \"rustc 1.95.0\"}}",
+                    id(spec)
+                )
+            })
+            .collect();
+        fs::write(
+            directory.join(".crates2.json"),
+            format!("{{\"installs\": {{{}}}}}", v2.join(", ")),
+        )
+        .unwrap();
+    };
+    let recorded = || {
+        let v1 = fs::read_to_string(root.join(".crates.toml")).unwrap();
+        let v2 = fs::read_to_string(root.join(".crates2.json")).unwrap();
+        let mut ids: Vec<_> = toml::from_str::<toml::Table>(&v1).unwrap()["v1"]
+            .as_table()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        let v2: serde_json::Value = serde_json::from_str(&v2).unwrap();
+        let v2_ids: Vec<_> = v2["installs"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(ids, v2_ids);
+        ids.sort();
+        ids
+    };
+
+    // What cargo recorded before cargo-switch took over
+    write_records(&root, &[("tool@0.9.0", "tool"), ("other@1.0.0", "other")]);
+    sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(recorded(), [id("other@1.0.0")]);
+
+    // Versions installed by cargo come with records of their own, which take over once switched to
+    write_records(&registry.join("tool/1.0.0"), &[("tool@1.0.0", "tool")]);
+    sandbox.switcher.switch_package("tool@1.0.0").unwrap();
+    assert_eq!(recorded(), [id("other@1.0.0"), id("tool@1.0.0")]);
+
+    sandbox.switcher.uninstall("tool@1.0.0", false).unwrap();
+    assert_eq!(recorded(), [id("other@1.0.0")]);
+
+    // Records cargo can't read are left for the user to fix, without failing the switch
+    fs::write(root.join(".crates2.json"), "{\"installs\": [").unwrap();
+    sandbox
+        .switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join(".crates2.json")).unwrap(),
+        "{\"installs\": ["
+    );
+}
+
+/// A cargo that fetches empty sources, writes a binary into `--root` and, if `$HANG` is set, hangs as if the build
+/// took forever. It records its pid in `pid_file` so that tests can tell whether it's still around.
+fn write_hanging_cargo(path: &Path, pid_file: &Path) {
+    fs::write(
+        path,
+        format!(
+            "#!/bin/sh\n\
+             for last; do :; done\n\
+             case $1 in\n\
+             metadata)\n\
+               sources=$(sed -n 's/^\"\\(.*\\)\" = \"=\\(.*\\)\"$/\\1-\\2/p' \"$last\")\n\
+               mkdir -p \"$HOME/.cargo/registry/src/index/$sources\"\n\
+               touch \"$HOME/.cargo/registry/src/index/$sources/Cargo.toml\"\n\
+               exit ;;\n\
+             locate-project) echo \"$last\"; exit ;;\n\
+             generate-lockfile) touch \"${{last%/*}}/Cargo.lock\"; exit ;;\n\
+             esac\n\
+             while [ $# -gt 0 ]; do [ \"$1\" = --root ] && root=$2; shift; done\n\
+             mkdir -p \"$root/bin\"\n\
+             printf '#!/bin/sh\\necho built\\n' > \"$root/bin/tool\"\n\
+             chmod +x \"$root/bin/tool\"\n\
+             echo $$ > {}\n\
+             if [ -n \"$HANG\" ]; then sleep 60 & wait; fi\n",
+            pid_file.display()
+        ),
+    )
+    .unwrap();
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+/// Whether the process `pid` is gone, zombies included since nobody may be around to reap them
+fn is_gone(pid: &str) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/stat")).map_or(true, |stat| {
+        stat.rsplit(')')
+            .next()
+            .unwrap()
+            .trim_start()
+            .starts_with('Z')
+    })
+}
+
+#[test]
+fn cleans_up_interrupted_installs() {
+    let root = tempfile::tempdir().unwrap();
+    let cargo_bin = root.path().join(".cargo").join("bin");
+    let registry = cargo_bin.join("cargo-switch-registry");
+    fs::create_dir_all(&cargo_bin).unwrap();
+    let cargo = root.path().join("cargo");
+    let pid_file = root.path().join("cargo.pid");
+    write_hanging_cargo(&cargo, &pid_file);
+
+    let path = std::env::join_paths(
+        [cargo_bin.clone()]
+            .into_iter()
+            .chain(std::env::split_paths(&std::env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    let cargo_switch = |spec: &str| {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-switch"));
+        command
+            .args(["install", spec])
+            .env("PATH", &path)
+            .env("HOME", root.path())
+            .env("XDG_CONFIG_HOME", root.path().join(".config"))
+            .env("CARGO", &cargo)
+            .env_remove("CARGO_HOME")
+            .env_remove("CARGO_INSTALL_ROOT")
+            .env_remove("HANG")
+            .current_dir(root.path())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        command
+    };
+    // Interrupt an install of `spec` with `signal` once its build is underway, returning the exit code
+    let interrupt = |spec: &str, version_path: &Path, signal| {
+        let _ = fs::remove_file(&pid_file);
+        let mut child = cargo_switch(spec).env("HANG", "1").spawn().unwrap();
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while pid_file.exists().not() || version_path.join("bin/tool").exists().not() {
+            assert!(Instant::now() < deadline, "the build never started");
+            thread::sleep(Duration::from_millis(20));
+        }
+
+        unsafe { libc::kill(child.id() as libc::pid_t, signal) };
+        let status = child.wait().unwrap();
+        let cargo_pid = fs::read_to_string(&pid_file).unwrap();
+        assert!(is_gone(cargo_pid.trim()), "cargo is still running");
+
+        status.code()
+    };
+
+    assert!(cargo_switch("tool@1.0.0").status().unwrap().success());
+
+    // The completed install is left alone, along with the links to it
+    let code = interrupt("tool@2.0.0", &registry.join("tool/2.0.0"), libc::SIGINT);
+    assert_eq!(code, Some(130));
+    assert!(registry.join("tool/2.0.0").exists().not());
+    assert!(registry.join("tool/1.0.0/bin/tool").exists());
+    assert!(is_link_to(
+        &cargo_bin.join("tool"),
+        &registry.join("tool/1.0.0/bin/tool")
+    ));
+
+    // Nothing at all is left of a package whose first install was interrupted, not even its lock
+    let other = registry.join("other");
+    let code = interrupt("other@1.0.0", &other.join("1.0.0"), libc::SIGTERM);
+    assert_eq!(code, Some(143));
+    assert!(other.exists().not());
+}
+
+#[test]
+fn rebuilds_installed_versions_in_place() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+    let options = InstallOptions {
+        profile: Some("dev".to_owned()),
+        ..fake_options()
+    };
+    switcher.install_package("tool@1.0.0", &options).unwrap();
+    let options = InstallOptions {
+        no_switch: true,
+        ..fake_options()
+    };
+    switcher.install_package("tool@2.0.0", &options).unwrap();
+    let binary = sandbox.root.path().join("weird");
+    fs::write(&binary, "#!/bin/sh\necho weird\n").unwrap();
+    fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+    switcher.add_binary("weird@1.0.0", &[binary]).unwrap();
+    installer.builds.borrow_mut().clear();
+
+    let active = sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry")
+        .join("tool/1.0.0+debug/bin/tool");
+    fs::write(&active, "#!/bin/sh\necho broken\n").unwrap();
+
+    // A failed rebuild leaves the old build as it was
+    installer.then(&[FakeBuild::Permanent]);
+    assert!(switcher.rebuild("tool@1.0.0+debug", false).is_err());
+    assert_eq!(run_binary(&cargo_bin, "tool"), "broken\n");
+
+    // Rebuilt with the profile it was installed with, and still active
+    switcher.rebuild("tool@1.0.0+debug", false).unwrap();
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "tool@1.0.0"]);
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 dev\n");
+    assert_eq!(
+        switcher.linked_version("tool").unwrap().as_deref(),
+        Some("1.0.0+debug")
+    );
+
+    // Binaries registered through add-binary can't be rebuilt, which doesn't stop the others from being
+    installer.builds.borrow_mut().clear();
+    let err = switcher.rebuild_all(false).unwrap_err();
+    assert_eq!(err.to_string(), "1 of 3 versions failed to rebuild");
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "tool@2.0.0"]);
+    // Nothing is left behind in the staging area
+    assert!(sandbox
+        .cargo_bin()
+        .join("cargo-switch-registry/.rebuild")
+        .read_dir()
+        .unwrap()
+        .next()
+        .is_none());
+}
+
+#[test]
+fn migrates_older_registries_on_first_touch() {
+    let layouts = [
+        ("no state file", None),
+        (
+            "defaults",
+            Some(include_str!("fixtures/state/defaults.json")),
+        ),
+        (
+            "channels",
+            Some(include_str!("fixtures/state/channels.json")),
+        ),
+    ];
+    for (layout, state) in layouts {
+        let root = tempfile::tempdir().unwrap();
+        let cargo_bin = root.path().join("bin");
+        let registry = cargo_bin.join("cargo-switch-registry");
+        fs::create_dir_all(registry.join("tool/1.0.0/bin")).unwrap();
+        fs::create_dir_all(registry.join("tool/3.0.0/bin")).unwrap();
+        if let Some(state) = state {
+            fs::write(registry.join(".state.json"), state).unwrap();
+        }
+        let build = || {
+            Switcher::builder()
+                .non_interactive(true)
+                .cargo_bin(&cargo_bin)
+                .config(Config::default())
+                .build()
+        };
+        let backups = || {
+            fs::read_dir(registry.join(".migration-backups"))
+                .map(|entries| entries.count())
+                .unwrap_or_default()
+        };
+
+        build().unwrap();
+        assert_eq!(
+            migrate::registry_format(&registry).unwrap(),
+            migrate::FORMAT_VERSION,
+            "{layout}"
+        );
+        assert_eq!(backups(), usize::from(state.is_some()), "{layout}");
+        let state = State::load(&registry).unwrap();
+        // Defaults and channels whose versions went away along with older releases are forgotten
+        assert!(state.defaults.contains_key("gone").not(), "{layout}");
+        assert!(state.channels.contains_key("gone").not(), "{layout}");
+        // And the directory the links went into is known to hold some
+        assert!(state.link_dirs.contains(&cargo_bin), "{layout}");
+        if layout == "channels" {
+            assert_eq!(state.defaults["tool"], "3.0.0");
+            assert_eq!(
+                state.channels["tool"].keys().collect::<Vec<_>>(),
+                ["beta", "stable"]
+            );
+        }
+
+        // Migrated registries are left alone from then on
+        build().unwrap();
+        assert_eq!(
+            backups(),
+            usize::from(layout != "no state file"),
+            "{layout}"
+        );
+    }
+}
+
+#[test]
+fn leaves_registries_alone_when_read_only() {
+    let root = tempfile::tempdir().unwrap();
+    let cargo_bin = root.path().join("bin");
+    let registry = cargo_bin.join("cargo-switch-registry");
+    fs::create_dir_all(&cargo_bin).unwrap();
+    let build = || {
+        Switcher::builder()
+            .non_interactive(true)
+            .cargo_bin(&cargo_bin)
+            .config(Config::default())
+            .read_only(true)
+            .build()
+    };
+
+    // Missing registries aren't created
+    assert!(build().is_err());
+    assert!(registry.exists().not());
+
+    // Nor are older ones migrated
+    fs::create_dir_all(registry.join("tool/1.0.0/bin")).unwrap();
+    fs::write(
+        registry.join(".state.json"),
+        include_str!("fixtures/state/defaults.json"),
+    )
+    .unwrap();
+    let format_version = migrate::registry_format(&registry).unwrap();
+    assert!(build().is_err());
+    assert_eq!(migrate::registry_format(&registry).unwrap(), format_version);
+    assert!(registry.join(".migration-backups").exists().not());
+
+    // Registries in the current format are read all the same
+    fs::remove_file(registry.join(".state.json")).unwrap();
+    fs::remove_dir_all(registry.join("tool")).unwrap();
+    Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .config(Config::default())
+        .build()
+        .unwrap();
+    build().unwrap();
+}
+
+#[test]
+fn refuses_registries_in_a_newer_format() {
+    let root = tempfile::tempdir().unwrap();
+    let cargo_bin = root.path().join("bin");
+    let registry = cargo_bin.join("cargo-switch-registry");
+    fs::create_dir_all(&registry).unwrap();
+    let state = include_str!("fixtures/state/newer.json");
+    fs::write(registry.join(".state.json"), state).unwrap();
+
+    let Err(err) = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .config(Config::default())
+        .build()
+    else {
+        panic!("a registry in a newer format was accepted");
+    };
+    assert!(err.to_string().contains("is in format 99"), "{err}");
+    assert_eq!(
+        fs::read_to_string(registry.join(".state.json")).unwrap(),
+        state
+    );
+    assert!(State::load(&registry).is_err());
+}
+
+/// Keeps every event, as the JSON line it would be written as
+#[derive(Debug, Default, Clone)]
+struct RecordedEvents(Rc<RefCell<Vec<String>>>);
+
+impl EventSink for RecordedEvents {
+    fn emit(&self, event: &Event) {
+        self.0
+            .borrow_mut()
+            .push(serde_json::to_string(event).unwrap());
+    }
+}
+
+#[test]
+fn emits_events_as_things_happen() {
+    let installer = FakeInstaller::default();
+    let events = RecordedEvents::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer.clone()).events(events.clone())
+    });
+    let switcher = &sandbox.switcher;
+
+    for version in ["1.0.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    installer.then(&[FakeBuild::Permanent]);
+    let err = switcher
+        .install_package("tool@3.0.0", &fake_options())
+        .unwrap_err();
+    events.emit(&Event::error(&err));
+    let err = switcher.switch_package("tool@4.0.0").unwrap_err();
+    events.emit(&Event::error(&err));
+
+    let root = sandbox.root.path().to_str().unwrap();
+    let stream = events.0.borrow().join("\n").replace(root, "$ROOT");
+    assert_eq!(
+        stream,
+        r#"{"event":"install-started","package":"tool","version":"1.0.0"}
+{"event":"cargo-output","chunk":"  Installing tool@1.0.0"}
+{"event":"install-finished","package":"tool","version":"1.0.0","duration-ms":1,"location":"$ROOT/.cargo/bin/cargo-switch-registry/tool/1.0.0","binaries":["$ROOT/.cargo/bin/cargo-switch-registry/tool/1.0.0/bin/tool"]}
+{"event":"link-created","link":"$ROOT/.cargo/bin/tool","target":"$ROOT/.cargo/bin/cargo-switch-registry/tool/1.0.0/bin/tool"}
+{"event":"install-started","package":"tool","version":"2.0.0"}
+{"event":"cargo-output","chunk":"  Installing tool@2.0.0"}
+{"event":"install-finished","package":"tool","version":"2.0.0","duration-ms":1,"location":"$ROOT/.cargo/bin/cargo-switch-registry/tool/2.0.0","binaries":["$ROOT/.cargo/bin/cargo-switch-registry/tool/2.0.0/bin/tool"]}
+{"event":"link-replaced","link":"$ROOT/.cargo/bin/tool","old-target":"$ROOT/.cargo/bin/cargo-switch-registry/tool/1.0.0/bin/tool","new-target":"$ROOT/.cargo/bin/cargo-switch-registry/tool/2.0.0/bin/tool"}
+{"event":"install-started","package":"tool","version":"3.0.0"}
+{"event":"cargo-output","chunk":"  Installing tool@3.0.0"}
+{"event":"error","kind":"build-failed","message":"Failed to install tool@3.0.0 after 1 attempt: could not compile `tool`"}
+{"event":"error","kind":"not-installed","message":"Project tool@4.0.0 is not installed! Did you mean `tool@1.0.0`? Installed versions: 1.0.0, 2.0.0"}"#
+    );
+}
+
+#[test]
+fn checks_that_active_binaries_run() {
+    let mut packages = BTreeMap::new();
+    packages.insert(
+        "picky".to_owned(),
+        PackageConfig {
+            check_args: Some(vec!["version".to_owned()]),
+            ..PackageConfig::default()
+        },
+    );
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(Config {
+            packages,
+            ..Config::default()
+        })
+    });
+    let switcher = &sandbox.switcher;
+    for package in ["tool@1.0.0", "stale@2.0.0", "slow@1.0.0", "picky@1.0.0"] {
+        switcher.install_package(package, &fake_options()).unwrap();
+    }
+    let options = InstallOptions {
+        no_switch: true,
+        ..fake_options()
+    };
+    switcher.install_package("idle@1.0.0", &options).unwrap();
+
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    let script = |binary: &str, contents: &str| fs::write(registry.join(binary), contents).unwrap();
+    script("stale/2.0.0/bin/stale", "#!/bin/sh\necho stale 1.9.0\n");
+    script("slow/1.0.0/bin/slow", "#!/bin/sh\nsleep 5\n");
+    script(
+        "picky/1.0.0/bin/picky",
+        "#!/bin/sh\n[ \"$1\" = version ] || exit 2\necho picky 1.0.0\n",
+    );
+
+    let results = switcher
+        .check_active(None, Duration::from_millis(500))
+        .unwrap();
+    let failures: Vec<_> = results
+        .iter()
+        .map(|result| (result.package.as_str(), result.failure.as_deref()))
+        .collect();
+    assert_eq!(
+        failures,
+        [
+            ("picky", None),
+            ("slow", Some("timed out after 0s")),
+            (
+                "stale",
+                Some(
+                    format!(
+                        "reported `stale 1.9.0`, runs {}",
+                        sandbox.cargo_bin().join("stale").display()
+                    )
+                    .as_str()
+                )
+            ),
+            ("tool", None),
+        ]
+    );
+
+    assert!(switcher
+        .check(None, Duration::from_millis(500), false, false)
+        .is_err());
+    switcher
+        .check(Some("tool"), Duration::from_secs(5), false, false)
+        .unwrap();
+    assert!(switcher
+        .check(Some("idle"), Duration::from_secs(5), false, false)
+        .is_err());
+}
+
+/// Run git in `repository`, returning what it printed
+fn git(repository: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repository)
+        .args([
+            "-c",
+            "user.name=Tester",
+            "-c",
+            "user.email=tester@example.com",
+        ])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_owned()
+}
+
+#[test]
+fn pins_git_installs_to_commits_and_follows_their_branch() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+    let repository = sandbox.root.path().join("tool.git");
+    fs::create_dir(&repository).unwrap();
+    git(
+        &repository,
+        &["init", "--quiet", "--initial-branch", "main"],
+    );
+    git(
+        &repository,
+        &["commit", "--quiet", "--allow-empty", "-m", "First"],
+    );
+    let first = git(&repository, &["rev-parse", "HEAD"]);
+    let url = repository.to_str().unwrap().to_owned();
+
+    // Named after the default branch, whatever it's called
+    let options = InstallOptions {
+        git: Some(url.clone()),
+        ..fake_options()
+    };
+    let report = switcher.install_package("tool", &options).unwrap();
+    let first_version = format!("main.{}", &first[..7]);
+    assert_eq!(report.version, first_version);
+    assert_eq!(
+        run_binary(&cargo_bin, "tool"),
+        format!("tool@{first_version} release\n")
+    );
+    let metadata = VersionMetadata::load(&report.location).unwrap().unwrap();
+    assert_eq!(
+        metadata.source,
+        Some(Source::Git {
+            url: url.clone(),
+            rev: Some(first.clone()),
+            branch: Some("main".to_owned()),
+        })
+    );
+    assert!(switcher.install_package("tool@1.0.0", &options).is_err());
+
+    // Nothing to do until the branch moves
+    switcher.update("tool", true).unwrap();
+    assert_eq!(installer.builds.borrow().len(), 1);
+
+    git(
+        &repository,
+        &["commit", "--quiet", "--allow-empty", "-m", "Second"],
+    );
+    let second = git(&repository, &["rev-parse", "HEAD"]);
+    let second_version = format!("main.{}", &second[..7]);
+    switcher.update("tool", true).unwrap();
+    assert_eq!(
+        *installer.builds.borrow(),
+        [
+            format!("tool@{first_version}"),
+            format!("tool@{second_version}")
+        ]
+    );
+    assert_eq!(
+        switcher.linked_version("tool").unwrap(),
+        Some(second_version.clone())
+    );
+
+    // The old commit is still there to go back to, and updating again needs no build
+    switcher
+        .switch_package(&format!("tool@{first_version}"))
+        .unwrap();
+    assert_eq!(
+        run_binary(&cargo_bin, "tool"),
+        format!("tool@{first_version} release\n")
+    );
+    switcher.update("tool", true).unwrap();
+    assert_eq!(installer.builds.borrow().len(), 2);
+    assert_eq!(
+        switcher.linked_version("tool").unwrap(),
+        Some(second_version.clone())
+    );
+
+    // Pinned commits are rebuilt as they were, wherever the branch went since
+    git(
+        &repository,
+        &["commit", "--quiet", "--allow-empty", "-m", "Third"],
+    );
+    switcher
+        .rebuild(&format!("tool@{first_version}"), false)
+        .unwrap();
+    assert_eq!(
+        installer.builds.borrow().last(),
+        Some(&format!("tool@{first_version}"))
+    );
+}
+
+#[test]
+fn keeps_forks_apart_through_labels() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+    let registry = cargo_bin.join("cargo-switch-registry");
+
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    let fork = InstallOptions {
+        path: Some(sandbox.root.path().join("fork")),
+        label: Some("mine".to_owned()),
+        ..fake_options()
+    };
+    let report = switcher.install_package("tool@1.0.0", &fork).unwrap();
+    assert_eq!(report.package, "tool#mine");
+    // Cargo is only ever asked for the crate itself
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "tool@1.0.0"]);
+    assert!(registry.join("tool#mine/1.0.0/bin/tool").exists());
+    assert_eq!(
+        switcher.linked_version("tool#mine").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+    assert_eq!(switcher.linked_version("tool").unwrap(), None);
+    let names: Vec<_> = switcher
+        .listing(false)
+        .unwrap()
+        .into_iter()
+        .map(|package| package.name)
+        .collect();
+    assert_eq!(names, ["tool", "tool#mine"]);
+
+    // Only forks can be labeled, and crates.io has none
+    assert!(switcher
+        .install_package("tool#other@1.0.0", &fake_options())
+        .is_err());
+    assert!(switcher.install_package("tool#mine@2.0.0", &fork).is_err());
+
+    // Whatever the fork provides on top of the upstream binaries goes away when switching back
+    sandbox.write_script(&registry.join("tool#mine/1.0.0/bin/tool-extra"), "extra");
+    switcher.switch_package("tool#mine@1.0.0").unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool-extra"), "extra\n");
+    switcher.switch_package("tool@1.0.0").unwrap();
+    assert!(cargo_bin.join("tool-extra").symlink_metadata().is_err());
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+    assert_eq!(switcher.linked_version("tool#mine").unwrap(), None);
+}
+
+#[test]
+fn treats_dashes_and_underscores_in_names_alike() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+
+    switcher
+        .install_package("fd_find@1.0.0", &fake_options())
+        .unwrap();
+    let report = switcher
+        .install_package("fd-find@2.0.0", &fake_options())
+        .unwrap();
+    // The spelling it was first installed under sticks
+    assert_eq!(report.package, "fd_find");
+    assert!(registry.join("fd_find/2.0.0").exists());
+    assert!(registry.join("fd-find").exists().not());
+    for spelling in ["fd-find", "fd_find"] {
+        assert_eq!(
+            switcher
+                .linked_version(&switcher.canonical_name(spelling).unwrap())
+                .unwrap()
+                .as_deref(),
+            Some("2.0.0")
+        );
+    }
+    switcher.switch_package("fd-find@1.0.0").unwrap();
+    assert_eq!(
+        switcher.linked_version("fd_find").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+
+    // As older registries may hold both spellings, doctor merges them into the active one
+    fs::create_dir_all(registry.join("fd-find")).unwrap();
+    fs::rename(
+        registry.join("fd_find/2.0.0"),
+        registry.join("fd-find/2.0.0"),
+    )
+    .unwrap();
+    switcher.set_default("fd-find", "2.0.0").unwrap();
+    switcher.doctor(false, None, true, false, false).unwrap();
+    assert!(registry.join("fd-find").exists().not());
+    assert!(registry.join("fd_find/2.0.0").exists());
+    let state = State::load(&registry).unwrap();
+    assert_eq!(state.defaults["fd_find"], "2.0.0");
+    assert!(state.defaults.contains_key("fd-find").not());
+    assert_eq!(
+        switcher.linked_version("fd_find").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+}
+
+#[test]
+fn goes_by_the_names_crates_io_has() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    sandbox
+        .switcher
+        .install_package("fd_find@1.0.0", &fake_options())
+        .unwrap();
+    assert!(registry.join("fd_find/1.0.0").exists());
+
+    // Once crates.io can be asked, cargo is asked for the crate it has, whatever it's installed under
+    let index = serve_index(&[("fd-find", &["1.0.0", "2.0.0"])]);
+    let installer = FakeInstaller {
+        fetches_from_crates_io: true,
+        ..FakeInstaller::default()
+    };
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(sandbox.cargo_bin())
+        .config(Config::default())
+        .installer(installer.clone())
+        .crates_io_index(&index)
+        .build()
+        .unwrap();
+    switcher.rebuild("fd_find@1.0.0", false).unwrap();
+    assert!(registry.join("fd_find/1.0.0").exists());
+    assert_eq!(*installer.builds.borrow(), ["fd-find@1.0.0"]);
+
+    // Installing goes by its spelling too, which the versions installed under another are moved to, what was
+    // active staying so
+    let options = InstallOptions {
+        no_switch: true,
+        skip_msrv_check: true,
+        ..fake_options()
+    };
+    let report = switcher.install_package("fd_find@2.0.0", &options).unwrap();
+    assert_eq!(report.package, "fd-find");
+    assert_eq!(
+        *installer.builds.borrow(),
+        ["fd-find@1.0.0", "fd-find@2.0.0"]
+    );
+    assert!(registry.join("fd_find").exists().not());
+    assert!(registry.join("fd-find/1.0.0").exists());
+    assert!(registry.join("fd-find/2.0.0").exists());
+    assert_eq!(
+        switcher.linked_version("fd-find").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "fd-find"),
+        "fd-find@1.0.0 release\n"
+    );
+}
+
+#[test]
+fn keeps_uninstalled_versions_in_the_trash() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    let installed = |switcher: &Switcher| {
+        switcher.listing(false).unwrap()[0]
+            .versions
+            .iter()
+            .map(|version| version.version.clone())
+            .collect::<Vec<_>>()
+    };
+    let trashed = |switcher: &Switcher| {
+        switcher
+            .trashed_versions()
+            .unwrap()
+            .into_iter()
+            .map(|trashed| format!("{}@{}", trashed.package, trashed.version))
+            .collect::<Vec<_>>()
+    };
+
+    for version in ["1.0.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+    assert_eq!(installed(switcher), ["2.0.0"]);
+    assert_eq!(trashed(switcher), ["tool@1.0.0"]);
+    assert!(switcher.switch_package("tool@1.0.0").is_err());
+
+    switcher.undelete("tool@1.0.0").unwrap();
+    assert_eq!(installed(switcher), ["1.0.0", "2.0.0"]);
+    assert!(trashed(switcher).is_empty());
+    assert!(switcher.undelete("tool@1.0.0").is_err());
+
+    // What pointed to it comes back along with it, unless it was pointed elsewhere since
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    switcher.set_default("tool", "1.0.0").unwrap();
+    switcher.set_channel("tool", "stable", "1.0.0").unwrap();
+    switcher.set_channel("tool", "lts", "1.0.0").unwrap();
+    switcher.lock_version("tool@1.0.0").unwrap();
+    switcher.uninstall("tool@1.0.0", true).unwrap();
+    let state = State::load(&registry).unwrap();
+    assert!(state.defaults.is_empty() && state.channels.is_empty() && state.locked.is_empty());
+    switcher.set_channel("tool", "lts", "2.0.0").unwrap();
+    switcher.undelete("tool@1.0.0").unwrap();
+    let state = State::load(&registry).unwrap();
+    assert_eq!(state.defaults["tool"], "1.0.0");
+    assert_eq!(state.channels["tool"]["stable"], "1.0.0");
+    assert_eq!(state.channels["tool"]["lts"], "2.0.0");
+    assert!(state.locked["tool"].contains("1.0.0"));
+    switcher.unlock_version("tool@1.0.0").unwrap();
+    switcher.unset_default("tool").unwrap();
+    switcher.unset_channel("tool", "stable").unwrap();
+    switcher.unset_channel("tool", "lts").unwrap();
+
+    // Packages whose last version was uninstalled come back too
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+    switcher.uninstall("tool@2.0.0", false).unwrap();
+    assert!(switcher.listing(false).unwrap().is_empty());
+    switcher.undelete("tool@2.0.0").unwrap();
+    assert_eq!(installed(switcher), ["2.0.0"]);
+
+    switcher
+        .empty_trash(Some(Duration::from_secs(24 * 60 * 60)))
+        .unwrap();
+    assert_eq!(trashed(switcher), ["tool@1.0.0"]);
+    switcher.empty_trash(None).unwrap();
+    assert!(trashed(switcher).is_empty());
+
+    // Pruning removes what was trashed longer than `trash-retention` ago
+    switcher
+        .install_package("tool@3.0.0", &fake_options())
+        .unwrap();
+    switcher.uninstall("tool@3.0.0", false).unwrap();
+    let trashed_copy = &switcher.trashed_versions().unwrap()[0].path;
+    fs::rename(
+        trashed_copy,
+        trashed_copy.with_file_name("3.0.0-1000000000"),
+    )
+    .unwrap();
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+    switcher.prune(Some("tool"), Some(1)).unwrap();
+    assert_eq!(trashed(switcher), ["tool@1.0.0"]);
+
+    // The trash goes before anything installed when the registry outgrows its max size
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(Config {
+            max_size: Some(512 * 1024),
+            ..Config::default()
+        })
+    });
+    let switcher = &sandbox.switcher;
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    for version in ["1.0.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    fs::write(registry.join("tool/1.0.0/large"), vec![0; 1024 * 1024]).unwrap();
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+    switcher
+        .install_package("tool@3.0.0", &fake_options())
+        .unwrap();
+    assert_eq!(installed(switcher), ["2.0.0", "3.0.0"]);
+    assert!(trashed(switcher).is_empty());
+}
+
+#[test]
+fn sums_up_many_installs_whether_failing_fast_or_not() {
+    let specs = ["tool@1.0.0", "other@1.0.0", "third@1.0.0"].map(str::to_owned);
+    let installed = |sandbox: &Sandbox| {
+        sandbox
+            .switcher
+            .listing(false)
+            .unwrap()
+            .into_iter()
+            .map(|package| package.name)
+            .collect::<Vec<_>>()
+    };
+
+    let installer = FakeInstaller::default();
+    let events = RecordedEvents::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer.clone()).events(events.clone())
+    });
+    installer.then(&[FakeBuild::Succeed, FakeBuild::Permanent]);
+    let err = sandbox
+        .switcher
+        .install_packages(&specs, &fake_options())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "1 of 3 packages failed");
+    assert_eq!(installed(&sandbox), ["third", "tool"]);
+    let summary = events.0.borrow().last().cloned().unwrap();
+    assert!(
+        summary.starts_with(
+            r#"{"event":"summary","specs":[{"spec":"tool@1.0.0","outcome":"installed","detail":"#
+        ),
+        "{summary}"
+    );
+    assert!(
+        summary.contains(r#"{"spec":"other@1.0.0","outcome":"failed","detail":"#),
+        "{summary}"
+    );
+
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder
+            .installer(installer.clone())
+            .failure_mode(FailureMode::FailFast)
+    });
+    installer.then(&[FakeBuild::Succeed, FakeBuild::Permanent]);
+    let err = sandbox
+        .switcher
+        .install_packages(&specs, &fake_options())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "1 of 3 packages failed");
+    // Nothing is tried past the first failure
+    assert_eq!(installed(&sandbox), ["tool"]);
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "other@1.0.0"]);
+}
+
+#[test]
+fn sums_up_single_installs_like_many() {
+    let installer = FakeInstaller::default();
+    let events = RecordedEvents::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer.clone()).events(events.clone())
+    });
+    installer.then(&[FakeBuild::Permanent]);
+    let err = sandbox
+        .switcher
+        .install_packages(&["tool@1.0.0".to_owned()], &fake_options())
+        .unwrap_err();
+    assert_eq!(err.to_string(), "1 of 1 packages failed");
+    let summary = events.0.borrow().last().cloned().unwrap();
+    assert!(
+        summary.starts_with(
+            r#"{"event":"summary","specs":[{"spec":"tool@1.0.0","outcome":"failed","detail":"#
+        ),
+        "{summary}"
+    );
+}
+
+#[test]
+fn installs_and_switches_groups_of_packages() {
+    let groups = BTreeMap::from([
+        (
+            "web".to_owned(),
+            vec!["tool@1.0.0".to_owned(), "shared@1.0.0".to_owned()],
+        ),
+        ("embedded".to_owned(), vec!["shared@2.0.0".to_owned()]),
+    ]);
+    let installer = FakeInstaller::default();
+    let events = RecordedEvents::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder
+            .installer(installer.clone())
+            .events(events.clone())
+            .config(Config {
+                groups,
+                ..Config::default()
+            })
+    });
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+
+    // Installing leaves switching to `group switch`, building as told
+    let locked = InstallOptions {
+        locked: Some(true),
+        ..fake_options()
+    };
+    switcher.group_install("web", &locked).unwrap();
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0", "shared@1.0.0"]);
+    assert_eq!(switcher.linked_version("tool").unwrap(), None);
+    let metadata = VersionMetadata::load(&cargo_bin.join("cargo-switch-registry/tool/1.0.0"))
+        .unwrap()
+        .unwrap();
+    assert!(metadata.flags.unwrap().locked);
+    switcher.group_install("web", &fake_options()).unwrap();
+    assert_eq!(installer.builds.borrow().len(), 2);
+
+    switcher
+        .group_switch("web", false, &fake_options())
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+    assert_eq!(run_binary(&cargo_bin, "shared"), "shared@1.0.0 release\n");
+
+    // What isn't installed is only installed when asked to, and whichever group came last wins
+    assert!(switcher
+        .group_switch("embedded", false, &fake_options())
+        .is_err());
+    assert_eq!(run_binary(&cargo_bin, "shared"), "shared@1.0.0 release\n");
+    switcher
+        .group_switch("embedded", true, &fake_options())
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "shared"), "shared@2.0.0 release\n");
+    switcher
+        .group_switch("web", false, &fake_options())
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "shared"), "shared@1.0.0 release\n");
+    let summary = events.0.borrow().last().cloned().unwrap();
+    assert!(
+        summary.contains(
+            r#"{"spec":"shared@1.0.0","outcome":"switched","detail":"replaced 2.0.0, from group embedded"}"#
+        ),
+        "{summary}"
+    );
+    assert!(
+        summary.contains(r#"{"spec":"tool@1.0.0","outcome":"skipped","detail":"active already"}"#),
+        "{summary}"
+    );
+
+    assert!(switcher
+        .group_switch("missing", false, &fake_options())
+        .is_err());
+}
+
+#[test]
+fn explains_how_versions_are_resolved() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    for version in ["1.0.0", "2.0.0"] {
+        switcher
+            .install_package(&format!("whytool@{version}"), &fake_options())
+            .unwrap();
+    }
+    let trace = |switcher: &Switcher, variable: Option<&str>| {
+        let (resolution, steps) = switcher
+            .trace_version("whytool", |name| {
+                variable
+                    .filter(|_| name == "CARGO_SWITCH_WHYTOOL_VERSION")
+                    .map(OsString::from)
+            })
+            .unwrap();
+        let steps: Vec<_> = steps
+            .into_iter()
+            .map(|step| (step.source, step.matched))
+            .collect();
+        (resolution.version, steps)
+    };
+
+    let (version, steps) = trace(switcher, None);
+    assert_eq!(version, "2.0.0");
+    let sources: Vec<_> = steps.iter().map(|(source, _)| source.as_str()).collect();
+    assert_eq!(
+        sources,
+        [
+            "$CARGO_SWITCH_WHYTOOL_VERSION",
+            "project pins",
+            "default version",
+            "installed versions"
+        ]
+    );
+    assert_eq!(
+        steps.iter().filter(|(_, matched)| *matched).count(),
+        1,
+        "{steps:?}"
+    );
+
+    // The override wins, leaving nothing else to consult
+    assert_eq!(
+        trace(switcher, Some("1.0.0")),
+        (
+            "1.0.0".to_owned(),
+            vec![("$CARGO_SWITCH_WHYTOOL_VERSION".to_owned(), true)]
+        )
+    );
+
+    switcher.why("whytool").unwrap();
+    assert!(switcher.why("nothing-like-it").is_err());
+}
+
+#[test]
+fn links_into_another_directory() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    let cargo_bin = sandbox.cargo_bin();
+    let registry = cargo_bin.join("cargo-switch-registry");
+
+    // The link dir is created, while the registry stays next to the cargo bin directory
+    let link_dir = sandbox.root.path().join(".local").join("bin");
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .link_dir(&link_dir)
+        .config(Config::default())
+        .installer(FakeInstaller::default())
+        .build()
+        .unwrap();
+    switcher.switch_package("tool@1.0.0").unwrap();
+    assert_eq!(run_binary(&link_dir, "tool"), "tool@1.0.0 release\n");
+    assert_eq!(
+        switcher.link_owner(OsStr::new("tool")),
+        Some(("tool".to_owned(), "1.0.0".to_owned()))
+    );
+
+    // What's left in .cargo/bin is found, and removed if asked to
+    assert!(cargo_bin.join("tool").symlink_metadata().is_ok());
+    assert!(switcher.doctor(false, None, false, false, false).is_err());
+    switcher.doctor(false, None, false, true, false).unwrap();
+    assert!(cargo_bin.join("tool").symlink_metadata().is_err());
+    assert!(link_dir.join("tool").exists());
+    let state = State::load(&registry).unwrap();
+    assert_eq!(state.link_dirs, BTreeSet::from([link_dir]));
+}
+
+#[test]
+fn switches_to_versions_of_the_shared_registry() {
+    let root = tempfile::tempdir().unwrap();
+    let shared = root.path().join("opt").join("registry");
+    let shared_config = || Config {
+        shared_registry: Some(shared.clone()),
+        ..Config::default()
+    };
+    let admin_bin = root.path().join("admin").join(".cargo").join("bin");
+    fs::create_dir_all(&admin_bin).unwrap();
+    let admin = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&admin_bin)
+        .config(shared_config())
+        .installer(FakeInstaller::default())
+        .system(true)
+        .build()
+        .unwrap();
+    let report = admin
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    assert!(shared.join("tool/1.0.0").exists());
+
+    // Which is all --system does, leaving the admin's own links and what's switched to up to them
+    assert!(report.switched.not());
+    assert!(admin_bin.join("tool").symlink_metadata().is_err());
+    assert!(admin.switch_package("tool@1.0.0").is_err());
+    assert!(admin.set_default("tool", "1.0.0").is_err());
+    let state = State::load(&shared).unwrap();
+    assert!(state.activated_at.is_empty() && state.defaults.is_empty());
+
+    // Users switch to it in their own .cargo/bin without building anything
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder
+            .installer(FakeInstaller::default())
+            .config(shared_config())
+    });
+    let switcher = &sandbox.switcher;
+    let listing = switcher.package_listing("tool").unwrap();
+    assert_eq!(listing.versions.len(), 1);
+    assert!(listing.versions[0].shared);
+    switcher.switch_package("tool@1.0.0").unwrap();
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@1.0.0 release\n"
+    );
+    assert_eq!(
+        switcher.linked_version("tool").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+
+    // Their installs go to their own registry, and what's shared is left alone
+    switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    assert!(registry.join("tool/2.0.0").exists());
+    assert!(shared.join("tool/2.0.0").exists().not());
+    let err = switcher.uninstall("tool@1.0.0", false).unwrap_err();
+    assert!(err.to_string().contains("--system"), "{err:#}");
+    let err = switcher.rebuild("tool@1.0.0", false).unwrap_err();
+    assert!(err.to_string().contains("--system"), "{err:#}");
+    assert!(shared.join("tool/1.0.0").exists());
+
+    // Nor is it purged, whether by those who stop using cargo-switch or by whoever runs it
+    switcher.switch_package("tool@1.0.0").unwrap();
+    switcher.purge(false, true).unwrap();
+    assert_eq!(
+        run_binary(&sandbox.cargo_bin(), "tool"),
+        "tool@1.0.0 release\n"
+    );
+    assert!(admin.purge(false, true).is_err());
+    assert!(shared.join("tool/1.0.0").exists());
+}
+
+#[test]
+fn leaves_prereleases_out_of_the_newest_version() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    for version in ["1.9.3", "2.0.0-beta.1"] {
+        sandbox
+            .switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    assert_eq!(
+        sandbox.switcher.resolve_version("tool").unwrap().version,
+        "1.9.3"
+    );
+
+    let cargo_bin = sandbox.cargo_bin();
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .config(Config::default())
+        .pre(true)
+        .build()
+        .unwrap();
+    assert_eq!(
+        switcher.resolve_version("tool").unwrap().version,
+        "2.0.0-beta.1"
+    );
+
+    // Explicit pre-releases are always fine
+    sandbox
+        .switcher
+        .switch_package("tool@2.0.0-beta.1")
+        .unwrap();
+    assert_eq!(
+        run_binary(&cargo_bin, "tool"),
+        "tool@2.0.0-beta.1 release\n"
+    );
+}
+
+#[test]
+fn builds_with_the_flags_of_the_config_file() {
+    let mut packages = BTreeMap::new();
+    packages.insert(
+        "tool".to_owned(),
+        PackageConfig {
+            features: vec!["fast".to_owned()],
+            default_features: Some(false),
+            locked: true,
+            toolchain: Some("1.75".to_owned()),
+            extra_args: vec!["--jobs".to_owned(), "2".to_owned()],
+            build_env: BTreeMap::from([(
+                "RUSTFLAGS".to_owned(),
+                "-C target-cpu=native".to_owned(),
+            )]),
+            ..PackageConfig::default()
+        },
+    );
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(Config {
+            packages,
+            ..Config::default()
+        })
+    });
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    let flags = |version: &str| {
+        VersionMetadata::load(&registry.join("tool").join(version))
+            .unwrap()
+            .unwrap()
+            .flags
+            .unwrap()
+    };
+
+    // Features add up, while the command line has the last word on the toolchain
+    let report = sandbox
+        .switcher
+        .install_package(
+            "tool@1.0.0",
+            &InstallOptions {
+                features: vec!["color".to_owned()],
+                toolchain: Some("stable".to_owned()),
+                ..fake_options()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.version, "1.0.0+color.fast");
+    // Whatever build-relevant variables the environment has are recorded too, beneath the configured ones
+    let recorded_env = |configured: BTreeMap<String, String>| {
+        Some(build_env(&configured, |variable| env::var(variable).ok()))
+    };
+    let configured_env =
+        BTreeMap::from([("RUSTFLAGS".to_owned(), "-C target-cpu=native".to_owned())]);
+    let expected = BuildFlags {
+        toolchain: Some("stable".to_owned()),
+        no_default_features: true,
+        locked: true,
+        extra_args: vec!["--jobs".to_owned(), "2".to_owned()],
+        env: recorded_env(configured_env.clone()),
+    };
+    assert_eq!(flags("1.0.0+color.fast"), expected);
+    assert_eq!(
+        BuildFlags {
+            env: Some(configured_env),
+            ..expected.clone()
+        }
+        .to_string(),
+        "RUSTFLAGS=\"-C target-cpu=native\" +stable --no-default-features --locked --jobs 2"
+    );
+
+    // Rebuilds go the same way
+    sandbox
+        .switcher
+        .rebuild("tool@1.0.0+color.fast", false)
+        .unwrap();
+    assert_eq!(flags("1.0.0+color.fast"), expected);
+
+    let report = sandbox
+        .switcher
+        .install_package(
+            "tool@1.0.0",
+            &InstallOptions {
+                no_config_flags: true,
+                ..fake_options()
+            },
+        )
+        .unwrap();
+    assert_eq!(report.version, "1.0.0");
+    assert_eq!(
+        flags("1.0.0"),
+        BuildFlags {
+            env: recorded_env(BTreeMap::new()),
+            ..BuildFlags::default()
+        }
+    );
+    // Flags the config file turns on can be turned off again for one build
+    let report = sandbox
+        .switcher
+        .install_package(
+            "tool@2.0.0",
+            &InstallOptions {
+                no_default_features: Some(false),
+                locked: Some(false),
+                ..fake_options()
+            },
+        )
+        .unwrap();
+    let flags = flags(&report.version);
+    assert!(flags.no_default_features.not());
+    assert!(flags.locked.not());
+    assert_eq!(flags.toolchain.as_deref(), Some("1.75"));
+}
+
+#[test]
+fn requires_checksums_only_when_asked_to() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let switcher = &sandbox.switcher;
+    let version_path = sandbox.cargo_bin().join("cargo-switch-registry/tool/1.0.0");
+
+    // The fake installer downloads no .crate, so there's nothing to check
+    let required = InstallOptions {
+        require_checksum: true,
+        ..fake_options()
+    };
+    let err = switcher
+        .install_package("tool@1.0.0", &required)
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("registry cache"), "{err:#}");
+    assert!(version_path.exists().not());
+
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    let metadata = VersionMetadata::load(&version_path).unwrap().unwrap();
+    assert_eq!(metadata.checksum, None);
+
+    // Once the index can be asked, what it lists is recorded, unverified for lack of the .crate
+    let index = serve_index(&[("tool", &["1.0.0", "2.0.0"])]);
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(sandbox.cargo_bin())
+        .config(Config::default())
+        .installer(FakeInstaller::default())
+        .crates_io_index(&index)
+        .build()
+        .unwrap();
+    switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    let version_path = sandbox.cargo_bin().join("cargo-switch-registry/tool/2.0.0");
+    let metadata = VersionMetadata::load(&version_path).unwrap().unwrap();
+    assert_eq!(metadata.checksum.as_deref(), Some("abc"));
+    assert!(metadata.checksum_unverified);
+}
+
+#[test]
+fn makes_sure_versions_are_published_before_building_them() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let index = serve_index(&[("tool", &["1.0.0", "1.1.0"])]);
+    let installer = FakeInstaller {
+        fetches_from_crates_io: true,
+        ..FakeInstaller::default()
+    };
+    let switcher = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(sandbox.cargo_bin())
+        .config(Config::default())
+        .installer(installer.clone())
+        .crates_io_index(&index)
+        .build()
+        .unwrap();
+    let options = InstallOptions {
+        skip_msrv_check: true,
+        ..fake_options()
+    };
+
+    // A version that was never published is caught before anything gets built
+    let err = switcher
+        .install_package("tool@1.2.0", &options)
+        .unwrap_err();
+    assert!(
+        format!("{err:#}").starts_with("tool has no version 1.2.0 on crates.io"),
+        "{err:#}"
+    );
+    assert!(installer.builds.borrow().is_empty());
+
+    switcher.install_package("tool@1.1.0", &options).unwrap();
+    assert_eq!(*installer.builds.borrow(), ["tool@1.1.0"]);
+}
+
+#[test]
+fn flags_versions_built_with_old_compilers() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    let options = InstallOptions {
+        skip_msrv_check: true,
+        ..fake_options()
+    };
+    let use_rustc = |output: &str| *installer.rustc.borrow_mut() = Some(output.to_owned());
+
+    use_rustc("rustc 1.68.2 (9eb3afe9e 2023-03-27)");
+    switcher.install_package("tool@1.0.0", &options).unwrap();
+    switcher.install_package("tool@1.1.0", &options).unwrap();
+    use_rustc("rustc 1.71.0 (8ede3aae2 2023-07-12)");
+    switcher.install_package("tool@1.2.0", &options).unwrap();
+    use_rustc("rustc 1.75.0 (82e1608df 2023-12-21)");
+    switcher.install_package("tool@2.0.0", &options).unwrap();
+    // As if installed before the compiler was recorded
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+    fs::remove_file(registry.join("tool/1.1.0/.cargo-switch/metadata.json")).unwrap();
+
+    let current = RustcVersion::parse("rustc 1.75.0 (82e1608df 2023-12-21)").unwrap();
+    let drifted = switcher.toolchain_drift(Some("tool"), &current).unwrap();
+    let drifted: Vec<_> = drifted
+        .iter()
+        .map(|(_, version, drift)| (version.as_str(), drift))
+        .collect();
+    let old = Drift::Behind {
+        rustc: RustcVersion::parse("rustc 1.68.2 (9eb3afe9e 2023-03-27)").unwrap(),
+        behind: 7,
+    };
+    // 1.2.0 is only 4 releases behind, which is fine
+    assert_eq!(drifted, [("1.0.0", &old), ("1.1.0", &Drift::Unknown)]);
+    assert!(switcher
+        .check(Some("tool"), Duration::from_secs(5), true, false)
+        .is_err());
+
+    // Rebuilding catches up with the default toolchain
+    switcher.rebuild("tool@1.0.0", false).unwrap();
+    let drifted = switcher.toolchain_drift(Some("tool"), &current).unwrap();
+    assert_eq!(drifted.len(), 1);
+    assert_eq!(drifted[0].2, Drift::Unknown);
+}
+
+#[test]
+fn finds_the_paths_of_installed_versions() {
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default().with_binaries("tool", &["tool", "toolctl"]))
+    });
+    let switcher = &sandbox.switcher;
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    let version_path = sandbox.cargo_bin().join("cargo-switch-registry/tool/1.0.0");
+
+    // The only version needs no asking for
+    assert_eq!(
+        switcher.installed_path("tool", None, None).unwrap(),
+        version_path
+    );
+    assert_eq!(
+        switcher
+            .installed_path("tool", Some("1.0.0"), Some("toolctl"))
+            .unwrap(),
+        version_path.join("bin/toolctl")
+    );
+    let err = switcher
+        .installed_path("tool", Some("1.0.0"), Some("nope"))
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("only tool, toolctl"));
+
+    switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    let err = switcher.installed_path("tool", None, None).unwrap_err();
+    assert!(format!("{err:#}").contains("1.0.0, 2.0.0"));
+    assert!(switcher
+        .installed_path("tool", Some("3.0.0"), None)
+        .is_err());
+}
+
+#[test]
+fn switches_several_packages_at_once() {
+    let installer = FakeInstaller::default().with_binaries("fake-rustfmt", &["rustfmt"]);
+    let events = RecordedEvents::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer.clone()).events(events.clone())
+    });
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+    sandbox.write_script(&cargo_bin.join("rustfmt"), "rustup proxy");
+    let options = InstallOptions {
+        no_switch: true,
+        ..fake_options()
+    };
+    for spec in [
+        "tool@1.0.0",
+        "tool@2.0.0",
+        "other@1.0.0",
+        "fake-rustfmt@1.0.0",
+    ] {
+        switcher.install_package(spec, &options).unwrap();
+    }
+    switcher.switch_package("tool@1.0.0").unwrap();
+    let specs =
+        |specs: &[&str]| -> Vec<String> { specs.iter().map(|spec| spec.to_string()).collect() };
+
+    // Nothing is switched to before every spec checks out
+    assert!(switcher
+        .switch_packages(&specs(&["tool@2.0.0", "missing@1.0.0"]), false)
+        .is_err());
+    assert!(switcher
+        .switch_packages(&specs(&["tool@2.0.0", "tool@1.0.0"]), false)
+        .is_err());
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+
+    // A switch failing halfway through undoes the ones before it
+    assert!(switcher
+        .switch_packages(
+            &specs(&["tool@2.0.0", "other@1.0.0", "fake-rustfmt@1.0.0"]),
+            false
+        )
+        .is_err());
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+    assert_eq!(switcher.linked_version("other").unwrap(), None);
+    assert_eq!(run_binary(&cargo_bin, "rustfmt"), "rustup proxy\n");
+    let summary = events.0.borrow().last().cloned().unwrap();
+    assert!(
+        summary.contains(
+            r#"{"spec":"tool@2.0.0","outcome":"rolled-back","detail":"switched back to 1.0.0"}"#
+        ),
+        "{summary}"
+    );
+    assert!(
+        summary.contains(
+            r#"{"spec":"other@1.0.0","outcome":"rolled-back","detail":"unlinked again"}"#
+        ),
+        "{summary}"
+    );
+
+    switcher
+        .switch_packages(&specs(&["tool@2.0.0", "other@1.0.0"]), false)
+        .unwrap();
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@2.0.0 release\n");
+    assert_eq!(run_binary(&cargo_bin, "other"), "other@1.0.0 release\n");
+}
+
+#[test]
+fn logs_everything_done_to_the_registry() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let switcher = &sandbox.switcher;
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    installer.then(&[FakeBuild::Permanent]);
+    assert!(switcher
+        .install_package("other@1.0.0", &fake_options())
+        .is_err());
+    switcher.uninstall("tool@1.0.0", false).unwrap();
+
+    let logged: Vec<_> = switcher
+        .log_entries(Some("tool"))
+        .unwrap()
+        .into_iter()
+        .map(|entry| {
+            (
+                entry.operation,
+                entry.version.unwrap_or_default(),
+                entry.detail,
+            )
+        })
+        .collect();
+    assert_eq!(
+        logged,
+        [
+            (
+                Operation::Install,
+                "1.0.0".to_owned(),
+                "built in 0s".to_owned()
+            ),
+            (Operation::Switch, "1.0.0".to_owned(), String::new()),
+            (
+                Operation::Install,
+                "2.0.0".to_owned(),
+                "built in 0s".to_owned()
+            ),
+            (
+                Operation::Switch,
+                "2.0.0".to_owned(),
+                "replaced 1.0.0".to_owned()
+            ),
+            (
+                Operation::Uninstall,
+                "1.0.0".to_owned(),
+                "moved to the trash".to_owned()
+            ),
+        ]
+    );
+
+    let failed = switcher.log_entries(Some("other")).unwrap();
+    assert_eq!(failed.len(), 1);
+    assert!(
+        failed[0].detail.starts_with("failed: ") && failed[0].detail.contains("could not compile"),
+        "{:?}",
+        failed[0]
+    );
+}
+
+#[test]
+fn execs_commands_with_versions_first_in_path() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let cargo_bin = sandbox.cargo_bin();
+    for version in ["1.0.0", "2.0.0"] {
+        sandbox
+            .switcher
+            .install_package(&format!("tool@{version}"), &fake_options())
+            .unwrap();
+    }
+    sandbox.switcher.switch_package("tool@1.0.0").unwrap();
+
+    // The command finds the version asked for, while the links stay as they were
+    let output = cargo_switch_in(&sandbox, &["exec", "--with", "tool@2.0.0", "--", "tool"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{output:?}");
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "tool@2.0.0 release\n"
+    );
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+
+    // Being replaced by the command, it exits as the command does
+    let status = cargo_switch_in(
+        &sandbox,
+        &["exec", "--with", "tool@2.0.0", "--", "sh", "-c", "exit 3"],
+    )
+    .status()
+    .unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    // Versions that aren't installed are only installed if asked to, which cargo-switch can't do here
+    let output = cargo_switch_in(&sandbox, &["exec", "--with", "tool@3.0.0", "--", "tool"])
+        .output()
+        .unwrap();
+    assert!(output.status.success().not());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("tool@3.0.0"),
+        "{output:?}"
+    );
+}
+
+#[test]
+fn spawns_shells_with_versions_first_in_path() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let cargo_bin = sandbox.cargo_bin();
+    for (package, version) in [("tool", "1.0.0"), ("tool", "2.0.0"), ("other", "1.0.0")] {
+        sandbox
+            .switcher
+            .install_package(&format!("{package}@{version}"), &fake_options())
+            .unwrap();
+    }
+    sandbox.switcher.switch_package("tool@1.0.0").unwrap();
+    // A shell that tells what it sees and exits as it pleases
+    let shell = sandbox.root.path().join("fake-shell");
+    fs::write(
+        &shell,
+        "#!/bin/sh\necho \"in $CARGO_SWITCH_SHELL\"\ntool\nother\nexit 4\n",
+    )
+    .unwrap();
+    fs::set_permissions(&shell, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = cargo_switch_in(&sandbox, &["shell", "tool@2.0.0", "other@1.0.0"])
+        .env("SHELL", &shell)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<_> = stdout.lines().collect();
+    assert_eq!(
+        lines[1..],
+        [
+            "in tool@2.0.0 other@1.0.0",
+            "tool@2.0.0 release",
+            "other@1.0.0 release",
+            "Left the shell with tool@2.0.0 other@1.0.0"
+        ]
+    );
+    assert!(lines[0].starts_with("Spawning"), "{stdout}");
+    // Nothing was switched along the way
+    assert_eq!(run_binary(&cargo_bin, "tool"), "tool@1.0.0 release\n");
+
+    // Nor can a package be asked for twice
+    let output = cargo_switch_in(&sandbox, &["shell", "tool@1.0.0", "tool@2.0.0"])
+        .env("SHELL", &shell)
+        .output()
+        .unwrap();
+    assert!(output.status.success().not());
+    assert!(
+        String::from_utf8_lossy(&output.stderr).contains("Conflicting versions of tool"),
+        "{output:?}"
+    );
+}
+
+#[test]
+fn runs_versions_that_arent_installed_without_keeping_them() {
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(installer.clone()));
+    let run = |missing| {
+        sandbox
+            .switcher
+            .run_package("tool", Some("1.0.0"), None, &[], missing)
+    };
+    let registry = sandbox.cargo_bin().join("cargo-switch-registry");
+
+    assert!(run(Missing::Fail).is_err());
+    assert!(installer.builds.borrow().is_empty());
+
+    // Built next to nothing else, and gone once it ran
+    let status = run(Missing::Temporary).unwrap();
+    assert!(status.success());
+    assert_eq!(*installer.builds.borrow(), ["tool@1.0.0"]);
+    assert!(registry.join("tool").exists().not());
+    // In a directory of its own that nobody could have guessed the name of
+    let root = installer.roots.borrow().last().cloned().unwrap();
+    assert!(root.starts_with(env::temp_dir()));
+    let name = root.file_name().unwrap().to_str().unwrap();
+    assert!(name.starts_with("cargo-switch-run-"), "{name}");
+    assert_ne!(name, format!("cargo-switch-run-{}", std::process::id()));
+    assert!(root.exists().not());
+
+    // Whatever cargo said is what the build failed with
+    installer.then(&[FakeBuild::Permanent]);
+    let err = run(Missing::Temporary).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("could not compile `tool`"),
+        "{err:#}"
+    );
+    let root = installer.roots.borrow().last().cloned().unwrap();
+    assert!(root.exists().not());
+    installer.then(&[FakeBuild::Permanent]);
+    let err = run(Missing::Install).unwrap_err();
+    assert!(
+        format!("{err:#}").contains("could not compile `tool`"),
+        "{err:#}"
+    );
+    assert!(registry.join("tool/1.0.0").exists().not());
+
+    // A registry that can't be read isn't taken for one without the package
+    let shared = tempfile::tempdir().unwrap();
+    fs::write(shared.path().join("tool"), "").unwrap();
+    let installer = FakeInstaller::default();
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(installer.clone()).config(Config {
+            shared_registry: Some(shared.path().to_owned()),
+            ..Config::default()
+        })
+    });
+    for version in [Some("1.0.0"), None] {
+        let run = sandbox
+            .switcher
+            .run_package("tool", version, None, &[], Missing::Install);
+        assert!(run.is_err());
+    }
+    assert!(installer.builds.borrow().is_empty());
+}
+
+#[test]
+fn reports_paths_and_settings_even_when_broken() {
+    let root = tempfile::tempdir().unwrap();
+    let cargo_bin = root.path().join(".cargo").join("bin");
+    let config = Config {
+        keep_versions: Some(3),
+        ..Config::default()
+    };
+    let builder = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .registry(root.path().join("registry"))
+        .config(config);
+
+    // The cargo bin directory doesn't exist, which building would fail over
+    let paths = builder.paths();
+    let links = paths.get("links").unwrap();
+    assert_eq!(links.value, Some(cargo_bin.display().to_string()));
+    assert_eq!(links.source.as_deref(), Some("--cargo-bin"));
+    assert_eq!(links.error.as_deref(), Some("doesn't exist"));
+    let registry = paths.get("registry").unwrap();
+    assert_eq!(registry.note.as_deref(), Some("doesn't exist yet"));
+    assert_eq!(
+        paths.get("cache").unwrap().value,
+        Some(
+            root.path()
+                .join("registry")
+                .join(".cache")
+                .display()
+                .to_string()
+        )
+    );
+    assert!(root.path().join("registry").exists().not());
+
+    let keep_versions = paths.get("keep-versions").unwrap();
+    assert_eq!(keep_versions.value.as_deref(), Some("3"));
+    assert_eq!(
+        keep_versions.source.as_deref(),
+        Some("the `keep-versions` config key")
+    );
+    let retries = paths.get("retries").unwrap();
+    assert_eq!(retries.value.as_deref(), Some("2"));
+    assert_eq!(retries.source.as_deref(), Some("the default"));
+
+    let json = serde_json::to_value(&paths).unwrap();
+    assert_eq!(json["links"]["error"], "doesn't exist");
+    assert_eq!(json["keep-versions"]["value"], "3");
+
+    // What's shown is what building goes by
+    fs::create_dir_all(&cargo_bin).unwrap();
+    let system = || {
+        Switcher::builder()
+            .non_interactive(true)
+            .cargo_bin(&cargo_bin)
+            .config(Config::default())
+            .system(true)
+    };
+    let shown = system().paths().get("registry").unwrap().error.clone();
+    let err = format!("{:#}", system().build().err().unwrap());
+    assert_eq!(shown, Some(err));
+}
+
+#[test]
+fn switches_a_copy_of_itself_without_pulling_the_rug() {
+    let sandbox = Sandbox::with_builder(|builder| builder.installer(FakeInstaller::default()));
+    let cargo_bin = sandbox.cargo_bin();
+    let registry = cargo_bin.join("cargo-switch-registry");
+    // cargo-switch under another name, so that what's running is one of the packages it manages
+    for version in ["1.0.0", "2.0.0"] {
+        sandbox
+            .switcher
+            .install_package(&format!("selfish@{version}"), &fake_options())
+            .unwrap();
+        let binary = registry.join("selfish").join(version).join("bin/selfish");
+        fs::copy(env!("CARGO_BIN_EXE_cargo-switch"), &binary).unwrap();
+    }
+    sandbox.switcher.switch_package("selfish@1.0.0").unwrap();
+
+    let path = env::join_paths(
+        [cargo_bin.clone()]
+            .into_iter()
+            .chain(env::split_paths(&env::var_os("PATH").unwrap())),
+    )
+    .unwrap();
+    // Run `program`, as `cargo switch` would
+    let run = |program: &Path, args: &[&str]| {
+        let output = Command::new(program)
+            .arg("switch")
+            .args(args)
+            .env("PATH", &path)
+            .env("HOME", sandbox.root.path())
+            .env("XDG_CONFIG_HOME", sandbox.root.path().join(".config"))
+            .env_remove("CARGO_HOME")
+            .env_remove("CARGO_INSTALL_ROOT")
+            .env_remove("CARGO_SWITCH_CONFIG")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{output:?}");
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let selfish = |args: &[&str]| run(&cargo_bin.join("selfish"), args);
+
+    // Started from 1.0.0, which is switched away from while it runs
+    let stdout = selfish(&["selfish@2.0.0"]);
+    assert!(
+        stdout.contains(
+            "Note: selfish is what's running right now, 2.0.0 takes effect the next time it runs"
+        ),
+        "{stdout}"
+    );
+    assert!(is_link_to(
+        &cargo_bin.join("selfish"),
+        &registry.join("selfish/2.0.0/bin/selfish")
+    ));
+    assert!(fs::read_dir(&cargo_bin).unwrap().all(|entry| entry
+        .unwrap()
+        .file_name()
+        .to_string_lossy()
+        .ends_with("-tmp")
+        .not()));
+
+    // Nor is 1.0.0 pruned by what runs from it, only by a later invocation
+    let running = registry.join("selfish/1.0.0");
+    let stdout = run(
+        &running.join("bin/selfish"),
+        &["prune", "selfish", "--keep", "1"],
+    );
+    assert!(
+        stdout.contains("Kept selfish@1.0.0, which is running right now"),
+        "{stdout}"
+    );
+    assert!(running.exists());
+
+    // Running 2.0.0, it's fine to go
+    let stdout = selfish(&["selfish@2.0.0"]);
+    assert!(stdout.contains("takes effect").not(), "{stdout}");
+    let stdout = selfish(&["prune", "selfish", "--keep", "1"]);
+    assert!(stdout.contains("Pruned selfish@1.0.0"), "{stdout}");
+    assert!(running.exists().not());
+}
+
+#[test]
+fn quarantines_versions_that_fail_their_smoke_test() {
+    let root = tempfile::tempdir().unwrap();
+    let broken = root.path().join("broken");
+    let config = || {
+        let mut packages = BTreeMap::new();
+        packages.insert(
+            "tool".to_owned(),
+            PackageConfig {
+                smoke_test: Some(format!(
+                    "if [ -e '{}' ]; then echo 'missing libfoo.so' >&2; exit 1; fi; tool",
+                    broken.display()
+                )),
+                ..PackageConfig::default()
+            },
+        );
+        Config {
+            packages,
+            ..Config::default()
+        }
+    };
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(config())
+    });
+    let switcher = &sandbox.switcher;
+    let cargo_bin = sandbox.cargo_bin();
+    let registry = cargo_bin.join("cargo-switch-registry");
+    let quarantined = |version: &str| {
+        VersionMetadata::load(&registry.join("tool").join(version))
+            .unwrap()
+            .unwrap()
+            .quarantined
+    };
+    switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+
+    // The new version stays out of the way, what was active before is still
+    fs::write(&broken, "").unwrap();
+    let report = switcher
+        .install_package("tool@2.0.0", &fake_options())
+        .unwrap();
+    let err = format!("{:#}", report.switch_error.unwrap());
+    assert!(err.contains("tool@2.0.0 failed its smoke test"), "{err}");
+    assert!(err.contains("missing libfoo.so"), "{err}");
+    assert!(report.switched.not());
+    assert_eq!(
+        switcher.linked_version("tool").unwrap().as_deref(),
+        Some("1.0.0")
+    );
+    assert!(quarantined("2.0.0").unwrap().contains("missing libfoo.so"));
+    let err = switcher.switch_package("tool@2.0.0").unwrap_err();
+    assert!(
+        format!("{err:#}").contains("tool@2.0.0 is quarantined"),
+        "{err:#}"
+    );
+
+    // Unless told otherwise
+    let ignoring = Switcher::builder()
+        .non_interactive(true)
+        .cargo_bin(&cargo_bin)
+        .registry(&registry)
+        .config(config())
+        .ignore_smoke_test(true)
+        .build()
+        .unwrap();
+    ignoring.switch_package("tool@2.0.0").unwrap();
+    assert_eq!(
+        switcher.linked_version("tool").unwrap().as_deref(),
+        Some("2.0.0")
+    );
+    switcher.switch_package("tool@1.0.0").unwrap();
+
+    // A smoke test that fails again keeps the quarantine, one that passes lifts it
+    let results = switcher.smoke_test_versions(Some("tool")).unwrap();
+    let failed: Vec<_> = results
+        .iter()
+        .filter(|result| result.failure.is_some())
+        .map(|result| result.version.as_str())
+        .collect();
+    assert_eq!(failed, ["1.0.0", "2.0.0"]);
+    assert!(quarantined("1.0.0").is_none());
+    assert!(quarantined("2.0.0").is_some());
+    fs::remove_file(&broken).unwrap();
+    switcher
+        .check(Some("tool"), Duration::from_secs(5), false, true)
+        .unwrap();
+    assert!(quarantined("2.0.0").is_none());
+    switcher.switch_package("tool@2.0.0").unwrap();
+
+    // Metadata that can't be read doesn't keep anything from being switched to
+    fs::write(
+        VersionMetadata::directory(&registry.join("tool").join("1.0.0")).join("metadata.json"),
+        "{",
+    )
+    .unwrap();
+    switcher.switch_package("tool@1.0.0").unwrap();
+
+    assert!(switcher.smoke_test_versions(Some("other")).is_err());
+}
+
+#[test]
+fn times_out_smoke_tests_that_leave_something_running() {
+    let mut packages = BTreeMap::new();
+    packages.insert(
+        "tool".to_owned(),
+        PackageConfig {
+            smoke_test: Some("sleep 30 & tool".to_owned()),
+            smoke_test_timeout: Some(Duration::from_secs(1)),
+            ..PackageConfig::default()
+        },
+    );
+    let sandbox = Sandbox::with_builder(|builder| {
+        builder.installer(FakeInstaller::default()).config(Config {
+            packages,
+            ..Config::default()
+        })
+    });
+
+    let started = Instant::now();
+    let report = sandbox
+        .switcher
+        .install_package("tool@1.0.0", &fake_options())
+        .unwrap();
+    let err = format!("{:#}", report.switch_error.unwrap());
+    assert!(err.contains("timed out"), "{err}");
+    assert!(started.elapsed() < Duration::from_secs(20));
+}
+
+#[test]
+fn shares_builds_through_the_binary_cache() {
+    let cache = tempfile::tempdir().unwrap();
+    // Somebody on the team, whose compiler says `rustc`
+    let teammate = |rustc: &str| {
+        let installer = FakeInstaller::default();
+        *installer.rustc.borrow_mut() = Some(rustc.to_owned());
+        let sandbox = Sandbox::with_builder(|builder| {
+            builder.installer(installer.clone()).config(Config {
+                binary_cache: Some(cache.path().to_owned()),
+                ..Config::default()
+            })
+        });
+        (sandbox, installer)
+    };
+    let options = InstallOptions {
+        skip_msrv_check: true,
+        ..fake_options()
+    };
+    let metadata = |sandbox: &Sandbox, version: &str| {
+        VersionMetadata::load(
+            &sandbox
+                .cargo_bin()
+                .join("cargo-switch-registry/tool")
+                .join(version),
+        )
+        .unwrap()
+        .unwrap()
+    };
+
+    let (alice, alice_installer) = teammate("rustc 1.80.0");
+    alice
+        .switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+    assert_eq!(*alice_installer.builds.borrow(), ["tool@1.0.0"]);
+    assert!(metadata(&alice, "1.0.0").binary_cache.is_none());
+
+    // The same build is copied rather than built, and says where it came from
+    let (bob, bob_installer) = teammate("rustc 1.80.0");
+    let report = bob
+        .switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+    assert!(report.switched);
+    assert!(bob_installer.builds.borrow().is_empty());
+    let output = Command::new(bob.cargo_bin().join("tool")).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "tool@1.0.0 release\n"
+    );
+    let provenance = metadata(&bob, "1.0.0").binary_cache.unwrap();
+    assert!(provenance.entry.starts_with(cache.path()));
+    assert!(provenance.copy_duration_ms.is_some());
+    // How long it took to build is what it took whoever built it
+    assert_eq!(
+        metadata(&bob, "1.0.0").build_duration_ms,
+        metadata(&alice, "1.0.0").build_duration_ms
+    );
+    assert!(provenance.pushed_by.is_empty().not());
+    assert_eq!(
+        metadata(&bob, "1.0.0").rustc.as_deref(),
+        Some("rustc 1.80.0")
+    );
+
+    // Other features, another compiler or being told not to all build
+    let with_features = InstallOptions {
+        features: vec!["fast".to_owned()],
+        ..options.clone()
+    };
+    bob.switcher
+        .install_package("tool@1.0.0", &with_features)
+        .unwrap();
+    assert_eq!(*bob_installer.builds.borrow(), ["tool@1.0.0"]);
+    let (carol, carol_installer) = teammate("rustc 1.81.0");
+    carol
+        .switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+    assert_eq!(*carol_installer.builds.borrow(), ["tool@1.0.0"]);
+    let (dave, dave_installer) = teammate("rustc 1.80.0");
+    let no_cache = InstallOptions {
+        no_binary_cache: true,
+        ..options.clone()
+    };
+    dave.switcher
+        .install_package("tool@1.0.0", &no_cache)
+        .unwrap();
+    assert_eq!(*dave_installer.builds.borrow(), ["tool@1.0.0"]);
+
+    // Binaries that don't match the checksums they were pushed with are built again
+    fs::write(provenance.entry.join("bin/tool"), "#!/bin/sh\necho pwned\n").unwrap();
+    let (erin, erin_installer) = teammate("rustc 1.80.0");
+    erin.switcher
+        .install_package("tool@1.0.0", &options)
+        .unwrap();
+    assert_eq!(*erin_installer.builds.borrow(), ["tool@1.0.0"]);
+    assert!(metadata(&erin, "1.0.0").binary_cache.is_none());
+    let output = Command::new(erin.cargo_bin().join("tool"))
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "tool@1.0.0 release\n"
+    );
+
+    // What was installed before the cache was around can be pushed all the same
+    let (frank, _) = teammate("rustc 1.82.0");
+    frank
+        .switcher
+        .install_package("tool@2.0.0", &no_cache)
+        .unwrap();
+    frank.switcher.push(&["tool@2.0.0".to_owned()]).unwrap();
+    let (grace, grace_installer) = teammate("rustc 1.82.0");
+    grace
+        .switcher
+        .install_package("tool@2.0.0", &options)
+        .unwrap();
+    assert!(grace_installer.builds.borrow().is_empty());
+
+    // Builds that fail their smoke test are kept to whoever built them
+    let smoke_tested = |smoke_test: &str| {
+        let installer = FakeInstaller::default();
+        *installer.rustc.borrow_mut() = Some("rustc 1.83.0".to_owned());
+        let sandbox = Sandbox::with_builder(|builder| {
+            builder.installer(installer.clone()).config(Config {
+                binary_cache: Some(cache.path().to_owned()),
+                packages: BTreeMap::from([(
+                    "tool".to_owned(),
+                    PackageConfig {
+                        smoke_test: Some(smoke_test.to_owned()),
+                        ..PackageConfig::default()
+                    },
+                )]),
+                ..Config::default()
+            })
+        });
+        (sandbox, installer)
+    };
+    let (heidi, _) = smoke_tested("exit 1");
+    assert!(heidi
+        .switcher
+        .install_package("tool@3.0.0", &options)
+        .unwrap()
+        .switch_error
+        .is_some());
+    let (ivan, _) = smoke_tested("exit 1");
+    let no_switch = InstallOptions {
+        no_switch: true,
+        ..options.clone()
+    };
+    ivan.switcher
+        .install_package("tool@3.0.0", &no_switch)
+        .unwrap();
+    let (judy, judy_installer) = smoke_tested("tool");
+    judy.switcher
+        .install_package("tool@3.0.0", &options)
+        .unwrap();
+    assert_eq!(*judy_installer.builds.borrow(), ["tool@3.0.0"]);
+    assert!(metadata(&judy, "3.0.0").binary_cache.is_none());
+}
+
+#[test]
+fn purges_everything_but_copies_of_the_active_binaries() {
+    let sandbox = || {
+        let mut packages = BTreeMap::new();
+        packages.insert(
+            "tool".to_owned(),
+            PackageConfig {
+                versioned_links: true,
+                ..PackageConfig::default()
+            },
+        );
+        packages.insert(
+            "wrapped".to_owned(),
+            PackageConfig {
+                env: BTreeMap::from([("WRAPPED".to_owned(), "yes".to_owned())]),
+                ..PackageConfig::default()
+            },
+        );
+        let sandbox = Sandbox::with_builder(|builder| {
+            builder.installer(FakeInstaller::default()).config(Config {
+                packages,
+                ..Config::default()
+            })
+        });
+        let switcher = &sandbox.switcher;
+        for spec in ["tool@1.0.0", "tool@2.0.0", "wrapped@1.0.0"] {
+            switcher.install_package(spec, &fake_options()).unwrap();
+        }
+        switcher.switch_package("tool@1.0.0").unwrap();
+
+        // Neither of which cargo-switch made
+        let cargo_bin = sandbox.cargo_bin();
+        fs::write(cargo_bin.join("mine"), "#!/bin/sh\necho mine\n").unwrap();
+        std::os::unix::fs::symlink("/bin/sh", cargo_bin.join("elsewhere")).unwrap();
+        sandbox
+    };
+    let registry = |sandbox: &Sandbox| sandbox.cargo_bin().join("cargo-switch-registry");
+    let untouched = |sandbox: &Sandbox| {
+        let cargo_bin = sandbox.cargo_bin();
+        assert_eq!(
+            fs::read_to_string(cargo_bin.join("mine")).unwrap(),
+            "#!/bin/sh\necho mine\n"
+        );
+        assert!(is_link_to(
+            &cargo_bin.join("elsewhere"),
+            Path::new("/bin/sh")
+        ));
+    };
+
+    let kept = sandbox();
+    let report = kept.switcher.purge(false, true).unwrap();
+    let cargo_bin = kept.cargo_bin();
+    for (name, output) in [
+        ("tool", "tool@1.0.0 release\n"),
+        ("tool-1", "tool@1.0.0 release\n"),
+        ("tool-2", "tool@2.0.0 release\n"),
+        ("wrapped", "wrapped@1.0.0 release\n"),
+    ] {
+        assert!(cargo_bin.join(name).symlink_metadata().unwrap().is_file());
+        assert_eq!(run_binary(&cargo_bin, name), output);
+    }
+    assert_eq!(report.materialized.len(), 4);
+    assert_eq!(report.unwrapped, [cargo_bin.join("wrapped")]);
+    assert!(report.removed_links.is_empty());
+    assert!(registry(&kept).exists().not());
+    untouched(&kept);
+    // There's nothing left to purge the second time around
+    let report = kept.switcher.purge(false, true).unwrap();
+    assert!(report.materialized.is_empty() && report.registry.is_none());
+
+    let removed = sandbox();
+    let report = removed.switcher.purge(true, true).unwrap();
+    let cargo_bin = removed.cargo_bin();
+    for name in ["tool", "tool-1", "tool-2", "wrapped"] {
+        assert!(cargo_bin.join(name).symlink_metadata().is_err());
+    }
+    assert_eq!(report.removed_links.len(), 4);
+    assert!(registry(&removed).exists().not());
+    untouched(&removed);
+
+    // Registries holding what cargo-switch didn't put there are left as they are
+    let foreign = sandbox();
+    fs::write(registry(&foreign).join("notes.txt"), "mine too").unwrap();
+    foreign.switcher.purge(false, true).unwrap_err();
+    assert!(is_link_to(
+        &foreign.cargo_bin().join("tool"),
+        &registry(&foreign).join("tool/1.0.0/bin/tool")
+    ));
+    untouched(&foreign);
+}